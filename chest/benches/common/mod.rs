@@ -0,0 +1,112 @@
+//! Scenes and timing shared by the benches, which print milliseconds per frame.
+//!
+//! Run with `cargo bench -p aurora_chest`.
+
+#![allow(dead_code)]
+
+use std::{
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use aurora_chest::{import::mesh_from_obj_indexed, material::PbrMaterial};
+use aurora_core::{
+    render::{
+        flow::RenderFlow,
+        helper::Transform,
+        mesh::NormalGenerationMode,
+        scene::{GpuScene, MaterialInstanceId},
+    },
+    util::testing::{self, TestTargets},
+    WgpuRenderer,
+};
+use glam::{UVec2, Vec3};
+use uuid::Uuid;
+use wgpu::{Maintain, TextureFormat};
+
+pub const SIZE: UVec2 = UVec2::new(1280, 720);
+pub const WARMUP_FRAMES: u32 = 10;
+pub const FRAMES: u32 = 100;
+
+/// The scene isn't checked in, so it's read from here unless `CASCADE_SCENE` points
+/// somewhere else.
+pub const CASCADE_SCENE: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/../gui/assets/large_scene_cascade_test.obj"
+);
+
+/// Renderer with the features `flow` requires and the meshes of [`CASCADE_SCENE`] sharing
+/// one material, `None` if either is missing, after saying why.
+pub fn cascade_scene(flow: &RenderFlow) -> Option<(WgpuRenderer, GpuScene)> {
+    let path = std::env::var("CASCADE_SCENE").unwrap_or_else(|_| CASCADE_SCENE.to_string());
+    if !Path::new(&path).exists() {
+        eprintln!("Skipped, {path} is missing.");
+        return None;
+    }
+    let Some(renderer) =
+        testing::renderer(Some(flow.required_features()), Some(flow.required_limits()))
+    else {
+        eprintln!("Skipped, no adapter supporting the flow.");
+        return None;
+    };
+
+    let mut scene = GpuScene::default();
+    let material = MaterialInstanceId(Uuid::new_v4());
+    scene
+        .original
+        .materials
+        .insert(material, Arc::new(PbrMaterial::default()));
+    for mesh in mesh_from_obj_indexed(&path, NormalGenerationMode::Smooth) {
+        testing::add_static_mesh(&mut scene, mesh, material);
+    }
+    scene.original.camera.transform = Transform::default()
+        .with_translation(Vec3::new(0., 20., 40.))
+        .looking_at(Vec3::ZERO, Vec3::Y);
+
+    Some((renderer, scene))
+}
+
+/// Color and depth targets of [`SIZE`].
+pub fn targets(renderer: &WgpuRenderer) -> TestTargets {
+    TestTargets::new(&renderer.device, SIZE, TextureFormat::Rgba16Float)
+        .with_depth(&renderer.device)
+}
+
+/// Average wall time of a frame, waiting for the GPU after each one. Drawn with
+/// [`RenderFlow::run_parallel`] if `parallel`, [`RenderFlow::run`] otherwise.
+pub fn frame_time(
+    renderer: &WgpuRenderer,
+    flow: &mut RenderFlow,
+    scene: &mut GpuScene,
+    test_targets: &TestTargets,
+    parallel: bool,
+) -> Duration {
+    let targets = test_targets.targets();
+    flow.set_queue(scene.static_meshes.clone());
+    flow.build(renderer, scene, None, &targets).unwrap();
+
+    let mut frame = |scene: &mut GpuScene| {
+        if parallel {
+            flow.run_parallel(renderer, scene, &targets);
+        } else {
+            flow.run(renderer, scene, &targets);
+        }
+        renderer.device.poll(Maintain::Wait);
+    };
+    for _ in 0..WARMUP_FRAMES {
+        frame(scene);
+    }
+    let start = Instant::now();
+    for _ in 0..FRAMES {
+        frame(scene);
+    }
+    start.elapsed() / FRAMES
+}
+
+pub fn report(name: &str, frame_time: Duration) {
+    println!(
+        "{name:<32} {:>8.3} ms/frame",
+        frame_time.as_secs_f64() * 1000.
+    );
+}
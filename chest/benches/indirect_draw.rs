@@ -0,0 +1,29 @@
+//! Frame time of the cascade scene with and without [`PbrNodeConfig::INDIRECT_DRAW`].
+
+mod common;
+
+use aurora_chest::node::{DepthPrepassNode, PbrNode, PbrNodeConfig};
+use aurora_core::render::flow::{GeneralNode, ImageFallbackNode, RenderFlow};
+
+fn main() {
+    let Some((renderer, mut scene)) = common::cascade_scene(&RenderFlow::default()) else {
+        return;
+    };
+    let test_targets = common::targets(&renderer);
+
+    for (name, node_cfg) in [
+        ("direct", PbrNodeConfig::empty()),
+        ("indirect", PbrNodeConfig::INDIRECT_DRAW),
+    ] {
+        let mut flow = RenderFlow::default();
+        flow.add::<GeneralNode>()
+            .add::<ImageFallbackNode>()
+            .add::<DepthPrepassNode>()
+            .add_initialized(PbrNode {
+                node_cfg,
+                ..Default::default()
+            });
+        let frame_time = common::frame_time(&renderer, &mut flow, &mut scene, &test_targets, false);
+        common::report(name, frame_time);
+    }
+}
@@ -0,0 +1,43 @@
+//! Frame time of the full PBR flow on the cascade scene, drawn in order and with prepasses
+//! and shadow mapping recorded in parallel, see [`RenderNode::parallel_safe`].
+//!
+//! PBR sampling shadow maps fails to compile on GL, so this needs another backend.
+//!
+//! [`RenderNode::parallel_safe`]: aurora_core::render::flow::RenderNode::parallel_safe
+
+mod common;
+
+use aurora_chest::node::{
+    DepthPrepassNode, FxaaNode, MotionVectorPrepassNode, NormalPrepassNode, PbrNode, PbrNodeConfig,
+    ShadowMappingNode, SsaoNode, TonemappingNode,
+};
+use aurora_core::render::flow::{GeneralNode, ImageFallbackNode, PresentNode, RenderFlow};
+
+fn main() {
+    let mut flow = RenderFlow::default();
+    flow.add::<GeneralNode>()
+        .add::<ImageFallbackNode>()
+        .add::<DepthPrepassNode>()
+        .add::<NormalPrepassNode>()
+        .add::<MotionVectorPrepassNode>()
+        .add::<ShadowMappingNode>()
+        .add::<SsaoNode>()
+        .add_initialized(PbrNode {
+            node_cfg: PbrNodeConfig::SHADOW_MAPPING | PbrNodeConfig::SSAO,
+            ..Default::default()
+        })
+        .add::<TonemappingNode>()
+        .add::<FxaaNode>()
+        .add::<PresentNode>();
+
+    let Some((renderer, mut scene)) = common::cascade_scene(&flow) else {
+        return;
+    };
+    let test_targets = common::targets(&renderer);
+
+    for (name, parallel) in [("run", false), ("run_parallel", true)] {
+        let frame_time =
+            common::frame_time(&renderer, &mut flow, &mut scene, &test_targets, parallel);
+        common::report(name, frame_time);
+    }
+}
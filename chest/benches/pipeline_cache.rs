@@ -0,0 +1,79 @@
+//! Time to build a PBR flow against an empty pipeline cache, and again once it's on disk.
+
+mod common;
+
+use std::{sync::Arc, time::Instant};
+
+use aurora_chest::{
+    material::PbrMaterial,
+    node::{DepthPrepassNode, PbrNode},
+};
+use aurora_core::{
+    render::{
+        flow::{GeneralNode, ImageFallbackNode, RenderFlow},
+        scene::{GpuScene, MaterialInstanceId},
+    },
+    util::testing,
+    RendererConfig, WgpuRenderer,
+};
+use glam::Vec2;
+use uuid::Uuid;
+use wgpu::{Features, Maintain};
+
+fn main() {
+    let config = RendererConfig {
+        optional_features: Features::PIPELINE_CACHE,
+        ..Default::default()
+    };
+    let Some(mut renderer) = testing::skip_unsupported(pollster::block_on(
+        WgpuRenderer::with_config(config, None, None),
+    )) else {
+        eprintln!("Skipped, no adapter.");
+        return;
+    };
+
+    let dir = std::env::temp_dir().join(format!("aurora_pipeline_cache_{}", Uuid::new_v4()));
+    if !renderer.load_pipeline_cache(&dir) {
+        eprintln!("Skipped, the adapter can't cache pipelines.");
+        return;
+    }
+
+    let mut scene = GpuScene::default();
+    let material = MaterialInstanceId(Uuid::new_v4());
+    scene
+        .original
+        .materials
+        .insert(material, Arc::new(PbrMaterial::default()));
+    testing::add_static_mesh(
+        &mut scene,
+        testing::quad(Vec2::splat(-1.), Vec2::splat(1.), -3.),
+        material,
+    );
+    let test_targets = common::targets(&renderer);
+    let targets = test_targets.targets();
+
+    let build = |renderer: &WgpuRenderer, scene: &mut GpuScene| {
+        let mut flow = RenderFlow::default();
+        flow.add::<GeneralNode>()
+            .add::<ImageFallbackNode>()
+            .add::<DepthPrepassNode>()
+            .add::<PbrNode>();
+        flow.set_queue(scene.static_meshes.clone());
+
+        let start = Instant::now();
+        flow.force_build(renderer, scene, None, &targets).unwrap();
+        renderer.device.poll(Maintain::Wait);
+        start.elapsed()
+    };
+
+    let cold = build(&renderer, &mut scene);
+    // Saved on drop, then read back like on the next launch.
+    renderer.pipeline_cache = None;
+    renderer.load_pipeline_cache(&dir);
+    let warm = build(&renderer, &mut scene);
+    renderer.pipeline_cache = None;
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    println!("{:<32} {:>8.3} ms", "cold", cold.as_secs_f64() * 1000.);
+    println!("{:<32} {:>8.3} ms", "warm", warm.as_secs_f64() * 1000.);
+}
@@ -1,9 +1,9 @@
-use std::{f32::consts::FRAC_PI_4, path::Path, rc::Rc};
+use std::{f32::consts::FRAC_PI_4, path::Path, sync::Arc};
 
 use glam::{Mat4, Quat, Vec2, Vec3, Vec4};
 use gltf::{
     accessor::DataType,
-    json::{Index, Node, Root},
+    json::{material::AlphaMode as GltfAlphaMode, Index, Node, Root},
     Gltf, Semantic,
 };
 use image::ImageFormat;
@@ -16,7 +16,10 @@ use aurora_core::render::{
         Camera, CameraProjection, Exposure, OrthographicProjection, PerspectiveProjection,
         Transform,
     },
-    mesh::{Mesh, MeshIndices, MeshVertexAttributeData, StaticMesh},
+    mesh::{
+        AlphaMode, GenerateTangentsError, Mesh, MeshIndices, MeshVertexAttributeData,
+        NormalGenerationMode, StaticMesh, DEFAULT_RENDER_LAYER,
+    },
     resource::{GpuDirectionalLight, GpuPointLight, GpuSpotLight, Image},
     scene::{GpuScene, MaterialInstanceId, MeshInstanceId, TextureId},
 };
@@ -37,8 +40,6 @@ pub enum BufferType {
 pub enum GltfLoadError {
     #[error("{0}")]
     GltfParse(#[from] gltf::Error),
-    #[error("{0} cameras found, expected 1.")]
-    MultipleCameras(u32),
     #[error("Missing blob.")]
     MissingBlob,
     #[error["{0}"]]
@@ -47,6 +48,8 @@ pub enum GltfLoadError {
     BufferFormatUnsupported,
     #[error("{0}")]
     IoError(#[from] std::io::Error),
+    #[error("{0}")]
+    Tangents(#[from] GenerateTangentsError),
 }
 
 pub type GltfLoadResult<T> = Result<T, GltfLoadError>;
@@ -88,18 +91,26 @@ impl<'a> DataUri<'a> {
     }
 }
 
+/// Options of [`load_gltf`].
+#[derive(Debug, Default, Clone, Copy)]
+pub struct GltfLoadOptions {
+    /// Textures larger than this or the device limit are downscaled to fit.
+    pub max_texture_size: Option<u32>,
+    /// How normals are generated for meshes shipping without them.
+    pub normal_mode: NormalGenerationMode,
+}
+
 pub fn load_gltf(
     path: impl AsRef<Path>,
     device: &Device,
     queue: &Queue,
+    options: &GltfLoadOptions,
 ) -> GltfLoadResult<GpuScene> {
     let mut scene = GpuScene::default();
     let model = Gltf::open(path)?;
     let json = model.as_json();
 
-    if model.cameras().len() > 1 {
-        return Err(GltfLoadError::MultipleCameras(model.cameras().len() as u32));
-    } else if model.cameras().len() == 0 {
+    if model.cameras().len() == 0 {
         scene.original.camera = Camera {
             transform: Default::default(),
             projection: CameraProjection::Perspective(PerspectiveProjection {
@@ -109,11 +120,16 @@ pub fn load_gltf(
                 far: 200.,
             }),
             exposure: Default::default(),
+            jitter: Default::default(),
         };
     }
 
     let buffers = load_buffers_data(&model)?;
-    let textures = load_textures(&model, &buffers)
+    let device_limit = device.limits().max_texture_dimension_2d;
+    let max_texture_size = options
+        .max_texture_size
+        .map_or(device_limit, |max| max.min(device_limit));
+    let textures = load_textures(&model, &buffers, max_texture_size)
         .into_iter()
         .map(|tex| {
             let id = TextureId(Uuid::new_v4());
@@ -128,20 +144,33 @@ pub fn load_gltf(
         })
         .collect();
 
-    for node in &json.nodes {
+    for (node, world) in node_world_transforms(json) {
+        let node = &json.nodes[node];
+
+        // Every camera is kept, the first one is rendered from.
         if let Some(index) = node.camera {
-            scene.original.camera = load_camera(json, node, index);
+            let id = Uuid::new_v4();
+            scene
+                .original
+                .cameras
+                .insert(id, load_camera(json, world, index));
+            if scene.original.active_camera.is_none() {
+                scene.original.set_active_camera(id);
+            }
         }
 
         if let Some(index) = node.mesh {
-            let (mesh, mat) = load_mesh(json, node, index, &buffers, &textures);
+            let (mesh, mat) =
+                load_mesh(json, world, index, &buffers, &textures, options.normal_mode)?;
 
             let sm = StaticMesh {
                 mesh: MeshInstanceId(Uuid::new_v4()),
                 material: MaterialInstanceId(Uuid::new_v4()),
+                render_layer: DEFAULT_RENDER_LAYER,
+                transform: Default::default(),
             };
             scene.assets.meshes.insert(sm.mesh, mesh);
-            scene.original.materials.insert(sm.material, Rc::new(mat));
+            scene.original.materials.insert(sm.material, Arc::new(mat));
             scene.static_meshes.push(sm);
         }
 
@@ -150,7 +179,7 @@ pub fn load_gltf(
             .as_ref()
             .and_then(|ext| ext.khr_lights_punctual.clone())
         {
-            let (dir, point, spot) = load_light(json, node, light.light);
+            let (dir, point, spot) = load_light(json, world, light.light);
             if let Some(dir) = dir {
                 scene.original.dir_lights.insert(Uuid::new_v4(), dir);
             }
@@ -163,6 +192,18 @@ pub fn load_gltf(
         }
     }
 
+    if model.cameras().len() == 0 {
+        let bounds = scene
+            .assets
+            .meshes
+            .values()
+            .filter_map(Mesh::aabb)
+            .reduce(|a, b| a.merge(&b));
+        if let Some(bounds) = bounds {
+            scene.original.camera.auto_near_far(bounds);
+        }
+    }
+
     Ok(scene)
 }
 
@@ -199,7 +240,7 @@ fn load_buffers_data(model: &Gltf) -> GltfLoadResult<Vec<Vec<u8>>> {
     Ok(data)
 }
 
-fn load_textures(model: &Gltf, buffers: &Vec<Vec<u8>>) -> Vec<Image> {
+fn load_textures(model: &Gltf, buffers: &Vec<Vec<u8>>, max_texture_size: u32) -> Vec<Image> {
     let mut textures = Vec::with_capacity(model.textures().len());
     for texture in model.textures() {
         match texture.source().source() {
@@ -228,6 +269,7 @@ fn load_textures(model: &Gltf, buffers: &Vec<Vec<u8>>) -> Vec<Image> {
                     &buffers[view.buffer().index()][view.offset()..view.offset() + view.length()],
                     format,
                     true,
+                    Some(max_texture_size),
                 );
 
                 textures.push(image);
@@ -236,19 +278,72 @@ fn load_textures(model: &Gltf, buffers: &Vec<Vec<u8>>) -> Vec<Image> {
                 let uri = percent_encoding::percent_decode_str(uri)
                     .decode_utf8()
                     .unwrap();
-                textures.push(Image::from_path(uri.as_ref(), None, true).unwrap());
+                textures.push(
+                    Image::from_path(uri.as_ref(), None, true, Some(max_texture_size)).unwrap(),
+                );
             }
         }
     }
     textures
 }
 
-fn load_camera(json: &Root, node: &Node, index: Index<gltf::json::Camera>) -> Camera {
+fn local_transform(node: &Node) -> Mat4 {
+    match node.matrix {
+        Some(matrix) => Mat4::from_cols_array(&matrix),
+        None => Mat4::from_scale_rotation_translation(
+            node.scale.map(Vec3::from_array).unwrap_or(Vec3::ONE),
+            node.rotation
+                .map(|r| Quat::from_array(r.0))
+                .unwrap_or_default(),
+            node.translation.unwrap_or_default().into(),
+        ),
+    }
+}
+
+/// World transform of every node reachable from the default scene.
+///
+/// Files without scenes fall back to treating every node without a parent as a root.
+fn node_world_transforms(json: &Root) -> Vec<(usize, Mat4)> {
+    fn visit(json: &Root, node: usize, parent: Mat4, result: &mut Vec<(usize, Mat4)>) {
+        let world = parent * local_transform(&json.nodes[node]);
+        result.push((node, world));
+        for child in json.nodes[node].children.iter().flatten() {
+            visit(json, child.value(), world, result);
+        }
+    }
+
+    let roots = match json
+        .scene
+        .and_then(|s| json.get(s))
+        .or_else(|| json.scenes.first())
+    {
+        Some(scene) => scene.nodes.iter().map(|n| n.value()).collect::<Vec<_>>(),
+        None => {
+            let children = json
+                .nodes
+                .iter()
+                .flat_map(|n| n.children.iter().flatten().map(|c| c.value()))
+                .collect::<Vec<_>>();
+            (0..json.nodes.len())
+                .filter(|n| !children.contains(n))
+                .collect()
+        }
+    };
+
+    let mut result = Vec::with_capacity(json.nodes.len());
+    for root in roots {
+        visit(json, root, Mat4::IDENTITY, &mut result);
+    }
+    result
+}
+
+fn load_camera(json: &Root, world: Mat4, index: Index<gltf::json::Camera>) -> Camera {
     let camera = json.get(index).unwrap();
+    let (_, rotation, translation) = world.to_scale_rotation_translation();
     Camera {
         transform: Transform {
-            translation: node.translation.unwrap_or_default().into(),
-            rotation: Quat::from_array(node.rotation.unwrap_or_default().0),
+            translation,
+            rotation,
             scale: Vec3::ONE,
         },
         projection: if let Some(proj) = &camera.orthographic {
@@ -266,12 +361,13 @@ fn load_camera(json: &Root, node: &Node, index: Index<gltf::json::Camera>) -> Ca
             unreachable!()
         },
         exposure: Exposure::default(),
+        jitter: Default::default(),
     }
 }
 
 fn load_light(
     json: &Root,
-    node: &Node,
+    world: Mat4,
     light: Index<gltf::json::extensions::scene::khr_lights_punctual::Light>,
 ) -> (
     Option<GpuDirectionalLight>,
@@ -279,15 +375,12 @@ fn load_light(
     Option<GpuSpotLight>,
 ) {
     let light = json.get(light).unwrap();
+    let (_, rotation, translation) = world.to_scale_rotation_translation();
 
     match light.type_.unwrap() {
         gltf::json::extensions::scene::khr_lights_punctual::Type::Directional => (
             Some(GpuDirectionalLight {
-                direction: node
-                    .rotation
-                    .map(|r| Quat::from_array(r.0))
-                    .unwrap_or_default()
-                    .mul_vec3(Vec3::Z),
+                direction: rotation.mul_vec3(Vec3::Z),
                 color: light.color.into(),
                 intensity: light.intensity,
                 radius: 1.,
@@ -298,7 +391,7 @@ fn load_light(
         gltf::json::extensions::scene::khr_lights_punctual::Type::Point => (
             None,
             Some(GpuPointLight {
-                position: node.translation.unwrap_or_default().into(),
+                position: translation,
                 color: light.color.into(),
                 intensity: light.intensity,
                 radius: 1.,
@@ -311,12 +404,8 @@ fn load_light(
                 None,
                 None,
                 Some(GpuSpotLight {
-                    position: node.translation.unwrap_or_default().into(),
-                    direction: node
-                        .rotation
-                        .map(|r| Quat::from_array(r.0))
-                        .unwrap_or_default()
-                        .mul_vec3(Vec3::Z),
+                    position: translation,
+                    direction: rotation.mul_vec3(Vec3::Z),
                     color: light.color.into(),
                     intensity: light.intensity,
                     radius: 1.,
@@ -330,17 +419,20 @@ fn load_light(
 
 fn load_mesh(
     json: &Root,
-    node: &Node,
+    world: Mat4,
     index: Index<gltf::json::Mesh>,
     buffers: &Vec<Vec<u8>>,
     textures: &Vec<TextureId>,
-) -> (Mesh, PbrMaterial) {
+    normal_mode: NormalGenerationMode,
+) -> GltfLoadResult<(Mesh, PbrMaterial)> {
     let gltf_mesh = json.get(index).unwrap();
 
     let mut positions = Vec::new();
     let mut normals = Vec::new();
     let mut tangents = Vec::new();
     let mut texcoords = Vec::new();
+    let mut texcoords_1 = Vec::new();
+    let mut colors = Vec::new();
 
     let material = load_material(
         json,
@@ -405,7 +497,28 @@ fn load_mesh(
                             .map(Vec4::from_slice),
                     );
                 }
-                Semantic::Colors(_) => todo!(),
+                Semantic::Colors(0) => {
+                    let components = match accessor.type_.unwrap() {
+                        gltf::json::accessor::Type::Vec3 => 3,
+                        gltf::json::accessor::Type::Vec4 => 4,
+                        _ => unreachable!(),
+                    };
+                    let data: Vec<f32> = match data_type {
+                        DataType::F32 => bytemuck::cast_slice(buffer).to_vec(),
+                        DataType::U8 => buffer.iter().map(|c| *c as f32 / 255.).collect(),
+                        DataType::U16 => bytemuck::cast_slice::<_, u16>(buffer)
+                            .iter()
+                            .map(|c| *c as f32 / 65535.)
+                            .collect(),
+                        _ => unreachable!(),
+                    };
+                    colors.extend(data.chunks_exact(components).map(|c| match c {
+                        [r, g, b] => Vec4::new(*r, *g, *b, 1.),
+                        _ => Vec4::from_slice(c),
+                    }));
+                }
+                // Only the first color set is used.
+                Semantic::Colors(_) => {}
                 Semantic::TexCoords(0) => {
                     assert_eq!(data_type, DataType::F32);
                     texcoords.extend(
@@ -414,7 +527,14 @@ fn load_mesh(
                             .map(Vec2::from_slice),
                     );
                 }
-                Semantic::TexCoords(1) => todo!(),
+                Semantic::TexCoords(1) => {
+                    assert_eq!(data_type, DataType::F32);
+                    texcoords_1.extend(
+                        bytemuck::cast_slice(buffer)
+                            .chunks_exact(2)
+                            .map(Vec2::from_slice),
+                    );
+                }
                 Semantic::TexCoords(_) => todo!(),
                 Semantic::Joints(_) => todo!(),
                 Semantic::Weights(_) => todo!(),
@@ -425,18 +545,39 @@ fn load_mesh(
     mesh.insert_attribute(
         Mesh::POSITION_ATTR,
         MeshVertexAttributeData::Float32x3(positions),
-    )
-    .insert_attribute(
-        Mesh::NORMAL_ATTR,
-        MeshVertexAttributeData::Float32x3(normals),
-    )
-    .insert_attribute(
-        Mesh::TEX_COORDS_ATTR,
-        MeshVertexAttributeData::Float32x2(texcoords),
     );
 
+    if !texcoords.is_empty() {
+        mesh.insert_attribute(
+            Mesh::TEX_COORDS_ATTR,
+            MeshVertexAttributeData::Float32x2(texcoords),
+        );
+    }
+
+    if !texcoords_1.is_empty() {
+        mesh.insert_attribute(
+            Mesh::TEX_COORDS_1_ATTR,
+            MeshVertexAttributeData::Float32x2(texcoords_1),
+        );
+    }
+
+    if !colors.is_empty() {
+        mesh.insert_attribute(Mesh::COLOR_ATTR, MeshVertexAttributeData::Float32x4(colors));
+    }
+
+    // Generated last, as it may split vertices. Tangents are ignored without normals.
+    if normals.is_empty() {
+        mesh.recalculate_normals(normal_mode);
+        tangents.clear();
+    } else {
+        mesh.insert_attribute(
+            Mesh::NORMAL_ATTR,
+            MeshVertexAttributeData::Float32x3(normals),
+        );
+    }
+
     if tangents.is_empty() {
-        mesh.recalculate_tangent();
+        mesh.recalculate_tangent()?;
     } else {
         mesh.insert_attribute(
             Mesh::TANGENT_ATTR,
@@ -444,15 +585,9 @@ fn load_mesh(
         );
     }
 
-    mesh.transform(Mat4::from_scale_rotation_translation(
-        node.scale.map(Vec3::from_array).unwrap_or(Vec3::ONE),
-        node.rotation
-            .map(|r| Quat::from_array(r.0))
-            .unwrap_or_default(),
-        node.translation.unwrap_or_default().into(),
-    ));
+    mesh.transform(world);
 
-    (mesh, material)
+    Ok((mesh, material))
 }
 
 fn load_material(
@@ -466,23 +601,290 @@ fn load_material(
 
     let material = json.get(index).unwrap();
     let met_rough = &material.pbr_metallic_roughness;
+    let spec_gloss = material
+        .extensions
+        .as_ref()
+        .and_then(|ext| ext.pbr_specular_glossiness.as_ref());
+
+    // Specular glossiness is preferred when both are present.
+    let surface = match spec_gloss {
+        Some(spec_gloss) => PbrMaterial {
+            alpha: spec_gloss.diffuse_factor.0[3],
+            tex_base_color: spec_gloss
+                .diffuse_texture
+                .as_ref()
+                .map(|info| textures[info.index.value()]),
+            // The specular glossiness texture can't be converted without rewriting its texels,
+            // so only the factors are used.
+            ..PbrMaterial::from_specular_glossiness(
+                Vec4::from(spec_gloss.diffuse_factor.0).truncate(),
+                Vec3::from(spec_gloss.specular_factor.0),
+                spec_gloss.glossiness_factor.0,
+            )
+        },
+        None => PbrMaterial {
+            base_color: Srgb::from_components((
+                met_rough.base_color_factor.0[0],
+                met_rough.base_color_factor.0[1],
+                met_rough.base_color_factor.0[2],
+            )),
+            alpha: met_rough.base_color_factor.0[3],
+            tex_base_color: met_rough
+                .base_color_texture
+                .as_ref()
+                .map(|info| textures[info.index.value()]),
+            roughness: met_rough.roughness_factor.0,
+            metallic: met_rough.metallic_factor.0,
+            ..Default::default()
+        },
+    };
 
     PbrMaterial {
-        base_color: Srgb::from_components((
-            met_rough.base_color_factor.0[0],
-            met_rough.base_color_factor.0[1],
-            met_rough.base_color_factor.0[2],
-        )),
-        tex_base_color: met_rough
-            .base_color_texture
-            .as_ref()
-            .map(|info| textures[info.index.value()]),
+        alpha_mode: match material.alpha_mode.unwrap() {
+            GltfAlphaMode::Opaque => AlphaMode::Opaque,
+            GltfAlphaMode::Mask => {
+                AlphaMode::Mask(material.alpha_cutoff.map_or(0.5, |cutoff| cutoff.0))
+            }
+            GltfAlphaMode::Blend => AlphaMode::Blend,
+        },
+        double_sided: material.double_sided,
         tex_normal: material
             .normal_texture
             .as_ref()
             .map(|info| textures[info.index.value()]),
-        roughness: met_rough.roughness_factor.0,
-        metallic: met_rough.metallic_factor.0,
-        reflectance: 0.5,
+        tex_occlusion: material
+            .occlusion_texture
+            .as_ref()
+            .map(|info| textures[info.index.value()]),
+        occlusion_uv_set: material
+            .occlusion_texture
+            .as_ref()
+            .map_or(0, |info| info.tex_coord.min(1) as u8),
+        occlusion_strength: material
+            .occlusion_texture
+            .as_ref()
+            .map_or(1., |info| info.strength.0),
+        emissive: Srgb::from_components((
+            material.emissive_factor.0[0],
+            material.emissive_factor.0[1],
+            material.emissive_factor.0[2],
+        )),
+        emissive_strength: material
+            .extensions
+            .as_ref()
+            .and_then(|ext| ext.emissive_strength.as_ref())
+            .map_or(1., |strength| strength.emissive_strength.0),
+        tex_emissive: material
+            .emissive_texture
+            .as_ref()
+            .map(|info| textures[info.index.value()]),
+        ..surface
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aurora_core::{
+        render::{
+            helper::CameraProjection,
+            mesh::{AlphaMode, Mesh, MeshVertexAttributeData},
+            scene::TextureId,
+        },
+        util::ext::RgbToVec3,
+        util::testing,
+    };
+    use glam::{Mat4, Vec3, Vec4};
+    use gltf::{json::Index, Gltf};
+    use wgpu::VertexFormat;
+
+    use crate::material::SpecularWorkflow;
+
+    use super::{load_buffers_data, load_gltf, load_material, load_mesh, node_world_transforms};
+
+    #[test]
+    fn nested_node_transforms() {
+        let model = Gltf::open(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/nested_hierarchy.gltf"
+        ))
+        .unwrap();
+        let json = model.as_json();
+        let buffers = load_buffers_data(&model).unwrap();
+
+        let transforms = node_world_transforms(json);
+        let &(_, child_world) = transforms.iter().find(|(node, _)| *node == 1).unwrap();
+        let (mesh, _) = load_mesh(
+            json,
+            child_world,
+            json.nodes[1].mesh.unwrap(),
+            &buffers,
+            &Vec::new(),
+            Default::default(),
+        )
+        .unwrap();
+
+        let Some(MeshVertexAttributeData::Float32x3(positions)) =
+            mesh.attribute(Mesh::POSITION_ATTR)
+        else {
+            panic!("Positions should be Float32x3.");
+        };
+
+        let parent = Mat4::from_scale_rotation_translation(
+            Vec3::splat(2.),
+            Default::default(),
+            Vec3::new(10., 0., 0.),
+        );
+        let child = Mat4::from_translation(Vec3::Y);
+        for (position, local) in positions.iter().zip([Vec3::ZERO, Vec3::X, Vec3::Z]) {
+            let expected = (parent * child).transform_point3(local);
+            assert!(position.abs_diff_eq(expected, 1e-5));
+        }
+        assert!(positions[0].abs_diff_eq(Vec3::new(10., 2., 0.), 1e-5));
+    }
+
+    #[test]
+    fn vertex_colors() {
+        let model = Gltf::open(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/vertex_colors.gltf"
+        ))
+        .unwrap();
+        let json = model.as_json();
+        let buffers = load_buffers_data(&model).unwrap();
+        let (mesh, _) = load_mesh(
+            json,
+            Mat4::IDENTITY,
+            json.nodes[0].mesh.unwrap(),
+            &buffers,
+            &Vec::new(),
+            Default::default(),
+        )
+        .unwrap();
+
+        let Some(MeshVertexAttributeData::Float32x4(colors)) = mesh.attribute(Mesh::COLOR_ATTR)
+        else {
+            panic!("Colors should be Float32x4.");
+        };
+        assert_eq!(colors[0], Vec4::new(1., 0., 0., 1.));
+        assert!(colors[2].abs_diff_eq(Vec4::new(0., 0., 1., 128. / 255.), 1e-6));
+
+        // Color comes right after the tangent, which is generated for this mesh.
+        let color = mesh.vertex_attributes()[4];
+        assert_eq!(color.format, VertexFormat::Float32x4);
+        assert_eq!(color.offset, 12 + 12 + 8 + 16);
+        assert_eq!(mesh.vertex_stride(), 12 + 12 + 8 + 16 + 16);
+    }
+
+    #[test]
+    fn emissive_material() {
+        let model = Gltf::open(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/emissive.gltf"
+        ))
+        .unwrap();
+        let json = model.as_json();
+        let emitted = |index: u32| {
+            let material = load_material(json, Some(Index::new(index)), &Vec::new());
+            material.emissive.into_linear().to_vec3() * material.emissive_strength
+        };
+
+        let neon = emitted(0);
+        assert_eq!(neon.x, 4.);
+        let plain = emitted(1);
+        assert_eq!(plain, Vec3::ZERO);
+    }
+
+    #[test]
+    fn alpha_modes() {
+        let model = Gltf::open(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/alpha_modes.gltf"
+        ))
+        .unwrap();
+        let json = model.as_json();
+        let material = |index: u32| load_material(json, Some(Index::new(index)), &Vec::new());
+
+        assert_eq!(material(0).alpha_mode, AlphaMode::Opaque);
+        assert_eq!(material(1).alpha_mode, AlphaMode::Mask(0.25));
+        let glass = material(2);
+        assert_eq!(glass.alpha_mode, AlphaMode::Blend);
+        assert_eq!(glass.alpha, 0.3);
+        assert!(glass.double_sided);
+        assert!(!material(0).double_sided);
+        assert_eq!(material(3).alpha_mode, AlphaMode::Mask(0.5));
+    }
+
+    #[test]
+    fn specular_glossiness() {
+        let model = Gltf::open(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/spec_gloss.gltf"
+        ))
+        .unwrap();
+        let json = model.as_json();
+        let material = |index: u32| load_material(json, Some(Index::new(index)), &Vec::new());
+
+        let plastic = material(0);
+        assert_eq!(plastic.workflow, SpecularWorkflow::SpecularGlossiness);
+        assert_eq!(plastic.metallic, 0.);
+        assert!((plastic.roughness - 0.25).abs() < 1e-6);
+        // 2% specular, instead of the default 4%.
+        assert!((0.16 * plastic.reflectance * plastic.reflectance - 0.02).abs() < 1e-6);
+        assert!(Vec3::from(plastic.base_color.into_components())
+            .abs_diff_eq(Vec3::splat(0.5 * 0.98 / 0.96), 1e-4));
+
+        let gold = material(1);
+        assert!((gold.metallic - 1.).abs() < 1e-4);
+        assert!(Vec3::from(gold.base_color.into_components())
+            .abs_diff_eq(Vec3::new(1., 0.8, 0.3), 1e-3));
+    }
+
+    #[test]
+    fn occlusion_strength() {
+        let model = Gltf::open(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/occlusion.gltf"
+        ))
+        .unwrap();
+        let json = model.as_json();
+        let textures = vec![TextureId::default()];
+        let material = |index: u32| load_material(json, Some(Index::new(index)), &textures);
+
+        let baked = material(0);
+        assert_eq!(baked.tex_occlusion, Some(textures[0]));
+        assert_eq!(baked.occlusion_uv_set, 1);
+        assert_eq!(baked.occlusion_strength, 0.4);
+        // Irrelevant without a texture, so left at the default.
+        let plain = material(1);
+        assert_eq!(plain.tex_occlusion, None);
+        assert_eq!(plain.occlusion_strength, 1.);
+    }
+
+    #[test]
+    fn multiple_cameras() {
+        let Some(renderer) = testing::renderer(None, None) else {
+            return;
+        };
+
+        let scene = load_gltf(
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/tests/fixtures/multiple_cameras.gltf"
+            ),
+            &renderer.device,
+            &renderer.queue,
+            &Default::default(),
+        )
+        .unwrap()
+        .original;
+
+        assert_eq!(scene.cameras.len(), 2);
+        let active = scene.active_camera.unwrap();
+        assert_eq!(scene.cameras[&active], scene.camera);
+        assert_eq!(scene.camera.transform.translation, Vec3::new(0., 0., 5.));
+        assert!(scene
+            .cameras
+            .values()
+            .any(|camera| matches!(camera.projection, CameraProjection::Orthographic(_))));
     }
 }
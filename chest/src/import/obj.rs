@@ -1,53 +1,176 @@
-use std::path::Path;
+use std::{collections::HashMap, path::Path};
 
-use aurora_core::render::mesh::{Mesh, MeshVertexAttributeData};
+use aurora_core::render::mesh::{Mesh, MeshIndices, MeshVertexAttributeData, NormalGenerationMode};
+use glam::{Vec2, Vec3};
 
-pub fn mesh_from_obj(path: impl AsRef<Path>) -> Vec<Mesh> {
+fn load_obj(path: impl AsRef<Path>) -> obj::ObjData {
     let mut source = Vec::new();
     std::io::Read::read_to_end(&mut std::fs::File::open(path).unwrap(), &mut source).unwrap();
-    let obj = obj::ObjData::load_buf(&source[..]).unwrap();
+    obj::ObjData::load_buf(&source[..]).unwrap()
+}
+
+/// Triangulated corners of the object, as `(position, texcoord, normal)` indices.
+fn triangulate(object: &obj::Object) -> impl Iterator<Item = obj::IndexTuple> + '_ {
+    object.groups.iter().flat_map(|group| {
+        group.polys.iter().flat_map(|poly| {
+            (2..poly.0.len())
+                .flat_map(move |end_index| [0, end_index - 1, end_index].map(|i| poly.0[i]))
+        })
+    })
+}
+
+/// Normals are recalculated with `normal_mode` if any corner lacks one, after the indices
+/// are set.
+fn build_mesh(
+    positions: Vec<Vec3>,
+    normals: Option<Vec<Vec3>>,
+    texcoords: Vec<Vec2>,
+    indices: Option<MeshIndices>,
+    normal_mode: NormalGenerationMode,
+) -> Mesh {
+    let mut mesh = Mesh::new()
+        .with_attribute(
+            Mesh::POSITION_ATTR,
+            MeshVertexAttributeData::Float32x3(positions),
+        )
+        .with_attribute(
+            Mesh::TEX_COORDS_ATTR,
+            MeshVertexAttributeData::Float32x2(texcoords),
+        );
+    if let Some(indices) = indices {
+        mesh.insert_indices(indices);
+    }
+
+    match normals {
+        Some(normals) => {
+            mesh.insert_attribute(
+                Mesh::NORMAL_ATTR,
+                MeshVertexAttributeData::Float32x3(normals),
+            );
+        }
+        None => mesh.recalculate_normals(normal_mode),
+    }
+    mesh.recalculate_tangent()
+        .expect("Obj meshes always have positions, normals and uvs.");
+    mesh
+}
 
+/// Objects without normals get them generated with `normal_mode`. Their corners aren't
+/// shared, so [`NormalGenerationMode::Smooth`] gives flat normals here.
+pub fn mesh_from_obj(path: impl AsRef<Path>, normal_mode: NormalGenerationMode) -> Vec<Mesh> {
+    let obj = load_obj(path);
     let mut meshes = Vec::new();
 
-    for object in obj.objects {
+    for object in &obj.objects {
         let mut positions = Vec::new();
         let mut normals = Vec::new();
         let mut texcoords = Vec::new();
 
-        for group in object.groups {
-            for poly in group.polys {
-                for end_index in 2..poly.0.len() {
-                    for &index in &[0, end_index - 1, end_index] {
-                        let obj::IndexTuple(position_id, Some(texture_id), Some(normal_id)) =
-                            poly.0[index]
-                        else {
-                            unreachable!()
-                        };
-
-                        positions.push(obj.position[position_id].into());
-                        normals.push(obj.normal[normal_id].into());
-                        texcoords.push(obj.texture[texture_id].into());
-                    }
-                }
-            }
+        for obj::IndexTuple(position_id, texture_id, normal_id) in triangulate(object) {
+            positions.push(obj.position[position_id].into());
+            normals.push(normal_id.map(|id| Vec3::from(obj.normal[id])));
+            // Files without texture coordinates still get a valid uv attribute.
+            texcoords.push(texture_id.map_or([0.; 2], |id| obj.texture[id]).into());
         }
 
-        let mut mesh = Mesh::new()
-            .with_attribute(
-                Mesh::POSITION_ATTR,
-                MeshVertexAttributeData::Float32x3(positions),
-            )
-            .with_attribute(
-                Mesh::NORMAL_ATTR,
-                MeshVertexAttributeData::Float32x3(normals),
-            )
-            .with_attribute(
-                Mesh::TEX_COORDS_ATTR,
-                MeshVertexAttributeData::Float32x2(texcoords),
-            );
-        mesh.recalculate_tangent();
-        meshes.push(mesh);
+        let normals = normals.into_iter().collect();
+        meshes.push(build_mesh(positions, normals, texcoords, None, normal_mode));
     }
 
     meshes
 }
+
+/// Same as [`mesh_from_obj`], but corners sharing position, normal and uv are
+/// deduplicated into a single vertex referenced by an index buffer.
+pub fn mesh_from_obj_indexed(
+    path: impl AsRef<Path>,
+    normal_mode: NormalGenerationMode,
+) -> Vec<Mesh> {
+    let obj = load_obj(path);
+    let mut meshes = Vec::new();
+
+    for object in &obj.objects {
+        let mut positions = Vec::new();
+        let mut normals = Vec::new();
+        let mut texcoords = Vec::new();
+        let mut indices = Vec::new();
+        let mut vertices = HashMap::new();
+
+        for obj::IndexTuple(position_id, texture_id, normal_id) in triangulate(object) {
+            let index = *vertices
+                .entry((position_id, texture_id, normal_id))
+                .or_insert_with(|| {
+                    positions.push(obj.position[position_id].into());
+                    normals.push(normal_id.map(|id| Vec3::from(obj.normal[id])));
+                    texcoords.push(texture_id.map_or([0.; 2], |id| obj.texture[id]).into());
+                    positions.len() as u32 - 1
+                });
+            indices.push(index);
+        }
+
+        let normals = normals.into_iter().collect();
+        let indices = Some(MeshIndices::UInt32(indices));
+        meshes.push(build_mesh(
+            positions,
+            normals,
+            texcoords,
+            indices,
+            normal_mode,
+        ));
+    }
+
+    meshes
+}
+
+#[cfg(test)]
+mod tests {
+    use aurora_core::render::mesh::{Mesh, MeshIndices, MeshVertexAttributeData};
+    use glam::Vec2;
+
+    use super::{mesh_from_obj, mesh_from_obj_indexed};
+
+    fn texcoords(mesh: &Mesh) -> &[Vec2] {
+        let Some(MeshVertexAttributeData::Float32x2(texcoords)) =
+            mesh.attribute(Mesh::TEX_COORDS_ATTR)
+        else {
+            panic!("Texture coordinates should be Float32x2.");
+        };
+        texcoords
+    }
+
+    #[test]
+    fn obj_texcoords() {
+        let meshes = mesh_from_obj(
+            concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/uv_quad.obj"),
+            Default::default(),
+        );
+        assert_eq!(meshes.len(), 1);
+        // The quad is triangulated into 2 triangles.
+        let quad = texcoords(&meshes[0]);
+        assert_eq!(quad.len(), 6);
+        assert_eq!(quad[2], Vec2::new(1., 1.));
+
+        let meshes = mesh_from_obj(
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/tests/fixtures/no_uv_triangle.obj"
+            ),
+            Default::default(),
+        );
+        assert!(texcoords(&meshes[0]).iter().all(|uv| *uv == Vec2::ZERO));
+    }
+
+    #[test]
+    fn obj_indexed() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/uv_quad.obj");
+        let plain = mesh_from_obj(path, Default::default());
+        let indexed = mesh_from_obj_indexed(path, Default::default());
+
+        let Some(MeshIndices::UInt32(indices)) = indexed[0].indices() else {
+            panic!("Indexed mesh should have u32 indices.");
+        };
+        assert_eq!(indices.len() / 3, plain[0].vertices_count() / 3);
+        // Both triangles share the diagonal, so only the 4 corners remain.
+        assert_eq!(indexed[0].vertices_count(), 4);
+    }
+}
@@ -1,6 +1,7 @@
 pub mod import;
 pub mod material;
 pub mod node;
+pub mod scene;
 pub mod shader_defs;
 pub mod texture;
 pub mod util;
@@ -1,3 +1,7 @@
 mod pbr;
+mod unlit;
 
-pub use pbr::{PbrMaterial, PbrMaterialUniform};
+pub use pbr::{
+    PbrMaterial, PbrMaterialUniform, SpecularWorkflow, VertexAnimationTexture, WindConfig,
+};
+pub use unlit::{UnlitMaterial, UnlitMaterialUniform};
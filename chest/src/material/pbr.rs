@@ -2,11 +2,14 @@ use std::any::TypeId;
 
 use aurora_core::{
     render::{
-        mesh::{CreateBindGroupLayout, Material},
+        mesh::{AlphaMode, CreateBindGroupLayout, Material, VertexDisplacement},
         resource::DUMMY_2D_TEX,
         scene::{GpuAssets, MaterialInstanceId, MaterialTypeId, TextureId},
     },
-    util::ext::{RgbToVec3, TypeIdAsUuid},
+    util::{
+        create_sampler,
+        ext::{RgbToVec3, TypeIdAsUuid},
+    },
 };
 use encase::ShaderType;
 use glam::Vec3;
@@ -20,25 +23,176 @@ use wgpu::{
 
 use crate::node::TONY_MC_MAPFACE_LUT;
 
+/// Which workflow the material was authored in.
+///
+/// Only metallic roughness is handled by the shader, specular glossiness
+/// parameters are converted when the material is created.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SpecularWorkflow {
+    #[default]
+    MetallicRoughness,
+    SpecularGlossiness,
+}
+
+/// Per vertex animation baked into a texture, for crowds, foliage and the like.
+///
+/// The texture holds object space position offsets, with vertex `i` of frame `f` at
+/// `(i % width, f * rows + i / width)`, where `rows` is the number of rows each frame
+/// takes, so meshes with more vertices than fit in a row wrap onto the next one. When
+/// `normals` is set, the normals of all frames follow the offsets in the same layout,
+/// starting at row `frames * rows`. Only `rgb` is read, a float format like
+/// [`TextureFormat::Rgba32Float`] is expected.
+///
+/// Frames are interpolated and loop. Other passes, like shadows and prepasses, still
+/// see the rest pose.
+///
+/// [`TextureFormat::Rgba32Float`]: wgpu::TextureFormat::Rgba32Float
+#[derive(Debug, Clone, Copy)]
+pub struct VertexAnimationTexture {
+    pub texture: TextureId,
+    pub frames: u32,
+    pub fps: f32,
+    /// Added to the scene time, to desync instances sharing the animation.
+    pub time_offset: f32,
+    pub normals: bool,
+}
+
+/// Sways vertices with the wind, for vegetation.
+///
+/// The red channel of the vertex colors is the stiffness, from 0 moving freely, like leaves,
+/// to 1 not moving at all, like trunks. Note the vertex colors are still multiplied with the
+/// base color. Without vertex colors, the whole mesh sways.
+#[derive(Debug, Clone, Copy)]
+pub struct WindConfig {
+    /// Displacement in world units at the peak of a gust.
+    pub strength: f32,
+    /// Gusts per second.
+    pub frequency: f32,
+    /// Direction the wind blows towards, doesn't need to be normalized.
+    pub direction: Vec3,
+}
+
+impl Default for WindConfig {
+    fn default() -> Self {
+        Self {
+            strength: 0.1,
+            frequency: 1.,
+            direction: Vec3::X,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct PbrMaterial {
+    pub workflow: SpecularWorkflow,
     pub base_color: Srgb,
+    /// Multiplied with the alpha of `tex_base_color`, ignored when opaque.
+    pub alpha: f32,
+    pub alpha_mode: AlphaMode,
+    /// Disables back face culling, back faces are shaded with the flipped normal.
+    pub double_sided: bool,
+    /// Smooth the cutout of [`AlphaMode::Mask`] using the MSAA samples, like for foliage.
+    ///
+    /// Ignored in other alpha modes and without MSAA.
+    pub alpha_to_coverage: bool,
     pub tex_base_color: Option<TextureId>,
     pub tex_normal: Option<TextureId>,
+    /// Ambient occlusion in the red channel, darkens environment lighting.
+    pub tex_occlusion: Option<TextureId>,
+    /// Which uv set `tex_occlusion` is sampled with, 0 or 1.
+    pub occlusion_uv_set: u8,
+    /// How much of `tex_occlusion` is applied, from 0 ignoring it to 1 applying it fully.
+    pub occlusion_strength: f32,
     pub roughness: f32,
     pub metallic: f32,
+    /// Specular of dielectrics, `F0 = 0.16 * reflectance²`.
     pub reflectance: f32,
+    pub emissive: Srgb,
+    /// Multiplier of `emissive`. Emission is added after exposure, so a strength of
+    /// 1 maps to 1 in the color target regardless of the camera.
+    pub emissive_strength: f32,
+    pub tex_emissive: Option<TextureId>,
+    pub vertex_animation: Option<VertexAnimationTexture>,
+    pub wind: Option<WindConfig>,
 }
 
 impl Default for PbrMaterial {
     fn default() -> Self {
         Self {
+            workflow: SpecularWorkflow::MetallicRoughness,
             base_color: Srgb::new(1., 1., 1.),
+            alpha: 1.,
+            alpha_mode: AlphaMode::Opaque,
+            double_sided: false,
+            alpha_to_coverage: false,
             tex_base_color: Default::default(),
             tex_normal: Default::default(),
+            tex_occlusion: Default::default(),
+            occlusion_uv_set: 0,
+            occlusion_strength: 1.,
             roughness: 1.,
             metallic: 0.,
             reflectance: 0.5,
+            emissive: Srgb::new(0., 0., 0.),
+            emissive_strength: 1.,
+            tex_emissive: Default::default(),
+            vertex_animation: None,
+            wind: None,
+        }
+    }
+}
+
+impl PbrMaterial {
+    /// F0 of common dielectrics, matching the default reflectance of 0.5.
+    pub const DIELECTRIC_F0: f32 = 0.04;
+
+    /// Reflectance giving the dielectric `f0`, for authoring F0 directly.
+    pub fn reflectance_from_f0(f0: f32) -> f32 {
+        (f0 / 0.16).sqrt().clamp(0., 1.)
+    }
+
+    /// Convert linear specular glossiness parameters to metallic roughness.
+    ///
+    /// Dielectrics keep their specular color as reflectance instead of the default 4%.
+    pub fn from_specular_glossiness(diffuse: Vec3, specular: Vec3, glossiness: f32) -> Self {
+        fn perceived_brightness(color: Vec3) -> f32 {
+            (color * color).dot(Vec3::new(0.299, 0.587, 0.114)).sqrt()
+        }
+
+        let f0 = Self::DIELECTRIC_F0;
+        let one_minus_specular_strength = 1. - specular.max_element();
+        let diffuse_brightness = perceived_brightness(diffuse);
+        let specular_brightness = perceived_brightness(specular);
+
+        let metallic = if specular_brightness <= f0 {
+            0.
+        } else {
+            let b = diffuse_brightness * one_minus_specular_strength / (1. - f0)
+                + specular_brightness
+                - 2. * f0;
+            let c = f0 - specular_brightness;
+            let d = (b * b - 4. * f0 * c).max(0.);
+            ((-b + d.sqrt()) / (2. * f0)).clamp(0., 1.)
+        };
+
+        let from_diffuse =
+            diffuse * one_minus_specular_strength / (1. - f0) / (1. - metallic).max(1e-4);
+        let from_specular = (specular - f0 * (1. - metallic)) / metallic.max(1e-4);
+        let base_color = from_diffuse
+            .lerp(from_specular, metallic * metallic)
+            .clamp(Vec3::ZERO, Vec3::ONE);
+
+        Self {
+            workflow: SpecularWorkflow::SpecularGlossiness,
+            base_color: Srgb::from_components(base_color.into()),
+            roughness: 1. - glossiness,
+            metallic,
+            reflectance: if metallic == 0. {
+                Self::reflectance_from_f0(specular.max_element())
+            } else {
+                0.5
+            },
+            ..Default::default()
         }
     }
 }
@@ -49,6 +203,19 @@ pub struct PbrMaterialUniform {
     pub roughness: f32,
     pub metallic: f32,
     pub ior: f32,
+    pub occlusion_uv_set: u32,
+    pub occlusion_strength: f32,
+    pub emissive: Vec3,
+    pub emissive_strength: f32,
+    pub alpha: f32,
+    pub alpha_cutoff: f32,
+    pub vat_frames: u32,
+    pub vat_fps: f32,
+    pub vat_time_offset: f32,
+    pub vat_normals: u32,
+    pub wind_direction: Vec3,
+    pub wind_strength: f32,
+    pub wind_frequency: f32,
 }
 
 impl CreateBindGroupLayout for PbrMaterial {
@@ -61,7 +228,7 @@ impl CreateBindGroupLayout for PbrMaterial {
                     // Material Uniform
                     BindGroupLayoutEntry {
                         binding: 0,
-                        visibility: ShaderStages::FRAGMENT,
+                        visibility: ShaderStages::VERTEX_FRAGMENT,
                         ty: BindingType::Buffer {
                             ty: BufferBindingType::Uniform,
                             has_dynamic_offset: true,
@@ -116,6 +283,39 @@ impl CreateBindGroupLayout for PbrMaterial {
                         ty: BindingType::Sampler(SamplerBindingType::Filtering),
                         count: None,
                     },
+                    // tex_occlusion
+                    BindGroupLayoutEntry {
+                        binding: 6,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Texture {
+                            sample_type: TextureSampleType::Float { filterable: true },
+                            view_dimension: TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    // tex_emissive
+                    BindGroupLayoutEntry {
+                        binding: 7,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Texture {
+                            sample_type: TextureSampleType::Float { filterable: true },
+                            view_dimension: TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    // tex_vertex_animation
+                    BindGroupLayoutEntry {
+                        binding: 8,
+                        visibility: ShaderStages::VERTEX,
+                        ty: BindingType::Texture {
+                            sample_type: TextureSampleType::Float { filterable: false },
+                            view_dimension: TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
                 ],
             }),
         );
@@ -162,13 +362,16 @@ impl Material for PbrMaterial {
                 },
                 BindGroupEntry {
                     binding: 3,
-                    resource: BindingResource::Sampler(&device.create_sampler(
+                    resource: BindingResource::Sampler(&create_sampler(
+                        device,
                         &SamplerDescriptor {
                             mag_filter: FilterMode::Linear,
                             min_filter: FilterMode::Linear,
                             mipmap_filter: FilterMode::Linear,
+                            anisotropy_clamp: assets.max_anisotropy,
                             ..Default::default()
                         },
+                        assets.max_anisotropy,
                     )),
                 },
                 BindGroupEntry {
@@ -180,15 +383,40 @@ impl Material for PbrMaterial {
                 },
                 BindGroupEntry {
                     binding: 5,
-                    resource: BindingResource::Sampler(&device.create_sampler(
+                    resource: BindingResource::Sampler(&create_sampler(
+                        device,
                         &SamplerDescriptor {
                             mag_filter: FilterMode::Linear,
                             min_filter: FilterMode::Linear,
                             mipmap_filter: FilterMode::Linear,
                             ..Default::default()
                         },
+                        assets.max_anisotropy,
                     )),
                 },
+                BindGroupEntry {
+                    binding: 6,
+                    resource: BindingResource::TextureView(
+                        &assets.textures[&self.tex_occlusion.unwrap_or(DUMMY_2D_TEX)]
+                            .create_view(&TextureViewDescriptor::default()),
+                    ),
+                },
+                BindGroupEntry {
+                    binding: 7,
+                    resource: BindingResource::TextureView(
+                        &assets.textures[&self.tex_emissive.unwrap_or(DUMMY_2D_TEX)]
+                            .create_view(&TextureViewDescriptor::default()),
+                    ),
+                },
+                BindGroupEntry {
+                    binding: 8,
+                    resource: BindingResource::TextureView(
+                        &assets.textures[&self
+                            .vertex_animation
+                            .map_or(DUMMY_2D_TEX, |vat| vat.texture)]
+                            .create_view(&TextureViewDescriptor::default()),
+                    ),
+                },
             ],
         });
 
@@ -198,12 +426,58 @@ impl Material for PbrMaterial {
     }
 
     fn prepare(&self, _device: &Device, assets: &mut GpuAssets) -> u32 {
+        let vat = self.vertex_animation;
+        let wind = self.wind.unwrap_or_default();
         let buffer = assets.material_uniforms.get_mut(&self.id()).unwrap();
         buffer.push(&PbrMaterialUniform {
             base_color: self.base_color.into_linear().to_vec3(),
             roughness: self.roughness,
             metallic: self.metallic,
             ior: self.reflectance,
+            occlusion_uv_set: self.occlusion_uv_set as u32,
+            occlusion_strength: self.occlusion_strength,
+            emissive: self.emissive.into_linear().to_vec3(),
+            emissive_strength: self.emissive_strength,
+            alpha: self.alpha,
+            alpha_cutoff: match self.alpha_mode {
+                AlphaMode::Mask(cutoff) => cutoff,
+                _ => 0.,
+            },
+            vat_frames: vat.map_or(0, |vat| vat.frames.max(1)),
+            vat_fps: vat.map_or(0., |vat| vat.fps),
+            vat_time_offset: vat.map_or(0., |vat| vat.time_offset),
+            vat_normals: vat.is_some_and(|vat| vat.normals) as u32,
+            wind_direction: wind.direction.normalize_or_zero(),
+            wind_strength: wind.strength,
+            wind_frequency: wind.frequency,
         })
     }
+
+    fn alpha_mode(&self) -> AlphaMode {
+        self.alpha_mode
+    }
+
+    fn double_sided(&self) -> bool {
+        self.double_sided
+    }
+
+    fn alpha_to_coverage(&self) -> bool {
+        self.alpha_to_coverage
+    }
+
+    fn vertex_displacement(&self) -> VertexDisplacement {
+        let mut displacement = VertexDisplacement::empty();
+        displacement.set(
+            VertexDisplacement::ANIMATION_TEXTURE,
+            self.vertex_animation.is_some(),
+        );
+        displacement.set(VertexDisplacement::WIND, self.wind.is_some());
+        displacement
+    }
+
+    /// Only the constant `base_color` and `alpha` are used, textures are ignored.
+    fn shadow_transmittance(&self) -> Option<Vec3> {
+        (self.alpha_mode == AlphaMode::Blend)
+            .then(|| Vec3::ONE.lerp(self.base_color.into_linear().to_vec3(), self.alpha))
+    }
 }
@@ -0,0 +1,144 @@
+use std::any::TypeId;
+
+use aurora_core::{
+    render::{
+        mesh::{CreateBindGroupLayout, Material},
+        resource::DUMMY_2D_TEX,
+        scene::{GpuAssets, MaterialInstanceId, MaterialTypeId, TextureId},
+    },
+    util::{
+        create_sampler,
+        ext::{RgbToVec3, TypeIdAsUuid},
+    },
+};
+use encase::ShaderType;
+use glam::Vec3;
+use palette::Srgb;
+use wgpu::{
+    BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor, BindGroupLayoutEntry,
+    BindingResource, BindingType, BufferBindingType, Device, FilterMode, SamplerBindingType,
+    SamplerDescriptor, ShaderStages, TextureSampleType, TextureViewDescriptor,
+    TextureViewDimension,
+};
+
+/// Flat color unaffected by lights and exposure, drawn by [`UnlitNode`](crate::node::UnlitNode).
+#[derive(Clone)]
+pub struct UnlitMaterial {
+    pub base_color: Srgb,
+    pub tex_base_color: Option<TextureId>,
+}
+
+impl Default for UnlitMaterial {
+    fn default() -> Self {
+        Self {
+            base_color: Srgb::new(1., 1., 1.),
+            tex_base_color: Default::default(),
+        }
+    }
+}
+
+#[derive(ShaderType)]
+pub struct UnlitMaterialUniform {
+    pub base_color: Vec3,
+}
+
+impl CreateBindGroupLayout for UnlitMaterial {
+    fn create_layout(device: &Device, assets: &mut GpuAssets) {
+        assets.material_layouts.insert(
+            MaterialTypeId(TypeId::of::<Self>().to_uuid()),
+            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("unlit_material_layout"),
+                entries: &[
+                    // Material Uniform
+                    BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Buffer {
+                            ty: BufferBindingType::Uniform,
+                            has_dynamic_offset: true,
+                            min_binding_size: Some(UnlitMaterialUniform::min_size()),
+                        },
+                        count: None,
+                    },
+                    // tex_base_color
+                    BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Texture {
+                            sample_type: TextureSampleType::Float { filterable: true },
+                            view_dimension: TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    // Sampler
+                    BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: ShaderStages::FRAGMENT,
+                        ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
+            }),
+        );
+    }
+}
+
+impl Material for UnlitMaterial {
+    fn create_bind_group(
+        &self,
+        device: &Device,
+        assets: &mut GpuAssets,
+        material: MaterialInstanceId,
+    ) {
+        let Some(buffer) = assets
+            .material_uniforms
+            .get(&self.id())
+            .and_then(|b| b.binding::<UnlitMaterialUniform>())
+        else {
+            return;
+        };
+
+        let layout = assets.material_layouts.get(&self.id()).unwrap();
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("unlit_material_bind_group"),
+            layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: buffer,
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(
+                        &assets.textures[&self.tex_base_color.unwrap_or(DUMMY_2D_TEX)]
+                            .create_view(&TextureViewDescriptor::default()),
+                    ),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::Sampler(&create_sampler(
+                        device,
+                        &SamplerDescriptor {
+                            mag_filter: FilterMode::Linear,
+                            min_filter: FilterMode::Linear,
+                            mipmap_filter: FilterMode::Linear,
+                            anisotropy_clamp: assets.max_anisotropy,
+                            ..Default::default()
+                        },
+                        assets.max_anisotropy,
+                    )),
+                },
+            ],
+        });
+
+        assets.material_bind_groups.insert(material, bind_group);
+    }
+
+    fn prepare(&self, _device: &Device, assets: &mut GpuAssets) -> u32 {
+        let buffer = assets.material_uniforms.get_mut(&self.id()).unwrap();
+        buffer.push(&UnlitMaterialUniform {
+            base_color: self.base_color.into_linear().to_vec3(),
+        })
+    }
+}
@@ -0,0 +1,436 @@
+use std::mem::{offset_of, size_of};
+
+use aurora_core::render::{
+    flow::{RenderContext, RenderNode},
+    resource::{DynamicGpuBuffer, GpuCamera},
+    scene::GpuScene,
+};
+use encase::ShaderType;
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferBindingType,
+    BufferDescriptor, BufferUsages, ComputePassDescriptor, ComputePipeline,
+    ComputePipelineDescriptor, PipelineLayoutDescriptor, ShaderStages, TextureSampleType,
+    TextureViewDimension,
+};
+
+const HISTOGRAM_BINS: u64 = 256;
+const HISTOGRAM_WORKGROUP_SIZE: u32 = 16;
+
+#[derive(ShaderType)]
+pub struct AutoExposureConfig {
+    /// Scene luminance range covered by the histogram, in log2.
+    pub min_log_luminance: f32,
+    pub max_log_luminance: f32,
+    /// Fractions of the darkest and brightest pixels left out of the average.
+    pub low_percentile: f32,
+    pub high_percentile: f32,
+    /// How fast the exposure approaches the measured one, in 1/s.
+    pub adaptation_speed: f32,
+    /// In EV, positive values brighten the image.
+    pub compensation: f32,
+    pub min_ev100: f32,
+    pub max_ev100: f32,
+    pub camera_ev100: f32,
+    pub delta_time: f32,
+}
+
+impl Default for AutoExposureConfig {
+    fn default() -> Self {
+        Self {
+            min_log_luminance: -8.,
+            max_log_luminance: 16.,
+            low_percentile: 0.1,
+            high_percentile: 0.9,
+            adaptation_speed: 1.5,
+            compensation: 0.,
+            min_ev100: -4.,
+            max_ev100: 16.,
+            camera_ev100: 0.,
+            delta_time: 0.,
+        }
+    }
+}
+
+pub struct AutoExposureNodeData {
+    pub histogram_pipeline: ComputePipeline,
+    pub average_pipeline: ComputePipeline,
+    pub layout: BindGroupLayout,
+    pub config: DynamicGpuBuffer,
+    pub histogram: Buffer,
+    /// Adapted ev100, followed by whether it has been measured yet.
+    pub state: Buffer,
+}
+
+/// Adapts the camera exposure to the luminance of the rendered frame.
+///
+/// Place it after `PbrNode` and before bloom and tonemapping. The adapted ev100 is written
+/// into the camera uniform, so `PbrNode` renders the next frame with it, while
+/// `Camera::exposure` stays the starting point and is left untouched. Nodes reading the
+/// exposure on the CPU, like the bloom threshold, don't see the adapted value.
+#[derive(Default)]
+pub struct AutoExposureNode {
+    pub config: AutoExposureConfig,
+
+    pub data: Option<AutoExposureNodeData>,
+}
+
+impl RenderNode for AutoExposureNode {
+    fn require_shaders(&self) -> Option<&'static [(&'static [&'static str], &'static str)]> {
+        Some(&[(
+            &[include_str!("../shader/math.wgsl")],
+            include_str!("../shader/post_processing/auto_exposure.wgsl"),
+        )])
+    }
+
+    fn build(
+        &mut self,
+        GpuScene { original, .. }: &mut GpuScene,
+        RenderContext { device, node, .. }: RenderContext,
+    ) {
+        let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("auto_exposure_layout"),
+            entries: &[
+                // Color
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: false },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                // Config
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: Some(AutoExposureConfig::min_size()),
+                    },
+                    count: None,
+                },
+                // Histogram
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // State
+                BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("auto_exposure_pipeline_layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+
+        let histogram_pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: Some("auto_exposure_histogram_pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &node.shaders[0],
+            entry_point: "histogram_pass",
+            compilation_options: Default::default(),
+            cache: node.pipeline_cache.as_deref(),
+        });
+
+        let average_pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: Some("auto_exposure_average_pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &node.shaders[0],
+            entry_point: "average_pass",
+            compilation_options: Default::default(),
+            cache: node.pipeline_cache.as_deref(),
+        });
+
+        let histogram = device.create_buffer(&BufferDescriptor {
+            label: Some("auto_exposure_histogram"),
+            size: HISTOGRAM_BINS * size_of::<u32>() as u64,
+            usage: BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        // Copied into the camera before anything is measured, so start from its own exposure.
+        let mut initial_state = original.camera.exposure.ev100.to_le_bytes().to_vec();
+        initial_state.extend_from_slice(&0u32.to_le_bytes());
+        let state = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("auto_exposure_state"),
+            contents: &initial_state,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+        });
+
+        self.data = Some(AutoExposureNodeData {
+            histogram_pipeline,
+            average_pipeline,
+            layout,
+            config: DynamicGpuBuffer::new(BufferUsages::UNIFORM),
+            histogram,
+            state,
+        });
+    }
+
+    fn prepare(
+        &mut self,
+        GpuScene {
+            original,
+            assets,
+            delta_time,
+            ..
+        }: &mut GpuScene,
+        RenderContext { device, queue, .. }: RenderContext,
+    ) {
+        let Some(AutoExposureNodeData { config, state, .. }) = &mut self.data else {
+            return;
+        };
+
+        self.config.camera_ev100 = original.camera.exposure.ev100;
+        self.config.delta_time = *delta_time;
+        config.clear();
+        config.push(&self.config);
+        config.write::<AutoExposureConfig>(device, queue);
+
+        // The camera uniform was just written by `GeneralNode`, and writes land before
+        // later submissions, so this overrides the exposure for the rest of the frame.
+        let Some(camera) = assets.camera_uniform.buffer() else {
+            return;
+        };
+        let mut command_encoder = device.create_command_encoder(&Default::default());
+        let cameras = assets.camera_uniform.len() / size_of::<GpuCamera>();
+        for index in 0..cameras.max(1) {
+            command_encoder.copy_buffer_to_buffer(
+                state,
+                0,
+                camera,
+                (index * size_of::<GpuCamera>() + offset_of!(GpuCamera, exposure)) as u64,
+                size_of::<f32>() as u64,
+            );
+        }
+        queue.submit([command_encoder.finish()]);
+    }
+
+    fn draw(
+        &self,
+        GpuScene { assets, .. }: &mut GpuScene,
+        RenderContext {
+            device,
+            queue,
+            targets,
+            ..
+        }: RenderContext,
+    ) {
+        let Some(AutoExposureNodeData {
+            histogram_pipeline,
+            average_pipeline,
+            layout,
+            config,
+            histogram,
+            state,
+        }) = &self.data
+        else {
+            return;
+        };
+
+        let bind_group = assets.bind_group_cache.get_or_create(
+            device,
+            &BindGroupDescriptor {
+                label: Some("auto_exposure_bind_group"),
+                layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::TextureView(targets.swap_chain.current_view()),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: config.entire_binding().unwrap(),
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: histogram.as_entire_binding(),
+                    },
+                    BindGroupEntry {
+                        binding: 3,
+                        resource: state.as_entire_binding(),
+                    },
+                ],
+            },
+        );
+
+        let mut command_encoder = device.create_command_encoder(&Default::default());
+
+        {
+            let mut pass = command_encoder.begin_compute_pass(&ComputePassDescriptor {
+                label: Some("auto_exposure_histogram_pass"),
+                ..Default::default()
+            });
+
+            pass.set_pipeline(histogram_pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups(
+                targets.size.x.div_ceil(HISTOGRAM_WORKGROUP_SIZE),
+                targets.size.y.div_ceil(HISTOGRAM_WORKGROUP_SIZE),
+                1,
+            );
+        }
+
+        {
+            let mut pass = command_encoder.begin_compute_pass(&ComputePassDescriptor {
+                label: Some("auto_exposure_average_pass"),
+                ..Default::default()
+            });
+
+            pass.set_pipeline(average_pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups(1, 1, 1);
+        }
+
+        queue.submit([command_encoder.finish()]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem::{offset_of, size_of};
+
+    use aurora_core::{
+        render::{
+            flow::{GeneralNode, RenderFlow},
+            resource::{GpuCamera, RenderTargets},
+            scene::GpuScene,
+        },
+        util::testing::{self, TestTargets},
+        WgpuRenderer,
+    };
+    use glam::UVec2;
+    use half::f16;
+    use wgpu::{
+        BufferDescriptor, BufferUsages, Extent3d, ImageCopyTexture, ImageDataLayout, Maintain,
+        MapMode, Origin3d, TextureAspect, TextureFormat,
+    };
+
+    use super::{AutoExposureConfig, AutoExposureNode};
+
+    /// Fill the swap chain with an exposed gray and run the flow.
+    fn render(
+        renderer: &WgpuRenderer,
+        flow: &mut RenderFlow,
+        scene: &mut GpuScene,
+        targets: &RenderTargets,
+        exposed: f32,
+    ) {
+        let size = targets.size;
+        let frame = [exposed, exposed, exposed, 1.]
+            .map(f16::from_f32)
+            .repeat((size.x * size.y) as usize);
+        renderer.queue.write_texture(
+            ImageCopyTexture {
+                texture: targets.swap_chain.current_texture(),
+                mip_level: 0,
+                origin: Origin3d::ZERO,
+                aspect: TextureAspect::All,
+            },
+            bytemuck::cast_slice(&frame),
+            ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(size.x * 8),
+                rows_per_image: None,
+            },
+            Extent3d {
+                width: size.x,
+                height: size.y,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        flow.build(renderer, scene, None, targets).unwrap();
+        flow.run(renderer, scene, targets);
+    }
+
+    /// Exposure the camera uniform was rendered with.
+    fn camera_ev100(renderer: &WgpuRenderer, scene: &GpuScene) -> f32 {
+        let readback = renderer.device.create_buffer(&BufferDescriptor {
+            label: None,
+            size: size_of::<f32>() as u64,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut command_encoder = renderer.device.create_command_encoder(&Default::default());
+        command_encoder.copy_buffer_to_buffer(
+            scene.assets.camera_uniform.buffer().unwrap(),
+            offset_of!(GpuCamera, exposure) as u64,
+            &readback,
+            0,
+            size_of::<f32>() as u64,
+        );
+        renderer.queue.submit([command_encoder.finish()]);
+
+        readback.slice(..).map_async(MapMode::Read, |_| {});
+        renderer.device.poll(Maintain::Wait);
+        let bytes = readback.slice(..).get_mapped_range();
+        f32::from_le_bytes(bytes[..4].try_into().unwrap())
+    }
+
+    #[test]
+    fn auto_exposure_adaptation() {
+        let Some(renderer) = testing::renderer(None, None) else {
+            return;
+        };
+
+        let size = UVec2::new(64, 64);
+        let test_targets = TestTargets::new(&renderer.device, size, TextureFormat::Rgba16Float);
+        let targets = test_targets.targets();
+
+        let mut scene = GpuScene::default();
+        scene
+            .assets
+            .camera_uniform
+            .usage_mut()
+            .insert(BufferUsages::COPY_SRC);
+        let mut flow = RenderFlow::default();
+        flow.add::<GeneralNode>().add::<AutoExposureNode>();
+
+        // Scene luminance is the exposed value with the camera exposure undone.
+        let luminance = 0.1 * scene.original.camera.exposure.ev100.exp2() * 1.2;
+        let expected = (luminance * 100. / 12.5).log2();
+
+        // The measurement is applied to the camera of the frame after.
+        render(&renderer, &mut flow, &mut scene, &targets, 0.1);
+        render(&renderer, &mut flow, &mut scene, &targets, 0.1);
+        let first = camera_ev100(&renderer, &scene);
+        // No history, so it snaps, up to the histogram bin width.
+        assert!((first - expected).abs() < 0.1, "{first} != {expected}");
+
+        // Way brighter, past `max_ev100`, so exposure heads there without snapping.
+        let max_ev100 = AutoExposureConfig::default().max_ev100;
+        render(&renderer, &mut flow, &mut scene, &targets, 100.);
+        render(&renderer, &mut flow, &mut scene, &targets, 100.);
+        let second = camera_ev100(&renderer, &scene);
+        assert!(second > first && second < max_ev100 - 1., "{second}");
+
+        for _ in 0..100 {
+            render(&renderer, &mut flow, &mut scene, &targets, 100.);
+        }
+        let third = camera_ev100(&renderer, &scene);
+        assert!(third > second && third <= max_ev100, "{third}");
+    }
+}
@@ -21,7 +21,10 @@ impl RenderNode for BasicTriangleNode {
         &mut self,
         _scene: &mut GpuScene,
         RenderContext {
-            device, targets, ..
+            device,
+            node,
+            targets,
+            ..
         }: RenderContext,
     ) {
         let shader_module = device.create_shader_module(ShaderModuleDescriptor {
@@ -34,7 +37,7 @@ impl RenderNode for BasicTriangleNode {
         self.pipeline = Some(device.create_render_pipeline(&RenderPipelineDescriptor {
             label: Some("basic_triagle_pipeline"),
             layout: None,
-            cache: None,
+            cache: node.pipeline_cache.as_deref(),
             vertex: VertexState {
                 module: &shader_module,
                 entry_point: "vertex",
@@ -1,17 +1,21 @@
 use std::collections::HashMap;
 
-use aurora_core::render::{
-    flow::{RenderContext, RenderNode},
-    resource::DynamicGpuBuffer,
-    scene::GpuScene,
+use aurora_core::{
+    render::{
+        flow::{RenderContext, RenderNode},
+        resource::DynamicGpuBuffer,
+        scene::GpuScene,
+    },
+    util::create_sampler,
 };
 use encase::ShaderType;
+use glam::UVec2;
 use naga_oil::compose::ShaderDefValue;
 use wgpu::{
     AddressMode, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
     BindGroupLayoutEntry, BindingResource, BindingType, BlendComponent, BlendFactor,
     BlendOperation, BlendState, BufferBindingType, BufferUsages, Color, ColorTargetState,
-    ColorWrites, Extent3d, Features, FilterMode, FragmentState, LoadOp, Operations,
+    ColorWrites, Device, Extent3d, Features, FilterMode, FragmentState, LoadOp, Operations,
     PipelineLayoutDescriptor, RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline,
     RenderPipelineDescriptor, Sampler, SamplerBindingType, SamplerDescriptor, ShaderStages,
     StoreOp, Texture, TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType,
@@ -19,6 +23,8 @@ use wgpu::{
 };
 
 pub const BLOOM_TEXTURE_FORMAT: TextureFormat = TextureFormat::Rg11b10Float;
+/// Used when [`BLOOM_TEXTURE_FORMAT`] isn't renderable, at twice the bandwidth.
+pub const BLOOM_FALLBACK_TEXTURE_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
 
 #[derive(ShaderType)]
 pub struct BloomConfig {
@@ -38,6 +44,20 @@ pub struct BloomNodeData {
     pub layout: BindGroupLayout,
 }
 
+/// Which space [`BloomNodeConfig::threshold`] is expressed in.
+///
+/// Exposure is applied in the PBR pass, so bloom always reads exposed HDR color.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum BloomThresholdSpace {
+    /// Threshold is compared directly against the exposed color.
+    #[default]
+    Exposed,
+    /// Threshold is in pre-exposure scene radiance, and is scaled by the
+    /// camera exposure every frame, so the same emitters bloom regardless
+    /// of exposure.
+    PreExposure,
+}
+
 pub struct BloomNodeConfig {
     pub max_mip_dimension: u32,
     pub intensity: f32,
@@ -45,6 +65,7 @@ pub struct BloomNodeConfig {
     pub eliminate_firefly: bool,
     pub threshold: f32,
     pub soft_threshold: f32,
+    pub threshold_space: BloomThresholdSpace,
 }
 
 impl Default for BloomNodeConfig {
@@ -56,6 +77,30 @@ impl Default for BloomNodeConfig {
             eliminate_firefly: true,
             threshold: 0.8,
             soft_threshold: 0.9,
+            threshold_space: Default::default(),
+        }
+    }
+}
+
+impl BloomNodeConfig {
+    /// Threshold in exposed space, given the exposure multiplier of the camera.
+    pub fn effective_threshold(&self, exposure_multiplier: f32) -> f32 {
+        match self.threshold_space {
+            BloomThresholdSpace::Exposed => self.threshold,
+            BloomThresholdSpace::PreExposure => self.threshold * exposure_multiplier,
+        }
+    }
+
+    pub fn precompute_filter(&self, exposure_multiplier: f32) -> BloomConfig {
+        let threshold = self.effective_threshold(exposure_multiplier);
+        let knee = self.soft_threshold * threshold;
+        BloomConfig {
+            precomputed_filter: [
+                threshold,
+                threshold - knee,
+                2.0 * knee,
+                0.25 / (knee + 0.00001),
+            ],
         }
     }
 }
@@ -68,6 +113,55 @@ pub struct BloomNode {
 }
 
 impl BloomNode {
+    /// Format of the bloom pyramid on `device`.
+    pub fn texture_format(device: &Device) -> TextureFormat {
+        if device
+            .features()
+            .contains(Features::RG11B10UFLOAT_RENDERABLE)
+        {
+            BLOOM_TEXTURE_FORMAT
+        } else {
+            BLOOM_FALLBACK_TEXTURE_FORMAT
+        }
+    }
+
+    fn create_pyramid(
+        config: &BloomNodeConfig,
+        device: &Device,
+        size: UVec2,
+    ) -> (Texture, Vec<TextureView>) {
+        let mip_count = config.max_mip_dimension.ilog2().max(2) - 1;
+        let scale = config.max_mip_dimension as f32 / size.x.min(size.y) as f32;
+
+        let pyramid_textures = device.create_texture(&TextureDescriptor {
+            label: Some("bloom_pyramid_textures"),
+            size: Extent3d {
+                width: (size.x as f32 * scale).round() as u32,
+                height: (size.y as f32 * scale).round() as u32,
+                depth_or_array_layers: 1,
+            },
+            dimension: TextureDimension::D2,
+            format: Self::texture_format(device),
+            mip_level_count: mip_count,
+            sample_count: 1,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+
+        let texture_views = (0..mip_count)
+            .map(|mip| {
+                pyramid_textures.create_view(&TextureViewDescriptor {
+                    label: Some(&format!("bloom_pyramid_texture_mip{}", mip)),
+                    base_mip_level: mip,
+                    mip_level_count: Some(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+
+        (pyramid_textures, texture_views)
+    }
+
     pub fn calculate_blend_factor(&self, mip: usize) -> Color {
         let mip = mip as f32;
         let max_mip = self.data.as_ref().unwrap().texture_views.len() as f32 - 1.0;
@@ -91,7 +185,7 @@ impl BloomNode {
 }
 
 impl RenderNode for BloomNode {
-    fn require_renderer_features(&self, features: &mut Features) {
+    fn request_renderer_features(&self, features: &mut Features) {
         *features |= Features::RG11B10UFLOAT_RENDERABLE;
     }
 
@@ -122,17 +216,18 @@ impl RenderNode for BloomNode {
     }
 
     fn require_local_shader_defs(&self) -> Vec<Option<Vec<(String, ShaderDefValue)>>> {
-        vec![
-            None,
-            self.config
-                .eliminate_firefly
-                .then(|| vec![("FIRST_DOWNSAMPLE".to_string(), Default::default())]),
-        ]
+        let mut first_downsample = vec![("FIRST_DOWNSAMPLE".to_string(), Default::default())];
+        if self.config.eliminate_firefly {
+            first_downsample.push(("ELIMINATE_FIREFLY".to_string(), Default::default()));
+        }
+        vec![None, Some(first_downsample)]
     }
 
     fn build(
         &mut self,
-        _scene: &mut GpuScene,
+        GpuScene {
+            assets, original, ..
+        }: &mut GpuScene,
         RenderContext {
             device,
             queue,
@@ -141,6 +236,8 @@ impl RenderNode for BloomNode {
             ..
         }: RenderContext,
     ) {
+        let format = Self::texture_format(device);
+
         let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
             label: Some("bloom_layout"),
             entries: &[
@@ -173,16 +270,20 @@ impl RenderNode for BloomNode {
             ],
         });
 
-        let sampler = device.create_sampler(&SamplerDescriptor {
-            label: Some("bloom_sampler"),
-            mag_filter: FilterMode::Linear,
-            min_filter: FilterMode::Linear,
-            mipmap_filter: FilterMode::Linear,
-            address_mode_u: AddressMode::ClampToEdge,
-            address_mode_v: AddressMode::ClampToEdge,
-            address_mode_w: AddressMode::ClampToEdge,
-            ..Default::default()
-        });
+        let sampler = create_sampler(
+            device,
+            &SamplerDescriptor {
+                label: Some("bloom_sampler"),
+                mag_filter: FilterMode::Linear,
+                min_filter: FilterMode::Linear,
+                mipmap_filter: FilterMode::Linear,
+                address_mode_u: AddressMode::ClampToEdge,
+                address_mode_v: AddressMode::ClampToEdge,
+                address_mode_w: AddressMode::ClampToEdge,
+                ..Default::default()
+            },
+            assets.max_anisotropy,
+        );
 
         let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
             label: Some("bloom_pipeline_layout"),
@@ -205,7 +306,7 @@ impl RenderNode for BloomNode {
                     entry_point: "downsample",
                     compilation_options: Default::default(),
                     targets: &[Some(ColorTargetState {
-                        format: BLOOM_TEXTURE_FORMAT,
+                        format,
                         blend: None,
                         write_mask: ColorWrites::ALL,
                     })],
@@ -214,7 +315,7 @@ impl RenderNode for BloomNode {
                 depth_stencil: Default::default(),
                 multisample: Default::default(),
                 multiview: Default::default(),
-                cache: Default::default(),
+                cache: node.pipeline_cache.as_deref(),
             });
 
         let downsampling_pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
@@ -227,11 +328,11 @@ impl RenderNode for BloomNode {
                 buffers: &[],
             },
             fragment: Some(FragmentState {
-                module: &node.shaders[1],
+                module: &node.shaders[2],
                 entry_point: "downsample",
                 compilation_options: Default::default(),
                 targets: &[Some(ColorTargetState {
-                    format: BLOOM_TEXTURE_FORMAT,
+                    format,
                     blend: None,
                     write_mask: ColorWrites::ALL,
                 })],
@@ -240,7 +341,7 @@ impl RenderNode for BloomNode {
             depth_stencil: Default::default(),
             multisample: Default::default(),
             multiview: Default::default(),
-            cache: Default::default(),
+            cache: node.pipeline_cache.as_deref(),
         });
 
         let upsampling_pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
@@ -257,8 +358,19 @@ impl RenderNode for BloomNode {
                 entry_point: "upsample",
                 compilation_options: Default::default(),
                 targets: &[Some(ColorTargetState {
-                    format: BLOOM_TEXTURE_FORMAT,
-                    blend: None,
+                    format,
+                    blend: Some(BlendState {
+                        color: BlendComponent {
+                            src_factor: BlendFactor::Constant,
+                            dst_factor: BlendFactor::OneMinusConstant,
+                            operation: BlendOperation::Add,
+                        },
+                        alpha: BlendComponent {
+                            src_factor: BlendFactor::Zero,
+                            dst_factor: BlendFactor::One,
+                            operation: BlendOperation::Add,
+                        },
+                    }),
                     write_mask: ColorWrites::ALL,
                 })],
             }),
@@ -266,7 +378,7 @@ impl RenderNode for BloomNode {
             depth_stencil: Default::default(),
             multisample: Default::default(),
             multiview: Default::default(),
-            cache: Default::default(),
+            cache: node.pipeline_cache.as_deref(),
         });
 
         let final_upsampling_pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
@@ -304,49 +416,18 @@ impl RenderNode for BloomNode {
             depth_stencil: Default::default(),
             multisample: Default::default(),
             multiview: Default::default(),
-            cache: Default::default(),
-        });
-
-        let mip_count = self.config.max_mip_dimension.ilog2().max(2) - 1;
-        let scale =
-            self.config.max_mip_dimension as f32 / targets.size.x.min(targets.size.y) as f32;
-
-        let pyramid_textures = device.create_texture(&TextureDescriptor {
-            label: Some("bloom_pyramid_textures"),
-            size: Extent3d {
-                width: (targets.size.x as f32 * scale).round() as u32,
-                height: (targets.size.y as f32 * scale).round() as u32,
-                depth_or_array_layers: 1,
-            },
-            dimension: TextureDimension::D2,
-            format: BLOOM_TEXTURE_FORMAT,
-            mip_level_count: mip_count,
-            sample_count: 1,
-            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::RENDER_ATTACHMENT,
-            view_formats: &[],
+            cache: node.pipeline_cache.as_deref(),
         });
 
-        let texture_views = (0..mip_count)
-            .map(|mip| {
-                pyramid_textures.create_view(&TextureViewDescriptor {
-                    label: Some(&format!("bloom_pyramid_texture_mip{}", mip)),
-                    base_mip_level: mip,
-                    mip_level_count: Some(1),
-                    ..Default::default()
-                })
-            })
-            .collect();
+        let (pyramid_textures, texture_views) =
+            Self::create_pyramid(&self.config, device, targets.size);
 
         let mut config = DynamicGpuBuffer::new(BufferUsages::UNIFORM);
-        let knee = self.config.soft_threshold * self.config.threshold;
-        config.push(&BloomConfig {
-            precomputed_filter: [
-                self.config.threshold,
-                self.config.threshold - knee,
-                2.0 * knee,
-                0.25 / (knee + 0.00001),
-            ],
-        });
+        config.push(
+            &self
+                .config
+                .precompute_filter(original.camera.exposure.multiplier()),
+        );
         config.write::<BloomConfig>(device, queue);
 
         self.data = Some(BloomNodeData {
@@ -362,9 +443,48 @@ impl RenderNode for BloomNode {
         });
     }
 
+    fn resize(
+        &mut self,
+        _scene: &mut GpuScene,
+        RenderContext { device, .. }: RenderContext,
+        new_size: UVec2,
+    ) {
+        let Some(data) = &mut self.data else {
+            return;
+        };
+
+        let (pyramid_textures, texture_views) =
+            Self::create_pyramid(&self.config, device, new_size);
+        data.pyramid_textures.destroy();
+        data.pyramid_textures = pyramid_textures;
+        data.texture_views = texture_views;
+    }
+
+    fn prepare(
+        &mut self,
+        GpuScene { original, .. }: &mut GpuScene,
+        RenderContext { device, queue, .. }: RenderContext,
+    ) {
+        if self.config.threshold_space != BloomThresholdSpace::PreExposure {
+            return;
+        }
+
+        let Some(BloomNodeData { config, .. }) = &mut self.data else {
+            return;
+        };
+
+        config.clear();
+        config.push(
+            &self
+                .config
+                .precompute_filter(original.camera.exposure.multiplier()),
+        );
+        config.write::<BloomConfig>(device, queue);
+    }
+
     fn draw(
         &self,
-        _scene: &mut GpuScene,
+        GpuScene { assets, .. }: &mut GpuScene,
         RenderContext {
             device,
             queue,
@@ -387,24 +507,27 @@ impl RenderNode for BloomNode {
             return;
         };
 
-        let first_downsample_bind_group = device.create_bind_group(&BindGroupDescriptor {
-            label: Some("bloom_first_downsample_bind_group"),
-            layout,
-            entries: &[
-                BindGroupEntry {
-                    binding: 0,
-                    resource: BindingResource::TextureView(targets.swap_chain.current_view()),
-                },
-                BindGroupEntry {
-                    binding: 1,
-                    resource: BindingResource::Sampler(sampler),
-                },
-                BindGroupEntry {
-                    binding: 2,
-                    resource: config.entire_binding().unwrap(),
-                },
-            ],
-        });
+        let first_downsample_bind_group = assets.bind_group_cache.get_or_create(
+            device,
+            &BindGroupDescriptor {
+                label: Some("bloom_first_downsample_bind_group"),
+                layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::TextureView(targets.swap_chain.current_view()),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::Sampler(sampler),
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: config.entire_binding().unwrap(),
+                    },
+                ],
+            },
+        );
 
         let mut command_encoder = device.create_command_encoder(&Default::default());
 
@@ -427,24 +550,27 @@ impl RenderNode for BloomNode {
         queue.submit([command_encoder.finish()]);
 
         for mip in 1..texture_views.len() {
-            let downsample_bind_group = device.create_bind_group(&BindGroupDescriptor {
-                label: Some("bloom_downsample_bind_group"),
-                layout,
-                entries: &[
-                    BindGroupEntry {
-                        binding: 0,
-                        resource: BindingResource::TextureView(&texture_views[mip - 1]),
-                    },
-                    BindGroupEntry {
-                        binding: 1,
-                        resource: BindingResource::Sampler(sampler),
-                    },
-                    BindGroupEntry {
-                        binding: 2,
-                        resource: config.entire_binding().unwrap(),
-                    },
-                ],
-            });
+            let downsample_bind_group = assets.bind_group_cache.get_or_create(
+                device,
+                &BindGroupDescriptor {
+                    label: Some("bloom_downsample_bind_group"),
+                    layout,
+                    entries: &[
+                        BindGroupEntry {
+                            binding: 0,
+                            resource: BindingResource::TextureView(&texture_views[mip - 1]),
+                        },
+                        BindGroupEntry {
+                            binding: 1,
+                            resource: BindingResource::Sampler(sampler),
+                        },
+                        BindGroupEntry {
+                            binding: 2,
+                            resource: config.entire_binding().unwrap(),
+                        },
+                    ],
+                },
+            );
 
             let mut command_encoder = device.create_command_encoder(&Default::default());
 
@@ -468,24 +594,27 @@ impl RenderNode for BloomNode {
         }
 
         for mip in (1..texture_views.len()).rev() {
-            let upsample_bind_group = device.create_bind_group(&BindGroupDescriptor {
-                label: Some("bloom_upsample_pass"),
-                layout,
-                entries: &[
-                    BindGroupEntry {
-                        binding: 0,
-                        resource: BindingResource::TextureView(&texture_views[mip]),
-                    },
-                    BindGroupEntry {
-                        binding: 1,
-                        resource: BindingResource::Sampler(sampler),
-                    },
-                    BindGroupEntry {
-                        binding: 2,
-                        resource: config.entire_binding().unwrap(),
-                    },
-                ],
-            });
+            let upsample_bind_group = assets.bind_group_cache.get_or_create(
+                device,
+                &BindGroupDescriptor {
+                    label: Some("bloom_upsample_pass"),
+                    layout,
+                    entries: &[
+                        BindGroupEntry {
+                            binding: 0,
+                            resource: BindingResource::TextureView(&texture_views[mip]),
+                        },
+                        BindGroupEntry {
+                            binding: 1,
+                            resource: BindingResource::Sampler(sampler),
+                        },
+                        BindGroupEntry {
+                            binding: 2,
+                            resource: config.entire_binding().unwrap(),
+                        },
+                    ],
+                },
+            );
 
             let mut command_encoder = device.create_command_encoder(&Default::default());
 
@@ -495,7 +624,10 @@ impl RenderNode for BloomNode {
                     color_attachments: &[Some(RenderPassColorAttachment {
                         view: &texture_views[mip - 1],
                         resolve_target: None,
-                        ops: Operations::default(),
+                        ops: Operations {
+                            load: LoadOp::Load,
+                            store: StoreOp::Store,
+                        },
                     })],
                     ..Default::default()
                 });
@@ -509,24 +641,27 @@ impl RenderNode for BloomNode {
             queue.submit([command_encoder.finish()]);
         }
 
-        let final_upsample_bind_group = device.create_bind_group(&BindGroupDescriptor {
-            label: Some("bloom_upsample_pass"),
-            layout,
-            entries: &[
-                BindGroupEntry {
-                    binding: 0,
-                    resource: BindingResource::TextureView(&texture_views[0]),
-                },
-                BindGroupEntry {
-                    binding: 1,
-                    resource: BindingResource::Sampler(sampler),
-                },
-                BindGroupEntry {
-                    binding: 2,
-                    resource: config.entire_binding().unwrap(),
-                },
-            ],
-        });
+        let final_upsample_bind_group = assets.bind_group_cache.get_or_create(
+            device,
+            &BindGroupDescriptor {
+                label: Some("bloom_upsample_pass"),
+                layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::TextureView(&texture_views[0]),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::Sampler(sampler),
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: config.entire_binding().unwrap(),
+                    },
+                ],
+            },
+        );
 
         let mut command_encoder = device.create_command_encoder(&Default::default());
 
@@ -555,3 +690,160 @@ impl RenderNode for BloomNode {
         queue.submit([command_encoder.finish()]);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use aurora_core::{
+        render::{
+            flow::{GeneralNode, RenderFlow},
+            helper::Exposure,
+            scene::GpuScene,
+        },
+        util::{
+            testing::{self, TestTargets},
+            TextureReadback,
+        },
+        WgpuRenderer,
+    };
+    use glam::UVec2;
+    use half::f16;
+    use wgpu::{
+        Extent3d, Features, ImageCopyTexture, ImageDataLayout, Maintain, Origin3d, TextureAspect,
+        TextureFormat,
+    };
+
+    use super::{
+        BloomNode, BloomNodeConfig, BloomThresholdSpace, BLOOM_FALLBACK_TEXTURE_FORMAT,
+        BLOOM_TEXTURE_FORMAT,
+    };
+
+    /// Render bloom over a bright square on black, exposed with `ev100`, and return the glow
+    /// around the square in pre-exposure units.
+    fn glow(renderer: &WgpuRenderer, config: BloomNodeConfig, ev100: f32) -> f32 {
+        const SIZE: u32 = 64;
+        const RADIANCE: f32 = 400.;
+        let spot = SIZE / 2 - 4..SIZE / 2 + 4;
+
+        let exposure = Exposure { ev100 };
+        let mut flow = RenderFlow::default();
+        flow.add::<GeneralNode>().add::<BloomNode>();
+        flow.get_mut::<BloomNode>().unwrap().config = config;
+
+        let mut scene = GpuScene::default();
+        scene.original.camera.exposure = exposure;
+
+        let size = UVec2::splat(SIZE);
+        let test_targets = TestTargets::new(&renderer.device, size, TextureFormat::Rgba16Float);
+        let targets = test_targets.targets();
+
+        let exposed = RADIANCE * exposure.multiplier();
+        let frame = (0..SIZE * SIZE)
+            .flat_map(|i| {
+                let lit = spot.contains(&(i % SIZE)) && spot.contains(&(i / SIZE));
+                let v = if lit { exposed } else { 0. };
+                [v, v, v, 1.].map(f16::from_f32)
+            })
+            .collect::<Vec<_>>();
+        renderer.queue.write_texture(
+            ImageCopyTexture {
+                texture: targets.swap_chain.current_texture(),
+                mip_level: 0,
+                origin: Origin3d::ZERO,
+                aspect: TextureAspect::All,
+            },
+            bytemuck::cast_slice(&frame),
+            ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(SIZE * 8),
+                rows_per_image: None,
+            },
+            Extent3d {
+                width: SIZE,
+                height: SIZE,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        flow.build(renderer, &mut scene, None, &targets).unwrap();
+        flow.run(renderer, &mut scene, &targets);
+
+        let readback = TextureReadback::new(
+            targets.swap_chain.current_texture(),
+            &renderer.device,
+            &renderer.queue,
+        )
+        .unwrap();
+        renderer.device.poll(Maintain::wait()).panic_on_timeout();
+        let data = pollster::block_on(readback.read()).unwrap();
+        let texels = bytemuck::pod_collect_to_vec::<u8, f16>(&data.bytes);
+        let row = (data.bytes_per_row / 2) as usize;
+        let glow = (0..SIZE)
+            .flat_map(|y| (0..SIZE).map(move |x| (x, y)))
+            .filter(|(x, y)| !spot.contains(x) || !spot.contains(y))
+            .map(|(x, y)| texels[y as usize * row + x as usize * 4].to_f32())
+            .sum::<f32>();
+        glow / exposure.multiplier()
+    }
+
+    #[test]
+    fn bloom_scales_with_exposure() {
+        let Some(renderer) = testing::renderer(None, None) else {
+            return;
+        };
+
+        // The square only passes the exposed threshold when exposed brightly.
+        let bright = glow(&renderer, BloomNodeConfig::default(), 6.);
+        let dim = glow(&renderer, BloomNodeConfig::default(), 10.);
+        assert!(bright > 0. && dim < bright * 0.1, "{bright} {dim}");
+
+        // A pre-exposure threshold blooms the same radiance whatever the exposure. Firefly
+        // elimination weighs exposed luminance, so it's left out of the comparison.
+        let pre_exposure = || BloomNodeConfig {
+            threshold: 100.,
+            threshold_space: BloomThresholdSpace::PreExposure,
+            eliminate_firefly: false,
+            ..Default::default()
+        };
+        let bright = glow(&renderer, pre_exposure(), 6.);
+        let dim = glow(&renderer, pre_exposure(), 8.);
+        assert!(
+            bright > 0. && (dim / bright - 1.).abs() < 0.05,
+            "{bright} {dim}"
+        );
+    }
+
+    #[test]
+    fn bloom_format_fallback() {
+        let mut flow = RenderFlow::default();
+        flow.add::<GeneralNode>().add::<BloomNode>();
+        assert!(!flow
+            .required_features()
+            .contains(Features::RG11B10UFLOAT_RENDERABLE));
+
+        let Some(renderer) =
+            testing::skip_unsupported(pollster::block_on(flow.request_renderer(None, None)))
+        else {
+            return;
+        };
+        let expected = if renderer
+            .supported_features()
+            .contains(Features::RG11B10UFLOAT_RENDERABLE)
+        {
+            BLOOM_TEXTURE_FORMAT
+        } else {
+            BLOOM_FALLBACK_TEXTURE_FORMAT
+        };
+        assert_eq!(BloomNode::texture_format(&renderer.device), expected);
+
+        let size = UVec2::splat(64);
+        let test_targets = TestTargets::new(&renderer.device, size, TextureFormat::Rgba16Float);
+        let targets = test_targets.targets();
+
+        let mut scene = GpuScene::default();
+        flow.build(&renderer, &mut scene, None, &targets).unwrap();
+        flow.run(&renderer, &mut scene, &targets);
+
+        let pyramid = &flow.get_mut::<BloomNode>().unwrap().data.as_ref().unwrap();
+        assert_eq!(pyramid.pyramid_textures.format(), expected);
+    }
+}
@@ -0,0 +1,348 @@
+use std::collections::HashMap;
+
+use aurora_core::{
+    render::{
+        flow::{RenderContext, RenderNode},
+        resource::DynamicGpuBuffer,
+        scene::GpuScene,
+    },
+    util::create_sampler,
+};
+use encase::ShaderType;
+use naga_oil::compose::ShaderDefValue;
+use wgpu::{
+    AddressMode, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, BufferBindingType, BufferUsages,
+    ColorTargetState, ColorWrites, FilterMode, FragmentState, PipelineLayoutDescriptor,
+    RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor,
+    Sampler, SamplerBindingType, SamplerDescriptor, ShaderStages, TextureSampleType,
+    TextureViewDimension, VertexState,
+};
+
+/// Each effect is compiled out when its amount is 0.
+#[derive(ShaderType)]
+pub struct CameraEffectsConfig {
+    /// Darkening at the corners of the screen.
+    pub vignette_strength: f32,
+    /// Amplitude of the grain, relative to the color.
+    pub grain_amount: f32,
+    /// UV offset of the red and blue channels, scaled by the distance to the center.
+    pub ca_strength: f32,
+    /// Seeds the grain, advance it every frame to animate it.
+    pub time: f32,
+}
+
+impl Default for CameraEffectsConfig {
+    fn default() -> Self {
+        Self {
+            vignette_strength: 0.4,
+            grain_amount: 0.05,
+            ca_strength: 0.01,
+            time: 0.,
+        }
+    }
+}
+
+pub struct CameraEffectsNodeData {
+    pub pipeline: RenderPipeline,
+    pub layout: BindGroupLayout,
+    pub sampler: Sampler,
+    pub config: DynamicGpuBuffer,
+}
+
+/// Vignette, film grain and chromatic aberration in a single pass.
+#[derive(Default)]
+pub struct CameraEffectsNode {
+    pub config: CameraEffectsConfig,
+
+    pub data: Option<CameraEffectsNodeData>,
+}
+
+impl RenderNode for CameraEffectsNode {
+    fn require_shader_defs(&self, shader_defs: &mut HashMap<String, ShaderDefValue>) {
+        for (amount, def) in [
+            (self.config.vignette_strength, "VIGNETTE"),
+            (self.config.grain_amount, "FILM_GRAIN"),
+            (self.config.ca_strength, "CHROMATIC_ABERRATION"),
+        ] {
+            if amount != 0. {
+                shader_defs.insert(def.to_string(), Default::default());
+            }
+        }
+    }
+
+    fn require_shaders(&self) -> Option<&'static [(&'static [&'static str], &'static str)]> {
+        Some(&[
+            (&[], include_str!("../shader/fullscreen.wgsl")),
+            (
+                &[
+                    include_str!("../shader/fullscreen.wgsl"),
+                    include_str!("../shader/hash.wgsl"),
+                ],
+                include_str!("../shader/post_processing/camera_effects.wgsl"),
+            ),
+        ])
+    }
+
+    fn build(
+        &mut self,
+        GpuScene { assets, .. }: &mut GpuScene,
+        RenderContext {
+            device,
+            node,
+            targets,
+            ..
+        }: RenderContext,
+    ) {
+        let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("camera_effects_layout"),
+            entries: &[
+                // Color
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                // Color Sampler
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+                // Config
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: Some(CameraEffectsConfig::min_size()),
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("camera_effects_pipeline_layout"),
+            bind_group_layouts: &[&layout],
+            ..Default::default()
+        });
+
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("camera_effects_pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &node.shaders[0],
+                entry_point: "vertex",
+                compilation_options: Default::default(),
+                buffers: &[],
+            },
+            fragment: Some(FragmentState {
+                module: &node.shaders[1],
+                entry_point: "fragment",
+                compilation_options: Default::default(),
+                targets: &[Some(ColorTargetState {
+                    format: targets.color_format,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: Default::default(),
+            depth_stencil: Default::default(),
+            multisample: Default::default(),
+            multiview: Default::default(),
+            cache: node.pipeline_cache.as_deref(),
+        });
+
+        let sampler = create_sampler(
+            device,
+            &SamplerDescriptor {
+                label: Some("camera_effects_sampler"),
+                address_mode_u: AddressMode::ClampToEdge,
+                address_mode_v: AddressMode::ClampToEdge,
+                mag_filter: FilterMode::Linear,
+                min_filter: FilterMode::Linear,
+                ..Default::default()
+            },
+            assets.max_anisotropy,
+        );
+
+        self.data = Some(CameraEffectsNodeData {
+            pipeline,
+            layout,
+            sampler,
+            config: DynamicGpuBuffer::new(BufferUsages::UNIFORM),
+        });
+    }
+
+    fn prepare(
+        &mut self,
+        _scene: &mut GpuScene,
+        RenderContext { device, queue, .. }: RenderContext,
+    ) {
+        let Some(CameraEffectsNodeData { config, .. }) = &mut self.data else {
+            return;
+        };
+
+        config.clear();
+        config.push(&self.config);
+        config.write::<CameraEffectsConfig>(device, queue);
+    }
+
+    fn draw(
+        &self,
+        GpuScene { assets, .. }: &mut GpuScene,
+        RenderContext {
+            device,
+            queue,
+            targets,
+            ..
+        }: RenderContext,
+    ) {
+        let Some(CameraEffectsNodeData {
+            pipeline,
+            layout,
+            sampler,
+            config,
+        }) = &self.data
+        else {
+            return;
+        };
+
+        let post_process = targets.swap_chain.start_post_process();
+        let bind_group = assets.bind_group_cache.get_or_create(
+            device,
+            &BindGroupDescriptor {
+                label: Some("camera_effects_bind_group"),
+                layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::TextureView(post_process.src),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::Sampler(sampler),
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: config.entire_binding().unwrap(),
+                    },
+                ],
+            },
+        );
+
+        let mut command_encoder = device.create_command_encoder(&Default::default());
+
+        {
+            let mut pass = command_encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("camera_effects_pass"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: post_process.dst,
+                    resolve_target: None,
+                    ops: Default::default(),
+                })],
+                ..Default::default()
+            });
+
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.draw(0..3, 0..1);
+        }
+
+        queue.submit([command_encoder.finish()]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aurora_core::{
+        render::{
+            flow::{GeneralNode, RenderFlow},
+            scene::GpuScene,
+        },
+        util::testing::{self, TestTargets},
+        WgpuRenderer,
+    };
+    use glam::UVec2;
+    use image::RgbaImage;
+    use wgpu::{
+        Extent3d, ImageCopyTexture, ImageDataLayout, Origin3d, TextureAspect, TextureFormat,
+    };
+
+    use super::{CameraEffectsConfig, CameraEffectsNode};
+
+    fn apply(renderer: &WgpuRenderer, config: CameraEffectsConfig, frame: &[u8]) -> RgbaImage {
+        let size = UVec2::new(32, 32);
+        let test_targets = TestTargets::new(&renderer.device, size, TextureFormat::Rgba8Unorm);
+        let targets = test_targets.targets();
+
+        renderer.queue.write_texture(
+            ImageCopyTexture {
+                texture: test_targets.swap_chain.current_texture(),
+                mip_level: 0,
+                origin: Origin3d::ZERO,
+                aspect: TextureAspect::All,
+            },
+            frame,
+            ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(size.x * 4),
+                rows_per_image: None,
+            },
+            Extent3d {
+                width: size.x,
+                height: size.y,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        let mut scene = GpuScene::default();
+        let mut flow = RenderFlow::default();
+        flow.add::<GeneralNode>()
+            .add_initialized(CameraEffectsNode { config, data: None });
+        flow.build(renderer, &mut scene, None, &targets).unwrap();
+        flow.run(renderer, &mut scene, &targets);
+
+        test_targets.read(&renderer)
+    }
+
+    #[test]
+    fn camera_effects() {
+        let Some(renderer) = testing::renderer(None, None) else {
+            return;
+        };
+
+        let frame = (0..32 * 32 * 4)
+            .map(|i: u32| (i.wrapping_mul(2654435761) >> 24) as u8)
+            .collect::<Vec<_>>();
+        let disabled = CameraEffectsConfig {
+            vignette_strength: 0.,
+            grain_amount: 0.,
+            ca_strength: 0.,
+            time: 0.,
+        };
+        assert_eq!(apply(&renderer, disabled, &frame).into_raw(), frame);
+
+        let gray = [128; 32 * 32 * 4];
+        let vignette = apply(
+            &renderer,
+            CameraEffectsConfig {
+                vignette_strength: 0.5,
+                grain_amount: 0.,
+                ca_strength: 0.,
+                time: 0.,
+            },
+            &gray,
+        );
+        assert!(vignette.get_pixel(0, 0)[0] < 80);
+        assert_eq!(vignette.get_pixel(16, 16)[0], 128);
+    }
+}
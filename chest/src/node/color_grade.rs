@@ -0,0 +1,398 @@
+use std::path::Path;
+
+use aurora_core::{
+    render::{
+        flow::{RenderContext, RenderNode},
+        resource::DynamicGpuBuffer,
+        scene::GpuScene,
+    },
+    util::create_sampler,
+};
+use encase::ShaderType;
+use glam::Vec3;
+use wgpu::{
+    AddressMode, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, BufferBindingType, BufferUsages,
+    ColorTargetState, ColorWrites, FilterMode, FragmentState, PipelineLayoutDescriptor,
+    RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor,
+    Sampler, SamplerBindingType, SamplerDescriptor, ShaderStages, Texture, TextureSampleType,
+    TextureView, TextureViewDimension, VertexState,
+};
+
+use crate::texture::{CubeLut, CubeLutError};
+
+#[derive(ShaderType)]
+struct ColorGradeUniform {
+    domain_min: Vec3,
+    strength: f32,
+    domain_max: Vec3,
+}
+
+pub struct ColorGradeNodeData {
+    pub pipeline: RenderPipeline,
+    pub layout: BindGroupLayout,
+    pub sampler: Sampler,
+    pub lut: Texture,
+    pub lut_view: TextureView,
+    pub uniform: DynamicGpuBuffer,
+}
+
+/// Grades the display encoded color through a 3D LUT.
+///
+/// Place it after `TonemappingNode`, with `TonemappingNode::to_surface` disabled.
+pub struct ColorGradeNode {
+    /// Uploaded when the node is built.
+    pub lut: CubeLut,
+    /// Blend from the original color at 0 to the graded one at 1.
+    pub strength: f32,
+
+    pub data: Option<ColorGradeNodeData>,
+}
+
+impl ColorGradeNode {
+    pub fn new(lut: CubeLut) -> Self {
+        Self {
+            lut,
+            strength: 1.,
+            data: None,
+        }
+    }
+
+    /// Load the LUT from an Adobe `.cube` file.
+    pub fn from_cube(path: impl AsRef<Path>) -> Result<Self, CubeLutError> {
+        Ok(Self::new(CubeLut::load(path)?))
+    }
+}
+
+impl RenderNode for ColorGradeNode {
+    fn require_shaders(&self) -> Option<&'static [(&'static [&'static str], &'static str)]> {
+        Some(&[
+            (&[], include_str!("../shader/fullscreen.wgsl")),
+            (
+                &[
+                    include_str!("../shader/fullscreen.wgsl"),
+                    include_str!("../shader/math.wgsl"),
+                ],
+                include_str!("../shader/post_processing/color_grade.wgsl"),
+            ),
+        ])
+    }
+
+    fn build(
+        &mut self,
+        GpuScene { assets, .. }: &mut GpuScene,
+        RenderContext {
+            device,
+            queue,
+            node,
+            targets,
+            ..
+        }: RenderContext,
+    ) {
+        let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("color_grade_layout"),
+            entries: &[
+                // Color
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: false },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                // LUT
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D3,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                // LUT Sampler
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+                // Config
+                BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: Some(ColorGradeUniform::min_size()),
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("color_grade_pipeline_layout"),
+            bind_group_layouts: &[&layout],
+            ..Default::default()
+        });
+
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("color_grade_pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &node.shaders[0],
+                entry_point: "vertex",
+                compilation_options: Default::default(),
+                buffers: &[],
+            },
+            fragment: Some(FragmentState {
+                module: &node.shaders[1],
+                entry_point: "fragment",
+                compilation_options: Default::default(),
+                targets: &[Some(ColorTargetState {
+                    format: targets.color_format,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: Default::default(),
+            depth_stencil: Default::default(),
+            multisample: Default::default(),
+            multiview: Default::default(),
+            cache: node.pipeline_cache.as_deref(),
+        });
+
+        // Clamped, so the edges of the cube don't wrap around.
+        let sampler = create_sampler(
+            device,
+            &SamplerDescriptor {
+                label: Some("color_grade_lut_sampler"),
+                address_mode_u: AddressMode::ClampToEdge,
+                address_mode_v: AddressMode::ClampToEdge,
+                address_mode_w: AddressMode::ClampToEdge,
+                mag_filter: FilterMode::Linear,
+                min_filter: FilterMode::Linear,
+                ..Default::default()
+            },
+            assets.max_anisotropy,
+        );
+
+        let lut = self.lut.create_texture(device, queue);
+        self.data = Some(ColorGradeNodeData {
+            pipeline,
+            layout,
+            sampler,
+            lut_view: lut.create_view(&Default::default()),
+            lut,
+            uniform: DynamicGpuBuffer::new(BufferUsages::UNIFORM),
+        });
+    }
+
+    fn prepare(
+        &mut self,
+        _scene: &mut GpuScene,
+        RenderContext { device, queue, .. }: RenderContext,
+    ) {
+        let Some(ColorGradeNodeData { uniform, .. }) = &mut self.data else {
+            return;
+        };
+
+        uniform.clear();
+        uniform.push(&ColorGradeUniform {
+            domain_min: self.lut.domain_min,
+            strength: self.strength,
+            domain_max: self.lut.domain_max,
+        });
+        uniform.write::<ColorGradeUniform>(device, queue);
+    }
+
+    fn draw(
+        &self,
+        GpuScene { assets, .. }: &mut GpuScene,
+        RenderContext {
+            device,
+            queue,
+            targets,
+            ..
+        }: RenderContext,
+    ) {
+        let Some(ColorGradeNodeData {
+            pipeline,
+            layout,
+            sampler,
+            lut_view,
+            uniform,
+            ..
+        }) = &self.data
+        else {
+            return;
+        };
+
+        let post_process = targets.swap_chain.start_post_process();
+        let bind_group = assets.bind_group_cache.get_or_create(
+            device,
+            &BindGroupDescriptor {
+                label: Some("color_grade_bind_group"),
+                layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::TextureView(post_process.src),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::TextureView(lut_view),
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: BindingResource::Sampler(sampler),
+                    },
+                    BindGroupEntry {
+                        binding: 3,
+                        resource: uniform.entire_binding().unwrap(),
+                    },
+                ],
+            },
+        );
+
+        let mut command_encoder = device.create_command_encoder(&Default::default());
+
+        {
+            let mut pass = command_encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("color_grade_pass"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: post_process.dst,
+                    resolve_target: None,
+                    ops: Default::default(),
+                })],
+                ..Default::default()
+            });
+
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.draw(0..3, 0..1);
+        }
+
+        queue.submit([command_encoder.finish()]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aurora_core::{
+        render::{
+            flow::{GeneralNode, RenderFlow},
+            scene::GpuScene,
+        },
+        util::testing::{self, TestTargets},
+        WgpuRenderer,
+    };
+    use glam::{UVec2, Vec3};
+    use wgpu::{
+        Extent3d, ImageCopyTexture, ImageDataLayout, Origin3d, TextureAspect, TextureFormat,
+    };
+
+    use super::ColorGradeNode;
+    use crate::texture::CubeLut;
+
+    const SIZE: u32 = 16;
+
+    fn grade(renderer: &WgpuRenderer, node: ColorGradeNode, frame: &[u8]) -> Vec<u8> {
+        let size = UVec2::splat(SIZE);
+        let test_targets = TestTargets::new(&renderer.device, size, TextureFormat::Rgba8Unorm);
+        let targets = test_targets.targets();
+
+        renderer.queue.write_texture(
+            ImageCopyTexture {
+                texture: test_targets.swap_chain.current_texture(),
+                mip_level: 0,
+                origin: Origin3d::ZERO,
+                aspect: TextureAspect::All,
+            },
+            frame,
+            ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(size.x * 4),
+                rows_per_image: None,
+            },
+            Extent3d {
+                width: size.x,
+                height: size.y,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        let mut scene = GpuScene::default();
+        let mut flow = RenderFlow::default();
+        flow.add::<GeneralNode>().add_initialized(node);
+        flow.build(renderer, &mut scene, None, &targets).unwrap();
+        flow.run(renderer, &mut scene, &targets);
+
+        test_targets.read(&renderer).into_raw()
+    }
+
+    #[test]
+    fn color_grade_lut() {
+        let Some(renderer) = testing::renderer(None, None) else {
+            return;
+        };
+
+        let frame = (0..SIZE * SIZE * 4)
+            .map(|i| if i % 4 == 3 { 255 } else { (i * 7 % 256) as u8 })
+            .collect::<Vec<_>>();
+
+        // Not a power of two, which must still map the domain onto the entries exactly.
+        let identity = grade(&renderer, ColorGradeNode::new(CubeLut::identity(5)), &frame);
+        for (graded, original) in identity.iter().zip(&frame) {
+            assert!(graded.abs_diff(*original) <= 2, "{graded} != {original}");
+        }
+
+        let mut inverted = CubeLut::identity(5);
+        inverted.data.iter_mut().for_each(|c| *c = Vec3::ONE - *c);
+        let off = grade(
+            &renderer,
+            ColorGradeNode {
+                strength: 0.,
+                ..ColorGradeNode::new(inverted.clone())
+            },
+            &frame,
+        );
+        assert_eq!(off, frame);
+
+        let black = [0, 0, 0, 255].repeat((SIZE * SIZE) as usize);
+        let white = grade(&renderer, ColorGradeNode::new(inverted), &black);
+        assert!(white.iter().all(|c| *c == 255));
+    }
+
+    #[test]
+    fn bind_group_reused() {
+        let Some(renderer) = testing::renderer(None, None) else {
+            return;
+        };
+
+        let test_targets = TestTargets::new(
+            &renderer.device,
+            UVec2::splat(SIZE),
+            TextureFormat::Rgba8Unorm,
+        );
+        let targets = test_targets.targets();
+        let mut scene = GpuScene::default();
+        let mut flow = RenderFlow::default();
+        flow.add::<GeneralNode>()
+            .add_initialized(ColorGradeNode::new(CubeLut::identity(2)));
+        flow.build(&renderer, &mut scene, None, &targets).unwrap();
+
+        // Grading swaps the swap chain, so a bind group for each of its textures.
+        for _ in 0..2 {
+            flow.run(&renderer, &mut scene, &targets);
+        }
+        flow.run(&renderer, &mut scene, &targets);
+        let stats = scene.assets.bind_group_cache.stats();
+        assert_eq!((stats.created, stats.reused), (0, 1));
+    }
+}
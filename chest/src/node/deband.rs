@@ -0,0 +1,260 @@
+use std::collections::HashMap;
+
+use aurora_core::render::{
+    flow::{RenderContext, RenderNode},
+    resource::DynamicGpuBuffer,
+    scene::GpuScene,
+    ShaderDefEnum,
+};
+use aurora_derive::ShaderDefEnum;
+use encase::ShaderType;
+use naga_oil::compose::ShaderDefValue;
+use wgpu::{
+    BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, BufferBindingType, BufferUsages,
+    ColorTargetState, ColorWrites, FragmentState, PipelineLayoutDescriptor,
+    RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor,
+    ShaderStages, TextureFormat, TextureSampleType, TextureViewDimension, VertexState,
+};
+
+#[derive(ShaderDefEnum, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DitherMethod {
+    /// Static 8x8 ordered dithering.
+    Bayer,
+    /// Hash noise with triangular distribution, changing every frame.
+    #[default]
+    TriangularNoise,
+}
+
+#[derive(ShaderType)]
+pub struct DebandConfig {
+    /// Dither amplitude in quantization steps of the surface.
+    pub strength: f32,
+    pub quantization_step: f32,
+    pub srgb_surface: u32,
+    pub frame: u32,
+}
+
+impl Default for DebandConfig {
+    fn default() -> Self {
+        Self {
+            strength: 1.0,
+            quantization_step: 1.0 / 255.0,
+            srgb_surface: 0,
+            frame: 0,
+        }
+    }
+}
+
+/// Size of a single quantization step of `format`, or `None` if it's a float format
+/// that doesn't band visibly.
+pub fn quantization_step(format: TextureFormat) -> Option<f32> {
+    match format {
+        TextureFormat::Rgb10a2Unorm => Some(1.0 / 1023.0),
+        TextureFormat::Rgba16Unorm => Some(1.0 / 65535.0),
+        TextureFormat::Rgba16Float
+        | TextureFormat::Rgba32Float
+        | TextureFormat::Rg11b10Float
+        | TextureFormat::Rgb9e5Ufloat => None,
+        _ => Some(1.0 / 255.0),
+    }
+}
+
+pub struct DebandNodeData {
+    pub pipeline: RenderPipeline,
+    pub layout: BindGroupLayout,
+    pub config: DynamicGpuBuffer,
+}
+
+/// Dithers the swap chain to hide banding once it's quantized to the surface format.
+///
+/// Place it right before `PresentNode`, with `TonemappingNode::to_surface` disabled.
+#[derive(Default)]
+pub struct DebandNode {
+    pub method: DitherMethod,
+    pub config: DebandConfig,
+
+    pub data: Option<DebandNodeData>,
+}
+
+impl RenderNode for DebandNode {
+    fn require_shader_defs(&self, shader_defs: &mut HashMap<String, ShaderDefValue>) {
+        shader_defs.extend([self.method.to_def()]);
+    }
+
+    fn require_shaders(&self) -> Option<&'static [(&'static [&'static str], &'static str)]> {
+        Some(&[
+            (&[], include_str!("../shader/fullscreen.wgsl")),
+            (
+                &[
+                    include_str!("../shader/fullscreen.wgsl"),
+                    include_str!("../shader/hash.wgsl"),
+                    include_str!("../shader/math.wgsl"),
+                ],
+                include_str!("../shader/post_processing/deband.wgsl"),
+            ),
+        ])
+    }
+
+    fn build(
+        &mut self,
+        _scene: &mut GpuScene,
+        RenderContext {
+            device,
+            node,
+            targets,
+            ..
+        }: RenderContext,
+    ) {
+        let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("deband_layout"),
+            entries: &[
+                // Color
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: false },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                // Config
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: Some(DebandConfig::min_size()),
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("deband_pipeline_layout"),
+            bind_group_layouts: &[&layout],
+            ..Default::default()
+        });
+
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("deband_pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &node.shaders[0],
+                entry_point: "vertex",
+                compilation_options: Default::default(),
+                buffers: &[],
+            },
+            fragment: Some(FragmentState {
+                module: &node.shaders[1],
+                entry_point: "fragment",
+                compilation_options: Default::default(),
+                targets: &[Some(ColorTargetState {
+                    format: targets.color_format,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: Default::default(),
+            depth_stencil: Default::default(),
+            multisample: Default::default(),
+            multiview: Default::default(),
+            cache: node.pipeline_cache.as_deref(),
+        });
+
+        self.data = Some(DebandNodeData {
+            pipeline,
+            layout,
+            config: DynamicGpuBuffer::new(BufferUsages::UNIFORM),
+        });
+    }
+
+    fn prepare(
+        &mut self,
+        GpuScene { frame_count, .. }: &mut GpuScene,
+        RenderContext {
+            device,
+            queue,
+            targets,
+            ..
+        }: RenderContext,
+    ) {
+        let Some(DebandNodeData { config, .. }) = &mut self.data else {
+            return;
+        };
+
+        // Float surfaces don't band, so there is nothing to hide.
+        self.config.quantization_step = quantization_step(targets.surface_format).unwrap_or(0.0);
+        // Whether the format or the node writing the surface encodes, values are quantized
+        // after it.
+        self.config.srgb_surface =
+            (targets.surface_format.is_srgb() || targets.encodes_srgb()) as u32;
+        self.config.frame = *frame_count;
+        config.clear();
+        config.push(&self.config);
+        config.write::<DebandConfig>(device, queue);
+    }
+
+    fn draw(
+        &self,
+        GpuScene { assets, .. }: &mut GpuScene,
+        RenderContext {
+            device,
+            queue,
+            targets,
+            ..
+        }: RenderContext,
+    ) {
+        let Some(DebandNodeData {
+            pipeline,
+            layout,
+            config,
+        }) = &self.data
+        else {
+            return;
+        };
+
+        let post_process = targets.swap_chain.start_post_process();
+        let bind_group = assets.bind_group_cache.get_or_create(
+            device,
+            &BindGroupDescriptor {
+                label: Some("deband_bind_group"),
+                layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::TextureView(post_process.src),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: config.entire_binding().unwrap(),
+                    },
+                ],
+            },
+        );
+
+        let mut command_encoder = device.create_command_encoder(&Default::default());
+
+        {
+            let mut pass = command_encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("deband_pass"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: post_process.dst,
+                    resolve_target: None,
+                    ops: Default::default(),
+                })],
+                ..Default::default()
+            });
+
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.draw(0..3, 0..1);
+        }
+
+        queue.submit([command_encoder.finish()]);
+    }
+}
@@ -0,0 +1,369 @@
+use std::collections::HashMap;
+
+use aurora_core::render::{
+    flow::{RenderContext, RenderNode},
+    scene::GpuScene,
+};
+use wgpu::{
+    BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, Color, ColorTargetState, ColorWrites,
+    FragmentState, LoadOp, Operations, PipelineLayout, PipelineLayoutDescriptor,
+    RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor,
+    ShaderStages, StoreOp, TextureSampleType, TextureViewDescriptor, TextureViewDimension,
+    VertexState,
+};
+
+use crate::node::{
+    DEPTH_PREPASS_TEXTURE, MOTION_VECTOR_PREPASS_TEXTURE, NORMAL_PREPASS_TEXTURE, SHADOW_MAPPING,
+    SSAO,
+};
+
+/// Buffer shown by [`DebugViewNode`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DebugTarget {
+    /// Depth prepass, linear between the near and far plane.
+    #[default]
+    Depth,
+    Normal,
+    /// Hue for the direction, brightness for the length.
+    MotionVector,
+    Ssao,
+    /// A cascade of the directional shadow map.
+    ShadowCascade(u32),
+}
+
+impl DebugTarget {
+    fn entry_point(self) -> &'static str {
+        match self {
+            DebugTarget::Depth => "depth_fragment",
+            DebugTarget::Normal => "normal_fragment",
+            DebugTarget::MotionVector => "motion_vector_fragment",
+            DebugTarget::Ssao => "ssao_fragment",
+            DebugTarget::ShadowCascade(_) => "shadow_fragment",
+        }
+    }
+
+    fn is_depth(self) -> bool {
+        matches!(self, DebugTarget::Depth | DebugTarget::ShadowCascade(_))
+    }
+}
+
+pub struct DebugViewNodeData {
+    pub depth_layout: BindGroupLayout,
+    pub color_layout: BindGroupLayout,
+    pub depth_pipeline_layout: PipelineLayout,
+    pub color_pipeline_layout: PipelineLayout,
+    /// Keyed by fragment entry point.
+    pub pipelines: HashMap<&'static str, RenderPipeline>,
+}
+
+/// Replaces the image with one of the intermediate buffers, for inspecting them.
+///
+/// The node producing the buffer must be in the flow as well, nothing is drawn otherwise.
+/// Switch buffers between frames by changing `target` through `RenderFlow::get_mut`.
+#[derive(Default)]
+pub struct DebugViewNode {
+    pub target: DebugTarget,
+
+    pub data: Option<DebugViewNodeData>,
+}
+
+impl RenderNode for DebugViewNode {
+    fn require_shaders(&self) -> Option<&'static [(&'static [&'static str], &'static str)]> {
+        Some(&[
+            (&[], include_str!("../shader/fullscreen.wgsl")),
+            (
+                &[
+                    include_str!("../shader/fullscreen.wgsl"),
+                    include_str!("../shader/math.wgsl"),
+                ],
+                include_str!("../shader/debug_view.wgsl"),
+            ),
+            (
+                &[
+                    include_str!("../shader/common/common_type.wgsl"),
+                    include_str!("../shader/common/camera.wgsl"),
+                    include_str!("../shader/common/common_binding.wgsl"),
+                    include_str!("../shader/fullscreen.wgsl"),
+                ],
+                include_str!("../shader/debug_view_depth.wgsl"),
+            ),
+        ])
+    }
+
+    fn build(
+        &mut self,
+        GpuScene { assets, .. }: &mut GpuScene,
+        RenderContext { device, .. }: RenderContext,
+    ) {
+        let depth_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("debug_view_depth_layout"),
+            entries: &[
+                // Depth
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Depth,
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let color_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("debug_view_color_layout"),
+            entries: &[
+                // Color
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: false },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let common_layout = assets.common_layout.as_ref().unwrap();
+        let depth_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("debug_view_depth_pipeline_layout"),
+            bind_group_layouts: &[common_layout, &depth_layout],
+            ..Default::default()
+        });
+        let color_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("debug_view_color_pipeline_layout"),
+            bind_group_layouts: &[common_layout, &color_layout],
+            ..Default::default()
+        });
+
+        self.data = Some(DebugViewNodeData {
+            depth_layout,
+            color_layout,
+            depth_pipeline_layout,
+            color_pipeline_layout,
+            pipelines: Default::default(),
+        });
+    }
+
+    fn prepare(
+        &mut self,
+        _scene: &mut GpuScene,
+        RenderContext {
+            device,
+            node,
+            targets,
+            ..
+        }: RenderContext,
+    ) {
+        let Some(data) = &mut self.data else {
+            return;
+        };
+
+        // Built once the target is first shown, as some backends can't load from depth
+        // textures at all.
+        let entry_point = self.target.entry_point();
+        if data.pipelines.contains_key(entry_point) {
+            return;
+        }
+
+        let (layout, module) = if self.target.is_depth() {
+            (&data.depth_pipeline_layout, &node.shaders[2])
+        } else {
+            (&data.color_pipeline_layout, &node.shaders[1])
+        };
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("debug_view_pipeline"),
+            layout: Some(layout),
+            vertex: VertexState {
+                module: &node.shaders[0],
+                entry_point: "vertex",
+                compilation_options: Default::default(),
+                buffers: &[],
+            },
+            fragment: Some(FragmentState {
+                module,
+                entry_point,
+                compilation_options: Default::default(),
+                targets: &[Some(ColorTargetState {
+                    format: targets.color_format,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: Default::default(),
+            depth_stencil: Default::default(),
+            multisample: Default::default(),
+            multiview: Default::default(),
+            cache: node.pipeline_cache.as_deref(),
+        });
+        data.pipelines.insert(entry_point, pipeline);
+    }
+
+    fn draw(
+        &self,
+        GpuScene { assets, .. }: &mut GpuScene,
+        RenderContext {
+            device,
+            queue,
+            targets,
+            ..
+        }: RenderContext,
+    ) {
+        let Some(data) = &self.data else {
+            return;
+        };
+
+        let Some(pipeline) = data.pipelines.get(self.target.entry_point()) else {
+            return;
+        };
+
+        // Cascades are layers of an array, so a single one needs its own view.
+        let cascade_view;
+        let view = match self.target {
+            DebugTarget::Depth => assets.texture_views.get(&DEPTH_PREPASS_TEXTURE.view),
+            DebugTarget::Normal => assets.texture_views.get(&NORMAL_PREPASS_TEXTURE.view),
+            DebugTarget::MotionVector => assets
+                .texture_views
+                .get(&MOTION_VECTOR_PREPASS_TEXTURE.view),
+            DebugTarget::Ssao => assets.texture_views.get(&SSAO.ssao_texture_view),
+            DebugTarget::ShadowCascade(cascade) => {
+                cascade_view = assets
+                    .textures
+                    .get(&SHADOW_MAPPING.directional_shadow_map)
+                    .filter(|map| cascade < map.depth_or_array_layers())
+                    .map(|map| {
+                        map.create_view(&TextureViewDescriptor {
+                            label: Some("debug_view_cascade_view"),
+                            dimension: Some(TextureViewDimension::D2),
+                            base_array_layer: cascade,
+                            array_layer_count: Some(1),
+                            ..Default::default()
+                        })
+                    });
+                cascade_view.as_ref()
+            }
+        };
+        let Some(view) = view else {
+            return;
+        };
+
+        let (layout, binding) = if self.target.is_depth() {
+            (&data.depth_layout, 0)
+        } else {
+            (&data.color_layout, 1)
+        };
+        let bind_group = assets.bind_group_cache.get_or_create(
+            device,
+            &BindGroupDescriptor {
+                label: Some("debug_view_bind_group"),
+                layout,
+                entries: &[BindGroupEntry {
+                    binding,
+                    resource: BindingResource::TextureView(view),
+                }],
+            },
+        );
+
+        let mut command_encoder = device.create_command_encoder(&Default::default());
+
+        {
+            let mut pass = command_encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("debug_view_pass"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: targets.swap_chain.current_view(),
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Clear(Color::TRANSPARENT),
+                        store: StoreOp::Store,
+                    },
+                })],
+                ..Default::default()
+            });
+
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, assets.common_bind_group.as_ref().unwrap(), &[]);
+            pass.set_bind_group(1, &bind_group, &[]);
+            pass.draw(0..3, 0..1);
+        }
+
+        queue.submit([command_encoder.finish()]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aurora_core::{
+        render::{
+            flow::{GeneralNode, RenderFlow},
+            helper::{CameraProjection, OrthographicProjection},
+            scene::{GpuScene, MaterialInstanceId},
+        },
+        util::testing::{self, TestTargets},
+    };
+    use glam::{UVec2, Vec2};
+
+    use wgpu::TextureFormat;
+
+    use super::{DebugTarget, DebugViewNode};
+    use crate::node::{DepthPrepassNode, MotionVectorPrepassNode, NormalPrepassNode};
+
+    const SIZE: u32 = 64;
+
+    #[test]
+    fn debug_view_targets() {
+        let Some(renderer) = testing::renderer(None, None) else {
+            return;
+        };
+
+        // A quad facing the camera, covering the middle half of the view.
+        let mesh = testing::quad(Vec2::splat(-0.5), Vec2::splat(0.5), -1.);
+
+        let mut scene = GpuScene::default();
+        testing::add_static_mesh(&mut scene, mesh, MaterialInstanceId::default());
+        scene.original.camera.projection =
+            CameraProjection::Orthographic(OrthographicProjection::symmetric(2., 2., 0.1, 10.));
+
+        let size = UVec2::splat(SIZE);
+        let test_targets = TestTargets::new(&renderer.device, size, TextureFormat::Rgba8Unorm)
+            .with_depth(&renderer.device);
+        let targets = test_targets.targets();
+
+        let mut flow = RenderFlow::default();
+        flow.add::<GeneralNode>()
+            .add::<DepthPrepassNode>()
+            .add::<NormalPrepassNode>()
+            .add::<MotionVectorPrepassNode>()
+            .add_initialized(DebugViewNode {
+                target: DebugTarget::Normal,
+                data: None,
+            });
+        flow.set_queue(scene.static_meshes.clone());
+        flow.build(&renderer, &mut scene, None, &targets).unwrap();
+
+        let mut run = |flow: &mut RenderFlow| {
+            flow.run(&renderer, &mut scene, &targets);
+            test_targets.read(&renderer)
+        };
+
+        // +Z remapped into [0, 1].
+        let image = run(&mut flow);
+        let normal = image.get_pixel(32, 32);
+        assert!(normal[0].abs_diff(128) <= 1 && normal[1].abs_diff(128) <= 1 && normal[2] == 255);
+        assert_eq!(image.get_pixel(4, 4).0, [0, 0, 0, 255]);
+
+        // Switched between frames, and nothing moves.
+        flow.get_mut::<DebugViewNode>().unwrap().target = DebugTarget::MotionVector;
+        let image = run(&mut flow);
+        assert_eq!(image.get_pixel(32, 32).0, [0, 0, 0, 255]);
+
+        // Not in the flow, so nothing is drawn.
+        flow.get_mut::<DebugViewNode>().unwrap().target = DebugTarget::Ssao;
+        assert_eq!(run(&mut flow).get_pixel(32, 32).0, [0, 0, 0, 255]);
+    }
+}
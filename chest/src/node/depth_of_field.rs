@@ -1,21 +1,35 @@
-use aurora_core::render::{
-    flow::{RenderContext, RenderNode},
-    resource::DynamicGpuBuffer,
-    scene::GpuScene,
+use std::collections::HashMap;
+
+use aurora_core::{
+    render::{
+        budget::Quality,
+        flow::{NodeContext, RenderContext, RenderNode},
+        resource::{DynamicGpuBuffer, RenderTargets},
+        scene::GpuScene,
+    },
+    util::create_sampler,
 };
 use encase::ShaderType;
+use glam::UVec2;
+use naga_oil::compose::ShaderDefValue;
 use wgpu::{
     BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
     BindGroupLayoutEntry, BindingResource, BindingType, BufferBindingType, BufferUsages, Color,
-    ColorTargetState, ColorWrites, FilterMode, FragmentState, LoadOp, Operations,
+    ColorTargetState, ColorWrites, Device, Extent3d, FilterMode, FragmentState, LoadOp, Operations,
     PipelineLayoutDescriptor, RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline,
     RenderPipelineDescriptor, Sampler, SamplerBindingType, SamplerDescriptor, ShaderStages,
-    StoreOp, Texture, TextureDescriptor, TextureSampleType, TextureView, TextureViewDimension,
-    VertexState,
+    StoreOp, Texture, TextureDescriptor, TextureFormat, TextureSampleType, TextureUsages,
+    TextureView, TextureViewDimension, VertexState,
 };
 
 use crate::node::DEPTH_PREPASS_TEXTURE;
 
+pub const DOF_NEAR_COC_FORMAT: TextureFormat = TextureFormat::R16Float;
+pub const DOF_HALF_RES_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+pub const DOF_TILE_COC_FORMAT: TextureFormat = TextureFormat::R16Float;
+/// Tile size in full resolution pixels, for [`DofQuality::Half`].
+pub const DOF_TILE_SIZE: u32 = 16;
+
 pub enum DofPass {
     GaussianHorizontal,
     GaussianVertical,
@@ -39,6 +53,26 @@ pub struct HexagonDofTargets {
     pub target_view_b: TextureView,
 }
 
+impl HexagonDofTargets {
+    pub fn new(device: &Device, desc: &TextureDescriptor) -> Self {
+        let target_a = device.create_texture(&TextureDescriptor {
+            label: Some("dof_mrt_a"),
+            ..desc.clone()
+        });
+        let target_b = device.create_texture(&TextureDescriptor {
+            label: Some("dof_mrt_b"),
+            ..desc.clone()
+        });
+
+        Self {
+            target_view_a: target_a.create_view(&Default::default()),
+            target_a,
+            target_view_b: target_b.create_view(&Default::default()),
+            target_b,
+        }
+    }
+}
+
 pub struct HexagonDof {
     pub vert_and_diag: RenderPipeline,
     pub vert_and_diag_layout: BindGroupLayout,
@@ -50,6 +84,96 @@ pub struct HexagonDof {
     pub sampler: Sampler,
 }
 
+pub struct TiledDofTargets {
+    pub half_color: Texture,
+    pub half_color_view: TextureView,
+    pub tile_coc: Texture,
+    pub tile_coc_view: TextureView,
+    pub half_blur: Texture,
+    pub half_blur_view: TextureView,
+}
+
+impl TiledDofTargets {
+    pub fn new(device: &Device, desc: &TextureDescriptor) -> Self {
+        let full_size = desc.size;
+        let half_size = Extent3d {
+            width: full_size.width.div_ceil(2),
+            height: full_size.height.div_ceil(2),
+            depth_or_array_layers: 1,
+        };
+        let tile_size = Extent3d {
+            width: full_size.width.div_ceil(DOF_TILE_SIZE),
+            height: full_size.height.div_ceil(DOF_TILE_SIZE),
+            depth_or_array_layers: 1,
+        };
+
+        let create_target = |label, size, format| {
+            let texture = device.create_texture(&TextureDescriptor {
+                label: Some(label),
+                size,
+                format,
+                usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+                ..desc.clone()
+            });
+            let view = texture.create_view(&Default::default());
+            (texture, view)
+        };
+
+        let (half_color, half_color_view) =
+            create_target("dof_half_color", half_size, DOF_HALF_RES_FORMAT);
+        let (tile_coc, tile_coc_view) =
+            create_target("dof_tile_coc", tile_size, DOF_TILE_COC_FORMAT);
+        let (half_blur, half_blur_view) =
+            create_target("dof_half_blur", half_size, DOF_HALF_RES_FORMAT);
+
+        Self {
+            half_color,
+            half_color_view,
+            tile_coc,
+            tile_coc_view,
+            half_blur,
+            half_blur_view,
+        }
+    }
+}
+
+pub struct TiledDof {
+    pub downsample: RenderPipeline,
+    pub downsample_layout: BindGroupLayout,
+    pub max_coc: RenderPipeline,
+    pub max_coc_layout: BindGroupLayout,
+    pub gather: RenderPipeline,
+    pub gather_layout: BindGroupLayout,
+    pub composite: RenderPipeline,
+    pub composite_layout: BindGroupLayout,
+
+    pub targets: TiledDofTargets,
+    pub config: DynamicGpuBuffer,
+    pub sampler: Sampler,
+}
+
+pub struct DofNearCoc {
+    pub pipeline: RenderPipeline,
+    pub layout: BindGroupLayout,
+    pub texture: Texture,
+    pub view: TextureView,
+}
+
+impl DofNearCoc {
+    pub fn create_texture(device: &Device, desc: &TextureDescriptor) -> (Texture, TextureView) {
+        let texture = device.create_texture(&TextureDescriptor {
+            label: Some("dof_near_coc"),
+            format: DOF_NEAR_COC_FORMAT,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+            ..desc.clone()
+        });
+        let view = texture.create_view(&Default::default());
+        (texture, view)
+    }
+}
+
 #[derive(ShaderType)]
 pub struct DepthOfField {
     pub focal_length: f32,
@@ -74,6 +198,7 @@ impl Default for DepthOfField {
 pub enum DepthOfFieldData {
     Gaussian(GaussianDof),
     Hexagon(HexagonDof),
+    Tiled(TiledDof),
 }
 
 #[derive(Default)]
@@ -83,15 +208,56 @@ pub enum DepthOfFieldMode {
     Hexagon,
 }
 
-#[derive(Default)]
+#[derive(Default, Clone, Copy, PartialEq, Eq)]
+pub enum DofQuality {
+    /// Blur at full resolution, using the kernel of [`DepthOfFieldMode`].
+    #[default]
+    Full,
+    /// Blur at half resolution, with the gather radius bounded by the max CoC of each tile.
+    /// Much cheaper for large CoC. [`DepthOfFieldMode`] is ignored.
+    Half,
+}
+
 pub struct DepthOfFieldNode {
     pub config: DepthOfField,
     pub mode: DepthOfFieldMode,
+    pub quality: DofQuality,
+    /// Blur objects in front of the focal plane. Their CoC is dilated in a dedicated pass,
+    /// so they bleed over the in focus background.
+    pub near_blur: bool,
+    /// Blur objects behind the focal plane.
+    pub far_blur: bool,
+    /// Set by the frame budget, [`Quality::Low`] forces [`DofQuality::Half`].
+    pub budget_quality: Quality,
 
+    pub near_coc: Option<DofNearCoc>,
     pub data: Option<DepthOfFieldData>,
 }
 
+impl Default for DepthOfFieldNode {
+    fn default() -> Self {
+        Self {
+            config: Default::default(),
+            mode: Default::default(),
+            quality: Default::default(),
+            near_blur: true,
+            far_blur: true,
+            budget_quality: Default::default(),
+            near_coc: None,
+            data: None,
+        }
+    }
+}
+
 impl DepthOfFieldNode {
+    /// Quality actually built, taking the frame budget into account.
+    pub fn effective_quality(&self) -> DofQuality {
+        match self.budget_quality {
+            Quality::Low => DofQuality::Half,
+            _ => self.quality,
+        }
+    }
+
     pub fn draw_gaussian(
         &self,
         GpuScene { assets, .. }: &mut GpuScene,
@@ -106,30 +272,42 @@ impl DepthOfFieldNode {
     ) {
         let post_processing = targets.swap_chain.start_post_process();
 
-        let bind_group = device.create_bind_group(&BindGroupDescriptor {
-            label: Some("dof_gaussian_bind_group"),
-            layout: &data.layout,
-            entries: &[
-                BindGroupEntry {
-                    binding: 0,
-                    resource: BindingResource::TextureView(
-                        &assets.texture_views[&DEPTH_PREPASS_TEXTURE.view],
-                    ),
-                },
-                BindGroupEntry {
-                    binding: 1,
-                    resource: BindingResource::TextureView(post_processing.src),
-                },
-                BindGroupEntry {
-                    binding: 2,
-                    resource: BindingResource::Sampler(&data.sampler),
-                },
-                BindGroupEntry {
-                    binding: 3,
-                    resource: data.config.entire_binding().unwrap(),
-                },
-            ],
-        });
+        let mut entries = vec![
+            BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(
+                    &assets.texture_views[&DEPTH_PREPASS_TEXTURE.view],
+                ),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: BindingResource::TextureView(post_processing.src),
+            },
+            BindGroupEntry {
+                binding: 2,
+                resource: BindingResource::Sampler(&data.sampler),
+            },
+            BindGroupEntry {
+                binding: 3,
+                resource: data.config.entire_binding().unwrap(),
+            },
+        ];
+
+        if let Some(near_coc) = &self.near_coc {
+            entries.push(BindGroupEntry {
+                binding: 5,
+                resource: BindingResource::TextureView(&near_coc.view),
+            });
+        }
+
+        let bind_group = assets.bind_group_cache.get_or_create(
+            device,
+            &BindGroupDescriptor {
+                label: Some("dof_gaussian_bind_group"),
+                layout: &data.layout,
+                entries: &entries,
+            },
+        );
 
         let mut command_encoder = device.create_command_encoder(&Default::default());
 
@@ -159,6 +337,60 @@ impl DepthOfFieldNode {
         queue.submit([command_encoder.finish()]);
     }
 
+    pub fn draw_near_coc(
+        &self,
+        GpuScene { assets, .. }: &mut GpuScene,
+        RenderContext { device, queue, .. }: &RenderContext,
+        near_coc: &DofNearCoc,
+    ) {
+        let config = match self.data.as_ref().unwrap() {
+            DepthOfFieldData::Gaussian(data) => &data.config,
+            DepthOfFieldData::Hexagon(data) => &data.config,
+            DepthOfFieldData::Tiled(data) => &data.config,
+        };
+
+        let bind_group = assets.bind_group_cache.get_or_create(
+            device,
+            &BindGroupDescriptor {
+                label: Some("dof_near_coc_bind_group"),
+                layout: &near_coc.layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::TextureView(
+                            &assets.texture_views[&DEPTH_PREPASS_TEXTURE.view],
+                        ),
+                    },
+                    BindGroupEntry {
+                        binding: 3,
+                        resource: config.entire_binding().unwrap(),
+                    },
+                ],
+            },
+        );
+
+        let mut command_encoder = device.create_command_encoder(&Default::default());
+
+        {
+            let mut pass = command_encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("dof_near_coc_pass"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: &near_coc.view,
+                    resolve_target: None,
+                    ops: Default::default(),
+                })],
+                ..Default::default()
+            });
+
+            pass.set_pipeline(&near_coc.pipeline);
+            pass.set_bind_group(0, assets.common_bind_group.as_ref().unwrap(), &[]);
+            pass.set_bind_group(1, &bind_group, &[]);
+            pass.draw(0..3, 0..1);
+        }
+
+        queue.submit([command_encoder.finish()]);
+    }
+
     pub fn draw_hexagon(
         &self,
         GpuScene { assets, .. }: &mut GpuScene,
@@ -169,34 +401,46 @@ impl DepthOfFieldNode {
         color_src0: &TextureView,
         color_src1: &TextureView,
     ) {
-        let bind_group = device.create_bind_group(&BindGroupDescriptor {
-            label: Some("dof_hexagon_vert_and_diag"),
-            layout: &data.vert_and_diag_layout,
-            entries: &[
-                BindGroupEntry {
-                    binding: 0,
-                    resource: BindingResource::TextureView(
-                        &assets.texture_views[&DEPTH_PREPASS_TEXTURE.view],
-                    ),
-                },
-                BindGroupEntry {
-                    binding: 1,
-                    resource: BindingResource::TextureView(color_src0),
-                },
-                BindGroupEntry {
-                    binding: 2,
-                    resource: BindingResource::Sampler(&data.sampler),
-                },
-                BindGroupEntry {
-                    binding: 3,
-                    resource: data.config.entire_binding().unwrap(),
-                },
-                BindGroupEntry {
-                    binding: 4,
-                    resource: BindingResource::TextureView(color_src1),
-                },
-            ],
-        });
+        let mut entries = vec![
+            BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(
+                    &assets.texture_views[&DEPTH_PREPASS_TEXTURE.view],
+                ),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: BindingResource::TextureView(color_src0),
+            },
+            BindGroupEntry {
+                binding: 2,
+                resource: BindingResource::Sampler(&data.sampler),
+            },
+            BindGroupEntry {
+                binding: 3,
+                resource: data.config.entire_binding().unwrap(),
+            },
+            BindGroupEntry {
+                binding: 4,
+                resource: BindingResource::TextureView(color_src1),
+            },
+        ];
+
+        if let Some(near_coc) = &self.near_coc {
+            entries.push(BindGroupEntry {
+                binding: 5,
+                resource: BindingResource::TextureView(&near_coc.view),
+            });
+        }
+
+        let bind_group = assets.bind_group_cache.get_or_create(
+            device,
+            &BindGroupDescriptor {
+                label: Some("dof_hexagon_vert_and_diag"),
+                layout: &data.vert_and_diag_layout,
+                entries: &entries,
+            },
+        );
 
         let pipeline = match pass_type {
             DofPass::HexagonVertAndDiag => &data.vert_and_diag,
@@ -221,15 +465,289 @@ impl DepthOfFieldNode {
 
         queue.submit([command_encoder.finish()]);
     }
+
+    pub fn draw_tiled(
+        &self,
+        GpuScene { assets, .. }: &mut GpuScene,
+        RenderContext {
+            device,
+            queue,
+            targets,
+            ..
+        }: &RenderContext,
+        data: &TiledDof,
+    ) {
+        let post_process = targets.swap_chain.start_post_process();
+
+        let mut full_res_entries = vec![
+            BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(
+                    &assets.texture_views[&DEPTH_PREPASS_TEXTURE.view],
+                ),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: BindingResource::TextureView(post_process.src),
+            },
+            BindGroupEntry {
+                binding: 2,
+                resource: BindingResource::Sampler(&data.sampler),
+            },
+            BindGroupEntry {
+                binding: 3,
+                resource: data.config.entire_binding().unwrap(),
+            },
+        ];
+
+        if let Some(near_coc) = &self.near_coc {
+            full_res_entries.push(BindGroupEntry {
+                binding: 5,
+                resource: BindingResource::TextureView(&near_coc.view),
+            });
+        }
+
+        let downsample = assets.bind_group_cache.get_or_create(
+            device,
+            &BindGroupDescriptor {
+                label: Some("dof_tiled_downsample_bind_group"),
+                layout: &data.downsample_layout,
+                entries: &full_res_entries,
+            },
+        );
+
+        let max_coc = assets.bind_group_cache.get_or_create(
+            device,
+            &BindGroupDescriptor {
+                label: Some("dof_tiled_max_coc_bind_group"),
+                layout: &data.max_coc_layout,
+                entries: &[BindGroupEntry {
+                    binding: 6,
+                    resource: BindingResource::TextureView(&data.targets.half_color_view),
+                }],
+            },
+        );
+
+        let gather = assets.bind_group_cache.get_or_create(
+            device,
+            &BindGroupDescriptor {
+                label: Some("dof_tiled_gather_bind_group"),
+                layout: &data.gather_layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: BindingResource::Sampler(&data.sampler),
+                    },
+                    BindGroupEntry {
+                        binding: 3,
+                        resource: data.config.entire_binding().unwrap(),
+                    },
+                    BindGroupEntry {
+                        binding: 6,
+                        resource: BindingResource::TextureView(&data.targets.half_color_view),
+                    },
+                    BindGroupEntry {
+                        binding: 7,
+                        resource: BindingResource::TextureView(&data.targets.tile_coc_view),
+                    },
+                ],
+            },
+        );
+
+        full_res_entries.push(BindGroupEntry {
+            binding: 8,
+            resource: BindingResource::TextureView(&data.targets.half_blur_view),
+        });
+        let composite = assets.bind_group_cache.get_or_create(
+            device,
+            &BindGroupDescriptor {
+                label: Some("dof_tiled_composite_bind_group"),
+                layout: &data.composite_layout,
+                entries: &full_res_entries,
+            },
+        );
+
+        let mut command_encoder = device.create_command_encoder(&Default::default());
+
+        for (label, pipeline, bind_group, target) in [
+            (
+                "dof_tiled_downsample_pass",
+                &data.downsample,
+                &downsample,
+                &data.targets.half_color_view,
+            ),
+            (
+                "dof_tiled_max_coc_pass",
+                &data.max_coc,
+                &max_coc,
+                &data.targets.tile_coc_view,
+            ),
+            (
+                "dof_tiled_gather_pass",
+                &data.gather,
+                &gather,
+                &data.targets.half_blur_view,
+            ),
+            (
+                "dof_tiled_composite_pass",
+                &data.composite,
+                &composite,
+                post_process.dst,
+            ),
+        ] {
+            let mut pass = command_encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some(label),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: Default::default(),
+                })],
+                ..Default::default()
+            });
+
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, assets.common_bind_group.as_ref().unwrap(), &[]);
+            pass.set_bind_group(1, bind_group, &[]);
+            pass.draw(0..3, 0..1);
+        }
+
+        queue.submit([command_encoder.finish()]);
+    }
+
+    fn build_tiled(
+        device: &Device,
+        node: &NodeContext,
+        targets: &RenderTargets,
+        common_layout: &BindGroupLayout,
+        entries: &[BindGroupLayoutEntry],
+        config: DynamicGpuBuffer,
+        sampler: Sampler,
+    ) -> TiledDof {
+        let texture_entry = |binding| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::FRAGMENT,
+            ty: BindingType::Texture {
+                sample_type: TextureSampleType::Float { filterable: true },
+                view_dimension: TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+
+        let downsample_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("dof_tiled_downsample_layout"),
+            entries,
+        });
+        let max_coc_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("dof_tiled_max_coc_layout"),
+            entries: &[texture_entry(6)],
+        });
+        let gather_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("dof_tiled_gather_layout"),
+            entries: &[entries[2], entries[3], texture_entry(6), texture_entry(7)],
+        });
+        let composite_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("dof_tiled_composite_layout"),
+            entries: &[entries, &[texture_entry(8)]].concat(),
+        });
+
+        let create_pipeline = |label, layout, entry_point, format| {
+            device.create_render_pipeline(&RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&device.create_pipeline_layout(&PipelineLayoutDescriptor {
+                    label: None,
+                    bind_group_layouts: &[common_layout, layout],
+                    ..Default::default()
+                })),
+                vertex: VertexState {
+                    module: &node.shaders[0],
+                    entry_point: "vertex",
+                    compilation_options: Default::default(),
+                    buffers: &[],
+                },
+                fragment: Some(FragmentState {
+                    module: &node.shaders[1],
+                    entry_point,
+                    compilation_options: Default::default(),
+                    targets: &[Some(ColorTargetState {
+                        format,
+                        blend: None,
+                        write_mask: ColorWrites::ALL,
+                    })],
+                }),
+                primitive: Default::default(),
+                depth_stencil: None,
+                multisample: Default::default(),
+                multiview: Default::default(),
+                cache: node.pipeline_cache.as_deref(),
+            })
+        };
+
+        let downsample = create_pipeline(
+            "dof_tiled_downsample",
+            &downsample_layout,
+            "tiled_downsample",
+            DOF_HALF_RES_FORMAT,
+        );
+        let max_coc = create_pipeline(
+            "dof_tiled_max_coc",
+            &max_coc_layout,
+            "tiled_max_coc",
+            DOF_TILE_COC_FORMAT,
+        );
+        let gather = create_pipeline(
+            "dof_tiled_gather",
+            &gather_layout,
+            "tiled_gather",
+            DOF_HALF_RES_FORMAT,
+        );
+        let composite = create_pipeline(
+            "dof_tiled_composite",
+            &composite_layout,
+            "tiled_composite",
+            targets.color_format,
+        );
+
+        TiledDof {
+            downsample,
+            downsample_layout,
+            max_coc,
+            max_coc_layout,
+            gather,
+            gather_layout,
+            composite,
+            composite_layout,
+
+            targets: TiledDofTargets::new(device, targets.swap_chain.desc()),
+            config,
+            sampler,
+        }
+    }
 }
 
 impl RenderNode for DepthOfFieldNode {
+    fn require_shader_defs(&self, shader_defs: &mut HashMap<String, ShaderDefValue>) {
+        shader_defs.insert(
+            "DOF_TILE_SIZE".to_string(),
+            ShaderDefValue::UInt(DOF_TILE_SIZE),
+        );
+
+        if self.near_blur {
+            shader_defs.insert("DOF_NEAR_BLUR".to_string(), Default::default());
+        }
+
+        if self.far_blur {
+            shader_defs.insert("DOF_FAR_BLUR".to_string(), Default::default());
+        }
+    }
+
     fn require_shaders(&self) -> Option<&'static [(&'static [&'static str], &'static str)]> {
         Some(&[
             (&[], include_str!("../shader/fullscreen.wgsl")),
             (
                 &[
                     include_str!("../shader/common/common_type.wgsl"),
+                    include_str!("../shader/common/camera.wgsl"),
                     include_str!("../shader/common/common_binding.wgsl"),
                     include_str!("../shader/fullscreen.wgsl"),
                     include_str!("../shader/math.wgsl"),
@@ -247,6 +765,7 @@ impl RenderNode for DepthOfFieldNode {
             queue,
             node,
             targets,
+            ..
         }: RenderContext,
     ) {
         let mut bf_config = DynamicGpuBuffer::new(BufferUsages::UNIFORM);
@@ -296,7 +815,9 @@ impl RenderNode for DepthOfFieldNode {
             },
         ];
 
-        if matches!(self.mode, DepthOfFieldMode::Hexagon) {
+        if self.effective_quality() == DofQuality::Full
+            && matches!(self.mode, DepthOfFieldMode::Hexagon)
+        {
             entries.push(BindGroupLayoutEntry {
                 binding: 4,
                 visibility: ShaderStages::FRAGMENT,
@@ -310,6 +831,62 @@ impl RenderNode for DepthOfFieldNode {
         }
 
         let common_layout = assets.common_layout.as_ref().unwrap();
+
+        if self.near_blur {
+            let near_coc_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("dof_near_coc_layout"),
+                entries: &[entries[0], entries[3]],
+            });
+
+            let near_coc_pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+                label: Some("dof_near_coc_dilation"),
+                layout: Some(&device.create_pipeline_layout(&PipelineLayoutDescriptor {
+                    label: Some("dof_near_coc_pipeline_layout"),
+                    bind_group_layouts: &[common_layout, &near_coc_layout],
+                    ..Default::default()
+                })),
+                vertex: VertexState {
+                    module: &node.shaders[0],
+                    entry_point: "vertex",
+                    compilation_options: Default::default(),
+                    buffers: &[],
+                },
+                fragment: Some(FragmentState {
+                    module: &node.shaders[1],
+                    entry_point: "near_coc_dilation",
+                    compilation_options: Default::default(),
+                    targets: &[Some(ColorTargetState {
+                        format: DOF_NEAR_COC_FORMAT,
+                        blend: None,
+                        write_mask: ColorWrites::ALL,
+                    })],
+                }),
+                primitive: Default::default(),
+                depth_stencil: None,
+                multisample: Default::default(),
+                multiview: Default::default(),
+                cache: node.pipeline_cache.as_deref(),
+            });
+
+            let (texture, view) = DofNearCoc::create_texture(device, targets.swap_chain.desc());
+            self.near_coc = Some(DofNearCoc {
+                pipeline: near_coc_pipeline,
+                layout: near_coc_layout,
+                texture,
+                view,
+            });
+
+            entries.push(BindGroupLayoutEntry {
+                binding: 5,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: true },
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            });
+        }
         let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
             label: Some("dof_layout"),
             entries: &entries,
@@ -345,16 +922,34 @@ impl RenderNode for DepthOfFieldNode {
             depth_stencil: None,
             multisample: Default::default(),
             multiview: Default::default(),
-            cache: None,
+            cache: node.pipeline_cache.as_deref(),
         };
 
-        let sampler = device.create_sampler(&SamplerDescriptor {
-            label: Some("dof_sampler"),
-            mag_filter: FilterMode::Linear,
-            min_filter: FilterMode::Linear,
-            mipmap_filter: FilterMode::Linear,
-            ..Default::default()
-        });
+        let sampler = create_sampler(
+            device,
+            &SamplerDescriptor {
+                label: Some("dof_sampler"),
+                mag_filter: FilterMode::Linear,
+                min_filter: FilterMode::Linear,
+                mipmap_filter: FilterMode::Linear,
+                ..Default::default()
+            },
+            assets.max_anisotropy,
+        );
+
+        if self.effective_quality() == DofQuality::Half {
+            let tiled = Self::build_tiled(
+                device,
+                node,
+                targets,
+                common_layout,
+                &entries,
+                bf_config,
+                sampler,
+            );
+            self.data = Some(DepthOfFieldData::Tiled(tiled));
+            return;
+        }
 
         match self.mode {
             DepthOfFieldMode::Gaussian => {
@@ -415,28 +1010,13 @@ impl RenderNode for DepthOfFieldNode {
                     ..desc.clone()
                 });
 
-                let tex = targets.swap_chain.desc();
-                let target_a = device.create_texture(&TextureDescriptor {
-                    label: Some("dof_mrt_a"),
-                    ..tex.clone()
-                });
-                let target_b = device.create_texture(&TextureDescriptor {
-                    label: Some("dof_mrt_b"),
-                    ..tex.clone()
-                });
-
                 self.data = Some(DepthOfFieldData::Hexagon(HexagonDof {
                     vert_and_diag,
                     rhomboid,
                     vert_and_diag_layout,
                     rhomboid_layout,
 
-                    mrt: HexagonDofTargets {
-                        target_view_a: target_a.create_view(&Default::default()),
-                        target_a,
-                        target_view_b: target_b.create_view(&Default::default()),
-                        target_b,
-                    },
+                    mrt: HexagonDofTargets::new(device, targets.swap_chain.desc()),
                     config: bf_config,
                     sampler,
                 }));
@@ -444,7 +1024,43 @@ impl RenderNode for DepthOfFieldNode {
         }
     }
 
+    fn set_quality(&mut self, quality: Quality) -> bool {
+        let previous = self.effective_quality();
+        self.budget_quality = quality;
+        self.effective_quality() != previous
+    }
+
+    fn resize(
+        &mut self,
+        _scene: &mut GpuScene,
+        RenderContext {
+            device, targets, ..
+        }: RenderContext,
+        _new_size: UVec2,
+    ) {
+        // Intermediate targets follow the swap chain, which is already resized.
+        let desc = targets.swap_chain.desc();
+
+        if let Some(near_coc) = &mut self.near_coc {
+            (near_coc.texture, near_coc.view) = DofNearCoc::create_texture(device, desc);
+        }
+
+        match &mut self.data {
+            Some(DepthOfFieldData::Hexagon(hexagon)) => {
+                hexagon.mrt = HexagonDofTargets::new(device, desc)
+            }
+            Some(DepthOfFieldData::Tiled(tiled)) => {
+                tiled.targets = TiledDofTargets::new(device, desc)
+            }
+            _ => {}
+        }
+    }
+
     fn draw(&self, scene: &mut GpuScene, context: RenderContext) {
+        if let Some(near_coc) = &self.near_coc {
+            self.draw_near_coc(scene, &context, near_coc);
+        }
+
         match self.data.as_ref().unwrap() {
             DepthOfFieldData::Gaussian(data) => {
                 self.draw_gaussian(scene, &context, data, DofPass::GaussianHorizontal);
@@ -495,6 +1111,7 @@ impl RenderNode for DepthOfFieldNode {
                     &data.mrt.target_view_b,
                 );
             }
+            DepthOfFieldData::Tiled(data) => self.draw_tiled(scene, &context, data),
         }
     }
 }
@@ -1,30 +1,128 @@
+use std::{collections::HashMap, num::NonZeroU32};
+
 use aurora_core::render::{
     flow::{RenderContext, RenderNode},
-    scene::{GpuScene, TextureId, TextureViewId},
+    mesh::{AlphaMode, Mesh},
+    resource::ModelUniforms,
+    scene::{GpuAssets, GpuScene, TextureId, TextureViewId},
 };
+use glam::UVec2;
 use uuid::Uuid;
 use wgpu::{
-    CompareFunction, DepthStencilState, Extent3d, FragmentState, LoadOp, Operations,
-    PipelineLayoutDescriptor, RenderPassDepthStencilAttachment, RenderPassDescriptor,
-    RenderPipelineDescriptor, StoreOp, TextureDescriptor, TextureDimension, TextureFormat,
-    TextureUsages, TextureViewDescriptor, VertexBufferLayout, VertexFormat, VertexState,
-    VertexStepMode,
+    CommandBuffer, CompareFunction, DepthStencilState, Device, Extent3d, FragmentState, LoadOp,
+    Operations, PipelineLayoutDescriptor, RenderPassDepthStencilAttachment, RenderPassDescriptor,
+    RenderPipeline, RenderPipelineDescriptor, StoreOp, TextureDescriptor, TextureDimension,
+    TextureFormat, TextureUsages, TextureViewDescriptor, TextureViewDimension, VertexFormat,
+    VertexState,
 };
 
+use crate::node::pbr::mesh_alpha_mode;
+
 pub struct DepthPrepassTexture {
     pub texture: TextureId,
+    /// 2D view, of the left eye when the flow is stereo, for nodes sampling depth or
+    /// drawing a single view.
     pub view: TextureViewId,
+    /// Array view of both eyes, only present when the flow is stereo.
+    pub eyes: TextureViewId,
+}
+
+impl DepthPrepassTexture {
+    /// View to attach to passes drawn with `multiview`, see [`NodeContext::multiview`].
+    ///
+    /// [`NodeContext::multiview`]: aurora_core::render::flow::NodeContext::multiview
+    pub fn attachment(&self, multiview: Option<NonZeroU32>) -> TextureViewId {
+        match multiview {
+            Some(_) => self.eyes,
+            None => self.view,
+        }
+    }
 }
 
 pub const DEPTH_PREPASS_TEXTURE: DepthPrepassTexture = DepthPrepassTexture {
     texture: TextureId(Uuid::from_u128(849651230456123074856245)),
     view: TextureViewId(Uuid::from_u128(8978946514851414745)),
+    eyes: TextureViewId(Uuid::from_u128(8978946514851414746)),
 };
 
 pub const DEPTH_PREPASS_FORMAT: TextureFormat = TextureFormat::Depth32Float;
 
+/// What a color pass starts from in [`DEPTH_PREPASS_TEXTURE`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DepthLoadOp {
+    /// Start from the far plane, so the pass writes the depth of everything it draws.
+    #[default]
+    Clear,
+    /// Keep the depth written by earlier passes, like [`DepthPrepassNode`].
+    Load,
+}
+
+impl DepthLoadOp {
+    pub fn load_op(self) -> LoadOp<f32> {
+        match self {
+            DepthLoadOp::Clear => LoadOp::Clear(1.),
+            DepthLoadOp::Load => LoadOp::Load,
+        }
+    }
+}
+
 #[derive(Default)]
-pub struct DepthPrepassNode;
+pub struct DepthPrepassNode {
+    /// Only positions are read, so meshes share pipelines by vertex stride.
+    pipelines: HashMap<u64, RenderPipeline>,
+    models: Option<ModelUniforms>,
+}
+
+impl DepthPrepassNode {
+    fn create_texture(
+        assets: &mut GpuAssets,
+        device: &Device,
+        size: UVec2,
+        multiview: Option<NonZeroU32>,
+    ) {
+        let depth_texture = device.create_texture(&TextureDescriptor {
+            label: Some("depth_prepass_texture"),
+            dimension: TextureDimension::D2,
+            format: DEPTH_PREPASS_FORMAT,
+            mip_level_count: 1,
+            sample_count: 1,
+            size: Extent3d {
+                width: size.x,
+                height: size.y,
+                depth_or_array_layers: multiview.map_or(1, |v| v.get()),
+            },
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        let depth_texture_view = depth_texture.create_view(&TextureViewDescriptor {
+            label: Some("depth_prepass_texture_view"),
+            dimension: Some(TextureViewDimension::D2),
+            array_layer_count: Some(1),
+            ..Default::default()
+        });
+
+        if multiview.is_some() {
+            let eyes_view = depth_texture.create_view(&TextureViewDescriptor {
+                label: Some("depth_prepass_eyes_view"),
+                dimension: Some(TextureViewDimension::D2Array),
+                ..Default::default()
+            });
+            assets
+                .texture_views
+                .insert(DEPTH_PREPASS_TEXTURE.eyes, eyes_view);
+        } else {
+            assets.texture_views.remove(&DEPTH_PREPASS_TEXTURE.eyes);
+        }
+
+        assets
+            .textures
+            .insert(DEPTH_PREPASS_TEXTURE.texture, depth_texture);
+        assets
+            .texture_views
+            .insert(DEPTH_PREPASS_TEXTURE.view, depth_texture_view);
+    }
+}
 
 impl RenderNode for DepthPrepassNode {
     fn restrict_mesh_format(&self) -> Option<&'static [VertexFormat]> {
@@ -56,45 +154,28 @@ impl RenderNode for DepthPrepassNode {
             ..
         }: RenderContext,
     ) {
-        let depth_texture = device.create_texture(&TextureDescriptor {
-            label: Some("depth_prepass_texture"),
-            dimension: TextureDimension::D2,
-            format: DEPTH_PREPASS_FORMAT,
-            mip_level_count: 1,
-            sample_count: 1,
-            size: Extent3d {
-                width: targets.size.x,
-                height: targets.size.y,
-                depth_or_array_layers: 1,
-            },
-            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
-            view_formats: &[],
-        });
-
-        let depth_texture_view = depth_texture.create_view(&TextureViewDescriptor {
-            label: Some("depth_prepass_texture_view"),
-            ..Default::default()
-        });
-
-        assets
-            .textures
-            .insert(DEPTH_PREPASS_TEXTURE.texture, depth_texture);
-        assets
-            .texture_views
-            .insert(DEPTH_PREPASS_TEXTURE.view, depth_texture_view);
+        Self::create_texture(assets, device, targets.size, node.multiview);
+        let models = self.models.insert(ModelUniforms::new(device));
 
         let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
             label: Some("depth_prepass_pipeline_layout"),
-            bind_group_layouts: &[assets.common_layout.as_ref().unwrap()],
+            bind_group_layouts: &[assets.common_layout.as_ref().unwrap(), &models.layout],
             push_constant_ranges: &[],
         });
 
-        for mesh in &node.meshes {
-            if node.pipelines.contains_key(&mesh.mesh.mesh) {
+        // Skinned meshes only have their interleaved vertices, which may be replaced after this.
+        let strides = node
+            .meshes
+            .iter()
+            .map(|mesh| assets.meshes[&mesh.mesh.mesh].vertex_stride())
+            .chain([Mesh::POSITION_ATTR.format.size()]);
+
+        self.pipelines.clear();
+        for stride in strides {
+            if self.pipelines.contains_key(&stride) {
                 continue;
             }
 
-            let instance = &assets.meshes[&mesh.mesh.mesh];
             let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
                 label: Some("depth_prepass_pipeline"),
                 layout: Some(&pipeline_layout),
@@ -102,11 +183,7 @@ impl RenderNode for DepthPrepassNode {
                     module: &node.shaders[0],
                     entry_point: "vertex",
                     compilation_options: Default::default(),
-                    buffers: &[VertexBufferLayout {
-                        array_stride: instance.vertex_stride(),
-                        step_mode: VertexStepMode::Vertex,
-                        attributes: &instance.vertex_attributes(),
-                    }],
+                    buffers: &[Mesh::position_vertex_layout(stride)],
                 },
                 fragment: Some(FragmentState {
                     module: &node.shaders[0],
@@ -123,16 +200,25 @@ impl RenderNode for DepthPrepassNode {
                     bias: Default::default(),
                 }),
                 multisample: Default::default(),
-                multiview: Default::default(),
-                cache: Default::default(),
+                multiview: node.multiview,
+                cache: node.pipeline_cache.as_deref(),
             });
-            node.pipelines.insert(mesh.mesh.mesh, pipeline);
+            self.pipelines.insert(stride, pipeline);
         }
     }
 
-    fn draw(
-        &self,
+    fn resize(
+        &mut self,
         GpuScene { assets, .. }: &mut GpuScene,
+        RenderContext { device, node, .. }: RenderContext,
+        new_size: UVec2,
+    ) {
+        Self::create_texture(assets, device, new_size, node.multiview);
+    }
+
+    fn prepare(
+        &mut self,
+        _scene: &mut GpuScene,
         RenderContext {
             device,
             queue,
@@ -140,6 +226,36 @@ impl RenderNode for DepthPrepassNode {
             ..
         }: RenderContext,
     ) {
+        if let Some(models) = &mut self.models {
+            models.prepare(device, queue, &mut node.meshes);
+        }
+    }
+
+    fn records(&self) -> bool {
+        true
+    }
+
+    fn supports_multiview(&self) -> bool {
+        true
+    }
+
+    fn parallel_safe(&self) -> bool {
+        true
+    }
+
+    fn record(
+        &self,
+        GpuScene {
+            original, assets, ..
+        }: &GpuScene,
+        RenderContext {
+            device,
+            node,
+            material_override,
+            ..
+        }: RenderContext,
+    ) -> Option<CommandBuffer> {
+        let b_model = self.models.as_ref()?.bind_group.as_ref()?;
         let mut command_encoder = device.create_command_encoder(&Default::default());
 
         {
@@ -147,7 +263,7 @@ impl RenderNode for DepthPrepassNode {
                 label: Some("depth_prepass"),
                 color_attachments: &[None],
                 depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
-                    view: &assets.texture_views[&DEPTH_PREPASS_TEXTURE.view],
+                    view: &assets.texture_views[&DEPTH_PREPASS_TEXTURE.attachment(node.multiview)],
                     depth_ops: Some(Operations {
                         load: LoadOp::Clear(1.),
                         store: StoreOp::Store,
@@ -159,13 +275,18 @@ impl RenderNode for DepthPrepassNode {
 
             pass.set_bind_group(0, assets.common_bind_group.as_ref().unwrap(), &[]);
             for mesh in &node.meshes {
-                let (instance, pipeline) = (
-                    &assets.gpu_meshes[&mesh.mesh.mesh],
-                    &node.pipelines[&mesh.mesh.mesh],
-                );
+                // Masked and blended meshes would occlude what's seen through them.
+                if mesh_alpha_mode(original, material_override, &mesh.mesh) != AlphaMode::Opaque {
+                    continue;
+                }
+
+                let instance = &assets.gpu_meshes[&mesh.mesh.mesh];
+                let (positions, stride) =
+                    instance.position_vertices(assets.meshes[&mesh.mesh.mesh].vertex_stride());
 
-                pass.set_pipeline(pipeline);
-                pass.set_vertex_buffer(0, instance.vertex_buffer.slice(..));
+                pass.set_pipeline(&self.pipelines[&stride]);
+                pass.set_bind_group(1, b_model, &[mesh.model_offset.unwrap()]);
+                pass.set_vertex_buffer(0, positions);
                 if let Some(indices) = &instance.index_buffer {
                     pass.set_index_buffer(indices.buffer.slice(..), indices.format);
                     pass.draw_indexed(0..indices.count, 0, 0..1);
@@ -175,6 +296,6 @@ impl RenderNode for DepthPrepassNode {
             }
         }
 
-        queue.submit([command_encoder.finish()]);
+        Some(command_encoder.finish())
     }
 }
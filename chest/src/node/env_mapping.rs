@@ -2,26 +2,33 @@ use std::path::PathBuf;
 
 use aurora_core::{
     render::{
-        flow::{RenderContext, RenderNode},
-        resource::{DynamicGpuBuffer, Image},
-        scene::{ExtraBindGroupId, ExtraBufferId, ExtraLayoutId, GpuScene, SamplerId, TextureId},
+        flow::{NodeContext, RenderContext, RenderNode},
+        resource::DynamicGpuBuffer,
+        scene::{
+            ExtraBindGroupId, ExtraBufferId, ExtraLayoutId, GpuAssets, GpuScene, SamplerId,
+            TextureId, TextureViewId,
+        },
+    },
+    util::{
+        create_sampler,
+        cube::{face_view, CUBE_MAP_FACES},
     },
-    util::cube::CUBE_MAP_FACES,
 };
 use encase::ShaderType;
 use glam::{Mat4, Vec3};
-use image::ImageFormat;
 use uuid::Uuid;
 use wgpu::{
     BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor,
     BindGroupLayoutEntry, BindingResource, BindingType, BufferBindingType, BufferUsages,
-    ColorTargetState, ColorWrites, Extent3d, Features, FilterMode, FragmentState,
-    PipelineLayoutDescriptor, RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline,
-    RenderPipelineDescriptor, SamplerBindingType, SamplerDescriptor, ShaderStages,
+    ColorTargetState, ColorWrites, Device, Extent3d, Features, FilterMode, FragmentState,
+    PipelineLayoutDescriptor, Queue, RenderPassColorAttachment, RenderPassDescriptor,
+    RenderPipeline, RenderPipelineDescriptor, SamplerBindingType, SamplerDescriptor, ShaderStages,
     TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType, TextureUsages,
     TextureView, TextureViewDescriptor, TextureViewDimension, VertexState,
 };
 
+use crate::texture::load_hdr_cube_map;
+
 #[derive(ShaderType)]
 pub struct CubeMapFace {
     pub view: Mat4,
@@ -33,12 +40,19 @@ pub struct EnvironmentMappingConfig {
     pub intensity: f32,
 }
 
+#[derive(ShaderType)]
+struct EnvironmentMappingUniform {
+    intensity: f32,
+    max_mip: f32,
+}
+
 impl Default for EnvironmentMappingConfig {
     fn default() -> Self {
         Self { intensity: 1000. }
     }
 }
 
+#[derive(Default)]
 pub struct EnvironmentMappingNodeConfig {
     pub env_map_path: PathBuf,
 }
@@ -60,24 +74,71 @@ impl Default for EnvironmentMapConvolutionConfig {
     }
 }
 
+/// Prefiltering of the specular map for glossy reflections.
+pub struct SpecularPrefilterConfig {
+    /// Mips of the specular map, the last one is prefiltered for full roughness. Clamped to
+    /// the size of the map.
+    pub mip_levels: u32,
+    /// GGX samples per texel, for both the mips and the BRDF LUT.
+    pub sample_count: u32,
+    pub brdf_lut_size: u32,
+}
+
+impl Default for SpecularPrefilterConfig {
+    fn default() -> Self {
+        Self {
+            mip_levels: 5,
+            sample_count: 128,
+            brdf_lut_size: 128,
+        }
+    }
+}
+
+#[derive(ShaderType)]
+struct SpecularPrefilter {
+    roughness: f32,
+    sample_count: u32,
+}
+
 pub struct EnvironmentMappingData {
     pub irradiance_faces: Vec<(TextureView, u32)>,
     pub convolution_pipeline: RenderPipeline,
     pub convolution_bind_group: BindGroup,
+    /// Where the environment map was loaded from.
+    pub env_map_path: PathBuf,
 }
 
-pub struct EnvironmentMapping {
-    pub env_map_texture: TextureId,
-    pub env_map_sampler: SamplerId,
+/// The cube maps loaded by [`EnvironmentMappingNode`], shared with other nodes like
+/// [`SkyboxNode`](super::SkyboxNode) through [`GpuAssets`].
+pub struct EnvironmentMap {
+    /// Prefiltered for increasing roughness along the mips, mip 0 is the loaded map.
+    pub specular: TextureId,
+    pub specular_view: TextureViewId,
+    pub irradiance: TextureId,
+    pub irradiance_view: TextureViewId,
+    /// Scale and bias of F0 for split sum specular lighting.
+    pub brdf_lut: TextureId,
+    pub brdf_lut_view: TextureViewId,
+    pub sampler: SamplerId,
+}
+
+pub const ENVIRONMENT_MAP: EnvironmentMap = EnvironmentMap {
+    specular: TextureId(Uuid::from_u128(78974561230215021548120154)),
+    specular_view: TextureViewId(Uuid::from_u128(3165498403216574980321654)),
+    irradiance: TextureId(Uuid::from_u128(9874102365410236541023657)),
+    irradiance_view: TextureViewId(Uuid::from_u128(6540123984651320654987132)),
+    brdf_lut: TextureId(Uuid::from_u128(1230564897013265498703216)),
+    brdf_lut_view: TextureViewId(Uuid::from_u128(8790213654879023165487902)),
+    sampler: SamplerId(Uuid::from_u128(27313021528494090841905800393)),
+};
 
+pub struct EnvironmentMapping {
     pub env_mapping_layout: ExtraLayoutId,
     pub env_mapping_bind_group: ExtraBindGroupId,
     pub env_map_config: ExtraBufferId,
 }
 
 pub const ENV_MAPPING: EnvironmentMapping = EnvironmentMapping {
-    env_map_texture: TextureId(Uuid::from_u128(78974561230215021548120154)),
-    env_map_sampler: SamplerId(Uuid::from_u128(27313021528494090841905800393)),
     env_mapping_layout: ExtraLayoutId(Uuid::from_u128(12487544531485120554561230)),
     env_mapping_bind_group: ExtraBindGroupId(Uuid::from_u128(798465100154312025145463519945612)),
     env_map_config: ExtraBufferId(Uuid::from_u128(4856410345313210325401521354)),
@@ -85,49 +146,36 @@ pub const ENV_MAPPING: EnvironmentMapping = EnvironmentMapping {
 
 pub const ENVIRONMENT_MAP_PATH_ATTR: &'static str = "ENVIRONMENT_MAP";
 
+/// Loads the environment map once and convolves it for image based lighting.
+///
+/// The cube maps are published under [`ENVIRONMENT_MAP`], nodes drawing the environment
+/// should depend on this node instead of loading the file again, and swap the map by
+/// requesting a file for [`EnvironmentMap::specular`] in [`GpuAssets::requested_files`].
+#[derive(Default)]
 pub struct EnvironmentMappingNode {
     pub node_config: EnvironmentMappingNodeConfig,
     pub config: EnvironmentMappingConfig,
     pub convolution_config: EnvironmentMapConvolutionConfig,
+    pub prefilter_config: SpecularPrefilterConfig,
 
     pub data: Option<EnvironmentMappingData>,
 }
 
-impl RenderNode for EnvironmentMappingNode {
-    fn require_renderer_features(&self, features: &mut Features) {
-        *features = Features::FLOAT32_FILTERABLE
+impl EnvironmentMappingNode {
+    /// Swap the environment map, loaded on the next frame.
+    pub fn set_env_map(&mut self, path: impl Into<PathBuf>) {
+        self.node_config.env_map_path = path.into();
     }
 
-    fn require_shaders(&self) -> Option<&'static [(&'static [&'static str], &'static str)]> {
-        Some(&[
-            (&[], include_str!("../shader/fullscreen.wgsl")),
-            (
-                &[
-                    include_str!("../shader/math.wgsl"),
-                    include_str!("../shader/fullscreen.wgsl"),
-                ],
-                include_str!("../shader/env_mapping/convolve_env_map.wgsl"),
-            ),
-        ])
-    }
-
-    fn build(
-        &mut self,
-        GpuScene { assets, .. }: &mut GpuScene,
-        RenderContext {
+    fn load(&mut self, assets: &mut GpuAssets, device: &Device, queue: &Queue, node: &NodeContext) {
+        let specular_texture = load_hdr_cube_map(
             device,
             queue,
-            node,
-            ..
-        }: RenderContext,
-    ) {
-        let specular_texture = Image::from_buffer(
-            &std::fs::read(&self.node_config.env_map_path).unwrap(),
-            ImageFormat::Hdr,
-            false,
-        )
-        .to_cube_map(device, queue, &Default::default());
+            &self.node_config.env_map_path,
+            self.prefilter_config.mip_levels,
+        );
         let cube_face_size = specular_texture.width();
+        let mip_level_count = specular_texture.mip_level_count();
 
         let irradiance_texture = device.create_texture(&TextureDescriptor {
             label: Some("irradiance_texture"),
@@ -156,14 +204,43 @@ impl RenderNode for EnvironmentMappingNode {
             ..Default::default()
         });
 
-        let env_map_sampler = device.create_sampler(&SamplerDescriptor {
-            label: Some("environment_map_sampler"),
-            mag_filter: FilterMode::Linear,
-            min_filter: FilterMode::Linear,
-            mipmap_filter: FilterMode::Linear,
+        // The loaded map, the other mips are rendered from it.
+        let source_texture_view = specular_texture.create_view(&TextureViewDescriptor {
+            label: Some("specular_texture_source_view"),
+            dimension: Some(TextureViewDimension::Cube),
+            mip_level_count: Some(1),
             ..Default::default()
         });
 
+        let brdf_lut_size = self.prefilter_config.brdf_lut_size;
+        let brdf_lut = device.create_texture(&TextureDescriptor {
+            label: Some("brdf_lut"),
+            size: Extent3d {
+                width: brdf_lut_size,
+                height: brdf_lut_size,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::Rg16Float,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        let brdf_lut_view = brdf_lut.create_view(&Default::default());
+
+        let env_map_sampler = create_sampler(
+            device,
+            &SamplerDescriptor {
+                label: Some("environment_map_sampler"),
+                mag_filter: FilterMode::Linear,
+                min_filter: FilterMode::Linear,
+                mipmap_filter: FilterMode::Linear,
+                ..Default::default()
+            },
+            assets.max_anisotropy,
+        );
+
         let env_mapping_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
             label: Some("env_map_convolution_layout"),
             entries: &[
@@ -203,7 +280,18 @@ impl RenderNode for EnvironmentMappingNode {
                     ty: BindingType::Buffer {
                         ty: BufferBindingType::Uniform,
                         has_dynamic_offset: false,
-                        min_binding_size: Some(EnvironmentMappingConfig::min_size()),
+                        min_binding_size: Some(EnvironmentMappingUniform::min_size()),
+                    },
+                    count: None,
+                },
+                // BRDF LUT
+                BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
                     },
                     count: None,
                 },
@@ -211,8 +299,11 @@ impl RenderNode for EnvironmentMappingNode {
         });
 
         let mut bf_config = DynamicGpuBuffer::new(BufferUsages::UNIFORM);
-        bf_config.push(&self.config);
-        bf_config.write::<EnvironmentMappingConfig>(device, queue);
+        bf_config.push(&EnvironmentMappingUniform {
+            intensity: self.config.intensity,
+            max_mip: (mip_level_count - 1) as f32,
+        });
+        bf_config.write::<EnvironmentMappingUniform>(device, queue);
 
         let env_mapping_bind_group = device.create_bind_group(&BindGroupDescriptor {
             label: Some("env_mapping_bind_group"),
@@ -234,6 +325,10 @@ impl RenderNode for EnvironmentMappingNode {
                     binding: 3,
                     resource: bf_config.entire_binding().unwrap(),
                 },
+                BindGroupEntry {
+                    binding: 4,
+                    resource: BindingResource::TextureView(&brdf_lut_view),
+                },
             ],
         });
 
@@ -255,13 +350,7 @@ impl RenderNode for EnvironmentMappingNode {
         let mut irradiance_faces = Vec::with_capacity(6);
 
         for (index, face) in CUBE_MAP_FACES.into_iter().enumerate() {
-            let view = irradiance_texture.create_view(&TextureViewDescriptor {
-                label: Some("irradiance_texture_sliced_view"),
-                dimension: Some(TextureViewDimension::D2),
-                base_array_layer: index as u32,
-                array_layer_count: Some(1),
-                ..Default::default()
-            });
+            let view = face_view(&irradiance_texture, index as u32, 0);
             let offset = bf_sample_faces.push(&CubeMapFace {
                 view: Mat4::look_to_rh(Vec3::ZERO, face.target, face.up).inverse(),
                 up: face.up,
@@ -325,7 +414,7 @@ impl RenderNode for EnvironmentMappingNode {
                 // Src
                 BindGroupEntry {
                     binding: 0,
-                    resource: BindingResource::TextureView(&specular_texture_view),
+                    resource: BindingResource::TextureView(&source_texture_view),
                 },
                 // Sampler
                 BindGroupEntry {
@@ -375,21 +464,268 @@ impl RenderNode for EnvironmentMappingNode {
             depth_stencil: Default::default(),
             multisample: Default::default(),
             multiview: Default::default(),
-            cache: Default::default(),
+            cache: node.pipeline_cache.as_deref(),
+        });
+
+        let mut bf_prefilter = DynamicGpuBuffer::new(BufferUsages::UNIFORM);
+        let mip_offsets = (0..mip_level_count)
+            .map(|mip| {
+                let perceptual_roughness = mip as f32 / (mip_level_count - 1).max(1) as f32;
+                bf_prefilter.push(&SpecularPrefilter {
+                    roughness: perceptual_roughness * perceptual_roughness,
+                    sample_count: self.prefilter_config.sample_count,
+                })
+            })
+            .collect::<Vec<_>>();
+        bf_prefilter.write::<SpecularPrefilter>(device, queue);
+
+        let prefilter_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("specular_prefilter_layout"),
+            entries: &[
+                // Src
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::Cube,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                // Sampler
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+                // Roughness of the mip
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: Some(SpecularPrefilter::min_size()),
+                    },
+                    count: None,
+                },
+                // Sample face
+                BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: Some(CubeMapFace::min_size()),
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let prefilter_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("specular_prefilter_bind_group"),
+            layout: &prefilter_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&source_texture_view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(&env_map_sampler),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: bf_prefilter.binding::<SpecularPrefilter>().unwrap(),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: bf_sample_faces.binding::<CubeMapFace>().unwrap(),
+                },
+            ],
         });
 
+        let prefilter_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("specular_prefilter_pipeline_layout"),
+            bind_group_layouts: &[&prefilter_layout],
+            ..Default::default()
+        });
+
+        let create_prefilter_pipeline = |entry_point, format| {
+            device.create_render_pipeline(&RenderPipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&prefilter_pipeline_layout),
+                vertex: VertexState {
+                    module: &node.shaders[0],
+                    entry_point: "vertex",
+                    compilation_options: Default::default(),
+                    buffers: &[],
+                },
+                fragment: Some(FragmentState {
+                    module: &node.shaders[2],
+                    entry_point,
+                    compilation_options: Default::default(),
+                    targets: &[Some(ColorTargetState {
+                        format,
+                        blend: None,
+                        write_mask: ColorWrites::all(),
+                    })],
+                }),
+                primitive: Default::default(),
+                depth_stencil: Default::default(),
+                multisample: Default::default(),
+                multiview: Default::default(),
+                cache: node.pipeline_cache.as_deref(),
+            })
+        };
+        let prefilter_pipeline =
+            create_prefilter_pipeline("prefilter_fragment", specular_texture.format());
+        let brdf_lut_pipeline =
+            create_prefilter_pipeline("brdf_lut_fragment", TextureFormat::Rg16Float);
+
+        // Both only depend on the loaded map, so they are rendered once here.
+        let mut command_encoder = device.create_command_encoder(&Default::default());
+
+        for (mip, mip_offset) in mip_offsets.iter().enumerate().skip(1) {
+            for (face, (_, face_offset)) in irradiance_faces.iter().enumerate() {
+                let view = face_view(&specular_texture, face as u32, mip as u32);
+
+                let mut pass = command_encoder.begin_render_pass(&RenderPassDescriptor {
+                    label: Some("specular_prefilter_pass"),
+                    color_attachments: &[Some(RenderPassColorAttachment {
+                        view: &view,
+                        resolve_target: None,
+                        ops: Default::default(),
+                    })],
+                    ..Default::default()
+                });
+
+                pass.set_pipeline(&prefilter_pipeline);
+                pass.set_bind_group(0, &prefilter_bind_group, &[*mip_offset, *face_offset]);
+                pass.draw(0..3, 0..1);
+            }
+        }
+
+        {
+            let mut pass = command_encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("brdf_lut_pass"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: &brdf_lut_view,
+                    resolve_target: None,
+                    ops: Default::default(),
+                })],
+                ..Default::default()
+            });
+
+            pass.set_pipeline(&brdf_lut_pipeline);
+            pass.set_bind_group(
+                0,
+                &prefilter_bind_group,
+                &[mip_offsets[0], irradiance_faces[0].1],
+            );
+            pass.draw(0..3, 0..1);
+        }
+
+        queue.submit([command_encoder.finish()]);
+
+        assets
+            .textures
+            .insert(ENVIRONMENT_MAP.specular, specular_texture);
+        assets
+            .texture_views
+            .insert(ENVIRONMENT_MAP.specular_view, specular_texture_view);
+        assets
+            .textures
+            .insert(ENVIRONMENT_MAP.irradiance, irradiance_texture);
+        assets
+            .texture_views
+            .insert(ENVIRONMENT_MAP.irradiance_view, irradiance_texture_view);
+        assets.textures.insert(ENVIRONMENT_MAP.brdf_lut, brdf_lut);
+        assets
+            .texture_views
+            .insert(ENVIRONMENT_MAP.brdf_lut_view, brdf_lut_view);
+        assets
+            .samplers
+            .insert(ENVIRONMENT_MAP.sampler, env_map_sampler);
+
         self.data = Some(EnvironmentMappingData {
             irradiance_faces,
             convolution_bind_group,
             convolution_pipeline,
+            env_map_path: self.node_config.env_map_path.clone(),
         });
     }
+}
+
+impl RenderNode for EnvironmentMappingNode {
+    fn require_renderer_features(&self, features: &mut Features) {
+        *features |= Features::FLOAT32_FILTERABLE;
+    }
+
+    fn require_shaders(&self) -> Option<&'static [(&'static [&'static str], &'static str)]> {
+        Some(&[
+            (&[], include_str!("../shader/fullscreen.wgsl")),
+            (
+                &[
+                    include_str!("../shader/math.wgsl"),
+                    include_str!("../shader/fullscreen.wgsl"),
+                ],
+                include_str!("../shader/env_mapping/convolve_env_map.wgsl"),
+            ),
+            (
+                &[
+                    include_str!("../shader/math.wgsl"),
+                    include_str!("../shader/fullscreen.wgsl"),
+                ],
+                include_str!("../shader/env_mapping/prefilter_env_map.wgsl"),
+            ),
+        ])
+    }
+
+    fn build(
+        &mut self,
+        GpuScene { assets, .. }: &mut GpuScene,
+        RenderContext {
+            device,
+            queue,
+            node,
+            ..
+        }: RenderContext,
+    ) {
+        self.load(assets, device, queue, node);
+    }
+
+    fn prepare(
+        &mut self,
+        GpuScene { assets, .. }: &mut GpuScene,
+        RenderContext {
+            device,
+            queue,
+            node,
+            ..
+        }: RenderContext,
+    ) {
+        if let Some(path) = assets.requested_files.remove(&ENVIRONMENT_MAP.specular) {
+            self.set_env_map(path);
+        }
+        if self
+            .data
+            .as_ref()
+            .is_some_and(|data| data.env_map_path != self.node_config.env_map_path)
+        {
+            self.load(assets, device, queue, node);
+        }
+    }
 
     fn draw(&self, _scene: &mut GpuScene, RenderContext { device, queue, .. }: RenderContext) {
         let Some(EnvironmentMappingData {
             irradiance_faces,
             convolution_pipeline,
             convolution_bind_group,
+            ..
         }) = &self.data
         else {
             return;
@@ -416,3 +752,166 @@ impl RenderNode for EnvironmentMappingNode {
         queue.submit([command_encoder.finish()]);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{f32::consts::PI, fs::File, path::Path, sync::Arc};
+
+    use aurora_core::{
+        render::{
+            flow::{GeneralNode, ImageFallbackNode, RenderFlow},
+            helper::{CameraProjection, OrthographicProjection},
+            mesh::{Mesh, MeshIndices, MeshVertexAttributeData, StaticMesh, DEFAULT_RENDER_LAYER},
+            scene::{GpuScene, MaterialInstanceId, MeshInstanceId},
+        },
+        util::testing::{self, TestTargets},
+        WgpuRenderer,
+    };
+    use glam::{UVec2, Vec2, Vec3};
+    use image::{codecs::hdr::HdrEncoder, Rgb, RgbaImage};
+    use uuid::Uuid;
+    use wgpu::{Features, TextureFormat};
+
+    use super::{EnvironmentMappingNode, EnvironmentMappingNodeConfig};
+    use crate::{
+        material::PbrMaterial,
+        node::{DepthPrepassNode, PbrNode, PbrNodeConfig},
+    };
+
+    const SIZE: u32 = 64;
+
+    fn sphere(rings: u32, segments: u32) -> Mesh {
+        let mut positions = Vec::new();
+        let mut uvs = Vec::new();
+        for ring in 0..=rings {
+            for segment in 0..=segments {
+                let uv = Vec2::new(segment as f32 / segments as f32, ring as f32 / rings as f32);
+                let (theta, phi) = (uv.y * PI, uv.x * 2. * PI);
+                positions.push(Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                ));
+                uvs.push(uv);
+            }
+        }
+
+        let mut indices = Vec::new();
+        for ring in 0..rings {
+            for segment in 0..segments {
+                let i = ring * (segments + 1) + segment;
+                let below = i + segments + 1;
+                indices.extend([i, below + 1, below, i, i + 1, below + 1]);
+            }
+        }
+
+        let mut mesh = Mesh::new()
+            .with_attribute(
+                Mesh::POSITION_ATTR,
+                MeshVertexAttributeData::Float32x3(
+                    positions.iter().map(|p| *p * 0.9 - Vec3::Z * 3.).collect(),
+                ),
+            )
+            .with_attribute(
+                Mesh::NORMAL_ATTR,
+                MeshVertexAttributeData::Float32x3(positions),
+            )
+            .with_attribute(
+                Mesh::TEX_COORDS_ATTR,
+                MeshVertexAttributeData::Float32x2(uvs),
+            )
+            .with_indices(MeshIndices::UInt32(indices));
+        mesh.recalculate_tangent().unwrap();
+        mesh
+    }
+
+    /// Vertical stripes one texel wide on every face.
+    fn striped_cube_map(path: &Path) {
+        let (width, height) = (16 * 4, 16 * 3);
+        let pixels = (0..width * height)
+            .map(|i| Rgb([(i % 2) as f32 * 0.8; 3]))
+            .collect::<Vec<_>>();
+        HdrEncoder::new(File::create(path).unwrap())
+            .encode(&pixels, width, height)
+            .unwrap();
+    }
+
+    fn render(renderer: &WgpuRenderer, env_map: &Path, roughness: f32) -> RgbaImage {
+        let mut scene = GpuScene::default();
+        let mesh_id = MeshInstanceId(Uuid::from_u128(1));
+        let material_id = MaterialInstanceId(Uuid::from_u128(2));
+        scene.assets.meshes.insert(mesh_id, sphere(16, 32));
+        scene.original.materials.insert(
+            material_id,
+            Arc::new(PbrMaterial {
+                roughness,
+                metallic: 1.,
+                ..Default::default()
+            }),
+        );
+        scene.static_meshes.push(StaticMesh {
+            mesh: mesh_id,
+            material: material_id,
+            render_layer: DEFAULT_RENDER_LAYER,
+            transform: Default::default(),
+        });
+        scene.original.camera.projection =
+            CameraProjection::Orthographic(OrthographicProjection::symmetric(2., 2., 0.1, 10.));
+
+        let size = UVec2::splat(SIZE);
+        let test_targets = TestTargets::new(&renderer.device, size, TextureFormat::Rgba8Unorm)
+            .with_depth(&renderer.device);
+        let targets = test_targets.targets();
+
+        let mut flow = RenderFlow::default();
+        flow.add::<GeneralNode>()
+            .add::<ImageFallbackNode>()
+            .add::<DepthPrepassNode>()
+            .add_initialized(EnvironmentMappingNode {
+                node_config: EnvironmentMappingNodeConfig {
+                    env_map_path: env_map.into(),
+                },
+                ..Default::default()
+            })
+            .add_initialized(PbrNode {
+                node_cfg: PbrNodeConfig::ENVIRONMENT_MAPPING,
+                ..Default::default()
+            });
+        flow.set_queue(scene.static_meshes.clone());
+        flow.build(renderer, &mut scene, None, &targets).unwrap();
+        flow.run(renderer, &mut scene, &targets);
+
+        test_targets.read(&renderer)
+    }
+
+    /// Mean difference between horizontal neighbours in the middle of the sphere.
+    fn detail(image: &RgbaImage) -> f32 {
+        let mut sum = 0;
+        for y in SIZE / 4..SIZE * 3 / 4 {
+            for x in SIZE / 4..SIZE * 3 / 4 {
+                sum += image.get_pixel(x, y)[0].abs_diff(image.get_pixel(x + 1, y)[0]) as u32;
+            }
+        }
+        sum as f32 / (SIZE * SIZE / 4) as f32
+    }
+
+    #[test]
+    fn roughness_blurs_reflections() {
+        let features = Some(Features::FLOAT32_FILTERABLE);
+        let Some(renderer) = testing::renderer(features, None) else {
+            return;
+        };
+
+        let env_map = std::env::temp_dir().join("aurora_env_map_stripes.hdr");
+        striped_cube_map(&env_map);
+
+        let smooth = render(&renderer, &env_map, 0.);
+        let rough = render(&renderer, &env_map, 1.);
+
+        // Both are lit by the environment.
+        assert!(smooth.get_pixel(SIZE / 2, SIZE / 2)[0] > 0);
+        assert!(rough.get_pixel(SIZE / 2, SIZE / 2)[0] > 0);
+        // The stripes only survive on the mirror.
+        assert!(detail(&smooth) > detail(&rough) * 4.);
+    }
+}
@@ -0,0 +1,245 @@
+use std::collections::HashMap;
+
+use aurora_core::{
+    render::{
+        flow::{RenderContext, RenderNode},
+        scene::GpuScene,
+    },
+    util::create_sampler,
+};
+use naga_oil::compose::ShaderDefValue;
+use wgpu::{
+    AddressMode, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, ColorTargetState, ColorWrites, FilterMode,
+    FragmentState, PipelineLayoutDescriptor, RenderPassColorAttachment, RenderPassDescriptor,
+    RenderPipeline, RenderPipelineDescriptor, Sampler, SamplerBindingType, SamplerDescriptor,
+    ShaderStages, TextureSampleType, TextureViewDimension, VertexState,
+};
+
+#[derive(Default, Clone, Copy)]
+pub enum FxaaQuality {
+    Low,
+    #[default]
+    Medium,
+    High,
+}
+
+impl FxaaQuality {
+    /// Reciprocal of the relative contrast threshold to detect an edge.
+    pub fn edge_threshold(self) -> u32 {
+        match self {
+            FxaaQuality::Low => 4,
+            FxaaQuality::Medium => 8,
+            FxaaQuality::High => 16,
+        }
+    }
+
+    /// Max steps to search for the end of an edge.
+    pub fn search_steps(self) -> u32 {
+        match self {
+            FxaaQuality::Low => 4,
+            FxaaQuality::Medium => 8,
+            FxaaQuality::High => 12,
+        }
+    }
+}
+
+pub struct FxaaNodeData {
+    pub pipeline: RenderPipeline,
+    pub layout: BindGroupLayout,
+    pub sampler: Sampler,
+}
+
+pub struct FxaaNode {
+    pub quality: FxaaQuality,
+    /// Whether the input is already tonemapped.
+    /// HDR input is compressed before calculating luma, so edge thresholds still make sense.
+    pub tonemapped: bool,
+
+    pub data: Option<FxaaNodeData>,
+}
+
+impl Default for FxaaNode {
+    fn default() -> Self {
+        Self {
+            quality: Default::default(),
+            tonemapped: true,
+            data: None,
+        }
+    }
+}
+
+impl RenderNode for FxaaNode {
+    fn require_shader_defs(&self, shader_defs: &mut HashMap<String, ShaderDefValue>) {
+        shader_defs.extend([
+            (
+                "EDGE_THRESHOLD".to_string(),
+                ShaderDefValue::UInt(self.quality.edge_threshold()),
+            ),
+            (
+                "SEARCH_STEPS".to_string(),
+                ShaderDefValue::UInt(self.quality.search_steps()),
+            ),
+        ]);
+
+        if !self.tonemapped {
+            shader_defs.insert("FXAA_HDR_INPUT".to_string(), Default::default());
+        }
+    }
+
+    fn require_shaders(&self) -> Option<&'static [(&'static [&'static str], &'static str)]> {
+        Some(&[
+            (&[], include_str!("../shader/fullscreen.wgsl")),
+            (
+                &[
+                    include_str!("../shader/fullscreen.wgsl"),
+                    include_str!("../shader/math.wgsl"),
+                ],
+                include_str!("../shader/post_processing/fxaa.wgsl"),
+            ),
+        ])
+    }
+
+    fn build(
+        &mut self,
+        GpuScene { assets, .. }: &mut GpuScene,
+        RenderContext {
+            device,
+            node,
+            targets,
+            ..
+        }: RenderContext,
+    ) {
+        let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("fxaa_layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("fxaa_pipeline_layout"),
+            bind_group_layouts: &[&layout],
+            ..Default::default()
+        });
+
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("fxaa_pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &node.shaders[0],
+                entry_point: "vertex",
+                compilation_options: Default::default(),
+                buffers: &[],
+            },
+            fragment: Some(FragmentState {
+                module: &node.shaders[1],
+                entry_point: "fragment",
+                compilation_options: Default::default(),
+                targets: &[Some(ColorTargetState {
+                    format: targets.color_format,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: Default::default(),
+            depth_stencil: Default::default(),
+            multisample: Default::default(),
+            multiview: Default::default(),
+            cache: node.pipeline_cache.as_deref(),
+        });
+
+        let sampler = create_sampler(
+            device,
+            &SamplerDescriptor {
+                label: Some("fxaa_sampler"),
+                address_mode_u: AddressMode::ClampToEdge,
+                address_mode_v: AddressMode::ClampToEdge,
+                mag_filter: FilterMode::Linear,
+                min_filter: FilterMode::Linear,
+                ..Default::default()
+            },
+            assets.max_anisotropy,
+        );
+
+        self.data = Some(FxaaNodeData {
+            pipeline,
+            layout,
+            sampler,
+        });
+    }
+
+    fn draw(
+        &self,
+        GpuScene { assets, .. }: &mut GpuScene,
+        RenderContext {
+            device,
+            queue,
+            targets,
+            ..
+        }: RenderContext,
+    ) {
+        let Some(FxaaNodeData {
+            pipeline,
+            layout,
+            sampler,
+        }) = &self.data
+        else {
+            return;
+        };
+
+        let post_process = targets.swap_chain.start_post_process();
+        let bind_group = assets.bind_group_cache.get_or_create(
+            device,
+            &BindGroupDescriptor {
+                label: Some("fxaa_bind_group"),
+                layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::TextureView(post_process.src),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::Sampler(sampler),
+                    },
+                ],
+            },
+        );
+
+        let mut command_encoder = device.create_command_encoder(&Default::default());
+
+        {
+            let mut pass = command_encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("fxaa_pass"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: post_process.dst,
+                    resolve_target: None,
+                    ops: Default::default(),
+                })],
+                ..Default::default()
+            });
+
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.draw(0..3, 0..1);
+        }
+
+        queue.submit([command_encoder.finish()]);
+    }
+}
@@ -1,12 +1,17 @@
 use std::collections::HashMap;
 
-use aurora_core::render::{
-    flow::{RenderContext, RenderNode},
-    resource::{DynamicGpuBuffer, Image, ImageTextureDescriptor},
-    scene::GpuScene,
+use aurora_core::{
+    render::{
+        flow::{DependencyNodeIndex, RenderContext, RenderNode},
+        resource::{DynamicGpuBuffer, Image, ImageTextureDescriptor, DUMMY_2D_TEX},
+        scene::{GpuScene, TextureId},
+    },
+    util::{create_sampler, ext::RgbToVec3},
 };
 use encase::ShaderType;
+use glam::{UVec2, Vec3};
 use naga_oil::compose::ShaderDefValue;
+use palette::Srgb;
 use wgpu::{
     AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
     BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, BlendComponent,
@@ -18,6 +23,8 @@ use wgpu::{
     TextureView, TextureViewDescriptor, TextureViewDimension, VertexState,
 };
 
+use crate::node::{DepthPrepassNode, DEPTH_PREPASS_TEXTURE};
+
 pub const LENS_FLARE_TEXTURE_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
 
 #[derive(ShaderType)]
@@ -28,6 +35,8 @@ pub struct LensFlareConfig {
     pub upper_threshold: f32,
     pub ca_strength: f32,
     pub halo_radius: f32,
+    /// Radius in texels around the light's screen position used for occlusion test.
+    pub occlusion_radius: f32,
 }
 
 impl Default for LensFlareConfig {
@@ -39,6 +48,41 @@ impl Default for LensFlareConfig {
             upper_threshold: 1.5,
             ca_strength: 20.0,
             halo_radius: 0.4,
+            occlusion_radius: 4.0,
+        }
+    }
+}
+
+/// An authored flare element, placed on the axis from the light to the screen center.
+pub struct FlareGhost {
+    /// Position on the axis, 1 is on the light, 0 is the screen center, negative values
+    /// place the ghost on the opposite side.
+    pub position_scale: f32,
+    /// Radius relative to the screen height.
+    pub size: f32,
+    pub tint: Srgb,
+    /// Sprite of this ghost. A soft disk is used if `None`.
+    ///
+    /// Only its color is added, so sprites with straight alpha should be loaded with
+    /// `premultiply_alpha`, or their transparent texels still show.
+    pub texture: Option<TextureId>,
+}
+
+#[derive(ShaderType)]
+pub struct FlareGhostUniform {
+    pub position_scale: f32,
+    pub size: f32,
+    pub tint: Vec3,
+    pub textured: u32,
+}
+
+impl From<&FlareGhost> for FlareGhostUniform {
+    fn from(value: &FlareGhost) -> Self {
+        Self {
+            position_scale: value.position_scale,
+            size: value.size,
+            tint: value.tint.into_linear().to_vec3(),
+            textured: value.texture.is_some() as u32,
         }
     }
 }
@@ -48,6 +92,11 @@ pub struct LensFlareNodeConfig {
     pub chromatic_aberration: bool,
     pub halo: bool,
     pub startburst: bool,
+    /// Fade the flare out when lights are occluded by scene geometry.
+    /// Requires depth prepass.
+    pub occlusion: bool,
+    /// Authored ghosts, layered over the procedural ones.
+    pub ghosts: Vec<FlareGhost>,
 }
 
 impl Default for LensFlareNodeConfig {
@@ -57,6 +106,8 @@ impl Default for LensFlareNodeConfig {
             chromatic_aberration: true,
             halo: true,
             startburst: true,
+            occlusion: true,
+            ghosts: Vec::new(),
         }
     }
 }
@@ -68,6 +119,8 @@ pub struct LensFlareNodeData {
 
     pub blit_layout: BindGroupLayout,
     pub effect_bind_group: BindGroup,
+    pub ghost_pipeline: Option<RenderPipeline>,
+    pub ghost_bind_groups: Vec<(BindGroup, u32)>,
     pub downsample_output: TextureView,
     pub effect_output: TextureView,
     pub sampler: Sampler,
@@ -82,6 +135,17 @@ pub struct LensFlareNode {
 }
 
 impl RenderNode for LensFlareNode {
+    fn add_node_dependencies(&self) -> Vec<(DependencyNodeIndex, Box<dyn RenderNode>)> {
+        if self.node_config.occlusion {
+            vec![(
+                DependencyNodeIndex::Before,
+                Box::new(DepthPrepassNode::default()),
+            )]
+        } else {
+            Vec::new()
+        }
+    }
+
     fn require_shader_defs(&self, shader_defs: &mut HashMap<String, ShaderDefValue>) {
         if self.node_config.chromatic_aberration {
             shader_defs.insert("CHROMATIC_ABERRATION".to_string(), Default::default());
@@ -94,6 +158,10 @@ impl RenderNode for LensFlareNode {
         if self.node_config.startburst {
             shader_defs.insert("STAR_BURST".to_string(), Default::default());
         }
+
+        if self.node_config.occlusion {
+            shader_defs.insert("LENS_FLARE_OCCLUSION".to_string(), Default::default());
+        }
     }
 
     fn require_shaders(&self) -> Option<&'static [(&'static [&'static str], &'static str)]> {
@@ -104,6 +172,7 @@ impl RenderNode for LensFlareNode {
                     include_str!("../shader/fullscreen.wgsl"),
                     include_str!("../shader/math.wgsl"),
                     include_str!("../shader/hash.wgsl"),
+                    include_str!("../shader/common/common_type.wgsl"),
                 ],
                 include_str!("../shader/post_processing/lens_flare.wgsl"),
             ),
@@ -116,12 +185,13 @@ impl RenderNode for LensFlareNode {
 
     fn build(
         &mut self,
-        _scene: &mut GpuScene,
+        GpuScene { assets, .. }: &mut GpuScene,
         RenderContext {
             device,
             queue,
             node,
             targets,
+            ..
         }: RenderContext,
     ) {
         let blit_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
@@ -175,7 +245,7 @@ impl RenderNode for LensFlareNode {
             depth_stencil: Default::default(),
             multisample: Default::default(),
             multiview: Default::default(),
-            cache: Default::default(),
+            cache: node.pipeline_cache.as_deref(),
         });
 
         let upsample_pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
@@ -212,54 +282,101 @@ impl RenderNode for LensFlareNode {
             depth_stencil: Default::default(),
             multisample: Default::default(),
             multiview: Default::default(),
-            cache: Default::default(),
+            cache: node.pipeline_cache.as_deref(),
         });
 
-        let effect_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-            label: Some("lens_flare_layout"),
-            entries: &[
-                BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: ShaderStages::FRAGMENT,
-                    ty: BindingType::Texture {
-                        sample_type: TextureSampleType::Float { filterable: true },
-                        view_dimension: TextureViewDimension::D2,
-                        multisampled: false,
-                    },
-                    count: None,
+        let mut effect_layout_entries = vec![
+            BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: true },
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
                 },
-                BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: ShaderStages::FRAGMENT,
-                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
-                    count: None,
+                count: None,
+            },
+            BindGroupLayoutEntry {
+                binding: 1,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                count: None,
+            },
+            BindGroupLayoutEntry {
+                binding: 2,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: Some(LensFlareConfig::min_size()),
                 },
-                BindGroupLayoutEntry {
-                    binding: 2,
-                    visibility: ShaderStages::FRAGMENT,
-                    ty: BindingType::Buffer {
-                        ty: BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: Some(LensFlareConfig::min_size()),
-                    },
-                    count: None,
+                count: None,
+            },
+            BindGroupLayoutEntry {
+                binding: 3,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: true },
+                    view_dimension: TextureViewDimension::D1,
+                    multisampled: false,
                 },
-                BindGroupLayoutEntry {
-                    binding: 3,
-                    visibility: ShaderStages::FRAGMENT,
-                    ty: BindingType::Texture {
-                        sample_type: TextureSampleType::Float { filterable: true },
-                        view_dimension: TextureViewDimension::D1,
-                        multisampled: false,
-                    },
-                    count: None,
+                count: None,
+            },
+        ];
+
+        if self.node_config.occlusion {
+            effect_layout_entries.push(BindGroupLayoutEntry {
+                binding: 4,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Depth,
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
                 },
-            ],
+                count: None,
+            });
+        }
+
+        let mut ghost_layout_entries = effect_layout_entries.clone();
+        ghost_layout_entries.extend([
+            BindGroupLayoutEntry {
+                binding: 5,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: Some(FlareGhostUniform::min_size()),
+                },
+                count: None,
+            },
+            BindGroupLayoutEntry {
+                binding: 6,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Texture {
+                    sample_type: TextureSampleType::Float { filterable: true },
+                    view_dimension: TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+        ]);
+
+        let effect_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("lens_flare_layout"),
+            entries: &effect_layout_entries,
         });
 
+        let mut effect_bind_group_layouts = vec![&effect_layout];
+        if self.node_config.occlusion {
+            effect_bind_group_layouts.extend([
+                assets.common_layout.as_ref().unwrap(),
+                assets.lights_layout.as_ref().unwrap(),
+            ]);
+        }
+
         let effect_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
             label: Some("lens_flare_effect_pipeline_layout"),
-            bind_group_layouts: &[&effect_layout],
+            bind_group_layouts: &effect_bind_group_layouts,
             ..Default::default()
         });
 
@@ -286,7 +403,7 @@ impl RenderNode for LensFlareNode {
             depth_stencil: Default::default(),
             multisample: Default::default(),
             multiview: Default::default(),
-            cache: Default::default(),
+            cache: node.pipeline_cache.as_deref(),
         });
 
         let desc = TextureDescriptor {
@@ -316,22 +433,32 @@ impl RenderNode for LensFlareNode {
             ..Default::default()
         });
 
-        let sampler = device.create_sampler(&SamplerDescriptor {
-            label: Some("lens_flare_sampler"),
-            mag_filter: FilterMode::Linear,
-            min_filter: FilterMode::Linear,
-            mipmap_filter: FilterMode::Linear,
-            address_mode_u: AddressMode::ClampToEdge,
-            address_mode_v: AddressMode::ClampToEdge,
-            address_mode_w: AddressMode::ClampToEdge,
-            ..Default::default()
-        });
+        let sampler = create_sampler(
+            device,
+            &SamplerDescriptor {
+                label: Some("lens_flare_sampler"),
+                mag_filter: FilterMode::Linear,
+                min_filter: FilterMode::Linear,
+                mipmap_filter: FilterMode::Linear,
+                address_mode_u: AddressMode::ClampToEdge,
+                address_mode_v: AddressMode::ClampToEdge,
+                address_mode_w: AddressMode::ClampToEdge,
+                ..Default::default()
+            },
+            assets.max_anisotropy,
+        );
 
         let mut config = DynamicGpuBuffer::new(BufferUsages::UNIFORM);
         config.push(&self.config);
         config.write::<LensFlareConfig>(device, queue);
 
-        let starburst_image = Image::from_path("chest/assets/starburst.png", None, true).unwrap();
+        let starburst_image = Image::from_path(
+            concat!(env!("CARGO_MANIFEST_DIR"), "/assets/starburst.png"),
+            None,
+            true,
+            Some(device.limits().max_texture_dimension_1d),
+        )
+        .unwrap();
         let starburst_texture = starburst_image.to_texture(
             device,
             queue,
@@ -342,44 +469,155 @@ impl RenderNode for LensFlareNode {
         );
         let starburst_view = starburst_texture.create_view(&Default::default());
 
+        let mut effect_entries = vec![
+            BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(&downsample_output),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: BindingResource::Sampler(&sampler),
+            },
+            BindGroupEntry {
+                binding: 2,
+                resource: config.entire_binding().unwrap(),
+            },
+            BindGroupEntry {
+                binding: 3,
+                resource: BindingResource::TextureView(&starburst_view),
+            },
+        ];
+
+        if self.node_config.occlusion {
+            effect_entries.push(BindGroupEntry {
+                binding: 4,
+                resource: BindingResource::TextureView(
+                    &assets.texture_views[&DEPTH_PREPASS_TEXTURE.view],
+                ),
+            });
+        }
+
         let effect_bind_group = device.create_bind_group(&BindGroupDescriptor {
             label: Some("lens_flare_effect_bind_group"),
             layout: &effect_layout,
-            entries: &[
-                BindGroupEntry {
-                    binding: 0,
-                    resource: BindingResource::TextureView(&downsample_output),
-                },
-                BindGroupEntry {
-                    binding: 1,
-                    resource: BindingResource::Sampler(&sampler),
-                },
-                BindGroupEntry {
-                    binding: 2,
-                    resource: config.entire_binding().unwrap(),
-                },
-                BindGroupEntry {
-                    binding: 3,
-                    resource: BindingResource::TextureView(&starburst_view),
-                },
-            ],
+            entries: &effect_entries,
         });
 
+        let mut ghost_pipeline = None;
+        let mut ghost_bind_groups = Vec::with_capacity(self.node_config.ghosts.len());
+
+        if !self.node_config.ghosts.is_empty() {
+            let ghost_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("lens_flare_ghost_layout"),
+                entries: &ghost_layout_entries,
+            });
+
+            let ghost_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+                label: Some("lens_flare_ghost_pipeline_layout"),
+                bind_group_layouts: &[
+                    &ghost_layout,
+                    assets.common_layout.as_ref().unwrap(),
+                    assets.lights_layout.as_ref().unwrap(),
+                ],
+                ..Default::default()
+            });
+
+            ghost_pipeline = Some(device.create_render_pipeline(&RenderPipelineDescriptor {
+                label: Some("lens_flare_ghost_pipeline"),
+                layout: Some(&ghost_pipeline_layout),
+                vertex: VertexState {
+                    module: &node.shaders[0],
+                    entry_point: "vertex",
+                    compilation_options: Default::default(),
+                    buffers: &[],
+                },
+                fragment: Some(FragmentState {
+                    module: &node.shaders[1],
+                    entry_point: "ghost",
+                    compilation_options: Default::default(),
+                    targets: &[Some(ColorTargetState {
+                        format: LENS_FLARE_TEXTURE_FORMAT,
+                        blend: Some(BlendState {
+                            color: BlendComponent {
+                                src_factor: BlendFactor::One,
+                                dst_factor: BlendFactor::One,
+                                operation: BlendOperation::Add,
+                            },
+                            alpha: BlendComponent {
+                                src_factor: BlendFactor::One,
+                                dst_factor: BlendFactor::One,
+                                operation: BlendOperation::Add,
+                            },
+                        }),
+                        write_mask: ColorWrites::ALL,
+                    })],
+                }),
+                primitive: Default::default(),
+                depth_stencil: Default::default(),
+                multisample: Default::default(),
+                multiview: Default::default(),
+                cache: node.pipeline_cache.as_deref(),
+            }));
+
+            let mut ghosts = DynamicGpuBuffer::new(BufferUsages::UNIFORM);
+            let offsets = self
+                .node_config
+                .ghosts
+                .iter()
+                .map(|ghost| ghosts.push(&FlareGhostUniform::from(ghost)))
+                .collect::<Vec<_>>();
+            ghosts.write::<FlareGhostUniform>(device, queue);
+
+            for (ghost, offset) in self.node_config.ghosts.iter().zip(offsets) {
+                let sprite = assets.textures[&ghost.texture.unwrap_or(DUMMY_2D_TEX)]
+                    .create_view(&Default::default());
+
+                let mut entries = effect_entries.clone();
+                entries.extend([
+                    BindGroupEntry {
+                        binding: 5,
+                        resource: ghosts.binding::<FlareGhostUniform>().unwrap(),
+                    },
+                    BindGroupEntry {
+                        binding: 6,
+                        resource: BindingResource::TextureView(&sprite),
+                    },
+                ]);
+
+                ghost_bind_groups.push((
+                    device.create_bind_group(&BindGroupDescriptor {
+                        label: Some("lens_flare_ghost_bind_group"),
+                        layout: &ghost_layout,
+                        entries: &entries,
+                    }),
+                    offset,
+                ));
+            }
+        }
+
         self.data = Some(LensFlareNodeData {
             downsample_pipeline,
             upsample_pipeline,
             effect_pipeline,
             blit_layout,
             effect_bind_group,
+            ghost_pipeline,
+            ghost_bind_groups,
             sampler,
             downsample_output,
             effect_output,
         });
     }
 
+    fn resize(&mut self, scene: &mut GpuScene, context: RenderContext, _new_size: UVec2) {
+        // Bind groups made in `build` capture both the downsampled targets and the depth
+        // prepass, so rebuild the node. Shaders are already compiled at this point.
+        self.build(scene, context);
+    }
+
     fn draw(
         &self,
-        _scene: &mut GpuScene,
+        GpuScene { assets, .. }: &mut GpuScene,
         RenderContext {
             device,
             queue,
@@ -395,23 +633,28 @@ impl RenderNode for LensFlareNode {
             downsample_output,
             effect_output,
             effect_bind_group,
+            ghost_pipeline,
+            ghost_bind_groups,
             sampler,
         } = self.data.as_ref().unwrap();
 
-        let downsample_bind_group = device.create_bind_group(&BindGroupDescriptor {
-            label: Some("lens_flare_downsample_bind_group"),
-            layout: blit_layout,
-            entries: &[
-                BindGroupEntry {
-                    binding: 0,
-                    resource: BindingResource::TextureView(targets.swap_chain.current_view()),
-                },
-                BindGroupEntry {
-                    binding: 1,
-                    resource: BindingResource::Sampler(sampler),
-                },
-            ],
-        });
+        let downsample_bind_group = assets.bind_group_cache.get_or_create(
+            device,
+            &BindGroupDescriptor {
+                label: Some("lens_flare_downsample_bind_group"),
+                layout: blit_layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::TextureView(targets.swap_chain.current_view()),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::Sampler(sampler),
+                    },
+                ],
+            },
+        );
 
         let mut command_encoder = device.create_command_encoder(&Default::default());
 
@@ -448,25 +691,55 @@ impl RenderNode for LensFlareNode {
 
             pass.set_pipeline(effect_pipeline);
             pass.set_bind_group(0, &effect_bind_group, &[]);
+            if self.node_config.occlusion {
+                pass.set_bind_group(1, assets.common_bind_group.as_ref().unwrap(), &[]);
+                pass.set_bind_group(2, assets.light_bind_group.as_ref().unwrap(), &[]);
+            }
             pass.draw(0..3, 0..1);
         }
 
+        if let Some(ghost_pipeline) = ghost_pipeline {
+            let mut pass = command_encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("lens_flare_ghost_pass"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: effect_output,
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Load,
+                        store: StoreOp::Store,
+                    },
+                })],
+                ..Default::default()
+            });
+
+            pass.set_pipeline(ghost_pipeline);
+            pass.set_bind_group(1, assets.common_bind_group.as_ref().unwrap(), &[]);
+            pass.set_bind_group(2, assets.light_bind_group.as_ref().unwrap(), &[]);
+            for (bind_group, offset) in ghost_bind_groups {
+                pass.set_bind_group(0, bind_group, &[*offset]);
+                pass.draw(0..3, 0..1);
+            }
+        }
+
         queue.submit([command_encoder.finish()]);
 
-        let upsample_bind_group = device.create_bind_group(&BindGroupDescriptor {
-            label: Some("lens_flare_upsample_bind_group"),
-            layout: blit_layout,
-            entries: &[
-                BindGroupEntry {
-                    binding: 0,
-                    resource: BindingResource::TextureView(effect_output),
-                },
-                BindGroupEntry {
-                    binding: 1,
-                    resource: BindingResource::Sampler(sampler),
-                },
-            ],
-        });
+        let upsample_bind_group = assets.bind_group_cache.get_or_create(
+            device,
+            &BindGroupDescriptor {
+                label: Some("lens_flare_upsample_bind_group"),
+                layout: blit_layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::TextureView(effect_output),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::Sampler(sampler),
+                    },
+                ],
+            },
+        );
 
         let mut command_encoder = device.create_command_encoder(&Default::default());
 
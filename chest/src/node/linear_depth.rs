@@ -0,0 +1,225 @@
+use aurora_core::render::{
+    flow::{DependencyNodeIndex, RenderContext, RenderNode},
+    scene::{GpuAssets, GpuScene, TextureId, TextureViewId},
+};
+use glam::UVec2;
+use uuid::Uuid;
+use wgpu::{
+    BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, ColorTargetState, ColorWrites,
+    CommandBuffer, Device, Extent3d, Features, FragmentState, PipelineLayoutDescriptor,
+    RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor,
+    ShaderStages, TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType,
+    TextureUsages, TextureViewDescriptor, TextureViewDimension, VertexState,
+};
+
+use crate::node::{DepthPrepassNode, DEPTH_PREPASS_TEXTURE};
+
+pub struct LinearDepthTexture {
+    pub texture: TextureId,
+    pub view: TextureViewId,
+}
+
+/// View space depth, positive in front of the camera.
+pub const LINEAR_DEPTH_TEXTURE: LinearDepthTexture = LinearDepthTexture {
+    texture: TextureId(Uuid::from_u128(4189620375190246857130985)),
+    view: TextureViewId(Uuid::from_u128(7130984652098163540927)),
+};
+
+pub const LINEAR_DEPTH_FORMAT: TextureFormat = TextureFormat::R32Float;
+
+pub struct LinearDepthNodeData {
+    pub pipeline: RenderPipeline,
+    pub layout: BindGroupLayout,
+}
+
+/// Resolves the depth prepass into [`LINEAR_DEPTH_TEXTURE`] once, so consumers don't have
+/// to unproject the raw depth themselves.
+#[derive(Default)]
+pub struct LinearDepthNode {
+    /// Request [`Features::FLOAT32_FILTERABLE`], so the texture can be sampled bilinearly.
+    pub filterable: bool,
+
+    pub data: Option<LinearDepthNodeData>,
+}
+
+impl LinearDepthNode {
+    fn create_texture(assets: &mut GpuAssets, device: &Device, size: UVec2) {
+        let texture = device.create_texture(&TextureDescriptor {
+            label: Some("linear_depth_texture"),
+            size: Extent3d {
+                width: size.x,
+                height: size.y,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: LINEAR_DEPTH_FORMAT,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        assets.texture_views.insert(
+            LINEAR_DEPTH_TEXTURE.view,
+            texture.create_view(&TextureViewDescriptor {
+                label: Some("linear_depth_texture_view"),
+                ..Default::default()
+            }),
+        );
+        assets
+            .textures
+            .insert(LINEAR_DEPTH_TEXTURE.texture, texture);
+    }
+}
+
+impl RenderNode for LinearDepthNode {
+    fn add_node_dependencies(&self) -> Vec<(DependencyNodeIndex, Box<dyn RenderNode>)> {
+        vec![(
+            DependencyNodeIndex::Before,
+            Box::new(DepthPrepassNode::default()),
+        )]
+    }
+
+    fn require_renderer_features(&self, features: &mut Features) {
+        if self.filterable {
+            *features |= Features::FLOAT32_FILTERABLE;
+        }
+    }
+
+    fn require_shaders(&self) -> Option<&'static [(&'static [&'static str], &'static str)]> {
+        Some(&[
+            (&[], include_str!("../shader/fullscreen.wgsl")),
+            (
+                &[
+                    include_str!("../shader/common/common_type.wgsl"),
+                    include_str!("../shader/common/camera.wgsl"),
+                    include_str!("../shader/common/common_binding.wgsl"),
+                    include_str!("../shader/fullscreen.wgsl"),
+                ],
+                include_str!("../shader/prepass/linear_depth.wgsl"),
+            ),
+        ])
+    }
+
+    fn build(
+        &mut self,
+        GpuScene { assets, .. }: &mut GpuScene,
+        RenderContext {
+            device,
+            node,
+            targets,
+            ..
+        }: RenderContext,
+    ) {
+        Self::create_texture(assets, device, targets.size);
+
+        let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("linear_depth_layout"),
+            entries: &[
+                // Depth
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Depth,
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("linear_depth_pipeline_layout"),
+            bind_group_layouts: &[assets.common_layout.as_ref().unwrap(), &layout],
+            ..Default::default()
+        });
+
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("linear_depth_pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &node.shaders[0],
+                entry_point: "vertex",
+                compilation_options: Default::default(),
+                buffers: &[],
+            },
+            fragment: Some(FragmentState {
+                module: &node.shaders[1],
+                entry_point: "fragment",
+                compilation_options: Default::default(),
+                targets: &[Some(ColorTargetState {
+                    format: LINEAR_DEPTH_FORMAT,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: Default::default(),
+            depth_stencil: Default::default(),
+            multisample: Default::default(),
+            multiview: Default::default(),
+            cache: node.pipeline_cache.as_deref(),
+        });
+
+        self.data = Some(LinearDepthNodeData { pipeline, layout });
+    }
+
+    fn resize(
+        &mut self,
+        GpuScene { assets, .. }: &mut GpuScene,
+        RenderContext { device, .. }: RenderContext,
+        new_size: UVec2,
+    ) {
+        Self::create_texture(assets, device, new_size);
+    }
+
+    fn records(&self) -> bool {
+        true
+    }
+
+    fn parallel_safe(&self) -> bool {
+        true
+    }
+
+    fn record(
+        &self,
+        GpuScene { assets, .. }: &GpuScene,
+        RenderContext { device, .. }: RenderContext,
+    ) -> Option<CommandBuffer> {
+        let LinearDepthNodeData { pipeline, layout } = self.data.as_ref()?;
+
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("linear_depth_bind_group"),
+            layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: BindingResource::TextureView(
+                    &assets.texture_views[&DEPTH_PREPASS_TEXTURE.view],
+                ),
+            }],
+        });
+
+        let mut command_encoder = device.create_command_encoder(&Default::default());
+
+        {
+            let mut pass = command_encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("linear_depth_pass"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: &assets.texture_views[&LINEAR_DEPTH_TEXTURE.view],
+                    resolve_target: None,
+                    ops: Default::default(),
+                })],
+                ..Default::default()
+            });
+
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, assets.common_bind_group.as_ref().unwrap(), &[]);
+            pass.set_bind_group(1, &bind_group, &[]);
+            pass.draw(0..3, 0..1);
+        }
+
+        Some(command_encoder.finish())
+    }
+}
@@ -1,31 +1,57 @@
+mod auto_exposure;
 mod basic_triangle;
 mod bloom;
+mod camera_effects;
+mod color_grade;
+mod deband;
+mod debug_view;
 mod depth_of_field;
 mod depth_prepass;
-mod depth_view;
 mod env_mapping;
+mod fxaa;
 mod lens_flare;
+mod linear_depth;
 mod motion_blur;
 mod motion_vector_prepass;
 mod normal_prepass;
+mod outline;
 mod pbr;
 mod shadow_mapping;
+mod sharpen;
+mod skinning;
 mod skybox;
 mod ssao;
+mod taa;
 mod tone_mapping;
+mod unlit;
+mod volumetric_fog;
+mod wireframe;
 
+pub use auto_exposure::*;
 pub use basic_triangle::*;
 pub use bloom::*;
+pub use camera_effects::*;
+pub use color_grade::*;
+pub use deband::*;
+pub use debug_view::*;
 pub use depth_of_field::*;
 pub use depth_prepass::*;
-pub use depth_view::*;
 pub use env_mapping::*;
+pub use fxaa::*;
 pub use lens_flare::*;
+pub use linear_depth::*;
 pub use motion_blur::*;
 pub use motion_vector_prepass::*;
 pub use normal_prepass::*;
+pub use outline::*;
 pub use pbr::*;
 pub use shadow_mapping::*;
+pub use sharpen::*;
+pub use skinning::*;
 pub use skybox::*;
 pub use ssao::*;
+pub use taa::*;
 pub use tone_mapping::*;
+pub use unlit::*;
+pub use volumetric_fog::*;
+pub use wireframe::*;
@@ -1,7 +1,10 @@
-use aurora_core::render::{
-    flow::{RenderContext, RenderNode},
-    resource::DynamicGpuBuffer,
-    scene::GpuScene,
+use aurora_core::{
+    render::{
+        flow::{RenderContext, RenderNode},
+        resource::DynamicGpuBuffer,
+        scene::GpuScene,
+    },
+    util::create_sampler,
 };
 use encase::ShaderType;
 use wgpu::{
@@ -72,7 +75,7 @@ impl RenderNode for MotionBlurNode {
 
     fn build(
         &mut self,
-        _scene: &mut GpuScene,
+        GpuScene { assets, .. }: &mut GpuScene,
         RenderContext {
             device,
             node,
@@ -162,21 +165,29 @@ impl RenderNode for MotionBlurNode {
             depth_stencil: Default::default(),
             multisample: Default::default(),
             multiview: Default::default(),
-            cache: Default::default(),
+            cache: node.pipeline_cache.as_deref(),
         });
 
-        let motion_vector_sampler = device.create_sampler(&SamplerDescriptor {
-            label: Some("motion_vector_sampler"),
-            ..Default::default()
-        });
+        let motion_vector_sampler = create_sampler(
+            device,
+            &SamplerDescriptor {
+                label: Some("motion_vector_sampler"),
+                ..Default::default()
+            },
+            assets.max_anisotropy,
+        );
 
-        let color_sampler = device.create_sampler(&SamplerDescriptor {
-            label: Some("color_sampler"),
-            mag_filter: FilterMode::Linear,
-            min_filter: FilterMode::Linear,
-            mipmap_filter: FilterMode::Linear,
-            ..Default::default()
-        });
+        let color_sampler = create_sampler(
+            device,
+            &SamplerDescriptor {
+                label: Some("color_sampler"),
+                mag_filter: FilterMode::Linear,
+                min_filter: FilterMode::Linear,
+                mipmap_filter: FilterMode::Linear,
+                ..Default::default()
+            },
+            assets.max_anisotropy,
+        );
 
         let config = DynamicGpuBuffer::new(BufferUsages::UNIFORM);
 
@@ -226,34 +237,37 @@ impl RenderNode for MotionBlurNode {
         };
 
         let post_process = targets.swap_chain.start_post_process();
-        let bind_group = device.create_bind_group(&BindGroupDescriptor {
-            label: Some("motion_blur_bind_group"),
-            layout,
-            entries: &[
-                BindGroupEntry {
-                    binding: 0,
-                    resource: BindingResource::TextureView(post_process.src),
-                },
-                BindGroupEntry {
-                    binding: 1,
-                    resource: BindingResource::TextureView(
-                        &assets.texture_views[&MOTION_VECTOR_PREPASS_TEXTURE.view],
-                    ),
-                },
-                BindGroupEntry {
-                    binding: 2,
-                    resource: BindingResource::Sampler(color_sampler),
-                },
-                BindGroupEntry {
-                    binding: 3,
-                    resource: BindingResource::Sampler(motion_vector_sampler),
-                },
-                BindGroupEntry {
-                    binding: 4,
-                    resource: config.entire_binding().unwrap(),
-                },
-            ],
-        });
+        let bind_group = assets.bind_group_cache.get_or_create(
+            device,
+            &BindGroupDescriptor {
+                label: Some("motion_blur_bind_group"),
+                layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::TextureView(post_process.src),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::TextureView(
+                            &assets.texture_views[&MOTION_VECTOR_PREPASS_TEXTURE.view],
+                        ),
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: BindingResource::Sampler(color_sampler),
+                    },
+                    BindGroupEntry {
+                        binding: 3,
+                        resource: BindingResource::Sampler(motion_vector_sampler),
+                    },
+                    BindGroupEntry {
+                        binding: 4,
+                        resource: config.entire_binding().unwrap(),
+                    },
+                ],
+            },
+        );
 
         let mut command_encoder = device.create_command_encoder(&Default::default());
 
@@ -1,19 +1,23 @@
+use std::collections::HashMap;
+
 use aurora_core::render::{
     flow::{RenderContext, RenderNode},
-    resource::{DynamicGpuBuffer, GpuCamera},
-    scene::{GpuScene, TextureId, TextureViewId},
+    helper::Transform,
+    mesh::Mesh,
+    resource::{DynamicGpuBuffer, GpuCamera, ModelUniforms},
+    scene::{GpuAssets, GpuScene, MeshInstanceId, TextureId, TextureViewId},
 };
 use encase::ShaderType;
-use glam::Mat4;
+use glam::{Mat4, UVec2};
 use uuid::Uuid;
 use wgpu::{
     BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
     BindGroupLayoutEntry, BindingType, BufferBindingType, BufferUsages, ColorTargetState,
-    ColorWrites, CompareFunction, DepthStencilState, Extent3d, FragmentState, LoadOp, Operations,
-    PipelineLayoutDescriptor, RenderPassColorAttachment, RenderPassDepthStencilAttachment,
-    RenderPassDescriptor, RenderPipelineDescriptor, ShaderStages, StoreOp, TextureDescriptor,
-    TextureDimension, TextureFormat, TextureUsages, VertexBufferLayout, VertexFormat, VertexState,
-    VertexStepMode,
+    ColorWrites, CommandBuffer, CompareFunction, DepthStencilState, Device, Extent3d,
+    FragmentState, LoadOp, Operations, PipelineLayoutDescriptor, RenderPassColorAttachment,
+    RenderPassDepthStencilAttachment, RenderPassDescriptor, RenderPipelineDescriptor, ShaderStages,
+    StoreOp, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages, VertexAttribute,
+    VertexBufferLayout, VertexFormat, VertexState, VertexStepMode,
 };
 
 use crate::node::DEPTH_PREPASS_TEXTURE;
@@ -30,6 +34,15 @@ pub const MOTION_VECTOR_PREPASS_TEXTURE: MotionVectorTexture = MotionVectorTextu
     view: TextureViewId(Uuid::from_u128(711332160019988)),
 };
 
+/// Previous positions next to the current ones, see [`GpuMesh::previous_position_vertices`].
+///
+/// [`GpuMesh::previous_position_vertices`]: aurora_core::render::mesh::GpuMesh::previous_position_vertices
+const PREVIOUS_POSITION_ATTRIBUTES: &[VertexAttribute] = &[VertexAttribute {
+    format: VertexFormat::Float32x3,
+    offset: 0,
+    shader_location: 1,
+}];
+
 #[derive(ShaderType)]
 pub struct MotionVectorPrepassConfig {
     pub previous_view: Mat4,
@@ -39,13 +52,58 @@ pub struct MotionVectorPrepassNodeData {
     pub current_view: Mat4,
     pub previous_view: DynamicGpuBuffer,
     pub layout: BindGroupLayout,
+    pub models: ModelUniforms,
+    /// Models of the previous frame, bound with the same offsets as `models`.
+    pub previous_models: ModelUniforms,
+    /// Transforms uploaded this frame, by mesh instance like the previous positions.
+    pub current_transforms: HashMap<MeshInstanceId, Transform>,
 }
 
+/// Screen space motion of the scene since the previous frame.
+///
+/// Motion comes from the camera, from the [`StaticMesh::transform`] of each mesh and from
+/// vertices deformed on the GPU, like skinning, which keep their previous positions in
+/// [`GpuMesh::previous_vertex_buffer`]. There are no morph
+/// targets to track yet, and vertex animation textures and wind are evaluated in the pbr
+/// shader only, so those meshes ghost under TAA and motion blur.
+///
+/// [`StaticMesh::transform`]: aurora_core::render::mesh::StaticMesh::transform
+/// [`GpuMesh::previous_vertex_buffer`]: aurora_core::render::mesh::GpuMesh::previous_vertex_buffer
 #[derive(Default)]
 pub struct MotionVectorPrepassNode {
     pub data: Option<MotionVectorPrepassNodeData>,
 }
 
+impl MotionVectorPrepassNode {
+    fn create_texture(assets: &mut GpuAssets, device: &Device, size: UVec2) {
+        let texture = device.create_texture(&TextureDescriptor {
+            label: Some("motion_vector_prepass"),
+            size: Extent3d {
+                width: size.x,
+                height: size.y,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: MOTION_VECTOR_PREPASS_FORMAT,
+            usage: TextureUsages::TEXTURE_BINDING
+                | TextureUsages::RENDER_ATTACHMENT
+                | TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+
+        let view = texture.create_view(&Default::default());
+
+        assets
+            .textures
+            .insert(MOTION_VECTOR_PREPASS_TEXTURE.texture, texture);
+        assets
+            .texture_views
+            .insert(MOTION_VECTOR_PREPASS_TEXTURE.view, view);
+    }
+}
+
 impl RenderNode for MotionVectorPrepassNode {
     fn restrict_mesh_format(&self) -> Option<&'static [VertexFormat]> {
         Some(&[
@@ -76,29 +134,7 @@ impl RenderNode for MotionVectorPrepassNode {
             ..
         }: RenderContext,
     ) {
-        let texture = device.create_texture(&TextureDescriptor {
-            label: Some("motion_vector_prepass"),
-            size: Extent3d {
-                width: targets.size.x,
-                height: targets.size.y,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: TextureDimension::D2,
-            format: MOTION_VECTOR_PREPASS_FORMAT,
-            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::RENDER_ATTACHMENT,
-            view_formats: &[],
-        });
-
-        let view = texture.create_view(&Default::default());
-
-        assets
-            .textures
-            .insert(MOTION_VECTOR_PREPASS_TEXTURE.texture, texture);
-        assets
-            .texture_views
-            .insert(MOTION_VECTOR_PREPASS_TEXTURE.view, view);
+        Self::create_texture(assets, device, targets.size);
 
         let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
             label: Some("motion_vector_prepass_layout"),
@@ -126,9 +162,12 @@ impl RenderNode for MotionVectorPrepassNode {
             ],
         });
 
+        let models = ModelUniforms::new(device);
+        let previous_models = ModelUniforms::new(device);
+
         let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
             label: Some("motion_vector_prepass_pipeline_layout"),
-            bind_group_layouts: &[&layout],
+            bind_group_layouts: &[&layout, &models.layout, &previous_models.layout],
             ..Default::default()
         });
 
@@ -136,6 +175,9 @@ impl RenderNode for MotionVectorPrepassNode {
             current_view: Default::default(),
             previous_view: DynamicGpuBuffer::new(BufferUsages::UNIFORM),
             layout,
+            models,
+            previous_models,
+            current_transforms: Default::default(),
         });
 
         for mesh in &node.meshes {
@@ -143,7 +185,10 @@ impl RenderNode for MotionVectorPrepassNode {
                 continue;
             }
 
-            let instance = &assets.meshes[&mesh.mesh.mesh];
+            let stride = assets.meshes[&mesh.mesh.mesh].vertex_stride();
+            let gpu_mesh = &assets.gpu_meshes[&mesh.mesh.mesh];
+            let (_, current_stride) = gpu_mesh.position_vertices(stride);
+            let (_, previous_stride) = gpu_mesh.previous_position_vertices(stride);
             let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
                 label: Some("motion_vector_prepass_pipeline"),
                 layout: Some(&pipeline_layout),
@@ -151,11 +196,14 @@ impl RenderNode for MotionVectorPrepassNode {
                     module: &node.shaders[0],
                     entry_point: "vertex",
                     compilation_options: Default::default(),
-                    buffers: &[VertexBufferLayout {
-                        array_stride: instance.vertex_stride(),
-                        step_mode: VertexStepMode::Vertex,
-                        attributes: &instance.vertex_attributes(),
-                    }],
+                    buffers: &[
+                        Mesh::position_vertex_layout(current_stride),
+                        VertexBufferLayout {
+                            array_stride: previous_stride,
+                            step_mode: VertexStepMode::Vertex,
+                            attributes: PREVIOUS_POSITION_ATTRIBUTES,
+                        },
+                    ],
                 },
                 fragment: Some(FragmentState {
                     module: &node.shaders[0],
@@ -177,21 +225,38 @@ impl RenderNode for MotionVectorPrepassNode {
                 }),
                 multisample: Default::default(),
                 multiview: None,
-                cache: None,
+                cache: node.pipeline_cache.as_deref(),
             });
 
             node.pipelines.insert(mesh.mesh.mesh, pipeline);
         }
     }
 
+    fn resize(
+        &mut self,
+        GpuScene { assets, .. }: &mut GpuScene,
+        RenderContext { device, .. }: RenderContext,
+        new_size: UVec2,
+    ) {
+        Self::create_texture(assets, device, new_size);
+    }
+
     fn prepare(
         &mut self,
         GpuScene { original, .. }: &mut GpuScene,
-        RenderContext { device, queue, .. }: RenderContext,
+        RenderContext {
+            device,
+            queue,
+            node,
+            ..
+        }: RenderContext,
     ) {
         let Some(MotionVectorPrepassNodeData {
             current_view,
             previous_view,
+            models,
+            previous_models,
+            current_transforms,
             ..
         }) = &mut self.data
         else {
@@ -204,26 +269,47 @@ impl RenderNode for MotionVectorPrepassNode {
         });
         previous_view.write::<MotionVectorPrepassConfig>(device, queue);
         *current_view = original.camera.transform.compute_matrix().inverse();
+
+        // Meshes new this frame didn't move.
+        previous_models.prepare_with(device, queue, &mut node.meshes, |mesh| {
+            current_transforms
+                .get(&mesh.mesh.mesh)
+                .copied()
+                .unwrap_or(mesh.mesh.transform)
+        });
+        models.prepare(device, queue, &mut node.meshes);
+        *current_transforms = node
+            .meshes
+            .iter()
+            .map(|mesh| (mesh.mesh.mesh, mesh.mesh.transform))
+            .collect();
     }
 
-    fn draw(
+    fn records(&self) -> bool {
+        true
+    }
+
+    fn parallel_safe(&self) -> bool {
+        true
+    }
+
+    fn record(
         &self,
-        GpuScene { assets, .. }: &mut GpuScene,
-        RenderContext {
-            device,
-            queue,
-            node,
-            ..
-        }: RenderContext,
-    ) {
+        GpuScene { assets, .. }: &GpuScene,
+        RenderContext { device, node, .. }: RenderContext,
+    ) -> Option<CommandBuffer> {
         let Some(MotionVectorPrepassNodeData {
             previous_view,
             layout,
+            models,
+            previous_models,
             ..
         }) = &self.data
         else {
-            return;
+            return None;
         };
+        let b_model = models.bind_group.as_ref()?;
+        let b_previous_model = previous_models.bind_group.as_ref()?;
 
         let bind_group = device.create_bind_group(&BindGroupDescriptor {
             label: Some("motion_vector_prepass_bind_group"),
@@ -267,9 +353,13 @@ impl RenderNode for MotionVectorPrepassNode {
                     &assets.gpu_meshes[&mesh.mesh.mesh],
                     &node.pipelines[&mesh.mesh.mesh],
                 );
+                let stride = assets.meshes[&mesh.mesh.mesh].vertex_stride();
 
                 pass.set_pipeline(pipeline);
-                pass.set_vertex_buffer(0, instance.vertex_buffer.slice(..));
+                pass.set_bind_group(1, b_model, &[mesh.model_offset.unwrap()]);
+                pass.set_bind_group(2, b_previous_model, &[mesh.model_offset.unwrap()]);
+                pass.set_vertex_buffer(0, instance.position_vertices(stride).0);
+                pass.set_vertex_buffer(1, instance.previous_position_vertices(stride).0);
                 if let Some(indices) = &instance.index_buffer {
                     pass.set_index_buffer(indices.buffer.slice(..), indices.format);
                     pass.draw_indexed(0..indices.count, 0, 0..1);
@@ -279,6 +369,88 @@ impl RenderNode for MotionVectorPrepassNode {
             }
         }
 
-        queue.submit([command_encoder.finish()]);
+        Some(command_encoder.finish())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aurora_core::{
+        render::{
+            flow::{GeneralNode, RenderFlow},
+            helper::{CameraProjection, OrthographicProjection, Transform},
+            scene::{GpuScene, MaterialInstanceId},
+        },
+        util::{
+            testing::{self, TestTargets},
+            TextureReadback,
+        },
+    };
+    use glam::{UVec2, Vec2, Vec3};
+    use half::f16;
+    use wgpu::{Maintain, TextureFormat};
+
+    use super::{MotionVectorPrepassNode, MOTION_VECTOR_PREPASS_TEXTURE};
+    use crate::node::DepthPrepassNode;
+
+    const SIZE: u32 = 64;
+
+    #[test]
+    fn moved_mesh() {
+        let Some(renderer) = testing::renderer(None, None) else {
+            return;
+        };
+
+        let mut scene = GpuScene::default();
+        // A quad covering the middle half of the view.
+        testing::add_static_mesh(
+            &mut scene,
+            testing::quad(Vec2::splat(-0.5), Vec2::splat(0.5), -1.),
+            MaterialInstanceId::default(),
+        );
+        scene.original.camera.projection =
+            CameraProjection::Orthographic(OrthographicProjection::symmetric(2., 2., 0.1, 10.));
+
+        let size = UVec2::splat(SIZE);
+        let test_targets = TestTargets::new(&renderer.device, size, TextureFormat::Rgba8Unorm)
+            .with_depth(&renderer.device);
+        let targets = test_targets.targets();
+
+        let mut flow = RenderFlow::default();
+        flow.add::<GeneralNode>()
+            .add::<DepthPrepassNode>()
+            .add::<MotionVectorPrepassNode>();
+        flow.set_queue(scene.static_meshes.clone());
+        flow.build(&renderer, &mut scene, None, &targets).unwrap();
+        flow.run(&renderer, &mut scene, &targets);
+
+        // A quarter of the view to the right, now covering pixels 32 to 63.
+        scene.static_meshes[0].transform = Transform {
+            translation: Vec3::new(0.5, 0., 0.),
+            ..Default::default()
+        };
+        flow.set_queue(scene.static_meshes.clone());
+        flow.run(&renderer, &mut scene, &targets);
+
+        let readback = TextureReadback::new(
+            &scene.assets.textures[&MOTION_VECTOR_PREPASS_TEXTURE.texture],
+            &renderer.device,
+            &renderer.queue,
+        )
+        .unwrap();
+        renderer.device.poll(Maintain::wait()).panic_on_timeout();
+        let data = pollster::block_on(readback.read()).unwrap();
+        let texels = bytemuck::pod_collect_to_vec::<u8, f16>(&data.bytes);
+        let row = (data.bytes_per_row / 2) as usize;
+        let motion = |x: usize, y: usize| {
+            Vec2::new(
+                texels[y * row + x * 2].to_f32(),
+                texels[y * row + x * 2 + 1].to_f32(),
+            )
+        };
+
+        // Half a unit of the two unit wide view.
+        let moved = motion(56, 32);
+        assert!((moved - Vec2::new(0.5, 0.)).length() < 0.01, "{moved}");
     }
 }
@@ -1,18 +1,20 @@
 use aurora_core::render::{
     flow::{RenderContext, RenderNode},
-    resource::GpuCamera,
-    scene::{GpuScene, TextureId, TextureViewId},
+    mesh::MeshVertexBufferLayout,
+    resource::{GpuCamera, ModelUniforms},
+    scene::{GpuAssets, GpuScene, TextureId, TextureViewId},
 };
 use encase::ShaderType;
+use glam::UVec2;
 use uuid::Uuid;
 use wgpu::{
     BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
     BindGroupLayoutEntry, BindingType, BufferBindingType, Color, ColorTargetState, ColorWrites,
-    CompareFunction, DepthBiasState, DepthStencilState, Extent3d, FragmentState, LoadOp,
-    Operations, PipelineLayoutDescriptor, RenderPassColorAttachment,
+    CommandBuffer, CompareFunction, DepthBiasState, DepthStencilState, Device, Extent3d,
+    FragmentState, LoadOp, Operations, PipelineLayoutDescriptor, RenderPassColorAttachment,
     RenderPassDepthStencilAttachment, RenderPassDescriptor, RenderPipelineDescriptor, ShaderStages,
     StoreOp, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
-    TextureViewDescriptor, VertexBufferLayout, VertexFormat, VertexState, VertexStepMode,
+    TextureViewDescriptor, VertexFormat, VertexState,
 };
 
 use crate::node::DEPTH_PREPASS_TEXTURE;
@@ -33,6 +35,38 @@ pub const NORMAL_PREPASS_FORMAT: TextureFormat = TextureFormat::Rgb10a2Unorm;
 pub struct NormalPrepassNode {
     layout: Option<BindGroupLayout>,
     bind_group: Option<BindGroup>,
+    models: Option<ModelUniforms>,
+}
+
+impl NormalPrepassNode {
+    fn create_texture(assets: &mut GpuAssets, device: &Device, size: UVec2) {
+        let normal_texture = device.create_texture(&TextureDescriptor {
+            label: Some("normal_prepass_texture"),
+            size: Extent3d {
+                width: size.x,
+                height: size.y,
+                depth_or_array_layers: 1,
+            },
+            dimension: TextureDimension::D2,
+            format: NORMAL_PREPASS_FORMAT,
+            mip_level_count: 1,
+            sample_count: 1,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+
+        let normal_texture_view = normal_texture.create_view(&TextureViewDescriptor {
+            label: Some("normal_prepass_texture_view"),
+            ..Default::default()
+        });
+
+        assets
+            .textures
+            .insert(NORMAL_PREPASS_TEXTURE.texture, normal_texture);
+        assets
+            .texture_views
+            .insert(NORMAL_PREPASS_TEXTURE.view, normal_texture_view);
+    }
 }
 
 impl RenderNode for NormalPrepassNode {
@@ -62,32 +96,7 @@ impl RenderNode for NormalPrepassNode {
             ..
         }: RenderContext,
     ) {
-        let normal_texture = device.create_texture(&TextureDescriptor {
-            label: Some("normal_prepass_texture"),
-            size: Extent3d {
-                width: targets.size.x,
-                height: targets.size.y,
-                depth_or_array_layers: 1,
-            },
-            dimension: TextureDimension::D2,
-            format: NORMAL_PREPASS_FORMAT,
-            mip_level_count: 1,
-            sample_count: 1,
-            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::RENDER_ATTACHMENT,
-            view_formats: &[],
-        });
-
-        let normal_texture_view = normal_texture.create_view(&TextureViewDescriptor {
-            label: Some("normal_prepass_texture_view"),
-            ..Default::default()
-        });
-
-        assets
-            .textures
-            .insert(NORMAL_PREPASS_TEXTURE.texture, normal_texture);
-        assets
-            .texture_views
-            .insert(NORMAL_PREPASS_TEXTURE.view, normal_texture_view);
+        Self::create_texture(assets, device, targets.size);
         let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
             label: Some("normal_prepass_layout"),
             entries: &[BindGroupLayoutEntry {
@@ -101,10 +110,11 @@ impl RenderNode for NormalPrepassNode {
                 count: None,
             }],
         });
+        let models = self.models.insert(ModelUniforms::new(device));
 
         let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
             label: Some("normal_prepass_pipeline_layout"),
-            bind_group_layouts: &[&layout],
+            bind_group_layouts: &[&layout, &models.layout],
             push_constant_ranges: &[],
         });
 
@@ -123,11 +133,11 @@ impl RenderNode for NormalPrepassNode {
                         module: &node.shaders[0],
                         entry_point: "vertex",
                         compilation_options: Default::default(),
-                        buffers: &[VertexBufferLayout {
-                            array_stride: instance.vertex_stride(),
-                            step_mode: VertexStepMode::Vertex,
-                            attributes: &instance.vertex_attributes(),
-                        }],
+                        buffers: &instance
+                            .vertex_buffer_layouts()
+                            .iter()
+                            .map(MeshVertexBufferLayout::layout)
+                            .collect::<Vec<_>>(),
                     },
                     fragment: Some(FragmentState {
                         module: &node.shaders[0],
@@ -149,7 +159,7 @@ impl RenderNode for NormalPrepassNode {
                     }),
                     multisample: Default::default(),
                     multiview: None,
-                    cache: None,
+                    cache: node.pipeline_cache.as_deref(),
                 }),
             );
         }
@@ -157,11 +167,29 @@ impl RenderNode for NormalPrepassNode {
         self.layout = Some(layout);
     }
 
-    fn prepare(
+    fn resize(
         &mut self,
         GpuScene { assets, .. }: &mut GpuScene,
         RenderContext { device, .. }: RenderContext,
+        new_size: UVec2,
     ) {
+        Self::create_texture(assets, device, new_size);
+    }
+
+    fn prepare(
+        &mut self,
+        GpuScene { assets, .. }: &mut GpuScene,
+        RenderContext {
+            device,
+            queue,
+            node,
+            ..
+        }: RenderContext,
+    ) {
+        if let Some(models) = &mut self.models {
+            models.prepare(device, queue, &mut node.meshes);
+        }
+
         let bind_group = device.create_bind_group(&BindGroupDescriptor {
             label: Some("normal_prepass_bind_group"),
             layout: self.layout.as_ref().unwrap(),
@@ -174,16 +202,20 @@ impl RenderNode for NormalPrepassNode {
         self.bind_group = Some(bind_group);
     }
 
-    fn draw(
+    fn records(&self) -> bool {
+        true
+    }
+
+    fn parallel_safe(&self) -> bool {
+        true
+    }
+
+    fn record(
         &self,
-        GpuScene { assets, .. }: &mut GpuScene,
-        RenderContext {
-            device,
-            queue,
-            node,
-            ..
-        }: RenderContext,
-    ) {
+        GpuScene { assets, .. }: &GpuScene,
+        RenderContext { device, node, .. }: RenderContext,
+    ) -> Option<CommandBuffer> {
+        let b_model = self.models.as_ref()?.bind_group.as_ref()?;
         let mut command_encoder = device.create_command_encoder(&Default::default());
 
         {
@@ -215,7 +247,8 @@ impl RenderNode for NormalPrepassNode {
                 let pipeline = &node.pipelines[&mesh.mesh.mesh];
 
                 pass.set_pipeline(pipeline);
-                pass.set_vertex_buffer(0, instance.vertex_buffer.slice(..));
+                pass.set_bind_group(1, b_model, &[mesh.model_offset.unwrap()]);
+                instance.set_vertex_buffers(&mut pass);
                 if let Some(indices) = &instance.index_buffer {
                     pass.set_index_buffer(indices.buffer.slice(..), indices.format);
                     pass.draw_indexed(0..indices.count, 0, 0..1);
@@ -225,6 +258,6 @@ impl RenderNode for NormalPrepassNode {
             }
         }
 
-        queue.submit([command_encoder.finish()]);
+        Some(command_encoder.finish())
     }
 }
@@ -0,0 +1,616 @@
+use std::collections::HashSet;
+
+use aurora_core::{
+    render::{
+        flow::{RenderContext, RenderNode},
+        mesh::MeshVertexBufferLayout,
+        resource::{DynamicGpuBuffer, ModelUniforms},
+        scene::{GpuScene, MeshInstanceId},
+    },
+    util,
+};
+use encase::ShaderType;
+use glam::{UVec2, Vec4};
+use wgpu::{
+    BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, BufferBindingType, BufferUsages, Color,
+    ColorTargetState, ColorWrites, Device, FragmentState, LoadOp, Operations,
+    PipelineLayoutDescriptor, RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline,
+    RenderPipelineDescriptor, ShaderStages, StoreOp, TextureFormat, TextureSampleType,
+    TextureUsages, TextureView, TextureViewDimension, VertexFormat, VertexState,
+};
+
+/// Closest selected pixel of each pixel, found by jump flooding.
+///
+/// Coordinates are packed in 16 bits each, as 2 channel 32 bit formats aren't renderable
+/// everywhere.
+const OUTLINE_SEED_FORMAT: TextureFormat = TextureFormat::R32Uint;
+
+#[derive(Clone, Copy)]
+pub struct OutlineConfig {
+    /// Alpha is the opacity of the outline.
+    pub color: Vec4,
+    /// Fraction of the target height, so the outline looks the same at any resolution.
+    pub width: f32,
+}
+
+impl Default for OutlineConfig {
+    fn default() -> Self {
+        Self {
+            color: Vec4::new(1., 0.5, 0., 1.),
+            width: 0.003,
+        }
+    }
+}
+
+#[derive(ShaderType)]
+struct OutlineUniform {
+    color: Vec4,
+    /// In pixels.
+    width: f32,
+}
+
+#[derive(ShaderType)]
+struct JumpFloodUniform {
+    step: i32,
+}
+
+pub struct OutlineNodeData {
+    pub jump_flood_pipeline: RenderPipeline,
+    pub jump_flood_layout: BindGroupLayout,
+    pub composite_pipeline: RenderPipeline,
+    pub composite_layout: BindGroupLayout,
+    /// Ping-ponged by the jump flood passes.
+    pub seeds: [TextureView; 2],
+    pub uniform: DynamicGpuBuffer,
+    pub steps: DynamicGpuBuffer,
+    pub step_offsets: Vec<u32>,
+    pub models: ModelUniforms,
+}
+
+/// Outlines the silhouette of the selected meshes, like the selection of an editor.
+///
+/// Selected meshes are drawn without depth testing, so their whole silhouette is outlined
+/// even when partially occluded. The color is composited as is, place the node after
+/// tonemapping to keep it unaffected by exposure.
+#[derive(Default)]
+pub struct OutlineNode {
+    pub config: OutlineConfig,
+    /// Meshes of the queue to outline.
+    pub selected: HashSet<MeshInstanceId>,
+
+    pub data: Option<OutlineNodeData>,
+}
+
+impl OutlineNode {
+    fn create_seeds(device: &Device, size: UVec2) -> [TextureView; 2] {
+        [(); 2].map(|_| {
+            util::create_texture(
+                device,
+                size.extend(1),
+                OUTLINE_SEED_FORMAT,
+                TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+            )
+            .create_view(&Default::default())
+        })
+    }
+}
+
+impl RenderNode for OutlineNode {
+    fn restrict_mesh_format(&self) -> Option<&'static [VertexFormat]> {
+        Some(&[
+            VertexFormat::Float32x3,
+            VertexFormat::Float32x3,
+            VertexFormat::Float32x2,
+            VertexFormat::Float32x4,
+        ])
+    }
+
+    fn require_shaders(&self) -> Option<&'static [(&'static [&'static str], &'static str)]> {
+        Some(&[
+            (
+                &[
+                    include_str!("../shader/common/common_type.wgsl"),
+                    include_str!("../shader/common/common_binding.wgsl"),
+                ],
+                include_str!("../shader/post_processing/outline_mask.wgsl"),
+            ),
+            (&[], include_str!("../shader/fullscreen.wgsl")),
+            (
+                &[
+                    include_str!("../shader/fullscreen.wgsl"),
+                    include_str!("../shader/post_processing/outline_type.wgsl"),
+                ],
+                include_str!("../shader/post_processing/outline_jump_flood.wgsl"),
+            ),
+            (
+                &[
+                    include_str!("../shader/fullscreen.wgsl"),
+                    include_str!("../shader/post_processing/outline_type.wgsl"),
+                ],
+                include_str!("../shader/post_processing/outline.wgsl"),
+            ),
+        ])
+    }
+
+    fn build(
+        &mut self,
+        GpuScene { assets, .. }: &mut GpuScene,
+        RenderContext {
+            device,
+            node,
+            targets,
+            ..
+        }: RenderContext,
+    ) {
+        let models = ModelUniforms::new(device);
+
+        let mask_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("outline_mask_pipeline_layout"),
+            bind_group_layouts: &[assets.common_layout.as_ref().unwrap(), &models.layout],
+            push_constant_ranges: &[],
+        });
+
+        for mesh in &node.meshes {
+            if node.pipelines.contains_key(&mesh.mesh.mesh) {
+                continue;
+            }
+
+            let instance = &assets.meshes[&mesh.mesh.mesh];
+            let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+                label: Some("outline_mask_pipeline"),
+                layout: Some(&mask_pipeline_layout),
+                vertex: VertexState {
+                    module: &node.shaders[0],
+                    entry_point: "vertex",
+                    compilation_options: Default::default(),
+                    buffers: &instance
+                        .vertex_buffer_layouts()
+                        .iter()
+                        .map(MeshVertexBufferLayout::layout)
+                        .collect::<Vec<_>>(),
+                },
+                fragment: Some(FragmentState {
+                    module: &node.shaders[0],
+                    entry_point: "fragment",
+                    compilation_options: Default::default(),
+                    targets: &[Some(ColorTargetState {
+                        format: OUTLINE_SEED_FORMAT,
+                        blend: None,
+                        write_mask: ColorWrites::ALL,
+                    })],
+                }),
+                primitive: Default::default(),
+                depth_stencil: None,
+                multisample: Default::default(),
+                multiview: Default::default(),
+                cache: node.pipeline_cache.as_deref(),
+            });
+            node.pipelines.insert(mesh.mesh.mesh, pipeline);
+        }
+
+        let jump_flood_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("outline_jump_flood_layout"),
+            entries: &[
+                // Seeds
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Uint,
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                // Step
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: Some(JumpFloodUniform::min_size()),
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let composite_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("outline_composite_layout"),
+            entries: &[
+                // Color
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: false },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                // Seeds
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Uint,
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                // Config
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: Some(OutlineUniform::min_size()),
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let fullscreen_pipeline = |label, layout: &BindGroupLayout, module, format| {
+            let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+                label: Some(label),
+                bind_group_layouts: &[layout],
+                ..Default::default()
+            });
+
+            device.create_render_pipeline(&RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                vertex: VertexState {
+                    module: &node.shaders[1],
+                    entry_point: "vertex",
+                    compilation_options: Default::default(),
+                    buffers: &[],
+                },
+                fragment: Some(FragmentState {
+                    module,
+                    entry_point: "fragment",
+                    compilation_options: Default::default(),
+                    targets: &[Some(ColorTargetState {
+                        format,
+                        blend: None,
+                        write_mask: ColorWrites::ALL,
+                    })],
+                }),
+                primitive: Default::default(),
+                depth_stencil: Default::default(),
+                multisample: Default::default(),
+                multiview: Default::default(),
+                cache: node.pipeline_cache.as_deref(),
+            })
+        };
+
+        self.data = Some(OutlineNodeData {
+            jump_flood_pipeline: fullscreen_pipeline(
+                "outline_jump_flood_pipeline",
+                &jump_flood_layout,
+                &node.shaders[2],
+                OUTLINE_SEED_FORMAT,
+            ),
+            composite_pipeline: fullscreen_pipeline(
+                "outline_composite_pipeline",
+                &composite_layout,
+                &node.shaders[3],
+                targets.color_format,
+            ),
+            jump_flood_layout,
+            composite_layout,
+            seeds: Self::create_seeds(device, targets.size),
+            uniform: DynamicGpuBuffer::new(BufferUsages::UNIFORM),
+            steps: DynamicGpuBuffer::new(BufferUsages::UNIFORM),
+            step_offsets: Vec::new(),
+            models,
+        });
+    }
+
+    fn resize(
+        &mut self,
+        _scene: &mut GpuScene,
+        RenderContext { device, .. }: RenderContext,
+        new_size: UVec2,
+    ) {
+        if let Some(data) = &mut self.data {
+            data.seeds = Self::create_seeds(device, new_size);
+        }
+    }
+
+    fn prepare(
+        &mut self,
+        _scene: &mut GpuScene,
+        RenderContext {
+            device,
+            queue,
+            node,
+            targets,
+            ..
+        }: RenderContext,
+    ) {
+        let Some(OutlineNodeData {
+            uniform,
+            steps,
+            step_offsets,
+            models,
+            ..
+        }) = &mut self.data
+        else {
+            return;
+        };
+
+        let width = self.config.width * targets.size.y as f32;
+        uniform.clear();
+        uniform.push(&OutlineUniform {
+            color: self.config.color,
+            width,
+        });
+        uniform.write::<OutlineUniform>(device, queue);
+
+        // Halving steps from the smallest power of two covering the outline, so every
+        // pixel within the width finds its closest seed.
+        let mut step = (width.ceil().max(1.) as u32).next_power_of_two();
+        steps.clear();
+        step_offsets.clear();
+        while step > 0 {
+            step_offsets.push(steps.push(&JumpFloodUniform { step: step as i32 }));
+            step /= 2;
+        }
+        steps.write::<JumpFloodUniform>(device, queue);
+        models.prepare(device, queue, &mut node.meshes);
+    }
+
+    fn draw(
+        &self,
+        GpuScene { assets, .. }: &mut GpuScene,
+        RenderContext {
+            device,
+            queue,
+            node,
+            targets,
+            ..
+        }: RenderContext,
+    ) {
+        let Some(OutlineNodeData {
+            jump_flood_pipeline,
+            jump_flood_layout,
+            composite_pipeline,
+            composite_layout,
+            seeds,
+            uniform,
+            steps,
+            step_offsets,
+            models,
+        }) = &self.data
+        else {
+            return;
+        };
+        let Some(b_model) = &models.bind_group else {
+            return;
+        };
+
+        if !node
+            .meshes
+            .iter()
+            .any(|mesh| self.selected.contains(&mesh.mesh.mesh))
+        {
+            return;
+        }
+
+        let mut command_encoder = device.create_command_encoder(&Default::default());
+
+        {
+            let mut pass = command_encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("outline_mask_pass"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: &seeds[0],
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Clear(Color {
+                            r: u32::MAX as f64,
+                            ..Color::TRANSPARENT
+                        }),
+                        store: StoreOp::Store,
+                    },
+                })],
+                ..Default::default()
+            });
+
+            pass.set_bind_group(0, assets.common_bind_group.as_ref().unwrap(), &[]);
+            for mesh in &node.meshes {
+                if !self.selected.contains(&mesh.mesh.mesh) {
+                    continue;
+                }
+
+                let (instance, pipeline) = (
+                    &assets.gpu_meshes[&mesh.mesh.mesh],
+                    &node.pipelines[&mesh.mesh.mesh],
+                );
+
+                pass.set_pipeline(pipeline);
+                pass.set_bind_group(1, b_model, &[mesh.model_offset.unwrap()]);
+                instance.set_vertex_buffers(&mut pass);
+                if let Some(indices) = &instance.index_buffer {
+                    pass.set_index_buffer(indices.buffer.slice(..), indices.format);
+                    pass.draw_indexed(0..indices.count, 0, 0..1);
+                } else {
+                    pass.draw(0..instance.vertices_count, 0..1);
+                }
+            }
+        }
+
+        let jump_flood_bind_groups = [0, 1].map(|src| {
+            assets.bind_group_cache.get_or_create(
+                device,
+                &BindGroupDescriptor {
+                    label: Some("outline_jump_flood_bind_group"),
+                    layout: jump_flood_layout,
+                    entries: &[
+                        BindGroupEntry {
+                            binding: 0,
+                            resource: BindingResource::TextureView(&seeds[src]),
+                        },
+                        BindGroupEntry {
+                            binding: 1,
+                            resource: steps.binding::<JumpFloodUniform>().unwrap(),
+                        },
+                    ],
+                },
+            )
+        });
+
+        for (index, offset) in step_offsets.iter().enumerate() {
+            let mut pass = command_encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("outline_jump_flood_pass"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: &seeds[(index + 1) % 2],
+                    resolve_target: None,
+                    ops: Default::default(),
+                })],
+                ..Default::default()
+            });
+
+            pass.set_pipeline(jump_flood_pipeline);
+            pass.set_bind_group(0, &jump_flood_bind_groups[index % 2], &[*offset]);
+            pass.draw(0..3, 0..1);
+        }
+
+        let post_process = targets.swap_chain.start_post_process();
+        let composite_bind_group = assets.bind_group_cache.get_or_create(
+            device,
+            &BindGroupDescriptor {
+                label: Some("outline_composite_bind_group"),
+                layout: composite_layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::TextureView(post_process.src),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::TextureView(&seeds[step_offsets.len() % 2]),
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: uniform.entire_binding().unwrap(),
+                    },
+                ],
+            },
+        );
+
+        {
+            let mut pass = command_encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("outline_composite_pass"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: post_process.dst,
+                    resolve_target: None,
+                    ops: Default::default(),
+                })],
+                ..Default::default()
+            });
+
+            pass.set_pipeline(composite_pipeline);
+            pass.set_bind_group(0, &composite_bind_group, &[]);
+            pass.draw(0..3, 0..1);
+        }
+
+        queue.submit([command_encoder.finish()]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aurora_core::{
+        render::{
+            flow::{GeneralNode, RenderFlow},
+            helper::{CameraProjection, OrthographicProjection, Transform},
+            scene::{GpuScene, MaterialInstanceId},
+        },
+        util::testing::{self, TestTargets},
+        WgpuRenderer,
+    };
+    use glam::{UVec2, Vec2, Vec3, Vec4};
+    use image::RgbaImage;
+    use wgpu::TextureFormat;
+
+    use super::{OutlineConfig, OutlineNode};
+
+    const SIZE: u32 = 64;
+
+    /// Outlines a quad covering the middle half of the view, placed by `transform`.
+    fn render(renderer: &WgpuRenderer, transform: Transform) -> RgbaImage {
+        let mut scene = GpuScene::default();
+        let mesh_id = testing::add_static_mesh(
+            &mut scene,
+            testing::quad(Vec2::splat(-0.5), Vec2::splat(0.5), -1.),
+            MaterialInstanceId::default(),
+        );
+        scene.static_meshes[0].transform = transform;
+        scene.original.camera.projection =
+            CameraProjection::Orthographic(OrthographicProjection::symmetric(2., 2., 0.1, 10.));
+
+        let size = UVec2::splat(SIZE);
+        let test_targets = TestTargets::new(&renderer.device, size, TextureFormat::Rgba8Unorm);
+        let targets = test_targets.targets();
+
+        let mut flow = RenderFlow::default();
+        flow.add::<GeneralNode>().add_initialized(OutlineNode {
+            config: OutlineConfig {
+                color: Vec4::new(1., 0., 0., 1.),
+                // 4 pixels.
+                width: 4. / SIZE as f32,
+            },
+            selected: [mesh_id].into(),
+            data: None,
+        });
+        flow.set_queue(scene.static_meshes.clone());
+        flow.build(renderer, &mut scene, None, &targets).unwrap();
+        flow.run(renderer, &mut scene, &targets);
+
+        test_targets.read(renderer)
+    }
+
+    #[test]
+    fn outline_selected_mesh() {
+        let Some(renderer) = testing::renderer(None, None) else {
+            return;
+        };
+
+        let image = render(&renderer, Transform::default());
+        // The quad covers pixels 16 to 47.
+        let center = SIZE / 2;
+        assert_eq!(image.get_pixel(center, center)[0], 0);
+        assert_eq!(image.get_pixel(17, center)[0], 0);
+        assert_eq!(image.get_pixel(14, center)[0], 255);
+        assert_eq!(image.get_pixel(center, 49)[0], 255);
+        assert_eq!(image.get_pixel(8, center)[0], 0);
+        assert_eq!(image.get_pixel(2, 2)[0], 0);
+    }
+
+    #[test]
+    fn outline_follows_transform() {
+        let Some(renderer) = testing::renderer(None, None) else {
+            return;
+        };
+
+        let image = render(
+            &renderer,
+            Transform {
+                translation: Vec3::new(0.5, 0., 0.),
+                ..Default::default()
+            },
+        );
+        // Moved by 16 pixels, the quad now covers pixels 32 to 63.
+        let center = SIZE / 2;
+        assert_eq!(image.get_pixel(14, center)[0], 0);
+        assert_eq!(image.get_pixel(30, center)[0], 255);
+        assert_eq!(image.get_pixel(40, center)[0], 0);
+    }
+}
@@ -1,32 +1,41 @@
 use std::{
     any::TypeId,
-    collections::{hash_map::Entry, HashMap},
+    collections::{hash_map::Entry, HashMap, HashSet},
 };
 
 use aurora_core::{
     render::{
-        flow::{RenderContext, RenderNode},
-        mesh::CreateBindGroupLayout,
-        resource::DynamicGpuBuffer,
-        scene::{GpuScene, MaterialTypeId, TextureId},
+        flow::{NodeContext, RenderContext, RenderNode},
+        helper::{Scene, Transform},
+        mesh::{
+            AlphaMode, CreateBindGroupLayout, InstancedMesh, Material, Mesh, MeshIndices,
+            MeshVertexBufferLayout, StaticMesh, VertexDisplacement,
+        },
+        resource::{DynamicGpuBuffer, ModelUniforms, RenderTargets, MATERIAL_OVERRIDE},
+        scene::{
+            GpuAssets, GpuScene, MaterialInstanceId, MaterialTypeId, MeshInstanceId, TextureId,
+        },
         ShaderDefEnum,
     },
     util::ext::TypeIdAsUuid,
 };
+use glam::{UVec2, Vec3};
 use naga_oil::compose::ShaderDefValue;
 use uuid::Uuid;
 use wgpu::{
-    BufferUsages, ColorTargetState, ColorWrites, CommandEncoderDescriptor, CompareFunction,
-    DepthBiasState, DepthStencilState, Face, FragmentState, Limits, LoadOp, MultisampleState,
-    Operations, PipelineCompilationOptions, PipelineLayoutDescriptor, PrimitiveState,
+    util::{BufferInitDescriptor, DeviceExt, DrawIndexedIndirectArgs, DrawIndirectArgs},
+    BindGroup, BlendState, Buffer, BufferAddress, BufferUsages, Color, ColorTargetState,
+    ColorWrites, CommandBuffer, CommandEncoderDescriptor, CompareFunction, DepthBiasState,
+    DepthStencilState, Device, Face, FragmentState, Limits, LoadOp, MultisampleState, Operations,
+    PipelineCompilationOptions, PipelineLayoutDescriptor, PrimitiveState, RenderPass,
     RenderPassColorAttachment, RenderPassDepthStencilAttachment, RenderPassDescriptor,
-    RenderPipelineDescriptor, StencilState, StoreOp, VertexBufferLayout, VertexFormat, VertexState,
-    VertexStepMode,
+    RenderPipeline, RenderPipelineDescriptor, ShaderModule, StencilState, StoreOp, Texture,
+    TextureDescriptor, TextureUsages, TextureView, VertexFormat, VertexState,
 };
 
 use crate::{
     material::{PbrMaterial, PbrMaterialUniform},
-    node::{shadow_mapping::SHADOW_MAPPING, DEPTH_PREPASS_TEXTURE, ENV_MAPPING, SSAO},
+    node::{shadow_mapping::SHADOW_MAPPING, DepthLoadOp, DEPTH_PREPASS_TEXTURE, ENV_MAPPING, SSAO},
     shader_defs::{PbrDiffuse, PbrSpecular},
     texture,
 };
@@ -37,9 +46,320 @@ pub const TONY_MC_MAPFACE_LUT: TextureId =
 bitflags::bitflags! {
     #[derive(Default)]
     pub struct PbrNodeConfig: u32 {
+        /// Sample the maps of `ShadowMappingNode`. Without it lights are unshadowed, and the
+        /// shadow node can be left out of the flow.
         const SHADOW_MAPPING = 1 << 0;
         const ENVIRONMENT_MAPPING = 1 << 1;
         const SSAO = 1 << 2;
+        /// Draw opaque and masked meshes sharing a pipeline and a material together, from
+        /// indirect arguments culling writes, if the adapter supports indirect execution.
+        const INDIRECT_DRAW = 1 << 3;
+    }
+}
+
+/// Combinations of optional vertex attributes and vertex displacements, repeated for each
+/// alpha mode, plus masks using alpha-to-coverage.
+const PBR_ATTRIBUTE_VARIANTS: usize = 16;
+const VERTEX_COLORS_VARIANT: usize = 1 << 0;
+const TEX_COORDS_1_VARIANT: usize = 1 << 1;
+const VAT_VARIANT: usize = 1 << 2;
+const WIND_VARIANT: usize = 1 << 3;
+
+/// Index of the shader variant matching the optional attributes of the mesh and the material.
+fn shader_variant(mesh: &Mesh, material: Option<&dyn Material>, multisampled: bool) -> usize {
+    let mut variant = 0;
+    if mesh.attribute(Mesh::COLOR_ATTR).is_some() {
+        variant |= VERTEX_COLORS_VARIANT;
+    }
+    if mesh.attribute(Mesh::TEX_COORDS_1_ATTR).is_some() {
+        variant |= TEX_COORDS_1_VARIANT;
+    }
+    let displacement = material.map_or(VertexDisplacement::empty(), |m| m.vertex_displacement());
+    if displacement.contains(VertexDisplacement::ANIMATION_TEXTURE) {
+        variant |= VAT_VARIANT;
+    }
+    if displacement.contains(VertexDisplacement::WIND) {
+        variant |= WIND_VARIANT;
+    }
+    variant
+        + PBR_ATTRIBUTE_VARIANTS
+            * match material.map_or(AlphaMode::Opaque, |m| m.alpha_mode()) {
+                AlphaMode::Opaque => 0,
+                AlphaMode::Mask(_)
+                    if multisampled && material.is_some_and(|m| m.alpha_to_coverage()) =>
+                {
+                    3
+                }
+                AlphaMode::Mask(_) => 1,
+                AlphaMode::Blend => 2,
+            }
+}
+
+/// Compiled once for each variant used, see [`PbrNode::shaders`].
+const PBR_SHADER: (&[&str], &str) = (
+    &[
+        include_str!("../shader/math.wgsl"),
+        include_str!("../shader/hash.wgsl"),
+        include_str!("../shader/common/common_type.wgsl"),
+        include_str!("../shader/common/common_binding.wgsl"),
+        include_str!("../shader/shadow/shadow_type.wgsl"),
+        include_str!("../shader/shadow/shadow_mapping.wgsl"),
+        include_str!("../shader/post_processing/ssao.wgsl"),
+        include_str!("../shader/pbr/pbr_type.wgsl"),
+        include_str!("../shader/pbr/pbr_binding.wgsl"),
+        include_str!("../shader/pbr/pbr_function.wgsl"),
+        include_str!("../shader/env_mapping/env_mapping_type.wgsl"),
+        include_str!("../shader/env_mapping/env_mapping_binding.wgsl"),
+        include_str!("../shader/env_mapping/env_mapping.wgsl"),
+        include_str!("../shader/pbr/pbr.wgsl"),
+    ],
+    include_str!("../shader/pbr/pbr.wgsl"),
+);
+
+/// Shader defs of a variant returned by [`shader_variant`].
+fn variant_shader_defs(variant: usize) -> Option<Vec<(String, ShaderDefValue)>> {
+    let attributes = variant % PBR_ATTRIBUTE_VARIANTS;
+    let defs = [
+        (VERTEX_COLORS_VARIANT, "VERTEX_COLORS"),
+        (TEX_COORDS_1_VARIANT, "TEX_COORDS_1"),
+        (VAT_VARIANT, "VAT"),
+        (WIND_VARIANT, "WIND"),
+    ]
+    .into_iter()
+    .filter(|(bit, _)| attributes & bit != 0)
+    .map(|(_, def)| def)
+    .chain(
+        match variant / PBR_ATTRIBUTE_VARIANTS {
+            1 => &["ALPHA_MASK"][..],
+            2 => &["ALPHA_BLEND"],
+            3 => &["ALPHA_MASK", "ALPHA_TO_COVERAGE"],
+            _ => &[],
+        }
+        .iter()
+        .copied(),
+    )
+    .map(|def| (def.to_string(), Default::default()))
+    .collect::<Vec<_>>();
+    (!defs.is_empty()).then_some(defs)
+}
+
+/// Material the mesh is drawn with, taking the material override into account.
+pub(crate) fn mesh_material<'a>(
+    scene: &'a Scene,
+    material_override: Option<&'a dyn Material>,
+    mesh: &StaticMesh,
+) -> Option<&'a dyn Material> {
+    material_override.or_else(|| scene.materials.get(&mesh.material).map(|m| m.as_ref()))
+}
+
+/// Material the instanced mesh is drawn with, taking the material override into account.
+fn instanced_material<'a>(
+    scene: &'a Scene,
+    material_override: Option<&'a dyn Material>,
+    mesh: &InstancedMesh,
+) -> Option<&'a dyn Material> {
+    material_override.or_else(|| scene.materials.get(&mesh.material).map(|m| m.as_ref()))
+}
+
+/// Alpha mode the mesh is drawn with, taking the material override into account.
+pub(crate) fn mesh_alpha_mode(
+    scene: &Scene,
+    material_override: Option<&dyn Material>,
+    mesh: &StaticMesh,
+) -> AlphaMode {
+    mesh_material(scene, material_override, mesh)
+        .map(|material| material.alpha_mode())
+        .unwrap_or_default()
+}
+
+/// Everything a pbr pipeline depends on, besides the node config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PbrPipelineKey {
+    pub mesh: MeshInstanceId,
+    /// Index of the shader variant, covering optional attributes, vertex displacements and
+    /// the alpha mode.
+    pub variant: usize,
+    pub double_sided: bool,
+    /// Drawn from an [`InstancedMesh`], with a model matrix for each instance.
+    pub instanced: bool,
+}
+
+impl PbrPipelineKey {
+    /// `multisampled` is whether the pass uses MSAA, needed for alpha-to-coverage.
+    pub fn new(
+        id: MeshInstanceId,
+        mesh: &Mesh,
+        material: Option<&dyn Material>,
+        multisampled: bool,
+    ) -> Self {
+        Self {
+            mesh: id,
+            variant: shader_variant(mesh, material, multisampled),
+            double_sided: material.is_some_and(|m| m.double_sided()),
+            instanced: false,
+        }
+    }
+
+    #[inline]
+    pub fn is_blended(&self) -> bool {
+        self.variant / PBR_ATTRIBUTE_VARIANTS == 2
+    }
+
+    /// Whether the masked cutout is antialiased, see [`Material::alpha_to_coverage`].
+    #[inline]
+    pub fn alpha_to_coverage(&self) -> bool {
+        self.variant / PBR_ATTRIBUTE_VARIANTS == 3
+    }
+
+    /// Whether [`DepthPrepassNode`](crate::node::DepthPrepassNode) wrote the same depth, as it
+    /// only draws opaque static meshes, without vertex displacements.
+    #[inline]
+    pub fn matches_depth_prepass(&self) -> bool {
+        !self.instanced
+            && self.variant < PBR_ATTRIBUTE_VARIANTS
+            && self.variant & (VAT_VARIANT | WIND_VARIANT) == 0
+    }
+
+    #[inline]
+    pub fn cull_mode(&self) -> Option<Face> {
+        (!self.double_sided).then_some(Face::Back)
+    }
+}
+
+/// Meshes sharing a pipeline and a material, drawn from their own buffers with arguments
+/// from a shared indirect buffer, so state is only set once and culling writes the arguments.
+///
+/// See [`PbrNodeConfig::INDIRECT_DRAW`].
+pub struct PbrDrawBatch {
+    /// Pipeline of the first mesh, the others only differ by their mesh id.
+    pub key: PbrPipelineKey,
+    pub material: MaterialInstanceId,
+    pub meshes: Vec<StaticMesh>,
+    /// One draw for each mesh, culled ones have no instance. Meshes without indices draw
+    /// `index_count` vertices instead.
+    pub args: Vec<DrawIndexedIndirectArgs>,
+    /// Whether each mesh has indices, others read [`DrawIndirectArgs`] from their slot.
+    pub indexed: Vec<bool>,
+    /// [`PbrDrawBatch::ARGS_STRIDE`] bytes for each mesh.
+    pub indirect_buffer: Buffer,
+    /// Material uniform offset, `None` if no mesh of the batch is in view.
+    pub offset: Option<u32>,
+}
+
+impl PbrDrawBatch {
+    pub const ARGS_STRIDE: BufferAddress = std::mem::size_of::<DrawIndexedIndirectArgs>() as _;
+
+    /// Draw each of `meshes` in full, from offset 0 of its vertex and index buffers.
+    fn new(
+        device: &Device,
+        key: PbrPipelineKey,
+        material: MaterialInstanceId,
+        meshes: Vec<StaticMesh>,
+        instances: &HashMap<MeshInstanceId, Mesh>,
+    ) -> Self {
+        let (args, indexed): (Vec<_>, Vec<_>) = meshes
+            .iter()
+            .map(|mesh| {
+                let instance = &instances[&mesh.mesh];
+                let index_count = match instance.indices() {
+                    Some(MeshIndices::UInt16(i)) => i.len(),
+                    Some(MeshIndices::UInt32(i)) => i.len(),
+                    None => instance.vertices_count(),
+                };
+                let args = DrawIndexedIndirectArgs {
+                    index_count: index_count as u32,
+                    instance_count: 1,
+                    first_index: 0,
+                    base_vertex: 0,
+                    first_instance: 0,
+                };
+                (args, instance.indices().is_some())
+            })
+            .unzip();
+
+        Self {
+            key,
+            material,
+            meshes,
+            indirect_buffer: device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("pbr_batch_indirect"),
+                contents: &Self::args_bytes(&args, &indexed),
+                usage: BufferUsages::INDIRECT | BufferUsages::COPY_DST,
+            }),
+            args,
+            indexed,
+            offset: None,
+        }
+    }
+
+    /// Contents of [`PbrDrawBatch::indirect_buffer`].
+    fn args_bytes(args: &[DrawIndexedIndirectArgs], indexed: &[bool]) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(args.len() * Self::ARGS_STRIDE as usize);
+        for (args, indexed) in args.iter().zip(indexed) {
+            if *indexed {
+                bytes.extend_from_slice(args.as_bytes());
+            } else {
+                let args = DrawIndirectArgs {
+                    vertex_count: args.index_count,
+                    instance_count: args.instance_count,
+                    first_vertex: 0,
+                    first_instance: 0,
+                };
+                bytes.extend_from_slice(args.as_bytes());
+                bytes.resize(bytes.len().next_multiple_of(Self::ARGS_STRIDE as usize), 0);
+            }
+        }
+        bytes
+    }
+}
+
+/// An [`InstancedMesh`] drawn with a single call.
+pub struct PbrInstancedDraw {
+    pub key: PbrPipelineKey,
+    pub material: MaterialInstanceId,
+    /// Model matrices, see [`InstancedMesh::create_instance_buffer`].
+    pub instance_buffer: Buffer,
+    pub instance_count: u32,
+    /// Material uniform offset.
+    pub offset: u32,
+}
+
+pub struct PbrMsaaTargets {
+    pub color: Texture,
+    pub color_view: TextureView,
+    pub depth: Texture,
+    pub depth_view: TextureView,
+}
+
+impl PbrMsaaTargets {
+    /// Multisampled targets matching the swap chain, `None` if MSAA is disabled.
+    pub fn new(device: &Device, targets: &RenderTargets) -> Option<Self> {
+        (targets.sample_count > 1).then(|| {
+            let desc = targets.swap_chain.desc();
+            let color = device.create_texture(&TextureDescriptor {
+                label: Some("pbr_msaa_color"),
+                sample_count: targets.sample_count,
+                format: targets.color_format,
+                usage: TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[],
+                ..desc.clone()
+            });
+            let depth = device.create_texture(&TextureDescriptor {
+                label: Some("pbr_msaa_depth"),
+                sample_count: targets.sample_count,
+                format: targets.depth_format.unwrap(),
+                usage: TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[],
+                ..desc.clone()
+            });
+
+            PbrMsaaTargets {
+                color_view: color.create_view(&Default::default()),
+                color,
+                depth_view: depth.create_view(&Default::default()),
+                depth,
+            }
+        })
     }
 }
 
@@ -53,6 +373,103 @@ pub struct PbrNode {
     pub shadow_mapping_index: u32,
     pub env_mapping_index: u32,
     pub ssao_index: u32,
+    /// Multisampled color and depth, only allocated when `sample_count > 1`.
+    ///
+    /// The depth prepass is single sampled, so depth is cleared and redrawn in this pass.
+    /// Anything drawn to the main color before this node is overwritten by the resolve.
+    pub msaa: Option<PbrMsaaTargets>,
+    /// With [`DepthLoadOp::Load`], meshes the depth prepass drew are only shaded where they
+    /// are visible, testing for equal depth. Ignored with msaa, which always clears.
+    pub depth_load_op: DepthLoadOp,
+    /// Pipelines for each mesh, derived from the material it's drawn with.
+    pub pipelines: HashMap<PbrPipelineKey, RenderPipeline>,
+    pub mesh_centers: HashMap<MeshInstanceId, Vec3>,
+    /// Mesh indices and their pipelines, opaque ones first, then blended ones back to front.
+    /// Meshes in [`PbrNode::batches`] are left out, unless moved by their transform.
+    pub draw_order: Vec<(usize, PbrPipelineKey)>,
+    /// Only filled with [`PbrNodeConfig::INDIRECT_DRAW`].
+    pub batches: Vec<PbrDrawBatch>,
+    pub batched: HashSet<(MeshInstanceId, MaterialInstanceId)>,
+    /// One for each of [`GpuScene::instanced_meshes`] with transforms. Opaque and masked ones
+    /// are drawn before other meshes, blended ones after them, without sorting.
+    pub instanced_draws: Vec<PbrInstancedDraw>,
+    /// Models of the node meshes, batches and instanced draws use the identity.
+    pub models: Option<ModelUniforms>,
+    /// Shader variants by [`PbrPipelineKey::variant`], compiled the first time a mesh needs
+    /// them.
+    pub shaders: HashMap<usize, ShaderModule>,
+    /// Shader defs of the flow `shaders` were compiled with, they're recompiled when these
+    /// change.
+    pub shader_defs: HashMap<String, ShaderDefValue>,
+}
+
+impl PbrNode {
+    /// Shader of `variant`, compiled if no mesh used it yet.
+    fn variant_shader<'a>(
+        shaders: &'a mut HashMap<usize, ShaderModule>,
+        device: &Device,
+        node: &NodeContext,
+        variant: usize,
+    ) -> &'a ShaderModule {
+        shaders.entry(variant).or_insert_with(|| {
+            node.compile_shader(device, "PbrNode", PBR_SHADER, variant_shader_defs(variant))
+        })
+    }
+
+    /// Pipeline of an instanced mesh, `None` if its material is drawn by another node.
+    fn instanced_key(
+        &self,
+        id: MeshInstanceId,
+        mesh: &Mesh,
+        material: Option<&dyn Material>,
+        multisampled: bool,
+    ) -> Option<PbrPipelineKey> {
+        material
+            .is_none_or(|m| m.id() == self.mat_uuid)
+            .then(|| PbrPipelineKey {
+                instanced: true,
+                ..PbrPipelineKey::new(id, mesh, material, multisampled)
+            })
+    }
+
+    /// Draw the blended or the other [`PbrNode::instanced_draws`], each with a single call.
+    fn draw_instanced(
+        &self,
+        pass: &mut RenderPass,
+        assets: &GpuAssets,
+        b_model: &BindGroup,
+        blended: bool,
+    ) {
+        let draws = self
+            .instanced_draws
+            .iter()
+            .filter(|draw| draw.key.is_blended() == blended);
+        for draw in draws {
+            let (Some(b_material), Some(instance), Some(pipeline)) = (
+                assets.material_bind_groups.get(&draw.material),
+                assets.gpu_meshes.get(&draw.key.mesh),
+                self.pipelines.get(&draw.key),
+            ) else {
+                continue;
+            };
+
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(2, b_material, &[draw.offset]);
+            pass.set_bind_group(3, b_model, &[ModelUniforms::IDENTITY_OFFSET]);
+            instance.set_vertex_buffers(pass);
+            pass.set_vertex_buffer(
+                instance.vertex_ranges.len() as u32,
+                draw.instance_buffer.slice(..),
+            );
+            let instances = 0..draw.instance_count;
+            if let Some(indices) = &instance.index_buffer {
+                pass.set_index_buffer(indices.buffer.slice(..), indices.format);
+                pass.draw_indexed(0..indices.count, 0, instances);
+            } else {
+                pass.draw(0..instance.vertices_count, instances);
+            }
+        }
+    }
 }
 
 impl RenderNode for PbrNode {
@@ -66,19 +483,24 @@ impl RenderNode for PbrNode {
     }
 
     fn require_renderer_limits(&self, limits: &mut Limits) {
-        limits.max_bind_groups = limits.max_bind_groups.max(6);
+        limits.max_bind_groups = limits.max_bind_groups.max(7);
     }
 
     fn require_shader_defs(&self, shader_defs: &mut HashMap<String, ShaderDefValue>) {
         shader_defs.extend([self.diffuse.to_def(), self.specular.to_def()]);
 
-        let mut bind_groups = 3;
+        let mut bind_groups = 4;
         if self.node_cfg.contains(PbrNodeConfig::SHADOW_MAPPING) {
             shader_defs.insert(
                 "SHADOW_MAPPING".to_string(),
                 ShaderDefValue::UInt(bind_groups),
             );
             bind_groups += 1;
+            // Set by `ShadowMappingNode`, keeps the shader composing without one so `build`
+            // can point out it's missing.
+            shader_defs
+                .entry("SHADOW_CASCADES".to_string())
+                .or_insert(ShaderDefValue::UInt(1));
         }
         if self.node_cfg.contains(PbrNodeConfig::SSAO) {
             shader_defs.insert("SSAO".to_string(), ShaderDefValue::UInt(bind_groups));
@@ -92,46 +514,37 @@ impl RenderNode for PbrNode {
         }
     }
 
-    fn require_shaders(&self) -> Option<&'static [(&'static [&'static str], &'static str)]> {
-        Some(&[(
-            &[
-                include_str!("../shader/math.wgsl"),
-                include_str!("../shader/hash.wgsl"),
-                include_str!("../shader/common/common_type.wgsl"),
-                include_str!("../shader/common/common_binding.wgsl"),
-                include_str!("../shader/shadow/shadow_type.wgsl"),
-                include_str!("../shader/shadow/shadow_mapping.wgsl"),
-                include_str!("../shader/post_processing/ssao.wgsl"),
-                include_str!("../shader/pbr/pbr_type.wgsl"),
-                include_str!("../shader/pbr/pbr_binding.wgsl"),
-                include_str!("../shader/pbr/pbr_function.wgsl"),
-                include_str!("../shader/env_mapping/env_mapping_type.wgsl"),
-                include_str!("../shader/env_mapping/env_mapping_binding.wgsl"),
-                include_str!("../shader/env_mapping/env_mapping.wgsl"),
-                include_str!("../shader/pbr/pbr.wgsl"),
-            ],
-            include_str!("../shader/pbr/pbr.wgsl"),
-        )])
-    }
-
     fn build(
         &mut self,
-        GpuScene { assets, .. }: &mut GpuScene,
+        GpuScene {
+            original,
+            assets,
+            instanced_meshes,
+            ..
+        }: &mut GpuScene,
         RenderContext {
             device,
             queue,
             node,
             targets,
-            ..
+            material_override,
         }: RenderContext,
     ) {
         assets.textures.insert(
             TONY_MC_MAPFACE_LUT,
-            texture::load_dds_texture(device, queue, "chest/assets/luts/tony_mc_mapface.dds"),
+            texture::load_dds_texture(
+                device,
+                queue,
+                concat!(
+                    env!("CARGO_MANIFEST_DIR"),
+                    "/assets/luts/tony_mc_mapface.dds"
+                ),
+            ),
         );
         self.mat_uuid = MaterialTypeId(TypeId::of::<PbrMaterial>().to_uuid());
 
         PbrMaterial::create_layout(device, assets);
+        let models = &*self.models.insert(ModelUniforms::new(device));
 
         let (l_camera, l_lights, l_material) = (
             assets.common_layout.as_ref().unwrap(),
@@ -139,18 +552,40 @@ impl RenderNode for PbrNode {
             &assets.material_layouts[&MaterialTypeId(TypeId::of::<PbrMaterial>().to_uuid())],
         );
 
-        let mut bind_group_layouts = vec![l_camera, l_lights, l_material];
+        // Nothing is drawn without the layouts of the enabled features.
+        self.pipelines.clear();
+        self.batches.clear();
+        self.batched.clear();
+        let mut bind_group_layouts = vec![l_camera, l_lights, l_material, &models.layout];
         if self.node_cfg.contains(PbrNodeConfig::SHADOW_MAPPING) {
+            let Some(layout) = assets.required_layout(
+                &SHADOW_MAPPING.shadow_maps_layout,
+                "shadow_maps_layout",
+                "ShadowMappingNode",
+            ) else {
+                return;
+            };
             self.shadow_mapping_index = bind_group_layouts.len() as u32;
-            bind_group_layouts.push(&assets.extra_layouts[&SHADOW_MAPPING.shadow_maps_layout]);
+            bind_group_layouts.push(layout);
         }
         if self.node_cfg.contains(PbrNodeConfig::SSAO) {
+            let Some(layout) = assets.required_layout(&SSAO.ssao_layout, "ssao_layout", "SsaoNode")
+            else {
+                return;
+            };
             self.ssao_index = bind_group_layouts.len() as u32;
-            bind_group_layouts.push(&assets.extra_layouts[&SSAO.ssao_layout]);
+            bind_group_layouts.push(layout);
         }
         if self.node_cfg.contains(PbrNodeConfig::ENVIRONMENT_MAPPING) {
+            let Some(layout) = assets.required_layout(
+                &ENV_MAPPING.env_mapping_layout,
+                "env_mapping_layout",
+                "EnvironmentMappingNode",
+            ) else {
+                return;
+            };
             self.env_mapping_index = bind_group_layouts.len() as u32;
-            bind_group_layouts.push(&assets.extra_layouts[&ENV_MAPPING.env_mapping_layout]);
+            bind_group_layouts.push(layout);
         }
 
         let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
@@ -159,48 +594,170 @@ impl RenderNode for PbrNode {
             push_constant_ranges: &[],
         });
 
-        for mesh in &node.meshes {
-            let instance = &assets.meshes[&mesh.mesh.mesh];
-            let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+        if self.shader_defs != node.shader_defs {
+            self.shaders.clear();
+            self.shader_defs = node.shader_defs.clone();
+        }
+
+        let depth_load_op = self.depth_load_op;
+        let create_pipeline = |key: &PbrPipelineKey, mesh: &Mesh, shader: &ShaderModule| {
+            let blend = key.is_blended();
+            let depth_compare = match depth_load_op {
+                DepthLoadOp::Load if targets.sample_count == 1 && key.matches_depth_prepass() => {
+                    CompareFunction::Equal
+                }
+                _ => CompareFunction::LessEqual,
+            };
+            let vertex_layouts = mesh.vertex_buffer_layouts();
+            let mut buffers = vertex_layouts
+                .iter()
+                .map(MeshVertexBufferLayout::layout)
+                .collect::<Vec<_>>();
+            if key.instanced {
+                buffers.push(InstancedMesh::instance_buffer_layout());
+            }
+            device.create_render_pipeline(&RenderPipelineDescriptor {
                 label: Some("pbr_pipeline"),
                 layout: Some(&layout),
-                cache: None,
+                cache: node.pipeline_cache.as_deref(),
                 vertex: VertexState {
-                    module: &node.shaders[0],
-                    entry_point: "vertex",
+                    module: shader,
+                    entry_point: match key.instanced {
+                        true => "vertex_instanced",
+                        false => "vertex",
+                    },
                     compilation_options: PipelineCompilationOptions::default(),
-                    buffers: &[VertexBufferLayout {
-                        array_stride: instance.vertex_stride(),
-                        step_mode: VertexStepMode::Vertex,
-                        attributes: &instance.vertex_attributes(),
-                    }],
+                    buffers: &buffers,
+                },
+                multisample: MultisampleState {
+                    count: targets.sample_count,
+                    alpha_to_coverage_enabled: key.alpha_to_coverage(),
+                    ..Default::default()
                 },
-                multisample: MultisampleState::default(),
                 fragment: Some(FragmentState {
-                    module: &node.shaders[0],
+                    module: shader,
                     entry_point: "fragment",
                     compilation_options: PipelineCompilationOptions::default(),
                     targets: &[Some(ColorTargetState {
                         format: targets.color_format,
-                        blend: None,
+                        blend: blend.then_some(BlendState::ALPHA_BLENDING),
                         write_mask: ColorWrites::ALL,
                     })],
                 }),
                 depth_stencil: Some(DepthStencilState {
                     format: targets.depth_format.unwrap(),
-                    depth_write_enabled: true,
-                    depth_compare: CompareFunction::LessEqual,
+                    depth_write_enabled: !blend,
+                    depth_compare,
                     stencil: StencilState::default(),
                     bias: DepthBiasState::default(),
                 }),
                 primitive: PrimitiveState {
-                    cull_mode: Some(Face::Back),
+                    cull_mode: key.cull_mode(),
                     ..Default::default()
                 },
-                multiview: None,
+                multiview: node.multiview,
+            })
+        };
+
+        self.mesh_centers.clear();
+        for mesh in &node.meshes {
+            let material = mesh_material(original, material_override, &mesh.mesh);
+            // Meshes with other kinds of materials are drawn by their own nodes.
+            if material.is_some_and(|m| m.id() != self.mat_uuid) {
+                continue;
+            }
+
+            let instance = &assets.meshes[&mesh.mesh.mesh];
+            let key =
+                PbrPipelineKey::new(mesh.mesh.mesh, instance, material, targets.sample_count > 1);
+            let shader = Self::variant_shader(&mut self.shaders, device, node, key.variant);
+            self.pipelines
+                .insert(key, create_pipeline(&key, instance, shader));
+            if let Some(aabb) = instance.aabb() {
+                self.mesh_centers.insert(mesh.mesh.mesh, aabb.center());
+            }
+        }
+
+        for mesh in instanced_meshes.iter() {
+            let instance = &assets.meshes[&mesh.mesh];
+            let material = instanced_material(original, material_override, mesh);
+            let Some(key) =
+                self.instanced_key(mesh.mesh, instance, material, targets.sample_count > 1)
+            else {
+                continue;
+            };
+            self.pipelines.entry(key).or_insert_with(|| {
+                let shader = Self::variant_shader(&mut self.shaders, device, node, key.variant);
+                create_pipeline(&key, instance, shader)
             });
-            node.pipelines.insert(mesh.mesh.mesh, pipeline);
         }
+
+        if self.node_cfg.contains(PbrNodeConfig::INDIRECT_DRAW) && node.indirect_execution {
+            let mut groups = Vec::<(PbrPipelineKey, MaterialInstanceId, Vec<StaticMesh>)>::new();
+            let mut group_indices = HashMap::new();
+            for mesh in &node.meshes {
+                let material = mesh_material(original, material_override, &mesh.mesh);
+                if material.is_some_and(|m| m.id() != self.mat_uuid) {
+                    continue;
+                }
+
+                let instance = &assets.meshes[&mesh.mesh.mesh];
+                let key = PbrPipelineKey::new(
+                    mesh.mesh.mesh,
+                    instance,
+                    material,
+                    targets.sample_count > 1,
+                );
+                // Blended meshes are sorted every frame.
+                if key.is_blended() {
+                    continue;
+                }
+
+                let material_id = match material_override {
+                    Some(_) => MATERIAL_OVERRIDE,
+                    None => mesh.mesh.material,
+                };
+                let group = (
+                    key.variant,
+                    key.double_sided,
+                    material_id,
+                    instance.vertex_attributes(),
+                    instance.vertex_stride(),
+                    instance.buffer_layout(),
+                );
+                let index = *group_indices.entry(group).or_insert_with(|| {
+                    groups.push((key, material_id, Vec::new()));
+                    groups.len() - 1
+                });
+                groups[index].2.push(mesh.mesh);
+            }
+
+            // A single mesh gains nothing from being drawn indirectly.
+            for (key, material, meshes) in groups.into_iter().filter(|(_, _, m)| m.len() > 1) {
+                self.batched
+                    .extend(meshes.iter().map(|mesh| (mesh.mesh, mesh.material)));
+                self.batches.push(PbrDrawBatch::new(
+                    device,
+                    key,
+                    material,
+                    meshes,
+                    &assets.meshes,
+                ));
+            }
+        }
+
+        self.msaa = PbrMsaaTargets::new(device, targets);
+    }
+
+    fn resize(
+        &mut self,
+        _scene: &mut GpuScene,
+        RenderContext {
+            device, targets, ..
+        }: RenderContext,
+        _new_size: UVec2,
+    ) {
+        self.msaa = PbrMsaaTargets::new(device, targets);
     }
 
     fn prepare(
@@ -210,7 +767,8 @@ impl RenderNode for PbrNode {
             device,
             queue,
             node,
-            ..
+            targets,
+            material_override,
         }: RenderContext,
     ) {
         match scene.assets.material_uniforms.entry(self.mat_uuid) {
@@ -220,18 +778,75 @@ impl RenderNode for PbrNode {
             }
         }
 
-        node.meshes
-            .iter_mut()
-            .filter_map(|rm| {
-                scene
-                    .original
-                    .materials
-                    .get(&rm.mesh.material)
-                    .map(|m| (m, rm))
-            })
-            .for_each(|(material, mesh)| {
-                mesh.offset = Some(material.prepare(device, &mut scene.assets));
+        if let Some(material) = material_override {
+            let offset = material.prepare(device, &mut scene.assets);
+            node.meshes
+                .iter_mut()
+                .for_each(|mesh| mesh.offset = Some(offset));
+        } else {
+            node.meshes
+                .iter_mut()
+                .filter_map(|rm| {
+                    scene
+                        .original
+                        .materials
+                        .get(&rm.mesh.material)
+                        .filter(|m| m.id() == self.mat_uuid)
+                        .map(|m| (m, rm))
+                })
+                .for_each(|(material, mesh)| {
+                    mesh.offset = Some(material.prepare(device, &mut scene.assets));
+                });
+        }
+
+        if let Some(models) = &mut self.models {
+            models.prepare(device, queue, &mut node.meshes);
+        }
+
+        // Buffers are reused by meshes keeping the same number of instances.
+        let mut instance_buffers = std::mem::take(&mut self.instanced_draws)
+            .into_iter()
+            .map(|draw| (draw.instance_count, draw.instance_buffer))
+            .collect::<Vec<_>>();
+        for mesh in &scene.instanced_meshes {
+            let material = instanced_material(&scene.original, material_override, mesh);
+            let (Some(material), Some(key)) = (
+                material,
+                self.instanced_key(
+                    mesh.mesh,
+                    &scene.assets.meshes[&mesh.mesh],
+                    material,
+                    targets.sample_count > 1,
+                ),
+            ) else {
+                continue;
+            };
+            let instance_count = mesh.transforms.len() as u32;
+            let instance_buffer = match instance_buffers
+                .iter()
+                .position(|(count, _)| *count == instance_count)
+            {
+                Some(index) => {
+                    let (_, buffer) = instance_buffers.swap_remove(index);
+                    queue.write_buffer(&buffer, 0, bytemuck::cast_slice(&mesh.model_matrices()));
+                    buffer
+                }
+                None => match mesh.create_instance_buffer(device) {
+                    Some(buffer) => buffer,
+                    None => continue,
+                },
+            };
+            self.instanced_draws.push(PbrInstancedDraw {
+                key,
+                material: match material_override {
+                    Some(_) => MATERIAL_OVERRIDE,
+                    None => mesh.material,
+                },
+                instance_buffer,
+                instance_count,
+                offset: material.prepare(device, &mut scene.assets),
             });
+        }
 
         scene
             .assets
@@ -240,72 +855,186 @@ impl RenderNode for PbrNode {
             .unwrap()
             .write::<PbrMaterialUniform>(&device, &queue);
 
-        node.meshes
-            .iter_mut()
-            .filter_map(|rm| {
-                scene
-                    .original
-                    .materials
-                    .get(&rm.mesh.material)
-                    .map(|m| (m, rm))
-            })
-            .for_each(|(material, mesh)| {
-                material.create_bind_group(device, &mut scene.assets, mesh.mesh.material);
-            });
+        if let Some(material) = material_override {
+            material.create_bind_group(device, &mut scene.assets, MATERIAL_OVERRIDE);
+        } else {
+            node.meshes
+                .iter_mut()
+                .filter_map(|rm| {
+                    scene
+                        .original
+                        .materials
+                        .get(&rm.mesh.material)
+                        .filter(|m| m.id() == self.mat_uuid)
+                        .map(|m| (m, rm))
+                })
+                .for_each(|(material, mesh)| {
+                    material.create_bind_group(device, &mut scene.assets, mesh.mesh.material);
+                });
+            for draw in &self.instanced_draws {
+                scene.original.materials[&draw.material].create_bind_group(
+                    device,
+                    &mut scene.assets,
+                    draw.material,
+                );
+            }
+        }
+
+        let camera = scene.original.camera.transform.translation;
+        let mut blended = Vec::new();
+        self.draw_order.clear();
+        for (index, mesh) in node.meshes.iter().enumerate() {
+            let material = mesh_material(&scene.original, material_override, &mesh.mesh);
+            if material.is_some_and(|m| m.id() != self.mat_uuid)
+                || (self.batched.contains(&(mesh.mesh.mesh, mesh.mesh.material))
+                    && mesh.mesh.transform == Transform::default())
+            {
+                continue;
+            }
+
+            let key = PbrPipelineKey::new(
+                mesh.mesh.mesh,
+                &scene.assets.meshes[&mesh.mesh.mesh],
+                material,
+                targets.sample_count > 1,
+            );
+            if key.is_blended() {
+                let distance = self.mesh_centers.get(&mesh.mesh.mesh).map_or(0., |center| {
+                    let center = mesh.mesh.transform.model_matrix().transform_point3(*center);
+                    center.distance_squared(camera)
+                });
+                blended.push((index, key, distance));
+            } else {
+                self.draw_order.push((index, key));
+            }
+        }
+        blended.sort_by(|(_, _, a), (_, _, b)| b.total_cmp(a));
+        self.draw_order
+            .extend(blended.into_iter().map(|(index, key, _)| (index, key)));
+
+        if self.batches.is_empty() {
+            return;
+        }
+        // Culled meshes stay in the batch, but without an instance. Batches are drawn with the
+        // identity model, so moved meshes are drawn on their own instead.
+        let visible = node
+            .meshes
+            .iter()
+            .filter(|mesh| mesh.mesh.transform == Transform::default())
+            .map(|mesh| ((mesh.mesh.mesh, mesh.mesh.material), mesh.offset))
+            .collect::<HashMap<_, _>>();
+        for batch in &mut self.batches {
+            batch.offset = None;
+            for (mesh, args) in batch.meshes.iter().zip(&mut batch.args) {
+                let offset = visible.get(&(mesh.mesh, mesh.material));
+                args.instance_count = offset.is_some() as u32;
+                batch.offset = batch.offset.or(offset.copied().flatten());
+            }
+            let args = PbrDrawBatch::args_bytes(&batch.args, &batch.indexed);
+            queue.write_buffer(&batch.indirect_buffer, 0, &args);
+        }
+    }
+
+    fn records(&self) -> bool {
+        true
+    }
+
+    fn supports_multiview(&self) -> bool {
+        true
     }
 
-    fn draw(
+    fn record(
         &self,
-        GpuScene { assets, .. }: &mut GpuScene,
+        GpuScene { assets, .. }: &GpuScene,
         RenderContext {
             device,
-            queue,
             node,
             targets,
+            material_override,
+            ..
         }: RenderContext,
-    ) {
+    ) -> Option<CommandBuffer> {
         let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor::default());
 
-        let (Some(b_camera), Some(b_lights)) =
-            (&assets.common_bind_group, &assets.light_bind_group)
-        else {
-            return;
+        let (Some(b_camera), Some(b_lights), Some(b_model)) = (
+            &assets.common_bind_group,
+            &assets.light_bind_group,
+            self.models.as_ref().and_then(|m| m.bind_group.as_ref()),
+        ) else {
+            return None;
         };
 
-        let b_shadow_maps = self
-            .node_cfg
-            .contains(PbrNodeConfig::SHADOW_MAPPING)
-            .then(|| &assets.extra_bind_groups[&SHADOW_MAPPING.shadow_maps_bind_group]);
+        let b_shadow_maps = match self.node_cfg.contains(PbrNodeConfig::SHADOW_MAPPING) {
+            true => Some(assets.required_bind_group(
+                &SHADOW_MAPPING.shadow_maps_bind_group,
+                "shadow_maps_bind_group",
+                "ShadowMappingNode",
+            )?),
+            false => None,
+        };
 
-        let b_env_mapping = self
-            .node_cfg
-            .contains(PbrNodeConfig::ENVIRONMENT_MAPPING)
-            .then(|| &assets.extra_bind_groups[&ENV_MAPPING.env_mapping_bind_group]);
+        let b_env_mapping = match self.node_cfg.contains(PbrNodeConfig::ENVIRONMENT_MAPPING) {
+            true => Some(assets.required_bind_group(
+                &ENV_MAPPING.env_mapping_bind_group,
+                "env_mapping_bind_group",
+                "EnvironmentMappingNode",
+            )?),
+            false => None,
+        };
 
-        let b_ssao = self
-            .node_cfg
-            .contains(PbrNodeConfig::SSAO)
-            .then(|| &assets.extra_bind_groups[&SSAO.ssao_bind_group]);
+        let b_ssao = match self.node_cfg.contains(PbrNodeConfig::SSAO) {
+            true => Some(assets.required_bind_group(
+                &SSAO.ssao_bind_group,
+                "ssao_bind_group",
+                "SsaoNode",
+            )?),
+            false => None,
+        };
 
-        {
-            let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
-                label: Some("pbr_pass"),
-                color_attachments: &[Some(RenderPassColorAttachment {
-                    view: &targets.swap_chain.current_view(),
+        let (color_attachment, depth_attachment) = match &self.msaa {
+            Some(msaa) => (
+                RenderPassColorAttachment {
+                    view: &msaa.color_view,
+                    resolve_target: Some(targets.swap_chain.current_view()),
+                    ops: Operations {
+                        load: LoadOp::Clear(Color::TRANSPARENT),
+                        store: StoreOp::Discard,
+                    },
+                },
+                RenderPassDepthStencilAttachment {
+                    view: &msaa.depth_view,
+                    depth_ops: Some(Operations {
+                        load: LoadOp::Clear(1.),
+                        store: StoreOp::Discard,
+                    }),
+                    stencil_ops: None,
+                },
+            ),
+            None => (
+                RenderPassColorAttachment {
+                    view: targets.swap_chain.current_view(),
                     resolve_target: None,
                     ops: Operations {
                         load: LoadOp::Load,
                         store: StoreOp::Store,
                     },
-                })],
-                depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
-                    view: &assets.texture_views[&DEPTH_PREPASS_TEXTURE.view],
+                },
+                RenderPassDepthStencilAttachment {
+                    view: &assets.texture_views[&DEPTH_PREPASS_TEXTURE.attachment(node.multiview)],
                     depth_ops: Some(Operations {
-                        load: LoadOp::Load,
+                        load: self.depth_load_op.load_op(),
                         store: StoreOp::Store,
                     }),
                     stencil_ops: None,
-                }),
+                },
+            ),
+        };
+
+        {
+            let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("pbr_pass"),
+                color_attachments: &[Some(color_attachment)],
+                depth_stencil_attachment: Some(depth_attachment),
                 ..Default::default()
             });
 
@@ -321,18 +1050,54 @@ impl RenderNode for PbrNode {
                 pass.set_bind_group(self.ssao_index, b_ssao.unwrap(), &[]);
             }
 
-            for mesh in &node.meshes {
+            for batch in &self.batches {
+                let (Some(offset), Some(b_material), Some(pipeline)) = (
+                    batch.offset,
+                    assets.material_bind_groups.get(&batch.material),
+                    self.pipelines.get(&batch.key),
+                ) else {
+                    continue;
+                };
+
+                pass.set_pipeline(pipeline);
+                pass.set_bind_group(2, b_material, &[offset]);
+                pass.set_bind_group(3, b_model, &[ModelUniforms::IDENTITY_OFFSET]);
+                for (index, mesh) in batch.meshes.iter().enumerate() {
+                    let Some(instance) = assets.gpu_meshes.get(&mesh.mesh) else {
+                        continue;
+                    };
+                    let offset = index as BufferAddress * PbrDrawBatch::ARGS_STRIDE;
+                    instance.set_vertex_buffers(&mut pass);
+                    match &instance.index_buffer {
+                        Some(indices) => {
+                            pass.set_index_buffer(indices.buffer.slice(..), indices.format);
+                            pass.draw_indexed_indirect(&batch.indirect_buffer, offset);
+                        }
+                        None => pass.draw_indirect(&batch.indirect_buffer, offset),
+                    }
+                }
+            }
+
+            self.draw_instanced(&mut pass, assets, b_model, false);
+
+            for (index, key) in &self.draw_order {
+                let mesh = &node.meshes[*index];
+                let material = match material_override {
+                    Some(_) => MATERIAL_OVERRIDE,
+                    None => mesh.mesh.material,
+                };
                 let (Some(b_material), Some(instance), Some(pipeline)) = (
-                    assets.material_bind_groups.get(&mesh.mesh.material),
+                    assets.material_bind_groups.get(&material),
                     assets.gpu_meshes.get(&mesh.mesh.mesh),
-                    node.pipelines.get(&mesh.mesh.mesh),
+                    self.pipelines.get(key),
                 ) else {
                     continue;
                 };
 
                 pass.set_pipeline(pipeline);
                 pass.set_bind_group(2, b_material, &[mesh.offset.unwrap()]);
-                pass.set_vertex_buffer(0, instance.vertex_buffer.slice(..));
+                pass.set_bind_group(3, b_model, &[mesh.model_offset.unwrap()]);
+                instance.set_vertex_buffers(&mut pass);
                 if let Some(indices) = &instance.index_buffer {
                     pass.set_index_buffer(indices.buffer.slice(..), indices.format);
                     pass.draw_indexed(0..indices.count, 0, 0..1);
@@ -340,8 +1105,842 @@ impl RenderNode for PbrNode {
                     pass.draw(0..instance.vertices_count, 0..1);
                 }
             }
+            self.draw_instanced(&mut pass, assets, b_model, true);
         }
 
-        queue.submit([encoder.finish()]);
+        Some(encoder.finish())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use aurora_core::{
+        render::{
+            flow::{GeneralNode, ImageFallbackNode, RenderContext, RenderFlow, RenderNode},
+            helper::{Camera, Transform},
+            mesh::{
+                AlphaMode, InstancedMesh, Mesh, MeshBufferLayout, MeshIndices,
+                MeshVertexAttributeData, StaticMesh, DEFAULT_RENDER_LAYER,
+            },
+            resource::GpuPointLight,
+            scene::{GpuScene, MaterialInstanceId, MeshInstanceId},
+        },
+        util::{
+            testing::{self, TestTargets},
+            TextureReadback,
+        },
+        WgpuRenderer,
+    };
+    use glam::{UVec2, Vec2, Vec3, Vec4};
+    use half::f16;
+    use palette::Srgb;
+    use uuid::Uuid;
+    use wgpu::{DownlevelFlags, Face, Maintain, TextureFormat};
+
+    use super::{PbrNode, PbrNodeConfig, PbrPipelineKey};
+    use crate::{
+        material::{PbrMaterial, VertexAnimationTexture, WindConfig},
+        node::{BloomNode, DepthLoadOp, DepthPrepassNode},
+    };
+
+    /// Red of the center pixel of a quad lit from the camera, seen from the front or from
+    /// `behind`.
+    fn quad_center(renderer: &WgpuRenderer, material: PbrMaterial, behind: bool) -> u8 {
+        const SIZE: u32 = 16;
+
+        let mut scene = GpuScene::default();
+        let material_id = MaterialInstanceId(Uuid::from_u128(2));
+        scene
+            .original
+            .materials
+            .insert(material_id, Arc::new(material));
+        testing::add_static_mesh(
+            &mut scene,
+            testing::quad(Vec2::splat(-1.), Vec2::splat(1.), -3.),
+            material_id,
+        );
+
+        let eye = if behind {
+            Vec3::new(0., 0., -6.)
+        } else {
+            Vec3::ZERO
+        };
+        scene.original.camera = Camera {
+            transform: Transform::default()
+                .with_translation(eye)
+                .looking_at(Vec3::new(0., 0., -3.), Vec3::Y),
+            ..Default::default()
+        };
+        scene.original.point_lights.insert(
+            Uuid::from_u128(3),
+            GpuPointLight {
+                position: eye,
+                color: Vec3::ONE,
+                intensity: 10000.,
+                radius: 0.1,
+            },
+        );
+
+        let size = UVec2::splat(SIZE);
+        let test_targets = TestTargets::new(&renderer.device, size, TextureFormat::Rgba8Unorm)
+            .with_depth(&renderer.device);
+        let targets = test_targets.targets();
+
+        let mut flow = RenderFlow::default();
+        flow.add::<GeneralNode>()
+            .add::<ImageFallbackNode>()
+            .add::<DepthPrepassNode>()
+            .add::<PbrNode>();
+        flow.set_queue(scene.static_meshes.clone());
+        flow.build(renderer, &mut scene, None, &targets).unwrap();
+        flow.run(renderer, &mut scene, &targets);
+
+        test_targets.read(renderer).get_pixel(SIZE / 2, SIZE / 2)[0]
+    }
+
+    #[test]
+    fn double_sided_quad() {
+        let Some(renderer) = testing::renderer(None, None) else {
+            return;
+        };
+
+        let front = quad_center(&renderer, PbrMaterial::default(), false);
+        assert!(front > 100, "{front}");
+        assert_eq!(quad_center(&renderer, PbrMaterial::default(), true), 0);
+
+        // Seen from behind, the quad is kept and shaded with its normal flipped towards the
+        // light, so it looks the same as from the front.
+        let double = || PbrMaterial {
+            double_sided: true,
+            ..Default::default()
+        };
+        assert_eq!(quad_center(&renderer, double(), false), front);
+        let back = quad_center(&renderer, double(), true);
+        assert!(back.abs_diff(front) <= 2, "{front} {back}");
+    }
+
+    #[test]
+    fn double_sided_quad_pipeline() {
+        let quad = testing::quad(Vec2::splat(-1.), Vec2::splat(1.), 0.);
+
+        let single = PbrMaterial::default();
+        let key = PbrPipelineKey::new(MeshInstanceId::default(), &quad, Some(&single), false);
+        assert_eq!(key.cull_mode(), Some(Face::Back));
+
+        let double = PbrMaterial {
+            double_sided: true,
+            ..Default::default()
+        };
+        let double_key =
+            PbrPipelineKey::new(MeshInstanceId::default(), &quad, Some(&double), false);
+        assert_eq!(double_key.cull_mode(), None);
+        // Same mesh, different material, so each needs its own pipeline.
+        assert_ne!(key, double_key);
+    }
+
+    #[test]
+    fn compiles_used_variants() {
+        let Some(renderer) = testing::renderer(None, None) else {
+            return;
+        };
+
+        let mut scene = GpuScene::default();
+        let blended = MaterialInstanceId(Uuid::from_u128(2));
+        scene.original.materials.insert(
+            blended,
+            Arc::new(PbrMaterial {
+                alpha_mode: AlphaMode::Blend,
+                ..Default::default()
+            }),
+        );
+        let quad = || testing::quad(Vec2::splat(-1.), Vec2::splat(1.), -3.);
+        testing::add_static_mesh(&mut scene, quad(), MaterialInstanceId::default());
+
+        let size = UVec2::splat(16);
+        let test_targets = TestTargets::new(&renderer.device, size, TextureFormat::Rgba8Unorm)
+            .with_depth(&renderer.device);
+        let targets = test_targets.targets();
+
+        let mut flow = RenderFlow::default();
+        flow.add::<GeneralNode>()
+            .add::<ImageFallbackNode>()
+            .add::<DepthPrepassNode>()
+            .add::<PbrNode>();
+        flow.set_queue(scene.static_meshes.clone());
+        flow.build(&renderer, &mut scene, None, &targets).unwrap();
+
+        let shaders = &flow.get_mut::<PbrNode>().unwrap().shaders;
+        assert_eq!(shaders.keys().collect::<Vec<_>>(), [&0]);
+        let opaque = shaders[&0].global_id();
+
+        // Only the new variant is compiled, the opaque one is reused.
+        testing::add_static_mesh(&mut scene, quad(), blended);
+        flow.set_queue(scene.static_meshes.clone());
+        flow.force_build(&renderer, &mut scene, None, &targets)
+            .unwrap();
+        let shaders = &flow.get_mut::<PbrNode>().unwrap().shaders;
+        assert_eq!(shaders.len(), 2);
+        assert_eq!(shaders[&0].global_id(), opaque);
+
+        // Other shader defs recompile every variant.
+        flow.force_build(
+            &renderer,
+            &mut scene,
+            Some([("PBR_TEST".to_string(), Default::default())].into()),
+            &targets,
+        )
+        .unwrap();
+        assert_ne!(
+            flow.get_mut::<PbrNode>().unwrap().shaders[&0].global_id(),
+            opaque
+        );
+    }
+
+    #[test]
+    fn vertex_displacement_variants() {
+        let mesh = Mesh::new().with_attribute(
+            Mesh::POSITION_ATTR,
+            MeshVertexAttributeData::Float32x3(vec![Vec3::ZERO; 3]),
+        );
+
+        let still = PbrMaterial::default();
+        let animated = PbrMaterial {
+            vertex_animation: Some(VertexAnimationTexture {
+                texture: Default::default(),
+                frames: 8,
+                fps: 24.,
+                time_offset: 0.,
+                normals: true,
+            }),
+            ..Default::default()
+        };
+
+        let key = PbrPipelineKey::new(MeshInstanceId::default(), &mesh, Some(&still), false);
+        let animated_key =
+            PbrPipelineKey::new(MeshInstanceId::default(), &mesh, Some(&animated), false);
+        assert_ne!(key, animated_key);
+        // Still opaque, only the vertex path differs.
+        assert!(!animated_key.is_blended());
+
+        let windy = PbrMaterial {
+            wind: Some(WindConfig::default()),
+            ..animated.clone()
+        };
+        let windy_key = PbrPipelineKey::new(MeshInstanceId::default(), &mesh, Some(&windy), false);
+        assert_ne!(animated_key, windy_key);
+    }
+
+    #[test]
+    fn missing_shadow_mapping_node() {
+        // Shadows are enabled, but nothing creates the shadow maps.
+        let mut flow = RenderFlow::default();
+        flow.add::<GeneralNode>()
+            .add::<ImageFallbackNode>()
+            .add::<DepthPrepassNode>()
+            .add_initialized(PbrNode {
+                node_cfg: PbrNodeConfig::SHADOW_MAPPING,
+                ..Default::default()
+            });
+        let Some(renderer) =
+            testing::skip_unsupported(pollster::block_on(flow.request_renderer(None, None)))
+        else {
+            return;
+        };
+
+        let mut scene = GpuScene::default();
+        let material_id = MaterialInstanceId(Uuid::from_u128(2));
+        let quad = testing::quad(Vec2::splat(-1.), Vec2::splat(1.), -3.);
+        scene
+            .original
+            .materials
+            .insert(material_id, Arc::new(PbrMaterial::default()));
+        testing::add_static_mesh(&mut scene, quad, material_id);
+
+        let size = UVec2::splat(16);
+        let test_targets = TestTargets::new(&renderer.device, size, TextureFormat::Rgba8Unorm)
+            .with_depth(&renderer.device);
+        let targets = test_targets.targets();
+
+        flow.set_queue(scene.static_meshes.clone());
+        flow.build(&renderer, &mut scene, None, &targets).unwrap();
+        flow.run(&renderer, &mut scene, &targets);
+
+        // Warned about and skipped instead of panicking.
+        assert!(flow.get_mut::<PbrNode>().unwrap().pipelines.is_empty());
+    }
+
+    /// Pixels partially covered by a masked quad fading out to the left, rendered with MSAA.
+    fn partially_covered_pixels(renderer: &WgpuRenderer, alpha_to_coverage: bool) -> usize {
+        const SIZE: u32 = 32;
+
+        let mut scene = GpuScene::default();
+        let material_id = MaterialInstanceId(Uuid::from_u128(2));
+        let quad = testing::quad(Vec2::splat(-2.), Vec2::splat(2.), -3.).with_attribute(
+            Mesh::COLOR_ATTR,
+            MeshVertexAttributeData::Float32x4(vec![
+                Vec4::new(1., 1., 1., 0.),
+                Vec4::ONE,
+                Vec4::ONE,
+                Vec4::new(1., 1., 1., 0.),
+            ]),
+        );
+        scene.original.materials.insert(
+            material_id,
+            Arc::new(PbrMaterial {
+                alpha_mode: AlphaMode::Mask(0.5),
+                alpha_to_coverage,
+                emissive: Srgb::new(1., 1., 1.),
+                ..Default::default()
+            }),
+        );
+        testing::add_static_mesh(&mut scene, quad, material_id);
+
+        let size = UVec2::splat(SIZE);
+        let mut test_targets = TestTargets::new(&renderer.device, size, TextureFormat::Rgba8Unorm)
+            .with_depth(&renderer.device);
+        test_targets.sample_count = 4;
+        let targets = test_targets.targets();
+
+        let mut flow = RenderFlow::default();
+        flow.add::<GeneralNode>()
+            .add::<ImageFallbackNode>()
+            .add::<PbrNode>();
+        flow.set_queue(scene.static_meshes.clone());
+        flow.build(renderer, &mut scene, None, &targets).unwrap();
+        flow.run(renderer, &mut scene, &targets);
+
+        let image = test_targets.read(&renderer);
+        // Half of the quad is cut out.
+        assert!(image.get_pixel(2, SIZE / 2)[0] < 10);
+        assert!(image.get_pixel(SIZE - 3, SIZE / 2)[0] > 245);
+        image
+            .pixels()
+            .filter(|pixel| (10..=245).contains(&pixel[0]))
+            .count()
+    }
+
+    #[test]
+    fn alpha_to_coverage() {
+        let Some(renderer) = testing::renderer(None, None) else {
+            return;
+        };
+
+        // Discarding keeps or drops whole pixels, as the mask is only evaluated once.
+        assert_eq!(partially_covered_pixels(&renderer, false), 0);
+        // At least one on each row along the cutout.
+        assert!(partially_covered_pixels(&renderer, true) >= 32);
+    }
+
+    /// Quads sharing a material, the last one out of view, returning the image and the
+    /// number of batches.
+    fn render_quads(
+        renderer: &WgpuRenderer,
+        pbr: PbrNode,
+        buffer_layout: MeshBufferLayout,
+        sample_count: u32,
+    ) -> (Vec<u8>, usize) {
+        const SIZE: u32 = 32;
+
+        let mut scene = GpuScene::default();
+        let material_id = MaterialInstanceId(Uuid::from_u128(1));
+        scene.original.materials.insert(
+            material_id,
+            Arc::new(PbrMaterial {
+                emissive: Srgb::new(1., 1., 1.),
+                ..Default::default()
+            }),
+        );
+        for (i, (center, extent)) in [(-1., 0.3), (0., 0.5), (1., 0.2), (100., 1.)]
+            .into_iter()
+            .enumerate()
+        {
+            let mesh_id = MeshInstanceId(Uuid::from_u128(i as u128 + 2));
+            let mut quad = Mesh::new()
+                .with_attribute(
+                    Mesh::POSITION_ATTR,
+                    MeshVertexAttributeData::Float32x3(vec![
+                        Vec3::new(center - extent, -extent, -3.),
+                        Vec3::new(center + extent, -extent, -3.),
+                        Vec3::new(center + extent, extent, -3.),
+                        Vec3::new(center - extent, extent, -3.),
+                    ]),
+                )
+                .with_attribute(
+                    Mesh::NORMAL_ATTR,
+                    MeshVertexAttributeData::Float32x3(vec![Vec3::Z; 4]),
+                )
+                .with_attribute(
+                    Mesh::TEX_COORDS_ATTR,
+                    MeshVertexAttributeData::Float32x2(vec![Vec2::ZERO; 4]),
+                );
+            // Mixes indexed and non indexed meshes.
+            quad = match i % 2 {
+                0 => quad.with_indices(MeshIndices::UInt16(vec![0, 1, 2, 0, 2, 3])),
+                _ => quad,
+            };
+            quad.recalculate_tangent().unwrap();
+            quad.set_buffer_layout(buffer_layout);
+            scene.assets.meshes.insert(mesh_id, quad);
+            scene.static_meshes.push(StaticMesh {
+                mesh: mesh_id,
+                material: material_id,
+                render_layer: DEFAULT_RENDER_LAYER,
+                transform: Default::default(),
+            });
+        }
+
+        let size = UVec2::splat(SIZE);
+        let mut test_targets = TestTargets::new(&renderer.device, size, TextureFormat::Rgba8Unorm)
+            .with_depth(&renderer.device);
+        test_targets.sample_count = sample_count;
+        let targets = test_targets.targets();
+
+        let mut flow = RenderFlow::default();
+        flow.add::<GeneralNode>()
+            .add::<ImageFallbackNode>()
+            .add::<DepthPrepassNode>()
+            .add_initialized(pbr);
+        flow.set_queue(scene.static_meshes.clone());
+        flow.build(renderer, &mut scene, None, &targets).unwrap();
+        flow.run(renderer, &mut scene, &targets);
+
+        let image = test_targets.read(&renderer);
+        let node = flow.get_mut::<PbrNode>().unwrap();
+        assert_eq!(node.draw_order.is_empty(), !node.batches.is_empty());
+        (image.into_raw(), node.batches.len())
+    }
+
+    fn indirect_execution(renderer: &WgpuRenderer) -> bool {
+        renderer
+            .adapter
+            .get_downlevel_capabilities()
+            .flags
+            .contains(DownlevelFlags::INDIRECT_EXECUTION)
+    }
+
+    #[test]
+    fn indirect_draw() {
+        let Some(renderer) = testing::renderer(None, None) else {
+            return;
+        };
+
+        let (direct, batches) = render_quads(
+            &renderer,
+            PbrNode::default(),
+            MeshBufferLayout::Interleaved,
+            4,
+        );
+        assert_eq!(batches, 0);
+        assert!(direct.iter().any(|&c| c > 0));
+
+        // Falls back to drawing each mesh without indirect execution.
+        let supported = indirect_execution(&renderer);
+        let (indirect, batches) = render_quads(
+            &renderer,
+            PbrNode {
+                node_cfg: PbrNodeConfig::INDIRECT_DRAW,
+                ..Default::default()
+            },
+            MeshBufferLayout::Interleaved,
+            4,
+        );
+        assert_eq!(batches, supported as usize);
+        assert!(direct == indirect);
+    }
+
+    #[test]
+    fn separate_vertex_streams() {
+        let Some(renderer) = testing::renderer(None, None) else {
+            return;
+        };
+
+        let (interleaved, _) = render_quads(
+            &renderer,
+            PbrNode::default(),
+            MeshBufferLayout::Interleaved,
+            4,
+        );
+        assert!(interleaved.iter().any(|&c| c > 0));
+
+        // Each mesh is drawn from its own buffers, whatever their layout.
+        let supported = indirect_execution(&renderer);
+        let (separate, batches) = render_quads(
+            &renderer,
+            PbrNode {
+                node_cfg: PbrNodeConfig::INDIRECT_DRAW,
+                ..Default::default()
+            },
+            MeshBufferLayout::Separate,
+            4,
+        );
+        assert_eq!(batches, supported as usize);
+        assert!(interleaved == separate);
+    }
+
+    #[test]
+    fn depth_load_op() {
+        let Some(renderer) = testing::renderer(None, None) else {
+            return;
+        };
+
+        let (cleared, _) = render_quads(
+            &renderer,
+            PbrNode::default(),
+            MeshBufferLayout::Interleaved,
+            1,
+        );
+        assert!(cleared.iter().any(|&c| c > 0));
+
+        // Depth from the prepass matches exactly, so nothing fails the equal test.
+        let (loaded, _) = render_quads(
+            &renderer,
+            PbrNode {
+                depth_load_op: DepthLoadOp::Load,
+                ..Default::default()
+            },
+            MeshBufferLayout::Interleaved,
+            1,
+        );
+        assert!(cleared == loaded);
+    }
+
+    #[test]
+    fn instanced_mesh() {
+        const SIZE: u32 = 32;
+
+        let Some(renderer) = testing::renderer(None, None) else {
+            return;
+        };
+
+        let mut scene = GpuScene::default();
+        let mesh_id = MeshInstanceId(Uuid::from_u128(1));
+        let material_id = MaterialInstanceId(Uuid::from_u128(2));
+        let quad = testing::quad(Vec2::splat(-1.), Vec2::splat(1.), 0.);
+        scene.assets.meshes.insert(mesh_id, quad);
+        scene.original.materials.insert(
+            material_id,
+            Arc::new(PbrMaterial {
+                emissive: Srgb::new(1., 1., 1.),
+                ..Default::default()
+            }),
+        );
+        // A single copy in view, on the right, the others far behind the camera.
+        let mut transforms = (0..1000)
+            .map(|i| Transform {
+                translation: Vec3::new(i as f32, 0., 100.),
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+        transforms[500] = Transform {
+            translation: Vec3::new(0.5, 0., -3.),
+            scale: Vec3::splat(0.4),
+            ..Default::default()
+        };
+        scene.instanced_meshes.push(InstancedMesh {
+            mesh: mesh_id,
+            material: material_id,
+            transforms,
+        });
+
+        let size = UVec2::splat(SIZE);
+        let test_targets = TestTargets::new(&renderer.device, size, TextureFormat::Rgba8Unorm)
+            .with_depth(&renderer.device);
+        let targets = test_targets.targets();
+
+        // The depth prepass doesn't draw instances, which mustn't be tested for equal depth.
+        let mut flow = RenderFlow::default();
+        flow.add::<GeneralNode>()
+            .add::<ImageFallbackNode>()
+            .add::<DepthPrepassNode>()
+            .add_initialized(PbrNode {
+                depth_load_op: DepthLoadOp::Load,
+                ..Default::default()
+            });
+        flow.build(&renderer, &mut scene, None, &targets).unwrap();
+        flow.run(&renderer, &mut scene, &targets);
+
+        let node = flow.get_mut::<PbrNode>().unwrap();
+        assert_eq!(node.instanced_draws.len(), 1);
+        assert_eq!(node.instanced_draws[0].instance_count, 1000);
+        let image = test_targets.read(&renderer);
+        assert!(image.get_pixel(SIZE * 3 / 4, SIZE / 2)[0] > 245);
+        assert!(image.get_pixel(SIZE / 4, SIZE / 2)[0] < 10);
+
+        // Moved without touching the vertices, nothing clears the previous frame though.
+        scene.instanced_meshes[0].transforms[500].translation.x = -0.5;
+        flow.run(&renderer, &mut scene, &targets);
+        let image = test_targets.read(&renderer);
+        assert!(image.get_pixel(SIZE / 4, SIZE / 2)[0] > 245);
+    }
+
+    #[test]
+    fn moved_static_mesh() {
+        const SIZE: u32 = 32;
+
+        let Some(renderer) = testing::renderer(None, None) else {
+            return;
+        };
+
+        let mut scene = GpuScene::default();
+        let mesh_id = MeshInstanceId(Uuid::from_u128(1));
+        let material_id = MaterialInstanceId(Uuid::from_u128(2));
+        let quad = testing::quad(Vec2::splat(-1.), Vec2::splat(1.), 0.);
+        scene.assets.meshes.insert(mesh_id, quad);
+        scene.original.materials.insert(
+            material_id,
+            Arc::new(PbrMaterial {
+                emissive: Srgb::new(1., 1., 1.),
+                ..Default::default()
+            }),
+        );
+        scene.static_meshes.push(StaticMesh {
+            mesh: mesh_id,
+            material: material_id,
+            render_layer: DEFAULT_RENDER_LAYER,
+            transform: Transform {
+                translation: Vec3::new(0.5, 0., -3.),
+                scale: Vec3::new(0.4, 0.2, 1.),
+                ..Default::default()
+            },
+        });
+
+        let size = UVec2::splat(SIZE);
+        let test_targets = TestTargets::new(&renderer.device, size, TextureFormat::Rgba8Unorm)
+            .with_depth(&renderer.device);
+        let targets = test_targets.targets();
+
+        // The prepass moves the mesh the same way, so it still passes the equal depth test.
+        let mut flow = RenderFlow::default();
+        flow.add::<GeneralNode>()
+            .add::<ImageFallbackNode>()
+            .add::<DepthPrepassNode>()
+            .add_initialized(PbrNode {
+                depth_load_op: DepthLoadOp::Load,
+                ..Default::default()
+            });
+        flow.set_queue(scene.static_meshes.clone());
+        flow.build(&renderer, &mut scene, None, &targets).unwrap();
+        flow.run(&renderer, &mut scene, &targets);
+
+        let image = test_targets.read(&renderer);
+        assert!(image.get_pixel(SIZE * 3 / 4, SIZE / 2)[0] > 245);
+        assert!(image.get_pixel(SIZE * 3 / 4, SIZE / 8)[0] < 10);
+        assert!(image.get_pixel(SIZE / 4, SIZE / 2)[0] < 10);
+
+        // Moved without touching the vertices, nothing clears the previous frame though.
+        scene.static_meshes[0].transform.translation.x = -0.5;
+        flow.set_queue(scene.static_meshes.clone());
+        flow.run(&renderer, &mut scene, &targets);
+        let image = test_targets.read(&renderer);
+        assert!(image.get_pixel(SIZE / 4, SIZE / 2)[0] > 245);
+    }
+
+    /// Flips the swap chain while drawing, like post processing.
+    #[derive(Default)]
+    struct SwapNode;
+
+    impl RenderNode for SwapNode {
+        fn draw(&self, _scene: &mut GpuScene, context: RenderContext) {
+            context.targets.swap_chain.swap();
+        }
+    }
+
+    #[test]
+    fn parallel_run_after_swap() {
+        const SIZE: u32 = 8;
+
+        let Some(renderer) = testing::renderer(None, None) else {
+            return;
+        };
+
+        let mut scene = GpuScene::default();
+        let material_id = MaterialInstanceId(Uuid::from_u128(1));
+        scene.original.materials.insert(
+            material_id,
+            Arc::new(PbrMaterial {
+                emissive: Srgb::new(1., 1., 1.),
+                ..Default::default()
+            }),
+        );
+        let quad = testing::quad(Vec2::splat(-1.), Vec2::splat(1.), -3.);
+        testing::add_static_mesh(&mut scene, quad, material_id);
+
+        let size = UVec2::splat(SIZE);
+        let test_targets = TestTargets::new(&renderer.device, size, TextureFormat::Rgba8Unorm)
+            .with_depth(&renderer.device);
+        let targets = test_targets.targets();
+
+        let mut flow = RenderFlow::default();
+        flow.add::<GeneralNode>()
+            .add::<ImageFallbackNode>()
+            .add::<DepthPrepassNode>()
+            .add::<SwapNode>()
+            .add::<PbrNode>();
+        flow.set_queue(scene.static_meshes.clone());
+        flow.build(&renderer, &mut scene, None, &targets).unwrap();
+        flow.run_parallel(&renderer, &mut scene, &targets);
+
+        // Drawing into the swap chain, PBR isn't parallel safe by default, so it's recorded
+        // after the swap into the texture that's current now.
+        let image = test_targets.read(&renderer);
+        assert!(image.get_pixel(SIZE / 2, SIZE / 2)[0] > 245);
+    }
+
+    /// Render a black quad emitting `emissive` through bloom, and return the brightest
+    /// texel left of it.
+    fn bloom_beside_quad(renderer: &WgpuRenderer, emissive: Srgb) -> f32 {
+        const SIZE: u32 = 64;
+
+        let mut scene = GpuScene::default();
+        let material_id = MaterialInstanceId(Uuid::from_u128(2));
+        scene.original.materials.insert(
+            material_id,
+            Arc::new(PbrMaterial {
+                base_color: Srgb::new(0., 0., 0.),
+                emissive,
+                ..Default::default()
+            }),
+        );
+        let quad = testing::quad(Vec2::new(0., -0.5), Vec2::new(1., 0.5), -3.);
+        testing::add_static_mesh(&mut scene, quad, material_id);
+
+        let size = UVec2::splat(SIZE);
+        let test_targets = TestTargets::new(&renderer.device, size, TextureFormat::Rgba16Float)
+            .with_depth(&renderer.device);
+        let targets = test_targets.targets();
+
+        let mut flow = RenderFlow::default();
+        flow.add::<GeneralNode>()
+            .add::<ImageFallbackNode>()
+            .add::<DepthPrepassNode>()
+            .add::<PbrNode>()
+            .add::<BloomNode>();
+        flow.set_queue(scene.static_meshes.clone());
+        flow.build(renderer, &mut scene, None, &targets).unwrap();
+        flow.run(renderer, &mut scene, &targets);
+
+        let readback = TextureReadback::new(
+            test_targets.swap_chain.current_texture(),
+            &renderer.device,
+            &renderer.queue,
+        )
+        .unwrap();
+        renderer.device.poll(Maintain::wait()).panic_on_timeout();
+        let data = pollster::block_on(readback.read()).unwrap();
+        let texels = bytemuck::pod_collect_to_vec::<u8, f16>(&data.bytes);
+        let row = (data.bytes_per_row / 2) as usize;
+        // The quad starts at the center, so the left half only gets its bloom.
+        (0..SIZE as usize)
+            .flat_map(|y| (0..SIZE as usize / 2 - 2).map(move |x| y * row + x * 4))
+            .map(|i| texels[i].to_f32())
+            .fold(0., f32::max)
+    }
+
+    #[test]
+    fn emissive_blooms() {
+        let Some(renderer) = testing::renderer(None, None) else {
+            return;
+        };
+
+        // Emission passes the exposed threshold directly, and glows past the quad.
+        assert!(bloom_beside_quad(&renderer, Srgb::new(4., 4., 4.)) > 0.05);
+        // Below the threshold, and without emission, nothing spills over.
+        assert!(bloom_beside_quad(&renderer, Srgb::new(0.5, 0.5, 0.5)) < 1e-3);
+        assert!(bloom_beside_quad(&renderer, Srgb::new(0., 0., 0.)) < 1e-3);
+    }
+
+    /// Glossy quad lit from above, seen by two eyes apart from each other.
+    fn eyes_scene() -> (GpuScene, [Camera; 2]) {
+        let mut scene = GpuScene::default();
+        let material_id = MaterialInstanceId(Uuid::from_u128(2));
+        scene.original.materials.insert(
+            material_id,
+            Arc::new(PbrMaterial {
+                roughness: 0.3,
+                ..Default::default()
+            }),
+        );
+        testing::add_static_mesh(
+            &mut scene,
+            testing::quad(Vec2::splat(-2.), Vec2::splat(2.), -3.),
+            material_id,
+        );
+        scene.original.point_lights.insert(
+            Uuid::from_u128(3),
+            GpuPointLight {
+                position: Vec3::new(0., 1., -2.),
+                color: Vec3::ONE,
+                intensity: 20000.,
+                radius: 0.1,
+            },
+        );
+
+        let eyes = [-0.5, 0.5].map(|x| Camera {
+            transform: Transform::default().with_translation(Vec3::new(x, 0., 0.)),
+            ..Default::default()
+        });
+        (scene, eyes)
+    }
+
+    fn eyes_flow() -> RenderFlow {
+        let mut flow = RenderFlow::default();
+        flow.add::<GeneralNode>()
+            .add::<ImageFallbackNode>()
+            .add::<DepthPrepassNode>()
+            .add::<PbrNode>();
+        flow
+    }
+
+    #[test]
+    fn stereo_eyes() {
+        const SIZE: u32 = 32;
+
+        let mut stereo = eyes_flow();
+        stereo.set_stereo(true);
+        let Some(renderer) =
+            testing::skip_unsupported(pollster::block_on(stereo.request_renderer(None, None)))
+        else {
+            return;
+        };
+        let size = UVec2::splat(SIZE);
+
+        let (mut scene, eyes) = eyes_scene();
+        scene.original.eyes = eyes;
+        let test_targets = TestTargets::new(&renderer.device, size, TextureFormat::Rgba8Unorm)
+            .with_depth(&renderer.device)
+            .with_eyes(&renderer.device);
+        let targets = test_targets.targets();
+        stereo.set_queue(scene.static_meshes.clone());
+        stereo.build(&renderer, &mut scene, None, &targets).unwrap();
+        stereo.run(&renderer, &mut scene, &targets);
+
+        for (layer, eye) in eyes.into_iter().enumerate() {
+            let (mut scene, _) = eyes_scene();
+            scene.original.camera = eye;
+            let mono_targets = TestTargets::new(&renderer.device, size, TextureFormat::Rgba8Unorm)
+                .with_depth(&renderer.device);
+            let targets = mono_targets.targets();
+            let mut mono = eyes_flow();
+            mono.set_queue(scene.static_meshes.clone());
+            mono.build(&renderer, &mut scene, None, &targets).unwrap();
+            mono.run(&renderer, &mut scene, &targets);
+
+            // Each eye is positioned and shaded like a mono render from it.
+            let expected = mono_targets.read(&renderer);
+            let actual = test_targets.read_layer(&renderer, layer as u32);
+            let diff = expected
+                .pixels()
+                .zip(actual.pixels())
+                .flat_map(|(e, a)| (0..3).map(move |c| e[c].abs_diff(a[c])))
+                .max()
+                .unwrap();
+            assert!(diff <= 2, "eye {layer}: {diff}");
+        }
+        assert_ne!(
+            test_targets.read_layer(&renderer, 0),
+            test_targets.read_layer(&renderer, 1)
+        );
     }
 }
@@ -1,50 +1,82 @@
-use std::collections::HashMap;
-
-use aurora_core::render::{
-    flow::{RenderContext, RenderNode},
-    helper::{CameraProjection, Transform},
-    resource::{DynamicGpuBuffer, GpuCamera},
-    scene::{
-        ExtraBindGroupId, ExtraBufferId, ExtraLayoutId, GpuScene, SamplerId, TextureId,
-        TextureViewId,
+use std::{
+    collections::{HashMap, HashSet},
+    mem::size_of,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
     },
-    ShaderDefEnum,
+};
+
+use aurora_core::{
+    render::{
+        budget::Quality,
+        flow::{NodeContext, RenderContext, RenderNode},
+        helper::{Aabb, CameraProjection, Frustum, Scene, Transform},
+        mesh::Mesh,
+        resource::{DynamicGpuBuffer, GpuCamera, ModelUniforms},
+        scene::{
+            ExtraBindGroupId, ExtraBufferId, ExtraLayoutId, GpuAssets, GpuScene, MeshInstanceId,
+            SamplerId, TextureId, TextureViewId,
+        },
+        ShaderDefEnum,
+    },
+    util::create_sampler,
 };
 use encase::ShaderType;
 use glam::{Mat4, Vec2, Vec3, Vec4};
 use naga_oil::compose::ShaderDefValue;
 use uuid::Uuid;
 use wgpu::{
-    BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor, BindGroupLayoutEntry,
-    BindingResource, BindingType, BufferBindingType, BufferUsages, CompareFunction, DepthBiasState,
-    DepthStencilState, Extent3d, Face, Features, FilterMode, FragmentState, LoadOp,
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, BlendComponent, BlendFactor,
+    BlendOperation, BlendState, Buffer, BufferBindingType, BufferDescriptor, BufferUsages, Color,
+    ColorTargetState, ColorWrites, CommandBuffer, CompareFunction, ComputePassDescriptor,
+    ComputePipeline, ComputePipelineDescriptor, DepthBiasState, DepthStencilState, Device,
+    Extent3d, Face, Features, FilterMode, FragmentState, LoadOp, Maintain, MapMode,
     MultisampleState, Operations, PipelineCompilationOptions, PipelineLayoutDescriptor,
-    PrimitiveState, RenderPassDepthStencilAttachment, RenderPassDescriptor,
-    RenderPipelineDescriptor, SamplerBindingType, SamplerDescriptor, ShaderStages, StencilState,
-    StoreOp, TextureAspect, TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType,
-    TextureUsages, TextureView, TextureViewDescriptor, TextureViewDimension, VertexBufferLayout,
-    VertexFormat, VertexState, VertexStepMode,
+    PrimitiveState, Queue, RenderPassColorAttachment, RenderPassDepthStencilAttachment,
+    RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor, SamplerBindingType,
+    SamplerDescriptor, ShaderStages, StencilState, StoreOp, TextureAspect, TextureDescriptor,
+    TextureDimension, TextureFormat, TextureSampleType, TextureUsages, TextureView,
+    TextureViewDescriptor, TextureViewDimension, VertexFormat, VertexState,
 };
 
 use crate::{
+    node::{pbr::mesh_material, DEPTH_PREPASS_TEXTURE},
     shader_defs::ShadowFiltering,
-    util::{self, frustum_slice, Aabb},
+    util::{self, frustum_slice, frustum_slice_at, logarithmic_splits},
 };
 
+pub const SHADOW_TRANSMITTANCE_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+const SDSM_WORKGROUP_SIZE: u32 = 16;
+const SDSM_BOUNDS_SIZE: u64 = 2 * size_of::<u32>() as u64;
+
 bitflags::bitflags! {
     #[derive(Default)]
     pub struct ShadowMappingNodeConfig : u32 {
         const RANDOMIZE = 1 << 0;
+        /// Casters with [`Material::shadow_transmittance`] tint directional light instead
+        /// of blocking it. Point and spot lights still treat them as opaque.
+        ///
+        /// [`Material::shadow_transmittance`]: aurora_core::render::mesh::Material::shadow_transmittance
+        const TRANSLUCENT = 1 << 1;
     }
 }
 
 pub enum ShadowMapPartitioning {
+    /// Slices the whole camera frustum, blending logarithmic and uniform splits.
     PSSM(u32),
+    /// Slices the depth range visible in the depth prepass logarithmically.
+    ///
+    /// The range is read back from the GPU, so it lags a couple of frames behind. Falls
+    /// back to `PSSM` until it's measured, or without a `DepthPrepassNode`.
     SDSM(u32),
 }
 
 #[derive(Default)]
 pub enum DepthBiasing {
+    /// Moves receivers along their normals before looking up the shadow maps, see
+    /// [`ShadowMappingConfig::normal_offset_scale`].
     NormalOffset,
     #[default]
     SingleSideRendering,
@@ -59,6 +91,11 @@ pub struct ShadowMappingConfig {
     pub dir_pcss_radius: f32,
     pub point_pcf_radius: f32,
     pub point_pcss_radius: f32,
+    /// Receiver offset of [`DepthBiasing::NormalOffset`], in shadow map texels.
+    pub normal_offset_scale: f32,
+    /// Fraction of each cascade, at its far end, blended into the next one. `0.0` keeps
+    /// transitions hard.
+    pub cascade_blend_fraction: f32,
 }
 
 impl Default for ShadowMappingConfig {
@@ -71,6 +108,8 @@ impl Default for ShadowMappingConfig {
             dir_pcss_radius: 1.,
             point_pcf_radius: 0.2,
             point_pcss_radius: 0.1,
+            normal_offset_scale: 1.5,
+            cascade_blend_fraction: 0.1,
         }
     }
 }
@@ -78,12 +117,16 @@ impl Default for ShadowMappingConfig {
 pub struct ShadowMapping {
     pub light_views: ExtraBufferId,
     pub cascade_views: ExtraBufferId,
+    pub cascade_uv_scales: ExtraBufferId,
     pub point_light_views: ExtraBufferId,
+    pub point_shadow_layers: ExtraBufferId,
     pub poisson_disk: ExtraBufferId,
     pub config: ExtraBufferId,
 
     pub directional_shadow_map: TextureId,
     pub directional_shadow_map_view: TextureViewId,
+    pub directional_transmittance_map: TextureId,
+    pub directional_transmittance_map_view: TextureViewId,
     pub point_shadow_map: TextureId,
     pub point_shadow_map_view: TextureViewId,
     pub shadow_map_sampler: SamplerId,
@@ -99,12 +142,18 @@ pub struct ShadowMapping {
 pub const SHADOW_MAPPING: ShadowMapping = ShadowMapping {
     light_views: ExtraBufferId(Uuid::from_u128(89413211065410340136548487101523115648)),
     cascade_views: ExtraBufferId(Uuid::from_u128(894132906465410168465132984653696845)),
+    cascade_uv_scales: ExtraBufferId(Uuid::from_u128(30541687465130846513046873210684)),
     point_light_views: ExtraBufferId(Uuid::from_u128(8794041105348641631856410231)),
+    point_shadow_layers: ExtraBufferId(Uuid::from_u128(6541387024561320798456123087)),
     poisson_disk: ExtraBufferId(Uuid::from_u128(1687846160641318676894156310604693)),
     config: ExtraBufferId(Uuid::from_u128(1354687841323006814572453187684531684)),
 
     directional_shadow_map: TextureId(Uuid::from_u128(7861046541564897045132508964132)),
     directional_shadow_map_view: TextureViewId(Uuid::from_u128(10264856487964101541231456531)),
+    directional_transmittance_map: TextureId(Uuid::from_u128(4561320879413065410354987613)),
+    directional_transmittance_map_view: TextureViewId(Uuid::from_u128(
+        98413065403216540687413201354,
+    )),
     point_shadow_map: TextureId(Uuid::from_u128(204153435154865423112313232)),
     point_shadow_map_view: TextureViewId(Uuid::from_u128(8974689406540351354897321563484)),
     shadow_map_sampler: SamplerId(Uuid::from_u128(8713416357854635486345415311523415)),
@@ -117,17 +166,79 @@ pub const SHADOW_MAPPING: ShadowMapping = ShadowMapping {
     light_views_bind_group: ExtraBindGroupId(Uuid::from_u128(135648640640653130645120465123)),
 };
 
+pub struct TranslucentShadowData {
+    /// Keyed by vertex stride, like [`ShadowMappingNode::pipelines`].
+    pub pipelines: HashMap<u64, RenderPipeline>,
+    pub layout: BindGroupLayout,
+    pub transmittances: DynamicGpuBuffer,
+    pub bind_group: Option<BindGroup>,
+    /// Offset into `transmittances` for each of the node meshes, `None` for opaque casters.
+    pub offsets: Vec<Option<u32>>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SdsmReadback {
+    Idle,
+    /// Depth bounds are copied into the readback buffer this frame.
+    Measuring,
+    Mapping,
+}
+
+pub struct SdsmData {
+    pub pipeline: ComputePipeline,
+    pub layout: BindGroupLayout,
+    pub bind_group: Option<BindGroup>,
+    /// Clip space depth bits of the closest and farthest pixels.
+    pub bounds: Buffer,
+    pub readback: Buffer,
+    pub readback_state: SdsmReadback,
+    pub mapped: Arc<AtomicBool>,
+}
+
 pub struct ShadowMappingNode {
     pub config: ShadowMappingConfig,
     pub partitioning: Option<ShadowMapPartitioning>,
     pub filtering: Option<ShadowFiltering>,
     pub depth_biasing: DepthBiasing,
     pub show_cascades: bool,
+    /// Resolution of each cascade relative to `dir_map_resolution`, e.g. `[1.0, 0.75, 0.5]`.
+    ///
+    /// Missing cascades use `1.0`. Every layer is allocated at the largest cascade
+    /// resolution, smaller cascades only render into a corner of their layer.
+    pub cascade_resolution_scales: Vec<f32>,
+    /// Set by the frame budget, lower tiers shrink the directional shadow maps.
+    pub budget_quality: Quality,
+    /// Point and spot lights rendering shadow maps, see [`Self::shadow_casting_lights`].
+    /// The others still light the scene, unshadowed. `None` for every light.
+    pub max_shadow_casting_lights: Option<usize>,
     pub node_cfg: ShadowMappingNodeConfig,
 
     pub directional_views: HashMap<Uuid, Vec<TextureViewId>>,
+    pub directional_transmittance_views: HashMap<Uuid, Vec<TextureViewId>>,
     pub point_views: HashMap<Uuid, [TextureViewId; 6]>,
     pub offsets: Vec<u32>,
+    /// Whether each of the node meshes is inside each light view, in the order of `offsets`.
+    ///
+    /// Empty when culling is disabled, see `RenderFlow::set_culling`.
+    pub visible_meshes: Vec<Vec<bool>>,
+    pub translucent: Option<TranslucentShadowData>,
+    /// View space distances of the closest and farthest visible pixels, used by
+    /// [`ShadowMapPartitioning::SDSM`].
+    pub depth_range: Option<Vec2>,
+    pub sdsm: Option<SdsmData>,
+    /// Directional and shadow casting point/spot light counts the shadow maps are allocated
+    /// for.
+    ///
+    /// `prepare` reallocates them when the counts in `scene.original` differ, so lights can
+    /// be added and removed without rebuilding the flow.
+    pub shadow_map_lights: (usize, usize),
+    /// Casters only write depth from their positions, so they share pipelines by vertex
+    /// stride, see [`GpuMesh::position_vertices`].
+    ///
+    /// [`GpuMesh::position_vertices`]: aurora_core::render::mesh::GpuMesh::position_vertices
+    pub pipelines: HashMap<u64, RenderPipeline>,
+    /// Models of the node meshes, shared by every light view.
+    pub models: Option<ModelUniforms>,
 }
 
 impl Default for ShadowMappingNode {
@@ -139,9 +250,20 @@ impl Default for ShadowMappingNode {
             depth_biasing: Default::default(),
             node_cfg: Default::default(),
             show_cascades: Default::default(),
+            cascade_resolution_scales: Default::default(),
+            budget_quality: Default::default(),
+            max_shadow_casting_lights: Default::default(),
             directional_views: Default::default(),
+            directional_transmittance_views: Default::default(),
             point_views: Default::default(),
             offsets: Default::default(),
+            visible_meshes: Default::default(),
+            translucent: Default::default(),
+            depth_range: Default::default(),
+            sdsm: Default::default(),
+            shadow_map_lights: Default::default(),
+            pipelines: Default::default(),
+            models: Default::default(),
         }
     }
 }
@@ -157,6 +279,342 @@ impl ShadowMappingNode {
         }
     }
 
+    pub fn cascade_resolution(&self, cascade: u32) -> u32 {
+        let scale = self
+            .cascade_resolution_scales
+            .get(cascade as usize)
+            .copied()
+            .unwrap_or(1.)
+            * match self.budget_quality {
+                Quality::Low => 0.5,
+                Quality::Medium => 0.75,
+                Quality::High => 1.,
+            };
+        ((self.config.dir_map_resolution as f32 * scale).round() as u32).max(1)
+    }
+
+    /// Size of each layer of the directional shadow map, fitting the largest cascade.
+    pub fn cascade_layer_resolution(&self) -> u32 {
+        (0..self.cascade_count())
+            .map(|cascade| self.cascade_resolution(cascade))
+            .max()
+            .unwrap_or(self.config.dir_map_resolution)
+    }
+
+    /// Projections of each cascade, sliced from the camera projection `proj`.
+    pub fn slice_frustum(&self, proj: CameraProjection) -> Vec<CameraProjection> {
+        match (&self.partitioning, self.depth_range) {
+            // Orthographic cameras can start at 0, where logarithmic splits break down.
+            (Some(ShadowMapPartitioning::SDSM(count)), Some(range)) if range.x > 0. => {
+                // Keep flat ranges from collapsing the cascades.
+                let far = range.y.max(range.x * 1.01);
+                frustum_slice_at(proj, &logarithmic_splits(range.x, far, *count))
+            }
+            _ => frustum_slice(proj, self.cascade_count(), 0.5),
+        }
+    }
+
+    /// Point and spot lights rendering shadow maps, the ones with the most intensity
+    /// reaching the camera when there are more than [`Self::max_shadow_casting_lights`].
+    pub fn shadow_casting_lights(&self, scene: &Scene) -> HashSet<Uuid> {
+        let camera = scene.camera.transform.translation;
+        let mut lights = scene
+            .point_lights
+            .iter()
+            .map(|(id, light)| (*id, light.position, light.intensity))
+            .chain(
+                scene
+                    .spot_lights
+                    .iter()
+                    .map(|(id, light)| (*id, light.position, light.intensity)),
+            )
+            .collect::<Vec<_>>();
+
+        if let Some(max) = self.max_shadow_casting_lights {
+            // Ids break ties, so the selection doesn't flicker with the map order.
+            let importance = |position: Vec3, intensity: f32| {
+                intensity / (1. + camera.distance_squared(position))
+            };
+            lights.sort_by(|(a_id, a_pos, a_int), (b_id, b_pos, b_int)| {
+                importance(*b_pos, *b_int)
+                    .total_cmp(&importance(*a_pos, *a_int))
+                    .then(a_id.cmp(b_id))
+            });
+            lights.truncate(max);
+        }
+
+        lights.into_iter().map(|(id, ..)| id).collect()
+    }
+
+    /// (Re)allocates the shadow maps for `dir_lights` directional and `point_lights` point
+    /// and spot lights.
+    fn create_shadow_maps(
+        &mut self,
+        device: &Device,
+        assets: &mut GpuAssets,
+        dir_lights: usize,
+        point_lights: usize,
+    ) {
+        let translucent = self.node_cfg.contains(ShadowMappingNodeConfig::TRANSLUCENT);
+        // Every directional light renders each cascade into its own layer. Like the point
+        // shadow map below, exactly 6 layers would be taken for a cube map on GL.
+        let mut layers = self.cascade_count().max(1) * dir_lights.max(1) as u32;
+        if layers == 6 {
+            layers += 1;
+        }
+
+        let directional_shadow_map = device.create_texture(&TextureDescriptor {
+            label: Some("directional_shadow_map"),
+            size: Extent3d {
+                width: self.cascade_layer_resolution(),
+                height: self.cascade_layer_resolution(),
+                depth_or_array_layers: layers,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::Depth32Float,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        let directional_shadow_map_view =
+            directional_shadow_map.create_view(&TextureViewDescriptor {
+                label: Some("directional_shadow_map_view"),
+                dimension: Some(TextureViewDimension::D2Array),
+                ..Default::default()
+            });
+
+        // Still bound when translucent shadows are disabled, so keep it tiny.
+        let transmittance_resolution = if translucent {
+            self.cascade_layer_resolution()
+        } else {
+            1
+        };
+        let directional_transmittance_map = device.create_texture(&TextureDescriptor {
+            label: Some("directional_transmittance_map"),
+            size: Extent3d {
+                width: transmittance_resolution,
+                height: transmittance_resolution,
+                depth_or_array_layers: layers,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: SHADOW_TRANSMITTANCE_FORMAT,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        let directional_transmittance_map_view =
+            directional_transmittance_map.create_view(&TextureViewDescriptor {
+                label: Some("directional_transmittance_map_view"),
+                dimension: Some(TextureViewDimension::D2Array),
+                ..Default::default()
+            });
+
+        let point_shadow_map = device.create_texture(&TextureDescriptor {
+            label: Some("point_shadow_map"),
+            size: Extent3d {
+                width: self.config.point_map_resolution,
+                height: self.config.point_map_resolution,
+                // The GL backend treats textures with exactly 6 layers as plain cube maps,
+                // which can't be viewed as cube arrays.
+                depth_or_array_layers: (point_lights as u32 * 6).max(12),
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::Depth32Float,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        let point_shadow_map_view = point_shadow_map.create_view(&TextureViewDescriptor {
+            label: Some("point_shadow_map_view"),
+            dimension: Some(TextureViewDimension::CubeArray),
+            aspect: TextureAspect::DepthOnly,
+            ..Default::default()
+        });
+
+        assets.textures.insert(
+            SHADOW_MAPPING.directional_shadow_map,
+            directional_shadow_map,
+        );
+        assets.texture_views.insert(
+            SHADOW_MAPPING.directional_shadow_map_view,
+            directional_shadow_map_view,
+        );
+        assets.textures.insert(
+            SHADOW_MAPPING.directional_transmittance_map,
+            directional_transmittance_map,
+        );
+        assets.texture_views.insert(
+            SHADOW_MAPPING.directional_transmittance_map_view,
+            directional_transmittance_map_view,
+        );
+        assets
+            .textures
+            .insert(SHADOW_MAPPING.point_shadow_map, point_shadow_map);
+        assets
+            .texture_views
+            .insert(SHADOW_MAPPING.point_shadow_map_view, point_shadow_map_view);
+
+        self.shadow_map_lights = (dir_lights, point_lights);
+    }
+
+    fn cull_meshes(node: &NodeContext, assets: &GpuAssets, frustum: Frustum) -> Vec<bool> {
+        node.meshes
+            .iter()
+            .map(|mesh| {
+                assets
+                    .gpu_meshes
+                    .get(&mesh.mesh.mesh)
+                    .and_then(|gpu_mesh| gpu_mesh.aabb)
+                    .map(|aabb| aabb.transformed(mesh.mesh.transform.model_matrix()))
+                    .is_none_or(|aabb| frustum.intersects_aabb(&aabb))
+            })
+            .collect()
+    }
+
+    fn build_sdsm(device: &Device, node: &NodeContext) -> SdsmData {
+        let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("sdsm_layout"),
+            entries: &[
+                // Depth
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: false },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                // Bounds
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("sdsm_pipeline_layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: Some("sdsm_pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &node.shaders[1],
+            entry_point: "depth_bounds",
+            compilation_options: Default::default(),
+            cache: node.pipeline_cache.as_deref(),
+        });
+
+        let bounds = device.create_buffer(&BufferDescriptor {
+            label: Some("sdsm_depth_bounds"),
+            size: SDSM_BOUNDS_SIZE,
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let readback = device.create_buffer(&BufferDescriptor {
+            label: Some("sdsm_depth_bounds_readback"),
+            size: SDSM_BOUNDS_SIZE,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        SdsmData {
+            pipeline,
+            layout,
+            bind_group: None,
+            bounds,
+            readback,
+            readback_state: SdsmReadback::Idle,
+            mapped: Default::default(),
+        }
+    }
+
+    /// Picks up the depth bounds of an earlier frame once they are mapped, then measures
+    /// the current one.
+    fn prepare_sdsm(
+        &mut self,
+        assets: &GpuAssets,
+        proj: CameraProjection,
+        device: &Device,
+        queue: &Queue,
+    ) {
+        let Some(sdsm) = &mut self.sdsm else {
+            return;
+        };
+
+        if sdsm.readback_state == SdsmReadback::Measuring {
+            let mapped = sdsm.mapped.clone();
+            sdsm.readback
+                .slice(..)
+                .map_async(MapMode::Read, move |result| {
+                    mapped.store(result.is_ok(), Ordering::Release);
+                });
+            sdsm.readback_state = SdsmReadback::Mapping;
+        }
+
+        if sdsm.readback_state == SdsmReadback::Mapping {
+            device.poll(Maintain::Poll);
+            if !sdsm.mapped.swap(false, Ordering::Acquire) {
+                return;
+            }
+
+            let bounds: [u32; 2] =
+                bytemuck::pod_read_unaligned(&sdsm.readback.slice(..).get_mapped_range());
+            sdsm.readback.unmap();
+            sdsm.readback_state = SdsmReadback::Idle;
+
+            let [min, max] = bounds.map(f32::from_bits);
+            // Nothing was drawn when the bounds are still at their initial values.
+            self.depth_range = (bounds[0] <= bounds[1]).then(|| {
+                let inv_proj = proj.compute_matrix().inverse();
+                let distance = |depth: f32| {
+                    let view = inv_proj * Vec4::new(0., 0., depth, 1.);
+                    -view.z / view.w
+                };
+                Vec2::new(distance(min), distance(max))
+            });
+        }
+
+        let Some(depth) = assets.texture_views.get(&DEPTH_PREPASS_TEXTURE.view) else {
+            sdsm.bind_group = None;
+            return;
+        };
+
+        queue.write_buffer(&sdsm.bounds, 0, bytemuck::cast_slice(&[u32::MAX, 0]));
+        sdsm.bind_group = Some(device.create_bind_group(&BindGroupDescriptor {
+            label: Some("sdsm_bind_group"),
+            layout: &sdsm.layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(depth),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: sdsm.bounds.as_entire_binding(),
+                },
+            ],
+        }));
+        sdsm.readback_state = SdsmReadback::Measuring;
+    }
+
     pub fn calculate_cascade_view(
         camera_transform: Transform,
         camera_proj_slice: CameraProjection,
@@ -210,13 +668,16 @@ impl ShadowMappingNode {
             proj: cascade_proj,
             inv_proj: cascade_proj.inverse(),
             position_ws: match camera_proj_slice {
-                CameraProjection::Perspective(_) => center,
+                CameraProjection::Perspective(_) | CameraProjection::AsymmetricPerspective(_) => {
+                    center
+                }
                 CameraProjection::Orthographic(_) => light_dir,
             },
             // SPECIAL USE CASE!!
             exposure: match camera_proj_slice {
                 CameraProjection::Perspective(proj) => proj.near,
                 CameraProjection::Orthographic(proj) => proj.near,
+                CameraProjection::AsymmetricPerspective(proj) => proj.near,
             },
         }
     }
@@ -236,6 +697,16 @@ impl RenderNode for ShadowMappingNode {
         *features |= Features::DEPTH_CLIP_CONTROL;
     }
 
+    fn cull_to_camera(&self) -> bool {
+        false
+    }
+
+    fn set_quality(&mut self, quality: Quality) -> bool {
+        let changed = self.budget_quality != quality;
+        self.budget_quality = quality;
+        changed
+    }
+
     fn require_shader_defs(&self, shader_defs: &mut HashMap<String, ShaderDefValue>) {
         shader_defs.extend([(
             "SHADOW_CASCADES".to_owned(),
@@ -249,10 +720,21 @@ impl RenderNode for ShadowMappingNode {
             );
         }
 
+        if self.node_cfg.contains(ShadowMappingNodeConfig::TRANSLUCENT) {
+            shader_defs.insert(
+                "TRANSLUCENT_SHADOWS".to_string(),
+                ShaderDefValue::Bool(true),
+            );
+        }
+
         if let Some(filtering) = &self.filtering {
             shader_defs.extend([filtering.to_def()]);
         }
 
+        if self.show_cascades {
+            shader_defs.insert("SHOW_CASCADES".to_string(), ShaderDefValue::Bool(true));
+        }
+
         match self.depth_biasing {
             DepthBiasing::NormalOffset => {
                 shader_defs.insert("NORMAL_OFFSET".to_string(), ShaderDefValue::Bool(true));
@@ -262,15 +744,18 @@ impl RenderNode for ShadowMappingNode {
     }
 
     fn require_shaders(&self) -> Option<&'static [(&'static [&'static str], &'static str)]> {
-        Some(&[(
-            &[
-                include_str!("../shader/math.wgsl"),
-                include_str!("../shader/common/common_type.wgsl"),
-                include_str!("../shader/common/common_binding.wgsl"),
-                include_str!("../shader/shadow/shadow_type.wgsl"),
-            ],
-            include_str!("../shader/shadow/shadow_render.wgsl"),
-        )])
+        Some(&[
+            (
+                &[
+                    include_str!("../shader/math.wgsl"),
+                    include_str!("../shader/common/common_type.wgsl"),
+                    include_str!("../shader/common/common_binding.wgsl"),
+                    include_str!("../shader/shadow/shadow_type.wgsl"),
+                ],
+                include_str!("../shader/shadow/shadow_render.wgsl"),
+            ),
+            (&[], include_str!("../shader/shadow/sdsm.wgsl")),
+        ])
     }
 
     fn build(
@@ -283,71 +768,42 @@ impl RenderNode for ShadowMappingNode {
             queue,
             node,
             targets,
+            material_override,
         }: RenderContext,
     ) {
-        let directional_shadow_map = device.create_texture(&TextureDescriptor {
-            label: Some("directional_shadow_map"),
-            size: Extent3d {
-                width: self.config.dir_map_resolution,
-                height: self.config.dir_map_resolution,
-                depth_or_array_layers: self.cascade_count().max(1),
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: TextureDimension::D2,
-            format: TextureFormat::Depth32Float,
-            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
-            view_formats: &[],
-        });
+        let translucent = self.node_cfg.contains(ShadowMappingNodeConfig::TRANSLUCENT);
 
-        let directional_shadow_map_view =
-            directional_shadow_map.create_view(&TextureViewDescriptor {
-                label: Some("directional_shadow_map_view"),
-                dimension: Some(TextureViewDimension::D2Array),
-                ..Default::default()
-            });
+        self.create_shadow_maps(
+            device,
+            assets,
+            original.dir_lights.len(),
+            self.shadow_casting_lights(original).len(),
+        );
 
-        let point_shadow_map = device.create_texture(&TextureDescriptor {
-            label: Some("point_shadow_map"),
-            size: Extent3d {
-                width: self.config.point_map_resolution,
-                height: self.config.point_map_resolution,
-                depth_or_array_layers: ((original.point_lights.len() as u32
-                    + original.spot_lights.len() as u32)
-                    * 6)
-                .max(6),
+        let shadow_map_sampler = create_sampler(
+            device,
+            &SamplerDescriptor {
+                label: Some("shadow_map_sampler"),
+                compare: Some(CompareFunction::LessEqual),
+                mag_filter: FilterMode::Linear,
+                min_filter: FilterMode::Linear,
+                mipmap_filter: FilterMode::Linear,
+                ..Default::default()
             },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: TextureDimension::D2,
-            format: TextureFormat::Depth32Float,
-            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
-            view_formats: &[],
-        });
-
-        let point_shadow_map_view = point_shadow_map.create_view(&TextureViewDescriptor {
-            label: Some("point_shadow_map_view"),
-            dimension: Some(TextureViewDimension::CubeArray),
-            aspect: TextureAspect::DepthOnly,
-            ..Default::default()
-        });
-
-        let shadow_map_sampler = device.create_sampler(&SamplerDescriptor {
-            label: Some("shadow_map_sampler"),
-            compare: Some(CompareFunction::LessEqual),
-            mag_filter: FilterMode::Linear,
-            min_filter: FilterMode::Linear,
-            mipmap_filter: FilterMode::Linear,
-            ..Default::default()
-        });
+            assets.max_anisotropy,
+        );
 
-        let shadow_texture_sampler = device.create_sampler(&SamplerDescriptor {
-            label: Some("shadow_texture_sampler"),
-            mag_filter: FilterMode::Linear,
-            min_filter: FilterMode::Linear,
-            mipmap_filter: FilterMode::Linear,
-            ..Default::default()
-        });
+        let shadow_texture_sampler = create_sampler(
+            device,
+            &SamplerDescriptor {
+                label: Some("shadow_texture_sampler"),
+                mag_filter: FilterMode::Linear,
+                min_filter: FilterMode::Linear,
+                mipmap_filter: FilterMode::Linear,
+                ..Default::default()
+            },
+            assets.max_anisotropy,
+        );
 
         let shadow_maps_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
             label: Some("shadow_maps_layout"),
@@ -434,6 +890,39 @@ impl RenderNode for ShadowMappingNode {
                     },
                     count: None,
                 },
+                // Cascade UV Scales
+                BindGroupLayoutEntry {
+                    binding: 8,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: Some(<f32 as encase::ShaderType>::min_size()),
+                    },
+                    count: None,
+                },
+                // Directional Light Transmittance Maps
+                BindGroupLayoutEntry {
+                    binding: 9,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: false },
+                        view_dimension: TextureViewDimension::D2Array,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                // Point Shadow Layers
+                BindGroupLayoutEntry {
+                    binding: 10,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: Some(<u32 as encase::ShaderType>::min_size()),
+                    },
+                    count: None,
+                },
             ],
         });
 
@@ -447,21 +936,6 @@ impl RenderNode for ShadowMappingNode {
             SHADOW_MAPPING.shadow_texture_sampler,
             shadow_texture_sampler,
         );
-        assets.textures.insert(
-            SHADOW_MAPPING.directional_shadow_map,
-            directional_shadow_map,
-        );
-        assets.texture_views.insert(
-            SHADOW_MAPPING.directional_shadow_map_view,
-            directional_shadow_map_view,
-        );
-        assets
-            .textures
-            .insert(SHADOW_MAPPING.point_shadow_map, point_shadow_map);
-        assets
-            .texture_views
-            .insert(SHADOW_MAPPING.point_shadow_map_view, point_shadow_map_view);
-
         let mut bf_poisson_disk = DynamicGpuBuffer::new(BufferUsages::STORAGE);
         let mut raw_poisson_disk = Vec::new();
         fast_poisson::Poisson2D::new()
@@ -487,6 +961,17 @@ impl RenderNode for ShadowMappingNode {
             .extra_buffers
             .insert(SHADOW_MAPPING.poisson_disk, bf_poisson_disk);
 
+        let layer_resolution = self.cascade_layer_resolution() as f32;
+        let raw_cascade_uv_scales = (0..self.cascade_count().max(1))
+            .map(|cascade| self.cascade_resolution(cascade) as f32 / layer_resolution)
+            .collect::<Vec<_>>();
+        let mut bf_cascade_uv_scales = DynamicGpuBuffer::new(BufferUsages::STORAGE);
+        bf_cascade_uv_scales.set(bytemuck::cast_slice(&raw_cascade_uv_scales).to_vec());
+        bf_cascade_uv_scales.write::<f32>(device, queue);
+        assets
+            .extra_buffers
+            .insert(SHADOW_MAPPING.cascade_uv_scales, bf_cascade_uv_scales);
+
         let mut bf_config = DynamicGpuBuffer::new(BufferUsages::UNIFORM);
         bf_config.push(&self.config);
         bf_config.write::<ShadowMappingConfig>(&device, &queue);
@@ -522,9 +1007,10 @@ impl RenderNode for ShadowMappingNode {
             ],
         });
 
+        let models = &*self.models.insert(ModelUniforms::new(device));
         let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
             label: Some("shadow_mapping_shader"),
-            bind_group_layouts: &[&light_view_layout],
+            bind_group_layouts: &[&light_view_layout, &models.layout],
             push_constant_ranges: &[],
         });
 
@@ -532,25 +1018,30 @@ impl RenderNode for ShadowMappingNode {
             .extra_layouts
             .insert(SHADOW_MAPPING.light_view_layout, light_view_layout);
 
-        for mesh in &node.meshes {
-            if node.pipelines.contains_key(&mesh.mesh.mesh) {
+        // Skinned meshes only have their interleaved vertices, see `DepthPrepassNode`.
+        let mesh_stride = |mesh: &MeshInstanceId| assets.meshes[mesh].vertex_stride();
+        let packed_stride = Mesh::POSITION_ATTR.format.size();
+
+        self.pipelines.clear();
+        for stride in node
+            .meshes
+            .iter()
+            .map(|mesh| mesh_stride(&mesh.mesh.mesh))
+            .chain([packed_stride])
+        {
+            if self.pipelines.contains_key(&stride) {
                 continue;
             };
 
-            let instance = &assets.meshes[&mesh.mesh.mesh];
             let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
                 label: Some("shadow_mapping_pipeline"),
                 layout: Some(&layout),
-                cache: None,
+                cache: node.pipeline_cache.as_deref(),
                 vertex: VertexState {
                     module: &node.shaders[0],
                     entry_point: "vertex",
                     compilation_options: PipelineCompilationOptions::default(),
-                    buffers: &[VertexBufferLayout {
-                        array_stride: instance.vertex_stride(),
-                        step_mode: VertexStepMode::Vertex,
-                        attributes: &instance.vertex_attributes(),
-                    }],
+                    buffers: &[Mesh::position_vertex_layout(stride)],
                 },
                 fragment: Some(FragmentState {
                     module: &node.shaders[0],
@@ -576,8 +1067,124 @@ impl RenderNode for ShadowMappingNode {
                 },
                 multiview: None,
             });
-            node.pipelines.insert(mesh.mesh.mesh, pipeline);
+            self.pipelines.insert(stride, pipeline);
+        }
+
+        // Reads the depth prepass as a single layer.
+        self.sdsm = match self.partitioning {
+            Some(ShadowMapPartitioning::SDSM(_)) if node.multiview.is_none() => {
+                Some(Self::build_sdsm(device, node))
+            }
+            _ => None,
+        };
+
+        if !translucent {
+            self.translucent = None;
+            return;
+        }
+
+        let transmittance_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("shadow_transmittance_layout"),
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::FRAGMENT,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: Some(<Vec4 as encase::ShaderType>::min_size()),
+                },
+                count: None,
+            }],
+        });
+
+        let translucent_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("translucent_shadow_mapping_shader"),
+            bind_group_layouts: &[
+                &assets.extra_layouts[&SHADOW_MAPPING.light_view_layout],
+                &models.layout,
+                &transmittance_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+
+        let strides = node
+            .meshes
+            .iter()
+            .filter(|mesh| {
+                mesh_material(original, material_override, &mesh.mesh)
+                    .and_then(|m| m.shadow_transmittance())
+                    .is_some()
+            })
+            .map(|mesh| mesh_stride(&mesh.mesh.mesh))
+            .chain([packed_stride]);
+
+        let mut pipelines = HashMap::new();
+        for stride in strides {
+            if pipelines.contains_key(&stride) {
+                continue;
+            }
+
+            let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+                label: Some("translucent_shadow_mapping_pipeline"),
+                layout: Some(&translucent_layout),
+                cache: node.pipeline_cache.as_deref(),
+                vertex: VertexState {
+                    module: &node.shaders[0],
+                    entry_point: "vertex",
+                    compilation_options: PipelineCompilationOptions::default(),
+                    buffers: &[Mesh::position_vertex_layout(stride)],
+                },
+                fragment: Some(FragmentState {
+                    module: &node.shaders[0],
+                    entry_point: "translucent_fragment",
+                    compilation_options: PipelineCompilationOptions::default(),
+                    targets: &[Some(ColorTargetState {
+                        format: SHADOW_TRANSMITTANCE_FORMAT,
+                        // Multiply the tints together, keep the nearest depth.
+                        blend: Some(BlendState {
+                            color: BlendComponent {
+                                src_factor: BlendFactor::Zero,
+                                dst_factor: BlendFactor::Src,
+                                operation: BlendOperation::Add,
+                            },
+                            alpha: BlendComponent {
+                                src_factor: BlendFactor::One,
+                                dst_factor: BlendFactor::One,
+                                operation: BlendOperation::Min,
+                            },
+                        }),
+                        write_mask: ColorWrites::ALL,
+                    })],
+                }),
+                multisample: MultisampleState::default(),
+                // Tested against opaque casters, so light blocked by them isn't tinted.
+                depth_stencil: Some(DepthStencilState {
+                    format: targets.depth_format.unwrap(),
+                    depth_write_enabled: false,
+                    depth_compare: CompareFunction::LessEqual,
+                    stencil: StencilState::default(),
+                    bias: DepthBiasState::default(),
+                }),
+                primitive: PrimitiveState {
+                    cull_mode: match self.depth_biasing {
+                        DepthBiasing::NormalOffset => None,
+                        DepthBiasing::SingleSideRendering => Some(Face::Front),
+                    },
+                    unclipped_depth: true,
+                    ..Default::default()
+                },
+                multiview: None,
+            });
+            pipelines.insert(stride, pipeline);
         }
+
+        self.translucent = Some(TranslucentShadowData {
+            pipelines,
+            layout: transmittance_layout,
+            transmittances: DynamicGpuBuffer::new(BufferUsages::UNIFORM),
+            bind_group: None,
+            offsets: Vec::new(),
+        });
     }
 
     fn prepare(
@@ -585,7 +1192,13 @@ impl RenderNode for ShadowMappingNode {
         GpuScene {
             original, assets, ..
         }: &mut GpuScene,
-        RenderContext { device, queue, .. }: RenderContext,
+        RenderContext {
+            device,
+            queue,
+            node,
+            material_override,
+            ..
+        }: RenderContext,
     ) {
         let mut directional_index = 0;
         let mut point_index = 0;
@@ -600,6 +1213,14 @@ impl RenderNode for ShadowMappingNode {
             ..Default::default()
         };
 
+        let mut transmittance_desc = TextureViewDescriptor {
+            label: Some("directional_transmittance_map_render_view"),
+            dimension: Some(TextureViewDimension::D2),
+            base_array_layer: 0,
+            array_layer_count: Some(1),
+            ..Default::default()
+        };
+
         let mut point_desc = TextureViewDescriptor {
             label: Some("point_shadow_map_render_view"),
             format: Some(TextureFormat::Depth32Float),
@@ -610,14 +1231,31 @@ impl RenderNode for ShadowMappingNode {
             ..Default::default()
         };
 
+        let shadow_casting = self.shadow_casting_lights(original);
+        let lights = (original.dir_lights.len(), shadow_casting.len());
+        if lights != self.shadow_map_lights {
+            self.create_shadow_maps(device, assets, lights.0, lights.1);
+        }
+        self.visible_meshes.clear();
+        self.point_views.clear();
+        if let Some(models) = &mut self.models {
+            models.prepare(device, queue, &mut node.meshes);
+        }
+        // Point shadow map of each point then spot light, `u32::MAX` for unshadowed ones.
+        let mut point_shadow_layers = Vec::new();
+
         let mut raw_cascade_views = Vec::new();
-        let mut bf_point_light_view = DynamicGpuBuffer::new(BufferUsages::STORAGE);
+        // Indexed as a tightly packed array, so pushing would pad each view to the alignment.
+        let mut raw_point_light_views = Vec::new();
         let mut bf_light_views = DynamicGpuBuffer::new(BufferUsages::UNIFORM);
 
         let directional_shadow_maps = &assets.textures[&SHADOW_MAPPING.directional_shadow_map];
+        let directional_transmittance_maps =
+            &assets.textures[&SHADOW_MAPPING.directional_transmittance_map];
         let point_shadow_maps = &assets.textures[&SHADOW_MAPPING.point_shadow_map];
 
-        let sliced_frustums = frustum_slice(original.camera.projection, self.cascade_count(), 0.5);
+        self.prepare_sdsm(assets, original.camera.projection, device, queue);
+        let sliced_frustums = self.slice_frustum(original.camera.projection);
 
         for (id, light) in &original.dir_lights {
             let cascade_views = sliced_frustums.clone().into_iter().map(|proj| {
@@ -625,6 +1263,7 @@ impl RenderNode for ShadowMappingNode {
             });
 
             let mut cascade_maps = Vec::new();
+            let mut transmittance_maps = Vec::new();
 
             for cascade_view in cascade_views {
                 directional_desc.base_array_layer = directional_index;
@@ -636,16 +1275,73 @@ impl RenderNode for ShadowMappingNode {
                     directional_shadow_maps.create_view(&directional_desc),
                 );
 
+                if self.translucent.is_some() {
+                    transmittance_desc.base_array_layer = directional_index;
+                    let texture_view_id = TextureViewId(Uuid::new_v4());
+                    transmittance_maps.push(texture_view_id);
+
+                    assets.texture_views.insert(
+                        texture_view_id,
+                        directional_transmittance_maps.create_view(&transmittance_desc),
+                    );
+                }
+
                 // bf_cascade_views.push(&cascade_view);
                 raw_cascade_views.extend_from_slice(bytemuck::bytes_of(&cascade_view));
                 self.offsets.push(bf_light_views.push(&cascade_view));
+                if node.culling {
+                    let mut frustum = cascade_view.frustum();
+                    // Casters between the light and the cascade are clamped onto its near
+                    // plane, as depth is unclipped.
+                    frustum.planes[4] = Vec4::ZERO;
+                    self.visible_meshes
+                        .push(Self::cull_meshes(node, assets, frustum));
+                }
                 directional_index += 1;
             }
 
             self.directional_views.insert(*id, cascade_maps);
+            self.directional_transmittance_views
+                .insert(*id, transmittance_maps);
+        }
+
+        if let Some(TranslucentShadowData {
+            layout,
+            transmittances,
+            bind_group,
+            offsets,
+            ..
+        }) = &mut self.translucent
+        {
+            transmittances.clear();
+            *offsets = node
+                .meshes
+                .iter()
+                .map(|mesh| {
+                    mesh_material(original, material_override, &mesh.mesh)
+                        .and_then(|m| m.shadow_transmittance())
+                        .map(|t| transmittances.push(&t.extend(1.)))
+                })
+                .collect();
+            transmittances.write::<Vec4>(device, queue);
+
+            *bind_group = Some(device.create_bind_group(&BindGroupDescriptor {
+                label: Some("shadow_transmittance_bind_group"),
+                layout,
+                entries: &[BindGroupEntry {
+                    binding: 0,
+                    resource: transmittances.binding::<Vec4>().unwrap(),
+                }],
+            }));
         }
 
         for (id, light) in &original.point_lights {
+            if !shadow_casting.contains(id) {
+                point_shadow_layers.push(u32::MAX);
+                continue;
+            }
+            point_shadow_layers.push(point_index);
+
             let light_views = light.light_view();
             let mut texture_views = [TextureViewId::default(); 6];
 
@@ -657,8 +1353,15 @@ impl RenderNode for ShadowMappingNode {
                     .texture_views
                     .insert(texture_view_id, point_shadow_maps.create_view(&point_desc));
 
-                bf_point_light_view.push(&light_views[i_face]);
+                raw_point_light_views.extend_from_slice(bytemuck::bytes_of(&light_views[i_face]));
                 self.offsets.push(bf_light_views.push(&light_views[i_face]));
+                if node.culling {
+                    self.visible_meshes.push(Self::cull_meshes(
+                        node,
+                        assets,
+                        light_views[i_face].frustum(),
+                    ));
+                }
             }
 
             self.point_views.insert(*id, texture_views);
@@ -666,6 +1369,12 @@ impl RenderNode for ShadowMappingNode {
         }
 
         for (id, light) in &original.spot_lights {
+            if !shadow_casting.contains(id) {
+                point_shadow_layers.push(u32::MAX);
+                continue;
+            }
+            point_shadow_layers.push(point_index);
+
             let light_views = light.light_view();
             let mut texture_views = [TextureViewId::default(); 6];
 
@@ -677,8 +1386,15 @@ impl RenderNode for ShadowMappingNode {
                     .texture_views
                     .insert(texture_view_id, point_shadow_maps.create_view(&point_desc));
 
-                bf_point_light_view.push(&light_views[i_face]);
+                raw_point_light_views.extend_from_slice(bytemuck::bytes_of(&light_views[i_face]));
                 self.offsets.push(bf_light_views.push(&light_views[i_face]));
+                if node.culling {
+                    self.visible_meshes.push(Self::cull_meshes(
+                        node,
+                        assets,
+                        light_views[i_face].frustum(),
+                    ));
+                }
             }
 
             self.point_views.insert(*id, texture_views);
@@ -687,6 +1403,11 @@ impl RenderNode for ShadowMappingNode {
 
         let mut bf_cascade_views = DynamicGpuBuffer::new(BufferUsages::STORAGE);
         bf_cascade_views.set(raw_cascade_views);
+        let mut bf_point_light_view = DynamicGpuBuffer::new(BufferUsages::STORAGE);
+        bf_point_light_view.set(raw_point_light_views);
+        let mut bf_point_shadow_layers = DynamicGpuBuffer::new(BufferUsages::STORAGE);
+        bf_point_shadow_layers.set(bytemuck::cast_slice(&point_shadow_layers).to_vec());
+        bf_point_shadow_layers.write::<u32>(device, queue);
 
         bf_cascade_views.write::<GpuCamera>(&device, &queue);
         bf_point_light_view.write::<GpuCamera>(&device, &queue);
@@ -698,6 +1419,9 @@ impl RenderNode for ShadowMappingNode {
         assets
             .extra_buffers
             .insert(SHADOW_MAPPING.point_light_views, bf_point_light_view);
+        assets
+            .extra_buffers
+            .insert(SHADOW_MAPPING.point_shadow_layers, bf_point_shadow_layers);
         assets
             .extra_buffers
             .insert(SHADOW_MAPPING.light_views, bf_light_views);
@@ -778,34 +1502,61 @@ impl RenderNode for ShadowMappingNode {
                             .entire_binding()
                             .unwrap(),
                     },
+                    BindGroupEntry {
+                        binding: 8,
+                        resource: assets.extra_buffers[&SHADOW_MAPPING.cascade_uv_scales]
+                            .entire_binding()
+                            .unwrap(),
+                    },
+                    BindGroupEntry {
+                        binding: 9,
+                        resource: BindingResource::TextureView(
+                            &assets.texture_views
+                                [&SHADOW_MAPPING.directional_transmittance_map_view],
+                        ),
+                    },
+                    BindGroupEntry {
+                        binding: 10,
+                        resource: assets.extra_buffers[&SHADOW_MAPPING.point_shadow_layers]
+                            .entire_binding()
+                            .unwrap(),
+                    },
                 ],
             }),
         );
     }
 
-    fn draw(
+    fn records(&self) -> bool {
+        true
+    }
+
+    fn parallel_safe(&self) -> bool {
+        true
+    }
+
+    fn record(
         &self,
         GpuScene {
             original, assets, ..
-        }: &mut GpuScene,
+        }: &GpuScene,
         RenderContext {
             device,
-            queue,
             node,
+            targets,
             ..
         }: RenderContext,
-    ) {
-        let Some(light_view_bind_groups) = assets
+    ) -> Option<CommandBuffer> {
+        let light_view_bind_groups = assets
             .extra_bind_groups
-            .get(&SHADOW_MAPPING.light_views_bind_group)
-        else {
-            return;
-        };
+            .get(&SHADOW_MAPPING.light_views_bind_group)?;
+        let b_model = self.models.as_ref()?.bind_group.as_ref()?;
 
         let mut view_index = 0;
         let mut encoder = device.create_command_encoder(&Default::default());
 
-        let mut _draw = |depth_view: &TextureView| {
+        let mut _draw = |depth_view: &TextureView,
+                         resolution: Option<u32>,
+                         transmittance_view: Option<&TextureView>| {
             let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
                 label: Some("shadow_pass"),
                 color_attachments: &[None],
@@ -820,18 +1571,39 @@ impl RenderNode for ShadowMappingNode {
                 ..Default::default()
             });
 
+            if let Some(resolution) = resolution {
+                pass.set_viewport(0., 0., resolution as f32, resolution as f32, 0., 1.);
+            }
             pass.set_bind_group(0, light_view_bind_groups, &[self.offsets[view_index]]);
+            let visible = self.visible_meshes.get(view_index);
 
-            for mesh in &node.meshes {
-                let (Some(pipeline), Some(instance)) = (
-                    node.pipelines.get(&mesh.mesh.mesh),
-                    assets.gpu_meshes.get(&mesh.mesh.mesh),
-                ) else {
+            for (i_mesh, mesh) in node.meshes.iter().enumerate() {
+                if visible.is_some_and(|visible| !visible[i_mesh]) {
+                    continue;
+                }
+
+                // Translucent casters are drawn into the transmittance map instead.
+                if transmittance_view.is_some()
+                    && self
+                        .translucent
+                        .as_ref()
+                        .is_some_and(|t| t.offsets.get(i_mesh).copied().flatten().is_some())
+                {
+                    continue;
+                }
+
+                let Some(instance) = assets.gpu_meshes.get(&mesh.mesh.mesh) else {
+                    continue;
+                };
+                let (positions, stride) =
+                    instance.position_vertices(assets.meshes[&mesh.mesh.mesh].vertex_stride());
+                let Some(pipeline) = self.pipelines.get(&stride) else {
                     continue;
                 };
 
                 pass.set_pipeline(pipeline);
-                pass.set_vertex_buffer(0, instance.vertex_buffer.slice(..));
+                pass.set_bind_group(1, b_model, &[mesh.model_offset.unwrap()]);
+                pass.set_vertex_buffer(0, positions);
                 if let Some(indices) = &instance.index_buffer {
                     pass.set_index_buffer(indices.buffer.slice(..), indices.format);
                     pass.draw_indexed(0..indices.count, 0, 0..1);
@@ -840,12 +1612,88 @@ impl RenderNode for ShadowMappingNode {
                 }
             }
 
+            drop(pass);
+
+            if let (
+                Some(transmittance_view),
+                Some(TranslucentShadowData {
+                    pipelines,
+                    bind_group: Some(bind_group),
+                    offsets,
+                    ..
+                }),
+            ) = (transmittance_view, &self.translucent)
+            {
+                let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                    label: Some("translucent_shadow_pass"),
+                    color_attachments: &[Some(RenderPassColorAttachment {
+                        view: transmittance_view,
+                        resolve_target: None,
+                        ops: Operations {
+                            load: LoadOp::Clear(Color::WHITE),
+                            store: StoreOp::Store,
+                        },
+                    })],
+                    depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                        view: depth_view,
+                        depth_ops: Some(Operations {
+                            load: LoadOp::Load,
+                            store: StoreOp::Store,
+                        }),
+                        stencil_ops: None,
+                    }),
+                    ..Default::default()
+                });
+
+                if let Some(resolution) = resolution {
+                    pass.set_viewport(0., 0., resolution as f32, resolution as f32, 0., 1.);
+                }
+                pass.set_bind_group(0, light_view_bind_groups, &[self.offsets[view_index]]);
+
+                for (i_mesh, (mesh, offset)) in node.meshes.iter().zip(offsets).enumerate() {
+                    if visible.is_some_and(|visible| !visible[i_mesh]) {
+                        continue;
+                    }
+
+                    let (Some(offset), Some(instance)) =
+                        (offset, assets.gpu_meshes.get(&mesh.mesh.mesh))
+                    else {
+                        continue;
+                    };
+                    let (positions, stride) =
+                        instance.position_vertices(assets.meshes[&mesh.mesh.mesh].vertex_stride());
+                    let Some(pipeline) = pipelines.get(&stride) else {
+                        continue;
+                    };
+
+                    pass.set_pipeline(pipeline);
+                    pass.set_bind_group(1, b_model, &[mesh.model_offset.unwrap()]);
+                    pass.set_bind_group(2, bind_group, &[*offset]);
+                    pass.set_vertex_buffer(0, positions);
+                    if let Some(indices) = &instance.index_buffer {
+                        pass.set_index_buffer(indices.buffer.slice(..), indices.format);
+                        pass.draw_indexed(0..indices.count, 0, 0..1);
+                    } else {
+                        pass.draw(0..instance.vertices_count, 0..1);
+                    }
+                }
+            }
+
             view_index += 1;
         };
 
         for id in original.dir_lights.keys() {
-            for texture_view_id in &self.directional_views[id] {
-                _draw(&assets.texture_views[&texture_view_id]);
+            for (cascade, texture_view_id) in self.directional_views[id].iter().enumerate() {
+                let transmittance_view = self
+                    .directional_transmittance_views
+                    .get(id)
+                    .and_then(|views| views.get(cascade))
+                    .map(|view| &assets.texture_views[view]);
+                _draw(
+                    &assets.texture_views[&texture_view_id],
+                    Some(self.cascade_resolution(cascade as u32)),
+                    transmittance_view,
+                );
             }
         }
 
@@ -854,11 +1702,717 @@ impl RenderNode for ShadowMappingNode {
             .keys()
             .chain(original.spot_lights.keys())
         {
-            for texture_view_id in &self.point_views[id] {
-                _draw(&assets.texture_views[&texture_view_id]);
+            // Lights over `max_shadow_casting_lights` have no views.
+            for texture_view_id in self.point_views.get(id).into_iter().flatten() {
+                _draw(&assets.texture_views[&texture_view_id], None, None);
+            }
+        }
+
+        if let Some(SdsmData {
+            pipeline,
+            bind_group: Some(bind_group),
+            bounds,
+            readback,
+            readback_state: SdsmReadback::Measuring,
+            ..
+        }) = &self.sdsm
+        {
+            {
+                let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+                    label: Some("sdsm_pass"),
+                    ..Default::default()
+                });
+
+                pass.set_pipeline(pipeline);
+                pass.set_bind_group(0, bind_group, &[]);
+                pass.dispatch_workgroups(
+                    targets.size.x.div_ceil(SDSM_WORKGROUP_SIZE),
+                    targets.size.y.div_ceil(SDSM_WORKGROUP_SIZE),
+                    1,
+                );
+            }
+
+            encoder.copy_buffer_to_buffer(bounds, 0, readback, 0, SDSM_BOUNDS_SIZE);
+        }
+
+        Some(encoder.finish())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{f32::consts::PI, sync::Arc};
+
+    use aurora_core::{
+        render::{
+            flow::{GeneralNode, ImageFallbackNode, RenderFlow, RenderNode},
+            helper::{CameraProjection, OrthographicProjection, Scene},
+            mesh::{Mesh, MeshIndices, MeshVertexAttributeData, StaticMesh, DEFAULT_RENDER_LAYER},
+            resource::{GpuDirectionalLight, GpuPointLight, GpuSpotLight},
+            scene::{GpuScene, MaterialInstanceId, MeshInstanceId},
+        },
+        util::testing::{self, TestTargets},
+        WgpuRenderer,
+    };
+    use glam::{UVec2, Vec2, Vec3};
+    use image::RgbaImage;
+    use uuid::Uuid;
+    use wgpu::{Backend, Features, Limits, Maintain, TextureFormat};
+
+    use super::{
+        DepthBiasing, ShadowMapPartitioning, ShadowMappingConfig, ShadowMappingNode, SHADOW_MAPPING,
+    };
+    use crate::{
+        material::PbrMaterial,
+        node::{DepthPrepassNode, PbrNode, PbrNodeConfig},
+        shader_defs::ShadowFiltering,
+    };
+
+    /// Shadow maps take a bind group of the pbr node on top of the default ones.
+    fn pbr_limits() -> Limits {
+        let mut limits = Limits::default();
+        PbrNode::default().require_renderer_limits(&mut limits);
+        limits
+    }
+
+    const SIZE: u32 = 64;
+
+    /// Quad in the xy plane at `z` facing +Z, optionally wound both ways so it casts shadows
+    /// with front faces culled.
+    fn quad(min: Vec2, max: Vec2, z: f32, double_sided: bool) -> Mesh {
+        let quad = testing::quad(min, max, z);
+        if double_sided {
+            quad.with_indices(MeshIndices::UInt32(vec![
+                0, 1, 2, 0, 2, 3, 0, 2, 1, 0, 3, 2,
+            ]))
+        } else {
+            quad
+        }
+    }
+
+    fn sphere(center: Vec3, radius: f32, rings: u32, segments: u32) -> Mesh {
+        let mut normals = Vec::new();
+        let mut uvs = Vec::new();
+        for ring in 0..=rings {
+            for segment in 0..=segments {
+                let uv = Vec2::new(segment as f32 / segments as f32, ring as f32 / rings as f32);
+                let (theta, phi) = (uv.y * PI, uv.x * 2. * PI);
+                normals.push(Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                ));
+                uvs.push(uv);
+            }
+        }
+
+        let mut indices = Vec::new();
+        for ring in 0..rings {
+            for segment in 0..segments {
+                let i = ring * (segments + 1) + segment;
+                let below = i + segments + 1;
+                indices.extend([i, below + 1, below, i, i + 1, below + 1]);
+            }
+        }
+
+        let mut mesh = Mesh::new()
+            .with_attribute(
+                Mesh::POSITION_ATTR,
+                MeshVertexAttributeData::Float32x3(
+                    normals.iter().map(|n| center + *n * radius).collect(),
+                ),
+            )
+            .with_attribute(
+                Mesh::NORMAL_ATTR,
+                MeshVertexAttributeData::Float32x3(normals),
+            )
+            .with_attribute(
+                Mesh::TEX_COORDS_ATTR,
+                MeshVertexAttributeData::Float32x2(uvs),
+            )
+            .with_indices(MeshIndices::UInt32(indices));
+        mesh.recalculate_tangent().unwrap();
+        mesh
+    }
+
+    /// A floor facing the camera, lit from the right by a light hidden behind an occluder
+    /// that sits outside of the view.
+    fn render(
+        renderer: &WgpuRenderer,
+        shadow_mapping: Option<ShadowMappingNode>,
+        spot: bool,
+        node_cfg: PbrNodeConfig,
+    ) -> RgbaImage {
+        let mut scene = GpuScene::default();
+        let material_id = MaterialInstanceId(Uuid::from_u128(1));
+        scene
+            .original
+            .materials
+            .insert(material_id, Arc::new(PbrMaterial::default()));
+
+        let meshes = [
+            quad(Vec2::new(-3., -2.), Vec2::new(3., 2.), -5., false),
+            quad(Vec2::new(1.2, -3.), Vec2::new(3., 3.), -3.5, true),
+        ];
+        for (i, mesh) in meshes.into_iter().enumerate() {
+            let mesh_id = MeshInstanceId(Uuid::from_u128(i as u128 + 2));
+            scene.assets.meshes.insert(mesh_id, mesh);
+            scene.static_meshes.push(StaticMesh {
+                mesh: mesh_id,
+                material: material_id,
+                render_layer: DEFAULT_RENDER_LAYER,
+                transform: Default::default(),
+            });
+        }
+
+        let position = Vec3::new(2., 0., -2.);
+        if spot {
+            // Spot lights come after every point light in the shadow map, so keep one around.
+            scene.original.point_lights.insert(
+                Uuid::from_u128(10),
+                GpuPointLight {
+                    position: Vec3::new(0., 0., 5.),
+                    color: Vec3::ONE,
+                    intensity: 0.,
+                    radius: 0.,
+                },
+            );
+            scene.original.spot_lights.insert(
+                Uuid::from_u128(11),
+                GpuSpotLight {
+                    position,
+                    direction: Vec3::Z,
+                    color: Vec3::ONE,
+                    intensity: 2000.,
+                    radius: 0.1,
+                    inner_angle: 1.,
+                    outer_angle: 1.5,
+                },
+            );
+        } else {
+            scene.original.point_lights.insert(
+                Uuid::from_u128(10),
+                GpuPointLight {
+                    position,
+                    color: Vec3::ONE,
+                    intensity: 2000.,
+                    radius: 0.1,
+                },
+            );
+        }
+        scene.original.camera.projection =
+            CameraProjection::Orthographic(OrthographicProjection::symmetric(2., 2., 0.1, 10.));
+
+        render_scene(renderer, scene, shadow_mapping, node_cfg)
+    }
+
+    fn render_scene(
+        renderer: &WgpuRenderer,
+        mut scene: GpuScene,
+        shadow_mapping: Option<ShadowMappingNode>,
+        node_cfg: PbrNodeConfig,
+    ) -> RgbaImage {
+        let size = UVec2::splat(SIZE);
+        let test_targets = TestTargets::new(&renderer.device, size, TextureFormat::Rgba8Unorm)
+            .with_depth(&renderer.device);
+        let targets = test_targets.targets();
+
+        let mut flow = RenderFlow::default();
+        flow.add::<GeneralNode>()
+            .add::<ImageFallbackNode>()
+            .add::<DepthPrepassNode>();
+        if let Some(shadow_mapping) = shadow_mapping {
+            flow.add_initialized(shadow_mapping);
+        }
+        flow.add_initialized(PbrNode {
+            node_cfg,
+            ..Default::default()
+        });
+        flow.set_queue(scene.static_meshes.clone());
+        flow.build(renderer, &mut scene, None, &targets).unwrap();
+        flow.run(renderer, &mut scene, &targets);
+
+        test_targets.read(&renderer)
+    }
+
+    #[test]
+    fn point_and_spot_shadows() {
+        let features = Some(Features::DEPTH_CLIP_CONTROL);
+        let Some(renderer) = testing::renderer(features, Some(pbr_limits())) else {
+            return;
+        };
+
+        for spot in [false, true] {
+            let mut filterings = vec![None, Some(ShadowFiltering::PCF)];
+            // PCSS samples the shadow map with two samplers, which GLSL doesn't support.
+            if renderer.adapter.get_info().backend != Backend::Gl {
+                filterings.push(Some(ShadowFiltering::PCSS));
             }
+
+            for filtering in filterings {
+                let shadow_mapping = ShadowMappingNode {
+                    filtering,
+                    ..Default::default()
+                };
+                let image = render(
+                    &renderer,
+                    Some(shadow_mapping),
+                    spot,
+                    PbrNodeConfig::SHADOW_MAPPING,
+                );
+                let lit = image.get_pixel(SIZE / 8, SIZE / 2)[0];
+                let shadowed = image.get_pixel(SIZE * 7 / 8, SIZE / 2)[0];
+                // Without the occluder the right side is closer to the light and brighter.
+                assert!(lit > 5, "{lit}");
+                assert!(shadowed < lit / 4, "{shadowed} {lit}");
+            }
+        }
+    }
+
+    #[test]
+    fn max_shadow_casting_lights() {
+        let node = ShadowMappingNode {
+            max_shadow_casting_lights: Some(2),
+            ..Default::default()
+        };
+        let mut scene = Scene::default();
+        for (i, (z, intensity)) in [(1., 100.), (10., 1000.), (2., 50.)]
+            .into_iter()
+            .enumerate()
+        {
+            scene.point_lights.insert(
+                Uuid::from_u128(i as u128),
+                GpuPointLight {
+                    position: Vec3::new(0., 0., z),
+                    color: Vec3::ONE,
+                    intensity,
+                    radius: 0.,
+                },
+            );
+        }
+        // Close lights win over brighter distant ones.
+        let selected = node.shadow_casting_lights(&scene);
+        assert_eq!(selected, [0, 2].map(Uuid::from_u128).into());
+
+        let features = Some(Features::DEPTH_CLIP_CONTROL);
+        let Some(renderer) = testing::renderer(features, Some(pbr_limits())) else {
+            return;
+        };
+
+        let render = |max_shadow_casting_lights| {
+            let shadow_mapping = ShadowMappingNode {
+                filtering: None,
+                max_shadow_casting_lights,
+                ..Default::default()
+            };
+            // The spot light is brighter than the point light before it, so it takes the
+            // only shadow map.
+            let image = render(
+                &renderer,
+                Some(shadow_mapping),
+                true,
+                PbrNodeConfig::SHADOW_MAPPING,
+            );
+            let lit = image.get_pixel(SIZE / 8, SIZE / 2)[0];
+            let shadowed = image.get_pixel(SIZE * 7 / 8, SIZE / 2)[0];
+            (lit, shadowed)
+        };
+
+        let (lit, shadowed) = render(Some(1));
+        assert!(shadowed < lit / 4, "{shadowed} {lit}");
+        // Still lit, without the occluder's shadow.
+        let (lit, unshadowed) = render(Some(0));
+        assert!(unshadowed > lit, "{unshadowed} {lit}");
+    }
+
+    #[test]
+    fn shadows_disabled_per_flow() {
+        let features = Some(Features::DEPTH_CLIP_CONTROL);
+        let Some(renderer) = testing::renderer(features, Some(pbr_limits())) else {
+            return;
+        };
+
+        // With the shadow maps still rendered but ignored, and without them at all.
+        for shadow_mapping in [Some(ShadowMappingNode::default()), None] {
+            let image = render(&renderer, shadow_mapping, false, PbrNodeConfig::empty());
+            let lit = image.get_pixel(SIZE / 8, SIZE / 2)[0];
+            let unshadowed = image.get_pixel(SIZE * 7 / 8, SIZE / 2)[0];
+            assert!(lit > 5, "{lit}");
+            // Closer to the light, so brighter now that the occluder is ignored.
+            assert!(unshadowed > lit, "{unshadowed} {lit}");
+        }
+    }
+
+    /// A sphere resting on a floor facing the camera, lit at a grazing angle from the right.
+    fn render_sphere_on_plane(
+        renderer: &WgpuRenderer,
+        depth_biasing: DepthBiasing,
+        normal_offset_scale: f32,
+    ) -> RgbaImage {
+        let mut scene = GpuScene::default();
+        let material_id = MaterialInstanceId(Uuid::from_u128(1));
+        scene
+            .original
+            .materials
+            .insert(material_id, Arc::new(PbrMaterial::default()));
+
+        let meshes = [
+            quad(Vec2::splat(-3.), Vec2::splat(3.), -5., false),
+            sphere(Vec3::new(0.3, 0., -4.6), 0.4, 16, 32),
+        ];
+        for (i, mesh) in meshes.into_iter().enumerate() {
+            let mesh_id = MeshInstanceId(Uuid::from_u128(i as u128 + 2));
+            scene.assets.meshes.insert(mesh_id, mesh);
+            scene.static_meshes.push(StaticMesh {
+                mesh: mesh_id,
+                material: material_id,
+                render_layer: DEFAULT_RENDER_LAYER,
+                transform: Default::default(),
+            });
+        }
+        scene.original.dir_lights.insert(
+            Uuid::from_u128(10),
+            GpuDirectionalLight {
+                direction: Vec3::new(1., 0., 0.15).normalize(),
+                color: Vec3::ONE,
+                intensity: 300.,
+                radius: 0.,
+            },
+        );
+        scene.original.camera.projection =
+            CameraProjection::Orthographic(OrthographicProjection::symmetric(2., 2., 0.1, 10.));
+
+        render_scene(
+            renderer,
+            scene,
+            Some(ShadowMappingNode {
+                config: ShadowMappingConfig {
+                    normal_offset_scale,
+                    ..Default::default()
+                },
+                filtering: None,
+                depth_biasing,
+                ..Default::default()
+            }),
+            PbrNodeConfig::SHADOW_MAPPING,
+        )
+    }
+
+    #[test]
+    fn normal_offset_sphere_on_plane() {
+        let features = Some(Features::DEPTH_CLIP_CONTROL);
+        let Some(renderer) = testing::renderer(features, Some(pbr_limits())) else {
+            return;
+        };
+
+        let before = render_sphere_on_plane(&renderer, DepthBiasing::NormalOffset, 0.);
+        let after = render_sphere_on_plane(
+            &renderer,
+            DepthBiasing::NormalOffset,
+            ShadowMappingConfig::default().normal_offset_scale,
+        );
+        // Kept around to compare by eye.
+        let dir = std::env::temp_dir();
+        before.save(dir.join("normal_offset_before.png")).unwrap();
+        after.save(dir.join("normal_offset_after.png")).unwrap();
+
+        // The lit floor above the sphere, acne shows up as dark stripes across it.
+        let contrast = |image: &RgbaImage| {
+            let row = (0..SIZE).map(|x| image.get_pixel(x, SIZE / 8)[0]);
+            let (min, max) = row.fold((u8::MAX, 0), |(min, max), r| (min.min(r), max.max(r)));
+            min as f32 / max.max(1) as f32
+        };
+        assert!(contrast(&before) < 0.5, "{}", contrast(&before));
+        assert!(contrast(&after) > 0.5, "{}", contrast(&after));
+
+        // Still casts a shadow right next to the sphere.
+        let lit = after.get_pixel(SIZE / 4, SIZE / 8)[0];
+        let shadowed = after.get_pixel(SIZE / 4, SIZE / 2)[0];
+        assert!(shadowed < lit / 4, "{shadowed} {lit}");
+    }
+
+    fn slice_ranges(slices: &[CameraProjection]) -> Vec<(f32, f32)> {
+        slices
+            .iter()
+            .map(|slice| match slice {
+                CameraProjection::Orthographic(proj) => (proj.near, proj.far),
+                _ => unreachable!(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sdsm_splits_shrink_with_depth_range() {
+        let proj =
+            CameraProjection::Orthographic(OrthographicProjection::symmetric(2., 2., 0.1, 10.));
+        let mut node = ShadowMappingNode {
+            partitioning: Some(ShadowMapPartitioning::SDSM(3)),
+            ..Default::default()
+        };
+
+        // Not measured yet, so the whole frustum is sliced.
+        let full = slice_ranges(&node.slice_frustum(proj));
+        node.depth_range = Some(Vec2::new(4., 6.));
+        let fitted = slice_ranges(&node.slice_frustum(proj));
+
+        assert_eq!(fitted.len(), 3);
+        assert!((fitted[0].0 - 4.).abs() < 1e-4 && (fitted[2].1 - 6.).abs() < 1e-4);
+        for ((near, far), (full_near, full_far)) in fitted.iter().zip(&full) {
+            assert!(far - near < full_far - full_near, "{fitted:?} {full:?}");
+        }
+    }
+
+    /// Pixels on the middle row mixing the colors of two cascades.
+    fn cascade_blend_pixels(renderer: &WgpuRenderer, cascade_blend_fraction: f32) -> usize {
+        let mut scene = GpuScene::default();
+        let material_id = MaterialInstanceId(Uuid::from_u128(1));
+        scene
+            .original
+            .materials
+            .insert(material_id, Arc::new(PbrMaterial::default()));
+
+        // Receding from left to right through every cascade.
+        let normal = Vec3::new(4., 0., 1.).normalize();
+        let mut floor = Mesh::new()
+            .with_attribute(
+                Mesh::POSITION_ATTR,
+                MeshVertexAttributeData::Float32x3(vec![
+                    Vec3::new(-1., -1., -1.),
+                    Vec3::new(1., -1., -9.),
+                    Vec3::new(1., 1., -9.),
+                    Vec3::new(-1., 1., -1.),
+                ]),
+            )
+            .with_attribute(
+                Mesh::NORMAL_ATTR,
+                MeshVertexAttributeData::Float32x3(vec![normal; 4]),
+            )
+            .with_attribute(
+                Mesh::TEX_COORDS_ATTR,
+                MeshVertexAttributeData::Float32x2(vec![
+                    Vec2::new(0., 1.),
+                    Vec2::new(1., 1.),
+                    Vec2::new(1., 0.),
+                    Vec2::new(0., 0.),
+                ]),
+            )
+            .with_indices(MeshIndices::UInt32(vec![0, 1, 2, 0, 2, 3]));
+        floor.recalculate_tangent().unwrap();
+        testing::add_static_mesh(&mut scene, floor, material_id);
+
+        // Grazing the floor so only the debug colors show up.
+        scene.original.dir_lights.insert(
+            Uuid::from_u128(10),
+            GpuDirectionalLight {
+                direction: Vec3::Y,
+                color: Vec3::ONE,
+                intensity: 800.,
+                radius: 0.,
+            },
+        );
+        scene.original.camera.projection =
+            CameraProjection::Orthographic(OrthographicProjection::symmetric(2., 2., 0.1, 10.));
+
+        let image = render_scene(
+            renderer,
+            scene,
+            Some(ShadowMappingNode {
+                config: ShadowMappingConfig {
+                    cascade_blend_fraction,
+                    ..Default::default()
+                },
+                filtering: None,
+                show_cascades: true,
+                ..Default::default()
+            }),
+            PbrNodeConfig::SHADOW_MAPPING,
+        );
+
+        (0..SIZE)
+            .map(|x| image.get_pixel(x, SIZE / 2))
+            .filter(|pixel| pixel.0[..3].iter().filter(|c| **c > 20).count() > 1)
+            .count()
+    }
+
+    #[test]
+    fn cascade_blending() {
+        let features = Some(Features::DEPTH_CLIP_CONTROL);
+        let Some(renderer) = testing::renderer(features, Some(pbr_limits())) else {
+            return;
+        };
+
+        let hard = cascade_blend_pixels(&renderer, 0.);
+        let blended = cascade_blend_pixels(&renderer, 0.5);
+        assert_eq!(hard, 0);
+        // Both transitions are spread over several pixels.
+        assert!(blended > 8, "{blended}");
+    }
+
+    #[test]
+    fn sdsm_measures_depth_range() {
+        let features = Some(Features::DEPTH_CLIP_CONTROL);
+        let Some(renderer) = testing::renderer(features, Some(pbr_limits())) else {
+            return;
+        };
+
+        let mut scene = GpuScene::default();
+        let material_id = MaterialInstanceId(Uuid::from_u128(1));
+        scene
+            .original
+            .materials
+            .insert(material_id, Arc::new(PbrMaterial::default()));
+
+        // Each half of the view sees a different depth.
+        let meshes = [
+            quad(Vec2::new(-2., -2.), Vec2::new(0., 2.), -4., false),
+            quad(Vec2::new(0., -2.), Vec2::new(2., 2.), -6., false),
+        ];
+        for (i, mesh) in meshes.into_iter().enumerate() {
+            let mesh_id = MeshInstanceId(Uuid::from_u128(i as u128 + 2));
+            scene.assets.meshes.insert(mesh_id, mesh);
+            scene.static_meshes.push(StaticMesh {
+                mesh: mesh_id,
+                material: material_id,
+                render_layer: DEFAULT_RENDER_LAYER,
+                transform: Default::default(),
+            });
         }
+        scene.original.dir_lights.insert(
+            Uuid::from_u128(10),
+            GpuDirectionalLight {
+                direction: Vec3::new(1., 1., 1.).normalize(),
+                color: Vec3::ONE,
+                intensity: 1.,
+                radius: 0.,
+            },
+        );
+        scene.original.camera.projection =
+            CameraProjection::Orthographic(OrthographicProjection::symmetric(2., 2., 0.1, 10.));
+
+        let size = UVec2::splat(SIZE);
+        let test_targets = TestTargets::new(&renderer.device, size, TextureFormat::Rgba8Unorm)
+            .with_depth(&renderer.device);
+        let targets = test_targets.targets();
+
+        let mut flow = RenderFlow::default();
+        flow.add::<GeneralNode>()
+            .add::<DepthPrepassNode>()
+            .add_initialized(ShadowMappingNode {
+                partitioning: Some(ShadowMapPartitioning::SDSM(3)),
+                filtering: None,
+                ..Default::default()
+            });
+        flow.set_queue(scene.static_meshes.clone());
+        flow.build(&renderer, &mut scene, None, &targets).unwrap();
 
-        queue.submit([encoder.finish()]);
+        // Measured in one frame, then read back by the next ones.
+        for _ in 0..4 {
+            flow.run(&renderer, &mut scene, &targets);
+            renderer.device.poll(Maintain::Wait);
+        }
+
+        let range = flow.get_mut::<ShadowMappingNode>().unwrap().depth_range;
+        let range = range.expect("Depth range wasn't measured.");
+        assert!(
+            (range - Vec2::new(4., 6.)).abs().max_element() < 0.01,
+            "{range}"
+        );
+    }
+
+    #[test]
+    fn lights_added_after_build() {
+        let features = Some(Features::DEPTH_CLIP_CONTROL);
+        let Some(renderer) = testing::renderer(features, Some(pbr_limits())) else {
+            return;
+        };
+
+        let mut scene = GpuScene::default();
+        let material_id = MaterialInstanceId(Uuid::from_u128(1));
+        scene
+            .original
+            .materials
+            .insert(material_id, Arc::new(PbrMaterial::default()));
+        let mesh_id = MeshInstanceId(Uuid::from_u128(2));
+        scene.assets.meshes.insert(
+            mesh_id,
+            quad(Vec2::new(-3., -2.), Vec2::new(3., 2.), -5., false),
+        );
+        scene.static_meshes.push(StaticMesh {
+            mesh: mesh_id,
+            material: material_id,
+            render_layer: DEFAULT_RENDER_LAYER,
+            transform: Default::default(),
+        });
+        scene.original.point_lights.insert(
+            Uuid::from_u128(10),
+            GpuPointLight {
+                position: Vec3::new(0., 0., -2.),
+                color: Vec3::ONE,
+                intensity: 100.,
+                radius: 0.1,
+            },
+        );
+        scene.original.camera.projection =
+            CameraProjection::Orthographic(OrthographicProjection::symmetric(2., 2., 0.1, 10.));
+
+        let size = UVec2::splat(SIZE);
+        let test_targets = TestTargets::new(&renderer.device, size, TextureFormat::Rgba8Unorm)
+            .with_depth(&renderer.device);
+        let targets = test_targets.targets();
+
+        let mut flow = RenderFlow::default();
+        flow.add::<GeneralNode>()
+            .add::<ImageFallbackNode>()
+            .add::<DepthPrepassNode>()
+            .add_initialized(ShadowMappingNode {
+                filtering: None,
+                ..Default::default()
+            })
+            .add_initialized(PbrNode {
+                node_cfg: PbrNodeConfig::SHADOW_MAPPING,
+                ..Default::default()
+            });
+        flow.set_queue(scene.static_meshes.clone());
+        flow.build(&renderer, &mut scene, None, &targets).unwrap();
+        flow.run(&renderer, &mut scene, &targets);
+
+        // The quad is culled from the cube faces looking up, down and away from it.
+        let visible = &flow.get_mut::<ShadowMappingNode>().unwrap().visible_meshes;
+        assert_eq!(visible.len(), 6);
+        assert_eq!(visible[4], [true]);
+        assert_eq!([&visible[2], &visible[3], &visible[5]], [&[false]; 3]);
+
+        // More lights than the shadow maps were built for.
+        for i in 0..2 {
+            scene.original.point_lights.insert(
+                Uuid::from_u128(11 + i),
+                GpuPointLight {
+                    position: Vec3::new(i as f32 - 0.5, 1., -2.),
+                    color: Vec3::ONE,
+                    intensity: 100.,
+                    radius: 0.1,
+                },
+            );
+        }
+        scene.original.dir_lights.insert(
+            Uuid::from_u128(20),
+            GpuDirectionalLight {
+                direction: Vec3::Z,
+                color: Vec3::ONE,
+                intensity: 1.,
+                radius: 0.,
+            },
+        );
+        flow.run(&renderer, &mut scene, &targets);
+
+        assert_eq!(
+            flow.get_mut::<ShadowMappingNode>()
+                .unwrap()
+                .shadow_map_lights,
+            (1, 3)
+        );
+        let layers = |id| scene.assets.textures[&id].size().depth_or_array_layers;
+        assert_eq!(layers(SHADOW_MAPPING.point_shadow_map), 18);
+        assert_eq!(layers(SHADOW_MAPPING.directional_shadow_map), 3);
     }
 }
@@ -0,0 +1,294 @@
+use aurora_core::render::{
+    flow::{RenderContext, RenderNode},
+    resource::DynamicGpuBuffer,
+    scene::GpuScene,
+};
+use encase::ShaderType;
+use wgpu::{
+    BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, BufferBindingType, BufferUsages,
+    ColorTargetState, ColorWrites, FragmentState, PipelineLayoutDescriptor,
+    RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor,
+    ShaderStages, TextureSampleType, TextureViewDimension, VertexState,
+};
+
+#[derive(ShaderType)]
+struct SharpenUniform {
+    sharpness: f32,
+}
+
+pub struct SharpenNodeData {
+    pub pipeline: RenderPipeline,
+    pub layout: BindGroupLayout,
+    pub config: DynamicGpuBuffer,
+}
+
+/// Contrast adaptive sharpening, to crisp up the image after TAA or FXAA.
+///
+/// Expects colors in [0, 1], so place it after tonemapping.
+pub struct SharpenNode {
+    /// From 0, which leaves the image untouched, to 1.
+    pub sharpness: f32,
+
+    pub data: Option<SharpenNodeData>,
+}
+
+impl Default for SharpenNode {
+    fn default() -> Self {
+        Self {
+            sharpness: 0.5,
+            data: None,
+        }
+    }
+}
+
+impl RenderNode for SharpenNode {
+    fn require_shaders(&self) -> Option<&'static [(&'static [&'static str], &'static str)]> {
+        Some(&[
+            (&[], include_str!("../shader/fullscreen.wgsl")),
+            (
+                &[include_str!("../shader/fullscreen.wgsl")],
+                include_str!("../shader/post_processing/sharpen.wgsl"),
+            ),
+        ])
+    }
+
+    fn build(
+        &mut self,
+        _scene: &mut GpuScene,
+        RenderContext {
+            device,
+            node,
+            targets,
+            ..
+        }: RenderContext,
+    ) {
+        let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("sharpen_layout"),
+            entries: &[
+                // Color
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: false },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                // Config
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: Some(SharpenUniform::min_size()),
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("sharpen_pipeline_layout"),
+            bind_group_layouts: &[&layout],
+            ..Default::default()
+        });
+
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("sharpen_pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &node.shaders[0],
+                entry_point: "vertex",
+                compilation_options: Default::default(),
+                buffers: &[],
+            },
+            fragment: Some(FragmentState {
+                module: &node.shaders[1],
+                entry_point: "fragment",
+                compilation_options: Default::default(),
+                targets: &[Some(ColorTargetState {
+                    format: targets.color_format,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: Default::default(),
+            depth_stencil: Default::default(),
+            multisample: Default::default(),
+            multiview: Default::default(),
+            cache: node.pipeline_cache.as_deref(),
+        });
+
+        self.data = Some(SharpenNodeData {
+            pipeline,
+            layout,
+            config: DynamicGpuBuffer::new(BufferUsages::UNIFORM),
+        });
+    }
+
+    fn prepare(
+        &mut self,
+        _scene: &mut GpuScene,
+        RenderContext { device, queue, .. }: RenderContext,
+    ) {
+        let Some(SharpenNodeData { config, .. }) = &mut self.data else {
+            return;
+        };
+
+        config.clear();
+        config.push(&SharpenUniform {
+            sharpness: self.sharpness,
+        });
+        config.write::<SharpenUniform>(device, queue);
+    }
+
+    fn draw(
+        &self,
+        GpuScene { assets, .. }: &mut GpuScene,
+        RenderContext {
+            device,
+            queue,
+            targets,
+            ..
+        }: RenderContext,
+    ) {
+        let Some(SharpenNodeData {
+            pipeline,
+            layout,
+            config,
+        }) = &self.data
+        else {
+            return;
+        };
+
+        let post_process = targets.swap_chain.start_post_process();
+        let bind_group = assets.bind_group_cache.get_or_create(
+            device,
+            &BindGroupDescriptor {
+                label: Some("sharpen_bind_group"),
+                layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::TextureView(post_process.src),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: config.entire_binding().unwrap(),
+                    },
+                ],
+            },
+        );
+
+        let mut command_encoder = device.create_command_encoder(&Default::default());
+
+        {
+            let mut pass = command_encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("sharpen_pass"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: post_process.dst,
+                    resolve_target: None,
+                    ops: Default::default(),
+                })],
+                ..Default::default()
+            });
+
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.draw(0..3, 0..1);
+        }
+
+        queue.submit([command_encoder.finish()]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aurora_core::{
+        render::{
+            flow::{GeneralNode, RenderFlow},
+            scene::GpuScene,
+        },
+        util::testing::{self, TestTargets},
+        WgpuRenderer,
+    };
+    use glam::UVec2;
+    use image::RgbaImage;
+    use wgpu::{
+        Extent3d, ImageCopyTexture, ImageDataLayout, Origin3d, TextureAspect, TextureFormat,
+    };
+
+    use super::SharpenNode;
+
+    fn sharpen(renderer: &WgpuRenderer, sharpness: f32, frame: &[u8]) -> RgbaImage {
+        let size = UVec2::new(16, 16);
+        let test_targets = TestTargets::new(&renderer.device, size, TextureFormat::Rgba8Unorm);
+        let targets = test_targets.targets();
+
+        renderer.queue.write_texture(
+            ImageCopyTexture {
+                texture: test_targets.swap_chain.current_texture(),
+                mip_level: 0,
+                origin: Origin3d::ZERO,
+                aspect: TextureAspect::All,
+            },
+            frame,
+            ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(size.x * 4),
+                rows_per_image: None,
+            },
+            Extent3d {
+                width: size.x,
+                height: size.y,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        let mut scene = GpuScene::default();
+        let mut flow = RenderFlow::default();
+        flow.add::<GeneralNode>().add_initialized(SharpenNode {
+            sharpness,
+            data: None,
+        });
+        flow.build(renderer, &mut scene, None, &targets).unwrap();
+        flow.run(renderer, &mut scene, &targets);
+
+        test_targets.read(&renderer)
+    }
+
+    #[test]
+    fn sharpen_increases_contrast() {
+        let Some(renderer) = testing::renderer(None, None) else {
+            return;
+        };
+
+        // A soft vertical edge from 64 to 192.
+        let ramp = [
+            64, 64, 64, 64, 64, 64, 96, 128, 160, 192, 192, 192, 192, 192, 192, 192,
+        ];
+        let frame = (0..16 * 16)
+            .flat_map(|i| {
+                let v = ramp[i % 16];
+                [v, v, v, 255]
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(sharpen(&renderer, 0., &frame).into_raw(), frame);
+
+        let contrast = |image: &RgbaImage| {
+            image.get_pixel(6, 8)[0] as i32 - image.get_pixel(5, 8)[0] as i32
+                + image.get_pixel(9, 8)[0] as i32
+                - image.get_pixel(8, 8)[0] as i32
+        };
+        let soft = sharpen(&renderer, 0.5, &frame);
+        let sharp = sharpen(&renderer, 1., &frame);
+        // Both shoulders of the ramp get steeper.
+        assert!(soft.get_pixel(5, 8)[0] < 64 && soft.get_pixel(9, 8)[0] > 192);
+        assert!(contrast(&sharp) > contrast(&soft));
+    }
+}
@@ -0,0 +1,581 @@
+use std::collections::HashMap;
+
+use aurora_core::render::{
+    flow::{RenderContext, RenderNode},
+    mesh::{Mesh, MeshBufferLayout},
+    resource::DynamicGpuBuffer,
+    scene::{GpuScene, MeshInstanceId},
+};
+use encase::ShaderType;
+use glam::{Mat4, UVec4, Vec4};
+use log::warn;
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingType, Buffer, BufferBindingType, BufferUsages, CommandBuffer,
+    CommandEncoder, ComputePassDescriptor, ComputePipeline, ComputePipelineDescriptor,
+    PipelineLayoutDescriptor, ShaderStages,
+};
+
+const SKINNING_WORKGROUP_SIZE: u32 = 64;
+/// Marks attributes the mesh doesn't have, see `skinning.wgsl`.
+const MISSING_ATTRIBUTE: u32 = u32::MAX;
+
+/// Joints moving the vertices of a mesh, see [`SkinningNode`].
+#[derive(Clone, Default)]
+pub struct Skin {
+    /// Up to four joints per vertex, indexing into `joint_matrices`.
+    pub joints: Vec<[u32; 4]>,
+    /// Weights of `joints`, summing up to 1.
+    pub weights: Vec<Vec4>,
+    /// Transforms from the bind pose into the current pose, usually the transform of the
+    /// joint times its inverse bind matrix.
+    pub joint_matrices: Vec<Mat4>,
+}
+
+#[derive(ShaderType)]
+struct SkinningLayout {
+    stride: u32,
+    normal_offset: u32,
+    tangent_offset: u32,
+    vertices: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct JointInfluence {
+    joints: UVec4,
+    weights: Vec4,
+}
+
+pub struct GpuSkin {
+    pub joint_matrices: Buffer,
+    pub joint_count: usize,
+    pub bind_group: BindGroup,
+    pub vertices: u32,
+}
+
+pub struct SkinningNodeData {
+    pub pipeline: ComputePipeline,
+    pub layout: BindGroupLayout,
+    pub skins: HashMap<MeshInstanceId, GpuSkin>,
+    /// Frames prepared since building, the first one has no previous pose to keep.
+    pub frames: u32,
+}
+
+/// Skins meshes in a compute pass once per frame, writing into their vertex buffers.
+///
+/// Place it right after `GeneralNode`, every later pass, like prepasses, shadow cascades
+/// and pbr, then draws the skinned vertices without skinning them again. Skins are set up
+/// when building, while joint matrices are uploaded every frame, see
+/// [`Self::set_joint_matrices`]. The joint count of a skin is fixed once built.
+///
+/// The pose of the previous frame is kept in [`GpuMesh::previous_vertex_buffer`], so
+/// motion vectors follow the skinned vertices.
+///
+/// [`GpuMesh::previous_vertex_buffer`]: aurora_core::render::mesh::GpuMesh::previous_vertex_buffer
+#[derive(Default)]
+pub struct SkinningNode {
+    pub skins: HashMap<MeshInstanceId, Skin>,
+
+    pub data: Option<SkinningNodeData>,
+}
+
+impl SkinningNode {
+    /// Skins inserted after building take effect once the flow is built again.
+    pub fn insert_skin(&mut self, mesh: MeshInstanceId, skin: Skin) -> &mut Self {
+        self.skins.insert(mesh, skin);
+        self
+    }
+
+    pub fn set_joint_matrices(&mut self, mesh: MeshInstanceId, joint_matrices: Vec<Mat4>) {
+        if let Some(skin) = self.skins.get_mut(&mesh) {
+            skin.joint_matrices = joint_matrices;
+        }
+    }
+
+    /// Offset of `attr` in `mesh`'s vertices, in 4 byte words.
+    fn attribute_offset(mesh: &Mesh, attr: usize) -> u32 {
+        mesh.vertex_attributes()
+            .into_iter()
+            .find(|a| a.shader_location == attr as u32)
+            .map(|a| a.offset as u32 / 4)
+            .unwrap_or(MISSING_ATTRIBUTE)
+    }
+}
+
+impl RenderNode for SkinningNode {
+    fn require_shaders(&self) -> Option<&'static [(&'static [&'static str], &'static str)]> {
+        Some(&[(&[], include_str!("../shader/skinning.wgsl"))])
+    }
+
+    // Shadow passes draw the skinned vertices from outside the camera too.
+    fn cull_to_camera(&self) -> bool {
+        false
+    }
+
+    fn build(
+        &mut self,
+        GpuScene { assets, .. }: &mut GpuScene,
+        RenderContext {
+            device,
+            queue,
+            node,
+            ..
+        }: RenderContext,
+    ) {
+        let storage = |binding, read_only| BindGroupLayoutEntry {
+            binding,
+            visibility: ShaderStages::COMPUTE,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("skinning_layout"),
+            entries: &[
+                // Bind pose
+                storage(0, true),
+                // Skinned vertices
+                storage(1, false),
+                // Joint influences
+                storage(2, true),
+                // Joint matrices
+                storage(3, true),
+                // Vertex layout
+                BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: Some(SkinningLayout::min_size()),
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("skinning_pipeline_layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: Some("skinning_pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &node.shaders[0],
+            entry_point: "skin",
+            compilation_options: Default::default(),
+            cache: node.pipeline_cache.as_deref(),
+        });
+
+        let mut skins = HashMap::new();
+        for (id, skin) in &self.skins {
+            let (Some(mesh), Some(gpu_mesh)) =
+                (assets.meshes.get(id), assets.gpu_meshes.get_mut(id))
+            else {
+                continue;
+            };
+
+            let vertices = mesh.vertices_count();
+            if mesh.buffer_layout() != MeshBufferLayout::Interleaved {
+                warn!(
+                    "Skipping skin of mesh {:?}, only interleaved vertices can be skinned.",
+                    id
+                );
+                continue;
+            }
+            if mesh.vertex_stride() % 4 != 0 {
+                warn!(
+                    "Skipping skin of mesh {:?}, its vertex stride isn't a multiple of 4.",
+                    id
+                );
+                continue;
+            }
+            if skin.joints.len() < vertices || skin.weights.len() < vertices {
+                warn!(
+                    "Skipping skin of mesh {:?}, not every vertex has joints.",
+                    id
+                );
+                continue;
+            }
+
+            let vertex_data = mesh.vertex_buffer_data();
+            let bind_pose = device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("skinning_bind_pose"),
+                contents: &vertex_data,
+                usage: BufferUsages::STORAGE,
+            });
+            // Replaces the buffer created by `GeneralNode`, so every pass draws the result.
+            let skinned = device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("skinned_vertex_buffer"),
+                contents: &vertex_data,
+                usage: BufferUsages::VERTEX | BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+            });
+            let previous = device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("skinned_previous_vertex_buffer"),
+                contents: &vertex_data,
+                usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+            });
+
+            let influences = skin
+                .joints
+                .iter()
+                .zip(&skin.weights)
+                .take(vertices)
+                .map(|(joints, weights)| JointInfluence {
+                    joints: UVec4::from_array(*joints),
+                    weights: *weights,
+                })
+                .collect::<Vec<_>>();
+            let influences = device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("skinning_joint_influences"),
+                contents: bytemuck::cast_slice(&influences),
+                usage: BufferUsages::STORAGE,
+            });
+
+            let joint_count = skin.joint_matrices.len().max(1);
+            let mut joint_matrices = skin.joint_matrices.clone();
+            joint_matrices.resize(joint_count, Mat4::IDENTITY);
+            let joint_matrices = device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("skinning_joint_matrices"),
+                contents: bytemuck::cast_slice(&joint_matrices),
+                usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+            });
+
+            let mut vertex_layout = DynamicGpuBuffer::new(BufferUsages::UNIFORM);
+            vertex_layout.push(&SkinningLayout {
+                stride: mesh.vertex_stride() as u32 / 4,
+                normal_offset: Self::attribute_offset(mesh, Mesh::NORMAL_ATTR.id),
+                tangent_offset: Self::attribute_offset(mesh, Mesh::TANGENT_ATTR.id),
+                vertices: vertices as u32,
+            });
+            vertex_layout.write::<SkinningLayout>(device, queue);
+
+            let bind_group = device.create_bind_group(&BindGroupDescriptor {
+                label: Some("skinning_bind_group"),
+                layout: &layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: bind_pose.as_entire_binding(),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: skinned.as_entire_binding(),
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: influences.as_entire_binding(),
+                    },
+                    BindGroupEntry {
+                        binding: 3,
+                        resource: joint_matrices.as_entire_binding(),
+                    },
+                    BindGroupEntry {
+                        binding: 4,
+                        resource: vertex_layout.entire_binding().unwrap(),
+                    },
+                ],
+            });
+
+            gpu_mesh.vertex_buffer = skinned;
+            // Depth only passes read the skinned positions from `vertex_buffer` instead.
+            gpu_mesh.position_buffer = None;
+            gpu_mesh.previous_vertex_buffer = Some(previous);
+            skins.insert(
+                *id,
+                GpuSkin {
+                    joint_matrices,
+                    joint_count,
+                    bind_group,
+                    vertices: vertices as u32,
+                },
+            );
+        }
+
+        self.data = Some(SkinningNodeData {
+            pipeline,
+            layout,
+            skins,
+            frames: 0,
+        });
+    }
+
+    fn prepare(&mut self, _scene: &mut GpuScene, RenderContext { queue, .. }: RenderContext) {
+        let Some(SkinningNodeData { skins, frames, .. }) = &mut self.data else {
+            return;
+        };
+
+        *frames = frames.saturating_add(1);
+
+        for (id, gpu_skin) in skins {
+            let matrices = &self.skins[id].joint_matrices;
+            let count = matrices.len().min(gpu_skin.joint_count);
+            queue.write_buffer(
+                &gpu_skin.joint_matrices,
+                0,
+                bytemuck::cast_slice(&matrices[..count]),
+            );
+        }
+    }
+
+    fn records(&self) -> bool {
+        true
+    }
+
+    fn parallel_safe(&self) -> bool {
+        true
+    }
+
+    fn record(
+        &self,
+        GpuScene { assets, .. }: &GpuScene,
+        RenderContext { device, .. }: RenderContext,
+    ) -> Option<CommandBuffer> {
+        let SkinningNodeData {
+            pipeline,
+            skins,
+            frames,
+            ..
+        } = self.data.as_ref()?;
+        if skins.is_empty() {
+            return None;
+        }
+
+        let mut command_encoder = device.create_command_encoder(&Default::default());
+        let keep_pose = |command_encoder: &mut CommandEncoder| {
+            for id in skins.keys() {
+                let gpu_mesh = &assets.gpu_meshes[id];
+                if let Some(previous) = &gpu_mesh.previous_vertex_buffer {
+                    command_encoder.copy_buffer_to_buffer(
+                        &gpu_mesh.vertex_buffer,
+                        0,
+                        previous,
+                        0,
+                        gpu_mesh.vertex_buffer.size(),
+                    );
+                }
+            }
+        };
+
+        // The bind pose isn't where the vertices were, the first frame keeps its own pose.
+        let first_frame = *frames <= 1;
+        if !first_frame {
+            keep_pose(&mut command_encoder);
+        }
+
+        {
+            let mut pass = command_encoder.begin_compute_pass(&ComputePassDescriptor {
+                label: Some("skinning_pass"),
+                ..Default::default()
+            });
+
+            pass.set_pipeline(pipeline);
+            for gpu_skin in skins.values() {
+                pass.set_bind_group(0, &gpu_skin.bind_group, &[]);
+                pass.dispatch_workgroups(gpu_skin.vertices.div_ceil(SKINNING_WORKGROUP_SIZE), 1, 1);
+            }
+        }
+
+        if first_frame {
+            keep_pose(&mut command_encoder);
+        }
+
+        Some(command_encoder.finish())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use aurora_core::{
+        render::{
+            flow::{GeneralNode, ImageFallbackNode, RenderFlow},
+            helper::{CameraProjection, OrthographicProjection},
+            mesh::{StaticMesh, DEFAULT_RENDER_LAYER},
+            scene::{GpuScene, MaterialInstanceId, MeshInstanceId},
+        },
+        util::{
+            testing::{self, TestTargets},
+            TextureReadback,
+        },
+    };
+    use glam::{Mat4, UVec2, Vec2, Vec3, Vec4};
+    use half::f16;
+    use uuid::Uuid;
+    use wgpu::{
+        Color, LoadOp, Maintain, Operations, RenderPassColorAttachment, RenderPassDescriptor,
+        StoreOp, TextureFormat,
+    };
+
+    use super::{Skin, SkinningNode};
+    use crate::{
+        material::UnlitMaterial,
+        node::{
+            DepthPrepassNode, MotionVectorPrepassNode, UnlitNode, MOTION_VECTOR_PREPASS_TEXTURE,
+        },
+    };
+
+    const SIZE: u32 = 32;
+
+    /// A quad covering the left half of the view, skinned by `SkinningNode`.
+    fn skinned_quad_scene() -> (GpuScene, MeshInstanceId) {
+        let mut scene = GpuScene::default();
+        let mesh_id = MeshInstanceId(Uuid::from_u128(1));
+        let material_id = MaterialInstanceId(Uuid::from_u128(2));
+        // Covers the left half of the view.
+        let quad = testing::quad(Vec2::splat(-1.), Vec2::new(0., 1.), -3.);
+        scene.assets.meshes.insert(mesh_id, quad);
+        scene
+            .original
+            .materials
+            .insert(material_id, Arc::new(UnlitMaterial::default()));
+        scene.static_meshes.push(StaticMesh {
+            mesh: mesh_id,
+            material: material_id,
+            render_layer: DEFAULT_RENDER_LAYER,
+            transform: Default::default(),
+        });
+        scene.original.camera.projection =
+            CameraProjection::Orthographic(OrthographicProjection::symmetric(2., 2., 0.1, 10.));
+        (scene, mesh_id)
+    }
+
+    #[test]
+    fn skinned_vertices_reach_later_passes() {
+        let Some(renderer) = testing::renderer(None, None) else {
+            return;
+        };
+
+        let (mut scene, mesh_id) = skinned_quad_scene();
+        let size = UVec2::splat(SIZE);
+        let test_targets = TestTargets::new(&renderer.device, size, TextureFormat::Rgba8Unorm)
+            .with_depth(&renderer.device);
+        let targets = test_targets.targets();
+
+        // Every vertex follows the second joint, which moves the quad to the right half.
+        let mut skinning = SkinningNode::default();
+        skinning.insert_skin(
+            mesh_id,
+            Skin {
+                joints: vec![[1, 0, 0, 0]; 4],
+                weights: vec![Vec4::X; 4],
+                joint_matrices: vec![Mat4::IDENTITY, Mat4::from_translation(Vec3::X)],
+            },
+        );
+
+        let mut flow = RenderFlow::default();
+        flow.add::<GeneralNode>()
+            .add::<ImageFallbackNode>()
+            .add_initialized(skinning)
+            .add::<UnlitNode>();
+        flow.set_queue(scene.static_meshes.clone());
+        flow.build(&renderer, &mut scene, None, &targets).unwrap();
+
+        let mut render = |flow: &mut RenderFlow| {
+            // Unlit loads the color target, clear what the last frame left.
+            let mut command_encoder = renderer.device.create_command_encoder(&Default::default());
+            command_encoder.begin_render_pass(&RenderPassDescriptor {
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: test_targets.swap_chain.current_view(),
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Clear(Color::BLACK),
+                        store: StoreOp::Store,
+                    },
+                })],
+                ..Default::default()
+            });
+            renderer.queue.submit([command_encoder.finish()]);
+
+            flow.run(&renderer, &mut scene, &targets);
+            let image = test_targets.read(&renderer);
+            (
+                image.get_pixel(SIZE / 4, SIZE / 2)[0],
+                image.get_pixel(SIZE * 3 / 4, SIZE / 2)[0],
+            )
+        };
+
+        let (left, right) = render(&mut flow);
+        assert!(left < 10 && right > 200, "{left} {right}");
+
+        // Joint matrices are uploaded every frame, back to the bind pose.
+        flow.get_mut::<SkinningNode>()
+            .unwrap()
+            .set_joint_matrices(mesh_id, vec![Mat4::IDENTITY; 2]);
+        let (left, right) = render(&mut flow);
+        assert!(left > 200 && right < 10, "{left} {right}");
+    }
+
+    #[test]
+    fn skinned_motion_vectors() {
+        let Some(renderer) = testing::renderer(None, None) else {
+            return;
+        };
+
+        let (mut scene, mesh_id) = skinned_quad_scene();
+        let size = UVec2::splat(SIZE);
+        let test_targets = TestTargets::new(&renderer.device, size, TextureFormat::Rgba8Unorm)
+            .with_depth(&renderer.device);
+        let targets = test_targets.targets();
+
+        let mut skinning = SkinningNode::default();
+        skinning.insert_skin(
+            mesh_id,
+            Skin {
+                joints: vec![[0, 0, 0, 0]; 4],
+                weights: vec![Vec4::X; 4],
+                joint_matrices: vec![Mat4::IDENTITY],
+            },
+        );
+
+        let mut flow = RenderFlow::default();
+        flow.add::<GeneralNode>()
+            .add::<ImageFallbackNode>()
+            .add_initialized(skinning)
+            .add::<DepthPrepassNode>()
+            .add::<MotionVectorPrepassNode>();
+        flow.set_queue(scene.static_meshes.clone());
+        flow.build(&renderer, &mut scene, None, &targets).unwrap();
+
+        // Horizontal motion in the middle of the left quarter, where the quad always is.
+        let mut motion = |flow: &mut RenderFlow, joint: Mat4| {
+            flow.get_mut::<SkinningNode>()
+                .unwrap()
+                .set_joint_matrices(mesh_id, vec![joint]);
+            flow.run(&renderer, &mut scene, &targets);
+
+            let readback = TextureReadback::new(
+                &scene.assets.textures[&MOTION_VECTOR_PREPASS_TEXTURE.texture],
+                &renderer.device,
+                &renderer.queue,
+            )
+            .unwrap();
+            renderer.device.poll(Maintain::wait()).panic_on_timeout();
+            let data = pollster::block_on(readback.read()).unwrap();
+            let texels = bytemuck::pod_collect_to_vec::<u8, f16>(&data.bytes);
+            let row = (data.bytes_per_row / 2) as usize;
+            let (x, y) = (SIZE as usize * 3 / 8, SIZE as usize / 2);
+            texels[y * row + x * 2].to_f32()
+        };
+
+        // The first frame has nothing to move from, even though the bind pose differs.
+        let shift = Mat4::from_translation(Vec3::X * 0.25);
+        assert_eq!(motion(&mut flow, shift), 0.);
+
+        // Moving an eighth of the view is an eighth of the uv, twice that in motion vectors.
+        let moved = motion(&mut flow, Mat4::from_translation(Vec3::X * 0.5));
+        assert!((moved - 0.25).abs() < 0.01, "{moved}");
+
+        // Holding the pose leaves no motion behind.
+        assert_eq!(motion(&mut flow, Mat4::from_translation(Vec3::X * 0.5)), 0.);
+    }
+}
@@ -1,52 +1,102 @@
 use std::path::PathBuf;
 
 use aurora_core::render::{
-    flow::{RenderContext, RenderNode},
-    resource::{GpuCamera, Image},
+    flow::{DependencyNodeIndex, RenderContext, RenderNode},
+    resource::{DynamicGpuBuffer, GpuCamera},
     scene::GpuScene,
 };
 use encase::ShaderType;
 use wgpu::{
     BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
-    BindGroupLayoutEntry, BindingResource, BindingType, BufferBindingType, ColorTargetState,
-    ColorWrites, Features, FilterMode, FragmentState, PipelineLayoutDescriptor,
-    RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor,
-    Sampler, SamplerBindingType, SamplerDescriptor, ShaderStages, TextureSampleType, TextureView,
-    TextureViewDescriptor, TextureViewDimension, VertexState,
+    BindGroupLayoutEntry, BindingResource, BindingType, BufferBindingType, BufferUsages,
+    ColorTargetState, ColorWrites, CompareFunction, DepthStencilState, Features, FragmentState,
+    LoadOp, Operations, PipelineLayoutDescriptor, RenderPassColorAttachment,
+    RenderPassDepthStencilAttachment, RenderPassDescriptor, RenderPipeline,
+    RenderPipelineDescriptor, SamplerBindingType, ShaderStages, StoreOp, TextureSampleType,
+    TextureViewDimension, VertexState,
+};
+
+use crate::node::{
+    DepthPrepassNode, EnvironmentMappingNode, EnvironmentMappingNodeConfig, DEPTH_PREPASS_FORMAT,
+    DEPTH_PREPASS_TEXTURE, ENVIRONMENT_MAP,
 };
 
 pub struct SkyboxNodeData {
     pub pipeline: RenderPipeline,
     pub layout: BindGroupLayout,
-    pub skybox: TextureView,
-    pub sampler: Sampler,
+    pub uniform: DynamicGpuBuffer,
+    /// The last `skybox_path` requested from [`EnvironmentMappingNode`].
+    pub requested_path: Option<PathBuf>,
 }
 
 pub struct SkyboxNodeConfig {
-    pub skybox_path: PathBuf,
+    /// Environment map to draw, loaded into the shared [`ENVIRONMENT_MAP`] by
+    /// [`EnvironmentMappingNode`]. `None` draws the map that node was configured with.
+    pub skybox_path: Option<PathBuf>,
+    /// Exposure compensation of the background in stops, independent of the camera
+    /// exposure. Negative values dim a blown-out sky without darkening the scene.
+    pub background_exposure: f32,
+}
+
+#[derive(ShaderType)]
+struct SkyboxUniform {
+    /// Multiplier derived from `background_exposure`.
+    exposure: f32,
 }
 
+/// Draws the environment map behind everything the depth prepass covered.
+///
+/// The cube map is the one loaded by [`EnvironmentMappingNode`], which is added with
+/// `skybox_path` unless the flow already has one. Swapping the map with
+/// [`SkyboxNode::set_cubemap`] swaps it for image based lighting too.
+/// Place it after the opaque geometry, like `PbrNode`.
 pub struct SkyboxNode {
     pub node_config: SkyboxNodeConfig,
     pub data: Option<SkyboxNodeData>,
 }
 
+impl SkyboxNode {
+    /// Swap the environment map, loaded by [`EnvironmentMappingNode`] when preparing the
+    /// next frame, so drawn from the frame after.
+    pub fn set_cubemap(&mut self, path: impl Into<PathBuf>) {
+        self.node_config.skybox_path = Some(path.into());
+    }
+}
+
 impl RenderNode for SkyboxNode {
+    fn add_node_dependencies(&self) -> Vec<(DependencyNodeIndex, Box<dyn RenderNode>)> {
+        vec![
+            (
+                DependencyNodeIndex::Before,
+                Box::new(DepthPrepassNode::default()),
+            ),
+            (
+                DependencyNodeIndex::Before,
+                Box::new(match &self.node_config.skybox_path {
+                    Some(path) => EnvironmentMappingNode {
+                        node_config: EnvironmentMappingNodeConfig {
+                            env_map_path: path.clone(),
+                        },
+                        ..Default::default()
+                    },
+                    None => EnvironmentMappingNode::default(),
+                }),
+            ),
+        ]
+    }
+
     fn require_renderer_features(&self, features: &mut Features) {
         *features |= Features::FLOAT32_FILTERABLE;
     }
 
     fn require_shaders(&self) -> Option<&'static [(&'static [&'static str], &'static str)]> {
-        Some(&[
-            (&[], include_str!("../shader/fullscreen.wgsl")),
-            (
-                &[
-                    include_str!("../shader/fullscreen.wgsl"),
-                    include_str!("../shader/common/common_type.wgsl"),
-                ],
-                include_str!("../shader/env_mapping/skybox.wgsl"),
-            ),
-        ])
+        Some(&[(
+            &[
+                include_str!("../shader/fullscreen.wgsl"),
+                include_str!("../shader/common/common_type.wgsl"),
+            ],
+            include_str!("../shader/env_mapping/skybox.wgsl"),
+        )])
     }
 
     fn build(
@@ -54,9 +104,9 @@ impl RenderNode for SkyboxNode {
         _scene: &mut GpuScene,
         RenderContext {
             device,
-            queue,
             node,
             targets,
+            ..
         }: RenderContext,
     ) {
         let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
@@ -88,6 +138,16 @@ impl RenderNode for SkyboxNode {
                     },
                     count: None,
                 },
+                BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: Some(SkyboxUniform::min_size()),
+                    },
+                    count: None,
+                },
             ],
         });
 
@@ -107,7 +167,7 @@ impl RenderNode for SkyboxNode {
                 buffers: &[],
             },
             fragment: Some(FragmentState {
-                module: &node.shaders[1],
+                module: &node.shaders[0],
                 entry_point: "fragment",
                 compilation_options: Default::default(),
                 targets: &[Some(ColorTargetState {
@@ -117,36 +177,56 @@ impl RenderNode for SkyboxNode {
                 })],
             }),
             primitive: Default::default(),
-            depth_stencil: Default::default(),
+            // Drawn at the far plane, so anything in front of it fails the test.
+            depth_stencil: Some(DepthStencilState {
+                format: DEPTH_PREPASS_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: CompareFunction::LessEqual,
+                stencil: Default::default(),
+                bias: Default::default(),
+            }),
             multisample: Default::default(),
             multiview: Default::default(),
-            cache: Default::default(),
-        });
-
-        let skybox = Image::from_path(&self.node_config.skybox_path, None, true)
-            .unwrap()
-            .to_cube_map(device, queue, &Default::default());
-
-        let skybox = skybox.create_view(&TextureViewDescriptor {
-            label: Some("skybox_view"),
-            dimension: Some(TextureViewDimension::Cube),
-            ..Default::default()
-        });
-
-        let sampler = device.create_sampler(&SamplerDescriptor {
-            label: Some("skybox_sampler"),
-            min_filter: FilterMode::Linear,
-            mag_filter: FilterMode::Linear,
-            mipmap_filter: FilterMode::Linear,
-            ..Default::default()
+            cache: node.pipeline_cache.as_deref(),
         });
 
         self.data = Some(SkyboxNodeData {
             pipeline,
             layout,
-            skybox,
-            sampler,
+            uniform: DynamicGpuBuffer::new(BufferUsages::UNIFORM),
+            requested_path: None,
+        });
+    }
+
+    fn prepare(
+        &mut self,
+        GpuScene { assets, .. }: &mut GpuScene,
+        RenderContext { device, queue, .. }: RenderContext,
+    ) {
+        let Some(SkyboxNodeData {
+            uniform,
+            requested_path,
+            ..
+        }) = &mut self.data
+        else {
+            return;
+        };
+
+        // The environment mapping node is prepared first, so it loads the map next frame.
+        if self.node_config.skybox_path != *requested_path {
+            if let Some(path) = &self.node_config.skybox_path {
+                assets
+                    .requested_files
+                    .insert(ENVIRONMENT_MAP.specular, path.clone());
+            }
+            *requested_path = self.node_config.skybox_path.clone();
+        }
+
+        uniform.clear();
+        uniform.push(&SkyboxUniform {
+            exposure: 2f32.powf(self.node_config.background_exposure),
         });
+        uniform.write::<SkyboxUniform>(device, queue);
     }
 
     fn draw(
@@ -162,31 +242,42 @@ impl RenderNode for SkyboxNode {
         let Some(SkyboxNodeData {
             pipeline,
             layout,
-            skybox,
-            sampler,
+            uniform,
+            ..
         }) = &self.data
         else {
             return;
         };
 
-        let bind_group = device.create_bind_group(&BindGroupDescriptor {
-            label: Some("skybox_bind_group"),
-            layout: &layout,
-            entries: &[
-                BindGroupEntry {
-                    binding: 0,
-                    resource: BindingResource::TextureView(&skybox),
-                },
-                BindGroupEntry {
-                    binding: 1,
-                    resource: BindingResource::Sampler(&sampler),
-                },
-                BindGroupEntry {
-                    binding: 2,
-                    resource: assets.camera_uniform.entire_binding().unwrap(),
-                },
-            ],
-        });
+        let bind_group = assets.bind_group_cache.get_or_create(
+            device,
+            &BindGroupDescriptor {
+                label: Some("skybox_bind_group"),
+                layout: &layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::TextureView(
+                            &assets.texture_views[&ENVIRONMENT_MAP.specular_view],
+                        ),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::Sampler(
+                            &assets.samplers[&ENVIRONMENT_MAP.sampler],
+                        ),
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: assets.camera_uniform.entire_binding().unwrap(),
+                    },
+                    BindGroupEntry {
+                        binding: 3,
+                        resource: uniform.entire_binding().unwrap(),
+                    },
+                ],
+            },
+        );
 
         let mut command_encoder = device.create_command_encoder(&Default::default());
 
@@ -198,6 +289,14 @@ impl RenderNode for SkyboxNode {
                     resolve_target: None,
                     ops: Default::default(),
                 })],
+                depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                    view: &assets.texture_views[&DEPTH_PREPASS_TEXTURE.view],
+                    depth_ops: Some(Operations {
+                        load: LoadOp::Load,
+                        store: StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
                 ..Default::default()
             });
 
@@ -209,3 +308,156 @@ impl RenderNode for SkyboxNode {
         queue.submit([command_encoder.finish()]);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, path::PathBuf};
+
+    use aurora_core::{
+        render::{
+            flow::{GeneralNode, RenderFlow},
+            helper::{CameraProjection, OrthographicProjection},
+            scene::{GpuScene, MaterialInstanceId},
+        },
+        util::testing::{self, TestTargets},
+    };
+    use glam::{UVec2, Vec2};
+    use image::{codecs::hdr::HdrEncoder, Rgb};
+    use wgpu::{Features, TextureFormat};
+
+    use super::{SkyboxNode, SkyboxNodeConfig};
+    use crate::node::{
+        DepthPrepassNode, EnvironmentMappingNode, EnvironmentMappingNodeConfig, ENVIRONMENT_MAP,
+    };
+
+    const SIZE: u32 = 64;
+
+    /// A cross of 4x4 faces in a single color.
+    fn solid_cube_map(name: &str, color: [f32; 3]) -> PathBuf {
+        let path = std::env::temp_dir().join(name);
+        HdrEncoder::new(File::create(&path).unwrap())
+            .encode(&[Rgb(color); 16 * 12], 16, 12)
+            .unwrap();
+        path
+    }
+
+    #[test]
+    fn skybox_behind_geometry() {
+        let features = Some(Features::FLOAT32_FILTERABLE);
+        let Some(renderer) = testing::renderer(features, None) else {
+            return;
+        };
+
+        let mut scene = GpuScene::default();
+        // A quad covering the middle half of the view.
+        testing::add_static_mesh(
+            &mut scene,
+            testing::quad(Vec2::splat(-0.5), Vec2::splat(0.5), -1.),
+            MaterialInstanceId::default(),
+        );
+        scene.original.camera.projection =
+            CameraProjection::Orthographic(OrthographicProjection::symmetric(2., 2., 0.1, 10.));
+
+        let size = UVec2::splat(SIZE);
+        let test_targets = TestTargets::new(&renderer.device, size, TextureFormat::Rgba8Unorm)
+            .with_depth(&renderer.device);
+        let targets = test_targets.targets();
+
+        let red = solid_cube_map("aurora_skybox_red.hdr", [1., 0., 0.]);
+        let blue = solid_cube_map("aurora_skybox_blue.hdr", [0., 0., 1.]);
+
+        let mut flow = RenderFlow::default();
+        flow.add::<GeneralNode>()
+            .add::<DepthPrepassNode>()
+            .add_initialized(EnvironmentMappingNode {
+                node_config: EnvironmentMappingNodeConfig { env_map_path: red },
+                ..Default::default()
+            })
+            // Keeps the configured environment mapping node as its dependency.
+            .add_initialized(SkyboxNode {
+                node_config: SkyboxNodeConfig {
+                    skybox_path: None,
+                    background_exposure: 0.,
+                },
+                data: None,
+            });
+        flow.set_queue(scene.static_meshes.clone());
+        flow.build(&renderer, &mut scene, None, &targets).unwrap();
+        // The skybox samples the map loaded for image based lighting.
+        assert!(scene
+            .assets
+            .textures
+            .contains_key(&ENVIRONMENT_MAP.specular));
+
+        let mut run = |flow: &mut RenderFlow| {
+            flow.run(&renderer, &mut scene, &targets);
+            test_targets.read(&renderer)
+        };
+
+        let image = run(&mut flow);
+        assert_eq!(image.get_pixel(4, 4).0, [255, 0, 0, 255]);
+        // Occluded by the quad.
+        assert_eq!(image.get_pixel(32, 32)[0], 0);
+
+        flow.get_mut::<EnvironmentMappingNode>()
+            .unwrap()
+            .set_env_map(blue);
+        let image = run(&mut flow);
+        assert_eq!(image.get_pixel(4, 4).0, [0, 0, 255, 255]);
+        assert_eq!(image.get_pixel(32, 32)[2], 0);
+    }
+
+    #[test]
+    fn set_cubemap_loads_once() {
+        let features = Some(Features::FLOAT32_FILTERABLE);
+        let Some(renderer) = testing::renderer(features, None) else {
+            return;
+        };
+
+        let mut scene = GpuScene::default();
+        let size = UVec2::splat(SIZE);
+        let test_targets = TestTargets::new(&renderer.device, size, TextureFormat::Rgba8Unorm)
+            .with_depth(&renderer.device);
+        let targets = test_targets.targets();
+
+        let red = solid_cube_map("aurora_skybox_once_red.hdr", [1., 0., 0.]);
+        let blue = solid_cube_map("aurora_skybox_once_blue.hdr", [0., 0., 1.]);
+
+        // Adds an environment mapping node loading `red`.
+        let mut flow = RenderFlow::default();
+        flow.add::<GeneralNode>().add_initialized(SkyboxNode {
+            node_config: SkyboxNodeConfig {
+                skybox_path: Some(red),
+                background_exposure: 0.,
+            },
+            data: None,
+        });
+        flow.build(&renderer, &mut scene, None, &targets).unwrap();
+
+        // Every load publishes a new texture, an unchanged one wasn't loaded again.
+        let specular =
+            |scene: &GpuScene| scene.assets.textures[&ENVIRONMENT_MAP.specular].global_id();
+        let run = |flow: &mut RenderFlow, scene: &mut GpuScene| {
+            for _ in 0..2 {
+                flow.run(&renderer, scene, &targets);
+            }
+            test_targets.read(&renderer)
+        };
+
+        // Shared with image based lighting rather than decoded again by the skybox.
+        let red_map = specular(&scene);
+        let image = run(&mut flow, &mut scene);
+        assert_eq!(image.get_pixel(4, 4).0, [255, 0, 0, 255]);
+        assert_eq!(specular(&scene), red_map);
+
+        flow.get_mut::<SkyboxNode>().unwrap().set_cubemap(blue);
+        let image = run(&mut flow, &mut scene);
+        assert_eq!(image.get_pixel(4, 4).0, [0, 0, 255, 255]);
+        assert!(scene.assets.requested_files.is_empty());
+        let blue_map = specular(&scene);
+        assert_ne!(blue_map, red_map);
+
+        run(&mut flow, &mut scene);
+        assert_eq!(specular(&scene), blue_map);
+    }
+}
@@ -1,14 +1,21 @@
 use std::collections::HashMap;
 
-use aurora_core::render::{
-    flow::{RenderContext, RenderNode},
-    resource::{DynamicGpuBuffer, GpuCamera},
-    scene::{
-        ExtraBindGroupId, ExtraBufferId, ExtraLayoutId, GpuScene, SamplerId, TextureId,
-        TextureViewId,
+use aurora_core::{
+    render::{
+        budget::Quality,
+        flow::{RenderContext, RenderNode},
+        resource::{DynamicGpuBuffer, GpuCamera},
+        scene::{
+            ExtraBindGroupId, ExtraBufferId, ExtraLayoutId, GpuAssets, GpuScene, SamplerId,
+            TextureId, TextureViewId,
+        },
+        ShaderDefEnum,
     },
+    util::create_sampler,
 };
+use aurora_derive::ShaderDefEnum;
 use encase::ShaderType;
+use glam::UVec2;
 use naga_oil::compose::ShaderDefValue;
 use uuid::Uuid;
 use wgpu::{
@@ -22,6 +29,19 @@ use wgpu::{
 
 use crate::node::{DEPTH_PREPASS_TEXTURE, NORMAL_PREPASS_TEXTURE};
 
+/// How occlusion is computed from the horizons found along each slice.
+#[derive(ShaderDefEnum, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AoMode {
+    /// Horizon based, accumulating the weighted rise of the horizon.
+    #[default]
+    #[def_name = "HBAO"]
+    Hbao,
+    /// Ground truth AO, integrating the cosine weighted arc between both horizons of a slice,
+    /// with Jimenez's multi-bounce approximation on top.
+    #[def_name = "GTAO"]
+    Gtao,
+}
+
 #[derive(ShaderType)]
 pub struct SsaoConfig {
     pub slices: u32,
@@ -29,6 +49,13 @@ pub struct SsaoConfig {
     pub strength: f32,
     pub angle_bias: f32,
     pub max_depth_diff: f32,
+    /// Only used by [`AoMode::Gtao`]. How far occluders are assumed to extend behind what's
+    /// visible, from 0 where horizons drop as soon as a sample is lower, to 1 where they
+    /// never do.
+    pub thickness: f32,
+    /// Only used by [`AoMode::Gtao`]. View space distance horizons are searched within,
+    /// occluders fade out over the second half of it.
+    pub falloff_range: f32,
 }
 
 pub struct Ssao {
@@ -59,6 +86,8 @@ impl Default for SsaoConfig {
             angle_bias: std::f32::consts::FRAC_PI_3,
             // angle_bias: 0.0,
             max_depth_diff: 2.0,
+            thickness: 0.8,
+            falloff_range: 0.5,
         }
     }
 }
@@ -86,9 +115,12 @@ pub const SSAO_TEXTURE_FORMAT: TextureFormat = TextureFormat::R32Float;
 
 #[derive(Default)]
 pub struct SsaoNode {
+    pub mode: AoMode,
     pub config: SsaoConfig,
     pub denoise: bool,
     pub debug_ssao_only: bool,
+    /// Set by the frame budget, lower tiers take fewer slices and samples.
+    pub budget_quality: Quality,
 
     pub compute_pipeline: Option<ComputePipeline>,
     pub denoise_pipeline: Option<ComputePipeline>,
@@ -99,6 +131,63 @@ const HILBERT_WIDTH: u16 = 64;
 impl SsaoNode {
     pub const SSAO_WORKGROUP_SIZE: u32 = 16;
 
+    fn create_textures(assets: &mut GpuAssets, device: &Device, size: UVec2) {
+        let noisy_ssao_texture = device.create_texture(&TextureDescriptor {
+            label: Some("noisy_ssao_texture"),
+            size: Extent3d {
+                width: size.x,
+                height: size.y,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: SSAO_TEXTURE_FORMAT,
+            usage: TextureUsages::TEXTURE_BINDING
+                | TextureUsages::STORAGE_BINDING
+                | TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+
+        let noisy_ssao_texture_view = noisy_ssao_texture.create_view(&TextureViewDescriptor {
+            label: Some("noisy_ssao_texture"),
+            ..Default::default()
+        });
+
+        let ssao_texture = device.create_texture(&TextureDescriptor {
+            label: Some("ssao_texture"),
+            size: Extent3d {
+                width: size.x,
+                height: size.y,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: SSAO_TEXTURE_FORMAT,
+            usage: TextureUsages::TEXTURE_BINDING
+                | TextureUsages::STORAGE_BINDING
+                | TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+
+        let ssao_texture_view = ssao_texture.create_view(&TextureViewDescriptor {
+            label: Some("ssao_texture_view"),
+            ..Default::default()
+        });
+
+        assets
+            .textures
+            .insert(SSAO.noisy_ssao_texture, noisy_ssao_texture);
+        assets
+            .texture_views
+            .insert(SSAO.noisy_ssao_texture_view, noisy_ssao_texture_view);
+        assets.textures.insert(SSAO.ssao_texture, ssao_texture);
+        assets
+            .texture_views
+            .insert(SSAO.ssao_texture_view, ssao_texture_view);
+    }
+
     pub fn generate_hilbert_lut(device: &Device, queue: &Queue) -> Texture {
         let mut t = [[0; 64]; 64];
         for x in 0..64 {
@@ -161,6 +250,7 @@ impl RenderNode for SsaoNode {
             "SSAO_WORKGROUP_SIZE".to_string(),
             ShaderDefValue::UInt(Self::SSAO_WORKGROUP_SIZE),
         );
+        shader_defs.extend([self.mode.to_def()]);
 
         if self.denoise {
             shader_defs.insert("SSAO_DENOISE".to_string(), Default::default());
@@ -176,6 +266,7 @@ impl RenderNode for SsaoNode {
             (
                 &[
                     include_str!("../shader/common/common_type.wgsl"),
+                    include_str!("../shader/common/camera.wgsl"),
                     include_str!("../shader/math.wgsl"),
                     include_str!("../shader/hash.wgsl"),
                 ],
@@ -207,7 +298,7 @@ impl RenderNode for SsaoNode {
                     binding: 0,
                     visibility: ShaderStages::COMPUTE,
                     ty: BindingType::Texture {
-                        sample_type: TextureSampleType::Depth,
+                        sample_type: TextureSampleType::Float { filterable: false },
                         view_dimension: TextureViewDimension::D2,
                         multisampled: false,
                     },
@@ -290,48 +381,10 @@ impl RenderNode for SsaoNode {
             module: &node.shaders[0],
             entry_point: "main",
             compilation_options: Default::default(),
-            cache: None,
+            cache: node.pipeline_cache.as_deref(),
         }));
 
-        let noisy_ssao_texture = device.create_texture(&TextureDescriptor {
-            label: Some("noisy_ssao_texture"),
-            size: Extent3d {
-                width: targets.size.x,
-                height: targets.size.y,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: TextureDimension::D2,
-            format: SSAO_TEXTURE_FORMAT,
-            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::STORAGE_BINDING,
-            view_formats: &[],
-        });
-
-        let noisy_ssao_texture_view = noisy_ssao_texture.create_view(&TextureViewDescriptor {
-            label: Some("noisy_ssao_texture"),
-            ..Default::default()
-        });
-
-        let ssao_texture = device.create_texture(&TextureDescriptor {
-            label: Some("ssao_texture"),
-            size: Extent3d {
-                width: targets.size.x,
-                height: targets.size.y,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: TextureDimension::D2,
-            format: SSAO_TEXTURE_FORMAT,
-            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::STORAGE_BINDING,
-            view_formats: &[],
-        });
-
-        let ssao_texture_view = ssao_texture.create_view(&TextureViewDescriptor {
-            label: Some("ssao_texture_view"),
-            ..Default::default()
-        });
+        Self::create_textures(assets, device, targets.size);
 
         let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
             label: Some("ssao_layout"),
@@ -355,25 +408,19 @@ impl RenderNode for SsaoNode {
             ],
         });
 
-        let sampler = device.create_sampler(&SamplerDescriptor {
-            label: Some("ssao_sampler"),
-            ..Default::default()
-        });
+        let sampler = create_sampler(
+            device,
+            &SamplerDescriptor {
+                label: Some("ssao_sampler"),
+                ..Default::default()
+            },
+            assets.max_anisotropy,
+        );
 
         assets
             .extra_layouts
             .insert(SSAO.ssao_compute_layout, compute_layout);
         assets.extra_layouts.insert(SSAO.ssao_layout, layout);
-        assets
-            .textures
-            .insert(SSAO.noisy_ssao_texture, noisy_ssao_texture);
-        assets
-            .texture_views
-            .insert(SSAO.noisy_ssao_texture_view, noisy_ssao_texture_view);
-        assets.textures.insert(SSAO.ssao_texture, ssao_texture);
-        assets
-            .texture_views
-            .insert(SSAO.ssao_texture_view, ssao_texture_view);
         assets.samplers.insert(SSAO.ssao_sampler, sampler);
 
         let denoise_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
@@ -434,7 +481,7 @@ impl RenderNode for SsaoNode {
             module: &node.shaders[1],
             entry_point: "main",
             compilation_options: Default::default(),
-            cache: None,
+            cache: node.pipeline_cache.as_deref(),
         });
 
         self.denoise_pipeline = Some(pipeline);
@@ -450,13 +497,36 @@ impl RenderNode for SsaoNode {
         assets.textures.insert(SSAO.hilbert_lut, hilbert_lut);
     }
 
+    fn set_quality(&mut self, quality: Quality) -> bool {
+        self.budget_quality = quality;
+        false
+    }
+
+    fn resize(
+        &mut self,
+        GpuScene { assets, .. }: &mut GpuScene,
+        RenderContext { device, .. }: RenderContext,
+        new_size: UVec2,
+    ) {
+        Self::create_textures(assets, device, new_size);
+    }
+
     fn prepare(
         &mut self,
         GpuScene { assets, .. }: &mut GpuScene,
         RenderContext { device, queue, .. }: RenderContext,
     ) {
+        let divisor = match self.budget_quality {
+            Quality::Low => 4,
+            Quality::Medium => 2,
+            Quality::High => 1,
+        };
         let mut bf_config = DynamicGpuBuffer::new(BufferUsages::UNIFORM);
-        bf_config.push(&self.config);
+        bf_config.push(&SsaoConfig {
+            slices: (self.config.slices / divisor).max(1),
+            samples: (self.config.samples / divisor).max(1),
+            ..self.config
+        });
         bf_config.write::<SsaoConfig>(device, queue);
 
         let compute_bind_group = device.create_bind_group(&BindGroupDescriptor {
@@ -617,3 +687,192 @@ impl RenderNode for SsaoNode {
         queue.submit([command_encoder.finish()]);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use aurora_core::{
+        render::{
+            flow::{GeneralNode, RenderFlow},
+            helper::{Camera, Transform},
+            mesh::{Mesh, MeshIndices, MeshVertexAttributeData},
+            scene::{GpuScene, MaterialInstanceId},
+        },
+        util::testing,
+        WgpuRenderer,
+    };
+    use glam::{UVec2, Vec2, Vec3, Vec4Swizzles};
+
+    use wgpu::{
+        BufferDescriptor, BufferUsages, ImageCopyBuffer, ImageDataLayout, Maintain, MapMode,
+    };
+
+    use super::{AoMode, SsaoConfig, SsaoNode, SSAO};
+    use crate::node::{DepthPrepassNode, NormalPrepassNode};
+
+    const SIZE: u32 = 128;
+
+    /// A floor meeting a wall at `z = -2`.
+    fn corner_scene() -> GpuScene {
+        let positions = vec![
+            Vec3::new(-2., 0., -2.),
+            Vec3::new(2., 0., -2.),
+            Vec3::new(2., 0., 2.),
+            Vec3::new(-2., 0., 2.),
+            Vec3::new(-2., 0., -2.),
+            Vec3::new(-2., 4., -2.),
+            Vec3::new(2., 4., -2.),
+            Vec3::new(2., 0., -2.),
+        ];
+        let normals = [[Vec3::Y; 4], [Vec3::Z; 4]].concat();
+        let uvs = [[Vec2::ZERO, Vec2::X, Vec2::ONE, Vec2::Y]; 2].concat();
+        let mut mesh = Mesh::new()
+            .with_attribute(
+                Mesh::POSITION_ATTR,
+                MeshVertexAttributeData::Float32x3(positions),
+            )
+            .with_attribute(
+                Mesh::NORMAL_ATTR,
+                MeshVertexAttributeData::Float32x3(normals),
+            )
+            .with_attribute(
+                Mesh::TEX_COORDS_ATTR,
+                MeshVertexAttributeData::Float32x2(uvs),
+            )
+            .with_indices(MeshIndices::UInt16(vec![
+                0, 2, 1, 0, 3, 2, 4, 6, 5, 4, 7, 6,
+            ]));
+        mesh.recalculate_tangent().unwrap();
+
+        let mut scene = GpuScene::default();
+        testing::add_static_mesh(&mut scene, mesh, MaterialInstanceId::default());
+        scene.original.camera = Camera {
+            transform: Transform::default()
+                .with_translation(Vec3::new(0., 1.2, 1.5))
+                .looking_at(Vec3::new(0., 0., -1.), Vec3::Y),
+            ..Default::default()
+        };
+        scene
+    }
+
+    fn read_ao(renderer: &WgpuRenderer, scene: &GpuScene) -> Vec<f32> {
+        let readback = renderer.device.create_buffer(&BufferDescriptor {
+            label: None,
+            size: (SIZE * SIZE * 4) as u64,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = renderer.device.create_command_encoder(&Default::default());
+        let texture = &scene.assets.textures[&SSAO.noisy_ssao_texture];
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            ImageCopyBuffer {
+                buffer: &readback,
+                layout: ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(SIZE * 4),
+                    rows_per_image: None,
+                },
+            },
+            texture.size(),
+        );
+        renderer.queue.submit([encoder.finish()]);
+
+        readback.slice(..).map_async(MapMode::Read, |r| r.unwrap());
+        renderer.device.poll(Maintain::Wait).panic_on_timeout();
+        let ao = bytemuck::cast_slice(&readback.slice(..).get_mapped_range()).to_vec();
+        ao
+    }
+
+    /// Average over a few texels around where `position` lands, to smooth out the noise.
+    fn ao_at(ao: &[f32], scene: &GpuScene, position: Vec3) -> f32 {
+        let camera = scene.original.camera;
+        let clip = camera.compute_projection()
+            * camera.transform.compute_matrix().inverse()
+            * position.extend(1.);
+        let ndc = clip.xy() / clip.w;
+        let texel = ((Vec2::new(ndc.x, -ndc.y) * 0.5 + 0.5) * SIZE as f32).as_ivec2();
+
+        let mut sum = 0.;
+        for y in -2..=2 {
+            for x in -2..=2 {
+                sum += ao[((texel.y + y) as u32 * SIZE + (texel.x + x) as u32) as usize];
+            }
+        }
+        sum / 25.
+    }
+
+    /// Enough slices and samples, reaching far enough, for GTAO to get close to the ground
+    /// truth.
+    fn converged_config() -> SsaoConfig {
+        SsaoConfig {
+            slices: 16,
+            samples: 32,
+            falloff_range: 2.,
+            thickness: 1.,
+            ..Default::default()
+        }
+    }
+
+    /// Visibility on the floor at each distance from the wall, from the open to the corner.
+    fn floor_profile(renderer: &WgpuRenderer, mode: AoMode, distances: &[f32]) -> Vec<f32> {
+        let mut scene = corner_scene();
+        let mut flow = RenderFlow::default();
+        flow.add::<GeneralNode>()
+            .add::<DepthPrepassNode>()
+            .add::<NormalPrepassNode>()
+            .add_initialized(SsaoNode {
+                mode,
+                config: converged_config(),
+                ..Default::default()
+            });
+        flow.capture_sync(renderer, &mut scene, UVec2::splat(SIZE))
+            .unwrap();
+
+        let ao = read_ao(renderer, &scene);
+        distances
+            .iter()
+            .map(|distance| ao_at(&ao, &scene, Vec3::new(0., 0., distance - 2.)))
+            .collect()
+    }
+
+    /// Distances to the wall, from the open to the corner.
+    const DISTANCES: [f32; 9] = [2., 1.5, 1., 0.5, 0.3, 0.2, 0.1, 0.05, 0.03];
+
+    fn assert_darkens_towards_wall(profile: &[f32]) {
+        assert!(profile[0] > 0.99, "{profile:?}");
+        assert!(
+            profile.windows(2).all(|w| w[1] < w[0] + 0.01),
+            "{profile:?}"
+        );
+    }
+
+    #[test]
+    fn gtao_darkens_corners() {
+        let Some(renderer) = testing::renderer(None, None) else {
+            return;
+        };
+
+        let profile = floor_profile(&renderer, AoMode::Gtao, &DISTANCES);
+        assert_darkens_towards_wall(&profile);
+        // The wall hides half the hemisphere, a visibility of 0.5 is about 0.68 after the
+        // multi-bounce approximation.
+        let corner = profile[DISTANCES.len() - 1];
+        assert!((corner - 0.68).abs() < 0.05, "{profile:?}");
+    }
+
+    #[test]
+    fn gtao_against_hbao() {
+        let Some(renderer) = testing::renderer(None, None) else {
+            return;
+        };
+
+        let gtao = floor_profile(&renderer, AoMode::Gtao, &DISTANCES);
+        let hbao = floor_profile(&renderer, AoMode::Hbao, &DISTANCES);
+        assert_darkens_towards_wall(&hbao);
+        // HBAO darkens the corner more with its default strength, otherwise they agree.
+        assert!(
+            gtao.iter().zip(&hbao).all(|(g, h)| (g - h).abs() < 0.15),
+            "{gtao:?} {hbao:?}"
+        );
+    }
+}
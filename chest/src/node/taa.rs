@@ -0,0 +1,387 @@
+use aurora_core::{
+    render::{
+        flow::{DependencyNodeIndex, RenderContext, RenderNode},
+        resource::DynamicGpuBuffer,
+        scene::GpuScene,
+    },
+    util::create_sampler,
+};
+use encase::ShaderType;
+use glam::UVec2;
+use wgpu::{
+    AddressMode, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, BufferBindingType, BufferUsages,
+    ColorTargetState, ColorWrites, Device, Extent3d, FilterMode, FragmentState,
+    PipelineLayoutDescriptor, RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline,
+    RenderPipelineDescriptor, Sampler, SamplerBindingType, SamplerDescriptor, ShaderStages,
+    Texture, TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType, TextureUsages,
+    TextureView, TextureViewDimension, VertexState,
+};
+
+use crate::node::{DepthPrepassNode, MotionVectorPrepassNode, MOTION_VECTOR_PREPASS_TEXTURE};
+
+#[derive(ShaderType)]
+pub struct TaaConfig {
+    /// How much of the history is kept each frame.
+    pub feedback: f32,
+}
+
+impl Default for TaaConfig {
+    fn default() -> Self {
+        Self { feedback: 0.9 }
+    }
+}
+
+pub struct TaaNodeData {
+    pub pipeline: RenderPipeline,
+    pub layout: BindGroupLayout,
+    pub color_sampler: Sampler,
+    pub motion_vector_sampler: Sampler,
+    pub config: DynamicGpuBuffer,
+}
+
+/// Requires the camera to be jittered, which the flow does unless disabled with
+/// [`RenderFlow::set_jitter`].
+///
+/// [`RenderFlow::set_jitter`]: aurora_core::render::flow::RenderFlow::set_jitter
+pub struct TaaNode {
+    pub config: TaaConfig,
+
+    pub history: Option<Texture>,
+    pub history_view: Option<TextureView>,
+    pub history_size: UVec2,
+    pub data: Option<TaaNodeData>,
+}
+
+impl Default for TaaNode {
+    fn default() -> Self {
+        Self {
+            config: Default::default(),
+            history: None,
+            history_view: None,
+            history_size: UVec2::ZERO,
+            data: None,
+        }
+    }
+}
+
+impl TaaNode {
+    fn create_history(device: &Device, size: UVec2, format: TextureFormat) -> Texture {
+        device.create_texture(&TextureDescriptor {
+            label: Some("taa_history"),
+            size: Extent3d {
+                width: size.x,
+                height: size.y,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+            view_formats: &[],
+        })
+    }
+}
+
+impl RenderNode for TaaNode {
+    fn add_node_dependencies(&self) -> Vec<(DependencyNodeIndex, Box<dyn RenderNode>)> {
+        vec![
+            (
+                DependencyNodeIndex::Before,
+                Box::new(DepthPrepassNode::default()),
+            ),
+            (
+                DependencyNodeIndex::Before,
+                Box::new(MotionVectorPrepassNode::default()),
+            ),
+        ]
+    }
+
+    fn require_jitter(&self) -> bool {
+        true
+    }
+
+    fn require_shaders(&self) -> Option<&'static [(&'static [&'static str], &'static str)]> {
+        Some(&[
+            (&[], include_str!("../shader/fullscreen.wgsl")),
+            (
+                &[include_str!("../shader/fullscreen.wgsl")],
+                include_str!("../shader/post_processing/taa.wgsl"),
+            ),
+        ])
+    }
+
+    fn build(
+        &mut self,
+        GpuScene { assets, .. }: &mut GpuScene,
+        RenderContext {
+            device,
+            node,
+            targets,
+            ..
+        }: RenderContext,
+    ) {
+        let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("taa_layout"),
+            entries: &[
+                // Color
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                // History
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                // Motion Vector
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: false },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                // Color Sampler
+                BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+                // Motion Vector Sampler
+                BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::NonFiltering),
+                    count: None,
+                },
+                // Config
+                BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: Some(TaaConfig::min_size()),
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("taa_pipeline_layout"),
+            bind_group_layouts: &[&layout],
+            ..Default::default()
+        });
+
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("taa_pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &node.shaders[0],
+                entry_point: "vertex",
+                compilation_options: Default::default(),
+                buffers: &[],
+            },
+            fragment: Some(FragmentState {
+                module: &node.shaders[1],
+                entry_point: "fragment",
+                compilation_options: Default::default(),
+                targets: &[Some(ColorTargetState {
+                    format: targets.color_format,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: Default::default(),
+            depth_stencil: Default::default(),
+            multisample: Default::default(),
+            multiview: Default::default(),
+            cache: node.pipeline_cache.as_deref(),
+        });
+
+        let color_sampler = create_sampler(
+            device,
+            &SamplerDescriptor {
+                label: Some("taa_color_sampler"),
+                address_mode_u: AddressMode::ClampToEdge,
+                address_mode_v: AddressMode::ClampToEdge,
+                mag_filter: FilterMode::Linear,
+                min_filter: FilterMode::Linear,
+                ..Default::default()
+            },
+            assets.max_anisotropy,
+        );
+
+        let motion_vector_sampler = create_sampler(
+            device,
+            &SamplerDescriptor {
+                label: Some("taa_motion_vector_sampler"),
+                ..Default::default()
+            },
+            assets.max_anisotropy,
+        );
+
+        self.history = Some(Self::create_history(
+            device,
+            targets.size,
+            targets.swap_chain.desc().format,
+        ));
+        self.history_view = self
+            .history
+            .as_ref()
+            .map(|history| history.create_view(&Default::default()));
+        self.history_size = targets.size;
+
+        self.data = Some(TaaNodeData {
+            pipeline,
+            layout,
+            color_sampler,
+            motion_vector_sampler,
+            config: DynamicGpuBuffer::new(BufferUsages::UNIFORM),
+        });
+    }
+
+    fn prepare(
+        &mut self,
+        _scene: &mut GpuScene,
+        RenderContext {
+            device,
+            queue,
+            targets,
+            ..
+        }: RenderContext,
+    ) {
+        if self.history_size != targets.size {
+            if let Some(history) = self.history.take() {
+                history.destroy();
+            }
+            self.history = Some(Self::create_history(
+                device,
+                targets.size,
+                targets.swap_chain.desc().format,
+            ));
+            self.history_view = self
+                .history
+                .as_ref()
+                .map(|history| history.create_view(&Default::default()));
+            self.history_size = targets.size;
+        }
+
+        let Some(TaaNodeData { config, .. }) = &mut self.data else {
+            return;
+        };
+
+        config.clear();
+        config.push(&self.config);
+        config.write::<TaaConfig>(device, queue);
+    }
+
+    fn draw(
+        &self,
+        GpuScene { assets, .. }: &mut GpuScene,
+        RenderContext {
+            device,
+            queue,
+            targets,
+            ..
+        }: RenderContext,
+    ) {
+        let (
+            Some(TaaNodeData {
+                pipeline,
+                layout,
+                color_sampler,
+                motion_vector_sampler,
+                config,
+            }),
+            Some(history),
+            Some(history_view),
+        ) = (&self.data, &self.history, &self.history_view)
+        else {
+            return;
+        };
+
+        let post_process = targets.swap_chain.start_post_process();
+        let bind_group = assets.bind_group_cache.get_or_create(
+            device,
+            &BindGroupDescriptor {
+                label: Some("taa_bind_group"),
+                layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::TextureView(post_process.src),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::TextureView(history_view),
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: BindingResource::TextureView(
+                            &assets.texture_views[&MOTION_VECTOR_PREPASS_TEXTURE.view],
+                        ),
+                    },
+                    BindGroupEntry {
+                        binding: 3,
+                        resource: BindingResource::Sampler(color_sampler),
+                    },
+                    BindGroupEntry {
+                        binding: 4,
+                        resource: BindingResource::Sampler(motion_vector_sampler),
+                    },
+                    BindGroupEntry {
+                        binding: 5,
+                        resource: config.entire_binding().unwrap(),
+                    },
+                ],
+            },
+        );
+
+        let mut command_encoder = device.create_command_encoder(&Default::default());
+
+        {
+            let mut pass = command_encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("taa_pass"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: post_process.dst,
+                    resolve_target: None,
+                    ops: Default::default(),
+                })],
+                ..Default::default()
+            });
+
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.draw(0..3, 0..1);
+        }
+
+        // The resolved frame becomes the history of the next one.
+        command_encoder.copy_texture_to_texture(
+            targets.swap_chain.current_texture().as_image_copy(),
+            history.as_image_copy(),
+            history.size(),
+        );
+
+        queue.submit([command_encoder.finish()]);
+    }
+}
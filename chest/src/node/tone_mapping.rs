@@ -1,39 +1,93 @@
 use std::collections::HashMap;
 
-use aurora_core::render::{
-    flow::{RenderContext, RenderNode},
-    scene::GpuScene,
-    ShaderDefEnum,
+use aurora_core::{
+    render::{
+        flow::{RenderContext, RenderNode},
+        resource::DynamicGpuBuffer,
+        scene::GpuScene,
+        ShaderDefEnum,
+    },
+    util::create_sampler,
 };
 use aurora_derive::ShaderDefEnum;
+use encase::ShaderType;
 use naga_oil::compose::ShaderDefValue;
 use wgpu::{
     BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
-    BindGroupLayoutEntry, BindingResource, BindingType, ColorTargetState, ColorWrites, FilterMode,
-    FragmentState, PipelineLayoutDescriptor, RenderPassColorAttachment, RenderPassDescriptor,
-    RenderPipeline, RenderPipelineDescriptor, Sampler, SamplerBindingType, SamplerDescriptor,
-    ShaderStages, Texture, TextureSampleType, TextureViewDimension, VertexState,
+    BindGroupLayoutEntry, BindingResource, BindingType, BufferBindingType, BufferUsages,
+    ColorTargetState, ColorWrites, FilterMode, FragmentState, PipelineLayoutDescriptor,
+    RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor,
+    Sampler, SamplerBindingType, SamplerDescriptor, ShaderStages, Texture, TextureSampleType,
+    TextureView, TextureViewDimension, VertexState,
 };
 
 use crate::texture::load_dds_texture;
 
-#[derive(ShaderDefEnum, Default)]
-pub enum TonemappingMethod {
+/// Curve mapping exposed HDR radiance into displayable range.
+///
+/// Exposure is already applied by `PbrNode`, so operators add no exposure bias of their own.
+#[derive(ShaderDefEnum, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TonemappingOperator {
+    /// Per channel `x / (1 + x)`.
     Reinhard,
+    /// Luminance based Reinhard, reaching white at a finite luminance.
+    ReinhardExtended,
+    /// Stephen Hill's fit of the ACES RRT and ODT.
+    AcesFitted,
+    /// John Hable's filmic curve.
+    #[def_name = "UNCHARTED2"]
+    Uncharted2,
     #[default]
     TonyMcMapface,
 }
 
+#[derive(ShaderType)]
+struct TonemappingUniform {
+    white_point: f32,
+    highlight_desaturation: f32,
+    /// Output scale of SDR white on HDR surfaces, 0 tonemaps to SDR.
+    hdr_paper_white: f32,
+    hdr_peak: f32,
+    encode_srgb: u32,
+}
+
+/// scRGB 1.0, the unit of HDR surfaces.
+const SCRGB_WHITE_NITS: f32 = 80.;
+
 pub struct TonemappingNodeData {
     pub pipeline: RenderPipeline,
     pub layout: BindGroupLayout,
     pub lut_sampler: Sampler,
     pub color_sampler: Sampler,
     pub lut: Texture,
+    pub lut_view: TextureView,
+    pub uniform: DynamicGpuBuffer,
+    /// Whether the surface was HDR when built.
+    pub hdr: bool,
+    /// Whether the output is written to a surface expecting sRGB without a format encoding
+    /// it, see [`RenderTargets::encodes_srgb`](aurora_core::render::resource::RenderTargets::encodes_srgb).
+    pub encode_srgb: bool,
 }
 
 pub struct TonemappingNode {
-    pub method: Option<TonemappingMethod>,
+    pub operator: TonemappingOperator,
+    /// Write the result to the surface directly. Otherwise the result is written back
+    /// to the swap chain for further post processing, and a `PresentNode` is required.
+    pub to_surface: bool,
+    /// Luminance mapped to white, brighter highlights clip. `None` keeps the operator's own.
+    ///
+    /// Ignored by [`TonemappingOperator::TonyMcMapface`], whose response is baked.
+    pub white_point: Option<f32>,
+    /// From 0 to 1, how much highlights fade towards white instead of clipping one channel
+    /// at a time. Ignored by [`TonemappingOperator::TonyMcMapface`] as well.
+    pub highlight_desaturation: f32,
+    /// Nits of SDR white on HDR surfaces, see
+    /// [`RenderTargets::presents_hdr`](aurora_core::render::resource::RenderTargets::presents_hdr).
+    /// The operator is skipped then, highlights only roll off towards
+    /// [`TonemappingNode::hdr_peak_luminance`].
+    pub hdr_paper_white: f32,
+    /// Nits of the brightest output on HDR surfaces, ideally the peak of the display.
+    pub hdr_peak_luminance: f32,
 
     pub data: Option<TonemappingNodeData>,
 }
@@ -41,7 +95,13 @@ pub struct TonemappingNode {
 impl Default for TonemappingNode {
     fn default() -> Self {
         Self {
-            method: Some(Default::default()),
+            operator: Default::default(),
+            to_surface: true,
+            white_point: None,
+            highlight_desaturation: 0.,
+            // BT.2408 reference white.
+            hdr_paper_white: 203.,
+            hdr_peak_luminance: 1000.,
             data: Default::default(),
         }
     }
@@ -49,7 +109,7 @@ impl Default for TonemappingNode {
 
 impl RenderNode for TonemappingNode {
     fn require_shader_defs(&self, shader_defs: &mut HashMap<String, ShaderDefValue>) {
-        shader_defs.extend([self.method.as_ref().unwrap().to_def()]);
+        shader_defs.extend([self.operator.to_def()]);
     }
 
     fn require_shaders(&self) -> Option<&'static [(&'static [&'static str], &'static str)]> {
@@ -64,7 +124,7 @@ impl RenderNode for TonemappingNode {
 
     fn build(
         &mut self,
-        _scene: &mut GpuScene,
+        GpuScene { assets, .. }: &mut GpuScene,
         RenderContext {
             device,
             queue,
@@ -111,6 +171,17 @@ impl RenderNode for TonemappingNode {
                     ty: BindingType::Sampler(SamplerBindingType::Filtering),
                     count: None,
                 },
+                // Config
+                BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: Some(TonemappingUniform::min_size()),
+                    },
+                    count: None,
+                },
             ],
         });
 
@@ -133,7 +204,11 @@ impl RenderNode for TonemappingNode {
                 entry_point: "fragment",
                 compilation_options: Default::default(),
                 targets: &[Some(ColorTargetState {
-                    format: targets.surface_format,
+                    format: if self.to_surface {
+                        targets.surface_format
+                    } else {
+                        targets.color_format
+                    },
                     blend: None,
                     write_mask: ColorWrites::ALL,
                 })],
@@ -142,39 +217,85 @@ impl RenderNode for TonemappingNode {
             depth_stencil: Default::default(),
             multisample: Default::default(),
             multiview: Default::default(),
-            cache: Default::default(),
+            cache: node.pipeline_cache.as_deref(),
         });
 
-        let lut_sampler = device.create_sampler(&SamplerDescriptor {
-            label: Some("lut_sampler"),
-            mag_filter: FilterMode::Linear,
-            min_filter: FilterMode::Linear,
-            mipmap_filter: FilterMode::Linear,
-            ..Default::default()
-        });
+        let lut_sampler = create_sampler(
+            device,
+            &SamplerDescriptor {
+                label: Some("lut_sampler"),
+                mag_filter: FilterMode::Linear,
+                min_filter: FilterMode::Linear,
+                mipmap_filter: FilterMode::Linear,
+                ..Default::default()
+            },
+            assets.max_anisotropy,
+        );
 
-        let color_sampler = device.create_sampler(&SamplerDescriptor {
-            label: Some("color_sampler"),
-            mag_filter: FilterMode::Linear,
-            min_filter: FilterMode::Linear,
-            mipmap_filter: FilterMode::Linear,
-            ..Default::default()
-        });
+        let color_sampler = create_sampler(
+            device,
+            &SamplerDescriptor {
+                label: Some("color_sampler"),
+                mag_filter: FilterMode::Linear,
+                min_filter: FilterMode::Linear,
+                mipmap_filter: FilterMode::Linear,
+                ..Default::default()
+            },
+            assets.max_anisotropy,
+        );
 
-        let lut = load_dds_texture(device, queue, "chest/assets/luts/tony_mc_mapface.dds");
+        let lut = load_dds_texture(
+            device,
+            queue,
+            concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/assets/luts/tony_mc_mapface.dds"
+            ),
+        );
 
         self.data = Some(TonemappingNodeData {
             pipeline,
             layout,
             lut_sampler,
             color_sampler,
+            lut_view: lut.create_view(&Default::default()),
             lut,
+            uniform: DynamicGpuBuffer::new(BufferUsages::UNIFORM),
+            hdr: targets.presents_hdr(),
+            encode_srgb: self.to_surface && targets.encodes_srgb(),
         });
     }
 
+    fn prepare(
+        &mut self,
+        _scene: &mut GpuScene,
+        RenderContext { device, queue, .. }: RenderContext,
+    ) {
+        let Some(TonemappingNodeData {
+            uniform,
+            hdr,
+            encode_srgb,
+            ..
+        }) = &mut self.data
+        else {
+            return;
+        };
+
+        let hdr_scale = |nits: f32| if *hdr { nits / SCRGB_WHITE_NITS } else { 0. };
+        uniform.clear();
+        uniform.push(&TonemappingUniform {
+            white_point: self.white_point.unwrap_or_default(),
+            highlight_desaturation: self.highlight_desaturation,
+            hdr_paper_white: hdr_scale(self.hdr_paper_white),
+            hdr_peak: hdr_scale(self.hdr_peak_luminance),
+            encode_srgb: *encode_srgb as u32,
+        });
+        uniform.write::<TonemappingUniform>(device, queue);
+    }
+
     fn draw(
         &self,
-        _scene: &mut GpuScene,
+        GpuScene { assets, .. }: &mut GpuScene,
         RenderContext {
             device,
             queue,
@@ -185,39 +306,47 @@ impl RenderNode for TonemappingNode {
         let data = self.data.as_ref().unwrap();
         let post_process = targets.swap_chain.start_post_process();
 
-        let bind_group = device.create_bind_group(&BindGroupDescriptor {
-            label: Some("tonemapping_bind_group"),
-            layout: &data.layout,
-            entries: &[
-                BindGroupEntry {
-                    binding: 0,
-                    resource: BindingResource::TextureView(post_process.src),
-                },
-                BindGroupEntry {
-                    binding: 1,
-                    resource: BindingResource::TextureView(
-                        &data.lut.create_view(&Default::default()),
-                    ),
-                },
-                BindGroupEntry {
-                    binding: 2,
-                    resource: BindingResource::Sampler(&data.color_sampler),
-                },
-                BindGroupEntry {
-                    binding: 3,
-                    resource: BindingResource::Sampler(&data.lut_sampler),
-                },
-            ],
-        });
+        let bind_group = assets.bind_group_cache.get_or_create(
+            device,
+            &BindGroupDescriptor {
+                label: Some("tonemapping_bind_group"),
+                layout: &data.layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::TextureView(post_process.src),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::TextureView(&data.lut_view),
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: BindingResource::Sampler(&data.color_sampler),
+                    },
+                    BindGroupEntry {
+                        binding: 3,
+                        resource: BindingResource::Sampler(&data.lut_sampler),
+                    },
+                    BindGroupEntry {
+                        binding: 4,
+                        resource: data.uniform.entire_binding().unwrap(),
+                    },
+                ],
+            },
+        );
 
-        let data = self.data.as_ref().unwrap();
         let mut command_encoder = device.create_command_encoder(&Default::default());
 
         {
             let mut pass = command_encoder.begin_render_pass(&RenderPassDescriptor {
                 label: Some("tonemapping_pass"),
                 color_attachments: &[Some(RenderPassColorAttachment {
-                    view: &targets.surface,
+                    view: if self.to_surface {
+                        &targets.surface
+                    } else {
+                        post_process.dst
+                    },
                     resolve_target: None,
                     ops: Default::default(),
                 })],
@@ -231,4 +360,258 @@ impl RenderNode for TonemappingNode {
 
         queue.submit([command_encoder.finish()]);
     }
+
+    fn writes_surface(&self) -> bool {
+        self.to_surface
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aurora_core::{
+        render::{flow::RenderFlow, scene::GpuScene},
+        util::{
+            self,
+            testing::{self, TestTargets},
+            TextureReadback,
+        },
+        WgpuRenderer,
+    };
+    use glam::{UVec2, Vec3};
+    use half::f16;
+    use wgpu::{
+        Extent3d, ImageCopyTexture, ImageDataLayout, Maintain, Origin3d, Texture, TextureAspect,
+        TextureFormat,
+    };
+
+    use super::{TonemappingNode, TonemappingOperator};
+
+    /// Exponential ramps from 1/16 to 64 in three tints, one band each.
+    fn hdr_frame(size: UVec2) -> Vec<f16> {
+        let tints = [Vec3::ONE, Vec3::new(1., 0.5, 0.2), Vec3::new(0.2, 0.5, 1.)];
+        (0..size.y)
+            .flat_map(|y| {
+                let tint = tints[(y * tints.len() as u32 / size.y) as usize];
+                (0..size.x).flat_map(move |x| {
+                    let stimulus = (x as f32 / (size.x - 1) as f32 * 10. - 4.).exp2();
+                    (tint * stimulus).extend(1.).to_array().map(f16::from_f32)
+                })
+            })
+            .collect()
+    }
+
+    fn tonemap(
+        renderer: &WgpuRenderer,
+        node: TonemappingNode,
+        size: UVec2,
+        frame: &[f16],
+        surface_format: TextureFormat,
+        hdr_output: bool,
+    ) -> Texture {
+        let mut test_targets = TestTargets::new(&renderer.device, size, TextureFormat::Rgba16Float)
+            .with_surface_format(&renderer.device, surface_format);
+        test_targets.hdr_output = hdr_output;
+        // Post processing reads from the current texture first.
+        renderer.queue.write_texture(
+            ImageCopyTexture {
+                texture: test_targets.swap_chain.current_texture(),
+                mip_level: 0,
+                origin: Origin3d::ZERO,
+                aspect: TextureAspect::All,
+            },
+            bytemuck::cast_slice(frame),
+            ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(size.x * 8),
+                rows_per_image: None,
+            },
+            Extent3d {
+                width: size.x,
+                height: size.y,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        let targets = test_targets.targets();
+
+        let mut scene = GpuScene::default();
+        let mut flow = RenderFlow::default();
+        flow.add_initialized(node);
+        flow.build(renderer, &mut scene, None, &targets).unwrap();
+        flow.run(renderer, &mut scene, &targets);
+
+        test_targets.surface
+    }
+
+    #[test]
+    fn tonemapping_operators() {
+        let Some(renderer) = testing::renderer(None, None) else {
+            return;
+        };
+
+        let size = UVec2::new(256, 96);
+        let frame = hdr_frame(size);
+
+        for operator in [
+            TonemappingOperator::Reinhard,
+            TonemappingOperator::ReinhardExtended,
+            TonemappingOperator::AcesFitted,
+            TonemappingOperator::Uncharted2,
+            TonemappingOperator::TonyMcMapface,
+        ] {
+            let node = TonemappingNode {
+                operator,
+                ..Default::default()
+            };
+            let surface = tonemap(
+                &renderer,
+                node,
+                size,
+                &frame,
+                TextureFormat::Rgba8UnormSrgb,
+                false,
+            );
+
+            pollster::block_on(util::save_color_texture_as_image(
+                format!(
+                    concat!(
+                        env!("CARGO_MANIFEST_DIR"),
+                        "/../generated/tonemapping/{:?}.png"
+                    ),
+                    operator
+                ),
+                &surface,
+                &renderer.device,
+                &renderer.queue,
+            ));
+        }
+    }
+
+    #[test]
+    fn tonemapping_white_point() {
+        let Some(renderer) = testing::renderer(None, None) else {
+            return;
+        };
+
+        let size = UVec2::splat(4);
+        let pixel = |node: TonemappingNode, color: Vec3| {
+            let frame = [color.extend(1.).to_array().map(f16::from_f32)].repeat(16);
+            let surface = tonemap(
+                &renderer,
+                node,
+                size,
+                frame.concat().as_slice(),
+                TextureFormat::Rgba8UnormSrgb,
+                false,
+            );
+            pollster::block_on(util::read_color_texture(
+                &surface,
+                &renderer.device,
+                &renderer.queue,
+            ))
+            .get_pixel(0, 0)
+            .0
+        };
+
+        for operator in [
+            TonemappingOperator::Reinhard,
+            TonemappingOperator::ReinhardExtended,
+            TonemappingOperator::AcesFitted,
+            TonemappingOperator::Uncharted2,
+        ] {
+            let node = |white_point| TonemappingNode {
+                operator,
+                white_point,
+                ..Default::default()
+            };
+            // Clips right at the white point, well below the default one.
+            assert_eq!(
+                pixel(node(Some(2.)), Vec3::splat(2.))[0],
+                255,
+                "{operator:?}"
+            );
+            assert!(pixel(node(None), Vec3::splat(2.))[0] < 255, "{operator:?}");
+        }
+
+        let spread = |highlight_desaturation| {
+            let [r, _, b, _] = pixel(
+                TonemappingNode {
+                    operator: TonemappingOperator::ReinhardExtended,
+                    highlight_desaturation,
+                    ..Default::default()
+                },
+                Vec3::new(3., 1.5, 0.5),
+            );
+            r - b
+        };
+        assert!(spread(1.) < spread(0.));
+    }
+
+    #[test]
+    fn hdr_surface() {
+        let Some(renderer) = testing::renderer(None, None) else {
+            return;
+        };
+
+        let size = UVec2::splat(4);
+        let texel = |hdr_output, color: Vec3| {
+            let frame = [color.extend(1.).to_array().map(f16::from_f32)].repeat(16);
+            let surface = tonemap(
+                &renderer,
+                TonemappingNode::default(),
+                size,
+                frame.concat().as_slice(),
+                TextureFormat::Rgba16Float,
+                hdr_output,
+            );
+            let readback =
+                TextureReadback::new(&surface, &renderer.device, &renderer.queue).unwrap();
+            renderer.device.poll(Maintain::wait()).panic_on_timeout();
+            let data = pollster::block_on(readback.read()).unwrap();
+            bytemuck::pod_collect_to_vec::<u8, f16>(&data.bytes)[0].to_f32()
+        };
+
+        // 203 nits of paper white, slightly rolled off, in scRGB units of 80 nits.
+        let white = texel(true, Vec3::ONE);
+        assert!((2. ..203. / 80.).contains(&white), "{white}");
+        // Highlights approach the 1000 nit peak without passing it.
+        let highlight = texel(true, Vec3::splat(1000.));
+        assert!(highlight > 12. && highlight <= 1000. / 80., "{highlight}");
+        // Without the request, the same surface gets SDR.
+        assert!(texel(false, Vec3::splat(1000.)) <= 1.);
+    }
+
+    #[test]
+    fn unorm_surface_encoding() {
+        let Some(renderer) = testing::renderer(None, None) else {
+            return;
+        };
+
+        let size = UVec2::new(256, 96);
+        let frame = hdr_frame(size);
+        let image = |surface_format| {
+            let surface = tonemap(
+                &renderer,
+                TonemappingNode::default(),
+                size,
+                &frame,
+                surface_format,
+                false,
+            );
+            pollster::block_on(util::read_color_texture(
+                &surface,
+                &renderer.device,
+                &renderer.queue,
+            ))
+        };
+
+        // Encoded by the shader instead of the format, both look the same.
+        let encoded = image(TextureFormat::Rgba8UnormSrgb);
+        let unorm = image(TextureFormat::Rgba8Unorm);
+        assert!(encoded.pixels().zip(unorm.pixels()).all(|(a, b)| a
+            .0
+            .iter()
+            .zip(b.0)
+            .all(|(a, b)| a.abs_diff(b) <= 1)));
+    }
 }
@@ -0,0 +1,277 @@
+use std::{any::TypeId, collections::hash_map::Entry};
+
+use aurora_core::{
+    render::{
+        flow::{DependencyNodeIndex, RenderContext, RenderNode},
+        mesh::{CreateBindGroupLayout, MeshVertexBufferLayout},
+        resource::{DynamicGpuBuffer, ModelUniforms, MATERIAL_OVERRIDE},
+        scene::{GpuScene, MaterialTypeId},
+    },
+    util::ext::TypeIdAsUuid,
+};
+use wgpu::{
+    BufferUsages, ColorTargetState, ColorWrites, CommandBuffer, CompareFunction, DepthStencilState,
+    Face, FragmentState, LoadOp, Operations, PipelineLayoutDescriptor, PrimitiveState,
+    RenderPassColorAttachment, RenderPassDepthStencilAttachment, RenderPassDescriptor,
+    RenderPipelineDescriptor, StoreOp, VertexFormat, VertexState,
+};
+
+use crate::{
+    material::{UnlitMaterial, UnlitMaterialUniform},
+    node::{
+        pbr::mesh_material, DepthLoadOp, DepthPrepassNode, DEPTH_PREPASS_FORMAT,
+        DEPTH_PREPASS_TEXTURE,
+    },
+};
+
+/// Draws meshes with [`UnlitMaterial`], skipping every other mesh.
+///
+/// Always single sampled, so add it after [`PbrNode`](crate::node::PbrNode) when using msaa.
+pub struct UnlitNode {
+    pub mat_uuid: MaterialTypeId,
+    /// Loads by default, so meshes drawn by earlier nodes occlude unlit ones.
+    pub depth_load_op: DepthLoadOp,
+    pub models: Option<ModelUniforms>,
+}
+
+impl Default for UnlitNode {
+    fn default() -> Self {
+        Self {
+            mat_uuid: Default::default(),
+            depth_load_op: DepthLoadOp::Load,
+            models: None,
+        }
+    }
+}
+
+impl RenderNode for UnlitNode {
+    fn restrict_mesh_format(&self) -> Option<&'static [VertexFormat]> {
+        Some(&[
+            VertexFormat::Float32x3,
+            VertexFormat::Float32x3,
+            VertexFormat::Float32x2,
+        ])
+    }
+
+    fn add_node_dependencies(&self) -> Vec<(DependencyNodeIndex, Box<dyn RenderNode>)> {
+        vec![(
+            DependencyNodeIndex::Before,
+            Box::new(DepthPrepassNode::default()),
+        )]
+    }
+
+    fn require_shaders(&self) -> Option<&'static [(&'static [&'static str], &'static str)]> {
+        Some(&[(
+            &[
+                include_str!("../shader/common/common_type.wgsl"),
+                include_str!("../shader/common/common_binding.wgsl"),
+            ],
+            include_str!("../shader/unlit/unlit.wgsl"),
+        )])
+    }
+
+    fn build(
+        &mut self,
+        GpuScene {
+            original, assets, ..
+        }: &mut GpuScene,
+        RenderContext {
+            device,
+            node,
+            targets,
+            material_override,
+            ..
+        }: RenderContext,
+    ) {
+        self.mat_uuid = MaterialTypeId(TypeId::of::<UnlitMaterial>().to_uuid());
+        UnlitMaterial::create_layout(device, assets);
+        let models = self.models.insert(ModelUniforms::new(device));
+
+        let layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("unlit_pipeline_layout"),
+            bind_group_layouts: &[
+                assets.common_layout.as_ref().unwrap(),
+                &assets.material_layouts[&self.mat_uuid],
+                &models.layout,
+            ],
+            push_constant_ranges: &[],
+        });
+
+        node.pipelines.clear();
+        for mesh in &node.meshes {
+            if !mesh_material(original, material_override, &mesh.mesh)
+                .is_some_and(|m| m.id() == self.mat_uuid)
+            {
+                continue;
+            }
+
+            let instance = &assets.meshes[&mesh.mesh.mesh];
+            let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+                label: Some("unlit_pipeline"),
+                layout: Some(&layout),
+                vertex: VertexState {
+                    module: &node.shaders[0],
+                    entry_point: "vertex",
+                    compilation_options: Default::default(),
+                    buffers: &instance
+                        .vertex_buffer_layouts()
+                        .iter()
+                        .map(MeshVertexBufferLayout::layout)
+                        .collect::<Vec<_>>(),
+                },
+                fragment: Some(FragmentState {
+                    module: &node.shaders[0],
+                    entry_point: "fragment",
+                    compilation_options: Default::default(),
+                    targets: &[Some(ColorTargetState {
+                        format: targets.color_format,
+                        blend: None,
+                        write_mask: ColorWrites::ALL,
+                    })],
+                }),
+                primitive: PrimitiveState {
+                    cull_mode: Some(Face::Back),
+                    ..Default::default()
+                },
+                depth_stencil: Some(DepthStencilState {
+                    format: DEPTH_PREPASS_FORMAT,
+                    depth_write_enabled: true,
+                    depth_compare: CompareFunction::LessEqual,
+                    stencil: Default::default(),
+                    bias: Default::default(),
+                }),
+                multisample: Default::default(),
+                multiview: node.multiview,
+                cache: node.pipeline_cache.as_deref(),
+            });
+            node.pipelines.insert(mesh.mesh.mesh, pipeline);
+        }
+    }
+
+    fn prepare(
+        &mut self,
+        GpuScene {
+            original, assets, ..
+        }: &mut GpuScene,
+        RenderContext {
+            device,
+            queue,
+            node,
+            material_override,
+            ..
+        }: RenderContext,
+    ) {
+        match assets.material_uniforms.entry(self.mat_uuid) {
+            Entry::Occupied(mut e) => e.get_mut().clear(),
+            Entry::Vacant(e) => {
+                e.insert(DynamicGpuBuffer::new(BufferUsages::UNIFORM));
+            }
+        }
+
+        let mut prepared = Vec::with_capacity(node.meshes.len());
+        for mesh in &mut node.meshes {
+            mesh.offset = None;
+            let Some(material) = mesh_material(original, material_override, &mesh.mesh)
+                .filter(|m| m.id() == self.mat_uuid)
+            else {
+                continue;
+            };
+
+            mesh.offset = Some(material.prepare(device, assets));
+            prepared.push((mesh.mesh.material, material));
+        }
+
+        assets
+            .material_uniforms
+            .get_mut(&self.mat_uuid)
+            .unwrap()
+            .write::<UnlitMaterialUniform>(device, queue);
+
+        for (instance, material) in prepared {
+            let instance = match material_override {
+                Some(_) => MATERIAL_OVERRIDE,
+                None => instance,
+            };
+            material.create_bind_group(device, assets, instance);
+        }
+
+        if let Some(models) = &mut self.models {
+            models.prepare(device, queue, &mut node.meshes);
+        }
+    }
+
+    fn records(&self) -> bool {
+        true
+    }
+
+    fn supports_multiview(&self) -> bool {
+        true
+    }
+
+    fn record(
+        &self,
+        GpuScene { assets, .. }: &GpuScene,
+        RenderContext {
+            device,
+            node,
+            targets,
+            material_override,
+            ..
+        }: RenderContext,
+    ) -> Option<CommandBuffer> {
+        let b_model = self.models.as_ref()?.bind_group.as_ref()?;
+        let mut encoder = device.create_command_encoder(&Default::default());
+
+        {
+            let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("unlit_pass"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: targets.swap_chain.current_view(),
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Load,
+                        store: StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                    view: &assets.texture_views[&DEPTH_PREPASS_TEXTURE.attachment(node.multiview)],
+                    depth_ops: Some(Operations {
+                        load: self.depth_load_op.load_op(),
+                        store: StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                ..Default::default()
+            });
+
+            pass.set_bind_group(0, assets.common_bind_group.as_ref()?, &[]);
+            for mesh in &node.meshes {
+                let material = match material_override {
+                    Some(_) => MATERIAL_OVERRIDE,
+                    None => mesh.mesh.material,
+                };
+                let (Some(offset), Some(b_material), Some(instance), Some(pipeline)) = (
+                    mesh.offset,
+                    assets.material_bind_groups.get(&material),
+                    assets.gpu_meshes.get(&mesh.mesh.mesh),
+                    node.pipelines.get(&mesh.mesh.mesh),
+                ) else {
+                    continue;
+                };
+
+                pass.set_pipeline(pipeline);
+                pass.set_bind_group(1, b_material, &[offset]);
+                pass.set_bind_group(2, b_model, &[mesh.model_offset.unwrap()]);
+                instance.set_vertex_buffers(&mut pass);
+                if let Some(indices) = &instance.index_buffer {
+                    pass.set_index_buffer(indices.buffer.slice(..), indices.format);
+                    pass.draw_indexed(0..indices.count, 0, 0..1);
+                } else {
+                    pass.draw(0..instance.vertices_count, 0..1);
+                }
+            }
+        }
+
+        Some(encoder.finish())
+    }
+}
@@ -0,0 +1,636 @@
+use aurora_core::{
+    render::{
+        flow::{DependencyNodeIndex, RenderContext, RenderNode},
+        resource::DynamicGpuBuffer,
+        scene::GpuScene,
+    },
+    util::create_sampler,
+};
+use encase::ShaderType;
+use glam::{UVec2, UVec3};
+use naga_oil::compose::ShaderDefValue;
+use wgpu::{
+    AddressMode, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, BufferBindingType, BufferUsages,
+    ColorTargetState, ColorWrites, ComputePassDescriptor, ComputePipeline,
+    ComputePipelineDescriptor, Device, Extent3d, FilterMode, FragmentState,
+    PipelineLayoutDescriptor, RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline,
+    RenderPipelineDescriptor, Sampler, SamplerBindingType, SamplerDescriptor, ShaderStages,
+    StorageTextureAccess, Texture, TextureDescriptor, TextureDimension, TextureFormat,
+    TextureSampleType, TextureUsages, TextureViewDimension, VertexState,
+};
+
+use crate::node::{shadow_mapping::SHADOW_MAPPING, LinearDepthNode, LINEAR_DEPTH_TEXTURE};
+
+#[derive(Clone, Copy)]
+pub struct FogConfig {
+    /// Scales both coefficients below.
+    pub density: f32,
+    /// Light scattered per world unit at a density of 1.
+    pub scattering: f32,
+    /// Light absorbed per world unit at a density of 1.
+    pub absorption: f32,
+    /// Depth slices of the froxel grid.
+    pub steps: u32,
+    /// Henyey-Greenstein asymmetry. Positive values scatter forward, making shafts brighter
+    /// when looking towards the light.
+    pub anisotropy: f32,
+    /// View space depth the fog ends at. Slices get thicker the further they are.
+    pub max_distance: f32,
+}
+
+impl Default for FogConfig {
+    fn default() -> Self {
+        Self {
+            density: 1.,
+            scattering: 0.02,
+            absorption: 0.005,
+            steps: 64,
+            anisotropy: 0.6,
+            max_distance: 50.,
+        }
+    }
+}
+
+#[derive(ShaderType)]
+struct VolumetricFogUniform {
+    density: f32,
+    scattering: f32,
+    absorption: f32,
+    steps: u32,
+    anisotropy: f32,
+    max_distance: f32,
+    grid: UVec2,
+    tiles_per_row: u32,
+}
+
+pub struct VolumetricFogNodeData {
+    pub scatter_pipeline: RenderPipeline,
+    pub integrate_pipeline: ComputePipeline,
+    pub composite_pipeline: RenderPipeline,
+    pub scatter_layout: BindGroupLayout,
+    pub integrate_layout: BindGroupLayout,
+    pub composite_layout: BindGroupLayout,
+    pub sampler: Sampler,
+    pub uniform: DynamicGpuBuffer,
+}
+
+/// Scatters the light of directional lights through participating media, so shafts show
+/// wherever the shadow maps let light through.
+///
+/// The view frustum is split into froxels, each sampling the cascaded shadow maps once,
+/// which are then integrated front to back and composited over the lit color. Place it
+/// before tonemapping, and after a [`ShadowMappingNode`](crate::node::ShadowMappingNode), which
+/// is required. Frames without directional lights are left untouched.
+#[derive(Default)]
+pub struct VolumetricFogNode {
+    pub config: FogConfig,
+
+    /// Froxels, each depth slice tiled into a 2d atlas, as shadow maps can only be sampled in
+    /// fragment shaders.
+    pub froxels: Option<Texture>,
+    /// Integrated in-scattering and transmittance.
+    pub volume: Option<Texture>,
+    pub volume_size: UVec3,
+    pub data: Option<VolumetricFogNodeData>,
+}
+
+impl VolumetricFogNode {
+    /// Screen space size of a froxel in pixels.
+    pub const FROXEL_SIZE: u32 = 8;
+    pub const INTEGRATE_WORKGROUP_SIZE: u32 = 8;
+    pub const FROXEL_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+
+    fn froxel_grid(size: UVec2) -> UVec2 {
+        UVec2::new(
+            size.x.div_ceil(Self::FROXEL_SIZE),
+            size.y.div_ceil(Self::FROXEL_SIZE),
+        )
+    }
+
+    fn tiles_per_row(steps: u32) -> u32 {
+        (steps as f32).sqrt().ceil() as u32
+    }
+
+    fn create_textures(&mut self, device: &Device, size: UVec2) {
+        if let Some(froxels) = self.froxels.take() {
+            froxels.destroy();
+        }
+        if let Some(volume) = self.volume.take() {
+            volume.destroy();
+        }
+
+        let steps = self.config.steps.max(1);
+        let grid = Self::froxel_grid(size);
+        let tiles_per_row = Self::tiles_per_row(steps);
+
+        self.froxels = Some(device.create_texture(&TextureDescriptor {
+            label: Some("volumetric_fog_froxels"),
+            size: Extent3d {
+                width: grid.x * tiles_per_row,
+                height: grid.y * steps.div_ceil(tiles_per_row),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: Self::FROXEL_FORMAT,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        }));
+
+        self.volume = Some(device.create_texture(&TextureDescriptor {
+            label: Some("volumetric_fog_volume"),
+            size: Extent3d {
+                width: grid.x,
+                height: grid.y,
+                depth_or_array_layers: steps,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D3,
+            format: Self::FROXEL_FORMAT,
+            usage: TextureUsages::STORAGE_BINDING | TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        }));
+
+        self.volume_size = grid.extend(steps);
+    }
+}
+
+impl RenderNode for VolumetricFogNode {
+    fn add_node_dependencies(&self) -> Vec<(DependencyNodeIndex, Box<dyn RenderNode>)> {
+        vec![(
+            DependencyNodeIndex::Before,
+            Box::new(LinearDepthNode::default()),
+        )]
+    }
+
+    fn require_shaders(&self) -> Option<&'static [(&'static [&'static str], &'static str)]> {
+        Some(&[
+            (&[], include_str!("../shader/fullscreen.wgsl")),
+            (
+                &[
+                    include_str!("../shader/math.wgsl"),
+                    include_str!("../shader/hash.wgsl"),
+                    include_str!("../shader/common/common_type.wgsl"),
+                    include_str!("../shader/common/camera.wgsl"),
+                    include_str!("../shader/common/common_binding.wgsl"),
+                    include_str!("../shader/fullscreen.wgsl"),
+                    include_str!("../shader/shadow/shadow_type.wgsl"),
+                    include_str!("../shader/shadow/shadow_mapping.wgsl"),
+                    include_str!("../shader/post_processing/volumetric_fog.wgsl"),
+                ],
+                include_str!("../shader/post_processing/volumetric_fog_scatter.wgsl"),
+            ),
+            (
+                &[include_str!(
+                    "../shader/post_processing/volumetric_fog.wgsl"
+                )],
+                include_str!("../shader/post_processing/volumetric_fog_integrate.wgsl"),
+            ),
+            (
+                &[
+                    include_str!("../shader/fullscreen.wgsl"),
+                    include_str!("../shader/post_processing/volumetric_fog.wgsl"),
+                ],
+                include_str!("../shader/post_processing/volumetric_fog_composite.wgsl"),
+            ),
+        ])
+    }
+
+    fn require_local_shader_defs(&self) -> Vec<Option<Vec<(String, ShaderDefValue)>>> {
+        vec![
+            None,
+            // Common, lights and the fog itself come first.
+            Some(vec![(
+                "SHADOW_MAPPING".to_string(),
+                ShaderDefValue::UInt(3),
+            )]),
+        ]
+    }
+
+    fn build(
+        &mut self,
+        GpuScene { assets, .. }: &mut GpuScene,
+        RenderContext {
+            device,
+            node,
+            targets,
+            ..
+        }: RenderContext,
+    ) {
+        self.create_textures(device, targets.size);
+
+        let uniform_entry = |binding, visibility| BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: Some(VolumetricFogUniform::min_size()),
+            },
+            count: None,
+        };
+
+        let scatter_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("volumetric_fog_scatter_layout"),
+            entries: &[uniform_entry(0, ShaderStages::FRAGMENT)],
+        });
+
+        let integrate_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("volumetric_fog_integrate_layout"),
+            entries: &[
+                // Froxels
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: false },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                // Volume
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::COMPUTE,
+                    ty: BindingType::StorageTexture {
+                        access: StorageTextureAccess::WriteOnly,
+                        format: Self::FROXEL_FORMAT,
+                        view_dimension: TextureViewDimension::D3,
+                    },
+                    count: None,
+                },
+                // Fog
+                uniform_entry(2, ShaderStages::COMPUTE),
+            ],
+        });
+
+        let composite_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("volumetric_fog_composite_layout"),
+            entries: &[
+                // Color
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: false },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                // Linear Depth
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: false },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                // Volume
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D3,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                // Volume Sampler
+                BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+                // Fog
+                uniform_entry(4, ShaderStages::FRAGMENT),
+            ],
+        });
+
+        let Some(shadow_maps_layout) = assets.required_layout(
+            &SHADOW_MAPPING.shadow_maps_layout,
+            "shadow_maps_layout",
+            "ShadowMappingNode",
+        ) else {
+            self.data = None;
+            return;
+        };
+
+        let scatter_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("volumetric_fog_scatter_pipeline_layout"),
+            bind_group_layouts: &[
+                assets.common_layout.as_ref().unwrap(),
+                assets.lights_layout.as_ref().unwrap(),
+                &scatter_layout,
+                shadow_maps_layout,
+            ],
+            ..Default::default()
+        });
+
+        let scatter_pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("volumetric_fog_scatter_pipeline"),
+            layout: Some(&scatter_pipeline_layout),
+            vertex: VertexState {
+                module: &node.shaders[0],
+                entry_point: "vertex",
+                compilation_options: Default::default(),
+                buffers: &[],
+            },
+            fragment: Some(FragmentState {
+                module: &node.shaders[1],
+                entry_point: "fragment",
+                compilation_options: Default::default(),
+                targets: &[Some(ColorTargetState {
+                    format: Self::FROXEL_FORMAT,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: Default::default(),
+            depth_stencil: Default::default(),
+            multisample: Default::default(),
+            multiview: Default::default(),
+            cache: node.pipeline_cache.as_deref(),
+        });
+
+        let integrate_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("volumetric_fog_integrate_pipeline_layout"),
+            bind_group_layouts: &[&integrate_layout],
+            ..Default::default()
+        });
+
+        let integrate_pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
+            label: Some("volumetric_fog_integrate_pipeline"),
+            layout: Some(&integrate_pipeline_layout),
+            module: &node.shaders[2],
+            entry_point: "main",
+            compilation_options: Default::default(),
+            cache: node.pipeline_cache.as_deref(),
+        });
+
+        let composite_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("volumetric_fog_composite_pipeline_layout"),
+            bind_group_layouts: &[&composite_layout],
+            ..Default::default()
+        });
+
+        let composite_pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("volumetric_fog_composite_pipeline"),
+            layout: Some(&composite_pipeline_layout),
+            vertex: VertexState {
+                module: &node.shaders[0],
+                entry_point: "vertex",
+                compilation_options: Default::default(),
+                buffers: &[],
+            },
+            fragment: Some(FragmentState {
+                module: &node.shaders[3],
+                entry_point: "fragment",
+                compilation_options: Default::default(),
+                targets: &[Some(ColorTargetState {
+                    format: targets.color_format,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: Default::default(),
+            depth_stencil: Default::default(),
+            multisample: Default::default(),
+            multiview: Default::default(),
+            cache: node.pipeline_cache.as_deref(),
+        });
+
+        let sampler = create_sampler(
+            device,
+            &SamplerDescriptor {
+                label: Some("volumetric_fog_sampler"),
+                address_mode_u: AddressMode::ClampToEdge,
+                address_mode_v: AddressMode::ClampToEdge,
+                address_mode_w: AddressMode::ClampToEdge,
+                mag_filter: FilterMode::Linear,
+                min_filter: FilterMode::Linear,
+                ..Default::default()
+            },
+            assets.max_anisotropy,
+        );
+
+        self.data = Some(VolumetricFogNodeData {
+            scatter_pipeline,
+            integrate_pipeline,
+            composite_pipeline,
+            scatter_layout,
+            integrate_layout,
+            composite_layout,
+            sampler,
+            uniform: DynamicGpuBuffer::new(BufferUsages::UNIFORM),
+        });
+    }
+
+    fn prepare(
+        &mut self,
+        _scene: &mut GpuScene,
+        RenderContext {
+            device,
+            queue,
+            targets,
+            ..
+        }: RenderContext,
+    ) {
+        let grid = Self::froxel_grid(targets.size);
+        if self.volume_size != grid.extend(self.config.steps.max(1)) {
+            self.create_textures(device, targets.size);
+        }
+
+        let FogConfig {
+            density,
+            scattering,
+            absorption,
+            steps,
+            anisotropy,
+            max_distance,
+        } = self.config;
+        let Some(VolumetricFogNodeData { uniform, .. }) = &mut self.data else {
+            return;
+        };
+
+        let steps = steps.max(1);
+        uniform.clear();
+        uniform.push(&VolumetricFogUniform {
+            density,
+            scattering,
+            absorption,
+            steps,
+            anisotropy,
+            max_distance,
+            grid,
+            tiles_per_row: Self::tiles_per_row(steps),
+        });
+        uniform.write::<VolumetricFogUniform>(device, queue);
+    }
+
+    fn draw(
+        &self,
+        GpuScene {
+            original, assets, ..
+        }: &mut GpuScene,
+        RenderContext {
+            device,
+            queue,
+            targets,
+            ..
+        }: RenderContext,
+    ) {
+        if original.dir_lights.is_empty() {
+            return;
+        }
+
+        let (
+            Some(VolumetricFogNodeData {
+                scatter_pipeline,
+                integrate_pipeline,
+                composite_pipeline,
+                scatter_layout,
+                integrate_layout,
+                composite_layout,
+                sampler,
+                uniform,
+            }),
+            Some(froxels),
+            Some(volume),
+        ) = (&self.data, &self.froxels, &self.volume)
+        else {
+            return;
+        };
+        let Some(shadow_maps) = assets.required_bind_group(
+            &SHADOW_MAPPING.shadow_maps_bind_group,
+            "shadow_maps_bind_group",
+            "ShadowMappingNode",
+        ) else {
+            return;
+        };
+
+        let froxels_view = froxels.create_view(&Default::default());
+        let volume_view = volume.create_view(&Default::default());
+
+        let scatter_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("volumetric_fog_scatter_bind_group"),
+            layout: scatter_layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: uniform.entire_binding().unwrap(),
+            }],
+        });
+
+        let integrate_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("volumetric_fog_integrate_bind_group"),
+            layout: integrate_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&froxels_view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(&volume_view),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: uniform.entire_binding().unwrap(),
+                },
+            ],
+        });
+
+        let post_process = targets.swap_chain.start_post_process();
+        let composite_bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("volumetric_fog_composite_bind_group"),
+            layout: composite_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(post_process.src),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(
+                        &assets.texture_views[&LINEAR_DEPTH_TEXTURE.view],
+                    ),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::TextureView(&volume_view),
+                },
+                BindGroupEntry {
+                    binding: 3,
+                    resource: BindingResource::Sampler(sampler),
+                },
+                BindGroupEntry {
+                    binding: 4,
+                    resource: uniform.entire_binding().unwrap(),
+                },
+            ],
+        });
+
+        let mut command_encoder = device.create_command_encoder(&Default::default());
+
+        {
+            let mut pass = command_encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("volumetric_fog_scatter_pass"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: &froxels_view,
+                    resolve_target: None,
+                    ops: Default::default(),
+                })],
+                ..Default::default()
+            });
+
+            pass.set_pipeline(scatter_pipeline);
+            pass.set_bind_group(0, assets.common_bind_group.as_ref().unwrap(), &[]);
+            pass.set_bind_group(1, assets.light_bind_group.as_ref().unwrap(), &[]);
+            pass.set_bind_group(2, &scatter_bind_group, &[]);
+            pass.set_bind_group(3, shadow_maps, &[]);
+            pass.draw(0..3, 0..1);
+        }
+
+        {
+            let mut pass = command_encoder.begin_compute_pass(&ComputePassDescriptor {
+                label: Some("volumetric_fog_integrate_pass"),
+                ..Default::default()
+            });
+
+            pass.set_pipeline(integrate_pipeline);
+            pass.set_bind_group(0, &integrate_bind_group, &[]);
+            pass.dispatch_workgroups(
+                self.volume_size.x.div_ceil(Self::INTEGRATE_WORKGROUP_SIZE),
+                self.volume_size.y.div_ceil(Self::INTEGRATE_WORKGROUP_SIZE),
+                1,
+            );
+        }
+
+        {
+            let mut pass = command_encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("volumetric_fog_composite_pass"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: post_process.dst,
+                    resolve_target: None,
+                    ops: Default::default(),
+                })],
+                ..Default::default()
+            });
+
+            pass.set_pipeline(composite_pipeline);
+            pass.set_bind_group(0, &composite_bind_group, &[]);
+            pass.draw(0..3, 0..1);
+        }
+
+        queue.submit([command_encoder.finish()]);
+    }
+}
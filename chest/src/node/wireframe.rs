@@ -0,0 +1,516 @@
+use std::collections::{HashMap, HashSet};
+
+use aurora_core::render::{
+    flow::{DependencyNodeIndex, RenderContext, RenderNode},
+    mesh::MeshVertexBufferLayout,
+    resource::{DynamicGpuBuffer, ModelUniforms},
+    scene::{GpuScene, MeshInstanceId},
+};
+use encase::ShaderType;
+use glam::Vec4;
+use log::warn;
+use wgpu::{
+    BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingType, BlendState, BufferBindingType, BufferUsages,
+    ColorTargetState, ColorWrites, CompareFunction, DepthStencilState, Features, FragmentState,
+    LoadOp, Operations, PipelineLayoutDescriptor, PolygonMode, PrimitiveState,
+    RenderPassColorAttachment, RenderPassDepthStencilAttachment, RenderPassDescriptor,
+    RenderPipeline, RenderPipelineDescriptor, ShaderStages, StoreOp, VertexFormat, VertexState,
+};
+
+use crate::node::{DepthPrepassNode, DEPTH_PREPASS_FORMAT, DEPTH_PREPASS_TEXTURE};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum WireframeMode {
+    /// Every edge, including the ones behind other surfaces.
+    #[default]
+    Overlay,
+    /// Only the visible edges, tested against the depth of [`DepthPrepassNode`], for
+    /// technical visualization.
+    HiddenLine {
+        /// Color the meshes are first drawn with, flat shaded. `None` keeps the image drawn
+        /// by earlier nodes, like `PbrNode`.
+        fill: Option<Vec4>,
+    },
+}
+
+pub struct WireframeConfig {
+    /// Alpha blends the lines over the image.
+    pub color: Vec4,
+    /// Meshes to draw, `None` draws the whole queue.
+    pub only_selected: Option<HashSet<MeshInstanceId>>,
+    pub mode: WireframeMode,
+    /// With [`WireframeMode::HiddenLine`], fraction of the distance to the camera lines are
+    /// pulled closer by, so they aren't hidden by the faces they border.
+    pub depth_offset: f32,
+}
+
+impl Default for WireframeConfig {
+    fn default() -> Self {
+        Self {
+            color: Vec4::new(0., 1., 0., 1.),
+            only_selected: None,
+            mode: Default::default(),
+            depth_offset: 0.001,
+        }
+    }
+}
+
+#[derive(ShaderType)]
+struct WireframeUniform {
+    color: Vec4,
+    fill: Vec4,
+    depth_offset: f32,
+}
+
+pub struct WireframeNodeData {
+    pub layout: BindGroupLayout,
+    pub uniform: DynamicGpuBuffer,
+    /// Only filled with a [`WireframeMode::HiddenLine`] fill.
+    pub fill_pipelines: HashMap<MeshInstanceId, RenderPipeline>,
+    pub models: ModelUniforms,
+}
+
+/// Draws the edges of the meshes over the image, for debugging geometry, or hiding the
+/// edges behind surfaces with [`WireframeMode::HiddenLine`].
+///
+/// Needs [`Features::POLYGON_MODE_LINE`], and does nothing on devices without it.
+#[derive(Default)]
+pub struct WireframeNode {
+    pub config: WireframeConfig,
+
+    pub data: Option<WireframeNodeData>,
+}
+
+impl WireframeNode {
+    #[inline]
+    fn hidden_line(&self) -> bool {
+        matches!(self.config.mode, WireframeMode::HiddenLine { .. })
+    }
+}
+
+impl RenderNode for WireframeNode {
+    fn restrict_mesh_format(&self) -> Option<&'static [VertexFormat]> {
+        Some(&[
+            VertexFormat::Float32x3,
+            VertexFormat::Float32x3,
+            VertexFormat::Float32x2,
+            VertexFormat::Float32x4,
+        ])
+    }
+
+    fn add_node_dependencies(&self) -> Vec<(DependencyNodeIndex, Box<dyn RenderNode>)> {
+        match self.hidden_line() {
+            true => vec![(
+                DependencyNodeIndex::Before,
+                Box::new(DepthPrepassNode::default()),
+            )],
+            false => Vec::new(),
+        }
+    }
+
+    fn require_renderer_features(&self, features: &mut Features) {
+        *features |= Features::POLYGON_MODE_LINE;
+    }
+
+    fn require_shaders(&self) -> Option<&'static [(&'static [&'static str], &'static str)]> {
+        Some(&[(
+            &[
+                include_str!("../shader/common/common_type.wgsl"),
+                include_str!("../shader/common/common_binding.wgsl"),
+            ],
+            include_str!("../shader/wireframe.wgsl"),
+        )])
+    }
+
+    fn build(
+        &mut self,
+        GpuScene { assets, .. }: &mut GpuScene,
+        RenderContext {
+            device,
+            node,
+            targets,
+            ..
+        }: RenderContext,
+    ) {
+        if !device.features().contains(Features::POLYGON_MODE_LINE) {
+            warn!("Wireframe is disabled, as the device doesn't support POLYGON_MODE_LINE.");
+            self.data = None;
+            return;
+        }
+
+        let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("wireframe_layout"),
+            entries: &[
+                // Config
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX_FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: Some(WireframeUniform::min_size()),
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let models = ModelUniforms::new(device);
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("wireframe_pipeline_layout"),
+            bind_group_layouts: &[
+                assets.common_layout.as_ref().unwrap(),
+                &layout,
+                &models.layout,
+            ],
+            push_constant_ranges: &[],
+        });
+
+        let hidden_line = self.hidden_line();
+        let fill = matches!(
+            self.config.mode,
+            WireframeMode::HiddenLine { fill: Some(_) }
+        );
+        let depth_stencil = |depth_write_enabled| {
+            hidden_line.then(|| DepthStencilState {
+                format: DEPTH_PREPASS_FORMAT,
+                depth_write_enabled,
+                depth_compare: CompareFunction::LessEqual,
+                stencil: Default::default(),
+                bias: Default::default(),
+            })
+        };
+
+        // Pipelines depend on the mode, so they're recreated every build.
+        node.pipelines.clear();
+        let mut fill_pipelines = HashMap::new();
+        for mesh in &node.meshes {
+            if node.pipelines.contains_key(&mesh.mesh.mesh) {
+                continue;
+            }
+
+            let instance = &assets.meshes[&mesh.mesh.mesh];
+            let layouts = instance.vertex_buffer_layouts();
+            let buffers = layouts
+                .iter()
+                .map(MeshVertexBufferLayout::layout)
+                .collect::<Vec<_>>();
+            let create_pipeline = |label, entry_points: [&str; 2], polygon_mode, depth_write| {
+                device.create_render_pipeline(&RenderPipelineDescriptor {
+                    label: Some(label),
+                    layout: Some(&pipeline_layout),
+                    vertex: VertexState {
+                        module: &node.shaders[0],
+                        entry_point: entry_points[0],
+                        compilation_options: Default::default(),
+                        buffers: &buffers,
+                    },
+                    fragment: Some(FragmentState {
+                        module: &node.shaders[0],
+                        entry_point: entry_points[1],
+                        compilation_options: Default::default(),
+                        targets: &[Some(ColorTargetState {
+                            format: targets.color_format,
+                            blend: Some(BlendState::ALPHA_BLENDING),
+                            write_mask: ColorWrites::ALL,
+                        })],
+                    }),
+                    primitive: PrimitiveState {
+                        polygon_mode,
+                        ..Default::default()
+                    },
+                    depth_stencil: depth_stencil(depth_write),
+                    multisample: Default::default(),
+                    multiview: Default::default(),
+                    cache: node.pipeline_cache.as_deref(),
+                })
+            };
+
+            node.pipelines.insert(
+                mesh.mesh.mesh,
+                create_pipeline(
+                    "wireframe_pipeline",
+                    ["vertex", "fragment"],
+                    PolygonMode::Line,
+                    false,
+                ),
+            );
+            if fill {
+                // Writes depth, as the prepass skips meshes that aren't opaque.
+                fill_pipelines.insert(
+                    mesh.mesh.mesh,
+                    create_pipeline(
+                        "wireframe_fill_pipeline",
+                        ["fill_vertex", "fill_fragment"],
+                        PolygonMode::Fill,
+                        true,
+                    ),
+                );
+            }
+        }
+
+        self.data = Some(WireframeNodeData {
+            layout,
+            uniform: DynamicGpuBuffer::new(BufferUsages::UNIFORM),
+            fill_pipelines,
+            models,
+        });
+    }
+
+    fn prepare(
+        &mut self,
+        _scene: &mut GpuScene,
+        RenderContext {
+            device,
+            queue,
+            node,
+            ..
+        }: RenderContext,
+    ) {
+        let Some(WireframeNodeData {
+            uniform, models, ..
+        }) = &mut self.data
+        else {
+            return;
+        };
+
+        let (fill, depth_offset) = match self.config.mode {
+            WireframeMode::Overlay => (Vec4::ZERO, 0.),
+            WireframeMode::HiddenLine { fill } => {
+                (fill.unwrap_or_default(), self.config.depth_offset)
+            }
+        };
+        uniform.clear();
+        uniform.push(&WireframeUniform {
+            color: self.config.color,
+            fill,
+            depth_offset,
+        });
+        uniform.write::<WireframeUniform>(device, queue);
+        models.prepare(device, queue, &mut node.meshes);
+    }
+
+    fn draw(
+        &self,
+        GpuScene { assets, .. }: &mut GpuScene,
+        RenderContext {
+            device,
+            queue,
+            node,
+            targets,
+            ..
+        }: RenderContext,
+    ) {
+        let Some(WireframeNodeData {
+            layout,
+            uniform,
+            fill_pipelines,
+            models,
+        }) = &self.data
+        else {
+            return;
+        };
+        let Some(b_model) = &models.bind_group else {
+            return;
+        };
+
+        let bind_group = assets.bind_group_cache.get_or_create(
+            device,
+            &BindGroupDescriptor {
+                label: Some("wireframe_bind_group"),
+                layout,
+                entries: &[BindGroupEntry {
+                    binding: 0,
+                    resource: uniform.entire_binding().unwrap(),
+                }],
+            },
+        );
+
+        let mut command_encoder = device.create_command_encoder(&Default::default());
+
+        {
+            let mut pass = command_encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("wireframe_pass"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: targets.swap_chain.current_view(),
+                    resolve_target: None,
+                    ops: Default::default(),
+                })],
+                depth_stencil_attachment: self.hidden_line().then(|| {
+                    RenderPassDepthStencilAttachment {
+                        view: &assets.texture_views[&DEPTH_PREPASS_TEXTURE.view],
+                        depth_ops: Some(Operations {
+                            load: LoadOp::Load,
+                            store: StoreOp::Store,
+                        }),
+                        stencil_ops: None,
+                    }
+                }),
+                ..Default::default()
+            });
+
+            pass.set_bind_group(0, assets.common_bind_group.as_ref().unwrap(), &[]);
+            pass.set_bind_group(1, &bind_group, &[]);
+            let meshes = node.meshes.iter().filter(|mesh| {
+                self.config
+                    .only_selected
+                    .as_ref()
+                    .is_none_or(|selected| selected.contains(&mesh.mesh.mesh))
+            });
+            // Every fill first, so edges aren't covered by meshes drawn after them.
+            let passes = [(fill_pipelines, meshes.clone()), (&node.pipelines, meshes)];
+            for (pipelines, meshes) in passes {
+                for mesh in meshes {
+                    let Some(pipeline) = pipelines.get(&mesh.mesh.mesh) else {
+                        continue;
+                    };
+                    let instance = &assets.gpu_meshes[&mesh.mesh.mesh];
+
+                    pass.set_pipeline(pipeline);
+                    pass.set_bind_group(2, b_model, &[mesh.model_offset.unwrap()]);
+                    instance.set_vertex_buffers(&mut pass);
+                    if let Some(indices) = &instance.index_buffer {
+                        pass.set_index_buffer(indices.buffer.slice(..), indices.format);
+                        pass.draw_indexed(0..indices.count, 0, 0..1);
+                    } else {
+                        pass.draw(0..instance.vertices_count, 0..1);
+                    }
+                }
+            }
+        }
+
+        queue.submit([command_encoder.finish()]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aurora_core::{
+        render::{
+            flow::{GeneralNode, RenderFlow},
+            helper::{CameraProjection, OrthographicProjection},
+            mesh::{Mesh, StaticMesh, DEFAULT_RENDER_LAYER},
+            scene::{GpuScene, MaterialInstanceId, MeshInstanceId},
+        },
+        util::testing::{self, quad, TestTargets},
+        WgpuRenderer,
+    };
+    use glam::{UVec2, Vec2, Vec4};
+    use image::RgbaImage;
+    use uuid::Uuid;
+    use wgpu::{Features, TextureFormat};
+
+    use super::{WireframeConfig, WireframeMode, WireframeNode};
+
+    const SIZE: u32 = 64;
+
+    /// Renders with an orthographic camera seeing from -1 to 1 on both axes.
+    fn render(renderer: &WgpuRenderer, meshes: Vec<Mesh>, config: WireframeConfig) -> RgbaImage {
+        let mut scene = GpuScene::default();
+        for (i, mesh) in meshes.into_iter().enumerate() {
+            let mesh_id = MeshInstanceId(Uuid::from_u128(i as u128 + 1));
+            scene.assets.meshes.insert(mesh_id, mesh);
+            scene.static_meshes.push(StaticMesh {
+                mesh: mesh_id,
+                material: MaterialInstanceId(Uuid::from_u128(100)),
+                render_layer: DEFAULT_RENDER_LAYER,
+                transform: Default::default(),
+            });
+        }
+        scene.original.camera.projection =
+            CameraProjection::Orthographic(OrthographicProjection::symmetric(2., 2., 0.1, 10.));
+
+        let size = UVec2::splat(SIZE);
+        let test_targets = TestTargets::new(&renderer.device, size, TextureFormat::Rgba8Unorm);
+        let targets = test_targets.targets();
+
+        let mut flow = RenderFlow::default();
+        flow.add::<GeneralNode>().add_initialized(WireframeNode {
+            config,
+            ..Default::default()
+        });
+        flow.set_queue(scene.static_meshes.clone());
+        flow.build(renderer, &mut scene, None, &targets).unwrap();
+        flow.run(renderer, &mut scene, &targets);
+
+        test_targets.read(&renderer)
+    }
+
+    #[test]
+    fn wireframe() {
+        let Some(renderer) = testing::renderer(None, None) else {
+            return;
+        };
+
+        // A quad covering the middle half of the view.
+        let image = render(
+            &renderer,
+            vec![quad(Vec2::splat(-0.5), Vec2::splat(0.5), -1.)],
+            WireframeConfig::default(),
+        );
+        let supported = renderer
+            .device
+            .features()
+            .contains(Features::POLYGON_MODE_LINE);
+        // Lines only, the inside of the quad stays empty.
+        assert_eq!(image.get_pixel(24, 32)[1], 0);
+        // Without line mode the node does nothing.
+        assert_eq!(image.get_pixel(16, 32)[1] == 255, supported);
+    }
+
+    #[test]
+    fn hidden_line() {
+        let Some(renderer) = testing::renderer(None, None) else {
+            return;
+        };
+        if !renderer
+            .device
+            .features()
+            .contains(Features::POLYGON_MODE_LINE)
+        {
+            return;
+        }
+
+        // The front quad hides the left edge of the one behind, at pixel 24, but not its
+        // right edge at pixel 56.
+        let quads = || {
+            vec![
+                quad(Vec2::splat(-0.5), Vec2::splat(0.5), -1.),
+                quad(Vec2::new(-0.25, -0.25), Vec2::new(0.75, 0.25), -2.),
+            ]
+        };
+        let line_near =
+            |image: &RgbaImage, x: u32| (x - 1..=x + 1).any(|x| image.get_pixel(x, 28)[1] > 0);
+
+        let overlay = render(&renderer, quads(), WireframeConfig::default());
+        assert!(line_near(&overlay, 24) && line_near(&overlay, 56));
+
+        let hidden = render(
+            &renderer,
+            quads(),
+            WireframeConfig {
+                mode: WireframeMode::HiddenLine { fill: None },
+                ..Default::default()
+            },
+        );
+        assert!(!line_near(&hidden, 24) && line_near(&hidden, 56));
+        // Edges of the front quad stay visible despite lying on it.
+        assert!(line_near(&hidden, 16));
+
+        let filled = render(
+            &renderer,
+            quads(),
+            WireframeConfig {
+                mode: WireframeMode::HiddenLine {
+                    fill: Some(Vec4::new(1., 0., 0., 1.)),
+                },
+                ..Default::default()
+            },
+        );
+        assert!(!line_near(&filled, 24) && line_near(&filled, 16));
+        let inside = filled.get_pixel(28, 20);
+        assert!(inside[0] > 0 && inside[1] == 0);
+    }
+}
@@ -0,0 +1,164 @@
+use aurora_core::render::{
+    helper::{Aabb, Scene},
+    mesh::{Mesh, StaticMesh, DEFAULT_RENDER_LAYER},
+    resource::GpuPointLight,
+    scene::{GpuScene, MaterialInstanceId, MeshInstanceId},
+};
+use glam::{Mat4, Vec3};
+use uuid::Uuid;
+
+const GRID_ID_BASE: u128 = 0x9d3f_51c2_0000_0000_0000_0000_0000_0000;
+const LIGHT_ID_BASE: u128 = 0x4a7e_08b9_0000_0000_0000_0000_0000_0000;
+
+/// SplitMix64, small enough to keep generated scenes identical across platforms and
+/// dependency updates.
+struct SceneRng(u64);
+
+impl SceneRng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `[0, 1)`.
+    fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    fn next_vec3(&mut self) -> Vec3 {
+        Vec3::new(self.next_f32(), self.next_f32(), self.next_f32())
+    }
+}
+
+/// Add copies of `mesh` on a `rows` by `cols` grid in the XZ plane, `spacing` apart and
+/// centered on the origin, returning the added instances in row major order.
+///
+/// Each copy is a separate mesh, with ids following the meshes already in `scene`, so
+/// the same calls on the same scene always generate the same instances.
+pub fn generate_grid(
+    scene: &mut GpuScene,
+    rows: u32,
+    cols: u32,
+    mesh: &Mesh,
+    material: MaterialInstanceId,
+    spacing: f32,
+) -> Vec<StaticMesh> {
+    let first_id = scene.assets.meshes.len() as u128;
+    let origin = Vec3::new(cols as f32 - 1., 0., rows as f32 - 1.) * spacing * -0.5;
+
+    let mut instances = Vec::with_capacity((rows * cols) as usize);
+    for row in 0..rows {
+        for col in 0..cols {
+            let id = MeshInstanceId(Uuid::from_u128(
+                GRID_ID_BASE + first_id + instances.len() as u128,
+            ));
+            let offset = origin + Vec3::new(col as f32, 0., row as f32) * spacing;
+
+            let mut instance = mesh.clone();
+            instance.transform(Mat4::from_translation(offset));
+            scene.assets.meshes.insert(id, instance);
+
+            let instance = StaticMesh {
+                mesh: id,
+                material,
+                render_layer: DEFAULT_RENDER_LAYER,
+                transform: Default::default(),
+            };
+            scene.static_meshes.push(instance);
+            instances.push(instance);
+        }
+    }
+    instances
+}
+
+/// Add `n` point lights with random positions inside `bounds` and random colors, returning
+/// their ids.
+///
+/// Lights only depend on `seed`, which also derives their ids.
+pub fn generate_random_lights(scene: &mut Scene, n: u32, bounds: Aabb, seed: u64) -> Vec<Uuid> {
+    let mut rng = SceneRng(seed);
+    let id_base = LIGHT_ID_BASE + ((seed as u128) << 32);
+
+    (0..n)
+        .map(|i| {
+            let id = Uuid::from_u128(id_base + i as u128);
+            let light = GpuPointLight {
+                position: bounds.min + rng.next_vec3() * (bounds.max - bounds.min),
+                // Bright enough to see, without any channel close to black.
+                color: Vec3::splat(0.2) + rng.next_vec3() * 0.8,
+                intensity: 100. + rng.next_f32() * 900.,
+                radius: 0.1,
+            };
+            scene.point_lights.insert(id, light);
+            id
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use aurora_core::render::{
+        helper::{Aabb, Scene},
+        mesh::{Mesh, MeshVertexAttributeData},
+        scene::{GpuScene, MaterialInstanceId},
+    };
+    use glam::Vec3;
+
+    use super::{generate_grid, generate_random_lights};
+
+    #[test]
+    fn deterministic_scene() {
+        let triangle = Mesh::new().with_attribute(
+            Mesh::POSITION_ATTR,
+            MeshVertexAttributeData::Float32x3(vec![Vec3::ZERO, Vec3::X, Vec3::Y]),
+        );
+        let grid = |scene: &mut GpuScene| {
+            generate_grid(scene, 2, 3, &triangle, MaterialInstanceId::default(), 2.)
+        };
+
+        let mut scene = GpuScene::default();
+        let first = grid(&mut scene);
+        assert_eq!(first.len(), 6);
+        assert_eq!(scene.static_meshes.len(), 6);
+        // Centered on the origin, row major.
+        let corner = scene.assets.meshes[&first[0].mesh].aabb().unwrap();
+        assert_eq!(corner.min, Vec3::new(-2., 0., -1.));
+        let next = scene.assets.meshes[&first[1].mesh].aabb().unwrap();
+        assert_eq!(next.min, Vec3::new(0., 0., -1.));
+
+        // A second grid gets new ids, a fresh scene the same ones.
+        let second = grid(&mut scene);
+        assert_eq!(scene.assets.meshes.len(), 12);
+        assert!(second
+            .iter()
+            .all(|m| !first.iter().any(|f| f.mesh == m.mesh)));
+        let again = grid(&mut GpuScene::default());
+        assert!(again.iter().zip(&first).all(|(a, f)| a.mesh == f.mesh));
+
+        let bounds = Aabb {
+            min: Vec3::splat(-10.),
+            max: Vec3::new(10., 5., 10.),
+        };
+        let mut lights = Scene::default();
+        let ids = generate_random_lights(&mut lights, 64, bounds, 7);
+        assert_eq!(lights.point_lights.len(), 64);
+        assert!(lights
+            .point_lights
+            .values()
+            .all(|l| l.position.cmpge(bounds.min).all() && l.position.cmplt(bounds.max).all()));
+
+        let mut same = Scene::default();
+        assert_eq!(generate_random_lights(&mut same, 64, bounds, 7), ids);
+        assert_eq!(same.point_lights, lights.point_lights);
+
+        let mut other = Scene::default();
+        let other_ids = generate_random_lights(&mut other, 64, bounds, 8);
+        assert_ne!(
+            other.point_lights[&other_ids[0]].position,
+            lights.point_lights[&ids[0]].position
+        );
+    }
+}
@@ -0,0 +1,31 @@
+#define_import_path aurora::camera
+#import aurora::common_type::Camera
+
+// Depth reconstruction shared by the effects reading the depth buffer. The camera is passed
+// in, as not every pass binds it through `common_binding`.
+
+fn uv_to_ndc(uv: vec2f) -> vec2f {
+    return vec2f(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+}
+
+// Distance along the view direction, positive in front of the camera.
+fn linearize_depth(camera: Camera, depth: f32) -> f32 {
+    let t = camera.inv_proj * vec4f(0.0, 0.0, depth, 1.0);
+    return -t.z / t.w;
+}
+
+fn view_position(camera: Camera, uv: vec2f, depth: f32) -> vec3f {
+    let t = camera.inv_proj * vec4f(uv_to_ndc(uv), depth, 1.0);
+    return t.xyz / t.w;
+}
+
+fn reconstruct_world_pos(camera: Camera, uv: vec2f, depth: f32) -> vec3f {
+    return (camera.inv_view * vec4f(view_position(camera, uv, depth), 1.0)).xyz;
+}
+
+// View space point through `uv` at a linear depth of 1, so scaling it by a linear depth
+// gives the position there. Only meaningful for perspective projections.
+fn view_ray(camera: Camera, uv: vec2f) -> vec3f {
+    let ray = view_position(camera, uv, 0.5);
+    return ray / -ray.z;
+}
@@ -3,3 +3,8 @@
 
 @group(0) @binding(0) var<uniform> camera: Camera;
 @group(0) @binding(1) var<uniform> scene: Scene;
+
+#ifdef MULTIVIEW
+// Same buffer as `camera`, which therefore is the left eye.
+@group(0) @binding(2) var<uniform> eyes: array<Camera, 2>;
+#endif // MULTIVIEW
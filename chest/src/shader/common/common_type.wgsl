@@ -13,6 +13,8 @@ struct Scene {
     dir_lights: u32,
     point_lights: u32,
     spot_lights: u32,
+    // Seconds since the first frame.
+    time: f32,
 }
 
 struct DirectionalLight {
@@ -44,4 +46,25 @@ struct VertexInput {
     @location(1) normal: vec3f,
     @location(2) uv: vec2f,
     @location(3) tangent: vec4f,
+#ifdef VERTEX_COLORS
+    @location(4) color: vec4f,
+#endif // VERTEX_COLORS
+#ifdef TEX_COORDS_1
+    @location(5) second_uv: vec2f,
+#endif // TEX_COORDS_1
+#ifdef VAT
+    @builtin(vertex_index) index: u32,
+#endif // VAT
+}
+
+// Placement of a static mesh, see `GpuModel`.
+struct Model {
+    model: mat4x4f,
+    // Inverse transpose of `model`.
+    normal: mat3x3f,
+}
+
+// Vertices of depth only passes, see `Mesh::position_vertex_layout`.
+struct PositionInput {
+    @location(0) position: vec3f,
 }
@@ -0,0 +1,40 @@
+#import aurora::{fullscreen::FullscreenVertexOutput, math}
+
+@group(1) @binding(1) var color: texture_2d<f32>;
+
+// Motion of a tenth of the screen is shown at full brightness.
+const MOTION_VECTOR_SCALE: f32 = 10.;
+
+// The inspected textures don't always match the screen.
+fn texel(uv: vec2f, size: vec2u) -> vec2i {
+    return vec2i(min(vec2u(uv * vec2f(size)), size - 1u));
+}
+
+fn hue_to_rgb(hue: f32) -> vec3f {
+    return saturate(abs(fract(hue + vec3f(0., 2., 1.) / 3.) * 6. - 3.) - 1.);
+}
+
+@fragment
+fn normal_fragment(in: FullscreenVertexOutput) -> @location(0) vec4f {
+    // Stored as `n * 0.5 + 0.5` already.
+    let n = textureLoad(color, texel(in.uv, textureDimensions(color)), 0);
+    return vec4f(n.rgb, 1.);
+}
+
+@fragment
+fn motion_vector_fragment(in: FullscreenVertexOutput) -> @location(0) vec4f {
+    let v = textureLoad(color, texel(in.uv, textureDimensions(color)), 0).xy;
+    if all(v == vec2f(0.)) {
+        return vec4f(0., 0., 0., 1.);
+    }
+
+    // Hue for the direction, brightness for the length.
+    let hue = atan2(v.y, v.x) / (2. * math::PI) + 0.5;
+    return vec4f(hue_to_rgb(hue) * saturate(length(v) * MOTION_VECTOR_SCALE), 1.);
+}
+
+@fragment
+fn ssao_fragment(in: FullscreenVertexOutput) -> @location(0) vec4f {
+    let ao = textureLoad(color, texel(in.uv, textureDimensions(color)), 0).r;
+    return vec4f(vec3f(ao), 1.);
+}
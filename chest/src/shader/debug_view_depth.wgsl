@@ -0,0 +1,29 @@
+#import aurora::{
+    camera::linearize_depth,
+    common_binding::camera,
+    fullscreen::FullscreenVertexOutput,
+}
+
+// Kept apart from the other buffers, as some backends can't load from depth textures.
+@group(1) @binding(0) var depth: texture_depth_2d;
+
+// The inspected textures don't always match the screen, e.g. shadow maps.
+fn texel(uv: vec2f, size: vec2u) -> vec2i {
+    return vec2i(min(vec2u(uv * vec2f(size)), size - 1u));
+}
+
+@fragment
+fn depth_fragment(in: FullscreenVertexOutput) -> @location(0) vec4f {
+    let clip_z = textureLoad(depth, texel(in.uv, textureDimensions(depth)), 0);
+    let near = linearize_depth(camera, 0.);
+    let far = linearize_depth(camera, 1.);
+    let d = (linearize_depth(camera, clip_z) - near) / (far - near);
+    return vec4f(vec3f(saturate(d)), 1.);
+}
+
+@fragment
+fn shadow_fragment(in: FullscreenVertexOutput) -> @location(0) vec4f {
+    // Orthographic, so already linear.
+    let d = textureLoad(depth, texel(in.uv, textureDimensions(depth)), 0);
+    return vec4f(vec3f(d), 1.);
+}
@@ -0,0 +1,53 @@
+#import aurora::fullscreen::FullscreenVertexOutput
+
+@group(0) @binding(0) var input: texture_2d<f32>;
+// Full res, view space depth.
+@group(0) @binding(1) var depth: texture_2d<f32>;
+
+// How fast a tap is rejected as its depth differs, relative to the depth of the pixel.
+const DEPTH_SHARPNESS: f32 = 32.;
+
+// Low res texels are assumed to be rendered with the full res depth at their center.
+fn low_res_depth(texel: vec2u, scale: vec2f, full_size: vec2u) -> f32 {
+    let pixel = min(vec2u((vec2f(texel) + 0.5) * scale), full_size - 1u);
+    return textureLoad(depth, pixel, 0).r;
+}
+
+@fragment
+fn fragment(in: FullscreenVertexOutput) -> @location(0) vec4f {
+    let full_size = textureDimensions(depth);
+    let low_size = textureDimensions(input);
+    let scale = vec2f(full_size) / vec2f(low_size);
+    let center = textureLoad(depth, vec2u(in.position.xy), 0).r;
+
+    let position = in.position.xy / scale - 0.5;
+    let base = floor(position);
+    let t = position - base;
+
+    var color = vec4f(0.);
+    var total = 0.;
+    var nearest = vec4f(0.);
+    var nearest_diff = 3.4e38;
+    for (var i = 0u; i < 4u; i += 1u) {
+        let offset = vec2f(f32(i & 1u), f32(i >> 1u));
+        let texel = vec2u(clamp(base + offset, vec2f(0.), vec2f(low_size - 1u)));
+        let bilinear = mix(1. - t.x, t.x, offset.x) * mix(1. - t.y, t.y, offset.y);
+
+        let tap = textureLoad(input, texel, 0);
+        let diff = abs(low_res_depth(texel, scale, full_size) - center) / max(center, 1e-4);
+        let weight = bilinear * exp(-diff * DEPTH_SHARPNESS);
+        color += tap * weight;
+        total += weight;
+
+        if diff < nearest_diff {
+            nearest_diff = diff;
+            nearest = tap;
+        }
+    }
+
+    // Every tap lies across an edge, take the one closest in depth rather than blurring them.
+    if total < 1e-4 {
+        return nearest;
+    }
+    return color / total;
+}
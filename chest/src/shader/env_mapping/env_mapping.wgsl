@@ -1,10 +1,26 @@
 #define_import_path aurora::env_mapping::env_mapping
-#import aurora::env_mapping::env_mapping_binding::{env_map, irr_map, env_map_sampler, env_mapping}
+#import aurora::env_mapping::env_mapping_binding::{env_map, irr_map, env_map_sampler, env_mapping, brdf_lut}
+
+#ifdef ENVIRONMENT_MAPPING
 
 fn sample_env_map(dir: vec3f) -> vec3f {
-    return textureSample(env_map, env_map_sampler, dir).rgb * env_mapping.intensity;
+    return textureSampleLevel(env_map, env_map_sampler, dir, 0.).rgb * env_mapping.intensity;
 }
 
 fn sample_irr_map(dir: vec3f) -> vec3f {
     return textureSample(irr_map, env_map_sampler, dir).rgb * env_mapping.intensity;
 }
+
+// Radiance prefiltered for `perceptual_roughness`.
+fn sample_specular(dir: vec3f, perceptual_roughness: f32) -> vec3f {
+    let mip = perceptual_roughness * env_mapping.max_mip;
+    return textureSampleLevel(env_map, env_map_sampler, dir, mip).rgb * env_mapping.intensity;
+}
+
+// Split sum specular IBL, see `brdf_lut_fragment` for the LUT.
+fn specular_ibl(dir: vec3f, f_normal: vec3f, NdotV: f32, perceptual_roughness: f32) -> vec3f {
+    let brdf = textureSampleLevel(brdf_lut, env_map_sampler, vec2f(NdotV, perceptual_roughness), 0.).rg;
+    return sample_specular(dir, perceptual_roughness) * (f_normal * brdf.x + brdf.y);
+}
+
+#endif // ENVIRONMENT_MAPPING
@@ -1,7 +1,12 @@
 #define_import_path aurora::env_mapping::env_mapping_binding
 #import aurora::env_mapping::env_mapping_type::EnvironmentMapping
 
+#ifdef ENVIRONMENT_MAPPING
+
 @group(#ENVIRONMENT_MAPPING) @binding(0) var env_map: texture_cube<f32>;
 @group(#ENVIRONMENT_MAPPING) @binding(1) var irr_map: texture_cube<f32>;
 @group(#ENVIRONMENT_MAPPING) @binding(2) var env_map_sampler: sampler;
 @group(#ENVIRONMENT_MAPPING) @binding(3) var<uniform> env_mapping: EnvironmentMapping;
+@group(#ENVIRONMENT_MAPPING) @binding(4) var brdf_lut: texture_2d<f32>;
+
+#endif // ENVIRONMENT_MAPPING
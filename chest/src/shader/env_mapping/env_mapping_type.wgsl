@@ -2,4 +2,6 @@
 
 struct EnvironmentMapping {
     intensity: f32,
+    // Mip of the specular map prefiltered for full roughness.
+    max_mip: f32,
 }
@@ -0,0 +1,105 @@
+#import aurora::{
+    fullscreen::FullscreenVertexOutput,
+    math::PI,
+}
+
+struct SpecularPrefilter {
+    // GGX alpha, the squared perceptual roughness.
+    roughness: f32,
+    sample_count: u32,
+}
+
+struct CubeMapFace {
+    view: mat4x4f,
+    up: vec3f,
+}
+
+@group(0) @binding(0) var env_map: texture_cube<f32>;
+@group(0) @binding(1) var env_sampler: sampler;
+@group(0) @binding(2) var<uniform> config: SpecularPrefilter;
+@group(0) @binding(3) var<uniform> sample_face: CubeMapFace;
+
+// Van der Corput sequence, `reverseBits` isn't available on every backend.
+fn radical_inverse(index: u32) -> f32 {
+    var bits = (index << 16u) | (index >> 16u);
+    bits = ((bits & 0x55555555u) << 1u) | ((bits & 0xAAAAAAAAu) >> 1u);
+    bits = ((bits & 0x33333333u) << 2u) | ((bits & 0xCCCCCCCCu) >> 2u);
+    bits = ((bits & 0x0F0F0F0Fu) << 4u) | ((bits & 0xF0F0F0F0u) >> 4u);
+    bits = ((bits & 0x00FF00FFu) << 8u) | ((bits & 0xFF00FF00u) >> 8u);
+    return f32(bits) * 2.3283064365386963e-10;
+}
+
+fn hammersley(index: u32, count: u32) -> vec2f {
+    return vec2f(f32(index) / f32(count), radical_inverse(index));
+}
+
+// Half vector around `normal` distributed like the GGX NDF.
+fn importance_sample_ggx(xi: vec2f, normal: vec3f, roughness: f32) -> vec3f {
+    let r2 = roughness * roughness;
+    let phi = 2. * PI * xi.x;
+    let cos_theta = sqrt((1. - xi.y) / (1. + (r2 - 1.) * xi.y));
+    let sin_theta = sqrt(1. - cos_theta * cos_theta);
+    let half_ts = vec3f(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+
+    let up = select(vec3f(1., 0., 0.), vec3f(0., 0., 1.), abs(normal.z) < 0.999);
+    let tangent = normalize(cross(up, normal));
+    let bitangent = cross(normal, tangent);
+    return tangent * half_ts.x + bitangent * half_ts.y + normal * half_ts.z;
+}
+
+// Same as `G2_HeightCorrelated` in the pbr functions.
+fn visibility(roughness: f32, NdotL: f32, NdotV: f32) -> f32 {
+    let r2 = roughness * roughness;
+    let l = NdotV * sqrt(r2 + NdotL * (NdotL - r2 * NdotL));
+    let v = NdotL * sqrt(r2 + NdotV * (NdotV - r2 * NdotV));
+    return 0.5 / max(l + v, 0.001);
+}
+
+@fragment
+fn prefilter_fragment(in: FullscreenVertexOutput) -> @location(0) vec4f {
+    let texel = (in.uv * 2.0 - 1.0) * vec2f(1.0, -1.0);
+    // Assume the view direction is the normal, like split sum approximations do.
+    let normal = normalize((sample_face.view * vec4f(-texel, 1.0, 0.0)).xyz);
+
+    var color = vec3f(0.);
+    var weight = 0.;
+    for (var i = 0u; i < config.sample_count; i += 1u) {
+        let half_vector = importance_sample_ggx(hammersley(i, config.sample_count), normal, config.roughness);
+        let light = normalize(2. * dot(normal, half_vector) * half_vector - normal);
+        let NdotL = dot(normal, light);
+        if NdotL > 0. {
+            color += textureSampleLevel(env_map, env_sampler, light, 0.).rgb * NdotL;
+            weight += NdotL;
+        }
+    }
+
+    return vec4f(color / max(weight, 0.0001), 1.);
+}
+
+// Scale and bias applied to F0 by the specular BRDF, indexed by NdotV and perceptual roughness.
+@fragment
+fn brdf_lut_fragment(in: FullscreenVertexOutput) -> @location(0) vec4f {
+    let NdotV = max(in.uv.x, 0.0001);
+    let roughness = in.uv.y * in.uv.y;
+    let view = vec3f(sqrt(1. - NdotV * NdotV), 0., NdotV);
+    let normal = vec3f(0., 0., 1.);
+
+    var scale = 0.;
+    var bias = 0.;
+    for (var i = 0u; i < config.sample_count; i += 1u) {
+        let half_vector = importance_sample_ggx(hammersley(i, config.sample_count), normal, roughness);
+        let light = normalize(2. * dot(view, half_vector) * half_vector - view);
+        let NdotL = saturate(light.z);
+        let NdotH = saturate(half_vector.z);
+        let VdotH = saturate(dot(view, half_vector));
+        if NdotL > 0. {
+            // Divided by the pdf of the half vector, D * NdotH / (4 * VdotH).
+            let g = visibility(roughness, NdotL, NdotV) * 4. * NdotL * VdotH / max(NdotH, 0.0001);
+            let fresnel = pow(1. - VdotH, 5.);
+            scale += (1. - fresnel) * g;
+            bias += fresnel * g;
+        }
+    }
+
+    return vec4f(scale, bias, 0., 1.) / vec4f(vec2f(f32(config.sample_count)), 1., 1.);
+}
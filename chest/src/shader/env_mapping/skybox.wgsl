@@ -6,6 +6,17 @@
 @group(0) @binding(0) var skybox: texture_cube<f32>;
 @group(0) @binding(1) var skybox_sampler: sampler;
 @group(0) @binding(2) var<uniform> camera: Camera;
+@group(0) @binding(3) var<uniform> background_exposure: f32;
+
+// The fullscreen triangle pushed onto the far plane, so it only covers the background.
+@vertex
+fn vertex(@builtin(vertex_index) vertex_index: u32) -> FullscreenVertexOutput {
+    var output: FullscreenVertexOutput;
+    let t = vec2f(f32(vertex_index / 2u), f32(vertex_index % 2u));
+    output.position = vec4f(vec2f(t * 4. - 1.), 1., 1.);
+    output.uv = vec2f(t.x * 2., 1. - t.y * 2.);
+    return output;
+}
 
 @fragment
 fn fragment(in: FullscreenVertexOutput) -> @location(0) vec4f {
@@ -13,5 +24,7 @@ fn fragment(in: FullscreenVertexOutput) -> @location(0) vec4f {
     let position_view = camera.inv_proj * vec4f(position_ndc, 1.0, 1.0);
     let position_world_no_translation = camera.inv_view * vec4f(position_view.xyz / position_view.w, 0.0);
 
-    return textureSample(skybox, skybox_sampler, position_world_no_translation.xyz);
+    // Further mips are blurred for rough reflections.
+    let color = textureSampleLevel(skybox, skybox_sampler, position_world_no_translation.xyz, 0.);
+    return vec4f(color.rgb * background_exposure, color.a);
 }
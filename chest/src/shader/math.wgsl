@@ -71,15 +71,6 @@ fn interleaved_gradient_noise(pixel_coordinates: vec2<f32>, frame: u32) -> f32 {
     return fract(52.9829189 * fract(0.06711056 * xy.x + 0.00583715 * xy.y));
 }
 
-fn clip_depth_to_view(depth: f32, inv_proj: mat4x4f) -> f32 {
-    let t = inv_proj * vec4f(0.0, 0.0, depth, 1.0);
-    return -t.z / t.w;
-}
-
-fn linear_to_srgb(color: vec3f) -> vec3f {
-    return pow(color, vec3f(1.0 / 2.2));
-}
-
 fn luminance(c: vec3f) -> f32 {
     return c.r * 0.2126 + c.g * 0.7152 + c.b * 0.0722;
 }
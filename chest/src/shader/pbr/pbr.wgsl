@@ -1,12 +1,13 @@
 #define_import_path aurora::pbr::pbr
 #import aurora::{
+    common_binding,
     common_binding::{camera, scene},
-    common_type::VertexInput,
+    common_type::{Camera, VertexInput},
     env_mapping::env_mapping,
     math,
     math::PI,
     pbr::{
-        pbr_binding::{dir_lights, material, point_lights, spot_lights, tex_base_color, tex_sampler},
+        pbr_binding::{dir_lights, material, mesh_model, point_lights, spot_lights, tex_base_color, tex_emissive, tex_sampler, tex_vertex_animation},
         pbr_function,
         pbr_type::PbrVertexOutput,
     }
@@ -14,27 +15,157 @@
     shadow_mapping,
 }
 
+#ifdef VAT
+// See `VertexAnimationTexture` for the layout.
+fn load_vertex_animation(index: u32, frame: u32, section: u32) -> vec3f {
+    let size = textureDimensions(tex_vertex_animation);
+    let sections = select(1u, 2u, material.vat_normals != 0u);
+    let rows = max(size.y / (material.vat_frames * sections), 1u);
+    let row = (section * material.vat_frames + frame) * rows + index / size.x;
+    return textureLoad(tex_vertex_animation, vec2u(index % size.x, row), 0).rgb;
+}
+#endif // VAT
+
+#ifdef WIND
+fn wind_offset(position: vec3f, stiffness: f32) -> vec3f {
+    // Gusts travel along the wind, with a faster flutter varying across the mesh on top.
+    let phase = scene.time * material.wind_frequency * 2. * PI - dot(position, material.wind_direction);
+    let gust = 0.5 + 0.5 * sin(phase);
+    let flutter = 0.2 * sin(phase * 2.7 + position.x + position.z);
+    return material.wind_direction * material.wind_strength * (gust + flutter) * (1. - saturate(stiffness));
+}
+#endif // WIND
+
+// Model matrix of each instance, see `InstancedMesh`.
+struct InstanceInput {
+    @location(8) model_0: vec4f,
+    @location(9) model_1: vec4f,
+    @location(10) model_2: vec4f,
+    @location(11) model_3: vec4f,
+}
+
+@vertex
+fn vertex(
+    in: VertexInput,
+#ifdef MULTIVIEW
+    @builtin(view_index) view_index: i32,
+#endif // MULTIVIEW
+) -> PbrVertexOutput {
+#ifdef MULTIVIEW
+    return transform_vertex(in, mesh_model.model, mesh_model.normal, common_binding::eyes[view_index]);
+#else // MULTIVIEW
+    return transform_vertex(in, mesh_model.model, mesh_model.normal, camera);
+#endif // MULTIVIEW
+}
+
 @vertex
-fn vertex(in: VertexInput) -> PbrVertexOutput {
+fn vertex_instanced(
+    in: VertexInput,
+    instance: InstanceInput,
+#ifdef MULTIVIEW
+    @builtin(view_index) view_index: i32,
+#endif // MULTIVIEW
+) -> PbrVertexOutput {
+    let model = mat4x4f(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
+    // Instances only translate, rotate and scale, so the inverse transpose is the model
+    // matrix with the scale divided twice.
+    let linear = mat3x3f(model[0].xyz, model[1].xyz, model[2].xyz);
+    let normal = mat3x3f(linear[0] / dot(linear[0], linear[0]), linear[1] / dot(linear[1], linear[1]), linear[2] / dot(linear[2], linear[2]));
+#ifdef MULTIVIEW
+    return transform_vertex(in, model, normal, common_binding::eyes[view_index]);
+#else // MULTIVIEW
+    return transform_vertex(in, model, normal, camera);
+#endif // MULTIVIEW
+}
+
+fn transform_vertex(in: VertexInput, model: mat4x4f, normal_matrix: mat3x3f, view: Camera) -> PbrVertexOutput {
+    var position = in.position;
+    var normal = in.normal;
+#ifdef VAT
+    let time = max(scene.time + material.vat_time_offset, 0.) * material.vat_fps;
+    let frame = u32(time) % material.vat_frames;
+    let next = (frame + 1u) % material.vat_frames;
+    position += mix(load_vertex_animation(in.index, frame, 0u), load_vertex_animation(in.index, next, 0u), fract(time));
+    if material.vat_normals != 0u {
+        normal = normalize(mix(load_vertex_animation(in.index, frame, 1u), load_vertex_animation(in.index, next, 1u), fract(time)));
+    }
+#endif // VAT
+    let linear = mat3x3f(model[0].xyz, model[1].xyz, model[2].xyz);
+    position = (model * vec4f(position, 1.)).xyz;
+    normal = normalize(normal_matrix * normal);
+    let tangent = vec4f(linear * in.tangent.xyz, in.tangent.w * sign(determinant(linear)));
+#ifdef WIND
+#ifdef VERTEX_COLORS
+    position += wind_offset(position, in.color.r);
+#else // VERTEX_COLORS
+    position += wind_offset(position, 0.);
+#endif // VERTEX_COLORS
+#endif // WIND
+
     var output: PbrVertexOutput;
-    output.position_ws = in.position;
-    output.position_vs = camera.view * vec4f(in.position, 1.);
-    output.position_cs = camera.proj * output.position_vs;
-    output.normal = in.normal;
+    output.position_ws = position;
+    output.position_vs = view.view * vec4f(position, 1.);
+    output.position_cs = view.proj * output.position_vs;
+    output.normal = normal;
     output.uv = in.uv.xy;
-    output.tangent = in.tangent;
+#ifdef TEX_COORDS_1
+    output.second_uv = in.second_uv;
+#else // TEX_COORDS_1
+    // Meshes with a single uv set share it with textures using the second one.
+    output.second_uv = in.uv.xy;
+#endif // TEX_COORDS_1
+    output.tangent = tangent;
+#ifdef VERTEX_COLORS
+    output.color = in.color;
+#endif // VERTEX_COLORS
     return output;
 }
 
 @fragment
-fn fragment(in: PbrVertexOutput) -> @location(0) vec4f {
+fn fragment(
+    in: PbrVertexOutput,
+    @builtin(front_facing) front_facing: bool,
+#ifdef MULTIVIEW
+    @builtin(view_index) view_index: i32,
+#endif // MULTIVIEW
+) -> @location(0) vec4f {
+#ifdef MULTIVIEW
+    let view = common_binding::eyes[view_index];
+#else // MULTIVIEW
+    let view = camera;
+#endif // MULTIVIEW
 #ifdef TEX_NORMAL
-    let normal = pbr_function::unpack_normal(in.normal, in.tangent, in.uv);
+    var normal = pbr_function::unpack_normal(in.normal, in.tangent, in.uv);
 #else
-    let normal = in.normal;
+    var normal = in.normal;
 #endif
+    // Back faces are only drawn for double sided materials, shade them as if seen from the front.
+    normal = select(-normal, normal, front_facing);
+    // Shadow lookups are offset along the surface, ignoring normal maps.
+    let geometric_normal = normalize(select(-in.normal, in.normal, front_facing));
 
-    var unlit = pbr_function::construct_surface_unlit(in.position_ws, normal, in.uv, material);
+    var alpha = material.alpha * textureSample(tex_base_color, tex_sampler, in.uv).a;
+#ifdef VERTEX_COLORS
+    alpha *= in.color.a;
+#endif // VERTEX_COLORS
+#ifdef ALPHA_MASK
+#ifdef ALPHA_TO_COVERAGE
+    // Sharpened to about a pixel around the cutoff, the samples covered follow alpha.
+    alpha = saturate((alpha - material.alpha_cutoff) / max(fwidth(alpha), 0.0001) + 0.5);
+#else // ALPHA_TO_COVERAGE
+    if alpha < material.alpha_cutoff {
+        discard;
+    }
+#endif // ALPHA_TO_COVERAGE
+#endif // ALPHA_MASK
+
+#ifdef VERTEX_COLORS
+    var surface_material = material;
+    surface_material.base_color *= in.color.rgb;
+    var unlit = pbr_function::construct_surface_unlit(in.position_ws, normal, in.uv, surface_material, view.position);
+#else // VERTEX_COLORS
+    var unlit = pbr_function::construct_surface_unlit(in.position_ws, normal, in.uv, material, view.position);
+#endif // VERTEX_COLORS
 
     var color = vec3f(0.);
 
@@ -43,15 +174,22 @@ fn fragment(in: PbrVertexOutput) -> @location(0) vec4f {
         
         let irradiated = pbr_function::apply_lighting((*light).direction, (*light).intensity, (*light).color, unlit);
 #ifdef SHADOW_MAPPING
-        let shadow = shadow_mapping::sample_cascaded_shadow_map(i_light, in.position_ws, in.position_vs, (*light).radius * 2.);
+#ifdef TRANSLUCENT_SHADOWS
+        let shadow = shadow_mapping::sample_cascaded_shadow_map(i_light, in.position_ws, geometric_normal, in.position_vs, (*light).radius * 2.)
+            * shadow_mapping::sample_cascaded_transmittance(i_light, in.position_ws, in.position_vs);
+#else // TRANSLUCENT_SHADOWS
+        let shadow = shadow_mapping::sample_cascaded_shadow_map(i_light, in.position_ws, geometric_normal, in.position_vs, (*light).radius * 2.);
+#endif // TRANSLUCENT_SHADOWS
 #else // SHADOW_MAPPING
         let shadow = 1.;
 #endif // SHADOW_MAPPING
 
         color += irradiated * shadow;
+#ifdef SHADOW_MAPPING
 #ifdef SHOW_CASCADES
         color += shadow_mapping::debug_cascade_color(i_light, in.position_vs) * (*light).intensity;
 #endif // SHOW_CASCADES
+#endif // SHADOW_MAPPING
     }
 
     for (var i_light = 0u; i_light < scene.point_lights; i_light += 1u) {
@@ -64,7 +202,7 @@ fn fragment(in: PbrVertexOutput) -> @location(0) vec4f {
 
         let irradiated = pbr_function::apply_lighting(direction, intensity, (*light).color, unlit);
 #ifdef SHADOW_MAPPING
-        let shadow = shadow_mapping::sample_point_shadow_map(i_light, position_rel, (*light).radius);
+        let shadow = shadow_mapping::sample_point_shadow_map(i_light, position_rel, geometric_normal, (*light).radius);
 #else // SHADOW_MAPPING
         let shadow = 1.;
 #endif // SHADOW_MAPPING
@@ -87,7 +225,8 @@ fn fragment(in: PbrVertexOutput) -> @location(0) vec4f {
 
         let irradiated = pbr_function::apply_lighting(direction, intensity, (*light).color, unlit);
 #ifdef SHADOW_MAPPING
-        let shadow = shadow_mapping::sample_point_shadow_map(i_light, position_rel, (*light).radius);
+        // Spot lights are rendered like point lights, and indexed after every point light.
+        let shadow = shadow_mapping::sample_point_shadow_map(scene.point_lights + i_light, position_rel, geometric_normal, (*light).radius);
 #else // SHADOW_MAPPING
         let shadow = 1.;
 #endif // SHADOW_MAPPING
@@ -95,23 +234,37 @@ fn fragment(in: PbrVertexOutput) -> @location(0) vec4f {
         color += irradiated * shadow;
     }
 
+    // Not tinted by the diffuse color like the rest, metals reflect through `f_normal` only.
+    var specular_ibl = vec3f(0.);
 #ifdef ENVIRONMENT_MAPPING
-    color += env_mapping::sample_irr_map(reflect(-unlit.view, unlit.normal)) * unlit.base_color;
+    // Stacks with SSAO below, which darkens the whole diffuse term.
+    let occlusion = pbr_function::sample_occlusion(in.uv, in.second_uv, material);
+    let reflected = reflect(-unlit.view, unlit.normal);
+    color += env_mapping::sample_irr_map(reflected) * unlit.base_color * occlusion;
+    specular_ibl = env_mapping::specular_ibl(reflected, unlit.f_normal, unlit.NdotV, sqrt(unlit.roughness)) * occlusion;
 #endif // ENVIRONMENT_MAPPING
 
 #ifdef SSAO
     // TODO use position_cs directly from input.
 #ifdef SSAO_ONLY
-    color = vec3f(ssao::get_ao(camera.proj * in.position_vs));
+    color = vec3f(ssao::get_ao(view.proj * in.position_vs));
 #else // SSAO_ONLY
-    color *= vec3f(ssao::get_ao(camera.proj * in.position_vs));
+    color *= vec3f(ssao::get_ao(view.proj * in.position_vs));
 #endif // SSAO_ONLY
 #endif // SSAO
 
 #ifdef SSAO_ONLY
     return vec4f(color, 1.);
 #else // SSAO_ONLY
-    color = pbr_function::apply_exposure(color * unlit.base_color);
+    color = pbr_function::apply_exposure(color * unlit.base_color + specular_ibl);
+    // Emission is not affected by exposure, and stays above the bloom threshold when bright enough.
+    color += material.emissive * material.emissive_strength * textureSample(tex_emissive, tex_sampler, in.uv).rgb;
+#ifdef ALPHA_BLEND
+    return vec4f(color, alpha);
+#else ifdef ALPHA_TO_COVERAGE
+    return vec4f(color, alpha);
+#else // ALPHA_BLEND
     return vec4f(color, 1.);
+#endif // ALPHA_BLEND
 #endif // SSAO_ONLY
 }
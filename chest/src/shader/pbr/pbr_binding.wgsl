@@ -1,6 +1,6 @@
 #define_import_path aurora::pbr::pbr_binding
 #import aurora::{
-    common_type::{DirectionalLight, PointLight, SpotLight},
+    common_type::{DirectionalLight, Model, PointLight, SpotLight},
     pbr::pbr_type::PbrMaterial
 }
 
@@ -12,3 +12,8 @@
 @group(2) @binding(1) var tex_base_color: texture_2d<f32>;
 @group(2) @binding(2) var tex_normal: texture_2d<f32>;
 @group(2) @binding(3) var tex_sampler: sampler;
+@group(2) @binding(6) var tex_occlusion: texture_2d<f32>;
+@group(2) @binding(7) var tex_emissive: texture_2d<f32>;
+@group(2) @binding(8) var tex_vertex_animation: texture_2d<f32>;
+
+@group(3) @binding(0) var<uniform> mesh_model: Model;
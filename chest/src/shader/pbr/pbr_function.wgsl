@@ -3,7 +3,7 @@
     common_binding::camera,
     math::PI,
     pbr::{
-        pbr_binding::{tex_base_color, tex_normal, tex_sampler},
+        pbr_binding::{tex_base_color, tex_normal, tex_occlusion, tex_sampler},
         pbr_type::{BrdfSurfaceLit, BrdfSurfaceUnlit, PbrMaterial, PbrVertexOutput}
     }
 }
@@ -14,17 +14,20 @@ fn construct_surface_unlit(
     normal: vec3f,
     uv: vec2f,
     material: PbrMaterial,
+    view_position: vec3f,
 ) -> BrdfSurfaceUnlit {
     var surface: BrdfSurfaceUnlit;
 
     surface.roughness = material.roughness * material.roughness;
     surface.metallic = saturate(material.metallic);
-    surface.base_color = (1. - surface.metallic) * material.base_color * textureSample(tex_base_color, tex_sampler, uv).rgb;
+    let base_color = material.base_color * textureSample(tex_base_color, tex_sampler, uv).rgb;
+    surface.base_color = (1. - surface.metallic) * base_color;
     
     surface.normal = normal;
-    surface.view = normalize(camera.position - position);
+    surface.view = normalize(view_position - position);
 
-    surface.f_normal = mix(vec3f(0.16 * material.reflectance * material.reflectance), surface.base_color, surface.metallic);
+    // Metals reflect their base color, which is gone from the diffuse color above.
+    surface.f_normal = mix(vec3f(0.16 * material.reflectance * material.reflectance), base_color, surface.metallic);
 
     surface.NdotV = saturate(dot(surface.normal, surface.view));
 
@@ -45,6 +48,13 @@ fn construct_surface_lit(light: vec3f, unlit: BrdfSurfaceUnlit) -> BrdfSurfaceLi
     return surface;
 }
 
+// Baked ambient occlusion, pulled towards 1 as `occlusion_strength` decreases.
+fn sample_occlusion(uv: vec2f, second_uv: vec2f, material: PbrMaterial) -> f32 {
+    let occlusion_uv = select(uv, second_uv, material.occlusion_uv_set == 1u);
+    let occlusion = textureSample(tex_occlusion, tex_sampler, occlusion_uv).r;
+    return 1. + material.occlusion_strength * (occlusion - 1.);
+}
+
 fn unpack_normal(normal_os: vec3f, tangent_os: vec4f, uv: vec2f) -> vec3f {
     let bitangent_os = cross(normal_os, tangent_os.xyz) * tangent_os.w;
     let ttw = mat3x3f(tangent_os.xyz, bitangent_os, normal_os);
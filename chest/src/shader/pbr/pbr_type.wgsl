@@ -5,15 +5,32 @@ struct PbrMaterial {
     roughness: f32,
     metallic: f32,
     reflectance: f32,
+    occlusion_uv_set: u32,
+    occlusion_strength: f32,
+    emissive: vec3f,
+    emissive_strength: f32,
+    alpha: f32,
+    alpha_cutoff: f32,
+    vat_frames: u32,
+    vat_fps: f32,
+    vat_time_offset: f32,
+    vat_normals: u32,
+    wind_direction: vec3f,
+    wind_strength: f32,
+    wind_frequency: f32,
 }
 
 struct PbrVertexOutput {
-    @builtin(position) position_cs: vec4f,
+    @builtin(position) @invariant position_cs: vec4f,
     @location(0) position_ws: vec3f,
     @location(1) position_vs: vec4f,
     @location(2) normal: vec3f,
     @location(3) uv: vec2f,
     @location(4) tangent: vec4f,
+    @location(5) second_uv: vec2f,
+#ifdef VERTEX_COLORS
+    @location(6) color: vec4f,
+#endif // VERTEX_COLORS
 }
 
 struct BrdfSurfaceUnlit {
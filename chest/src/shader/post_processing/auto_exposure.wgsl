@@ -0,0 +1,119 @@
+#import aurora::math
+
+struct AutoExposureConfig {
+    min_log_luminance: f32,
+    max_log_luminance: f32,
+    low_percentile: f32,
+    high_percentile: f32,
+    adaptation_speed: f32,
+    compensation: f32,
+    min_ev100: f32,
+    max_ev100: f32,
+    camera_ev100: f32,
+    delta_time: f32,
+}
+
+struct AutoExposureState {
+    ev100: f32,
+    initialized: u32,
+}
+
+const BINS: u32 = 256u;
+
+@group(0) @binding(0) var color: texture_2d<f32>;
+@group(0) @binding(1) var<uniform> config: AutoExposureConfig;
+@group(0) @binding(2) var<storage, read_write> histogram: array<atomic<u32>, BINS>;
+@group(0) @binding(3) var<storage, read_write> state: AutoExposureState;
+
+var<workgroup> local_histogram: array<atomic<u32>, BINS>;
+var<workgroup> counts: array<u32, BINS>;
+
+// Bin 0 collects everything darker than the histogram range.
+fn luminance_to_bin(luminance: f32) -> u32 {
+    let log_luminance = log2(luminance);
+    if luminance <= 0. || log_luminance < config.min_log_luminance {
+        return 0u;
+    }
+
+    let range = config.max_log_luminance - config.min_log_luminance;
+    let t = saturate((log_luminance - config.min_log_luminance) / range);
+    return u32(t * f32(BINS - 2u)) + 1u;
+}
+
+fn bin_to_log_luminance(bin: u32) -> f32 {
+    if bin == 0u {
+        return config.min_log_luminance;
+    }
+
+    let range = config.max_log_luminance - config.min_log_luminance;
+    return config.min_log_luminance + (f32(bin - 1u) + 0.5) / f32(BINS - 2u) * range;
+}
+
+@compute @workgroup_size(16, 16, 1)
+fn histogram_pass(
+    @builtin(global_invocation_id) id: vec3u,
+    @builtin(local_invocation_index) index: u32,
+) {
+    atomicStore(&local_histogram[index], 0u);
+    workgroupBarrier();
+
+    if all(id.xy < textureDimensions(color)) {
+        // Undo the exposure applied in the pbr pass, the scene itself is measured.
+        var ev100 = config.camera_ev100;
+        if state.initialized != 0u {
+            ev100 = state.ev100;
+        }
+        let radiance = textureLoad(color, id.xy, 0).rgb * exp2(ev100) * 1.2;
+        atomicAdd(&local_histogram[luminance_to_bin(math::luminance(radiance))], 1u);
+    }
+
+    workgroupBarrier();
+    atomicAdd(&histogram[index], atomicLoad(&local_histogram[index]));
+}
+
+@compute @workgroup_size(256, 1, 1)
+fn average_pass(@builtin(local_invocation_index) index: u32) {
+    counts[index] = atomicLoad(&histogram[index]);
+    // Ready for the next frame.
+    atomicStore(&histogram[index], 0u);
+    workgroupBarrier();
+
+    if index != 0u {
+        return;
+    }
+
+    var total = 0u;
+    for (var i = 0u; i < BINS; i += 1u) {
+        total += counts[i];
+    }
+
+    let low = f32(total) * config.low_percentile;
+    let high = f32(total) * config.high_percentile;
+    var seen = 0.;
+    var weight = 0.;
+    var sum = 0.;
+    for (var i = 0u; i < BINS; i += 1u) {
+        let count = f32(counts[i]);
+        // Only the part of this bin between the percentiles.
+        let kept = max(min(seen + count, high) - max(seen, low), 0.);
+        seen += count;
+        sum += kept * bin_to_log_luminance(i);
+        weight += kept;
+    }
+
+    var average = config.min_log_luminance;
+    if weight > 0. {
+        average = sum / weight;
+    }
+
+    // EV100 = log2(L * S / K), with S = 100 and K = 12.5.
+    let target_ev100 = clamp(average + log2(100. / 12.5) - config.compensation, config.min_ev100, config.max_ev100);
+
+    if state.initialized == 0u {
+        state.ev100 = target_ev100;
+        state.initialized = 1u;
+    } else {
+        let t = 1. - exp(-config.delta_time * config.adaptation_speed);
+        state.ev100 = mix(state.ev100, target_ev100, t);
+    }
+}
@@ -1,4 +1,5 @@
 #import aurora::{
+    color_space,
     fullscreen::FullscreenVertexOutput,
     math,
 }
@@ -27,7 +28,7 @@ fn luminance(c: vec3f) -> f32 {
 }
 
 fn karis_average(c: vec3f) -> f32 {
-    let luma = math::luminance(math::linear_to_srgb(c)) * 0.25;
+    let luma = math::luminance(color_space::linear_to_srgb(c)) * 0.25;
     return 1.0 / (1.0 + luma);
 }
 
@@ -63,11 +64,13 @@ fn downsample(in: FullscreenVertexOutput) -> @location(0) vec4f {
     var group2 = (d + e + g + h) * 0.03125;
     var group3 = (e + f + h + i) * 0.03125;
     var group4 = (j + k + l + m) * 0.125;
+#ifdef ELIMINATE_FIREFLY
     group0 *= karis_average(group0);
     group1 *= karis_average(group1);
     group2 *= karis_average(group2);
     group3 *= karis_average(group3);
     group4 *= karis_average(group4);
+#endif // ELIMINATE_FIREFLY
     var col = group0 + group1 + group2 + group3 + group4;
 #ifdef SOFT_THRESHOLD
     col = soft_threshold(col);
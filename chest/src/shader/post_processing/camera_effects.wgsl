@@ -0,0 +1,39 @@
+#import aurora::{fullscreen::FullscreenVertexOutput, hash}
+
+struct CameraEffectsConfig {
+    vignette_strength: f32,
+    grain_amount: f32,
+    ca_strength: f32,
+    time: f32,
+}
+
+@group(0) @binding(0) var color: texture_2d<f32>;
+@group(0) @binding(1) var color_sampler: sampler;
+@group(0) @binding(2) var<uniform> config: CameraEffectsConfig;
+
+@fragment
+fn fragment(in: FullscreenVertexOutput) -> @location(0) vec4f {
+    var c = textureLoad(color, vec2u(in.position.xy), 0);
+    let offset = in.uv - 0.5;
+
+#ifdef CHROMATIC_ABERRATION
+    // Red and blue drift apart from the center, as if refracted by a cheap lens.
+    let shift = offset * config.ca_strength;
+    c.r = textureSampleLevel(color, color_sampler, in.uv - shift, 0.).r;
+    c.b = textureSampleLevel(color, color_sampler, in.uv + shift, 0.).b;
+#endif // CHROMATIC_ABERRATION
+
+#ifdef VIGNETTE
+    // Reaches full strength at the corners.
+    let falloff = smoothstep(0., 1., dot(offset, offset) * 2.);
+    c = vec4f(c.rgb * (1. - config.vignette_strength * falloff), c.a);
+#endif // VIGNETTE
+
+#ifdef FILM_GRAIN
+    // Relative to the color, so it looks the same before and after tonemapping.
+    let noise = hash::hash13u(vec3u(vec2u(in.position.xy), bitcast<u32>(config.time))) - 0.5;
+    c = vec4f(max(c.rgb * (1. + noise * config.grain_amount), vec3f(0.)), c.a);
+#endif // FILM_GRAIN
+
+    return c;
+}
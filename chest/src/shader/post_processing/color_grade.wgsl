@@ -0,0 +1,29 @@
+#import aurora::{color_space, fullscreen::FullscreenVertexOutput}
+
+struct ColorGrade {
+    domain_min: vec3f,
+    strength: f32,
+    domain_max: vec3f,
+}
+
+@group(0) @binding(0) var color: texture_2d<f32>;
+@group(0) @binding(1) var lut: texture_3d<f32>;
+@group(0) @binding(2) var lut_sampler: sampler;
+@group(0) @binding(3) var<uniform> config: ColorGrade;
+
+@fragment
+fn fragment(in: FullscreenVertexOutput) -> @location(0) vec4f {
+    let c = textureLoad(color, vec2u(in.position.xy), 0);
+
+    // LUTs are authored against display encoded colors.
+    let encoded = color_space::linear_to_srgb(max(c.rgb, vec3f(0.)));
+    let coord = saturate((encoded - config.domain_min) / (config.domain_max - config.domain_min));
+
+    // Sample between the centers of the first and last entries, so the domain bounds map
+    // to them exactly instead of blending towards the edges.
+    let size = vec3f(textureDimensions(lut));
+    let uvw = coord * (size - 1.) / size + 0.5 / size;
+    let graded = color_space::srgb_to_linear(textureSampleLevel(lut, lut_sampler, uvw, 0.).rgb);
+
+    return vec4f(mix(c.rgb, graded, config.strength), c.a);
+}
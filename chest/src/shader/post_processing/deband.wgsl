@@ -0,0 +1,56 @@
+#import aurora::{color_space, fullscreen::FullscreenVertexOutput, hash}
+
+struct DebandConfig {
+    strength: f32,
+    quantization_step: f32,
+    srgb_surface: u32,
+    frame: u32,
+}
+
+@group(0) @binding(0) var color: texture_2d<f32>;
+@group(0) @binding(1) var<uniform> config: DebandConfig;
+
+// Threshold of the 8x8 Bayer matrix in [0, 1).
+fn bayer_threshold(p: vec2u) -> f32 {
+    var v = 0u;
+    for (var bit = 0u; bit < 3u; bit += 1u) {
+        let x = (p.x >> bit) & 1u;
+        let y = (p.y >> bit) & 1u;
+        v += (2u * (x ^ y) + y) << (2u * (2u - bit));
+    }
+    return (f32(v) + 0.5) / 64.0;
+}
+
+// Dither offset in quantization steps, centered around zero.
+fn dither(p: vec2u) -> vec3f {
+#ifdef BAYER
+    return vec3f(bayer_threshold(p) - 0.5);
+#else // BAYER
+    // Sum of two uniform noises gives a triangular distribution in (-1, 1),
+    // which keeps the noise level independent of the signal.
+    let a = hash::hash33u(vec3u(p, config.frame));
+    let b = hash::hash33u(vec3u(p, config.frame + 0x9e3779b9u));
+    return a + b - 1.0;
+#endif // BAYER
+}
+
+@fragment
+fn fragment(in: FullscreenVertexOutput) -> @location(0) vec4f {
+    let p = vec2u(in.position.xy);
+    let c = textureLoad(color, p, 0);
+
+    // Dither where quantization happens, that is after the sRGB encoding of the surface.
+    var encoded = max(c.rgb, vec3f(0.0));
+    if config.srgb_surface != 0u {
+        encoded = color_space::linear_to_srgb(encoded);
+    }
+
+    encoded += dither(p) * config.strength * config.quantization_step;
+    encoded = max(encoded, vec3f(0.0));
+
+    if config.srgb_surface != 0u {
+        encoded = color_space::srgb_to_linear(encoded);
+    }
+
+    return vec4f(encoded, c.a);
+}
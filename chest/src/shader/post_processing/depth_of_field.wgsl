@@ -1,4 +1,9 @@
-#import aurora::{common_binding::camera, fullscreen::FullscreenVertexOutput, math}
+#import aurora::{
+    camera::linearize_depth,
+    common_binding::camera,
+    fullscreen::FullscreenVertexOutput,
+    math,
+}
 
 struct DofConfig {
     focal_length: f32,
@@ -13,14 +18,71 @@ struct DofConfig {
 @group(1) @binding(2) var color_sampler: sampler;
 @group(1) @binding(3) var<uniform> config: DofConfig;
 @group(1) @binding(4) var color_another: texture_2d<f32>;
+@group(1) @binding(5) var near_coc: texture_2d<f32>;
 
-fn calculate_coc_diameter(uv: vec2f) -> f32 {
+// Negative for near field, positive for far field.
+fn calculate_signed_coc_diameter(uv: vec2f) -> f32 {
     let dim = vec2f(textureDimensions(depth));
     let clip_z = textureLoad(depth, vec2i(uv * dim), 0);
-    let z = min(config.max_depth, math::clip_depth_to_view(clip_z, camera.inv_proj));
+    let z = min(config.max_depth, linearize_depth(camera, clip_z));
+
+    let d = config.coc_factor * (z - config.focal_distance) / (z * (config.focal_distance - config.focal_length));
+    return clamp(d * dim.y, -config.max_coc_radius * 2.0, config.max_coc_radius * 2.0);
+}
+
+fn calculate_near_coc_diameter(uv: vec2f) -> f32 {
+#ifdef DOF_NEAR_BLUR
+    return max(-calculate_signed_coc_diameter(uv), 0.0);
+#else // DOF_NEAR_BLUR
+    return 0.0;
+#endif // DOF_NEAR_BLUR
+}
 
-    let d = config.coc_factor * abs(z - config.focal_distance) / (z * (config.focal_distance - config.focal_length));
-    return min(config.max_coc_radius * 2.0, d * dim.y);
+fn calculate_far_coc_diameter(uv: vec2f) -> f32 {
+#ifdef DOF_FAR_BLUR
+    return max(calculate_signed_coc_diameter(uv), 0.0);
+#else // DOF_FAR_BLUR
+    return 0.0;
+#endif // DOF_FAR_BLUR
+}
+
+fn calculate_coc_diameter(uv: vec2f) -> f32 {
+#ifdef DOF_NEAR_BLUR
+    // Dilated near CoC, so blurry foreground bleeds over the background.
+    let dilated_near = textureSampleLevel(near_coc, color_sampler, uv, 0.0).r;
+    return max(calculate_far_coc_diameter(uv), dilated_near);
+#else // DOF_NEAR_BLUR
+    return calculate_far_coc_diameter(uv);
+#endif // DOF_NEAR_BLUR
+}
+
+// Prevents sharper samples, like in focus foreground, leaking into blurry background.
+fn sample_weight(uv: vec2f, coc: f32) -> f32 {
+    return saturate(calculate_coc_diameter(uv) / max(coc, 1e-4));
+}
+
+const NEAR_COC_DILATION_RINGS: i32 = 4;
+const NEAR_COC_DILATION_DIRECTIONS: i32 = 8;
+
+@fragment
+fn near_coc_dilation(in: FullscreenVertexOutput) -> @location(0) vec4f {
+    let texel = 1.0 / vec2f(textureDimensions(depth));
+    var coc = calculate_near_coc_diameter(in.uv);
+
+    for (var ring = 1; ring <= NEAR_COC_DILATION_RINGS; ring += 1) {
+        let radius = config.max_coc_radius * f32(ring) / f32(NEAR_COC_DILATION_RINGS);
+        for (var dir = 0; dir < NEAR_COC_DILATION_DIRECTIONS; dir += 1) {
+            let angle = f32(dir) / f32(NEAR_COC_DILATION_DIRECTIONS) * 2.0 * math::PI;
+            let offset = vec2f(cos(angle), sin(angle)) * radius;
+            let sample_coc = calculate_near_coc_diameter(in.uv + offset * texel);
+            // Only take samples whose circle of confusion covers this pixel.
+            if sample_coc * 0.5 >= radius {
+                coc = max(coc, sample_coc);
+            }
+        }
+    }
+
+    return vec4f(coc, 0.0, 0.0, 1.0);
 }
 
 fn gaussian_blur(uv: vec2f, coc: f32, step_texel_offset: vec2f) -> vec4f {
@@ -37,12 +99,12 @@ fn gaussian_blur(uv: vec2f, coc: f32, step_texel_offset: vec2f) -> vec4f {
         let w1 = exp(exp_factor * f32(step + 1) * f32(step + 1));
         let uv_offset = step_uv_offset * (f32(step) + w1 / (w0 + w1));
         let weight = w0 + w1;
+        let weight_pos = weight * sample_weight(uv + uv_offset, coc);
+        let weight_neg = weight * sample_weight(uv - uv_offset, coc);
 
-        sum += (
-            textureSampleLevel(color, color_sampler, uv + uv_offset, 0.0).rgb +
-            textureSampleLevel(color, color_sampler, uv - uv_offset, 0.0).rgb
-        ) * weight;
-        weight_sum += weight * 2.0;
+        sum += textureSampleLevel(color, color_sampler, uv + uv_offset, 0.0).rgb * weight_pos
+            + textureSampleLevel(color, color_sampler, uv - uv_offset, 0.0).rgb * weight_neg;
+        weight_sum += weight_pos + weight_neg;
     }
 
     return vec4f(sum / weight_sum, 1.0);
@@ -67,26 +129,34 @@ struct HexagonMrtOutput {
 
 fn blur_texture_a(uv: vec2f, coc: f32, step_texel_offset: vec2f) -> vec4f {
     var sum = vec3f(0.0);
+    var weight_sum = 0.0;
     let samples = i32(round(coc * 0.5));
     let step_uv_offset = step_texel_offset / vec2f(textureDimensions(color));
 
     for (var step = 0; step <= samples; step += 1) {
-        sum += textureSampleLevel(color, color_sampler, uv + step_uv_offset * f32(step), 0.0).rgb;
+        let sample_uv = uv + step_uv_offset * f32(step);
+        let weight = select(sample_weight(sample_uv, coc), 1.0, step == 0);
+        sum += textureSampleLevel(color, color_sampler, sample_uv, 0.0).rgb * weight;
+        weight_sum += weight;
     }
 
-    return vec4f(sum / vec3f(f32(samples + 1)), 1.0);
+    return vec4f(sum / weight_sum, 1.0);
 }
 
 fn blur_texture_b(uv: vec2f, coc: f32, step_texel_offset: vec2f) -> vec4f {
     var sum = vec3f(0.0);
+    var weight_sum = 0.0;
     let samples = i32(round(coc * 0.5));
     let step_uv_offset = step_texel_offset / vec2f(textureDimensions(color_another));
 
     for (var step = 0; step <= samples; step += 1) {
-        sum += textureSampleLevel(color_another, color_sampler, uv + step_uv_offset * f32(step), 0.0).rgb;
+        let sample_uv = uv + step_uv_offset * f32(step);
+        let weight = select(sample_weight(sample_uv, coc), 1.0, step == 0);
+        sum += textureSampleLevel(color_another, color_sampler, sample_uv, 0.0).rgb * weight;
+        weight_sum += weight;
     }
 
-    return vec4f(sum / vec3f(f32(samples + 1)), 1.0);
+    return vec4f(sum / weight_sum, 1.0);
 }
 
 const COS_NEG_FRAC_PI_6: f32 = 0.8660254037844387;
@@ -113,3 +183,85 @@ fn blur_rhomboid(in: FullscreenVertexOutput) -> @location(0) vec4f {
     let output_1 = blur_texture_b(in.uv, coc, vec2(COS_NEG_FRAC_PI_5_6, SIN_NEG_FRAC_PI_5_6));
     return mix(output_0, output_1, 0.5);
 }
+
+// Half resolution tiled path.
+// Color is downsampled together with its CoC, the max CoC of each tile bounds the gather radius,
+// then the blurred result is blended back with the sharp full resolution color.
+
+@group(1) @binding(6) var half_color: texture_2d<f32>;
+@group(1) @binding(7) var tile_coc: texture_2d<f32>;
+@group(1) @binding(8) var half_blur: texture_2d<f32>;
+
+// Tile size in full resolution pixels.
+const TILE_SIZE: u32 = #DOF_TILE_SIZE;
+const TILED_GATHER_SAMPLES: u32 = 48;
+const GOLDEN_ANGLE: f32 = 2.39996323;
+
+@fragment
+fn tiled_downsample(in: FullscreenVertexOutput) -> @location(0) vec4f {
+    let col = textureSampleLevel(color, color_sampler, in.uv, 0.0).rgb;
+    // CoC diameter in half resolution pixels.
+    return vec4f(col, calculate_coc_diameter(in.uv) * 0.5);
+}
+
+@fragment
+fn tiled_max_coc(in: FullscreenVertexOutput) -> @location(0) vec4f {
+    let half_tile = TILE_SIZE / 2u;
+    let dim = vec2u(textureDimensions(half_color));
+    let origin = vec2u(in.position.xy) * half_tile;
+
+    var coc = 0.0;
+    for (var x = 0u; x < half_tile; x += 1u) {
+        for (var y = 0u; y < half_tile; y += 1u) {
+            let p = min(origin + vec2u(x, y), dim - 1u);
+            coc = max(coc, textureLoad(half_color, p, 0).a);
+        }
+    }
+
+    return vec4f(coc, 0.0, 0.0, 1.0);
+}
+
+@fragment
+fn tiled_gather(in: FullscreenVertexOutput) -> @location(0) vec4f {
+    let dim = vec2f(textureDimensions(half_color));
+    let tile_dim = vec2i(textureDimensions(tile_coc));
+    let tile = vec2i(in.position.xy) / i32(TILE_SIZE / 2u);
+
+    // Neighbor tiles are included, so large CoC from nearby tiles can still spread into this one.
+    var max_coc = 0.0;
+    for (var x = -1; x <= 1; x += 1) {
+        for (var y = -1; y <= 1; y += 1) {
+            let t = clamp(tile + vec2i(x, y), vec2i(0), tile_dim - 1);
+            max_coc = max(max_coc, textureLoad(tile_coc, t, 0).r);
+        }
+    }
+
+    let center = textureSampleLevel(half_color, color_sampler, in.uv, 0.0);
+    let radius = min(max_coc, config.max_coc_radius) * 0.5;
+    if radius < 0.5 {
+        return center;
+    }
+
+    var sum = center.rgb;
+    var weight_sum = 1.0;
+    for (var i = 0u; i < TILED_GATHER_SAMPLES; i += 1u) {
+        let r = radius * sqrt((f32(i) + 0.5) / f32(TILED_GATHER_SAMPLES));
+        let theta = f32(i) * GOLDEN_ANGLE;
+        let sample_uv = in.uv + vec2f(cos(theta), sin(theta)) * r / dim;
+        let s = textureSampleLevel(half_color, color_sampler, sample_uv, 0.0);
+        // Only take samples whose circle of confusion covers this pixel.
+        let weight = saturate(s.a * 0.5 - r + 1.0);
+        sum += s.rgb * weight;
+        weight_sum += weight;
+    }
+
+    return vec4f(sum / weight_sum, center.a);
+}
+
+@fragment
+fn tiled_composite(in: FullscreenVertexOutput) -> @location(0) vec4f {
+    let sharp = textureSampleLevel(color, color_sampler, in.uv, 0.0);
+    let blurred = textureSampleLevel(half_blur, color_sampler, in.uv, 0.0);
+    let blend = smoothstep(1.0, 4.0, calculate_coc_diameter(in.uv));
+    return vec4f(mix(sharp.rgb, blurred.rgb, blend), 1.0);
+}
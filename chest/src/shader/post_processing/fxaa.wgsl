@@ -0,0 +1,164 @@
+#import aurora::{color_space, fullscreen::FullscreenVertexOutput, math}
+
+@group(0) @binding(0) var color: texture_2d<f32>;
+@group(0) @binding(1) var color_sampler: sampler;
+
+// EDGE_THRESHOLD is passed as reciprocal as shader defs can't be floats.
+const EDGE_THRESHOLD_MAX: f32 = 1.0 / f32(#EDGE_THRESHOLD);
+const EDGE_THRESHOLD_MIN: f32 = 0.0312;
+const SEARCH_STEPS: u32 = #SEARCH_STEPS;
+const SUBPIXEL_QUALITY: f32 = 0.75;
+
+// Luma in perceptual space, so thresholds behave the same before and after tonemapping.
+fn luma(col: vec3f) -> f32 {
+#ifdef FXAA_HDR_INPUT
+    let ldr = col / (1.0 + max(col.r, max(col.g, col.b)));
+#else // FXAA_HDR_INPUT
+    let ldr = saturate(col);
+#endif // FXAA_HDR_INPUT
+    return math::luminance(color_space::linear_to_srgb(ldr));
+}
+
+fn sample_luma(uv: vec2f) -> f32 {
+    return luma(textureSampleLevel(color, color_sampler, uv, 0.0).rgb);
+}
+
+fn search_step_scale(step: u32) -> f32 {
+    if step < 5u {
+        return 1.5;
+    } else if step < 8u {
+        return 2.0;
+    } else if step < 10u {
+        return 4.0;
+    }
+    return 8.0;
+}
+
+// http://blog.simonrodriguez.fr/articles/2016/07/implementing_fxaa.html
+@fragment
+fn fragment(in: FullscreenVertexOutput) -> @location(0) vec4f {
+    let texel = 1.0 / vec2f(textureDimensions(color));
+    let uv = in.uv;
+
+    let center = textureSampleLevel(color, color_sampler, uv, 0.0);
+    let luma_c = luma(center.rgb);
+    let luma_d = sample_luma(uv + vec2f(0.0, 1.0) * texel);
+    let luma_u = sample_luma(uv + vec2f(0.0, -1.0) * texel);
+    let luma_l = sample_luma(uv + vec2f(-1.0, 0.0) * texel);
+    let luma_r = sample_luma(uv + vec2f(1.0, 0.0) * texel);
+
+    let luma_min = min(luma_c, min(min(luma_d, luma_u), min(luma_l, luma_r)));
+    let luma_max = max(luma_c, max(max(luma_d, luma_u), max(luma_l, luma_r)));
+    let luma_range = luma_max - luma_min;
+
+    if luma_range < max(EDGE_THRESHOLD_MIN, luma_max * EDGE_THRESHOLD_MAX) {
+        return center;
+    }
+
+    let luma_dl = sample_luma(uv + vec2f(-1.0, 1.0) * texel);
+    let luma_ur = sample_luma(uv + vec2f(1.0, -1.0) * texel);
+    let luma_ul = sample_luma(uv + vec2f(-1.0, -1.0) * texel);
+    let luma_dr = sample_luma(uv + vec2f(1.0, 1.0) * texel);
+
+    let luma_du = luma_d + luma_u;
+    let luma_lr = luma_l + luma_r;
+    let luma_left_corners = luma_dl + luma_ul;
+    let luma_down_corners = luma_dl + luma_dr;
+    let luma_right_corners = luma_dr + luma_ur;
+    let luma_up_corners = luma_ur + luma_ul;
+
+    let edge_horizontal = abs(-2.0 * luma_l + luma_left_corners)
+        + abs(-2.0 * luma_c + luma_du) * 2.0
+        + abs(-2.0 * luma_r + luma_right_corners);
+    let edge_vertical = abs(-2.0 * luma_u + luma_up_corners)
+        + abs(-2.0 * luma_c + luma_lr) * 2.0
+        + abs(-2.0 * luma_d + luma_down_corners);
+    let is_horizontal = edge_horizontal >= edge_vertical;
+
+    let luma1 = select(luma_l, luma_u, is_horizontal);
+    let luma2 = select(luma_r, luma_d, is_horizontal);
+    let gradient1 = luma1 - luma_c;
+    let gradient2 = luma2 - luma_c;
+    let is_1_steepest = abs(gradient1) >= abs(gradient2);
+    let gradient_scaled = 0.25 * max(abs(gradient1), abs(gradient2));
+
+    var step_length = select(texel.x, texel.y, is_horizontal);
+    var luma_local_average = 0.0;
+    if is_1_steepest {
+        step_length = -step_length;
+        luma_local_average = 0.5 * (luma1 + luma_c);
+    } else {
+        luma_local_average = 0.5 * (luma2 + luma_c);
+    }
+
+    // Move to the middle of the edge.
+    var current_uv = uv;
+    if is_horizontal {
+        current_uv.y += step_length * 0.5;
+    } else {
+        current_uv.x += step_length * 0.5;
+    }
+
+    // Explore both sides along the edge until reaching the end.
+    let offset = select(vec2f(0.0, texel.y), vec2f(texel.x, 0.0), is_horizontal);
+    var uv1 = current_uv - offset;
+    var uv2 = current_uv + offset;
+
+    var luma_end1 = sample_luma(uv1) - luma_local_average;
+    var luma_end2 = sample_luma(uv2) - luma_local_average;
+    var reached1 = abs(luma_end1) >= gradient_scaled;
+    var reached2 = abs(luma_end2) >= gradient_scaled;
+
+    if !reached1 {
+        uv1 -= offset;
+    }
+    if !reached2 {
+        uv2 += offset;
+    }
+
+    for (var step = 2u; step < SEARCH_STEPS && !(reached1 && reached2); step += 1u) {
+        if !reached1 {
+            luma_end1 = sample_luma(uv1) - luma_local_average;
+        }
+        if !reached2 {
+            luma_end2 = sample_luma(uv2) - luma_local_average;
+        }
+        reached1 = abs(luma_end1) >= gradient_scaled;
+        reached2 = abs(luma_end2) >= gradient_scaled;
+
+        let scale = search_step_scale(step);
+        if !reached1 {
+            uv1 -= offset * scale;
+        }
+        if !reached2 {
+            uv2 += offset * scale;
+        }
+    }
+
+    let distance1 = select(uv.y - uv1.y, uv.x - uv1.x, is_horizontal);
+    let distance2 = select(uv2.y - uv.y, uv2.x - uv.x, is_horizontal);
+    let is_direction1 = distance1 < distance2;
+    let distance_final = min(distance1, distance2);
+    let edge_thickness = distance1 + distance2;
+    let pixel_offset = -distance_final / edge_thickness + 0.5;
+
+    // Only offset if the luma variation at the closer end is coherent with the center.
+    let is_luma_center_smaller = luma_c < luma_local_average;
+    let correct_variation = (select(luma_end2, luma_end1, is_direction1) < 0.0) != is_luma_center_smaller;
+    var final_offset = select(0.0, pixel_offset, correct_variation);
+
+    // Subpixel anti-aliasing.
+    let luma_average = (1.0 / 12.0) * (2.0 * (luma_du + luma_lr) + luma_left_corners + luma_right_corners);
+    let subpixel_offset1 = saturate(abs(luma_average - luma_c) / luma_range);
+    let subpixel_offset2 = (-2.0 * subpixel_offset1 + 3.0) * subpixel_offset1 * subpixel_offset1;
+    final_offset = max(final_offset, subpixel_offset2 * subpixel_offset2 * SUBPIXEL_QUALITY);
+
+    var final_uv = uv;
+    if is_horizontal {
+        final_uv.y += final_offset * step_length;
+    } else {
+        final_uv.x += final_offset * step_length;
+    }
+
+    return textureSampleLevel(color, color_sampler, final_uv, 0.0);
+}
@@ -1,4 +1,6 @@
 #import aurora::{
+    color_space,
+    common_type::{Camera, Scene, DirectionalLight, PointLight, SpotLight},
     fullscreen::FullscreenVertexOutput,
     hash,
     math,
@@ -11,6 +13,7 @@ struct LensFlareConfig {
     upper_threshold: f32,
     ca_strength: f32,
     halo_radius: f32,
+    occlusion_radius: f32,
 }
 
 @group(0) @binding(0) var color: texture_2d<f32>;
@@ -18,6 +21,123 @@ struct LensFlareConfig {
 @group(0) @binding(2) var<uniform> config: LensFlareConfig;
 @group(0) @binding(3) var startburst_texture: texture_1d<f32>;
 
+struct FlareGhost {
+    position_scale: f32,
+    size: f32,
+    tint: vec3f,
+    textured: u32,
+}
+
+@group(0) @binding(5) var<uniform> ghost_config: FlareGhost;
+@group(0) @binding(6) var ghost_texture: texture_2d<f32>;
+
+@group(1) @binding(0) var<uniform> camera: Camera;
+@group(1) @binding(1) var<uniform> scene: Scene;
+
+@group(2) @binding(0) var<storage, read> dir_lights: array<DirectionalLight>;
+@group(2) @binding(1) var<storage, read> point_lights: array<PointLight>;
+@group(2) @binding(2) var<storage, read> spot_lights: array<SpotLight>;
+
+// Returns (uv, depth, is_on_screen) of the light.
+fn project_light(position: vec4f) -> vec4f {
+    let clip = camera.proj * camera.view * position;
+    if clip.w <= 0.0 {
+        return vec4f(0.0);
+    }
+
+    let uv = math::clip_to_uv(clip);
+    if any(uv < vec2f(0.0)) || any(uv > vec2f(1.0)) {
+        return vec4f(0.0);
+    }
+
+    // Directional lights are infinitely far away, so they are only visible through the far plane.
+    var light_depth = 1.0;
+    if position.w != 0.0 {
+        light_depth = clip.z / clip.w;
+    }
+
+    return vec4f(uv, light_depth, 1.0);
+}
+
+#ifdef LENS_FLARE_OCCLUSION
+@group(0) @binding(4) var depth: texture_depth_2d;
+
+// Fraction of depth samples around the light that are not in front of it.
+fn light_visibility(position: vec4f) -> f32 {
+    let projected = project_light(position);
+    if projected.w == 0.0 {
+        return 0.0;
+    }
+
+    let dim = vec2i(textureDimensions(depth));
+    let center = vec2f(dim) * projected.xy;
+    var visible = 0.0;
+    for (var x = -2; x <= 2; x += 1) {
+        for (var y = -2; y <= 2; y += 1) {
+            let offset = vec2f(f32(x), f32(y)) * 0.5 * config.occlusion_radius;
+            let coord = clamp(vec2i(center + offset), vec2i(0), dim - 1);
+            if textureLoad(depth, coord, 0) >= projected.z {
+                visible += 1.0;
+            }
+        }
+    }
+    return visible / 25.0;
+}
+
+fn occlusion_factor() -> f32 {
+    let lights = scene.dir_lights + scene.point_lights + scene.spot_lights;
+    if lights == 0u {
+        return 1.0;
+    }
+
+    var visibility = 0.0;
+    for (var i = 0u; i < scene.dir_lights; i += 1u) {
+        visibility = max(visibility, light_visibility(vec4f(dir_lights[i].direction, 0.0)));
+    }
+    for (var i = 0u; i < scene.point_lights; i += 1u) {
+        visibility = max(visibility, light_visibility(vec4f(point_lights[i].position, 1.0)));
+    }
+    for (var i = 0u; i < scene.spot_lights; i += 1u) {
+        visibility = max(visibility, light_visibility(vec4f(spot_lights[i].position, 1.0)));
+    }
+    return visibility;
+}
+#endif // LENS_FLARE_OCCLUSION
+
+fn ghost_contribution(uv: vec2f, position: vec4f) -> vec3f {
+    let projected = project_light(position);
+    if projected.w == 0.0 {
+        return vec3f(0.0);
+    }
+
+    let dim = vec2f(textureDimensions(color));
+    let center = vec2f(0.5) + (projected.xy - vec2f(0.5)) * ghost_config.position_scale;
+    let local = (uv - center) * vec2f(dim.x / dim.y, 1.0) / ghost_config.size;
+    if length(local) > 1.0 && ghost_config.textured == 0u {
+        return vec3f(0.0);
+    }
+
+    var sprite = vec3f(smoothstep(1.0, 0.5, length(local)));
+    if ghost_config.textured != 0u {
+        if any(abs(local) > vec2f(1.0)) {
+            return vec3f(0.0);
+        }
+        sprite = textureSampleLevel(ghost_texture, color_sampler, local * 0.5 + 0.5, 0.0).rgb;
+    }
+
+    let pixel = textureSampleLevel(color, color_sampler, projected.xy, 0.0).rgb;
+    var luminance = saturate(math::luminance(color_space::linear_to_srgb(pixel)));
+    luminance = smoothstep(config.lower_threshold, config.upper_threshold, luminance);
+
+#ifdef LENS_FLARE_OCCLUSION
+    let visibility = light_visibility(position);
+#else // LENS_FLARE_OCCLUSION
+    let visibility = 1.0;
+#endif // LENS_FLARE_OCCLUSION
+
+    return pixel * luminance * sprite * ghost_config.tint * visibility;
+}
+
 fn chromatic_aberration(uv: vec2f, dir: vec2f, strength: vec3f) -> vec3f {
     let r = textureSample(color, color_sampler, uv + dir * strength.r).r;
     let g = textureSample(color, color_sampler, uv + dir * strength.g).g;
@@ -74,7 +194,7 @@ fn lens_flare(in: FullscreenVertexOutput) -> @location(0) vec4f {
 #endif // CHROMATIC_ABERRATION
 
         let falloff = length(vec2f(0.5) - sample_uv) / length(vec2f(0.5));
-        var luminance = saturate(math::luminance(math::linear_to_srgb(pixel)));
+        var luminance = saturate(math::luminance(color_space::linear_to_srgb(pixel)));
         luminance = smoothstep(config.lower_threshold, config.upper_threshold, luminance);
         col += pixel * pow((1.0 - falloff), config.center_falloff) * vec3f(luminance);
     }
@@ -83,6 +203,27 @@ fn lens_flare(in: FullscreenVertexOutput) -> @location(0) vec4f {
     col += halo(flipped_uv);
 #endif // HALO
 
-    let luminance = math::luminance(math::linear_to_srgb(col));
+#ifdef LENS_FLARE_OCCLUSION
+    col *= occlusion_factor();
+#endif // LENS_FLARE_OCCLUSION
+
+    let luminance = math::luminance(color_space::linear_to_srgb(col));
     return vec4f(col, luminance);
 }
+
+@fragment
+fn ghost(in: FullscreenVertexOutput) -> @location(0) vec4f {
+    var col = vec3f(0.0);
+
+    for (var i = 0u; i < scene.dir_lights; i += 1u) {
+        col += ghost_contribution(in.uv, vec4f(dir_lights[i].direction, 0.0));
+    }
+    for (var i = 0u; i < scene.point_lights; i += 1u) {
+        col += ghost_contribution(in.uv, vec4f(point_lights[i].position, 1.0));
+    }
+    for (var i = 0u; i < scene.spot_lights; i += 1u) {
+        col += ghost_contribution(in.uv, vec4f(spot_lights[i].position, 1.0));
+    }
+
+    return vec4f(col, math::luminance(color_space::linear_to_srgb(col)));
+}
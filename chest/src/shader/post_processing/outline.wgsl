@@ -0,0 +1,26 @@
+#import aurora::{fullscreen::FullscreenVertexOutput, outline::{NO_SEED, unpack_seed}}
+
+struct Outline {
+    color: vec4f,
+    width: f32,
+}
+
+@group(0) @binding(0) var color: texture_2d<f32>;
+@group(0) @binding(1) var seeds: texture_2d<u32>;
+@group(0) @binding(2) var<uniform> config: Outline;
+
+@fragment
+fn fragment(in: FullscreenVertexOutput) -> @location(0) vec4f {
+    let p = vec2i(in.position.xy);
+    let c = textureLoad(color, p, 0);
+    let seed = textureLoad(seeds, p, 0).r;
+    if seed == NO_SEED {
+        return c;
+    }
+
+    // 0 inside the silhouette, as the seed is the pixel itself.
+    let dist = distance(vec2f(unpack_seed(seed)), vec2f(p));
+    // Fade out over the last pixel to smooth the outer edge.
+    let coverage = saturate(config.width + 1. - dist) * step(0.5, dist);
+    return vec4f(mix(c.rgb, config.color.rgb, config.color.a * coverage), c.a);
+}
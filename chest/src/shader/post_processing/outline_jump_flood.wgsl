@@ -0,0 +1,40 @@
+#import aurora::{fullscreen::FullscreenVertexOutput, outline::{NO_SEED, unpack_seed}}
+
+struct JumpFlood {
+    step: i32,
+}
+
+@group(0) @binding(0) var seeds: texture_2d<u32>;
+@group(0) @binding(1) var<uniform> config: JumpFlood;
+
+// Keep the closest seed among the 3x3 neighbors `step` pixels apart.
+@fragment
+fn fragment(in: FullscreenVertexOutput) -> @location(0) u32 {
+    let p = vec2i(in.position.xy);
+    let size = vec2i(textureDimensions(seeds));
+
+    var closest = NO_SEED;
+    var closest_dist = 0x7fffffff;
+    for (var y = -1; y <= 1; y += 1) {
+        for (var x = -1; x <= 1; x += 1) {
+            let q = p + vec2i(x, y) * config.step;
+            if any(q < vec2i(0)) || any(q >= size) {
+                continue;
+            }
+
+            let seed = textureLoad(seeds, q, 0).r;
+            if seed == NO_SEED {
+                continue;
+            }
+
+            let offset = unpack_seed(seed) - p;
+            let dist = dot(offset, offset);
+            if dist < closest_dist {
+                closest = seed;
+                closest_dist = dist;
+            }
+        }
+    }
+
+    return closest;
+}
@@ -0,0 +1,15 @@
+#import aurora::{common_binding::camera, common_type::{Model, VertexInput}}
+
+@group(1) @binding(0) var<uniform> mesh_model: Model;
+
+@vertex
+fn vertex(in: VertexInput) -> @builtin(position) vec4f {
+    return camera.proj * camera.view * mesh_model.model * vec4f(in.position, 1.0);
+}
+
+// Each covered pixel is a seed of the jump flood, pointing at itself.
+@fragment
+fn fragment(@builtin(position) position: vec4f) -> @location(0) u32 {
+    let p = vec2u(position.xy);
+    return p.x | (p.y << 16u);
+}
@@ -0,0 +1,8 @@
+#define_import_path aurora::outline
+
+// Seeds are pixel coordinates packed in 16 bits each, all bits set where there's none.
+const NO_SEED: u32 = 0xffffffffu;
+
+fn unpack_seed(seed: u32) -> vec2i {
+    return vec2i(vec2u(seed & 0xffffu, seed >> 16u));
+}
@@ -0,0 +1,48 @@
+#import aurora::fullscreen::FullscreenVertexOutput
+
+struct SharpenConfig {
+    sharpness: f32,
+}
+
+@group(0) @binding(0) var color: texture_2d<f32>;
+@group(0) @binding(1) var<uniform> config: SharpenConfig;
+
+fn load(p: vec2i, offset: vec2i) -> vec3f {
+    let max_coord = vec2i(textureDimensions(color)) - 1;
+    return textureLoad(color, clamp(p + offset, vec2i(0), max_coord), 0).rgb;
+}
+
+// Contrast adaptive sharpening, after AMD FidelityFX CAS.
+@fragment
+fn fragment(in: FullscreenVertexOutput) -> @location(0) vec4f {
+    let p = vec2i(in.position.xy);
+    let e = textureLoad(color, p, 0);
+
+    // a b c
+    // d e f
+    // g h i
+    let a = load(p, vec2i(-1, -1));
+    let b = load(p, vec2i(0, -1));
+    let c = load(p, vec2i(1, -1));
+    let d = load(p, vec2i(-1, 0));
+    let f = load(p, vec2i(1, 0));
+    let g = load(p, vec2i(-1, 1));
+    let h = load(p, vec2i(0, 1));
+    let i = load(p, vec2i(1, 1));
+
+    // Soft min and max of the cross plus the corners.
+    let cross_min = min(min(min(d, e.rgb), min(f, b)), h);
+    let cross_max = max(max(max(d, e.rgb), max(f, b)), h);
+    let min_rgb = cross_min + min(cross_min, min(min(a, c), min(g, i)));
+    let max_rgb = cross_max + max(cross_max, max(max(a, c), max(g, i)));
+
+    // Less sharpening where the neighborhood already spans the whole range, which is
+    // mostly noise and hard edges.
+    let amp = sqrt(saturate(min(min_rgb, 2. - max_rgb) / max(max_rgb, vec3f(1e-5))));
+
+    // Negative lobe, 0 disables sharpening and 1 is the maximum of CAS.
+    let w = amp * (-0.2 * config.sharpness);
+    let sharpened = ((b + d + f + h) * w + e.rgb) / (1. + 4. * w);
+
+    return vec4f(max(sharpened, vec3f(0.)), e.a);
+}
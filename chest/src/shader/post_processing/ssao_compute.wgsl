@@ -1,4 +1,4 @@
-#import aurora::{common_type::Camera, hash, math, math::PI}
+#import aurora::{camera::view_position, common_type::Camera, hash, math, math::PI}
 
 struct SsaoConfig {
     slices: u32,
@@ -6,9 +6,12 @@ struct SsaoConfig {
     strength: f32,
     angle_bias: f32,
     max_depth_diff: f32,
+    thickness: f32,
+    falloff_range: f32,
 }
 
-@group(0) @binding(0) var depth: texture_depth_2d;
+// Bound as float like in SDSM, GLSL can't sample depth textures without comparing.
+@group(0) @binding(0) var depth: texture_2d<f32>;
 @group(0) @binding(1) var normal: texture_2d<f32>;
 @group(0) @binding(2) var output: texture_storage_2d<r32float, write>;
 @group(0) @binding(3) var<uniform> config: SsaoConfig;
@@ -29,19 +32,97 @@ fn view_space_normal(uv: vec2f) -> vec3f {
 }
 
 fn view_space_position(uv: vec2f) -> vec3f {
-    let clip = vec2f(uv.x * 2.0 - 1.0, 1.0 - 2.0 * uv.y);
-    let t = camera.inv_proj * vec4f(clip, frag_depth(uv), 1.0);
-    return t.xyz / t.w;
+    return view_position(camera, uv, frag_depth(uv));
 }
 
 fn frag_depth(uv: vec2f) -> f32 {
-    return textureSampleLevel(depth, tex_sampler, uv, 0.0);
+    return textureSampleLevel(depth, tex_sampler, uv, 0.0).r;
 }
 
 fn view_space_depth(uv: vec2f) -> f32 {
     return -view_space_position(uv).z;
 }
 
+#ifdef GTAO
+// Surfaces are assumed to have this albedo for the multi-bounce approximation, the prepasses
+// don't provide the real one.
+const MULTI_BOUNCE_ALBEDO: f32 = 0.5;
+
+// Cosine of the highest horizon along `dir3` on one side of the slice, starting from `low`,
+// the horizon of the hemisphere around the normal.
+fn search_horizon(texel_vs: vec3f, view_vs: vec3f, dir3: vec3f, low: f32, offset: f32) -> f32 {
+    var horizon = low;
+    for (var sample_index = 0u; sample_index < config.samples; sample_index += 1u) {
+        let planar_dist = (f32(sample_index) + offset) / f32(config.samples) * config.falloff_range;
+        let sample_uv = math::view_to_uv_and_depth(texel_vs + dir3 * planar_dist, camera.proj).xy;
+        if any(sample_uv < vec2f(0.0)) || any(sample_uv > vec2f(1.0)) {
+            break;
+        }
+
+        let delta = view_space_position(sample_uv) - texel_vs;
+        let dist = length(delta);
+        // Fade distant occluders back to the lowest horizon.
+        let falloff = saturate((config.falloff_range - dist) / (config.falloff_range * 0.5));
+        let sample_horizon = mix(low, dot(delta / dist, view_vs), falloff);
+
+        if sample_horizon > horizon {
+            horizon = sample_horizon;
+        } else {
+            // Thin occluders let the horizon drop back behind them.
+            horizon = max(mix(sample_horizon, horizon, config.thickness), low);
+        }
+    }
+    return horizon;
+}
+
+// Jimenez et al. 2016, Practical Realtime Strategies for Accurate Indirect Occlusion.
+fn multi_bounce(visibility: f32, albedo: f32) -> f32 {
+    let a = 2.0404 * albedo - 0.3324;
+    let b = -4.7951 * albedo + 0.6417;
+    let c = 2.7552 * albedo + 0.6903;
+    return max(visibility, ((visibility * a + b) * visibility + c) * visibility);
+}
+
+fn gtao(texel_vs: vec3f, normal_vs: vec3f, randomness: vec2f) -> f32 {
+    let view_vs = normalize(-texel_vs);
+    var visibility = 0.0;
+
+    for (var slice_index = 0u; slice_index < config.slices; slice_index += 1u) {
+        // Slices only need to cover half the circle, both sides are searched.
+        let angle = ((f32(slice_index) + randomness.x) / f32(config.slices)) * PI;
+        let dir3 = vec3f(cos(angle), sin(angle), 0.0);
+
+        // The slice plane contains the view vector, project the normal onto it.
+        let ortho_dir = dir3 - dot(dir3, view_vs) * view_vs;
+        let axis = normalize(cross(ortho_dir, view_vs));
+        let projected_normal = normal_vs - axis * dot(normal_vs, axis);
+        let projected_length = length(projected_normal);
+        if projected_length < 1e-4 {
+            continue;
+        }
+
+        let sign_n = sign(dot(ortho_dir, projected_normal));
+        let cos_n = saturate(dot(projected_normal, view_vs) / projected_length);
+        let n = sign_n * acos(cos_n);
+
+        let horizon_cos_0 = search_horizon(texel_vs, view_vs, dir3, cos(n + PI * 0.5), randomness.y);
+        let horizon_cos_1 = search_horizon(texel_vs, view_vs, -dir3, cos(n - PI * 0.5), randomness.y);
+
+        // Horizon angles relative to the view vector, clamped to the hemisphere.
+        let h0 = n + clamp(-acos(horizon_cos_1) - n, -PI * 0.5, PI * 0.5);
+        let h1 = n + clamp(acos(horizon_cos_0) - n, -PI * 0.5, PI * 0.5);
+
+        // Cosine weighted visible arc between both horizons.
+        let arc_0 = (cos_n + 2.0 * h0 * sin(n) - cos(2.0 * h0 - n)) * 0.25;
+        let arc_1 = (cos_n + 2.0 * h1 * sin(n) - cos(2.0 * h1 - n)) * 0.25;
+        visibility += projected_length * (arc_0 + arc_1);
+    }
+
+    visibility /= f32(config.slices);
+    return multi_bounce(saturate(visibility), MULTI_BOUNCE_ALBEDO);
+}
+#endif // GTAO
+
 @workgroup_size(#SSAO_WORKGROUP_SIZE, #SSAO_WORKGROUP_SIZE, 1)
 @compute
 fn main(@builtin(global_invocation_id) id: vec3u) {
@@ -58,6 +139,10 @@ fn main(@builtin(global_invocation_id) id: vec3u) {
     // Random rotation to avoid artifact.
     let randomness = math::hilbert_curve_noise(textureLoad(hilbert_lut, texel % 64, 0).r);
 
+#ifdef GTAO
+    // Already visibility, the denoise pass keeps it as is.
+    textureStore(output, id.xy, vec4f(gtao(texel_vs, normal_vs, randomness), 0.0, 0.0, 0.0));
+#else // GTAO
     var ao = 0.0;
 
     for (var slice_index = 0u; slice_index < config.slices; slice_index += 1u) {
@@ -113,4 +198,5 @@ fn main(@builtin(global_invocation_id) id: vec3u) {
 #endif // SSAO_DENOISE
 
     textureStore(output, id.xy, vec4f(ao, 0.0, 0.0, 0.0));
+#endif // GTAO
 }
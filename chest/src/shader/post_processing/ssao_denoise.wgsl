@@ -6,6 +6,8 @@ struct SsaoConfig {
     strength: f32,
     angle_bias: f32,
     max_depth_diff: f32,
+    thickness: f32,
+    falloff_range: f32,
 }
 
 @group(0) @binding(0) var<uniform> config: SsaoConfig;
@@ -37,5 +39,9 @@ fn main(@builtin(global_invocation_id) id: vec3u) {
         }
     }
 
+#ifdef GTAO
+    textureStore(filtered_ao, texel, vec4f(sum / weight));
+#else // GTAO
     textureStore(filtered_ao, texel, vec4f(pow(sum / weight, f32(config.strength))));
+#endif // GTAO
 }
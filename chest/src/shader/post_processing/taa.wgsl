@@ -0,0 +1,42 @@
+#import aurora::fullscreen::FullscreenVertexOutput
+
+struct TaaConfig {
+    feedback: f32,
+}
+
+@group(0) @binding(0) var color: texture_2d<f32>;
+@group(0) @binding(1) var history: texture_2d<f32>;
+@group(0) @binding(2) var motion_vector: texture_2d<f32>;
+@group(0) @binding(3) var color_sampler: sampler;
+@group(0) @binding(4) var motion_vector_sampler: sampler;
+@group(0) @binding(5) var<uniform> config: TaaConfig;
+
+@fragment
+fn fragment(in: FullscreenVertexOutput) -> @location(0) vec4f {
+    let texel = 1.0 / vec2f(textureDimensions(color));
+    let current = textureSampleLevel(color, color_sampler, in.uv, 0.0);
+
+    // Neighborhood min and max, used to reject stale history.
+    var neighborhood_min = current.rgb;
+    var neighborhood_max = current.rgb;
+    for (var x = -1; x <= 1; x += 1) {
+        for (var y = -1; y <= 1; y += 1) {
+            let offset = vec2f(f32(x), f32(y)) * texel;
+            let col = textureSampleLevel(color, color_sampler, in.uv + offset, 0.0).rgb;
+            neighborhood_min = min(neighborhood_min, col);
+            neighborhood_max = max(neighborhood_max, col);
+        }
+    }
+
+    // Motion vectors are stored as (uv - previous_uv) * 2.
+    let motion = textureSampleLevel(motion_vector, motion_vector_sampler, in.uv, 0.0).rg;
+    let previous_uv = in.uv - motion * 0.5;
+    if any(previous_uv < vec2f(0.0)) || any(previous_uv > vec2f(1.0)) {
+        return current;
+    }
+
+    let previous = textureSampleLevel(history, color_sampler, previous_uv, 0.0).rgb;
+    let clamped = clamp(previous, neighborhood_min, neighborhood_max);
+
+    return vec4f(mix(current.rgb, clamped, config.feedback), current.a);
+}
@@ -5,10 +5,93 @@
 @group(0) @binding(2) var color_sampler: sampler;
 @group(0) @binding(3) var lut_sampler: sampler;
 
+struct Tonemapping {
+    // Luminance mapped to white, 0 keeps the operator's own.
+    white_point: f32,
+    highlight_desaturation: f32,
+    // In scRGB units, 0 unless the surface is HDR.
+    hdr_paper_white: f32,
+    hdr_peak: f32,
+    // Non zero when writing to a surface without an sRGB format, which expects it encoded.
+    encode_srgb: u32,
+}
+
+@group(0) @binding(4) var<uniform> config: Tonemapping;
+
+// Operators take radiance that's already exposed, see `pbr_function::apply_exposure`,
+// so none of them adds an exposure bias of its own.
+
+// Piecewise sRGB transfer function, matching what sRGB formats do on write.
+fn encode_srgb(color: vec3f) -> vec3f {
+    let c = saturate(color);
+    return select(1.055 * pow(c, vec3f(1. / 2.4)) - 0.055, c * 12.92, c <= vec3f(0.0031308));
+}
+
+fn luminance(x: vec3f) -> f32 {
+    return dot(x, vec3f(0.2126, 0.7152, 0.0722));
+}
+
 fn tonemapping_reinhard(x: vec3f) -> vec3f {
     return x / (1. + x);
 }
 
+// Luminance this operator maps to 1.
+const REINHARD_EXTENDED_WHITE: f32 = 4.0;
+
+fn tonemapping_reinhard_extended(x: vec3f, white: f32) -> vec3f {
+    let l = luminance(x);
+    let mapped = l * (1. + l / (white * white)) / (1. + l);
+    return x * (mapped / max(l, 1e-5));
+}
+
+// Stephen Hill's fit of the ACES RRT and ODT.
+const ACES_INPUT: mat3x3f = mat3x3f(
+    vec3f(0.59719, 0.07600, 0.02840),
+    vec3f(0.35458, 0.90834, 0.13383),
+    vec3f(0.04823, 0.01566, 0.83777),
+);
+
+const ACES_OUTPUT: mat3x3f = mat3x3f(
+    vec3f(1.60475, -0.10208, -0.00327),
+    vec3f(-0.53108, 1.10813, -0.07276),
+    vec3f(-0.07367, -0.00605, 1.07602),
+);
+
+fn tonemapping_aces_fitted(x: vec3f) -> vec3f {
+    let v = ACES_INPUT * x;
+    let a = v * (v + 0.0245786) - 0.000090537;
+    let b = v * (0.983729 * v + 0.4329510) + 0.238081;
+    return saturate(ACES_OUTPUT * (a / b));
+}
+
+// John Hable's filmic curve.
+fn uncharted2_curve(x: vec3f) -> vec3f {
+    let a = 0.15;
+    let b = 0.50;
+    let c = 0.10;
+    let d = 0.20;
+    let e = 0.02;
+    let f = 0.30;
+    return ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f;
+}
+
+const UNCHARTED2_WHITE: f32 = 11.2;
+
+fn tonemapping_uncharted2(x: vec3f, white: f32) -> vec3f {
+    return uncharted2_curve(x) / uncharted2_curve(vec3f(white));
+}
+
+fn white_point(default_white: f32) -> f32 {
+    return select(default_white, config.white_point, config.white_point > 0.);
+}
+
+// Per channel curves clip the brightest channel first and skew the hue, so fade bright
+// colors towards gray instead.
+fn desaturate_highlights(x: vec3f) -> vec3f {
+    let t = config.highlight_desaturation * smoothstep(0.5, 1., luminance(x));
+    return mix(x, vec3f(max(x.r, max(x.g, x.b))), t);
+}
+
 const TONY_MC_MAPFACE_LUT_DIMS: f32 = 48.0;
 
 // Code from Bevy Engine
@@ -17,13 +100,43 @@ fn tonemapping_tony_mc_mapface(stimulus: vec3f) -> vec3f {
     return textureSampleLevel(tony_mc_mapface_lut, lut_sampler, uv, 0.0).rgb;
 }
 
+// Keeps the scene linear up to the highlights, which approach the peak of the display
+// without changing hue.
+fn tonemapping_hdr(x: vec3f) -> vec3f {
+    let scaled = x * config.hdr_paper_white;
+    return scaled / (1. + luminance(scaled) / config.hdr_peak);
+}
+
 @fragment
 fn fragment(in: FullscreenVertexOutput) -> @location(0) vec4f {
     let col = textureSample(color, color_sampler, in.uv).rgb;
+    if config.hdr_paper_white > 0. {
+        return vec4f(tonemapping_hdr(col), 1.0);
+    }
 #ifdef REINHARD
-    let mapped = tonemapping_reinhard(col);
+    var mapped = tonemapping_reinhard(col);
+    if config.white_point > 0. {
+        mapped /= tonemapping_reinhard(vec3f(config.white_point));
+    }
+#else ifdef REINHARD_EXTENDED
+    var mapped = tonemapping_reinhard_extended(col, white_point(REINHARD_EXTENDED_WHITE));
+#else ifdef ACES_FITTED
+    var mapped = tonemapping_aces_fitted(col);
+    if config.white_point > 0. {
+        mapped /= tonemapping_aces_fitted(vec3f(config.white_point));
+    }
+#else ifdef UNCHARTED2
+    var mapped = tonemapping_uncharted2(col, white_point(UNCHARTED2_WHITE));
 #else ifdef TONY_MC_MAPFACE
+    // Baked, so the white point and desaturation don't apply.
     let mapped = tonemapping_tony_mc_mapface(col);
 #endif
+
+#ifndef TONY_MC_MAPFACE
+    mapped = desaturate_highlights(mapped);
+#endif
+    if config.encode_srgb != 0u {
+        return vec4f(encode_srgb(mapped), 1.0);
+    }
     return vec4f(mapped, 1.0);
 }
@@ -0,0 +1,24 @@
+#define_import_path aurora::post_processing::volumetric_fog
+
+struct VolumetricFog {
+    density: f32,
+    scattering: f32,
+    absorption: f32,
+    steps: u32,
+    anisotropy: f32,
+    max_distance: f32,
+    // Froxels along x and y, the slices of the atlas are laid out in rows of `tiles_per_row`.
+    grid: vec2u,
+    tiles_per_row: u32,
+}
+
+// View space depth where `slice` starts. Slices grow quadratically to spend more of them
+// close to the camera.
+fn slice_depth(slice: f32, fog: VolumetricFog) -> f32 {
+    let t = slice / f32(fog.steps);
+    return fog.max_distance * t * t;
+}
+
+fn depth_to_slice(depth: f32, fog: VolumetricFog) -> f32 {
+    return sqrt(saturate(depth / fog.max_distance)) * f32(fog.steps);
+}
@@ -0,0 +1,25 @@
+#import aurora::{
+    fullscreen::FullscreenVertexOutput,
+    post_processing::volumetric_fog::{VolumetricFog, depth_to_slice},
+}
+
+@group(0) @binding(0) var color: texture_2d<f32>;
+@group(0) @binding(1) var linear_depth: texture_2d<f32>;
+@group(0) @binding(2) var volume: texture_3d<f32>;
+@group(0) @binding(3) var volume_sampler: sampler;
+@group(0) @binding(4) var<uniform> fog: VolumetricFog;
+
+@fragment
+fn fragment(in: FullscreenVertexOutput) -> @location(0) vec4f {
+    let pixel = vec2u(in.position.xy);
+    let scene = textureLoad(color, pixel, 0);
+    let depth = textureLoad(linear_depth, pixel, 0).r;
+
+    // Slices are stored at their far end, so the first one fades in from the camera.
+    let slice = depth_to_slice(depth, fog);
+    let w = (slice - 0.5) / f32(fog.steps);
+    let integrated = textureSampleLevel(volume, volume_sampler, vec3f(in.uv, w), 0.);
+    let fogged = mix(vec4f(0., 0., 0., 1.), integrated, saturate(slice));
+
+    return vec4f(scene.rgb * fogged.a + fogged.rgb, scene.a);
+}
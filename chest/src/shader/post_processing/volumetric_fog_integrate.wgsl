@@ -0,0 +1,30 @@
+#import aurora::post_processing::volumetric_fog::{VolumetricFog, slice_depth}
+
+@group(0) @binding(0) var froxels: texture_2d<f32>;
+@group(0) @binding(1) var volume: texture_storage_3d<rgba16float, write>;
+@group(0) @binding(2) var<uniform> fog: VolumetricFog;
+
+// Each slice of the volume stores the in-scattering and transmittance accumulated from the
+// camera up to its far end.
+@compute @workgroup_size(8, 8, 1)
+fn main(@builtin(global_invocation_id) id: vec3u) {
+    if any(id.xy >= fog.grid) {
+        return;
+    }
+
+    var inscattering = vec3f(0.);
+    var transmittance = 1.;
+    for (var slice = 0u; slice < fog.steps; slice += 1u) {
+        let tile = vec2u(slice % fog.tiles_per_row, slice / fog.tiles_per_row);
+        let froxel = textureLoad(froxels, tile * fog.grid + id.xy, 0);
+        let thickness = slice_depth(f32(slice + 1u), fog) - slice_depth(f32(slice), fog);
+        let extinction = max(froxel.a, 1e-5);
+        let slice_transmittance = exp(-extinction * thickness);
+
+        // Integrates the scattering analytically over the slice, instead of assuming it is
+        // constant, so thick slices don't gain energy.
+        inscattering += transmittance * froxel.rgb * (1. - slice_transmittance) / extinction;
+        transmittance *= slice_transmittance;
+        textureStore(volume, vec3u(id.xy, slice), vec4f(inscattering, transmittance));
+    }
+}
@@ -0,0 +1,60 @@
+#import aurora::{
+    camera::view_ray,
+    common_binding::{camera, scene},
+    common_type::DirectionalLight,
+    fullscreen::FullscreenVertexOutput,
+    math::PI,
+    post_processing::volumetric_fog::{VolumetricFog, slice_depth},
+    shadow_mapping,
+}
+
+@group(1) @binding(0) var<storage, read> dir_lights: array<DirectionalLight>;
+@group(2) @binding(0) var<uniform> fog: VolumetricFog;
+
+fn henyey_greenstein(cos_theta: f32, g: f32) -> f32 {
+    let denom = 1. + g * g - 2. * g * cos_theta;
+    return (1. - g * g) / (4. * PI * denom * sqrt(denom));
+}
+
+// Outputs in-scattered radiance in rgb and extinction in a, both per unit of view space depth.
+@fragment
+fn fragment(in: FullscreenVertexOutput) -> @location(0) vec4f {
+    let pixel = vec2u(in.position.xy);
+    let tile = pixel / fog.grid;
+    let slice = tile.y * fog.tiles_per_row + tile.x;
+    if slice >= fog.steps {
+        return vec4f(0.);
+    }
+
+    let uv = (vec2f(pixel % fog.grid) + 0.5) / vec2f(fog.grid);
+    let ray_vs = view_ray(camera, uv);
+    let depth = slice_depth(f32(slice) + 0.5, fog);
+    let position_vs = vec4f(ray_vs * depth, 1.);
+    let position_ws = (camera.inv_view * position_vs).xyz;
+    let view = normalize(position_ws - camera.position);
+
+    var radiance = vec3f(0.);
+    for (var i_light = 0u; i_light < scene.dir_lights; i_light += 1u) {
+        let light = &dir_lights[i_light];
+
+#ifdef TRANSLUCENT_SHADOWS
+        let shadow = shadow_mapping::sample_cascaded_shadow_map(i_light, position_ws, vec3f(0.), position_vs, (*light).radius * 2.)
+            * shadow_mapping::sample_cascaded_transmittance(i_light, position_ws, position_vs);
+#else // TRANSLUCENT_SHADOWS
+        let shadow = shadow_mapping::sample_cascaded_shadow_map(i_light, position_ws, vec3f(0.), position_vs, (*light).radius * 2.);
+#endif // TRANSLUCENT_SHADOWS
+
+        let phase = henyey_greenstein(dot(normalize((*light).direction), view), fog.anisotropy);
+        radiance += (*light).color * (*light).intensity * phase * shadow;
+    }
+
+    // Marching one unit of depth covers this much distance along the ray. Scaling both
+    // coefficients by it lets the integration step through depth only.
+    let stretch = length(ray_vs);
+    let scattering = fog.density * fog.scattering * stretch;
+    let extinction = fog.density * (fog.scattering + fog.absorption) * stretch;
+
+    // Same as pbr_function::apply_exposure, so compositing doesn't need the camera.
+    let exposed = radiance * scattering / (pow(2., camera.exposure) * 1.2);
+    return vec4f(exposed, extinction);
+}
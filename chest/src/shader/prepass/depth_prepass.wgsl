@@ -1,8 +1,21 @@
-#import aurora::{common_binding::camera, common_type::VertexInput}
+#import aurora::{common_binding, common_binding::camera, common_type::{Model, PositionInput}}
 
+@group(1) @binding(0) var<uniform> mesh_model: Model;
+
+// Invariant and computed in the same order as the pbr vertex shader, so it can test for
+// equal depth.
 @vertex
-fn vertex(in: VertexInput) -> @builtin(position) vec4f {
-    return camera.proj * camera.view * vec4f(in.position, 1.0);
+fn vertex(
+    in: PositionInput,
+#ifdef MULTIVIEW
+    @builtin(view_index) view_index: i32,
+#endif // MULTIVIEW
+) -> @builtin(position) @invariant vec4f {
+#ifdef MULTIVIEW
+    let camera = common_binding::eyes[view_index];
+#endif // MULTIVIEW
+    let position = (mesh_model.model * vec4f(in.position, 1.)).xyz;
+    return camera.proj * (camera.view * vec4f(position, 1.));
 }
 
 @fragment
@@ -0,0 +1,13 @@
+#import aurora::{
+    camera::linearize_depth,
+    common_binding::camera,
+    fullscreen::FullscreenVertexOutput,
+}
+
+@group(1) @binding(0) var depth: texture_depth_2d;
+
+@fragment
+fn fragment(in: FullscreenVertexOutput) -> @location(0) f32 {
+    let clip_z = textureLoad(depth, vec2i(in.position.xy), 0);
+    return linearize_depth(camera, clip_z);
+}
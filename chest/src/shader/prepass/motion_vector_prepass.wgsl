@@ -1,5 +1,5 @@
 #import aurora::{
-    common_type::{Camera, VertexInput},
+    common_type::{Camera, Model},
     math,
 }
 
@@ -9,6 +9,14 @@ struct MotionVectorPrepassConfig {
 
 @group(0) @binding(0) var<uniform> camera: Camera;
 @group(0) @binding(1) var<uniform> config: MotionVectorPrepassConfig;
+@group(1) @binding(0) var<uniform> mesh_model: Model;
+@group(2) @binding(0) var<uniform> previous_mesh_model: Model;
+
+// Previous positions only differ for meshes deformed on the GPU, like skinned ones.
+struct MotionVectorPrepassVertexInput {
+    @location(0) position: vec3f,
+    @location(1) previous_position: vec3f,
+}
 
 struct MotionVectorPrepassVertexOutput {
     @builtin(position) position: vec4f,
@@ -17,11 +25,16 @@ struct MotionVectorPrepassVertexOutput {
 }
 
 @vertex
-fn vertex(in: VertexInput) -> MotionVectorPrepassVertexOutput {
-    let current = camera.view * vec4f(in.position, 1.0);
-    let previous = config.previous_view * vec4f(in.position, 1.0);
+fn vertex(in: MotionVectorPrepassVertexInput) -> MotionVectorPrepassVertexOutput {
+    // Same order as the depth prepass, so it passes its depth test.
+    let position = (mesh_model.model * vec4f(in.position, 1.0)).xyz;
+    let current = camera.view * vec4f(position, 1.0);
+    let previous =
+        config.previous_view * previous_mesh_model.model * vec4f(in.previous_position, 1.0);
 
     var out: MotionVectorPrepassVertexOutput;
+    // Both positions share the jittered projection, so the jitter cancels out and only the
+    // motion is left.
     out.position = camera.proj * current;
     out.current_position = out.position;
     out.previous_position = camera.proj * previous;
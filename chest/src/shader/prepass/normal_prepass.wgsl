@@ -1,7 +1,8 @@
 #define_import_path aurora::prepass::normal_prepass
-#import aurora::common_type::{Camera, VertexInput}
+#import aurora::common_type::{Camera, Model, VertexInput}
 
 @group(0) @binding(0) var<uniform> camera: Camera;
+@group(1) @binding(0) var<uniform> mesh_model: Model;
 
 struct VertexOutput {
     @builtin(position) position_cs: vec4f,
@@ -11,8 +12,9 @@ struct VertexOutput {
 @vertex
 fn vertex(in: VertexInput) -> VertexOutput {
     var out: VertexOutput;
-    out.position_cs = camera.proj * camera.view * vec4f(in.position, 1.);
-    out.normal_ws = normalize(in.normal);
+    let position = (mesh_model.model * vec4f(in.position, 1.)).xyz;
+    out.position_cs = camera.proj * (camera.view * vec4f(position, 1.));
+    out.normal_ws = normalize(mesh_model.normal * in.normal);
     return out;
 }
 
@@ -0,0 +1,34 @@
+// Clip space depth bits, the smallest followed by the largest. Positive floats compare
+// the same as their bits, so atomics on u32 work.
+@group(0) @binding(0) var depth: texture_2d<f32>;
+@group(0) @binding(1) var<storage, read_write> bounds: array<atomic<u32>, 2>;
+
+var<workgroup> local_bounds: array<atomic<u32>, 2>;
+
+@compute @workgroup_size(16, 16, 1)
+fn depth_bounds(
+    @builtin(global_invocation_id) id: vec3u,
+    @builtin(local_invocation_index) index: u32,
+) {
+    if index == 0u {
+        atomicStore(&local_bounds[0], 0xFFFFFFFFu);
+        atomicStore(&local_bounds[1], 0u);
+    }
+    workgroupBarrier();
+
+    let size = textureDimensions(depth);
+    if all(id.xy < size) {
+        let d = textureLoad(depth, id.xy, 0).r;
+        // Pixels left at the cleared far plane have nothing to shadow.
+        if d < 1. {
+            atomicMin(&local_bounds[0], bitcast<u32>(max(d, 0.)));
+            atomicMax(&local_bounds[1], bitcast<u32>(d));
+        }
+    }
+    workgroupBarrier();
+
+    if index == 0u {
+        atomicMin(&bounds[0], atomicLoad(&local_bounds[0]));
+        atomicMax(&bounds[1], atomicLoad(&local_bounds[1]));
+    }
+}
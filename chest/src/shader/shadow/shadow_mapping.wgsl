@@ -24,8 +24,36 @@ const CONSTANT_BIAS: f32 = 0.0001;
 // First `samples` are 2d, then `samples` are 3d.
 @group(#SHADOW_MAPPING) @binding(6) var<storage> poisson_disk: array<vec4f>;
 @group(#SHADOW_MAPPING) @binding(7) var<uniform> config: ShadowMappingConfig;
+// Fraction of the layer each cascade renders to, starting from the top left corner.
+@group(#SHADOW_MAPPING) @binding(8) var<storage> cascade_uv_scales: array<f32>;
+#ifdef TRANSLUCENT_SHADOWS
+// Product of translucent caster tints in rgb, depth of the nearest one in a.
+@group(#SHADOW_MAPPING) @binding(9) var directional_transmittance_map: texture_2d_array<f32>;
+#endif // TRANSLUCENT_SHADOWS
+// Point shadow map of each point then spot light, NO_SHADOW_LAYER for unshadowed ones.
+@group(#SHADOW_MAPPING) @binding(10) var<storage> point_shadow_layers: array<u32>;
 
-fn dir_pcf_filtering(position_vs: vec4f, position_ws: vec3f, cascade: u32, radius: f32) -> f32 {
+const NO_SHADOW_LAYER: u32 = 0xffffffffu;
+
+#ifdef NORMAL_OFFSET
+// World space size of a texel of the light view `index`, rendering `cascade`.
+fn cascade_texel_size(index: u32, cascade: u32) -> f32 {
+    let resolution = cascade_uv_scales[cascade] * f32(textureDimensions(directional_shadow_map).x);
+    // Orthographic, so the diagonal of the projection is 2 over the size of the cascade.
+    let proj = cascade_views[index].proj;
+    return 2. / (min(proj[0][0], proj[1][1]) * resolution);
+}
+#endif // NORMAL_OFFSET
+
+fn cascade_uv(uv: vec2f, cascade: u32) -> vec2f {
+    let scale = cascade_uv_scales[cascade];
+    // Keep linear filtering from reading texels outside of this cascade.
+    let half_texel = 0.5 / f32(textureDimensions(directional_shadow_map).x);
+    return min(uv * scale, vec2f(scale - half_texel));
+}
+
+// `index` is the light view and layer, `cascade` only picks the resolution of the layer.
+fn dir_pcf_filtering(position_vs: vec4f, position_ws: vec3f, index: u32, cascade: u32, radius: f32) -> f32 {
     var shadow = 0.;
     for (var iteration = 0u; iteration < config.samples; iteration += 1u) {
         let sample = poisson_disk[iteration].xy;
@@ -35,11 +63,11 @@ fn dir_pcf_filtering(position_vs: vec4f, position_ws: vec3f, cascade: u32, radiu
 #else // SHADOW_MAP_SAMPLE_RANDOMIZE
         let view = position_vs + vec4f(sample * radius, 0., 0.);
 #endif // SHADOW_MAP_SAMPLE_RANDOMIZE
-        var offseted = math::view_to_uv_and_depth(view.xyz, cascade_views[cascade].proj);
+        var offseted = math::view_to_uv_and_depth(view.xyz, cascade_views[index].proj);
 
         if (offseted.x > 0. && offseted.x < 1. && offseted.y > 0. && offseted.y < 1.) {
             let frag_depth = saturate(offseted.z) - CONSTANT_BIAS;
-            shadow += textureSampleCompare(directional_shadow_map, shadow_map_sampler, offseted.xy, cascade, frag_depth);
+            shadow += textureSampleCompare(directional_shadow_map, shadow_map_sampler, cascade_uv(offseted.xy, cascade), index, frag_depth);
         } else {
             shadow += 1.;
         }
@@ -47,8 +75,8 @@ fn dir_pcf_filtering(position_vs: vec4f, position_ws: vec3f, cascade: u32, radiu
     return shadow / f32(config.samples);
 }
 
-fn dir_pcss_filtering(position_vs: vec4f, position_ws: vec3f, cascade: u32, radius: f32, light_width: f32) -> f32 {
-    let frag_depth = math::view_to_uv_and_depth(position_vs.xyz, cascade_views[cascade].proj).z;
+fn dir_pcss_filtering(position_vs: vec4f, position_ws: vec3f, index: u32, cascade: u32, radius: f32, light_width: f32) -> f32 {
+    let frag_depth = math::view_to_uv_and_depth(position_vs.xyz, cascade_views[index].proj).z;
     var avg_blocker_depth = 0.;
     var cnt = 0;
     for (var iteration = 0u; iteration < config.samples; iteration += 1u) {
@@ -59,10 +87,10 @@ fn dir_pcss_filtering(position_vs: vec4f, position_ws: vec3f, cascade: u32, radi
 #else // SHADOW_MAP_SAMPLE_RANDOMIZE
         let view = position_vs + vec4f(sample * radius, 0., 0.);
 #endif // SHADOW_MAP_SAMPLE_RANDOMIZE
-        var offseted = math::view_to_uv_and_depth(view.xyz, cascade_views[cascade].proj);
+        var offseted = math::view_to_uv_and_depth(view.xyz, cascade_views[index].proj);
 
         if (offseted.x > 0. && offseted.x < 1. && offseted.y > 0. && offseted.y < 1.) {
-            let shadow_depth = textureSample(directional_shadow_map, shadow_texture_sampler, offseted.xy, cascade);
+            let shadow_depth = textureSample(directional_shadow_map, shadow_texture_sampler, cascade_uv(offseted.xy, cascade), index);
             if (frag_depth - CONSTANT_BIAS > shadow_depth) {
                 avg_blocker_depth += shadow_depth;
                 cnt += 1;
@@ -73,40 +101,98 @@ fn dir_pcss_filtering(position_vs: vec4f, position_ws: vec3f, cascade: u32, radi
 
     let penumbra = max(frag_depth - avg_blocker_depth, 0.) / frag_depth * light_width;
 
-    return dir_pcf_filtering(position_vs, position_ws, cascade, penumbra);
+    return dir_pcf_filtering(position_vs, position_ws, index, cascade, penumbra);
 }
 
-fn dir_no_filtering(uv: vec2f, depth: f32, cascade: u32) -> f32 {
+fn dir_no_filtering(uv: vec2f, depth: f32, index: u32, cascade: u32) -> f32 {
     let frag_depth = saturate(depth) - CONSTANT_BIAS;
-    return textureSampleCompare(directional_shadow_map, shadow_map_sampler, uv, cascade, frag_depth);
+    return textureSampleCompare(directional_shadow_map, shadow_map_sampler, cascade_uv(uv, cascade), index, frag_depth);
+}
+
+// Weight of the next cascade, ramping up over the last `cascade_blend_fraction` of `cascade`.
+fn cascade_blend_weight(light: u32, cascade: u32, depth: f32) -> f32 {
+    // The last cascade has nothing to blend into.
+    if cascade + 1u >= #SHADOW_CASCADES || config.cascade_blend_fraction <= 0. {
+        return 0.;
+    }
+
+    let index = light * #SHADOW_CASCADES + cascade;
+    // Slices are contiguous, so the near plane of the next one is the far plane of this one.
+    let near = abs(cascade_views[index].exposure);
+    let far = abs(cascade_views[index + 1u].exposure);
+    let t = (depth - near) / max(far - near, 0.0001);
+    return saturate((t - 1. + config.cascade_blend_fraction) / config.cascade_blend_fraction);
+}
+
+fn sample_cascade(light: u32, cascade: u32, position_ws: vec3f, normal_ws: vec3f, light_width: f32) -> f32 {
+    let index = light * #SHADOW_CASCADES + cascade;
+#ifdef NORMAL_OFFSET
+    // Farther cascades have larger texels, so they need a larger offset.
+    let receiver_ws = position_ws + normal_ws * config.normal_offset_scale * cascade_texel_size(index, cascade);
+#else // NORMAL_OFFSET
+    let receiver_ws = position_ws;
+#endif // NORMAL_OFFSET
+    let position_vs = cascade_views[index].view * vec4f(receiver_ws, 1.);
+    let uv_and_depth = math::view_to_uv_and_depth(position_vs.xyz, cascade_views[index].proj);
+
+    if (uv_and_depth.x > 0. && uv_and_depth.x < 1. && uv_and_depth.y > 0. && uv_and_depth.y < 1.) {
+        #ifdef PCF
+            return dir_pcf_filtering(position_vs, receiver_ws, index, cascade, config.dir_pcf_radius);
+        #else ifdef PCSS
+            return dir_pcss_filtering(position_vs, receiver_ws, index, cascade, config.dir_pcss_radius, light_width);
+        #else
+            return dir_no_filtering(uv_and_depth.xy, uv_and_depth.z, index, cascade);
+        #endif
+    } else {
+        return 1.;
+    }
 }
 
-fn sample_cascaded_shadow_map(light: u32, position_ws: vec3f, position_vs: vec4f, light_width: f32) -> f32 {
+// `normal_ws` offsets the lookup with `NORMAL_OFFSET`, zero for positions off surfaces.
+fn sample_cascaded_shadow_map(light: u32, position_ws: vec3f, normal_ws: vec3f, position_vs: vec4f, light_width: f32) -> f32 {
     for (var cascade = #SHADOW_CASCADES - 1u; cascade >= 0u; cascade -= 1u) {
         let index = light * #SHADOW_CASCADES + cascade;
         // SPECIAL USE CASE FOR exposure FIELD!!
         // exposure = near plane of this camera.
         // If this point is inside this frustum slice.
+        if abs(position_vs.z) > abs(cascade_views[index].exposure) {
+            let shadow = sample_cascade(light, cascade, position_ws, normal_ws, light_width);
+            let blend = cascade_blend_weight(light, cascade, abs(position_vs.z));
+            if blend > 0. {
+                return mix(shadow, sample_cascade(light, cascade + 1u, position_ws, normal_ws, light_width), blend);
+            }
+            return shadow;
+        }
+    }
+
+    return 1.;
+}
+
+#ifdef TRANSLUCENT_SHADOWS
+fn sample_cascaded_transmittance(light: u32, position_ws: vec3f, position_vs: vec4f) -> vec3f {
+    for (var cascade = #SHADOW_CASCADES - 1u; cascade >= 0u; cascade -= 1u) {
+        let index = light * #SHADOW_CASCADES + cascade;
         if abs(position_vs.z) > abs(cascade_views[index].exposure) {
             let position_vs = cascade_views[index].view * vec4f(position_ws, 1.);
             let uv_and_depth = math::view_to_uv_and_depth(position_vs.xyz, cascade_views[index].proj);
 
             if (uv_and_depth.x > 0. && uv_and_depth.x < 1. && uv_and_depth.y > 0. && uv_and_depth.y < 1.) {
-                #ifdef PCF
-                    return dir_pcf_filtering(position_vs, position_ws, cascade, config.dir_pcf_radius);
-                #else ifdef PCSS
-                    return dir_pcss_filtering(position_vs, position_ws, cascade, config.dir_pcss_radius, light_width);
-                #else
-                    return dir_no_filtering(uv_and_depth.xy, uv_and_depth.z, cascade);
-                #endif
-            } else {
-                return 1.;
+                // Loaded rather than filtered, blending depths of neighbouring casters is meaningless.
+                let size = vec2f(textureDimensions(directional_transmittance_map));
+                let texel = vec2i(cascade_uv(uv_and_depth.xy, cascade) * size);
+                let transmittance = textureLoad(directional_transmittance_map, texel, index, 0);
+
+                if saturate(uv_and_depth.z) - CONSTANT_BIAS > transmittance.a {
+                    return transmittance.rgb;
+                }
             }
+            return vec3f(1.);
         }
     }
 
-    return 1.;
+    return vec3f(1.);
 }
+#endif // TRANSLUCENT_SHADOWS
 
 fn debug_cascade_color(light: u32, position_vs: vec4f) -> vec3f {
     var CASCADE_COLORS = array<vec3f, 6>(
@@ -124,7 +210,8 @@ fn debug_cascade_color(light: u32, position_vs: vec4f) -> vec3f {
         // exposure = near plane of this camera.
         // If this point is inside this frustum slice.
         if abs(position_vs.z) > abs(cascade_views[index].exposure) {
-            return CASCADE_COLORS[cascade % 6];
+            let blend = cascade_blend_weight(light, cascade, abs(position_vs.z));
+            return mix(CASCADE_COLORS[cascade % 6], CASCADE_COLORS[(cascade + 1u) % 6], blend);
         }
     }
 
@@ -163,13 +250,28 @@ fn point_no_filtering(relative_pos: vec3f, frag_depth: f32, light: u32) -> f32 {
     return textureSampleCompare(point_shadow_map, shadow_map_sampler, relative_pos, light, frag_depth - CONSTANT_BIAS);
 }
 
-fn sample_point_shadow_map(light: u32, relative_pos: vec3f, light_width: f32) -> f32 {
+// `surface_to_light` is relative to the light position, see `sample_cascaded_shadow_map` for `normal_ws`.
+// `light_index` counts point lights, then spot lights.
+fn sample_point_shadow_map(light_index: u32, surface_to_light: vec3f, normal_ws: vec3f, light_width: f32) -> f32 {
+    let light = point_shadow_layers[light_index];
+    if light == NO_SHADOW_LAYER {
+        return 1.;
+    }
+
+    var relative_pos = surface_to_light;
+#ifdef NORMAL_OFFSET
+    // Each face spans 90 degrees, so texels grow with the distance along the major axis.
+    let distance = max(abs(relative_pos).x, max(abs(relative_pos).y, abs(relative_pos).z));
+    let texel_size = 2. * distance / f32(config.point_map_resolution);
+    relative_pos -= normal_ws * config.normal_offset_scale * texel_size;
+#endif // NORMAL_OFFSET
+
     // Find the axis with largest absolute value.
     let abs_pos = abs(relative_pos);
     let frag_depth = -max(abs_pos.x, max(abs_pos.y, abs_pos.z));
 
-    // Do a simple projection.
-    let proj = point_light_views[light].proj;
+    // Do a simple projection, every face shares the same one.
+    let proj = point_light_views[light * 6u].proj;
     let v = vec2f(frag_depth * proj[2][2] + proj[3][2], -frag_depth);
     let projected_depth = v.x / v.y;
 
@@ -1,27 +1,28 @@
 #define_import_path aurora::shadow_render
 #import aurora::{
     common_binding::camera,
-    common_type::VertexInput,
-    math,
+    common_type::{Model, PositionInput},
     shadow_type::ShadowMappingConfig,
 }
 
 @group(0) @binding(1) var<uniform> config: ShadowMappingConfig;
+@group(1) @binding(0) var<uniform> mesh_model: Model;
 
 @vertex
-fn vertex(in: VertexInput) -> @builtin(position) vec4f {
-#ifdef NORMAL_OFFSET
-    var offset = 0.;
-    if (camera.proj[3][3] == 1.) {
-        offset = math::sin_between(camera.position, in.normal) * (204.8 / f32(config.dir_map_resolution));
-    } else {
-        offset = math::sin_between(camera.position - in.position, in.normal) * (12.8 / f32(config.point_map_resolution));
-    }
-    return camera.proj * camera.view * vec4f(in.position - offset * in.normal, 1.);
-#else // NORMAL_OFFSET
-    return camera.proj * camera.view * vec4f(in.position, 1.);
-#endif // NORMAL_OFFSET
+fn vertex(in: PositionInput) -> @builtin(position) vec4f {
+    // Normal offsets move the receivers instead, see `shadow_mapping.wgsl`.
+    return camera.proj * camera.view * mesh_model.model * vec4f(in.position, 1.);
 }
 
 @fragment
 fn fragment() { }
+
+#ifdef TRANSLUCENT_SHADOWS
+@group(2) @binding(0) var<uniform> transmittance: vec4f;
+
+// Color is multiplied into the target, alpha keeps the depth of the nearest caster.
+@fragment
+fn translucent_fragment(@builtin(position) position: vec4f) -> @location(0) vec4f {
+    return vec4f(transmittance.rgb, position.z);
+}
+#endif // TRANSLUCENT_SHADOWS
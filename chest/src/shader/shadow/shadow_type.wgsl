@@ -8,4 +8,6 @@ struct ShadowMappingConfig {
     dir_pcss_radius: f32,
     point_pcf_radius: f32,
     point_pcss_radius: f32,
+    normal_offset_scale: f32,
+    cascade_blend_fraction: f32,
 }
@@ -0,0 +1,59 @@
+struct SkinningLayout {
+    // In 4 byte words, offsets are `NONE` for missing attributes.
+    stride: u32,
+    normal_offset: u32,
+    tangent_offset: u32,
+    vertices: u32,
+}
+
+struct JointInfluence {
+    joints: vec4u,
+    weights: vec4f,
+}
+
+const NONE: u32 = 0xFFFFFFFFu;
+
+@group(0) @binding(0) var<storage> bind_pose: array<f32>;
+@group(0) @binding(1) var<storage, read_write> skinned: array<f32>;
+@group(0) @binding(2) var<storage> influences: array<JointInfluence>;
+@group(0) @binding(3) var<storage> joint_matrices: array<mat4x4f>;
+@group(0) @binding(4) var<uniform> vertex_layout: SkinningLayout;
+
+fn load_vec3(offset: u32) -> vec3f {
+    return vec3f(bind_pose[offset], bind_pose[offset + 1u], bind_pose[offset + 2u]);
+}
+
+fn store_vec3(offset: u32, value: vec3f) {
+    skinned[offset] = value.x;
+    skinned[offset + 1u] = value.y;
+    skinned[offset + 2u] = value.z;
+}
+
+@compute @workgroup_size(64, 1, 1)
+fn skin(@builtin(global_invocation_id) id: vec3u) {
+    if id.x >= vertex_layout.vertices {
+        return;
+    }
+
+    let influence = influences[id.x];
+    let skin = joint_matrices[influence.joints.x] * influence.weights.x
+        + joint_matrices[influence.joints.y] * influence.weights.y
+        + joint_matrices[influence.joints.z] * influence.weights.z
+        + joint_matrices[influence.joints.w] * influence.weights.w;
+
+    // Position always comes first, see `Mesh::POSITION_ATTR`.
+    let base = id.x * vertex_layout.stride;
+    store_vec3(base, (skin * vec4f(load_vec3(base), 1.)).xyz);
+
+    // Assumes joints scale uniformly, otherwise this would need the inverse transpose.
+    if vertex_layout.normal_offset != NONE {
+        let offset = base + vertex_layout.normal_offset;
+        store_vec3(offset, normalize((skin * vec4f(load_vec3(offset), 0.)).xyz));
+    }
+
+    // The handedness in w is left as is.
+    if vertex_layout.tangent_offset != NONE {
+        let offset = base + vertex_layout.tangent_offset;
+        store_vec3(offset, normalize((skin * vec4f(load_vec3(offset), 0.)).xyz));
+    }
+}
@@ -0,0 +1,46 @@
+#import aurora::{common_binding, common_binding::camera, common_type::Model}
+
+struct UnlitMaterial {
+    base_color: vec3f,
+}
+
+@group(1) @binding(0) var<uniform> material: UnlitMaterial;
+@group(1) @binding(1) var tex_base_color: texture_2d<f32>;
+@group(1) @binding(2) var tex_sampler: sampler;
+
+@group(2) @binding(0) var<uniform> mesh_model: Model;
+
+struct UnlitVertexInput {
+    @location(0) position: vec3f,
+    @location(2) uv: vec2f,
+}
+
+struct UnlitVertexOutput {
+    @builtin(position) position_cs: vec4f,
+    @location(0) uv: vec2f,
+}
+
+@vertex
+fn vertex(
+    in: UnlitVertexInput,
+#ifdef MULTIVIEW
+    @builtin(view_index) view_index: i32,
+#endif // MULTIVIEW
+) -> UnlitVertexOutput {
+#ifdef MULTIVIEW
+    let camera = common_binding::eyes[view_index];
+#endif // MULTIVIEW
+    var output: UnlitVertexOutput;
+    let position = (mesh_model.model * vec4f(in.position, 1.)).xyz;
+    // Same order as the depth prepass, so opaque unlit meshes pass its depth test.
+    output.position_cs = camera.proj * (camera.view * vec4f(position, 1.));
+    output.uv = in.uv;
+    return output;
+}
+
+@fragment
+fn fragment(in: UnlitVertexOutput) -> @location(0) vec4f {
+    // Neither lit nor exposed.
+    let color = material.base_color * textureSample(tex_base_color, tex_sampler, in.uv).rgb;
+    return vec4f(color, 1.);
+}
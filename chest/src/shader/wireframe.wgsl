@@ -0,0 +1,49 @@
+#import aurora::{common_binding::camera, common_type::{Model, VertexInput}}
+
+struct Wireframe {
+    color: vec4f,
+    fill: vec4f,
+    // Fraction of the distance to the camera lines are pulled closer by, so they aren't
+    // hidden by the faces they border.
+    depth_offset: f32,
+}
+
+@group(1) @binding(0) var<uniform> config: Wireframe;
+@group(2) @binding(0) var<uniform> mesh_model: Model;
+
+@vertex
+fn vertex(in: VertexInput) -> @builtin(position) vec4f {
+    var position_vs = camera.view * mesh_model.model * vec4f(in.position, 1.0);
+    // Along the view ray, so lines don't move on screen.
+    let orthographic = camera.proj[3][3] == 1.0;
+    let ray = select(position_vs.xyz, vec3f(0.0, 0.0, position_vs.z), orthographic);
+    position_vs = vec4f(position_vs.xyz - ray * config.depth_offset, 1.0);
+    return camera.proj * position_vs;
+}
+
+@fragment
+fn fragment() -> @location(0) vec4f {
+    return config.color;
+}
+
+struct FillVertexOutput {
+    @builtin(position) position_cs: vec4f,
+    @location(0) position_ws: vec3f,
+}
+
+@vertex
+fn fill_vertex(in: VertexInput) -> FillVertexOutput {
+    var output: FillVertexOutput;
+    output.position_ws = (mesh_model.model * vec4f(in.position, 1.0)).xyz;
+    output.position_cs = camera.proj * camera.view * vec4f(output.position_ws, 1.0);
+    return output;
+}
+
+// Faceted, lit from the camera, so every face reads clearly whatever the lights are.
+@fragment
+fn fill_fragment(in: FillVertexOutput) -> @location(0) vec4f {
+    let normal = normalize(cross(dpdx(in.position_ws), dpdy(in.position_ws)));
+    let view = normalize(camera.position - in.position_ws);
+    let shade = 0.25 + 0.75 * abs(dot(normal, view));
+    return vec4f(config.fill.rgb * shade, config.fill.a);
+}
@@ -1,6 +1,11 @@
 use std::{io::Cursor, path::Path};
 
+use aurora_core::render::resource::{Image, ImageTextureDescriptor};
 use ddsfile::{Dds, DxgiFormat};
+use glam::Vec3;
+use half::f16;
+use image::ImageFormat;
+use thiserror::Error;
 use wgpu::{
     util::{DeviceExt, TextureDataOrder},
     Device, Extent3d, Queue, Texture, TextureDescriptor, TextureDimension, TextureFormat,
@@ -37,3 +42,216 @@ pub fn load_dds_texture(device: &Device, queue: &Queue, path: impl AsRef<Path>)
         dds_data,
     )
 }
+
+/// Load an `.hdr` cube map laid out as a horizontal cross.
+///
+/// Only mip 0 is filled, further mips are left to render into and clamped to the face size.
+pub fn load_hdr_cube_map(
+    device: &Device,
+    queue: &Queue,
+    path: impl AsRef<Path>,
+    mip_level_count: u32,
+) -> Texture {
+    let image = Image::from_buffer(
+        &std::fs::read(path).unwrap(),
+        ImageFormat::Hdr,
+        false,
+        Some(device.limits().max_texture_dimension_2d),
+    );
+    let mip_level_count = mip_level_count.clamp(1, (image.width() / 4).ilog2() + 1);
+    image.to_cube_map(
+        device,
+        queue,
+        &ImageTextureDescriptor {
+            mip_level_count: Some(mip_level_count),
+            usage: (mip_level_count > 1).then_some(TextureUsages::RENDER_ATTACHMENT),
+            ..Default::default()
+        },
+    )
+}
+
+#[derive(Error, Debug)]
+pub enum CubeLutError {
+    #[error("{0}")]
+    IoError(#[from] std::io::Error),
+    #[error("Missing LUT_3D_SIZE.")]
+    MissingSize,
+    #[error("1D LUTs are unsupported.")]
+    Unsupported1d,
+    #[error("Invalid line {line}: {content}")]
+    InvalidLine { line: usize, content: String },
+    #[error("Expected {expected} entries, found {found}.")]
+    EntryCount { expected: usize, found: usize },
+}
+
+/// A 3D LUT in the Adobe `.cube` format.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CubeLut {
+    pub size: u32,
+    /// Input mapped to the first and last entries along each axis.
+    pub domain_min: Vec3,
+    pub domain_max: Vec3,
+    /// Red changes fastest, then green, then blue.
+    pub data: Vec<Vec3>,
+}
+
+impl CubeLut {
+    pub fn identity(size: u32) -> Self {
+        let step = 1. / (size - 1) as f32;
+        Self {
+            size,
+            domain_min: Vec3::ZERO,
+            domain_max: Vec3::ONE,
+            data: (0..size.pow(3))
+                .map(|i| {
+                    Vec3::new(
+                        (i % size) as f32,
+                        (i / size % size) as f32,
+                        (i / size / size) as f32,
+                    ) * step
+                })
+                .collect(),
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, CubeLutError> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn parse(source: &str) -> Result<Self, CubeLutError> {
+        let mut size = None;
+        let mut domain_min = Vec3::ZERO;
+        let mut domain_max = Vec3::ONE;
+        let mut data = Vec::new();
+
+        for (index, line) in source.lines().enumerate() {
+            let invalid = || CubeLutError::InvalidLine {
+                line: index + 1,
+                content: line.to_string(),
+            };
+            let floats = |values: &[&str]| {
+                values
+                    .iter()
+                    .map(|v| v.parse::<f32>().map_err(|_| invalid()))
+                    .collect::<Result<Vec<_>, _>>()
+            };
+            let vec3 = |values: &[&str]| match floats(values)?.as_slice() {
+                [x, y, z] => Ok(Vec3::new(*x, *y, *z)),
+                _ => Err(invalid()),
+            };
+
+            let tokens = line.split_whitespace().collect::<Vec<_>>();
+            match tokens.as_slice() {
+                [] => {}
+                [comment, ..] if comment.starts_with('#') => {}
+                ["TITLE", ..] => {}
+                ["LUT_1D_SIZE", ..] => return Err(CubeLutError::Unsupported1d),
+                ["LUT_3D_SIZE", value] => {
+                    size = Some(
+                        value
+                            .parse::<u32>()
+                            .ok()
+                            .filter(|s| *s >= 2)
+                            .ok_or_else(invalid)?,
+                    );
+                }
+                ["DOMAIN_MIN", values @ ..] => domain_min = vec3(values)?,
+                ["DOMAIN_MAX", values @ ..] => domain_max = vec3(values)?,
+                // Resolve's extension, the same range for all channels.
+                ["LUT_3D_INPUT_RANGE", values @ ..] => match floats(values)?.as_slice() {
+                    [min, max] => {
+                        domain_min = Vec3::splat(*min);
+                        domain_max = Vec3::splat(*max);
+                    }
+                    _ => return Err(invalid()),
+                },
+                values => data.push(vec3(values)?),
+            }
+        }
+
+        let size = size.ok_or(CubeLutError::MissingSize)?;
+        let expected = size.pow(3) as usize;
+        if data.len() != expected {
+            return Err(CubeLutError::EntryCount {
+                expected,
+                found: data.len(),
+            });
+        }
+
+        Ok(Self {
+            size,
+            domain_min,
+            domain_max,
+            data,
+        })
+    }
+
+    pub fn create_texture(&self, device: &Device, queue: &Queue) -> Texture {
+        let data = self
+            .data
+            .iter()
+            .flat_map(|c| c.extend(1.).to_array())
+            .map(f16::from_f32)
+            .collect::<Vec<_>>();
+
+        device.create_texture_with_data(
+            queue,
+            &TextureDescriptor {
+                label: Some("cube_lut"),
+                size: Extent3d {
+                    width: self.size,
+                    height: self.size,
+                    depth_or_array_layers: self.size,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D3,
+                format: TextureFormat::Rgba16Float,
+                usage: TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            },
+            TextureDataOrder::MipMajor,
+            bytemuck::cast_slice(&data),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Vec3;
+
+    use super::{CubeLut, CubeLutError};
+
+    #[test]
+    fn parse_cube_lut() {
+        let lut = CubeLut::parse(
+            "# Comment\n\
+             TITLE \"Test\"\n\
+             LUT_3D_SIZE 2\n\
+             DOMAIN_MIN 0 0 0\n\
+             DOMAIN_MAX 1 1 2\n\
+             \n\
+             0 0 0\n1 0 0\n0 1 0\n1 1 0\n0 0 1\n1 0 1\n0 1 1\n1 1 1\n",
+        )
+        .unwrap();
+        assert_eq!(lut.size, 2);
+        assert_eq!(lut.domain_max, Vec3::new(1., 1., 2.));
+        assert_eq!(lut.data, CubeLut::identity(2).data);
+
+        assert!(matches!(
+            CubeLut::parse("LUT_3D_SIZE 3\n0 0 0\n"),
+            Err(CubeLutError::EntryCount {
+                expected: 27,
+                found: 1
+            })
+        ));
+        assert!(matches!(
+            CubeLut::parse("LUT_3D_SIZE 2\n0 0\n"),
+            Err(CubeLutError::InvalidLine { line: 2, .. })
+        ));
+        assert!(matches!(
+            CubeLut::parse("0 0 0\n"),
+            Err(CubeLutError::MissingSize)
+        ));
+    }
+}
@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+
+use aurora_core::render::helper::{
+    AsymmetricPerspectiveProjection, CameraProjection, OrthographicProjection,
+    PerspectiveProjection,
+};
+use glam::{Mat4, Vec3, Vec4Swizzles};
+use naga_oil::compose::{
+    ComposableModuleDescriptor, Composer, ComposerError, NagaModuleDescriptor, ShaderDefValue,
+};
+use wgpu::naga::Module;
+
+pub mod upsample;
+
+pub fn build_shader<const N: usize>(
+    deps: [&str; N],
+    main: &str,
+    defs: impl Into<HashMap<String, ShaderDefValue>>,
+) -> Result<Module, ComposerError> {
+    let defs = defs.into();
+    let mut composer = Composer::default();
+    for dep in deps {
+        composer.add_composable_module(ComposableModuleDescriptor {
+            source: &dep,
+            shader_defs: defs.clone(),
+            ..Default::default()
+        })?;
+    }
+    composer.make_naga_module(NagaModuleDescriptor {
+        source: &main,
+        shader_defs: defs.clone(),
+        ..Default::default()
+    })
+}
+
+pub fn frustum_slice(proj: CameraProjection, count: u32, lambda: f32) -> Vec<CameraProjection> {
+    let (near, far) = near_far(proj);
+    frustum_slice_at(proj, &practical_splits(near, far, count, lambda))
+}
+
+/// Split distances of `count` slices from `near` to `far`, both included, blending
+/// logarithmic and uniform splits by `lambda`.
+fn practical_splits(near: f32, far: f32, count: u32, lambda: f32) -> Vec<f32> {
+    let r = (far / near).powf(1. / count as f32);
+    let d = far - near;
+    let mut split = near;
+
+    std::iter::once(near)
+        .chain((0..count).map(|x| {
+            let x = x as f32;
+            let d_log = near * r.powf(x);
+            let d_uni = near + d / count as f32 * (x + 1.);
+            split += lambda * d_log + (1. - lambda) * d_uni;
+            split
+        }))
+        .collect()
+}
+
+/// Logarithmic split distances of `count` slices from `near` to `far`, both included.
+pub fn logarithmic_splits(near: f32, far: f32, count: u32) -> Vec<f32> {
+    let ratio = far / near;
+    (0..=count)
+        .map(|x| near * ratio.powf(x as f32 / count as f32))
+        .collect()
+}
+
+/// Slice `proj` between each pair of consecutive view space distances in `splits`.
+pub fn frustum_slice_at(proj: CameraProjection, splits: &[f32]) -> Vec<CameraProjection> {
+    splits
+        .windows(2)
+        .map(|range| with_near_far(proj, range[0], range[1]))
+        .collect()
+}
+
+fn near_far(proj: CameraProjection) -> (f32, f32) {
+    match proj {
+        CameraProjection::Perspective(proj) => (proj.near, proj.far),
+        CameraProjection::Orthographic(proj) => (proj.near, proj.far),
+        CameraProjection::AsymmetricPerspective(proj) => (proj.near, proj.far),
+    }
+}
+
+fn with_near_far(proj: CameraProjection, near: f32, far: f32) -> CameraProjection {
+    match proj {
+        CameraProjection::Perspective(proj) => {
+            CameraProjection::Perspective(PerspectiveProjection { near, far, ..proj })
+        }
+        CameraProjection::Orthographic(proj) => {
+            CameraProjection::Orthographic(OrthographicProjection { near, far, ..proj })
+        }
+        CameraProjection::AsymmetricPerspective(proj) => {
+            CameraProjection::AsymmetricPerspective(AsymmetricPerspectiveProjection {
+                near,
+                far,
+                ..proj
+            })
+        }
+    }
+}
+
+pub fn calculate_frustum_corners(view_proj: Mat4) -> [Vec3; 8] {
+    let mut corners = [
+        // Near Plane
+        Vec3::new(1., 1., 0.),
+        Vec3::new(-1., 1., 0.),
+        Vec3::new(1., -1., 0.),
+        Vec3::new(-1., -1., 0.),
+        // Far Plane
+        Vec3::new(1., 1., 1.),
+        Vec3::new(-1., 1., 1.),
+        Vec3::new(1., -1., 1.),
+        Vec3::new(-1., -1., 1.),
+    ];
+
+    let mat = view_proj.inverse();
+    corners.iter_mut().for_each(|c| {
+        let clip = mat * c.extend(1.);
+        *c = clip.xyz() / clip.w;
+    });
+
+    corners
+}
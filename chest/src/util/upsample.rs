@@ -0,0 +1,231 @@
+use std::borrow::Cow;
+
+use wgpu::{
+    BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, ColorTargetState, ColorWrites,
+    CommandEncoder, Device, FragmentState, PipelineCache, PipelineLayoutDescriptor,
+    RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor,
+    ShaderModuleDescriptor, ShaderSource, ShaderStages, TextureFormat, TextureSampleType,
+    TextureView, TextureViewDimension, VertexState,
+};
+
+use crate::util::build_shader;
+
+/// Upsamples an effect rendered at a lower resolution, weighting the surrounding low res
+/// texels by how close their depth is to the full res pixel, so the effect doesn't bleed
+/// across depth edges.
+///
+/// Each low res texel is assumed to be rendered with the full res depth at its center.
+pub struct DepthAwareUpsample {
+    pub pipeline: RenderPipeline,
+    pub layout: BindGroupLayout,
+}
+
+impl DepthAwareUpsample {
+    /// `format` is the format of the textures upsampled into, `cache` is usually
+    /// [`NodeContext::pipeline_cache`].
+    ///
+    /// [`NodeContext::pipeline_cache`]: aurora_core::render::flow::NodeContext::pipeline_cache
+    pub fn new(device: &Device, format: TextureFormat, cache: Option<&PipelineCache>) -> Self {
+        let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("depth_aware_upsample_layout"),
+            entries: &[
+                // Input
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: false },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                // Depth
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: false },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let vertex = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("fullscreen_vertex_shader"),
+            source: ShaderSource::Naga(Cow::Owned(
+                build_shader([], include_str!("../shader/fullscreen.wgsl"), []).unwrap(),
+            )),
+        });
+        let fragment = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("depth_aware_upsample_shader"),
+            source: ShaderSource::Naga(Cow::Owned(
+                build_shader(
+                    [include_str!("../shader/fullscreen.wgsl")],
+                    include_str!("../shader/depth_aware_upsample.wgsl"),
+                    [],
+                )
+                .unwrap(),
+            )),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("depth_aware_upsample_pipeline_layout"),
+            bind_group_layouts: &[&layout],
+            ..Default::default()
+        });
+
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("depth_aware_upsample_pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                module: &vertex,
+                entry_point: "vertex",
+                compilation_options: Default::default(),
+                buffers: &[],
+            },
+            fragment: Some(FragmentState {
+                module: &fragment,
+                entry_point: "fragment",
+                compilation_options: Default::default(),
+                targets: &[Some(ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: Default::default(),
+            depth_stencil: Default::default(),
+            multisample: Default::default(),
+            multiview: Default::default(),
+            cache,
+        });
+
+        Self { pipeline, layout }
+    }
+
+    /// Record the upsampling of `input` into `output`, which must have the same size as
+    /// `depth`, a view space depth like [`LINEAR_DEPTH_TEXTURE`].
+    ///
+    /// [`LINEAR_DEPTH_TEXTURE`]: crate::node::LINEAR_DEPTH_TEXTURE
+    pub fn upsample(
+        &self,
+        device: &Device,
+        command_encoder: &mut CommandEncoder,
+        input: &TextureView,
+        depth: &TextureView,
+        output: &TextureView,
+    ) {
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("depth_aware_upsample_bind_group"),
+            layout: &self.layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(input),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(depth),
+                },
+            ],
+        });
+
+        let mut pass = command_encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("depth_aware_upsample_pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: output,
+                resolve_target: None,
+                ops: Default::default(),
+            })],
+            ..Default::default()
+        });
+
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aurora_core::util::{self, testing};
+    use glam::UVec3;
+    use wgpu::{
+        util::DeviceExt, Extent3d, TextureDescriptor, TextureDimension, TextureFormat,
+        TextureUsages,
+    };
+
+    use super::DepthAwareUpsample;
+
+    #[test]
+    fn upsample_keeps_depth_edges() {
+        let Some(renderer) = testing::renderer(None, None) else {
+            return;
+        };
+        let device = &renderer.device;
+        let queue = &renderer.queue;
+
+        // A depth edge down the middle, with the effect only present behind it.
+        let full = 8;
+        let low = full / 2;
+        let depth = (0..full * full)
+            .map(|i| if i % full < full / 2 { 1f32 } else { 10. })
+            .collect::<Vec<_>>();
+        let input = (0..low * low)
+            .flat_map(|i| if i % low < low / 2 { [0; 4] } else { [255; 4] })
+            .collect::<Vec<_>>();
+
+        let texture = |size: u32, format, data: &[u8]| {
+            device.create_texture_with_data(
+                queue,
+                &TextureDescriptor {
+                    label: None,
+                    size: Extent3d {
+                        width: size,
+                        height: size,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: TextureDimension::D2,
+                    format,
+                    usage: TextureUsages::TEXTURE_BINDING,
+                    view_formats: &[],
+                },
+                Default::default(),
+                data,
+            )
+        };
+        let depth = texture(full, TextureFormat::R32Float, bytemuck::cast_slice(&depth));
+        let input = texture(low, TextureFormat::Rgba8Unorm, &input);
+        let output = util::create_texture(
+            device,
+            UVec3::new(full, full, 1),
+            TextureFormat::Rgba8Unorm,
+            TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+        );
+
+        let upsample = DepthAwareUpsample::new(device, TextureFormat::Rgba8Unorm, None);
+        let mut command_encoder = device.create_command_encoder(&Default::default());
+        upsample.upsample(
+            device,
+            &mut command_encoder,
+            &input.create_view(&Default::default()),
+            &depth.create_view(&Default::default()),
+            &output.create_view(&Default::default()),
+        );
+        queue.submit([command_encoder.finish()]);
+
+        let image = pollster::block_on(util::read_color_texture(&output, device, queue));
+        for y in 0..full {
+            // Plain bilinear upsampling would blend a quarter of the other side in here.
+            assert_eq!(image.get_pixel(full / 2 - 1, y)[0], 0);
+            assert_eq!(image.get_pixel(full / 2, y)[0], 255);
+        }
+    }
+}
@@ -1,8 +1,20 @@
-use std::cell::RefCell;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 
+use glam::UVec2;
+use log::{info, warn};
+use thiserror::Error;
 use wgpu::{
-    Adapter, Device, DeviceDescriptor, Features, Instance, Limits, MemoryHints, Queue,
-    RequestAdapterOptions, Texture, TextureDescriptor, TextureView,
+    Adapter, AdapterInfo, Backends, Device, DeviceDescriptor, DownlevelFlags, Extent3d, Features,
+    Instance, InstanceDescriptor, Limits, MemoryHints, PipelineCache, PipelineCacheDescriptor,
+    PowerPreference, Queue, RequestAdapterOptions, RequestDeviceError, Texture, TextureDescriptor,
+    TextureDimension, TextureFormat, TextureFormatFeatureFlags, TextureUsages, TextureView,
 };
 
 pub mod render;
@@ -13,35 +25,219 @@ pub struct WgpuRenderer {
     pub adapter: Adapter,
     pub device: Device,
     pub queue: Queue,
+    /// Shared by the pipelines of every node, see [`WgpuRenderer::load_pipeline_cache`].
+    pub pipeline_cache: Option<PersistentPipelineCache>,
+}
+
+/// [`PipelineCache`] written back to disk when dropped.
+pub struct PersistentPipelineCache {
+    pub cache: Arc<PipelineCache>,
+    pub path: PathBuf,
+}
+
+impl PersistentPipelineCache {
+    pub fn save(&self) -> io::Result<()> {
+        let Some(data) = self.cache.get_data() else {
+            return Ok(());
+        };
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&self.path, data)
+    }
+}
+
+impl Drop for PersistentPipelineCache {
+    fn drop(&mut self) {
+        if let Err(err) = self.save() {
+            warn!("Failed to save pipeline cache to {:?}: {err}", self.path);
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum RendererError {
+    #[error("No suitable adapter found.")]
+    NoAdapter,
+    #[error("{0}")]
+    DeviceRequest(#[from] RequestDeviceError),
+    #[error("Features {0:?} are not supported by the adapter.")]
+    UnsupportedFeatures(Features),
+    #[error("{node} requires features {missing:?}, which the device doesn't have.")]
+    NodeMissingFeatures {
+        node: &'static str,
+        missing: Features,
+    },
+    #[error("{node} requires {limit} of {required}, but the device only allows {allowed}.")]
+    NodeLimitExceeded {
+        node: &'static str,
+        limit: &'static str,
+        required: u64,
+        allowed: u64,
+    },
+}
+
+/// Controls which backend and adapter the renderer picks.
+#[derive(Debug, Clone, Copy)]
+pub struct RendererConfig {
+    pub backends: Backends,
+    /// Use [`PowerPreference::LowPower`] for the integrated GPU and
+    /// [`PowerPreference::HighPerformance`] for the discrete one on dual GPU machines.
+    pub power_preference: PowerPreference,
+    pub force_fallback_adapter: bool,
+    /// Features enabled only if the adapter supports them, unlike the required ones.
+    pub optional_features: Features,
+}
+
+impl Default for RendererConfig {
+    fn default() -> Self {
+        Self {
+            backends: Backends::all(),
+            power_preference: PowerPreference::default(),
+            force_fallback_adapter: false,
+            optional_features: Features::empty(),
+        }
+    }
 }
 
 impl WgpuRenderer {
-    pub async fn new(required_features: Option<Features>, required_limits: Option<Limits>) -> Self {
-        let instance = Instance::default();
+    pub async fn new(
+        required_features: Option<Features>,
+        required_limits: Option<Limits>,
+    ) -> Result<Self, RendererError> {
+        Self::with_config(Default::default(), required_features, required_limits).await
+    }
+
+    pub async fn with_config(
+        config: RendererConfig,
+        required_features: Option<Features>,
+        required_limits: Option<Limits>,
+    ) -> Result<Self, RendererError> {
+        let instance = Instance::new(InstanceDescriptor {
+            backends: config.backends,
+            ..Default::default()
+        });
         let adapter = instance
-            .request_adapter(&RequestAdapterOptions::default())
+            .request_adapter(&RequestAdapterOptions {
+                power_preference: config.power_preference,
+                force_fallback_adapter: config.force_fallback_adapter,
+                compatible_surface: None,
+            })
             .await
-            .unwrap();
+            .ok_or(RendererError::NoAdapter)?;
+        info!("Using adapter {:?}", adapter.get_info());
+
+        let required_features = required_features.unwrap_or_default();
+        let missing_features = required_features - adapter.features();
+        if !missing_features.is_empty() {
+            return Err(RendererError::UnsupportedFeatures(missing_features));
+        }
+        let required_features = required_features | (config.optional_features & adapter.features());
+
         let (device, queue) = adapter
             .request_device(
                 &DeviceDescriptor {
                     label: None,
-                    required_features: required_features.unwrap_or_default(),
+                    required_features,
                     required_limits: required_limits.unwrap_or_default(),
                     memory_hints: MemoryHints::Performance,
                 },
                 None,
             )
-            .await
-            .unwrap();
+            .await?;
 
-        Self {
+        Ok(Self {
             instance,
             adapter,
             device,
             queue,
+            pipeline_cache: None,
+        })
+    }
+
+    /// Load the pipeline cache of this adapter from `dir`, saving it back there on drop.
+    ///
+    /// `false` without [`Features::PIPELINE_CACHE`]. Only flows built afterwards use it.
+    pub fn load_pipeline_cache(&mut self, dir: impl AsRef<Path>) -> bool {
+        if !self.device.features().contains(Features::PIPELINE_CACHE) {
+            return false;
         }
+        // Distinct for each GPU, the data of one is useless to another.
+        let Some(key) = wgpu::util::pipeline_cache_key(&self.adapter.get_info()) else {
+            return false;
+        };
+
+        let path = dir.as_ref().join(key);
+        let data = fs::read(&path).ok();
+        // SAFETY: The file is only ever written by `PersistentPipelineCache::save` with data
+        // from `PipelineCache::get_data`, for an adapter with the same key. Data from an
+        // outdated driver is rejected, falling back to an empty cache.
+        let cache = unsafe {
+            self.device.create_pipeline_cache(&PipelineCacheDescriptor {
+                label: Some("pipeline_cache"),
+                data: data.as_deref(),
+                fallback: true,
+            })
+        };
+        info!(
+            "Loaded pipeline cache {path:?}, {}",
+            if data.is_some() { "warm" } else { "cold" }
+        );
+        self.pipeline_cache = Some(PersistentPipelineCache {
+            cache: Arc::new(cache),
+            path,
+        });
+        true
     }
+
+    pub fn adapter_info(&self) -> AdapterInfo {
+        self.adapter.get_info()
+    }
+
+    /// Limits the device was actually created with.
+    pub fn limits(&self) -> Limits {
+        self.device.limits()
+    }
+
+    /// Features supported by the adapter, not only the ones enabled on the device.
+    pub fn supported_features(&self) -> Features {
+        self.adapter.features()
+    }
+
+    /// Highest `anisotropy_clamp` the adapter filters with, 16 as in WebGPU or 1 without
+    /// anisotropic filtering.
+    pub fn max_anisotropy(&self) -> u16 {
+        let flags = self.adapter.get_downlevel_capabilities().flags;
+        if flags.contains(DownlevelFlags::ANISOTROPIC_FILTERING) {
+            16
+        } else {
+            1
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum SwapChainError {
+    #[error("Format {0:?} is not filterable.")]
+    NotFilterable(TextureFormat),
+    #[error("Format {0:?} can't be used as render attachment.")]
+    NotRenderable(TextureFormat),
+    #[error(
+        "Usages {0:?} are missing, post process nodes need to sample and render to the swap chain."
+    )]
+    MissingUsages(TextureUsages),
+}
+
+/// Describes the ping-pong textures of a [`SwapChain`].
+#[derive(Debug, Clone, Copy)]
+pub struct SwapChainConfig {
+    /// Usually a HDR format like [`TextureFormat::Rgba16Float`].
+    pub format: TextureFormat,
+    /// Must contain [`SwapChain::REQUIRED_USAGES`], add [`TextureUsages::COPY_SRC`]
+    /// for nodes copying the frame like TAA, or [`TextureUsages::STORAGE_BINDING`]
+    /// for compute based ones.
+    pub usage: TextureUsages,
+    pub size: UVec2,
 }
 
 pub struct PostProcess<'a> {
@@ -50,7 +246,7 @@ pub struct PostProcess<'a> {
 }
 
 pub struct SwapChain {
-    main_texture: RefCell<bool>,
+    main_texture: AtomicBool,
     main_texture_a: Texture,
     main_view_a: TextureView,
     main_texture_b: Texture,
@@ -59,6 +255,58 @@ pub struct SwapChain {
 }
 
 impl SwapChain {
+    /// Post process nodes sample the previous texture and render to the next one.
+    pub const REQUIRED_USAGES: TextureUsages =
+        TextureUsages::TEXTURE_BINDING.union(TextureUsages::RENDER_ATTACHMENT);
+
+    /// Create a swap chain, checking the format and usages work for post processing.
+    pub fn from_config(device: &Device, config: &SwapChainConfig) -> Result<Self, SwapChainError> {
+        let SwapChainConfig {
+            format,
+            usage,
+            size,
+        } = *config;
+
+        let missing_usages = Self::REQUIRED_USAGES - usage;
+        if !missing_usages.is_empty() {
+            return Err(SwapChainError::MissingUsages(missing_usages));
+        }
+
+        let features = format.guaranteed_format_features(device.features());
+        if !features
+            .flags
+            .contains(TextureFormatFeatureFlags::FILTERABLE)
+        {
+            return Err(SwapChainError::NotFilterable(format));
+        }
+        if !features
+            .allowed_usages
+            .contains(TextureUsages::RENDER_ATTACHMENT)
+        {
+            return Err(SwapChainError::NotRenderable(format));
+        }
+
+        Ok(Self::new(
+            device,
+            &TextureDescriptor {
+                label: Some("post_process_chain"),
+                size: Extent3d {
+                    width: size.x,
+                    height: size.y,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format,
+                usage,
+                view_formats: &[],
+            },
+        ))
+    }
+
+    /// Create a swap chain from `desc` as is, like with a layer per eye for
+    /// [stereo flows](crate::render::flow::RenderFlow::set_stereo).
     pub fn new(device: &Device, desc: &TextureDescriptor<'static>) -> Self {
         let a = device.create_texture(&TextureDescriptor {
             label: Some("swap_chain_texture_a"),
@@ -70,7 +318,7 @@ impl SwapChain {
         });
 
         Self {
-            main_texture: RefCell::new(false),
+            main_texture: AtomicBool::new(false),
             main_view_a: a.create_view(&Default::default()),
             main_texture_a: a,
             main_view_b: b.create_view(&Default::default()),
@@ -86,16 +334,30 @@ impl SwapChain {
         std::mem::swap(self, &mut Self::new(device, &self.desc));
     }
 
+    /// Recreate both textures at `new_size`, keeping the format, usages and layers.
+    ///
+    /// On `WindowEvent::Resized`, reconfigure the surface and depth texture first,
+    /// then resize the swap chain and use the same size for `RenderTargets::size` of the
+    /// next frame.
+    pub fn resize(&mut self, device: &Device, new_size: UVec2) {
+        self.desc.size = Extent3d {
+            width: new_size.x,
+            height: new_size.y,
+            ..self.desc.size
+        };
+        self.clear(device);
+    }
+
     pub fn desc(&self) -> &TextureDescriptor {
         &self.desc
     }
 
     pub fn swap(&self) {
-        self.main_texture.replace(!self.main_texture());
+        self.main_texture.fetch_xor(true, Ordering::Relaxed);
     }
 
     pub fn main_texture(&self) -> bool {
-        *self.main_texture.borrow()
+        self.main_texture.load(Ordering::Relaxed)
     }
 
     pub fn current_texture(&self) -> &Texture {
@@ -138,3 +400,95 @@ impl SwapChain {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+    use wgpu::{
+        util::pipeline_cache_key, Features, PipelineLayoutDescriptor, RenderPipelineDescriptor,
+        ShaderModuleDescriptor, ShaderSource, VertexState,
+    };
+
+    use crate::{util::testing, RendererConfig, WgpuRenderer};
+
+    #[test]
+    fn scene_is_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<crate::render::scene::GpuScene>();
+        assert_send_sync::<crate::SwapChain>();
+    }
+
+    #[test]
+    fn headless_renderer_info() {
+        let Some(renderer) = testing::renderer(None, None) else {
+            return;
+        };
+
+        assert!(!renderer.adapter_info().name.is_empty());
+        assert!(renderer.limits().max_texture_dimension_2d > 0);
+        assert!(renderer
+            .supported_features()
+            .contains(renderer.device.features()));
+    }
+
+    #[test]
+    fn pipeline_cache_round_trip() {
+        let config = RendererConfig {
+            optional_features: Features::PIPELINE_CACHE,
+            ..Default::default()
+        };
+        let Some(mut renderer) = testing::skip_unsupported(pollster::block_on(
+            WgpuRenderer::with_config(config, None, None),
+        )) else {
+            return;
+        };
+
+        let dir = std::env::temp_dir().join(format!("aurora_pipeline_cache_{}", Uuid::new_v4()));
+        let supported = renderer
+            .device
+            .features()
+            .contains(Features::PIPELINE_CACHE)
+            && pipeline_cache_key(&renderer.adapter_info()).is_some();
+        assert_eq!(renderer.load_pipeline_cache(&dir), supported);
+        let Some(cache) = &renderer.pipeline_cache else {
+            return;
+        };
+
+        let shader = renderer
+            .device
+            .create_shader_module(ShaderModuleDescriptor {
+                label: None,
+                source: ShaderSource::Wgsl(
+                    "@vertex fn vertex() -> @builtin(position) vec4f { return vec4f(0.); }".into(),
+                ),
+            });
+        let layout = renderer
+            .device
+            .create_pipeline_layout(&PipelineLayoutDescriptor::default());
+        renderer
+            .device
+            .create_render_pipeline(&RenderPipelineDescriptor {
+                label: None,
+                layout: Some(&layout),
+                vertex: VertexState {
+                    module: &shader,
+                    entry_point: "vertex",
+                    compilation_options: Default::default(),
+                    buffers: &[],
+                },
+                primitive: Default::default(),
+                depth_stencil: None,
+                multisample: Default::default(),
+                fragment: None,
+                multiview: None,
+                cache: Some(&cache.cache),
+            });
+
+        // Saved on drop, ready to be loaded on the next run.
+        let path = cache.path.clone();
+        renderer.pipeline_cache = None;
+        assert!(path.exists());
+        assert!(renderer.load_pipeline_cache(&dir));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
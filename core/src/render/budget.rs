@@ -0,0 +1,130 @@
+/// Quality tier nodes are asked to render at, see [`RenderNode::set_quality`].
+///
+/// [`RenderNode::set_quality`]: crate::render::flow::RenderNode::set_quality
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Quality {
+    Low,
+    Medium,
+    /// Settings as configured on the node.
+    #[default]
+    High,
+}
+
+impl Quality {
+    pub fn lower(self) -> Option<Self> {
+        match self {
+            Quality::Low => None,
+            Quality::Medium => Some(Quality::Low),
+            Quality::High => Some(Quality::Medium),
+        }
+    }
+
+    pub fn higher(self) -> Option<Self> {
+        match self {
+            Quality::Low => Some(Quality::Medium),
+            Quality::Medium => Some(Quality::High),
+            Quality::High => None,
+        }
+    }
+}
+
+/// Picks a [`Quality`] keeping frame times around a target.
+///
+/// To avoid oscillating between two tiers, quality only drops once the smoothed frame time
+/// exceeds the target by `hysteresis`, and only rises again once it's below the target by
+/// twice that, as dropping a tier usually saves more than the margin. After every change,
+/// nothing happens for `cooldown` frames, so the rebuild hitch and the frames rendered with
+/// the old settings don't count against the new ones.
+pub struct FrameBudget {
+    /// Target frame time in milliseconds.
+    pub target_ms: f32,
+    /// Fraction of `target_ms`.
+    pub hysteresis: f32,
+    pub cooldown: u32,
+    /// Weight of the latest frame in the exponential average.
+    pub smoothing: f32,
+
+    quality: Quality,
+    smoothed_ms: Option<f32>,
+    frames_since_change: u32,
+}
+
+impl FrameBudget {
+    pub fn new(target_ms: f32) -> Self {
+        Self {
+            target_ms,
+            hysteresis: 0.1,
+            cooldown: 30,
+            smoothing: 0.1,
+            quality: Quality::High,
+            smoothed_ms: None,
+            frames_since_change: 0,
+        }
+    }
+
+    #[inline]
+    pub fn quality(&self) -> Quality {
+        self.quality
+    }
+
+    #[inline]
+    pub fn smoothed_ms(&self) -> Option<f32> {
+        self.smoothed_ms
+    }
+
+    /// Feed the time of the last frame, returning the new quality if it changed.
+    pub fn update(&mut self, frame_ms: f32) -> Option<Quality> {
+        self.frames_since_change += 1;
+        if self.frames_since_change <= self.cooldown {
+            return None;
+        }
+
+        let smoothed = match self.smoothed_ms {
+            Some(smoothed) => smoothed + (frame_ms - smoothed) * self.smoothing,
+            None => frame_ms,
+        };
+        self.smoothed_ms = Some(smoothed);
+
+        let new_quality = if smoothed > self.target_ms * (1. + self.hysteresis) {
+            self.quality.lower()
+        } else if smoothed < self.target_ms * (1. - self.hysteresis * 2.) {
+            self.quality.higher()
+        } else {
+            None
+        }?;
+
+        self.quality = new_quality;
+        self.smoothed_ms = None;
+        self.frames_since_change = 0;
+        Some(new_quality)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FrameBudget, Quality};
+
+    fn run(budget: &mut FrameBudget, frame_ms: f32, frames: u32) -> Vec<Quality> {
+        (0..frames)
+            .filter_map(|_| budget.update(frame_ms))
+            .collect()
+    }
+
+    #[test]
+    fn frame_budget_hysteresis() {
+        let mut budget = FrameBudget::new(16.);
+
+        // Within the band around the target nothing changes.
+        assert!(run(&mut budget, 17., 200).is_empty());
+
+        // Too slow, one tier per cooldown.
+        assert_eq!(run(&mut budget, 25., 30), [Quality::Medium]);
+        assert_eq!(run(&mut budget, 25., 200), [Quality::Low]);
+
+        // Slightly under the target isn't enough headroom to go back up.
+        assert!(run(&mut budget, 14., 200).is_empty());
+
+        assert_eq!(run(&mut budget, 8., 200), [Quality::Medium, Quality::High]);
+        assert_eq!(budget.quality(), Quality::High);
+    }
+}
@@ -0,0 +1,12 @@
+#define_import_path aurora::color_space
+
+// Piecewise sRGB transfer functions, matching what sRGB formats do on write and read.
+// Values above 1 follow the same curve, clamp before encoding to store them.
+
+fn linear_to_srgb(color: vec3f) -> vec3f {
+    return select(1.055 * pow(color, vec3f(1. / 2.4)) - 0.055, color * 12.92, color <= vec3f(0.0031308));
+}
+
+fn srgb_to_linear(color: vec3f) -> vec3f {
+    return select(pow((color + 0.055) / 1.055, vec3f(2.4)), color / 12.92, color <= vec3f(0.04045));
+}
@@ -1,44 +1,208 @@
 use std::{
-    any::{type_name, TypeId},
+    any::{type_name, Any, TypeId},
     borrow::Cow,
     collections::HashMap,
+    num::NonZeroU32,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
     time::Instant,
 };
 
 use encase::ShaderType;
+use glam::{UVec2, Vec2};
+use image::RgbaImage;
 use indexmap::IndexMap;
 use naga_oil::compose::{
     ComposableModuleDescriptor, Composer, NagaModuleDescriptor, ShaderDefValue,
 };
+use thiserror::Error;
 use wgpu::{
+    naga::valid::Capabilities,
     util::{DeviceExt, TextureDataOrder},
-    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor,
+    BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
     BindGroupLayoutEntry, BindingResource, BindingType, BufferBindingType, Color, ColorTargetState,
-    ColorWrites, Device, Extent3d, Features, FragmentState, Limits, LoadOp, Operations,
-    PipelineLayoutDescriptor, Queue, RenderPassColorAttachment, RenderPassDescriptor,
-    RenderPipeline, RenderPipelineDescriptor, SamplerBindingType, SamplerDescriptor, ShaderModule,
-    ShaderModuleDescriptor, ShaderSource, ShaderStages, StoreOp, TextureDescriptor,
-    TextureDimension, TextureFormat, TextureSampleType, TextureUsages, TextureViewDimension,
-    VertexFormat, VertexState,
+    ColorWrites, CommandBuffer, Device, DownlevelFlags, Extent3d, Features, FragmentState, Limits,
+    LoadOp, Operations, PipelineCache, PipelineLayoutDescriptor, Queue, RenderPassColorAttachment,
+    RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor, Sampler, SamplerBindingType,
+    SamplerDescriptor, ShaderModule, ShaderModuleDescriptor, ShaderSource, ShaderStages, StoreOp,
+    Texture, TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType, TextureUsages,
+    TextureViewDimension, VertexFormat, VertexState,
 };
 
 use crate::{
     render::{
+        budget::{FrameBudget, Quality},
         helper::Camera,
-        mesh::{GpuMesh, StaticMesh},
+        mesh::{GpuMesh, Material, MeshBufferLayout, StaticMesh, ALL_RENDER_LAYERS},
         resource::{
-            GpuCamera, GpuDirectionalLight, GpuPointLight, GpuSceneDesc, GpuSpotLight, RenderMesh,
-            RenderTargets, DUMMY_2D_TEX, POST_PROCESS_COLOR_LAYOUT_UUID,
-            POST_PROCESS_DEPTH_LAYOUT_UUID,
+            ColorSpace, ColorSpaceError, GpuCamera, GpuDirectionalLight, GpuPointLight,
+            GpuSceneDesc, GpuSpotLight, RenderMesh, RenderTargets, DUMMY_2D_TEX,
+            POST_PROCESS_COLOR_LAYOUT_UUID, POST_PROCESS_DEPTH_LAYOUT_UUID,
         },
-        scene::{GpuScene, MeshInstanceId},
+        scene::{GpuScene, MeshInstanceId, TextureId},
     },
-    WgpuRenderer,
+    util, RendererConfig, RendererError, SwapChain, SwapChainConfig, WgpuRenderer,
 };
 
+#[derive(Error, Debug)]
+pub enum OutputRedirectError {
+    #[error("{node} is redirected to texture {texture:?}, which isn't in the scene.")]
+    MissingTexture {
+        node: &'static str,
+        texture: TextureId,
+    },
+    #[error("{node} outputs {expected:?}, but the texture it's redirected to is {found:?}.")]
+    FormatMismatch {
+        node: &'static str,
+        expected: TextureFormat,
+        found: TextureFormat,
+    },
+    #[error(
+        "{node} outputs {expected:?} pixels, but the texture it's redirected to is {found:?}."
+    )]
+    SizeMismatch {
+        node: &'static str,
+        expected: Extent3d,
+        found: Extent3d,
+    },
+    #[error("The texture {node} is redirected to lacks usages {missing:?}.")]
+    TextureMissingUsages {
+        node: &'static str,
+        missing: TextureUsages,
+    },
+    #[error("Redirecting {node} requires the swap chain to have usages {missing:?}.")]
+    SwapChainMissingUsages {
+        node: &'static str,
+        missing: TextureUsages,
+    },
+    #[error("{node} writes to the surface, only output to the swap chain can be redirected.")]
+    WritesSurface { node: &'static str },
+}
+
+#[derive(Error, Debug)]
+pub enum BuildError {
+    #[error("{0}")]
+    OutputRedirect(#[from] OutputRedirectError),
+    #[error("{0}")]
+    ColorSpace(#[from] ColorSpaceError),
+}
+
+/// Offscreen targets [`RenderFlow::capture_sync`] built the flow for, kept between calls.
+struct CaptureTargets {
+    surface: Texture,
+    depth: Texture,
+    swap_chain: SwapChain,
+}
+
+impl CaptureTargets {
+    const COLOR_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+    const SURFACE_FORMAT: TextureFormat = TextureFormat::Rgba8UnormSrgb;
+    const DEPTH_FORMAT: TextureFormat = TextureFormat::Depth32Float;
+
+    fn new(device: &Device, size: UVec2) -> Self {
+        Self {
+            surface: util::create_texture(
+                device,
+                size.extend(1),
+                Self::SURFACE_FORMAT,
+                TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+            ),
+            depth: util::create_texture(
+                device,
+                size.extend(1),
+                Self::DEPTH_FORMAT,
+                TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+            ),
+            swap_chain: SwapChain::from_config(
+                device,
+                &SwapChainConfig {
+                    format: Self::COLOR_FORMAT,
+                    usage: SwapChain::REQUIRED_USAGES | TextureUsages::COPY_SRC,
+                    size,
+                },
+            )
+            .unwrap(),
+        }
+    }
+
+    #[inline]
+    fn size(&self) -> UVec2 {
+        UVec2::new(self.surface.width(), self.surface.height())
+    }
+
+    fn targets(&self) -> RenderTargets {
+        RenderTargets {
+            color_format: Self::COLOR_FORMAT,
+            swap_chain: &self.swap_chain,
+            surface: self.surface.create_view(&Default::default()),
+            surface_format: Self::SURFACE_FORMAT,
+            depth_format: Some(Self::DEPTH_FORMAT),
+            depth: Some(self.depth.create_view(&Default::default())),
+            size: self.size(),
+            sample_count: 1,
+            hdr_output: false,
+            color_space: ColorSpace::Srgb,
+        }
+    }
+}
+
+/// Where the output of a node goes instead of the swap chain.
+///
+/// The node draws into a copy of the swap chain in its other texture, leaving the input
+/// intact in the current one for later nodes. Post processing reads that copy and writes
+/// the current texture instead, so the input is then left in the other one.
+struct OutputRedirect {
+    texture: TextureId,
+}
+
+impl OutputRedirect {
+    const SWAP_CHAIN_USAGES: TextureUsages = TextureUsages::COPY_SRC.union(TextureUsages::COPY_DST);
+
+    fn begin(&self, renderer: &WgpuRenderer, swap_chain: &SwapChain) {
+        let mut encoder = renderer.device.create_command_encoder(&Default::default());
+        encoder.copy_texture_to_texture(
+            swap_chain.current_texture().as_image_copy(),
+            swap_chain.another_texture().as_image_copy(),
+            swap_chain.desc().size,
+        );
+        renderer.queue.submit([encoder.finish()]);
+        swap_chain.swap();
+    }
+
+    fn end(&self, renderer: &WgpuRenderer, scene: &GpuScene, swap_chain: &SwapChain) {
+        let mut encoder = renderer.device.create_command_encoder(&Default::default());
+        encoder.copy_texture_to_texture(
+            swap_chain.current_texture().as_image_copy(),
+            scene.assets.textures[&self.texture].as_image_copy(),
+            swap_chain.desc().size,
+        );
+        renderer.queue.submit([encoder.finish()]);
+        // Either way the texture the node didn't write holds the input.
+        swap_chain.swap();
+    }
+}
+
 struct PackedRenderNode {
     pub node: Box<dyn RenderNode>,
     pub context: NodeContext,
+    /// Every mesh queued for the node, `context.meshes` only keeps the visible ones.
+    pub queue: Vec<RenderMesh>,
+    pub render_layers: u32,
+    pub redirect: Option<OutputRedirect>,
+}
+
+impl PackedRenderNode {
+    fn new(node: Box<dyn RenderNode>) -> Self {
+        Self {
+            node,
+            context: Default::default(),
+            queue: Vec::new(),
+            render_layers: ALL_RENDER_LAYERS,
+            redirect: None,
+        }
+    }
 }
 
 #[derive(Default)]
@@ -46,6 +210,71 @@ pub struct NodeContext {
     pub shaders: Vec<ShaderModule>,
     pub meshes: Vec<RenderMesh>,
     pub pipelines: HashMap<MeshInstanceId, RenderPipeline>,
+    /// Multiview count for pipelines rendering the scene, `None` unless the flow is stereo
+    /// and the node [supports multiview](RenderNode::supports_multiview).
+    pub multiview: Option<NonZeroU32>,
+    /// Whether meshes outside the view should be skipped, see [`RenderFlow::set_culling`].
+    pub culling: bool,
+    /// Pass as `cache` when creating pipelines, see [`WgpuRenderer::load_pipeline_cache`].
+    pub pipeline_cache: Option<Arc<PipelineCache>>,
+    /// Whether the adapter can draw with arguments from a buffer, see
+    /// [`DownlevelFlags::INDIRECT_EXECUTION`].
+    pub indirect_execution: bool,
+    /// Shader defs of the flow, [`NodeContext::shaders`] were compiled with them.
+    pub shader_defs: HashMap<String, ShaderDefValue>,
+}
+
+impl NodeContext {
+    /// Compile `shader` like the ones of [`RenderNode::require_shaders`], for nodes only
+    /// compiling the variants they use while building.
+    ///
+    /// `local_shader_defs` are added to [`NodeContext::shader_defs`].
+    pub fn compile_shader(
+        &self,
+        device: &Device,
+        label: &str,
+        (deps, main): (&[&str], &str),
+        local_shader_defs: Option<Vec<(String, ShaderDefValue)>>,
+    ) -> ShaderModule {
+        let mut shader_defs = self.shader_defs.clone();
+        shader_defs.extend(local_shader_defs.unwrap_or_default());
+
+        let mut composer = Composer::default();
+        if shader_defs.contains_key("MULTIVIEW") {
+            composer = composer.with_capabilities(Capabilities::MULTIVIEW);
+        }
+        composer
+            .add_composable_module(ComposableModuleDescriptor {
+                source: include_str!("color_space.wgsl"),
+                shader_defs: shader_defs.clone(),
+                ..Default::default()
+            })
+            .unwrap();
+        for dep in deps {
+            composer
+                .add_composable_module(ComposableModuleDescriptor {
+                    source: dep,
+                    shader_defs: shader_defs.clone(),
+                    ..Default::default()
+                })
+                .expect(&format!(
+                    "Error on building shader dependencies for node {}",
+                    label
+                ));
+        }
+        let module = composer
+            .make_naga_module(NagaModuleDescriptor {
+                source: main,
+                shader_defs,
+                ..Default::default()
+            })
+            .expect(&format!("Error on building main shader for node {}", label));
+
+        device.create_shader_module(ShaderModuleDescriptor {
+            label: None,
+            source: ShaderSource::Naga(Cow::Owned(module)),
+        })
+    }
 }
 
 pub struct RenderContext<'a> {
@@ -53,12 +282,27 @@ pub struct RenderContext<'a> {
     pub queue: &'a Queue,
     pub node: &'a mut NodeContext,
     pub targets: &'a RenderTargets<'a>,
+    /// Material replacing every per-mesh material, see [`RenderFlow::set_material_override`].
+    pub material_override: Option<&'a dyn Material>,
 }
 
 #[derive(Default)]
 pub struct RenderFlow {
     flow: IndexMap<TypeId, PackedRenderNode>,
     is_built: bool,
+    stereo: bool,
+    material_override: Option<Box<dyn Material>>,
+    budget: Option<FrameBudget>,
+    /// Bits of the last measured frame time in milliseconds, 0 until the GPU reports back.
+    last_frame_ms: Arc<AtomicU32>,
+    jitter_disabled: bool,
+    /// Whether the camera was jittered last frame, so the jitter is removed once.
+    jittered: bool,
+    culling_disabled: bool,
+    paused: bool,
+    /// Targets the flow was last built for by [`RenderFlow::capture_sync`], if it wasn't
+    /// rebuilt or resized for other targets since.
+    capture: Option<CaptureTargets>,
 }
 
 impl RenderFlow {
@@ -66,15 +310,91 @@ impl RenderFlow {
         &self,
         features: Option<Features>,
         limits: Option<Limits>,
-    ) -> WgpuRenderer {
-        let mut features = features.unwrap_or_default();
+    ) -> Result<WgpuRenderer, RendererError> {
+        let features = features.unwrap_or_default() | self.required_features();
         let mut limits = limits.unwrap_or_default();
+        for node in self.flow.values() {
+            node.node.require_renderer_limits(&mut limits);
+        }
+        let config = RendererConfig {
+            // Only used once a cache is loaded, see `WgpuRenderer::load_pipeline_cache`.
+            optional_features: self.optional_features() | Features::PIPELINE_CACHE,
+            ..Default::default()
+        };
+        WgpuRenderer::with_config(config, Some(features), Some(limits)).await
+    }
 
+    /// Features the nodes in this flow need, without creating a device.
+    pub fn required_features(&self) -> Features {
+        let mut features = Features::empty();
         for node in self.flow.values() {
             node.node.require_renderer_features(&mut features);
+        }
+        if self.stereo {
+            features |= Features::MULTIVIEW;
+        }
+        features
+    }
+
+    /// Features the nodes in this flow can use but have fallbacks for.
+    pub fn optional_features(&self) -> Features {
+        let mut features = Features::empty();
+        for node in self.flow.values() {
+            node.node.request_renderer_features(&mut features);
+        }
+        features
+    }
+
+    /// Limits the nodes in this flow need on top of the default ones, without creating a device.
+    pub fn required_limits(&self) -> Limits {
+        let mut limits = Limits::default();
+        for node in self.flow.values() {
             node.node.require_renderer_limits(&mut limits);
         }
-        WgpuRenderer::new(Some(features), Some(limits)).await
+        limits
+    }
+
+    /// Check a device created elsewhere against every node in this flow, reporting the first
+    /// node whose features or limits it can't satisfy.
+    pub fn validate_device(&self, device: &Device) -> Result<(), RendererError> {
+        let features = device.features();
+        let allowed = device.limits();
+
+        if self.stereo && !features.contains(Features::MULTIVIEW) {
+            return Err(RendererError::NodeMissingFeatures {
+                node: "stereo rendering",
+                missing: Features::MULTIVIEW,
+            });
+        }
+
+        for node in self.flow.values() {
+            let mut required = Features::empty();
+            node.node.require_renderer_features(&mut required);
+            let missing = required.difference(features);
+            if !missing.is_empty() {
+                return Err(RendererError::NodeMissingFeatures {
+                    node: node.node.label(),
+                    missing,
+                });
+            }
+
+            let mut required = allowed.clone();
+            node.node.require_renderer_limits(&mut required);
+            let mut exceeded = None;
+            required.check_limits_with_fail_fn(&allowed, false, |limit, required, allowed| {
+                exceeded.get_or_insert((limit, required, allowed));
+            });
+            if let Some((limit, required, allowed)) = exceeded {
+                return Err(RendererError::NodeLimitExceeded {
+                    node: node.node.label(),
+                    limit,
+                    required,
+                    allowed,
+                });
+            }
+        }
+
+        Ok(())
     }
 
     #[inline]
@@ -89,13 +409,12 @@ impl RenderFlow {
         let mut after = Vec::new();
 
         for (index, dep) in node.add_node_dependencies() {
-            let elem = (
-                dep.identifier(),
-                PackedRenderNode {
-                    node: dep,
-                    context: Default::default(),
-                },
-            );
+            // Keep the configuration of nodes added before.
+            if self.flow.contains_key(&dep.identifier()) {
+                continue;
+            }
+
+            let elem = (dep.identifier(), PackedRenderNode::new(dep));
 
             match index {
                 DependencyNodeIndex::Before => before.push(elem),
@@ -104,30 +423,330 @@ impl RenderFlow {
         }
 
         self.flow.extend(before);
-        self.flow.insert(
-            TypeId::of::<T>(),
-            PackedRenderNode {
-                node: Box::new(node),
-                context: Default::default(),
-            },
-        );
+        self.flow
+            .insert(TypeId::of::<T>(), PackedRenderNode::new(Box::new(node)));
         self.flow.extend(after);
+        self.capture = None;
+
+        self
+    }
+
+    /// Build again before running, whether for the caller's targets or a capture.
+    fn invalidate(&mut self) {
+        self.is_built = false;
+        self.capture = None;
+    }
 
+    /// Render both eyes in a single pass using multiview.
+    ///
+    /// Cameras are taken from [`Scene::eyes`](crate::render::helper::Scene::eyes), and the
+    /// swap chain must have a layer per eye, see [`SwapChain::new`]. Only nodes which
+    /// [support multiview](RenderNode::supports_multiview) draw both eyes, others see the
+    /// left one, so post processing can't be part of a stereo flow yet.
+    pub fn set_stereo(&mut self, stereo: bool) -> &mut Self {
+        self.stereo = stereo;
+        self.invalidate();
+        self
+    }
+
+    /// Draw every mesh with `material` instead of its own, like a clay render.
+    ///
+    /// The material must share the bind group layout of the materials the nodes are built for.
+    pub fn set_material_override(&mut self, material: Option<Box<dyn Material>>) -> &mut Self {
+        self.material_override = material;
+        self
+    }
+
+    /// Lower or restore node settings to keep frames around `ms`, `None` restores full quality.
+    ///
+    /// Frames are timed from the start of `run` until the GPU finishes the submitted work,
+    /// there are no per node GPU timings. See [`FrameBudget`] for how oscillation is avoided.
+    /// Changes needing new resources, like smaller shadow maps, rebuild the flow on the next
+    /// [`RenderFlow::build`].
+    pub fn set_frame_budget(&mut self, ms: Option<f32>) -> &mut Self {
+        self.budget = ms.map(FrameBudget::new);
+        if ms.is_none() {
+            self.apply_quality(Quality::High);
+        }
         self
     }
 
     #[inline]
-    pub fn set_queue(&mut self, meshes: Vec<StaticMesh>) {
-        let meshes = meshes
-            .iter()
-            .map(|mesh| RenderMesh {
-                mesh: *mesh,
-                offset: None,
-            })
-            .collect::<Vec<_>>();
+    pub fn frame_budget(&self) -> Option<&FrameBudget> {
+        self.budget.as_ref()
+    }
+
+    #[inline]
+    pub fn frame_budget_mut(&mut self) -> Option<&mut FrameBudget> {
+        self.budget.as_mut()
+    }
+
+    fn apply_quality(&mut self, quality: Quality) {
+        let mut rebuild = false;
+        for node in self.flow.values_mut() {
+            rebuild |= node.node.set_quality(quality);
+        }
+        if rebuild {
+            self.invalidate();
+        }
+    }
+
+    /// Allow jittering the camera for nodes requiring it, see [`RenderNode::require_jitter`].
+    ///
+    /// Enabled by default, disable it when taking screenshots, so the output is stable and
+    /// matches the un-jittered camera exactly.
+    #[inline]
+    pub fn set_jitter(&mut self, enabled: bool) -> &mut Self {
+        self.jitter_disabled = !enabled;
+        self
+    }
+
+    /// Whether the camera is currently jittered each frame.
+    #[inline]
+    pub fn is_jittered(&self) -> bool {
+        !self.jitter_disabled && self.flow.values().any(|node| node.node.require_jitter())
+    }
+
+    /// Skip meshes whose bounds are outside the camera frustum, or both eyes when stereo.
+    ///
+    /// Enabled by default. Bounds come from the vertices when the meshes are uploaded, so
+    /// meshes moved on the GPU, like skinned ones, may be culled while still in view.
+    /// Nodes rendering from other viewpoints cull on their own, see
+    /// [`RenderNode::cull_to_camera`].
+    pub fn set_culling(&mut self, enabled: bool) -> &mut Self {
+        self.culling_disabled = !enabled;
+        if !enabled {
+            for node in self.flow.values_mut() {
+                node.context.meshes = node.queue.clone();
+            }
+        }
+        self
+    }
+
+    /// Skip [`RenderFlow::run`] while `paused`, like when the window is minimized. Pipelines,
+    /// textures and buffers are kept, so nothing is rebuilt when unpausing.
+    ///
+    /// Time spent paused isn't added to [`GpuScene::time`], animations continue where they
+    /// stopped. See [`RenderFlow::resume`] to also prepare the nodes right away.
+    pub fn set_paused(&mut self, paused: bool) -> &mut Self {
+        if self.paused && !paused {
+            if let Some(general) = self.get_mut::<GeneralNode>() {
+                general.last_update = (general.clock)();
+            }
+        }
+        self.paused = paused;
+        self
+    }
+
+    #[inline]
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Unpause and render a frame right away, so the output reflects changes made to `scene`
+    /// while paused without waiting for the next [`RenderFlow::run`].
+    pub fn resume(
+        &mut self,
+        renderer: &WgpuRenderer,
+        scene: &mut GpuScene,
+        targets: &RenderTargets,
+    ) {
+        self.set_paused(false);
+        self.run(renderer, scene, targets);
+    }
+
+    fn cull(&mut self, scene: &GpuScene) {
+        for node in self.flow.values_mut() {
+            node.context.culling = !self.culling_disabled;
+        }
+        if self.culling_disabled {
+            return;
+        }
+
+        let frustums = if self.stereo {
+            scene.original.eyes.to_vec()
+        } else {
+            vec![scene.original.camera]
+        }
+        .into_iter()
+        .map(|camera| <Camera as Into<GpuCamera>>::into(camera).frustum())
+        .collect::<Vec<_>>();
+
+        for node in self.flow.values_mut() {
+            if !node.node.cull_to_camera() {
+                continue;
+            }
+            node.context.meshes = node
+                .queue
+                .iter()
+                .filter(|mesh| {
+                    // Meshes without bounds are never culled.
+                    scene
+                        .assets
+                        .gpu_meshes
+                        .get(&mesh.mesh.mesh)
+                        .and_then(|gpu_mesh| gpu_mesh.aabb)
+                        .map(|aabb| aabb.transformed(mesh.mesh.transform.model_matrix()))
+                        .is_none_or(|aabb| frustums.iter().any(|f| f.intersects_aabb(&aabb)))
+                })
+                .cloned()
+                .collect();
+        }
+    }
+
+    fn apply_jitter(&mut self, scene: &mut GpuScene, size: UVec2) {
+        let jitter = self.is_jittered();
+        if !jitter && !self.jittered {
+            return;
+        }
+
+        let offset = if jitter {
+            Camera::jitter_offset(scene.frame_count, size)
+        } else {
+            Vec2::ZERO
+        };
+        scene.original.camera.jitter = offset;
+        for eye in &mut scene.original.eyes {
+            eye.jitter = offset;
+        }
+        self.jittered = jitter;
+    }
+
+    fn start_frame(&mut self, scene: &mut GpuScene, targets: &RenderTargets) -> Instant {
+        self.apply_jitter(scene, targets.size);
+        self.cull(scene);
+
+        let last_frame_ms = f32::from_bits(self.last_frame_ms.swap(0, Ordering::Relaxed));
+        if let Some(quality) = self
+            .budget
+            .as_mut()
+            .filter(|_| last_frame_ms > 0.)
+            .and_then(|budget| budget.update(last_frame_ms))
+        {
+            self.apply_quality(quality);
+        }
+        Instant::now()
+    }
+
+    fn end_frame(&self, renderer: &WgpuRenderer, scene: &mut GpuScene, start: Instant) {
+        scene.assets.bind_group_cache.end_frame();
+        if self.budget.is_none() {
+            return;
+        }
+
+        let last_frame_ms = self.last_frame_ms.clone();
+        renderer.queue.on_submitted_work_done(move || {
+            let ms = start.elapsed().as_secs_f32() * 1000.;
+            last_frame_ms.store(ms.to_bits(), Ordering::Relaxed);
+        });
+    }
+
+    #[inline]
+    pub fn multiview(&self) -> Option<NonZeroU32> {
+        self.stereo.then(|| NonZeroU32::new(2).unwrap())
+    }
+
+    /// Only give node `T` the meshes in at least one of `layers`, like keeping gizmos out
+    /// of shadow maps. Applied on the next [`RenderFlow::set_queue`].
+    ///
+    /// Nodes draw every layer by default. Does nothing if `T` isn't in the flow.
+    pub fn set_render_layers<T: RenderNode>(&mut self, layers: u32) -> &mut Self {
+        if let Some(node) = self.flow.get_mut(&TypeId::of::<T>()) {
+            node.render_layers = layers;
+        }
+        self
+    }
+
+    /// Render node `T` into `texture` instead of the swap chain, e.g. to composite the
+    /// 3D scene with a separate UI pass. `None` renders into the swap chain again.
+    ///
+    /// Later nodes see the swap chain as it was before `T`, as long as `T` swaps it at most
+    /// once. The texture must be in
+    /// [`GpuAssets::textures`](crate::render::scene::GpuAssets::textures), matching
+    /// the swap chain in format and size with [`TextureUsages::COPY_DST`], and the swap
+    /// chain needs [`TextureUsages::COPY_SRC`] and [`TextureUsages::COPY_DST`]. Nodes
+    /// [writing the surface](RenderNode::writes_surface) can't be redirected.
+    /// Applied on the next build, see [`RenderFlow::validate_redirects`].
+    pub fn redirect_output<T: RenderNode>(&mut self, texture: Option<TextureId>) -> &mut Self {
+        if let Some(node) = self.flow.get_mut(&TypeId::of::<T>()) {
+            node.redirect = texture.map(|texture| OutputRedirect { texture });
+            self.invalidate();
+        }
+        self
+    }
+
+    /// Check the textures nodes are redirected to against what the nodes output.
+    pub fn validate_redirects(
+        &self,
+        scene: &GpuScene,
+        targets: &RenderTargets,
+    ) -> Result<(), OutputRedirectError> {
+        for node in self.flow.values() {
+            let Some(redirect) = &node.redirect else {
+                continue;
+            };
+            if node.node.writes_surface() {
+                return Err(OutputRedirectError::WritesSurface {
+                    node: node.node.label(),
+                });
+            }
+            let node = node.node.label();
 
+            let missing = OutputRedirect::SWAP_CHAIN_USAGES - targets.swap_chain.desc().usage;
+            if !missing.is_empty() {
+                return Err(OutputRedirectError::SwapChainMissingUsages { node, missing });
+            }
+
+            let Some(texture) = scene.assets.textures.get(&redirect.texture) else {
+                return Err(OutputRedirectError::MissingTexture {
+                    node,
+                    texture: redirect.texture,
+                });
+            };
+            if texture.format() != targets.color_format {
+                return Err(OutputRedirectError::FormatMismatch {
+                    node,
+                    expected: targets.color_format,
+                    found: texture.format(),
+                });
+            }
+            if texture.size() != targets.swap_chain.desc().size {
+                return Err(OutputRedirectError::SizeMismatch {
+                    node,
+                    expected: targets.swap_chain.desc().size,
+                    found: texture.size(),
+                });
+            }
+            if !texture.usage().contains(TextureUsages::COPY_DST) {
+                return Err(OutputRedirectError::TextureMissingUsages {
+                    node,
+                    missing: TextureUsages::COPY_DST,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Node `T` in this flow, to tweak its settings between frames.
+    pub fn get_mut<T: RenderNode>(&mut self) -> Option<&mut T> {
+        let node: &mut dyn Any = self.flow.get_mut(&TypeId::of::<T>())?.node.as_mut();
+        node.downcast_mut()
+    }
+
+    #[inline]
+    pub fn set_queue(&mut self, meshes: Vec<StaticMesh>) {
         self.flow.values_mut().for_each(|node| {
-            node.context.meshes = meshes.clone();
+            node.queue = meshes
+                .iter()
+                .filter(|mesh| mesh.render_layer & node.render_layers != 0)
+                .map(|mesh| RenderMesh {
+                    mesh: *mesh,
+                    offset: None,
+                    model_offset: None,
+                })
+                .collect();
+            node.context.meshes = node.queue.clone();
         });
     }
 
@@ -138,13 +757,19 @@ impl RenderFlow {
         scene: &mut GpuScene,
         shader_defs: Option<HashMap<String, ShaderDefValue>>,
         targets: &RenderTargets,
-    ) {
+    ) -> Result<(), BuildError> {
         if !self.is_built {
-            self.force_build(renderer, scene, shader_defs, targets);
+            self.force_build(renderer, scene, shader_defs, targets)?;
             self.is_built = true;
         }
+        Ok(())
     }
 
+    /// Compile shaders and build every node against `targets`.
+    ///
+    /// Fails without building anything if a redirect or the color space of `targets` is
+    /// invalid, see [`RenderFlow::validate_redirects`] and
+    /// [`RenderTargets::validate_color_space`].
     #[inline]
     pub fn force_build(
         &mut self,
@@ -152,8 +777,16 @@ impl RenderFlow {
         scene: &mut GpuScene,
         shader_defs: Option<HashMap<String, ShaderDefValue>>,
         targets: &RenderTargets,
-    ) {
-        for PackedRenderNode { node, context } in self.flow.values_mut() {
+    ) -> Result<(), BuildError> {
+        self.validate_redirects(scene, targets)?;
+        targets.validate_color_space()?;
+        self.capture = None;
+        scene.assets.max_anisotropy = scene
+            .assets
+            .max_anisotropy
+            .clamp(1, renderer.max_anisotropy());
+
+        for PackedRenderNode { node, context, .. } in self.flow.values_mut() {
             if let Some(restriction) = node.restrict_mesh_format() {
                 for mesh in &context.meshes {
                     scene.assets.meshes[&mesh.mesh.mesh].assert_vertex(restriction);
@@ -165,58 +798,39 @@ impl RenderFlow {
         for node in self.flow.values() {
             node.node.require_shader_defs(&mut shader_defs);
         }
+        if self.stereo {
+            shader_defs.insert("MULTIVIEW".to_string(), Default::default());
+        }
+
+        let multiview = self.multiview();
 
-        for PackedRenderNode { node, context } in self.flow.values_mut() {
+        for PackedRenderNode { node, context, .. } in self.flow.values_mut() {
+            context.shader_defs = shader_defs.clone();
             if let Some(shaders) = node.require_shaders() {
-                let mut compiled = Vec::with_capacity(shaders.len());
                 let mut local_shader_defs = node.require_local_shader_defs();
-
-                for (index, (deps, main)) in shaders.into_iter().enumerate() {
-                    let shader_defs = match local_shader_defs.get_mut(index).and_then(|d| d.take())
-                    {
-                        Some(defs) => {
-                            let mut shader_defs = shader_defs.clone();
-                            shader_defs.extend(defs.clone());
-                            shader_defs
-                        }
-                        None => shader_defs.clone(),
-                    };
-
-                    let mut composer = Composer::default();
-                    for dep in deps.into_iter() {
-                        composer
-                            .add_composable_module(ComposableModuleDescriptor {
-                                source: dep,
-                                shader_defs: shader_defs.clone(),
-                                ..Default::default()
-                            })
-                            .expect(&format!(
-                                "Error on building shader dependencies for node {}",
-                                node.label()
-                            ));
-                    }
-                    let module = composer
-                        .make_naga_module(NagaModuleDescriptor {
-                            source: &main,
-                            shader_defs,
-                            ..Default::default()
-                        })
-                        .expect(&format!(
-                            "Error on building main shader for node {}",
-                            node.label()
-                        ));
-
-                    compiled.push(
-                        renderer
-                            .device
-                            .create_shader_module(ShaderModuleDescriptor {
-                                label: None,
-                                source: ShaderSource::Naga(Cow::Owned(module)),
-                            }),
-                    );
-                }
-                context.shaders = compiled;
+                context.shaders = shaders
+                    .iter()
+                    .enumerate()
+                    .map(|(index, shader)| {
+                        context.compile_shader(
+                            &renderer.device,
+                            &node.label(),
+                            *shader,
+                            local_shader_defs.get_mut(index).and_then(|d| d.take()),
+                        )
+                    })
+                    .collect();
             }
+            context.multiview = multiview.filter(|_| node.supports_multiview());
+            context.pipeline_cache = renderer
+                .pipeline_cache
+                .as_ref()
+                .map(|cache| cache.cache.clone());
+            context.indirect_execution = renderer
+                .adapter
+                .get_downlevel_capabilities()
+                .flags
+                .contains(DownlevelFlags::INDIRECT_EXECUTION);
 
             node.build(
                 scene,
@@ -225,13 +839,48 @@ impl RenderFlow {
                     queue: &renderer.queue,
                     node: context,
                     targets,
+                    material_override: self.material_override.as_deref(),
                 },
             );
         }
+
+        Ok(())
     }
 
+    /// Let every node reallocate its size dependent resources, without rebuilding the flow.
+    ///
+    /// `targets`, including the swap chain, must already be created at `new_size`.
+    pub fn resize(
+        &mut self,
+        renderer: &WgpuRenderer,
+        scene: &mut GpuScene,
+        targets: &RenderTargets,
+        new_size: UVec2,
+    ) {
+        self.capture = None;
+        for node in self.flow.values_mut() {
+            node.node.resize(
+                scene,
+                RenderContext {
+                    device: &renderer.device,
+                    queue: &renderer.queue,
+                    node: &mut node.context,
+                    targets,
+                    material_override: self.material_override.as_deref(),
+                },
+                new_size,
+            );
+        }
+    }
+
+    /// Prepare and draw every node, does nothing while paused, see [`RenderFlow::set_paused`].
     #[inline]
     pub fn run(&mut self, renderer: &WgpuRenderer, scene: &mut GpuScene, targets: &RenderTargets) {
+        if self.paused {
+            return;
+        }
+        let start = self.start_frame(scene, targets);
+
         for node in self.flow.values_mut() {
             node.node.prepare(
                 scene,
@@ -240,11 +889,15 @@ impl RenderFlow {
                     queue: &renderer.queue,
                     node: &mut node.context,
                     targets,
+                    material_override: self.material_override.as_deref(),
                 },
             );
         }
 
         for node in self.flow.values_mut() {
+            if let Some(redirect) = &node.redirect {
+                redirect.begin(renderer, targets.swap_chain);
+            }
             node.node.draw(
                 scene,
                 RenderContext {
@@ -252,9 +905,195 @@ impl RenderFlow {
                     queue: &renderer.queue,
                     node: &mut node.context,
                     targets,
+                    material_override: self.material_override.as_deref(),
                 },
             );
+            if let Some(redirect) = &node.redirect {
+                redirect.end(renderer, scene, targets.swap_chain);
+            }
         }
+
+        self.end_frame(renderer, scene, start);
+    }
+
+    /// Same as [`RenderFlow::run`], but nodes implementing [`RenderNode::record`] are recorded
+    /// on the rayon thread pool, and all command buffers are submitted in flow order.
+    ///
+    /// Depth, normal and motion vector prepasses, linear depth, skinning and shadow mapping
+    /// are recorded in parallel. Post processing nodes ping-pong the swap chain while drawing,
+    /// so they keep drawing in order on the calling thread, as do nodes writing buffers or
+    /// bind groups in `draw` and nodes not opting in with [`RenderNode::parallel_safe`].
+    /// Only recording nodes are handed to the pool.
+    pub fn run_parallel(
+        &mut self,
+        renderer: &WgpuRenderer,
+        scene: &mut GpuScene,
+        targets: &RenderTargets,
+    ) {
+        if self.paused {
+            return;
+        }
+        let start = self.start_frame(scene, targets);
+        let material_override = self.material_override.as_deref();
+
+        for node in self.flow.values_mut() {
+            node.node.prepare(
+                scene,
+                RenderContext {
+                    device: &renderer.device,
+                    queue: &renderer.queue,
+                    node: &mut node.context,
+                    targets,
+                    material_override,
+                },
+            );
+        }
+
+        // `Some` for nodes recorded on the pool, holding whatever they recorded.
+        let mut recorded = Vec::new();
+        recorded.resize_with(self.flow.len(), || None);
+        rayon::in_place_scope(|s| {
+            let scene = &*scene;
+            for (
+                PackedRenderNode {
+                    node,
+                    context,
+                    redirect,
+                    ..
+                },
+                commands,
+            ) in self.flow.values_mut().zip(&mut recorded)
+            {
+                let node = &**node;
+                // Redirected nodes draw into the swap chain texture swapped in by
+                // `OutputRedirect::begin`.
+                if node.records() && node.parallel_safe() && redirect.is_none() {
+                    s.spawn(move |_| {
+                        *commands = Some(node.record(
+                            scene,
+                            RenderContext {
+                                device: &renderer.device,
+                                queue: &renderer.queue,
+                                node: context,
+                                targets,
+                                material_override,
+                            },
+                        ));
+                    });
+                }
+            }
+        });
+
+        let mut pending = Vec::new();
+        for (node, commands) in self.flow.values_mut().zip(recorded) {
+            if let Some(redirect) = &node.redirect {
+                renderer.queue.submit(pending.drain(..));
+                redirect.begin(renderer, targets.swap_chain);
+            }
+            match commands {
+                Some(commands) => pending.extend(commands),
+                None => {
+                    renderer.queue.submit(pending.drain(..));
+                    node.node.draw(
+                        scene,
+                        RenderContext {
+                            device: &renderer.device,
+                            queue: &renderer.queue,
+                            node: &mut node.context,
+                            targets,
+                            material_override,
+                        },
+                    );
+                }
+            }
+            if let Some(redirect) = &node.redirect {
+                renderer.queue.submit(pending.drain(..));
+                redirect.end(renderer, scene, targets.swap_chain);
+            }
+        }
+        renderer.queue.submit(pending);
+
+        self.end_frame(renderer, scene, start);
+    }
+
+    /// Run the flow once for each eye, e.g. the views located by an XR runtime,
+    /// rendering into the targets of that eye.
+    ///
+    /// There's no XR integration, acquiring the swapchain image of each eye, wrapping it in
+    /// [`RenderTargets`] and submitting it back to the runtime is left to the caller.
+    /// This is the two pass approach, so CPU cost of preparing and recording doubles.
+    /// Nodes keeping history across frames, like motion vector prepass or TAA,
+    /// see the eyes as consecutive frames and should be left out of stereo flows.
+    pub fn run_stereo(
+        &mut self,
+        renderer: &WgpuRenderer,
+        scene: &mut GpuScene,
+        eyes: &[(Camera, &RenderTargets)],
+    ) {
+        let camera = scene.original.camera;
+        for (eye, targets) in eyes {
+            scene.original.camera = *eye;
+            self.run(renderer, scene, targets);
+        }
+        scene.original.camera = camera;
+    }
+
+    /// Run the flow offscreen, blocking until the final image is read back.
+    ///
+    /// Meant for renders like thumbnails. The flow is built against `scene` and its current
+    /// static meshes on the first call, and again whenever `size` changes, nodes are added,
+    /// or it was built or resized for other targets in between. Other calls only prepare
+    /// and draw. Renders even while paused. The queue is left as it was, and the next
+    /// [`RenderFlow::build`] builds the flow for its targets again.
+    pub fn capture_sync(
+        &mut self,
+        renderer: &WgpuRenderer,
+        scene: &mut GpuScene,
+        size: UVec2,
+    ) -> Result<RgbaImage, BuildError> {
+        let queues = self
+            .flow
+            .values()
+            .map(|node| node.queue.clone())
+            .collect::<Vec<_>>();
+        self.set_queue(scene.static_meshes.clone());
+
+        let image = self.capture_queued(renderer, scene, size);
+
+        self.is_built = false;
+        for (node, queue) in self.flow.values_mut().zip(queues) {
+            node.context.meshes = queue.clone();
+            node.queue = queue;
+        }
+        image
+    }
+
+    fn capture_queued(
+        &mut self,
+        renderer: &WgpuRenderer,
+        scene: &mut GpuScene,
+        size: UVec2,
+    ) -> Result<RgbaImage, BuildError> {
+        let capture = match self.capture.take() {
+            Some(capture) if capture.size() == size => capture,
+            _ => {
+                let capture = CaptureTargets::new(&renderer.device, size);
+                self.force_build(renderer, scene, None, &capture.targets())?;
+                capture
+            }
+        };
+
+        let paused = std::mem::take(&mut self.paused);
+        self.run(renderer, scene, &capture.targets());
+        self.paused = paused;
+
+        let image = pollster::block_on(util::read_color_texture(
+            &capture.surface,
+            &renderer.device,
+            &renderer.queue,
+        ));
+        self.capture = Some(capture);
+        Ok(image)
     }
 }
 
@@ -263,7 +1102,7 @@ pub enum DependencyNodeIndex {
     After,
 }
 
-pub trait RenderNode: 'static {
+pub trait RenderNode: Any + Send + Sync {
     fn identifier(&self) -> TypeId {
         TypeId::of::<Self>()
     }
@@ -278,7 +1117,7 @@ pub trait RenderNode: 'static {
         None
     }
 
-    /// Add nodes as dependencies.
+    /// Add nodes as dependencies, unless the flow already contains them.
     fn add_node_dependencies(&self) -> Vec<(DependencyNodeIndex, Box<dyn RenderNode>)> {
         Vec::new()
     }
@@ -286,6 +1125,10 @@ pub trait RenderNode: 'static {
     /// Add required features
     fn require_renderer_features(&self, _features: &mut Features) {}
 
+    /// Add features used when the adapter supports them, check `device.features()` when
+    /// building to fall back otherwise.
+    fn request_renderer_features(&self, _features: &mut Features) {}
+
     /// Add required limits
     fn require_renderer_limits(&self, _limits: &mut Limits) {}
 
@@ -297,39 +1140,130 @@ pub trait RenderNode: 'static {
         Vec::new()
     }
 
-    /// Construct required shader, returns (dependencies, main_shader)
-    fn require_shaders(&self) -> Option<&'static [(&'static [&'static str], &'static str)]> {
+    /// Construct required shader, returns (dependencies, main_shader)
+    ///
+    /// `aurora::color_space` can always be imported, without listing it as a dependency.
+    fn require_shaders(&self) -> Option<&'static [(&'static [&'static str], &'static str)]> {
+        None
+    }
+
+    /// Build the node.
+    fn build(&mut self, _scene: &mut GpuScene, _context: RenderContext) {}
+
+    /// Recreate resources depending on the size of render targets, like intermediate textures.
+    ///
+    /// Called by [`RenderFlow::resize`], `context.targets` is already at `new_size`.
+    fn resize(&mut self, _scene: &mut GpuScene, _context: RenderContext, _new_size: UVec2) {}
+
+    /// Lower settings for `quality`, or restore the configured ones at [`Quality::High`].
+    ///
+    /// Called by the frame budget, see [`RenderFlow::set_frame_budget`]. Returns whether
+    /// the node needs to be built again for the change to take effect.
+    fn set_quality(&mut self, _quality: Quality) -> bool {
+        false
+    }
+
+    /// Whether the camera projection needs a different sub-pixel jitter every frame, like
+    /// for temporal antialiasing. See [`RenderFlow::set_jitter`].
+    fn require_jitter(&self) -> bool {
+        false
+    }
+
+    /// Whether the flow only hands this node the meshes in view of the camera, see
+    /// [`RenderFlow::set_culling`].
+    ///
+    /// Nodes rendering from other viewpoints, like shadow maps, return `false` and cull
+    /// against their own frustums when [`NodeContext::culling`] is set.
+    fn cull_to_camera(&self) -> bool {
+        true
+    }
+
+    /// Prepare bind groups and other assets for rendering.
+    fn prepare(&mut self, _scene: &mut GpuScene, _context: RenderContext) {}
+
+    /// Draw meshes.
+    ///
+    /// Submits the commands from [`RenderNode::record`] by default.
+    fn draw(&self, scene: &mut GpuScene, context: RenderContext) {
+        let queue = context.queue;
+        if let Some(commands) = self.record(scene, context) {
+            queue.submit([commands]);
+        }
+    }
+
+    /// Record draw commands without submitting them.
+    ///
+    /// Nodes implementing this instead of [`RenderNode::draw`] can be recorded on worker
+    /// threads by [`RenderFlow::run_parallel`], so they must only read state set up in
+    /// `build` and `prepare`, not anything written by earlier nodes during drawing.
+    /// Nodes implementing it also return `true` from [`RenderNode::records`].
+    fn record(&self, _scene: &GpuScene, _context: RenderContext) -> Option<CommandBuffer> {
         None
     }
 
-    /// Build the node.
-    fn build(&mut self, _scene: &mut GpuScene, _context: RenderContext) {}
+    /// Whether the node implements [`RenderNode::record`].
+    ///
+    /// [`RenderFlow::run_parallel`] only hands these to worker threads, and doesn't draw them
+    /// afterwards when they recorded nothing, so no node is recorded twice.
+    fn records(&self) -> bool {
+        false
+    }
 
-    /// Prepare bind groups and other assets for rendering.
-    fn prepare(&mut self, _scene: &mut GpuScene, _context: RenderContext) {}
+    /// Whether [`RenderNode::record`] may run on a worker thread, before earlier nodes drew.
+    ///
+    /// Only return `true` if recording doesn't read state earlier nodes change while drawing,
+    /// like the current swap chain texture. Otherwise the node is drawn in order on the
+    /// calling thread, which is the default.
+    fn parallel_safe(&self) -> bool {
+        false
+    }
 
-    /// Draw meshes.
-    fn draw(&self, _scene: &mut GpuScene, _context: RenderContext) {}
+    /// Whether the node draws both eyes of a stereo flow in a single pass, and so gets
+    /// [`NodeContext::multiview`] set. Other nodes see the left eye, like a mono flow.
+    fn supports_multiview(&self) -> bool {
+        false
+    }
+
+    /// Whether the node writes [`RenderTargets::surface`], like presenting the frame,
+    /// rather than the swap chain. See [`RenderFlow::redirect_output`].
+    fn writes_surface(&self) -> bool {
+        false
+    }
 }
 
 /// Prepares camera, lights and post process bind groups.
 pub struct GeneralNode {
     pub last_update: Instant,
+    /// Where [`GpuScene::time`] and [`GpuScene::delta_time`] come from,
+    /// [`Instant::now`] by default.
+    pub clock: Arc<dyn Fn() -> Instant + Send + Sync>,
 }
 
 impl Default for GeneralNode {
     fn default() -> Self {
+        Self::with_clock(Instant::now)
+    }
+}
+
+impl GeneralNode {
+    pub fn with_clock(clock: impl Fn() -> Instant + Send + Sync + 'static) -> Self {
         Self {
-            last_update: Instant::now(),
+            last_update: clock(),
+            clock: Arc::new(clock),
         }
     }
 }
 
 impl RenderNode for GeneralNode {
+    /// Uploads both eyes for the nodes drawing them.
+    fn supports_multiview(&self) -> bool {
+        true
+    }
+
     fn build(
         &mut self,
         GpuScene { assets, .. }: &mut GpuScene,
-        RenderContext { device, .. }: RenderContext,
+        RenderContext { device, node, .. }: RenderContext,
     ) {
         for (id, mesh) in &assets.meshes {
             if !assets.gpu_meshes.contains_key(id) {
@@ -338,38 +1272,64 @@ impl RenderNode for GeneralNode {
                         *id,
                         GpuMesh {
                             vertex_buffer,
+                            vertex_ranges: mesh.vertex_buffer_ranges(),
+                            buffer_layout: mesh.buffer_layout(),
+                            position_buffer: match mesh.buffer_layout() {
+                                MeshBufferLayout::Interleaved => {
+                                    mesh.create_position_buffer(device)
+                                }
+                                MeshBufferLayout::Separate => None,
+                            },
+                            previous_vertex_buffer: None,
                             index_buffer: mesh.create_index_buffer(device),
                             vertices_count: mesh.vertices_count() as u32,
+                            aabb: mesh.aabb(),
                         },
                     );
                 }
             }
         }
 
-        assets.common_layout = Some(device.create_bind_group_layout(&BindGroupLayoutDescriptor {
-            label: Some("common_layout"),
-            entries: &[
-                BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: ShaderStages::VERTEX_FRAGMENT,
-                    ty: BindingType::Buffer {
-                        ty: BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: Some(GpuCamera::min_size()),
-                    },
-                    count: None,
+        let mut common_entries = vec![
+            BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::VERTEX_FRAGMENT,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: Some(GpuCamera::min_size()),
                 },
-                BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: ShaderStages::VERTEX_FRAGMENT,
-                    ty: BindingType::Buffer {
-                        ty: BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: Some(GpuSceneDesc::min_size()),
-                    },
-                    count: None,
+                count: None,
+            },
+            BindGroupLayoutEntry {
+                binding: 1,
+                visibility: ShaderStages::VERTEX_FRAGMENT,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: Some(GpuSceneDesc::min_size()),
                 },
-            ],
+                count: None,
+            },
+        ];
+
+        if node.multiview.is_some() {
+            // Same buffer as binding 0, seen as one camera per eye.
+            common_entries.push(BindGroupLayoutEntry {
+                binding: 2,
+                visibility: ShaderStages::VERTEX_FRAGMENT,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: Some(<[GpuCamera; 2]>::min_size()),
+                },
+                count: None,
+            });
+        }
+
+        assets.common_layout = Some(device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("common_layout"),
+            entries: &common_entries,
         }));
 
         assets.lights_layout = Some(device.create_bind_group_layout(&BindGroupLayoutDescriptor {
@@ -418,14 +1378,21 @@ impl RenderNode for GeneralNode {
             original,
             assets,
             delta_time,
+            time,
             frame_count,
             ..
         }: &mut GpuScene,
-        RenderContext { device, queue, .. }: RenderContext,
+        RenderContext {
+            device,
+            queue,
+            node,
+            ..
+        }: RenderContext,
     ) {
-        let now = Instant::now();
+        let now = (self.clock)();
         *delta_time = (now - self.last_update).as_secs_f32();
         self.last_update = now;
+        *time += *delta_time;
         *frame_count += 1;
 
         assets.directional_light_buffer.clear();
@@ -445,17 +1412,30 @@ impl RenderNode for GeneralNode {
         }
 
         assets.camera_uniform.clear();
-        assets
-            .camera_uniform
-            .push(&<Camera as Into<GpuCamera>>::into(original.camera));
+        if node.multiview.is_some() {
+            assets
+                .camera_uniform
+                .push(&original.eyes.map(<Camera as Into<GpuCamera>>::into));
+        } else {
+            assets
+                .camera_uniform
+                .push(&<Camera as Into<GpuCamera>>::into(original.camera));
+        }
         assets.scene_desc_uniform.clear();
         assets.scene_desc_uniform.push(&GpuSceneDesc {
             dir_lights: original.dir_lights.len() as u32,
             point_lights: original.point_lights.len() as u32,
             spot_lights: original.spot_lights.len() as u32,
+            time: *time,
         });
 
-        assets.camera_uniform.write::<GpuCamera>(&device, &queue);
+        if node.multiview.is_some() {
+            assets
+                .camera_uniform
+                .write::<[GpuCamera; 2]>(&device, &queue);
+        } else {
+            assets.camera_uniform.write::<GpuCamera>(&device, &queue);
+        }
         assets
             .scene_desc_uniform
             .write::<GpuSceneDesc>(&device, &queue);
@@ -486,19 +1466,28 @@ impl RenderNode for GeneralNode {
             return;
         };
 
+        let mut common_entries = vec![
+            BindGroupEntry {
+                binding: 0,
+                resource: bf_camera.clone(),
+            },
+            BindGroupEntry {
+                binding: 1,
+                resource: bf_gpu_scene_desc,
+            },
+        ];
+
+        if node.multiview.is_some() {
+            common_entries.push(BindGroupEntry {
+                binding: 2,
+                resource: bf_camera,
+            });
+        }
+
         assets.common_bind_group = Some(device.create_bind_group(&BindGroupDescriptor {
             label: Some("common_bind_group"),
             layout: assets.common_layout.as_ref().unwrap(),
-            entries: &[
-                BindGroupEntry {
-                    binding: 0,
-                    resource: bf_camera,
-                },
-                BindGroupEntry {
-                    binding: 1,
-                    resource: bf_gpu_scene_desc,
-                },
-            ],
+            entries: &common_entries,
         }));
 
         assets.light_bind_group = Some(device.create_bind_group(&BindGroupDescriptor {
@@ -535,6 +1524,7 @@ impl RenderNode for PostProcessGeneralNode {
             queue: _,
             node: _,
             targets: _,
+            ..
         }: RenderContext,
     ) {
         scene.assets.material_layouts.insert(
@@ -601,6 +1591,7 @@ impl RenderNode for ImageFallbackNode {
             queue,
             node: _,
             targets: _,
+            ..
         }: RenderContext,
     ) {
         scene.assets.textures.insert(
@@ -628,10 +1619,15 @@ impl RenderNode for ImageFallbackNode {
     }
 }
 
+/// Copies the current swap chain texture to the surface.
+///
+/// Values are copied as is, encoded to sRGB if [`RenderTargets::encodes_srgb`], so with
+/// [`RenderTargets::presents_hdr`] they should already be scRGB, as written by tonemapping.
 #[derive(Default)]
 pub struct PresentNode {
     pipeline: Option<RenderPipeline>,
-    bind_group: Option<BindGroup>,
+    layout: Option<BindGroupLayout>,
+    sampler: Option<Sampler>,
 }
 
 impl RenderNode for PresentNode {
@@ -641,7 +1637,7 @@ impl RenderNode for PresentNode {
 
     fn build(
         &mut self,
-        _scene: &mut GpuScene,
+        GpuScene { assets, .. }: &mut GpuScene,
         RenderContext {
             device,
             node,
@@ -649,10 +1645,14 @@ impl RenderNode for PresentNode {
             ..
         }: RenderContext,
     ) {
-        let sampler = device.create_sampler(&SamplerDescriptor {
-            label: Some("present_sampler"),
-            ..Default::default()
-        });
+        let sampler = util::create_sampler(
+            device,
+            &SamplerDescriptor {
+                label: Some("present_sampler"),
+                ..Default::default()
+            },
+            assets.max_anisotropy,
+        );
         let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
             label: Some("present_layout"),
             entries: &[
@@ -675,21 +1675,6 @@ impl RenderNode for PresentNode {
             ],
         });
 
-        let bind_group = device.create_bind_group(&BindGroupDescriptor {
-            label: Some("present_bind_group"),
-            layout: &layout,
-            entries: &[
-                BindGroupEntry {
-                    binding: 0,
-                    resource: BindingResource::TextureView(&targets.swap_chain.current_view()),
-                },
-                BindGroupEntry {
-                    binding: 1,
-                    resource: BindingResource::Sampler(&sampler),
-                },
-            ],
-        });
-
         let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
             label: Some("present_pipeline_layout"),
             bind_group_layouts: &[&layout],
@@ -707,7 +1692,10 @@ impl RenderNode for PresentNode {
             },
             fragment: Some(FragmentState {
                 module: &node.shaders[0],
-                entry_point: "fragment",
+                entry_point: match targets.encodes_srgb() {
+                    true => "fragment_srgb",
+                    false => "fragment",
+                },
                 compilation_options: Default::default(),
                 targets: &[Some(ColorTargetState {
                     format: targets.surface_format,
@@ -719,16 +1707,17 @@ impl RenderNode for PresentNode {
             depth_stencil: Default::default(),
             multisample: Default::default(),
             multiview: Default::default(),
-            cache: Default::default(),
+            cache: node.pipeline_cache.as_deref(),
         });
 
         self.pipeline = Some(pipeline);
-        self.bind_group = Some(bind_group);
+        self.layout = Some(layout);
+        self.sampler = Some(sampler);
     }
 
     fn draw(
         &self,
-        _scene: &mut GpuScene,
+        GpuScene { assets, .. }: &mut GpuScene,
         RenderContext {
             device,
             queue,
@@ -736,6 +1725,25 @@ impl RenderNode for PresentNode {
             ..
         }: RenderContext,
     ) {
+        // Swap chain may be swapped by post processing nodes, so look the bind group up here.
+        let bind_group = assets.bind_group_cache.get_or_create(
+            device,
+            &BindGroupDescriptor {
+                label: Some("present_bind_group"),
+                layout: self.layout.as_ref().unwrap(),
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::TextureView(targets.swap_chain.current_view()),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::Sampler(self.sampler.as_ref().unwrap()),
+                    },
+                ],
+            },
+        );
+
         let mut command_encoder = device.create_command_encoder(&Default::default());
 
         {
@@ -753,10 +1761,665 @@ impl RenderNode for PresentNode {
             });
 
             pass.set_pipeline(self.pipeline.as_ref().unwrap());
-            pass.set_bind_group(0, self.bind_group.as_ref().unwrap(), &[]);
+            pass.set_bind_group(0, &bind_group, &[]);
             pass.draw(0..3, 0..1);
         }
 
         queue.submit([command_encoder.finish()]);
     }
+
+    fn writes_surface(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        any::TypeId,
+        sync::{
+            atomic::{AtomicU32, Ordering},
+            Arc, Mutex,
+        },
+        time::{Duration, Instant},
+    };
+
+    use glam::{UVec2, Vec2, Vec3};
+    use uuid::Uuid;
+    use wgpu::{
+        Color, CommandBuffer, Features, Limits, LoadOp, Maintain, Operations,
+        RenderPassColorAttachment, RenderPassDescriptor, StoreOp, Texture, TextureFormat,
+        TextureUsages,
+    };
+
+    use super::{
+        BuildError, GeneralNode, OutputRedirectError, PresentNode, RenderContext, RenderFlow,
+        RenderNode,
+    };
+    use crate::{
+        render::{
+            helper::Transform,
+            mesh::{Mesh, MeshVertexAttributeData, StaticMesh, DEFAULT_RENDER_LAYER},
+            resource::{BindGroupCache, BindGroupCacheStats},
+            scene::{GpuScene, MaterialInstanceId, MeshInstanceId, TextureId},
+        },
+        util::{
+            self,
+            testing::{self, TestTargets},
+            TextureReadback,
+        },
+    };
+
+    #[derive(Default)]
+    struct DemandingNode;
+
+    impl RenderNode for DemandingNode {
+        fn require_renderer_features(&self, features: &mut Features) {
+            *features |= Features::DEPTH_CLIP_CONTROL;
+        }
+
+        fn request_renderer_features(&self, features: &mut Features) {
+            *features |= Features::RG11B10UFLOAT_RENDERABLE;
+        }
+
+        fn require_renderer_limits(&self, limits: &mut Limits) {
+            limits.max_bind_groups = limits.max_bind_groups.max(6);
+        }
+    }
+
+    #[test]
+    fn required_features_and_limits() {
+        let mut flow = RenderFlow::default();
+        flow.add::<DemandingNode>();
+
+        assert_eq!(flow.required_features(), Features::DEPTH_CLIP_CONTROL);
+        assert_eq!(flow.optional_features(), Features::RG11B10UFLOAT_RENDERABLE);
+        assert_eq!(flow.required_limits().max_bind_groups, 6);
+
+        flow.set_stereo(true);
+        assert!(flow.required_features().contains(Features::MULTIVIEW));
+    }
+
+    #[derive(Default)]
+    struct TemporalNode;
+
+    impl RenderNode for TemporalNode {
+        fn require_jitter(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn camera_jitter() {
+        let size = UVec2::splat(64);
+        let mut scene = GpuScene::default();
+        let mut flow = RenderFlow::default();
+        flow.add::<DemandingNode>();
+        flow.apply_jitter(&mut scene, size);
+        assert_eq!(scene.original.camera.jitter, Vec2::ZERO);
+
+        flow.add::<TemporalNode>();
+        flow.apply_jitter(&mut scene, size);
+        let first = scene.original.camera.jitter;
+        // Within half a pixel, in NDC.
+        assert!(first != Vec2::ZERO && first.abs().cmple(Vec2::splat(1. / 64.)).all());
+
+        scene.frame_count += 1;
+        flow.apply_jitter(&mut scene, size);
+        assert_ne!(scene.original.camera.jitter, first);
+
+        flow.set_jitter(false);
+        flow.apply_jitter(&mut scene, size);
+        assert_eq!(scene.original.camera.jitter, Vec2::ZERO);
+    }
+
+    #[test]
+    fn render_layers() {
+        const GIZMO_LAYER: u32 = 1 << 1;
+
+        let mesh = |id, render_layer| StaticMesh {
+            mesh: MeshInstanceId(Uuid::from_u128(id)),
+            material: MaterialInstanceId::default(),
+            render_layer,
+            transform: Default::default(),
+        };
+
+        let mut flow = RenderFlow::default();
+        flow.add::<DemandingNode>()
+            .add::<TemporalNode>()
+            .set_render_layers::<TemporalNode>(DEFAULT_RENDER_LAYER);
+        flow.set_queue(vec![
+            mesh(0, DEFAULT_RENDER_LAYER),
+            mesh(1, GIZMO_LAYER),
+            mesh(2, DEFAULT_RENDER_LAYER | GIZMO_LAYER),
+        ]);
+
+        let ids = |node: TypeId| {
+            flow.flow[&node]
+                .context
+                .meshes
+                .iter()
+                .map(|m| m.mesh.mesh.0.as_u128())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(TypeId::of::<DemandingNode>()), [0, 1, 2]);
+        assert_eq!(ids(TypeId::of::<TemporalNode>()), [0, 2]);
+    }
+
+    /// Commands filling the current swap chain texture with a color.
+    fn fill(context: &RenderContext, color: Color) -> CommandBuffer {
+        let mut encoder = context.device.create_command_encoder(&Default::default());
+        encoder.begin_render_pass(&RenderPassDescriptor {
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: context.targets.swap_chain.current_view(),
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Clear(color),
+                    store: StoreOp::Store,
+                },
+            })],
+            ..Default::default()
+        });
+        encoder.finish()
+    }
+
+    /// Fills the current swap chain texture with a color.
+    struct FillNode(Color);
+
+    impl RenderNode for FillNode {
+        fn draw(&self, _scene: &mut GpuScene, context: RenderContext) {
+            context.queue.submit([fill(&context, self.0)]);
+        }
+    }
+
+    /// Fills the swap chain like [`FillNode`], but from [`RenderNode::record`].
+    struct RecordedFillNode(Color);
+
+    impl RenderNode for RecordedFillNode {
+        fn records(&self) -> bool {
+            true
+        }
+
+        fn record(&self, _scene: &GpuScene, context: RenderContext) -> Option<CommandBuffer> {
+            Some(fill(&context, self.0))
+        }
+    }
+
+    /// Fills the swap chain on a worker thread if there's a color, counting recordings.
+    /// `N` tells the nodes in a flow apart, and the first ones take longest to record.
+    struct ParallelFillNode<const N: u64> {
+        color: Option<Color>,
+        recordings: Arc<AtomicU32>,
+    }
+
+    impl<const N: u64> ParallelFillNode<N> {
+        fn new(recordings: &Arc<AtomicU32>, color: Option<Color>) -> Self {
+            Self {
+                color,
+                recordings: recordings.clone(),
+            }
+        }
+    }
+
+    impl<const N: u64> RenderNode for ParallelFillNode<N> {
+        fn records(&self) -> bool {
+            true
+        }
+
+        fn record(&self, _scene: &GpuScene, context: RenderContext) -> Option<CommandBuffer> {
+            self.recordings.fetch_add(1, Ordering::Relaxed);
+            std::thread::sleep(Duration::from_millis(30 / (N + 1)));
+            self.color.map(|color| fill(&context, color))
+        }
+
+        fn parallel_safe(&self) -> bool {
+            true
+        }
+    }
+
+    /// Flips the swap chain while drawing, like post processing.
+    #[derive(Default)]
+    struct SwapNode;
+
+    impl RenderNode for SwapNode {
+        fn draw(&self, _scene: &mut GpuScene, context: RenderContext) {
+            context.targets.swap_chain.swap();
+        }
+    }
+
+    /// Fills the destination of a post process with a color, ignoring the source.
+    struct PostProcessFillNode(Color);
+
+    impl RenderNode for PostProcessFillNode {
+        fn draw(&self, _scene: &mut GpuScene, context: RenderContext) {
+            let post_process = context.targets.swap_chain.start_post_process();
+            let mut encoder = context.device.create_command_encoder(&Default::default());
+            encoder.begin_render_pass(&RenderPassDescriptor {
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: post_process.dst,
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Clear(self.0),
+                        store: StoreOp::Store,
+                    },
+                })],
+                ..Default::default()
+            });
+            context.queue.submit([encoder.finish()]);
+        }
+    }
+
+    /// Another type, so it can be in the same flow as [`FillNode`].
+    struct BackgroundNode(FillNode);
+
+    impl RenderNode for BackgroundNode {
+        fn draw(&self, scene: &mut GpuScene, context: RenderContext) {
+            self.0.draw(scene, context);
+        }
+    }
+
+    #[test]
+    fn redirect_output() {
+        let Some(renderer) = testing::renderer(None, None) else {
+            return;
+        };
+
+        let size = UVec2::splat(4);
+        let format = TextureFormat::Rgba8Unorm;
+        let test_targets = TestTargets::new(&renderer.device, size, format);
+        let targets = test_targets.targets();
+
+        let mut scene = GpuScene::default();
+        let texture = TextureId(Uuid::from_u128(1));
+        let create_texture = |format| {
+            util::create_texture(
+                &renderer.device,
+                size.extend(1),
+                format,
+                TextureUsages::COPY_DST | TextureUsages::COPY_SRC,
+            )
+        };
+
+        // Red ends up in the texture only, later nodes still see green.
+        let mut flow = RenderFlow::default();
+        flow.add_initialized(BackgroundNode(FillNode(Color::GREEN)))
+            .add_initialized(FillNode(Color::RED))
+            .redirect_output::<FillNode>(Some(texture));
+        scene
+            .assets
+            .textures
+            .insert(texture, create_texture(TextureFormat::Rgba8UnormSrgb));
+        assert!(matches!(
+            flow.validate_redirects(&scene, &targets),
+            Err(OutputRedirectError::FormatMismatch { .. })
+        ));
+
+        scene
+            .assets
+            .textures
+            .insert(texture, create_texture(format));
+        flow.build(&renderer, &mut scene, None, &targets).unwrap();
+        flow.run(&renderer, &mut scene, &targets);
+
+        let read = |texture: &Texture| {
+            pollster::block_on(util::read_color_texture(
+                texture,
+                &renderer.device,
+                &renderer.queue,
+            ))
+            .get_pixel(0, 0)
+            .0
+        };
+        assert_eq!(read(&scene.assets.textures[&texture]), [255, 0, 0, 255]);
+        assert_eq!(
+            read(test_targets.swap_chain.current_texture()),
+            [0, 255, 0, 255]
+        );
+
+        // Post processing writes the other texture, the input is kept all the same.
+        let mut flow = RenderFlow::default();
+        flow.add_initialized(BackgroundNode(FillNode(Color::GREEN)))
+            .add_initialized(PostProcessFillNode(Color::BLUE))
+            .redirect_output::<PostProcessFillNode>(Some(texture));
+        flow.build(&renderer, &mut scene, None, &targets).unwrap();
+        flow.run(&renderer, &mut scene, &targets);
+        assert_eq!(read(&scene.assets.textures[&texture]), [0, 0, 255, 255]);
+        assert_eq!(
+            read(test_targets.swap_chain.current_texture()),
+            [0, 255, 0, 255]
+        );
+
+        // Presenting writes the surface, which can't be redirected.
+        let mut flow = RenderFlow::default();
+        flow.add::<PresentNode>()
+            .redirect_output::<PresentNode>(Some(texture));
+        assert!(matches!(
+            flow.build(&renderer, &mut scene, None, &targets),
+            Err(BuildError::OutputRedirect(
+                OutputRedirectError::WritesSurface { .. }
+            ))
+        ));
+    }
+
+    #[test]
+    fn parallel_recording() {
+        let Some(renderer) = testing::renderer(None, None) else {
+            return;
+        };
+
+        let size = UVec2::splat(4);
+        let test_targets = TestTargets::new(&renderer.device, size, TextureFormat::Rgba8Unorm);
+        let targets = test_targets.targets();
+
+        // Not parallel safe by default, so it's recorded after the swap and the fill lands in
+        // the texture that's current now.
+        let mut scene = GpuScene::default();
+        let mut flow = RenderFlow::default();
+        flow.add::<SwapNode>()
+            .add_initialized(RecordedFillNode(Color::RED));
+        flow.build(&renderer, &mut scene, None, &targets).unwrap();
+        flow.run_parallel(&renderer, &mut scene, &targets);
+
+        let pixel = test_targets.read(&renderer).get_pixel(0, 0).0;
+        assert_eq!(pixel, [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn parallel_submission_order() {
+        let Some(renderer) = testing::renderer(None, None) else {
+            return;
+        };
+
+        let size = UVec2::splat(4);
+        let test_targets = TestTargets::new(&renderer.device, size, TextureFormat::Rgba8Unorm);
+        let targets = test_targets.targets();
+
+        // Later fills finish recording first, but are still submitted after earlier ones.
+        let recordings = Arc::new(AtomicU32::new(0));
+        let mut scene = GpuScene::default();
+        let mut flow = RenderFlow::default();
+        flow.add_initialized(ParallelFillNode::<0>::new(&recordings, Some(Color::RED)))
+            .add_initialized(ParallelFillNode::<1>::new(&recordings, Some(Color::BLUE)))
+            .add_initialized(FillNode(Color::GREEN))
+            .add_initialized(ParallelFillNode::<2>::new(&recordings, Some(Color::WHITE)))
+            .add_initialized(ParallelFillNode::<3>::new(&recordings, None));
+        flow.build(&renderer, &mut scene, None, &targets).unwrap();
+
+        let read = || test_targets.read(&renderer).get_pixel(0, 0).0;
+        flow.run_parallel(&renderer, &mut scene, &targets);
+        assert_eq!(read(), [255, 255, 255, 255]);
+        // Recording nothing doesn't fall back to drawing, which would record again.
+        assert_eq!(recordings.load(Ordering::Relaxed), 4);
+
+        flow.add_initialized(BackgroundNode(FillNode(Color::BLACK)));
+        flow.build(&renderer, &mut scene, None, &targets).unwrap();
+        flow.run_parallel(&renderer, &mut scene, &targets);
+        assert_eq!(read(), [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn pause_and_resume() {
+        let Some(renderer) = testing::renderer(None, None) else {
+            return;
+        };
+
+        let size = UVec2::splat(4);
+        let test_targets = TestTargets::new(&renderer.device, size, TextureFormat::Rgba8Unorm);
+        let targets = test_targets.targets();
+
+        let now = Arc::new(Mutex::new(Instant::now()));
+        let advance = |secs: f32| {
+            let mut now = now.lock().unwrap();
+            *now += Duration::from_secs_f32(secs);
+        };
+        let clock = now.clone();
+
+        let mut scene = GpuScene::default();
+        let mut flow = RenderFlow::default();
+        flow.add_initialized(GeneralNode::with_clock(move || *clock.lock().unwrap()));
+        flow.build(&renderer, &mut scene, None, &targets).unwrap();
+        advance(0.1);
+        flow.run(&renderer, &mut scene, &targets);
+        assert_eq!(scene.frame_count, 1);
+        assert!((scene.time - 0.1).abs() < 1e-4, "{}", scene.time);
+
+        flow.set_paused(true);
+        advance(5.);
+        flow.run(&renderer, &mut scene, &targets);
+        flow.run_parallel(&renderer, &mut scene, &targets);
+        assert_eq!(scene.frame_count, 1);
+
+        // The time spent paused is skipped.
+        flow.set_paused(false);
+        advance(0.1);
+        flow.run(&renderer, &mut scene, &targets);
+        assert_eq!(scene.frame_count, 2);
+        assert!((scene.time - 0.2).abs() < 1e-4, "{}", scene.time);
+
+        flow.set_paused(true);
+        advance(5.);
+        flow.resume(&renderer, &mut scene, &targets);
+        assert!(!flow.is_paused());
+        assert_eq!(scene.frame_count, 3);
+        assert!((scene.time - 0.2).abs() < 1e-4, "{}", scene.time);
+
+        // Resuming renders a full frame, the next run doesn't prepare twice.
+        advance(0.1);
+        flow.run(&renderer, &mut scene, &targets);
+        assert_eq!(scene.frame_count, 4);
+    }
+
+    /// Counts how often it's built.
+    #[derive(Default)]
+    struct BuildCounterNode(Arc<AtomicU32>);
+
+    impl RenderNode for BuildCounterNode {
+        fn build(&mut self, _scene: &mut GpuScene, _context: RenderContext) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn capture_builds_once_per_size() {
+        let Some(renderer) = testing::renderer(None, None) else {
+            return;
+        };
+
+        let builds = Arc::new(AtomicU32::new(0));
+        let mut scene = GpuScene::default();
+        let mut flow = RenderFlow::default();
+        flow.add_initialized(FillNode(Color::RED))
+            .add_initialized(BuildCounterNode(builds.clone()))
+            .add::<PresentNode>();
+
+        for _ in 0..3 {
+            let image = flow
+                .capture_sync(&renderer, &mut scene, UVec2::splat(4))
+                .unwrap();
+            assert_eq!(image.get_pixel(0, 0).0, [255, 0, 0, 255]);
+        }
+        assert_eq!(builds.load(Ordering::Relaxed), 1);
+
+        let image = flow
+            .capture_sync(&renderer, &mut scene, UVec2::splat(8))
+            .unwrap();
+        assert_eq!(image.dimensions(), (8, 8));
+        assert_eq!(builds.load(Ordering::Relaxed), 2);
+    }
+
+    /// Counts the meshes queued when building.
+    struct QueuedMeshesNode(Arc<AtomicU32>);
+
+    impl RenderNode for QueuedMeshesNode {
+        fn build(&mut self, _scene: &mut GpuScene, context: RenderContext) {
+            self.0
+                .store(context.node.meshes.len() as u32, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn build_after_capture() {
+        let Some(renderer) = testing::renderer(None, None) else {
+            return;
+        };
+
+        let queued = Arc::new(AtomicU32::new(0));
+        let mut scene = GpuScene::default();
+        scene.static_meshes.push(StaticMesh {
+            mesh: MeshInstanceId(Uuid::new_v4()),
+            material: MaterialInstanceId::default(),
+            render_layer: DEFAULT_RENDER_LAYER,
+            transform: Default::default(),
+        });
+        let mut flow = RenderFlow::default();
+        flow.add_initialized(FillNode(Color::RED))
+            .add_initialized(QueuedMeshesNode(queued.clone()))
+            .add::<PresentNode>();
+        flow.set_queue(Vec::new());
+
+        // Captures the static meshes of the scene.
+        flow.capture_sync(&renderer, &mut scene, UVec2::splat(4))
+            .unwrap();
+        assert_eq!(queued.load(Ordering::Relaxed), 1);
+
+        // Presenting to another surface format than the capture fails validation, unless
+        // the flow is built for the window again, with the queue it had.
+        let window = TestTargets::new(&renderer.device, UVec2::splat(8), TextureFormat::Rgba8Unorm)
+            .with_surface_format(&renderer.device, TextureFormat::Bgra8UnormSrgb);
+        flow.build(&renderer, &mut scene, None, &window.targets())
+            .unwrap();
+        assert_eq!(queued.load(Ordering::Relaxed), 0);
+        flow.run(&renderer, &mut scene, &window.targets());
+
+        let readback =
+            TextureReadback::new(&window.surface, &renderer.device, &renderer.queue).unwrap();
+        renderer.device.poll(Maintain::wait()).panic_on_timeout();
+        let image = pollster::block_on(readback.read())
+            .unwrap()
+            .into_rgba_image()
+            .unwrap();
+        assert_eq!(image.get_pixel(0, 0).0, [255, 0, 0, 255]);
+    }
+
+    /// Renders from somewhere else than the camera, like shadow mapping.
+    #[derive(Default)]
+    struct LightViewNode;
+
+    impl RenderNode for LightViewNode {
+        fn cull_to_camera(&self) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn frustum_culling() {
+        let Some(renderer) = testing::renderer(None, None) else {
+            return;
+        };
+
+        // Triangles in front of and behind the camera, which looks down -Z.
+        let mut scene = GpuScene::default();
+        for (id, z) in [(0, -5.), (1, 5.)] {
+            let mesh = MeshInstanceId(Uuid::from_u128(id));
+            scene.assets.meshes.insert(
+                mesh,
+                Mesh::new().with_attribute(
+                    Mesh::POSITION_ATTR,
+                    MeshVertexAttributeData::Float32x3(vec![
+                        Vec3::new(-1., -1., z),
+                        Vec3::new(1., -1., z),
+                        Vec3::new(0., 1., z),
+                    ]),
+                ),
+            );
+            scene.static_meshes.push(StaticMesh {
+                mesh,
+                material: MaterialInstanceId::default(),
+                render_layer: DEFAULT_RENDER_LAYER,
+                transform: Default::default(),
+            });
+        }
+
+        let size = UVec2::splat(4);
+        let test_targets = TestTargets::new(&renderer.device, size, TextureFormat::Rgba8Unorm);
+        let targets = test_targets.targets();
+
+        let mut flow = RenderFlow::default();
+        flow.add::<GeneralNode>()
+            .add::<TemporalNode>()
+            .add::<LightViewNode>();
+        flow.set_jitter(false);
+        flow.set_queue(scene.static_meshes.clone());
+        flow.build(&renderer, &mut scene, None, &targets).unwrap();
+
+        let ids = |flow: &RenderFlow, node: TypeId| {
+            flow.flow[&node]
+                .context
+                .meshes
+                .iter()
+                .map(|m| m.mesh.mesh.0.as_u128())
+                .collect::<Vec<_>>()
+        };
+        let camera_node = TypeId::of::<TemporalNode>();
+        let light_node = TypeId::of::<LightViewNode>();
+
+        flow.run(&renderer, &mut scene, &targets);
+        assert_eq!(ids(&flow, camera_node), [0]);
+        assert_eq!(ids(&flow, light_node), [0, 1]);
+
+        scene.original.camera.transform = Transform::default().looking_at(Vec3::Z, Vec3::Y);
+        flow.run(&renderer, &mut scene, &targets);
+        assert_eq!(ids(&flow, camera_node), [1]);
+
+        scene.original.camera.transform = Transform::default().looking_at(Vec3::X, Vec3::Y);
+        flow.run(&renderer, &mut scene, &targets);
+        assert!(ids(&flow, camera_node).is_empty());
+        assert_eq!(ids(&flow, light_node), [0, 1]);
+
+        flow.set_culling(false);
+        flow.run(&renderer, &mut scene, &targets);
+        assert_eq!(ids(&flow, camera_node), [0, 1]);
+    }
+
+    #[test]
+    fn bind_group_cache() {
+        let Some(renderer) = testing::renderer(None, None) else {
+            return;
+        };
+
+        let size = UVec2::splat(4);
+        let test_targets = TestTargets::new(&renderer.device, size, TextureFormat::Rgba8Unorm);
+        let targets = test_targets.targets();
+
+        let mut scene = GpuScene::default();
+        let mut flow = RenderFlow::default();
+        flow.add::<PresentNode>();
+        flow.build(&renderer, &mut scene, None, &targets).unwrap();
+        let mut run = |scene: &mut GpuScene| {
+            flow.run(&renderer, scene, &targets);
+            scene.assets.bind_group_cache.stats()
+        };
+        let (created, reused) = (
+            BindGroupCacheStats {
+                created: 1,
+                reused: 0,
+            },
+            BindGroupCacheStats {
+                created: 0,
+                reused: 1,
+            },
+        );
+
+        assert_eq!(run(&mut scene), created);
+        assert_eq!(run(&mut scene), reused);
+
+        // Presenting the other texture, the first one is kept a while in case it comes back.
+        test_targets.swap_chain.swap();
+        assert_eq!(run(&mut scene), created);
+        assert_eq!(scene.assets.bind_group_cache.len(), 2);
+        for _ in 0..BindGroupCache::MAX_UNUSED_FRAMES {
+            assert_eq!(run(&mut scene), reused);
+        }
+        assert_eq!(scene.assets.bind_group_cache.len(), 1);
+    }
 }
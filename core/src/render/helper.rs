@@ -1,6 +1,6 @@
-use std::{collections::HashMap, rc::Rc};
+use std::{collections::HashMap, hash::Hash, sync::Arc};
 
-use glam::{Mat3, Mat4, Quat, Vec3};
+use glam::{BVec3, Mat3, Mat4, Quat, UVec2, Vec2, Vec3, Vec4};
 use uuid::Uuid;
 
 use crate::{
@@ -12,27 +12,188 @@ use crate::{
     util::cube::CUBE_MAP_FACES,
 };
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct Scene {
+    /// Camera the scene is rendered from.
     pub camera: Camera,
+    /// Stored cameras to switch between with [`Scene::set_active_camera`].
+    pub cameras: HashMap<Uuid, Camera>,
+    /// Stored camera `camera` was taken from, if any.
+    pub active_camera: Option<Uuid>,
+    /// Left and right eye, used instead of `camera` when the flow renders in stereo.
+    pub eyes: [Camera; 2],
     pub dir_lights: HashMap<Uuid, GpuDirectionalLight>,
     pub point_lights: HashMap<Uuid, GpuPointLight>,
     pub spot_lights: HashMap<Uuid, GpuSpotLight>,
-    pub materials: HashMap<MaterialInstanceId, Rc<dyn Material>>,
+    pub materials: HashMap<MaterialInstanceId, Arc<dyn Material>>,
 }
 
-#[derive(Debug, Default, Clone, Copy)]
+/// State of a [`Scene`] taken with [`Scene::snapshot`], for undo and redo.
+///
+/// Materials are shared with the scene rather than deep copied, so replace materials
+/// instead of mutating them in place to keep the snapshot intact.
+#[derive(Clone)]
+pub struct SceneSnapshot(Scene);
+
+/// Keys added, removed or modified between two versions of a map.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Changes<K> {
+    pub added: Vec<K>,
+    pub removed: Vec<K>,
+    pub modified: Vec<K>,
+}
+
+impl<K> Default for Changes<K> {
+    fn default() -> Self {
+        Self {
+            added: Vec::new(),
+            removed: Vec::new(),
+            modified: Vec::new(),
+        }
+    }
+}
+
+impl<K> Changes<K> {
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+}
+
+impl<K: Copy + Eq + Hash> Changes<K> {
+    fn compute<V>(old: &HashMap<K, V>, new: &HashMap<K, V>, eq: impl Fn(&V, &V) -> bool) -> Self {
+        let mut changes = Self::default();
+        for (key, value) in new {
+            match old.get(key) {
+                Some(old_value) if !eq(old_value, value) => changes.modified.push(*key),
+                Some(_) => {}
+                None => changes.added.push(*key),
+            }
+        }
+        changes.removed = old
+            .keys()
+            .filter(|k| !new.contains_key(k))
+            .copied()
+            .collect();
+        changes
+    }
+}
+
+/// Changes of a [`Scene`] since a [`SceneSnapshot`], see [`Scene::diff`].
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SceneDiff {
+    /// Whether `camera`, `eyes` or `active_camera` changed. Jitter is ignored.
+    pub camera: bool,
+    pub cameras: Changes<Uuid>,
+    pub dir_lights: Changes<Uuid>,
+    pub point_lights: Changes<Uuid>,
+    pub spot_lights: Changes<Uuid>,
+    /// Materials are compared by pointer.
+    pub materials: Changes<MaterialInstanceId>,
+}
+
+impl SceneDiff {
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        !self.camera
+            && self.cameras.is_empty()
+            && self.dir_lights.is_empty()
+            && self.point_lights.is_empty()
+            && self.spot_lights.is_empty()
+            && self.materials.is_empty()
+    }
+}
+
+impl Scene {
+    pub fn snapshot(&self) -> SceneSnapshot {
+        SceneSnapshot(self.clone())
+    }
+
+    /// Render from the stored camera `id`, returning `false` if there's none.
+    ///
+    /// The current camera is written back to the previously active one first, so moving it
+    /// around isn't lost when switching back. Cameras are uploaded every frame, no rebuild
+    /// is needed.
+    pub fn set_active_camera(&mut self, id: Uuid) -> bool {
+        let Some(camera) = self.cameras.get(&id).copied() else {
+            return false;
+        };
+        if let Some(previous) = self.active_camera.and_then(|id| self.cameras.get_mut(&id)) {
+            *previous = self.camera;
+        }
+        self.camera = camera;
+        self.active_camera = Some(id);
+        true
+    }
+
+    /// Restore the scene to `snapshot`.
+    ///
+    /// Lights and cameras are uploaded every frame, but materials added back need
+    /// to be prepared again by rebuilding the flow.
+    pub fn apply_snapshot(&mut self, snapshot: &SceneSnapshot) {
+        *self = snapshot.0.clone();
+    }
+
+    /// What changed from `snapshot` to the current scene.
+    pub fn diff(&self, snapshot: &SceneSnapshot) -> SceneDiff {
+        let old = &snapshot.0;
+        let unjittered = |camera: &Camera| Camera {
+            jitter: Vec2::ZERO,
+            ..*camera
+        };
+
+        SceneDiff {
+            camera: unjittered(&old.camera) != unjittered(&self.camera)
+                || old
+                    .eyes
+                    .iter()
+                    .map(unjittered)
+                    .ne(self.eyes.iter().map(unjittered))
+                || old.active_camera != self.active_camera,
+            cameras: Changes::compute(&old.cameras, &self.cameras, |a, b| {
+                unjittered(a) == unjittered(b)
+            }),
+            dir_lights: Changes::compute(&old.dir_lights, &self.dir_lights, PartialEq::eq),
+            point_lights: Changes::compute(&old.point_lights, &self.point_lights, PartialEq::eq),
+            spot_lights: Changes::compute(&old.spot_lights, &self.spot_lights, PartialEq::eq),
+            materials: Changes::compute(&old.materials, &self.materials, Arc::ptr_eq),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
 pub struct Camera {
     pub transform: Transform,
     pub projection: CameraProjection,
     /// Camera exposure in EV100
     pub exposure: Exposure,
+    /// Sub-pixel offset of the projection in NDC.
+    ///
+    /// Overwritten every frame by flows with nodes requiring jitter, see
+    /// [`RenderFlow::set_jitter`].
+    ///
+    /// [`RenderFlow::set_jitter`]: crate::render::flow::RenderFlow::set_jitter
+    pub jitter: Vec2,
+}
+
+/// Length of the Halton(2, 3) sequence the camera is jittered with.
+pub const JITTER_SEQUENCE_LENGTH: u32 = 8;
+
+fn halton(mut index: u32, base: u32) -> f32 {
+    let mut f = 1.;
+    let mut r = 0.;
+    while index > 0 {
+        f /= base as f32;
+        r += f * (index % base) as f32;
+        index /= base;
+    }
+    r
 }
 
 impl Into<GpuCamera> for Camera {
     fn into(self) -> GpuCamera {
         let inv_view = self.transform.compute_matrix();
-        let proj = self.projection.compute_matrix();
+        let proj = self.compute_projection();
 
         GpuCamera {
             view: inv_view.inverse(),
@@ -45,10 +206,133 @@ impl Into<GpuCamera> for Camera {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+impl Camera {
+    /// Projection matrix with [`Self::jitter`] applied.
+    #[inline]
+    pub fn compute_projection(&self) -> Mat4 {
+        Mat4::from_translation(self.jitter.extend(0.)) * self.projection.compute_matrix()
+    }
+
+    /// Jitter of frame `frame_index`, within half a pixel of `size`.
+    pub fn jitter_offset(frame_index: u32, size: UVec2) -> Vec2 {
+        let index = frame_index % JITTER_SEQUENCE_LENGTH + 1;
+        let offset = Vec2::new(halton(index, 2), halton(index, 3)) - 0.5;
+        offset * 2. / size.as_vec2()
+    }
+
+    /// Fit near and far planes of the projection to `bounds`.
+    ///
+    /// Planes are derived from the bounding sphere, so they stay valid when the camera rotates.
+    pub fn auto_near_far(&mut self, bounds: Aabb) {
+        let radius = bounds.half_extents().length();
+        let distance = self.transform.translation.distance(bounds.center());
+        let far = (distance + radius) * 1.01;
+        if far <= 0. {
+            return;
+        }
+        // Keep the depth range ratio bounded when the camera is inside the bounds.
+        let near = (distance - radius).max(far * 1e-4);
+
+        match &mut self.projection {
+            CameraProjection::Perspective(p) => (p.near, p.far) = (near, far),
+            CameraProjection::Orthographic(p) => (p.near, p.far) = (near, far),
+            CameraProjection::AsymmetricPerspective(p) => (p.near, p.far) = (near, far),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn from_points(points: impl IntoIterator<Item = Vec3>) -> Option<Self> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        Some(points.fold(
+            Self {
+                min: first,
+                max: first,
+            },
+            |aabb, p| Self {
+                min: aabb.min.min(p),
+                max: aabb.max.max(p),
+            },
+        ))
+    }
+
+    #[inline]
+    pub fn merge(&self, other: &Self) -> Self {
+        Self {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    #[inline]
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    #[inline]
+    pub fn half_extents(&self) -> Vec3 {
+        (self.max - self.min) * 0.5
+    }
+
+    /// Bounds of the corners moved by `matrix`.
+    pub fn transformed(&self, matrix: Mat4) -> Self {
+        Self::from_points((0..8).map(|i| {
+            matrix.transform_point3(Vec3::select(
+                BVec3::new(i & 1 != 0, i & 2 != 0, i & 4 != 0),
+                self.max,
+                self.min,
+            ))
+        }))
+        .unwrap()
+    }
+}
+
+/// Planes bounding a view volume, pointing inwards.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frustum {
+    /// Left, right, bottom, top, near and far planes, `xyz` is the normal and `w` the
+    /// distance, so points inside have `plane.dot(p.extend(1.)) >= 0.`.
+    pub planes: [Vec4; 6],
+}
+
+impl Frustum {
+    /// Extract the planes of clip space, with depth from 0 to 1, from `view_proj`.
+    pub fn from_view_proj(view_proj: Mat4) -> Self {
+        let [x, y, z, w] = [0, 1, 2, 3].map(|i| view_proj.row(i));
+        Self {
+            planes: [w + x, w - x, w + y, w - y, z, w - z].map(|p| p / p.truncate().length()),
+        }
+    }
+
+    /// Conservative test, boxes outside the frustum but crossing several planes pass.
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            // The corner furthest along the normal.
+            let corner = Vec3::select(plane.truncate().cmpge(Vec3::ZERO), aabb.max, aabb.min);
+            plane.dot(corner.extend(1.)) >= 0.
+        })
+    }
+}
+
+impl GpuCamera {
+    #[inline]
+    pub fn frustum(&self) -> Frustum {
+        Frustum::from_view_proj(self.proj * self.view)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum CameraProjection {
     Perspective(PerspectiveProjection),
     Orthographic(OrthographicProjection),
+    AsymmetricPerspective(AsymmetricPerspectiveProjection),
 }
 
 impl Default for CameraProjection {
@@ -63,11 +347,12 @@ impl CameraProjection {
         match self {
             CameraProjection::Perspective(p) => p.compute_matrix(),
             CameraProjection::Orthographic(p) => p.compute_matrix(),
+            CameraProjection::AsymmetricPerspective(p) => p.compute_matrix(),
         }
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct PerspectiveProjection {
     pub fov: f32,
     pub aspect_ratio: f32,
@@ -93,7 +378,43 @@ impl PerspectiveProjection {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+/// Off-center perspective projection, like the per-eye field of view reported by XR runtimes.
+///
+/// Angles are in radians from the view direction, so `angle_left` and `angle_down` are usually negative.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AsymmetricPerspectiveProjection {
+    pub angle_left: f32,
+    pub angle_right: f32,
+    pub angle_up: f32,
+    pub angle_down: f32,
+    pub near: f32,
+    pub far: f32,
+}
+
+impl AsymmetricPerspectiveProjection {
+    #[inline]
+    pub fn compute_matrix(&self) -> Mat4 {
+        let left = self.angle_left.tan();
+        let right = self.angle_right.tan();
+        let up = self.angle_up.tan();
+        let down = self.angle_down.tan();
+        let r = self.far / (self.near - self.far);
+
+        Mat4::from_cols(
+            Vec4::new(2. / (right - left), 0., 0., 0.),
+            Vec4::new(0., 2. / (up - down), 0., 0.),
+            Vec4::new(
+                (right + left) / (right - left),
+                (up + down) / (up - down),
+                r,
+                -1.,
+            ),
+            Vec4::new(0., 0., r * self.near, 0.),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct OrthographicProjection {
     pub left: f32,
     pub right: f32,
@@ -182,7 +503,7 @@ impl GpuSpotLight {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Transform {
     pub translation: Vec3,
     pub rotation: Quat,
@@ -233,6 +554,12 @@ impl Transform {
         Mat4::from_rotation_translation(self.rotation, self.translation)
     }
 
+    /// Unlike [`Transform::compute_matrix`], which places views, this includes the scale.
+    #[inline]
+    pub fn model_matrix(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+
     #[inline]
     pub fn local_move(&mut self, delta: Vec3) {
         self.translation += self.rotation.inverse().mul_vec3(delta);
@@ -294,7 +621,7 @@ impl Transform {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Exposure {
     pub ev100: f32,
 }
@@ -311,4 +638,80 @@ impl Exposure {
             ev100: (aperture * aperture * 100. / shutter_speed / sensitivity).log2(),
         }
     }
+
+    /// Factor the scene radiance is multiplied by in the PBR pass.
+    ///
+    /// Must match `apply_exposure` in `pbr_function.wgsl`.
+    pub fn multiplier(&self) -> f32 {
+        1. / (2f32.powf(self.ev100) * 1.2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Vec2, Vec3};
+    use uuid::Uuid;
+
+    use super::{Camera, Scene, Transform};
+    use crate::render::resource::GpuPointLight;
+
+    #[test]
+    fn scene_snapshot_diff() {
+        let light = GpuPointLight {
+            position: Vec3::ZERO,
+            color: Vec3::ONE,
+            intensity: 1.,
+            radius: 0.,
+        };
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+
+        let mut scene = Scene::default();
+        scene.point_lights.insert(a, light);
+        let snapshot = scene.snapshot();
+
+        scene.camera.jitter = Vec2::splat(0.1);
+        assert!(scene.diff(&snapshot).is_empty());
+
+        scene.point_lights.get_mut(&a).unwrap().intensity = 2.;
+        scene.point_lights.insert(b, light);
+        scene.camera.transform.translation = Vec3::X;
+        let diff = scene.diff(&snapshot);
+        assert!(diff.camera);
+        assert_eq!(diff.point_lights.modified, [a]);
+        assert_eq!(diff.point_lights.added, [b]);
+        assert!(diff.point_lights.removed.is_empty());
+
+        let redo = scene.snapshot();
+        scene.apply_snapshot(&snapshot);
+        assert!(scene.diff(&snapshot).is_empty());
+        assert_eq!(scene.diff(&redo).point_lights.removed, [b]);
+    }
+
+    #[test]
+    fn switch_active_camera() {
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        let camera = |translation| Camera {
+            transform: Transform::default().with_translation(translation),
+            ..Default::default()
+        };
+
+        let mut scene = Scene::default();
+        scene.cameras.insert(a, camera(Vec3::X));
+        scene.cameras.insert(b, camera(Vec3::Y));
+        assert!(!scene.set_active_camera(Uuid::new_v4()));
+        assert!(scene.set_active_camera(a));
+        assert_eq!(scene.camera, camera(Vec3::X));
+
+        let snapshot = scene.snapshot();
+        scene.camera.transform.translation = Vec3::Z;
+        assert!(scene.set_active_camera(b));
+        assert_eq!(scene.camera, camera(Vec3::Y));
+        let diff = scene.diff(&snapshot);
+        assert!(diff.camera);
+        assert_eq!(diff.cameras.modified, [a]);
+
+        // Moved while active.
+        assert!(scene.set_active_camera(a));
+        assert_eq!(scene.camera, camera(Vec3::Z));
+    }
 }
@@ -1,15 +1,25 @@
-use std::{any::TypeId, collections::BTreeMap};
+use std::{
+    any::TypeId,
+    collections::{BTreeMap, HashMap},
+    ops::Range,
+};
 
 use dyn_clone::DynClone;
 use glam::{IVec2, IVec3, IVec4, Mat3, Mat4, UVec2, UVec3, UVec4, Vec2, Vec3, Vec4};
 use log::warn;
+use naga_oil::compose::ShaderDefValue;
+use thiserror::Error;
 use wgpu::{
     util::{BufferInitDescriptor, DeviceExt},
-    Buffer, BufferUsages, Device, IndexFormat, VertexAttribute, VertexFormat,
+    Buffer, BufferAddress, BufferSlice, BufferUsages, Device, IndexFormat, RenderPass,
+    VertexAttribute, VertexBufferLayout, VertexFormat, VertexStepMode,
 };
 
 use crate::{
-    render::scene::{GpuAssets, MaterialInstanceId, MaterialTypeId, MeshInstanceId},
+    render::{
+        helper::{Aabb, Transform},
+        scene::{GpuAssets, MaterialInstanceId, MaterialTypeId, MeshInstanceId},
+    },
     util::ext::TypeIdAsUuid,
 };
 
@@ -109,6 +119,42 @@ impl MeshVertexAttributeData {
     pub fn size(&self) -> u64 {
         self.format().size()
     }
+
+    /// The values at `indices`, in order.
+    pub fn gather(&self, indices: &[usize]) -> Self {
+        fn gather<T: Copy>(vec: &[T], indices: &[usize]) -> Vec<T> {
+            indices.iter().map(|i| vec[*i]).collect()
+        }
+
+        match self {
+            MeshVertexAttributeData::Sint32(vec) => Self::Sint32(gather(vec, indices)),
+            MeshVertexAttributeData::Uint32(vec) => Self::Uint32(gather(vec, indices)),
+            MeshVertexAttributeData::Float32(vec) => Self::Float32(gather(vec, indices)),
+            MeshVertexAttributeData::Sint32x2(vec) => Self::Sint32x2(gather(vec, indices)),
+            MeshVertexAttributeData::Uint23x2(vec) => Self::Uint23x2(gather(vec, indices)),
+            MeshVertexAttributeData::Float32x2(vec) => Self::Float32x2(gather(vec, indices)),
+            MeshVertexAttributeData::Sint32x3(vec) => Self::Sint32x3(gather(vec, indices)),
+            MeshVertexAttributeData::Uint23x3(vec) => Self::Uint23x3(gather(vec, indices)),
+            MeshVertexAttributeData::Float32x3(vec) => Self::Float32x3(gather(vec, indices)),
+            MeshVertexAttributeData::Sint32x4(vec) => Self::Sint32x4(gather(vec, indices)),
+            MeshVertexAttributeData::Uint23x4(vec) => Self::Uint23x4(gather(vec, indices)),
+            MeshVertexAttributeData::Float32x4(vec) => Self::Float32x4(gather(vec, indices)),
+        }
+    }
+}
+
+/// How [`Mesh::recalculate_normals`] shares normals between the triangles around a vertex.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum NormalGenerationMode {
+    /// Averaged over the triangles sharing the vertex, rounding hard edges of shared
+    /// vertices.
+    #[default]
+    Smooth,
+    /// The face normal of each triangle, keeping every edge hard.
+    Flat,
+    /// Averaged over the triangles at the same position whose faces are within this angle,
+    /// in radians, of each other, so only edges sharper than it stay hard.
+    SmoothWithAngleThreshold(f32),
 }
 
 pub struct GpuIndexBuffer {
@@ -125,14 +171,143 @@ pub enum MeshIndices {
 
 pub struct GpuMesh {
     pub vertex_buffer: Buffer,
+    /// Bytes of `vertex_buffer` bound to each slot, see [`Mesh::vertex_buffer_layouts`].
+    pub vertex_ranges: Vec<Range<BufferAddress>>,
+    pub buffer_layout: MeshBufferLayout,
+    /// Tightly packed positions for depth only passes, from [`Mesh::create_position_buffer`].
+    ///
+    /// `None` for [`MeshBufferLayout::Separate`], which already has a position stream, and
+    /// when the vertices are deformed on the GPU, as only `vertex_buffer` is updated.
+    pub position_buffer: Option<Buffer>,
+    /// `vertex_buffer` as it was the previous frame, laid out the same, for motion vectors.
+    ///
+    /// Only kept when the vertices are deformed on the GPU, other meshes don't move on their
+    /// own, see [`Self::previous_position_vertices`].
+    pub previous_vertex_buffer: Option<Buffer>,
     pub index_buffer: Option<GpuIndexBuffer>,
     pub vertices_count: u32,
+    /// World space bounds for culling, from [`Mesh::aabb`].
+    pub aabb: Option<Aabb>,
+}
+
+impl GpuMesh {
+    /// Bind `vertex_buffer` to the slots of [`Mesh::vertex_buffer_layouts`], from 0.
+    pub fn set_vertex_buffers(&self, pass: &mut RenderPass) {
+        for (slot, range) in self.vertex_ranges.iter().enumerate() {
+            pass.set_vertex_buffer(slot as u32, self.vertex_buffer.slice(range.clone()));
+        }
+    }
+
+    /// Vertices and stride to draw positions from with [`Mesh::position_vertex_layout`].
+    ///
+    /// Interleaved vertices without a `position_buffer` are read `vertex_stride` apart.
+    pub fn position_vertices(&self, vertex_stride: u64) -> (BufferSlice<'_>, u64) {
+        let position_stride = Mesh::POSITION_ATTR.format.size();
+        match (&self.position_buffer, self.buffer_layout) {
+            (Some(positions), _) => (positions.slice(..), position_stride),
+            (None, MeshBufferLayout::Separate) => (
+                self.vertex_buffer.slice(self.vertex_ranges[0].clone()),
+                position_stride,
+            ),
+            (None, MeshBufferLayout::Interleaved) => (self.vertex_buffer.slice(..), vertex_stride),
+        }
+    }
+
+    /// Like [`Self::position_vertices`], but where the vertices were the previous frame.
+    ///
+    /// Without a `previous_vertex_buffer` these are the current positions.
+    pub fn previous_position_vertices(&self, vertex_stride: u64) -> (BufferSlice<'_>, u64) {
+        match (&self.previous_vertex_buffer, self.buffer_layout) {
+            (None, _) => self.position_vertices(vertex_stride),
+            (Some(previous), MeshBufferLayout::Separate) => (
+                previous.slice(self.vertex_ranges[0].clone()),
+                Mesh::POSITION_ATTR.format.size(),
+            ),
+            (Some(previous), MeshBufferLayout::Interleaved) => (previous.slice(..), vertex_stride),
+        }
+    }
+}
+
+/// How the attributes of a [`Mesh`] are laid out in [`GpuMesh::vertex_buffer`].
+///
+/// Interleaved vertices keep the attributes of each vertex together, which is better for
+/// passes reading all of them, like forward shading. Separate streams keep each attribute
+/// contiguous and bind it to its own slot, so passes only fetch the streams they read,
+/// like positions in depth only passes. GPU skinning needs interleaved vertices.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MeshBufferLayout {
+    #[default]
+    Interleaved,
+    Separate,
+}
+
+/// A [`VertexBufferLayout`] owning its attributes.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MeshVertexBufferLayout {
+    pub array_stride: u64,
+    pub attributes: Vec<VertexAttribute>,
+}
+
+impl MeshVertexBufferLayout {
+    pub fn layout(&self) -> VertexBufferLayout<'_> {
+        VertexBufferLayout {
+            array_stride: self.array_stride,
+            step_mode: VertexStepMode::Vertex,
+            attributes: &self.attributes,
+        }
+    }
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum GenerateTangentsError {
+    #[error("Tangents need the {0} attribute, with a value for every vertex.")]
+    MissingAttribute(&'static str),
+    #[error("Tangents can't be generated from the {0} attribute in {1:?}.")]
+    InvalidFormat(&'static str, VertexFormat),
+    #[error("MikkTSpace failed to generate tangents.")]
+    MikkTSpace,
+}
+
+/// Triangles of a [`Mesh`], as seen by MikkTSpace.
+struct TangentGeometry<'a> {
+    positions: &'a [Vec3],
+    normals: &'a [Vec3],
+    uvs: &'a [Vec2],
+    indices: Vec<usize>,
+    tangents: Vec<Vec4>,
+}
+
+impl bevy_mikktspace::Geometry for TangentGeometry<'_> {
+    fn num_faces(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    fn num_vertices_of_face(&self, _face: usize) -> usize {
+        3
+    }
+
+    fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.positions[self.indices[face * 3 + vert]].to_array()
+    }
+
+    fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.normals[self.indices[face * 3 + vert]].to_array()
+    }
+
+    fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+        self.uvs[self.indices[face * 3 + vert]].to_array()
+    }
+
+    fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
+        self.tangents[self.indices[face * 3 + vert]] = Vec4::from_array(tangent);
+    }
 }
 
 #[derive(Default, Clone)]
 pub struct Mesh {
     attributes: BTreeMap<MeshVertexAttributeId, MeshVertexAttributeData>,
     indices: Option<MeshIndices>,
+    buffer_layout: MeshBufferLayout,
 }
 
 impl Mesh {
@@ -148,6 +323,20 @@ impl Mesh {
     pub const TANGENT_ATTR: MeshVertexAttributeId =
         MeshVertexAttributeId::new(3, "Tangent", VertexFormat::Float32x4);
 
+    /// Linear vertex color, multiplied with the base color.
+    pub const COLOR_ATTR: MeshVertexAttributeId =
+        MeshVertexAttributeId::new(4, "Color", VertexFormat::Float32x4);
+
+    /// Second uv set, used by textures like occlusion maps.
+    pub const TEX_COORDS_1_ATTR: MeshVertexAttributeId =
+        MeshVertexAttributeId::new(5, "TexCoords1", VertexFormat::Float32x2);
+
+    /// Shader location of the first custom attribute, the built in ones come before.
+    pub const FIRST_CUSTOM_ATTR: usize = 6;
+
+    /// Vertex attributes every backend supports, so custom attributes end at location 15.
+    pub const MAX_VERTEX_ATTRIBUTES: usize = 16;
+
     pub fn new() -> Self {
         Self::default()
     }
@@ -170,6 +359,96 @@ impl Mesh {
         self
     }
 
+    pub fn attribute(&self, id: MeshVertexAttributeId) -> Option<&MeshVertexAttributeData> {
+        self.attributes.get(&id)
+    }
+
+    /// Insert per vertex data the built in attributes don't cover, like blend weights for a
+    /// custom material.
+    ///
+    /// Custom attributes take the shader locations after the built in ones, in the order
+    /// they are first inserted. Inserting an existing name replaces the data but keeps the
+    /// location. See [`Self::custom_attribute_shader_defs`] to find the locations in shaders.
+    pub fn insert_custom_attribute(
+        &mut self,
+        name: &'static str,
+        data: MeshVertexAttributeData,
+    ) -> &mut Self {
+        let id = self.custom_attribute_id(name).unwrap_or_else(|| {
+            self.attributes
+                .keys()
+                .map(|attr| attr.id + 1)
+                .max()
+                .unwrap_or_default()
+                .max(Self::FIRST_CUSTOM_ATTR)
+        });
+        assert!(
+            id < Self::MAX_VERTEX_ATTRIBUTES,
+            "No shader location left for {name}."
+        );
+
+        // Keys compare by id only, so the old key has to go for the new format to be kept.
+        self.attributes.retain(|attr, _| attr.id != id);
+        self.attributes
+            .insert(MeshVertexAttributeId::new(id, name, data.format()), data);
+        self
+    }
+
+    pub fn with_custom_attribute(
+        mut self,
+        name: &'static str,
+        data: MeshVertexAttributeData,
+    ) -> Self {
+        self.insert_custom_attribute(name, data);
+        self
+    }
+
+    pub fn custom_attribute(&self, name: &str) -> Option<&MeshVertexAttributeData> {
+        self.attributes
+            .iter()
+            .find(|(attr, _)| attr.id >= Self::FIRST_CUSTOM_ATTR && attr.name == name)
+            .map(|(_, data)| data)
+    }
+
+    fn custom_attribute_id(&self, name: &str) -> Option<usize> {
+        self.attributes
+            .keys()
+            .find(|attr| attr.id >= Self::FIRST_CUSTOM_ATTR && attr.name == name)
+            .map(|attr| attr.id)
+    }
+
+    /// `CUSTOM_ATTR_<NAME>` set to the shader location of every custom attribute.
+    ///
+    /// The name is uppercased with anything but letters and digits replaced by `_`, so
+    /// `blend_weights` is read with `@location(#CUSTOM_ATTR_BLEND_WEIGHTS) weights: vec4f`.
+    pub fn custom_attribute_shader_defs(&self) -> Vec<(String, ShaderDefValue)> {
+        self.attributes
+            .keys()
+            .filter(|attr| attr.id >= Self::FIRST_CUSTOM_ATTR)
+            .map(|attr| {
+                let name = attr
+                    .name
+                    .chars()
+                    .map(|c| {
+                        if c.is_ascii_alphanumeric() {
+                            c.to_ascii_uppercase()
+                        } else {
+                            '_'
+                        }
+                    })
+                    .collect::<String>();
+                (
+                    format!("CUSTOM_ATTR_{name}"),
+                    ShaderDefValue::UInt(attr.id as u32),
+                )
+            })
+            .collect()
+    }
+
+    pub fn indices(&self) -> Option<&MeshIndices> {
+        self.indices.as_ref()
+    }
+
     pub fn insert_indices(&mut self, indices: MeshIndices) -> &mut Self {
         self.indices = Some(indices);
         self
@@ -180,90 +459,201 @@ impl Mesh {
         self
     }
 
-    pub fn recalculate_tangent(&mut self) {
-        let vertices_count = self.vertices_count();
-        let mut tangents = vec![Vec3::default(); vertices_count];
-        let mut bitangents = vec![Vec3::default(); vertices_count];
-        let mut result = vec![Vec4::default(); vertices_count];
+    pub fn buffer_layout(&self) -> MeshBufferLayout {
+        self.buffer_layout
+    }
 
-        let (
-            MeshVertexAttributeData::Float32x3(positions),
-            MeshVertexAttributeData::Float32x3(normals),
-            MeshVertexAttributeData::Float32x2(uvs),
-        ) = (
-            &self.attributes[&Self::POSITION_ATTR],
-            &self.attributes[&Self::NORMAL_ATTR],
-            &self.attributes[&Self::TEX_COORDS_ATTR],
-        )
+    pub fn set_buffer_layout(&mut self, layout: MeshBufferLayout) -> &mut Self {
+        self.buffer_layout = layout;
+        self
+    }
+
+    pub fn with_buffer_layout(mut self, layout: MeshBufferLayout) -> Self {
+        self.buffer_layout = layout;
+        self
+    }
+
+    /// Vertex indices of the triangles, three per triangle.
+    fn triangle_indices(&self) -> Vec<usize> {
+        match &self.indices {
+            Some(MeshIndices::UInt16(indices)) => indices.iter().map(|i| *i as usize).collect(),
+            Some(MeshIndices::UInt32(indices)) => indices.iter().map(|i| *i as usize).collect(),
+            None => (0..self.vertices_count()).collect(),
+        }
+    }
+
+    /// Normals from the triangles sharing each vertex, weighted by their area, see
+    /// [`NormalGenerationMode`]. Degenerate triangles are ignored, vertices only used by
+    /// them get a zero normal.
+    ///
+    /// All but [`NormalGenerationMode::Smooth`] split vertices where the normal changes,
+    /// which rebuilds every attribute and the indices.
+    pub fn recalculate_normals(&mut self, mode: NormalGenerationMode) {
+        let Some(MeshVertexAttributeData::Float32x3(positions)) =
+            self.attributes.get(&Self::POSITION_ATTR)
         else {
-            unreachable!()
+            panic!("Normals can only be calculated from Float32x3 positions.");
         };
 
-        for i_tri in 0..vertices_count / 3 {
-            let i0 = i_tri * 3;
-            let i1 = i0 + 1;
-            let i2 = i1 + 1;
+        let triangles = self.triangle_indices();
+        // Twice the area, so larger faces weigh more.
+        let face_normals = triangles
+            .chunks_exact(3)
+            .map(|tri| {
+                let [p0, p1, p2] = [0, 1, 2].map(|i| positions[tri[i]]);
+                let n = (p1 - p0).cross(p2 - p0);
+                if n.is_finite() {
+                    n
+                } else {
+                    Vec3::ZERO
+                }
+            })
+            .collect::<Vec<_>>();
 
-            let p0 = positions[i0];
-            let p1 = positions[i1];
-            let p2 = positions[i2];
+        let crease_cos = match mode {
+            NormalGenerationMode::Smooth => {
+                let mut normals = vec![Vec3::ZERO; positions.len()];
+                for (tri, n) in triangles.chunks_exact(3).zip(&face_normals) {
+                    for &i in tri {
+                        normals[i] += *n;
+                    }
+                }
 
-            let u0 = uvs[i0];
-            let u1 = uvs[i1];
-            let u2 = uvs[i2];
+                for n in &mut normals {
+                    *n = n.normalize_or_zero();
+                }
 
-            let e1 = p1 - p0;
-            let e2 = p2 - p0;
+                self.attributes.insert(
+                    Self::NORMAL_ATTR,
+                    MeshVertexAttributeData::Float32x3(normals),
+                );
+                return;
+            }
+            NormalGenerationMode::Flat => None,
+            NormalGenerationMode::SmoothWithAngleThreshold(angle) => Some(angle.cos()),
+        };
 
-            let x1 = u1.x - u0.x;
-            let x2 = u2.x - u0.x;
+        // Triangles around each position, so vertices already split by uv seams still
+        // share their normals.
+        let position_key = |i: usize| positions[i].to_array().map(f32::to_bits);
+        let mut around = HashMap::<_, Vec<usize>>::new();
+        if crease_cos.is_some() {
+            for (triangle, tri) in triangles.chunks_exact(3).enumerate() {
+                for &i in tri {
+                    around.entry(position_key(i)).or_default().push(triangle);
+                }
+            }
+        }
 
-            let y1 = u1.y - u0.y;
-            let y2 = u2.y - u0.y;
+        // Corners of the same vertex with the same normal stay welded.
+        let mut sources = Vec::new();
+        let mut normals = Vec::new();
+        let mut welded = HashMap::new();
+        let indices = triangles
+            .iter()
+            .enumerate()
+            .map(|(corner, &i)| {
+                let face = face_normals[corner / 3].normalize_or_zero();
+                let normal = match crease_cos {
+                    Some(cos) if face != Vec3::ZERO => around[&position_key(i)]
+                        .iter()
+                        .map(|&other| face_normals[other])
+                        .filter(|n| face.dot(n.normalize_or_zero()) >= cos)
+                        .sum::<Vec3>()
+                        .normalize_or_zero(),
+                    _ => face,
+                };
 
-            let r = 1. / (x1 * y2 - x2 * y1);
-            let t = (e1 * y2 - e2 * y1) * r;
-            let b = (e2 * x1 - e1 * x2) * r;
+                *welded
+                    .entry((i, normal.to_array().map(f32::to_bits)))
+                    .or_insert_with(|| {
+                        sources.push(i);
+                        normals.push(normal);
+                        sources.len() as u32 - 1
+                    })
+            })
+            .collect::<Vec<_>>();
 
-            tangents[i0] += t;
-            tangents[i1] += t;
-            tangents[i2] += t;
+        for data in self.attributes.values_mut() {
+            *data = data.gather(&sources);
+        }
+        self.attributes.insert(
+            Self::NORMAL_ATTR,
+            MeshVertexAttributeData::Float32x3(normals),
+        );
+        self.indices = Some(match self.indices {
+            Some(MeshIndices::UInt16(_)) if sources.len() <= u16::MAX as usize + 1 => {
+                MeshIndices::UInt16(indices.into_iter().map(|i| i as u16).collect())
+            }
+            _ => MeshIndices::UInt32(indices),
+        });
+    }
 
-            bitangents[i0] += b;
-            bitangents[i1] += b;
-            bitangents[i2] += b;
+    /// MikkTSpace tangents, with the handedness of the bitangent in `w`, matching the normal
+    /// maps most tools bake. Needs Float32x3 positions and normals and Float32x2 uvs.
+    ///
+    /// A vertex shared by triangles needing different tangents, like on a mirrored uv seam
+    /// without split vertices, keeps the tangent of one of them.
+    pub fn recalculate_tangent(&mut self) -> Result<(), GenerateTangentsError> {
+        let vertices_count = self.vertices_count();
+        for id in [
+            Self::POSITION_ATTR,
+            Self::NORMAL_ATTR,
+            Self::TEX_COORDS_ATTR,
+        ] {
+            let data = self
+                .attributes
+                .get(&id)
+                .ok_or(GenerateTangentsError::MissingAttribute(id.name))?;
+            if data.format() != id.format {
+                return Err(GenerateTangentsError::InvalidFormat(id.name, data.format()));
+            }
+            if data.len() != vertices_count {
+                return Err(GenerateTangentsError::MissingAttribute(id.name));
+            }
         }
 
-        for i_vert in 0..vertices_count {
-            let t = tangents[i_vert];
-            let b = bitangents[i_vert];
-            let n = normals[i_vert];
-            let sign = {
-                if t.cross(b).dot(n) > 0. {
-                    1.
-                } else {
-                    -1.
-                }
-            };
+        let (
+            MeshVertexAttributeData::Float32x3(positions),
+            MeshVertexAttributeData::Float32x3(normals),
+            MeshVertexAttributeData::Float32x2(uvs),
+        ) = (
+            &self.attributes[&Self::POSITION_ATTR],
+            &self.attributes[&Self::NORMAL_ATTR],
+            &self.attributes[&Self::TEX_COORDS_ATTR],
+        )
+        else {
+            unreachable!()
+        };
 
-            result[i_vert] = n.reject_from(t).extend(sign);
+        let mut geometry = TangentGeometry {
+            positions,
+            normals,
+            uvs,
+            indices: self.triangle_indices(),
+            tangents: vec![Vec4::ZERO; vertices_count],
+        };
+        if !geometry.indices.is_empty() && !bevy_mikktspace::generate_tangents(&mut geometry) {
+            return Err(GenerateTangentsError::MikkTSpace);
         }
 
+        let tangents = geometry.tangents;
         self.attributes.insert(
             Self::TANGENT_ATTR,
-            MeshVertexAttributeData::Float32x4(result),
+            MeshVertexAttributeData::Float32x4(tangents),
         );
+        Ok(())
     }
 
     pub fn vertex_layout(&self) -> Vec<VertexAttribute> {
         let mut layout = Vec::with_capacity(self.attributes.len());
         let mut offset = 0;
 
-        for (index, attr) in self.attributes.keys().enumerate() {
+        for attr in self.attributes.keys() {
             layout.push(VertexAttribute {
                 format: attr.format,
                 offset,
-                shader_location: index as u32,
+                shader_location: attr.id as u32,
             });
             offset += attr.format.size();
         }
@@ -287,12 +677,23 @@ impl Mesh {
         cnt.unwrap_or(0)
     }
 
+    /// Bounds of the positions, `None` if there is no position attribute.
+    pub fn aabb(&self) -> Option<Aabb> {
+        match self.attributes.get(&Self::POSITION_ATTR)? {
+            MeshVertexAttributeData::Float32x3(positions) => {
+                Aabb::from_points(positions.iter().copied())
+            }
+            _ => None,
+        }
+    }
+
     pub fn vertex_stride(&self) -> u64 {
         self.attributes
             .keys()
             .fold(0, |acc, a| acc + a.format.size())
     }
 
+    /// Interleaved vertices, whatever the [`Self::buffer_layout`].
     pub fn vertex_buffer_data(&self) -> Vec<u8> {
         let vertices_count = self.vertices_count();
         let vertex_stride = self.vertex_stride() as usize;
@@ -314,8 +715,43 @@ impl Mesh {
         buffer
     }
 
+    /// Each attribute after another, for [`MeshBufferLayout::Separate`].
+    pub fn separate_vertex_buffer_data(&self) -> Vec<u8> {
+        let vertices_count = self.vertices_count();
+        self.attributes
+            .values()
+            .flat_map(|attr| &attr.cast_bytes()[..vertices_count * attr.size() as usize])
+            .copied()
+            .collect()
+    }
+
+    /// Byte ranges of the vertex buffer bound to each of [`Self::vertex_buffer_layouts`].
+    pub fn vertex_buffer_ranges(&self) -> Vec<Range<BufferAddress>> {
+        let vertices_count = self.vertices_count() as u64;
+        match self.buffer_layout {
+            MeshBufferLayout::Interleaved => {
+                std::iter::once(0..vertices_count * self.vertex_stride()).collect()
+            }
+            MeshBufferLayout::Separate => {
+                let mut start = 0;
+                self.attributes
+                    .keys()
+                    .map(|attr| {
+                        let end = start + vertices_count * attr.format.size();
+                        let range = start..end;
+                        start = end;
+                        range
+                    })
+                    .collect()
+            }
+        }
+    }
+
     pub fn create_vertex_buffer(&self, device: &Device) -> Option<Buffer> {
-        let data = self.vertex_buffer_data();
+        let data = match self.buffer_layout {
+            MeshBufferLayout::Interleaved => self.vertex_buffer_data(),
+            MeshBufferLayout::Separate => self.separate_vertex_buffer_data(),
+        };
         if data.is_empty() {
             None
         } else {
@@ -327,6 +763,19 @@ impl Mesh {
         }
     }
 
+    pub fn create_position_buffer(&self, device: &Device) -> Option<Buffer> {
+        match self.attributes.get(&Self::POSITION_ATTR)? {
+            MeshVertexAttributeData::Float32x3(positions) if !positions.is_empty() => {
+                Some(device.create_buffer_init(&BufferInitDescriptor {
+                    label: Some("mesh_position_buffer"),
+                    contents: bytemuck::cast_slice(positions),
+                    usage: BufferUsages::VERTEX,
+                }))
+            }
+            _ => None,
+        }
+    }
+
     pub fn create_index_buffer(&self, device: &Device) -> Option<GpuIndexBuffer> {
         self.indices.as_ref().map(|indices| {
             let (contents, format, count) = match indices {
@@ -353,15 +802,16 @@ impl Mesh {
         })
     }
 
+    /// Shader locations are the attribute ids, so optional attributes keep fixed locations.
     pub fn vertex_attributes(&self) -> Vec<VertexAttribute> {
         let mut attrs = Vec::with_capacity(self.attributes.len());
         let mut offset = 0;
 
-        for (location, attr) in self.attributes.keys().enumerate() {
+        for attr in self.attributes.keys() {
             attrs.push(VertexAttribute {
                 format: attr.format,
                 offset,
-                shader_location: location as u32,
+                shader_location: attr.id as u32,
             });
             offset += attr.format.size();
         }
@@ -369,8 +819,54 @@ impl Mesh {
         attrs
     }
 
+    /// Layouts of the vertex buffers to bind with [`GpuMesh::set_vertex_buffers`], a single
+    /// one of [`Self::vertex_attributes`] unless the vertices are
+    /// [`MeshBufferLayout::Separate`].
+    pub fn vertex_buffer_layouts(&self) -> Vec<MeshVertexBufferLayout> {
+        match self.buffer_layout {
+            MeshBufferLayout::Interleaved => vec![MeshVertexBufferLayout {
+                array_stride: self.vertex_stride(),
+                attributes: self.vertex_attributes(),
+            }],
+            MeshBufferLayout::Separate => self
+                .attributes
+                .keys()
+                .map(|attr| MeshVertexBufferLayout {
+                    array_stride: attr.format.size(),
+                    attributes: vec![VertexAttribute {
+                        format: attr.format,
+                        offset: 0,
+                        shader_location: attr.id as u32,
+                    }],
+                })
+                .collect(),
+        }
+    }
+
+    /// Only reads [`Self::POSITION_ATTR`], for passes that write depth alone.
+    ///
+    /// Positions come first in every vertex, so this fits the packed positions, the
+    /// position stream and the interleaved vertices, see [`GpuMesh::position_vertices`].
+    pub fn position_vertex_layout(array_stride: u64) -> VertexBufferLayout<'static> {
+        const ATTRIBUTES: &[VertexAttribute] = &[VertexAttribute {
+            format: VertexFormat::Float32x3,
+            offset: 0,
+            shader_location: 0,
+        }];
+
+        VertexBufferLayout {
+            array_stride,
+            step_mode: VertexStepMode::Vertex,
+            attributes: ATTRIBUTES,
+        }
+    }
+
+    /// Optional attributes after the asserted ones, like [`Self::COLOR_ATTR`], are allowed.
     pub fn assert_vertex(&self, attrs: &[VertexFormat]) {
-        assert_eq!(attrs.len(), self.attributes.len());
+        assert!(
+            attrs.len() <= self.attributes.len(),
+            "Missing vertex attributes."
+        );
 
         self.attributes
             .keys()
@@ -403,13 +899,103 @@ impl Mesh {
     }
 }
 
+/// Layer meshes are placed in by default.
+pub const DEFAULT_RENDER_LAYER: u32 = 1;
+
+/// Layer mask of nodes drawing every mesh, see [`RenderFlow::set_render_layers`].
+///
+/// [`RenderFlow::set_render_layers`]: crate::render::flow::RenderFlow::set_render_layers
+pub const ALL_RENDER_LAYERS: u32 = u32::MAX;
+
 #[derive(Debug, Clone, Copy)]
 pub struct StaticMesh {
     pub mesh: MeshInstanceId,
     pub material: MaterialInstanceId,
+    /// Bitmask of the layers this mesh is in, usually [`DEFAULT_RENDER_LAYER`].
+    ///
+    /// Nodes only get meshes sharing at least one layer with their mask.
+    pub render_layer: u32,
+    /// Model transform, identity for vertices already in world space, like loaded ones.
+    ///
+    /// Applied by every node drawing static meshes, see [`ModelUniforms`].
+    ///
+    /// [`ModelUniforms`]: crate::render::resource::ModelUniforms
+    pub transform: Transform,
+}
+
+/// Copies of a mesh drawn with a single call, each placed by one of `transforms`.
+///
+/// Unlike [`StaticMesh`] the vertices are in model space. Meshes are not culled, and only
+/// nodes reading [`InstancedMesh::instance_buffer_layout`] draw them.
+#[derive(Debug, Clone)]
+pub struct InstancedMesh {
+    pub mesh: MeshInstanceId,
+    pub material: MaterialInstanceId,
+    pub transforms: Vec<Transform>,
 }
 
-pub trait Material: DynClone + 'static {
+impl InstancedMesh {
+    /// Model matrix columns of each instance, from location 8 to 11.
+    pub const INSTANCE_ATTRIBUTES: [VertexAttribute; 4] = wgpu::vertex_attr_array![
+        8 => Float32x4,
+        9 => Float32x4,
+        10 => Float32x4,
+        11 => Float32x4,
+    ];
+
+    /// Layout of the buffer from [`InstancedMesh::create_instance_buffer`].
+    pub fn instance_buffer_layout() -> VertexBufferLayout<'static> {
+        VertexBufferLayout {
+            array_stride: size_of::<Mat4>() as u64,
+            step_mode: VertexStepMode::Instance,
+            attributes: &Self::INSTANCE_ATTRIBUTES,
+        }
+    }
+
+    pub fn model_matrices(&self) -> Vec<Mat4> {
+        self.transforms
+            .iter()
+            .map(Transform::model_matrix)
+            .collect()
+    }
+
+    /// Writable, so nodes can update it while the number of instances stays the same. `None`
+    /// without any transform.
+    pub fn create_instance_buffer(&self, device: &Device) -> Option<Buffer> {
+        (!self.transforms.is_empty()).then(|| {
+            device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("instance_buffer"),
+                contents: bytemuck::cast_slice(&self.model_matrices()),
+                usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+            })
+        })
+    }
+}
+
+bitflags::bitflags! {
+    /// Ways a material moves vertices in the vertex shader, each needing its own shader
+    /// variant.
+    #[derive(Default)]
+    pub struct VertexDisplacement: u32 {
+        /// Offsets sampled from a vertex animation texture.
+        const ANIMATION_TEXTURE = 1 << 0;
+        /// Swaying in the wind, for foliage.
+        const WIND = 1 << 1;
+    }
+}
+
+/// How the alpha of a material is treated when drawn.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum AlphaMode {
+    #[default]
+    Opaque,
+    /// Fragments with alpha below the cutoff are discarded.
+    Mask(f32),
+    /// Blended over what's behind, without writing depth.
+    Blend,
+}
+
+pub trait Material: DynClone + Send + Sync + 'static {
     fn create_bind_group(
         &self,
         device: &Device,
@@ -418,6 +1004,39 @@ pub trait Material: DynClone + 'static {
     );
     fn prepare(&self, device: &Device, assets: &mut GpuAssets) -> u32;
 
+    #[inline]
+    fn alpha_mode(&self) -> AlphaMode {
+        AlphaMode::Opaque
+    }
+
+    /// Whether back faces are drawn too.
+    #[inline]
+    fn double_sided(&self) -> bool {
+        false
+    }
+
+    /// Whether the edges of [`AlphaMode::Mask`] are antialiased with alpha-to-coverage.
+    ///
+    /// Only applies to masked materials drawn with MSAA, otherwise fragments below the
+    /// cutoff are discarded as usual.
+    #[inline]
+    fn alpha_to_coverage(&self) -> bool {
+        false
+    }
+
+    /// Tint applied to light passing through the material, for colored shadows.
+    ///
+    /// `None` casts a regular opaque shadow.
+    #[inline]
+    fn shadow_transmittance(&self) -> Option<Vec3> {
+        None
+    }
+
+    #[inline]
+    fn vertex_displacement(&self) -> VertexDisplacement {
+        VertexDisplacement::empty()
+    }
+
     #[inline]
     fn id(&self) -> MaterialTypeId {
         MaterialTypeId(TypeId::of::<Self>().to_uuid())
@@ -427,3 +1046,296 @@ pub trait Material: DynClone + 'static {
 pub trait CreateBindGroupLayout {
     fn create_layout(device: &Device, assets: &mut GpuAssets);
 }
+
+#[cfg(test)]
+mod tests {
+    use glam::{Vec2, Vec3, Vec4};
+    use naga_oil::compose::{Composer, NagaModuleDescriptor, ShaderDefValue};
+    use wgpu::VertexFormat;
+
+    use super::{
+        GenerateTangentsError, Mesh, MeshBufferLayout, MeshIndices, MeshVertexAttributeData,
+        NormalGenerationMode,
+    };
+
+    #[test]
+    fn custom_attributes() {
+        let mut mesh = Mesh::new()
+            .with_attribute(
+                Mesh::POSITION_ATTR,
+                MeshVertexAttributeData::Float32x3(vec![Vec3::ZERO; 3]),
+            )
+            .with_custom_attribute(
+                "blend_weights",
+                MeshVertexAttributeData::Float32x4(vec![Vec4::X; 3]),
+            )
+            .with_custom_attribute("ao", MeshVertexAttributeData::Float32(vec![1.; 3]));
+
+        let attrs = mesh.vertex_attributes();
+        assert_eq!(attrs.len(), 3);
+        assert_eq!(attrs[1].shader_location, Mesh::FIRST_CUSTOM_ATTR as u32);
+        assert_eq!(attrs[1].offset, 12);
+        assert_eq!(attrs[2].shader_location, Mesh::FIRST_CUSTOM_ATTR as u32 + 1);
+        assert_eq!(mesh.vertex_stride(), 12 + 16 + 4);
+
+        // Replacing keeps the location but takes the new format.
+        mesh.insert_custom_attribute(
+            "blend_weights",
+            MeshVertexAttributeData::Float32x2(vec![Default::default(); 3]),
+        );
+        let attrs = mesh.vertex_attributes();
+        assert_eq!(attrs[1].shader_location, Mesh::FIRST_CUSTOM_ATTR as u32);
+        assert_eq!(attrs[1].format, VertexFormat::Float32x2);
+        assert!(mesh.custom_attribute("ao").is_some());
+
+        let defs = mesh.custom_attribute_shader_defs();
+        assert_eq!(
+            defs,
+            [
+                (
+                    "CUSTOM_ATTR_BLEND_WEIGHTS".to_string(),
+                    ShaderDefValue::UInt(6)
+                ),
+                ("CUSTOM_ATTR_AO".to_string(), ShaderDefValue::UInt(7)),
+            ]
+        );
+
+        Composer::default()
+            .make_naga_module(NagaModuleDescriptor {
+                source: "@vertex
+fn vertex(@location(#CUSTOM_ATTR_AO) ao: f32) -> @builtin(position) vec4f {
+    return vec4f(ao);
+}",
+                shader_defs: defs.into_iter().collect(),
+                ..Default::default()
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn position_vertex_layout() {
+        let mesh = Mesh::new()
+            .with_attribute(
+                Mesh::TEX_COORDS_ATTR,
+                MeshVertexAttributeData::Float32x2(vec![Default::default(); 3]),
+            )
+            .with_attribute(
+                Mesh::POSITION_ATTR,
+                MeshVertexAttributeData::Float32x3(vec![Vec3::ONE; 3]),
+            );
+
+        // Reading only the positions out of the interleaved vertices.
+        let layout = Mesh::position_vertex_layout(mesh.vertex_stride());
+        assert_eq!(layout.array_stride, 20);
+        assert_eq!(layout.attributes, &mesh.vertex_attributes()[..1]);
+
+        let packed = Mesh::position_vertex_layout(Mesh::POSITION_ATTR.format.size());
+        assert_eq!(packed.array_stride, 12);
+        assert_eq!(packed.attributes[0].format, Mesh::POSITION_ATTR.format);
+    }
+
+    #[test]
+    fn recalculate_normals() {
+        // Unit cube with 4 vertices per face, without normals.
+        let faces = [Vec3::X, -Vec3::X, Vec3::Y, -Vec3::Y, Vec3::Z, -Vec3::Z];
+        let mut positions = Vec::new();
+        let mut indices = Vec::new();
+        for n in faces {
+            let (u, v) = n.any_orthonormal_pair();
+            // Counter clockwise seen from outside.
+            let (u, v) = if u.cross(v).dot(n) > 0. {
+                (u, v)
+            } else {
+                (v, u)
+            };
+            let base = positions.len() as u16;
+            positions.extend([n - u - v, n + u - v, n + u + v, n - u + v].map(|p| p * 0.5));
+            indices.extend([0, 1, 2, 0, 2, 3].map(|i| base + i));
+        }
+        // A degenerate triangle doesn't affect the faces it touches.
+        indices.extend([0, 1, 1]);
+
+        let mut mesh = Mesh::new()
+            .with_attribute(
+                Mesh::POSITION_ATTR,
+                MeshVertexAttributeData::Float32x3(positions),
+            )
+            .with_indices(MeshIndices::UInt16(indices));
+        mesh.recalculate_normals(NormalGenerationMode::Smooth);
+
+        let Some(MeshVertexAttributeData::Float32x3(normals)) = mesh.attribute(Mesh::NORMAL_ATTR)
+        else {
+            panic!("Normals should be Float32x3.");
+        };
+        for (face, normals) in faces.iter().zip(normals.chunks_exact(4)) {
+            assert!(normals.iter().all(|n| n.abs_diff_eq(*face, 1e-6)));
+        }
+    }
+
+    #[test]
+    fn normal_generation_modes() {
+        // Unit cube sharing its 8 corners between the faces.
+        let corners = (0..8)
+            .map(|i| Vec3::new((i & 1) as f32, (i >> 1 & 1) as f32, (i >> 2) as f32) - 0.5)
+            .collect::<Vec<_>>();
+        let quads: [[u16; 4]; 6] = [
+            [1, 3, 7, 5],
+            [0, 4, 6, 2],
+            [2, 6, 7, 3],
+            [0, 1, 5, 4],
+            [4, 5, 7, 6],
+            [0, 2, 3, 1],
+        ];
+        let indices = quads
+            .iter()
+            .flat_map(|q| [q[0], q[1], q[2], q[0], q[2], q[3]])
+            .collect();
+        let cube = Mesh::new()
+            .with_attribute(
+                Mesh::POSITION_ATTR,
+                MeshVertexAttributeData::Float32x3(corners),
+            )
+            .with_attribute(
+                Mesh::TEX_COORDS_ATTR,
+                MeshVertexAttributeData::Float32x2(vec![Vec2::ZERO; 8]),
+            )
+            .with_indices(MeshIndices::UInt16(indices));
+
+        // Normals at the corners of every triangle, as they are rendered.
+        let corner_normals = |mode| {
+            let mut mesh = cube.clone();
+            mesh.recalculate_normals(mode);
+            let Some(MeshVertexAttributeData::Float32x3(normals)) =
+                mesh.attribute(Mesh::NORMAL_ATTR)
+            else {
+                panic!("Normals should be Float32x3.");
+            };
+            let normals = mesh
+                .triangle_indices()
+                .iter()
+                .map(|i| normals[*i])
+                .collect();
+            (normals, mesh)
+        };
+
+        // Rounded, each corner points away from the center.
+        let (smooth, mesh): (Vec<Vec3>, _) = corner_normals(NormalGenerationMode::Smooth);
+        assert_eq!(mesh.vertices_count(), 8);
+        let Some(MeshVertexAttributeData::Float32x3(positions)) =
+            mesh.attribute(Mesh::POSITION_ATTR)
+        else {
+            panic!("Positions should be Float32x3.");
+        };
+        for (n, i) in smooth.iter().zip(mesh.triangle_indices()) {
+            assert_eq!(n.signum(), positions[i].signum());
+            assert!(n.abs().min_element() > 0.3);
+        }
+
+        // Crisp, 4 vertices per face.
+        for mode in [
+            NormalGenerationMode::Flat,
+            NormalGenerationMode::SmoothWithAngleThreshold(30f32.to_radians()),
+        ] {
+            let (crisp, mesh) = corner_normals(mode);
+            assert_eq!(mesh.vertices_count(), 24);
+            let Some(MeshVertexAttributeData::Float32x2(uvs)) =
+                mesh.attribute(Mesh::TEX_COORDS_ATTR)
+            else {
+                panic!("Uvs should be kept.");
+            };
+            assert_eq!(uvs.len(), mesh.vertices_count());
+            assert!(matches!(mesh.indices(), Some(MeshIndices::UInt16(_))));
+            for (face, normals) in [Vec3::X, -Vec3::X, Vec3::Y, -Vec3::Y, Vec3::Z, -Vec3::Z]
+                .iter()
+                .zip(crisp.chunks_exact(6))
+            {
+                assert!(normals.iter().all(|n| n.abs_diff_eq(*face, 1e-6)));
+            }
+        }
+
+        // Above the 90 degrees between the faces, the edges are smoothed again.
+        let (wide, _) = corner_normals(NormalGenerationMode::SmoothWithAngleThreshold(
+            100f32.to_radians(),
+        ));
+        assert!(wide
+            .iter()
+            .zip(&smooth)
+            .all(|(w, s)| w.abs_diff_eq(*s, 1e-6)));
+    }
+
+    #[test]
+    fn mirrored_uv_tangents() {
+        // Two quads facing +Z, the right one with mirrored u, split along the seam.
+        let positions = [-1., 0., 0., 1.]
+            .into_iter()
+            .flat_map(|x| [Vec3::new(x, -1., 0.), Vec3::new(x, 1., 0.)])
+            .collect::<Vec<_>>();
+        let uvs = [0., 1., 1., 0.]
+            .into_iter()
+            .flat_map(|u| [Vec2::new(u, 0.), Vec2::new(u, 1.)])
+            .collect();
+        let mut quad = Mesh::new()
+            .with_attribute(
+                Mesh::POSITION_ATTR,
+                MeshVertexAttributeData::Float32x3(positions),
+            )
+            .with_attribute(
+                Mesh::NORMAL_ATTR,
+                MeshVertexAttributeData::Float32x3(vec![Vec3::Z; 8]),
+            )
+            .with_indices(MeshIndices::UInt16(vec![
+                0, 2, 3, 0, 3, 1, 4, 6, 7, 4, 7, 5,
+            ]));
+        assert_eq!(
+            quad.recalculate_tangent(),
+            Err(GenerateTangentsError::MissingAttribute("TexCoords"))
+        );
+
+        quad.insert_attribute(
+            Mesh::TEX_COORDS_ATTR,
+            MeshVertexAttributeData::Float32x2(uvs),
+        );
+        quad.recalculate_tangent().unwrap();
+        let Some(MeshVertexAttributeData::Float32x4(tangents)) = quad.attribute(Mesh::TANGENT_ATTR)
+        else {
+            panic!("Tangents should be Float32x4.");
+        };
+
+        // Along +u, with the bitangent `cross(normal, tangent) * w` along +v.
+        for (i, tangent) in tangents.iter().enumerate() {
+            let expected = if i < 4 {
+                Vec4::new(1., 0., 0., 1.)
+            } else {
+                Vec4::new(-1., 0., 0., -1.)
+            };
+            assert!(tangent.abs_diff_eq(expected, 1e-6), "{i}: {tangent}");
+        }
+    }
+
+    #[test]
+    fn separate_buffer_layout() {
+        let mesh = Mesh::new()
+            .with_attribute(
+                Mesh::TEX_COORDS_ATTR,
+                MeshVertexAttributeData::Float32x2(vec![Vec2::ONE; 3]),
+            )
+            .with_attribute(
+                Mesh::POSITION_ATTR,
+                MeshVertexAttributeData::Float32x3(vec![Vec3::ONE; 3]),
+            )
+            .with_buffer_layout(MeshBufferLayout::Separate);
+
+        let layouts = mesh.vertex_buffer_layouts();
+        assert_eq!(layouts.len(), 2);
+        assert_eq!(layouts[0].array_stride, 12);
+        assert_eq!(layouts[1].array_stride, 8);
+        assert_eq!(layouts[1].attributes[0].offset, 0);
+        assert_eq!(layouts[1].attributes[0].shader_location, 2);
+
+        // Positions come first, then the uvs.
+        assert_eq!(mesh.vertex_buffer_ranges(), [0..36, 36..60]);
+        let data = mesh.separate_vertex_buffer_data();
+        assert_eq!(data.len(), 60);
+        assert_eq!(&data[36..44], bytemuck::bytes_of(&Vec2::ONE));
+    }
+}
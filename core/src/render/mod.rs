@@ -1,5 +1,6 @@
 use naga_oil::compose::ShaderDefValue;
 
+pub mod budget;
 pub mod flow;
 pub mod helper;
 pub mod mesh;
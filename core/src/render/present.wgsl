@@ -1,5 +1,7 @@
 #define_import_path aurora::fullscreen
 
+#import aurora::color_space
+
 struct FullscreenVertexOutput {
     @builtin(position) position: vec4f,
     @location(0) uv: vec2f,
@@ -22,3 +24,9 @@ fn vertex(@builtin(vertex_index) vertex_index: u32) -> FullscreenVertexOutput {
 fn fragment(in: FullscreenVertexOutput) -> @location(0) vec4f {
     return textureSample(color, color_sampler, in.uv);
 }
+
+@fragment
+fn fragment_srgb(in: FullscreenVertexOutput) -> @location(0) vec4f {
+    let sampled = textureSample(color, color_sampler, in.uv);
+    return vec4f(color_space::linear_to_srgb(saturate(sampled.rgb)), sampled.a);
+}
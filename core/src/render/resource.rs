@@ -1,21 +1,33 @@
-use std::path::Path;
+use std::{
+    borrow::Cow,
+    collections::{hash_map::Entry, HashMap},
+    num::NonZeroU64,
+    path::Path,
+    sync::Arc,
+};
 
 use bytemuck::NoUninit;
 use encase::{internal::WriteInto, DynamicStorageBuffer, ShaderType};
-use glam::{Mat4, UVec2, Vec3};
-use image::{DynamicImage, ImageFormat, ImageResult};
+use glam::{Mat3, Mat4, UVec2, Vec3};
+use image::{imageops::FilterType, DynamicImage, ImageFormat, ImageResult};
+use log::warn;
+use palette::Srgb;
+use thiserror::Error;
 use uuid::Uuid;
 use wgpu::{
-    util::{BufferInitDescriptor, DeviceExt, TextureDataOrder},
-    BindingResource, Buffer, BufferBinding, BufferDescriptor, BufferUsages, Device, Extent3d,
-    ImageCopyTexture, Origin3d, Queue, Texture, TextureAspect, TextureDescriptor, TextureDimension,
-    TextureFormat, TextureUsages, TextureView,
+    util::{DeviceExt, TextureDataOrder},
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, Buffer, BufferBinding, BufferBindingType,
+    BufferDescriptor, BufferUsages, Device, Extent3d, Id, ImageCopyTexture, Origin3d, Queue,
+    Sampler, ShaderStages, SurfaceCapabilities, Texture, TextureAspect, TextureDescriptor,
+    TextureDimension, TextureFormat, TextureUsages, TextureView, COPY_BUFFER_ALIGNMENT,
 };
 
 use crate::{
     render::{
+        helper::Transform,
         mesh::StaticMesh,
-        scene::{MaterialTypeId, TextureId},
+        scene::{MaterialInstanceId, MaterialTypeId, TextureId},
     },
     util::cube::CUBE_MAP_OFFSETS,
     SwapChain,
@@ -26,8 +38,53 @@ pub const POST_PROCESS_COLOR_LAYOUT_UUID: MaterialTypeId =
 pub const POST_PROCESS_DEPTH_LAYOUT_UUID: MaterialTypeId =
     MaterialTypeId(Uuid::from_u128(887897413248965416140604016399654));
 
+/// Instance id the material override of a flow creates its bind group with.
+pub const MATERIAL_OVERRIDE: MaterialInstanceId =
+    MaterialInstanceId(Uuid::from_u128(5310947236418590624713865302187));
+
 pub const DUMMY_2D_TEX: TextureId = TextureId(Uuid::from_u128(8674167498640649160513219685401));
 
+/// Surface format for extended range output, holding linear extended sRGB (scRGB) where
+/// 1.0 is SDR white at 80 nits.
+///
+/// wgpu doesn't expose the color space of surfaces, so a `Rgb10a2Unorm` surface can't be
+/// told apart from an SDR one, and isn't used for HDR.
+pub const HDR_SURFACE_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+
+/// [`HDR_SURFACE_FORMAT`] if the surface advertises it, `None` for SDR only surfaces.
+///
+/// To present HDR, configure the surface with this format instead of the one from
+/// `Surface::get_default_config`, and set [`RenderTargets::hdr_output`]. No window setup is
+/// needed with winit, but it's only advertised by some backends, like DX12 and Vulkan on
+/// Windows, with HDR enabled for the display in the system settings.
+pub fn hdr_surface_format(capabilities: &SurfaceCapabilities) -> Option<TextureFormat> {
+    capabilities
+        .formats
+        .contains(&HDR_SURFACE_FORMAT)
+        .then_some(HDR_SURFACE_FORMAT)
+}
+
+/// How the display interprets the values of the surface.
+///
+/// Only describes the surface, textures between nodes, like the swap chain, always hold
+/// linear values, whatever their format.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ColorSpace {
+    /// Values are displayed sRGB encoded, by the format if it's an sRGB one, otherwise by
+    /// the node writing to the surface.
+    #[default]
+    Srgb,
+    /// Values are displayed as written, for surfaces consumed by something expecting linear
+    /// data. Can't be used with sRGB formats, which always encode.
+    Linear,
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ColorSpaceError {
+    #[error("The surface is {0:?}, which encodes to sRGB, but its color space is linear.")]
+    LinearSrgbSurface(TextureFormat),
+}
+
 pub struct RenderTargets<'a> {
     pub color_format: TextureFormat,
     pub swap_chain: &'a SwapChain,
@@ -36,12 +93,51 @@ pub struct RenderTargets<'a> {
     pub depth_format: Option<TextureFormat>,
     pub depth: Option<TextureView>,
     pub size: UVec2,
+    /// MSAA sample count of the main pass, 1 disables MSAA.
+    pub sample_count: u32,
+    /// Request extended range output, only honored on [`HDR_SURFACE_FORMAT`] surfaces.
+    /// See [`RenderTargets::presents_hdr`].
+    pub hdr_output: bool,
+    /// What the surface expects, see [`RenderTargets::encodes_srgb`]. Ignored when
+    /// [`RenderTargets::presents_hdr`], HDR output is always scRGB.
+    pub color_space: ColorSpace,
 }
 
+impl RenderTargets<'_> {
+    /// Whether nodes writing to the surface encode sRGB themselves, as the surface expects
+    /// it, but neither its format nor HDR output takes care of it.
+    #[inline]
+    pub fn encodes_srgb(&self) -> bool {
+        self.color_space == ColorSpace::Srgb
+            && !self.surface_format.is_srgb()
+            && self.surface_format != HDR_SURFACE_FORMAT
+    }
+
+    pub fn validate_color_space(&self) -> Result<(), ColorSpaceError> {
+        match self.color_space {
+            ColorSpace::Linear if self.surface_format.is_srgb() => {
+                Err(ColorSpaceError::LinearSrgbSurface(self.surface_format))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether HDR output is requested and the surface can hold it, otherwise nodes
+    /// writing to the surface keep it in SDR range.
+    #[inline]
+    pub fn presents_hdr(&self) -> bool {
+        self.hdr_output && self.surface_format == HDR_SURFACE_FORMAT
+    }
+}
+
+/// CPU side data uploaded to a GPU buffer that's reused while it's large enough.
+///
+/// The buffer grows by half its capacity when outgrown, and never shrinks, so per frame
+/// data doesn't reallocate it, and bind groups referring to it stay valid.
 pub struct DynamicGpuBuffer {
     raw: DynamicStorageBuffer<Vec<u8>>,
     buffer: Option<Buffer>,
-    changed: bool,
+    usage_changed: bool,
     usage: BufferUsages,
 }
 
@@ -50,7 +146,7 @@ impl DynamicGpuBuffer {
         Self {
             raw: DynamicStorageBuffer::new(Vec::new()),
             buffer: None,
-            changed: true,
+            usage_changed: false,
             usage: usage | BufferUsages::COPY_DST,
         }
     }
@@ -59,14 +155,18 @@ impl DynamicGpuBuffer {
         Self {
             raw: DynamicStorageBuffer::new_with_alignment(Vec::new(), alignment),
             buffer: None,
-            changed: true,
+            usage_changed: false,
             usage: usage | BufferUsages::COPY_DST,
         }
     }
 
-    pub fn set(&mut self, data: Vec<u8>) {
+    /// Replace the contents, padded to [`COPY_BUFFER_ALIGNMENT`] so they can be written.
+    pub fn set(&mut self, mut data: Vec<u8>) {
+        data.resize(
+            data.len().next_multiple_of(COPY_BUFFER_ALIGNMENT as usize),
+            0,
+        );
         self.raw = DynamicStorageBuffer::new(data);
-        self.changed = true;
     }
 
     pub fn push<E: ShaderType + WriteInto>(&mut self, data: &E) -> u32 {
@@ -77,36 +177,50 @@ impl DynamicGpuBuffer {
         &self.usage
     }
 
+    /// The buffer is reallocated by the next [`Self::write`].
     pub fn usage_mut(&mut self) -> &mut BufferUsages {
-        self.changed = true;
+        self.usage_changed = true;
         &mut self.usage
     }
 
+    /// Upload the contents, reallocating only if they outgrew the buffer or the usage changed.
+    ///
+    /// Empty contents still allocate `E::min_size()`, so the buffer can be bound.
     pub fn write<E: ShaderType + WriteInto>(&mut self, device: &Device, queue: &Queue) {
-        let capacity = self.buffer.as_ref().map(|b| b.size()).unwrap_or(0);
-        let size = self.raw.as_ref().len() as u64;
-
-        if capacity < size || self.changed {
-            if size == 0 {
-                self.buffer = Some(device.create_buffer(&BufferDescriptor {
-                    label: None,
-                    size: E::min_size().get(),
-                    usage: self.usage,
-                    mapped_at_creation: false,
-                }));
+        let capacity = self.capacity();
+        let required = (self.raw.as_ref().len() as u64).max(E::min_size().get());
+
+        if self.buffer.is_none() || self.usage_changed || capacity < required {
+            // Bindings can't be larger than the limits, so only grow up to them.
+            let limits = device.limits();
+            let max_binding = if self.usage.contains(BufferUsages::UNIFORM) {
+                limits.max_uniform_buffer_binding_size as u64
+            } else if self.usage.contains(BufferUsages::STORAGE) {
+                limits.max_storage_buffer_binding_size as u64
             } else {
-                self.buffer = Some(device.create_buffer_init(&BufferInitDescriptor {
-                    label: None,
-                    usage: self.usage,
-                    contents: self.raw.as_ref(),
-                }));
-            }
-            self.changed = false;
-        } else if let Some(buffer) = &self.buffer {
+                u64::MAX
+            };
+            let size = if capacity < required {
+                (capacity + capacity / 2).min(max_binding).max(required)
+            } else {
+                capacity
+            };
+
+            self.buffer = Some(device.create_buffer(&BufferDescriptor {
+                label: None,
+                size: size.next_multiple_of(COPY_BUFFER_ALIGNMENT),
+                usage: self.usage,
+                mapped_at_creation: false,
+            }));
+            self.usage_changed = false;
+        }
+
+        if let (Some(buffer), false) = (&self.buffer, self.raw.as_ref().is_empty()) {
             queue.write_buffer(buffer, 0, self.raw.as_ref());
         }
     }
 
+    /// Remove the contents, keeping the buffer for the next [`Self::write`].
     pub fn clear(&mut self) {
         self.raw.as_mut().clear();
         self.raw.set_offset(0);
@@ -128,9 +242,136 @@ impl DynamicGpuBuffer {
         self.buffer.as_ref()
     }
 
-    pub fn len_bytes(&self) -> usize {
+    /// Size of the contents in bytes.
+    pub fn len(&self) -> usize {
         self.raw.as_ref().len()
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.raw.as_ref().is_empty()
+    }
+
+    /// Size of the allocated buffer in bytes, 0 before the first [`Self::write`].
+    pub fn capacity(&self) -> u64 {
+        self.buffer.as_ref().map_or(0, |b| b.size())
+    }
+}
+
+type BufferKey = (Id<Buffer>, u64, Option<NonZeroU64>);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum BindingKey {
+    Buffer(BufferKey),
+    BufferArray(Vec<BufferKey>),
+    Sampler(Id<Sampler>),
+    SamplerArray(Vec<Id<Sampler>>),
+    TextureView(Id<TextureView>),
+    TextureViewArray(Vec<Id<TextureView>>),
+}
+
+impl BindingKey {
+    fn new(resource: &BindingResource) -> Option<Self> {
+        let buffer = |b: &BufferBinding| -> BufferKey { (b.buffer.global_id(), b.offset, b.size) };
+        Some(match resource {
+            BindingResource::Buffer(b) => Self::Buffer(buffer(b)),
+            BindingResource::BufferArray(b) => Self::BufferArray(b.iter().map(buffer).collect()),
+            BindingResource::Sampler(s) => Self::Sampler(s.global_id()),
+            BindingResource::SamplerArray(s) => {
+                Self::SamplerArray(s.iter().map(|s| s.global_id()).collect())
+            }
+            BindingResource::TextureView(v) => Self::TextureView(v.global_id()),
+            BindingResource::TextureViewArray(v) => {
+                Self::TextureViewArray(v.iter().map(|v| v.global_id()).collect())
+            }
+            _ => return None,
+        })
+    }
+}
+
+type BindGroupKey = (Id<BindGroupLayout>, Vec<(u32, BindingKey)>);
+
+/// Bind groups created and reused during a frame, see [`BindGroupCache`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BindGroupCacheStats {
+    pub created: u32,
+    pub reused: u32,
+}
+
+/// Bind groups reused across frames while their layout and resources stay the same.
+///
+/// Meant for bind groups nodes would otherwise create every frame. Resources are compared by
+/// id, so reallocated textures or buffers, like on resize, miss the cache, and bind groups
+/// left unused for [`Self::MAX_UNUSED_FRAMES`] are dropped by [`Self::end_frame`].
+#[derive(Default)]
+pub struct BindGroupCache {
+    entries: HashMap<BindGroupKey, (Arc<BindGroup>, u32)>,
+    frame: u32,
+    frame_stats: BindGroupCacheStats,
+    stats: BindGroupCacheStats,
+}
+
+impl BindGroupCache {
+    /// Unused frames after which a bind group is dropped. More than one, as nodes reading
+    /// the swap chain alternate between its textures when a frame swaps it an odd number of
+    /// times.
+    pub const MAX_UNUSED_FRAMES: u32 = 2;
+
+    pub fn get_or_create(&mut self, device: &Device, desc: &BindGroupDescriptor) -> Arc<BindGroup> {
+        let entries = desc
+            .entries
+            .iter()
+            .map(|entry| Some((entry.binding, BindingKey::new(&entry.resource)?)))
+            .collect::<Option<_>>();
+        // Resources added to wgpu later aren't cached.
+        let Some(entries) = entries else {
+            self.frame_stats.created += 1;
+            return Arc::new(device.create_bind_group(desc));
+        };
+        let key = (desc.layout.global_id(), entries);
+        let frame = self.frame;
+        let (bind_group, last_used) = match self.entries.entry(key) {
+            Entry::Occupied(entry) => {
+                self.frame_stats.reused += 1;
+                entry.into_mut()
+            }
+            Entry::Vacant(entry) => {
+                self.frame_stats.created += 1;
+                entry.insert((Arc::new(device.create_bind_group(desc)), frame))
+            }
+        };
+        *last_used = frame;
+        bind_group.clone()
+    }
+
+    /// Drop stale bind groups and start counting the next frame, called by the flow after
+    /// running.
+    pub fn end_frame(&mut self) {
+        let frame = self.frame;
+        self.entries
+            .retain(|_, (_, last_used)| frame.wrapping_sub(*last_used) < Self::MAX_UNUSED_FRAMES);
+        self.frame = self.frame.wrapping_add(1);
+        self.stats = std::mem::take(&mut self.frame_stats);
+    }
+
+    /// Bind groups created and reused during the last frame.
+    #[inline]
+    pub fn stats(&self) -> BindGroupCacheStats {
+        self.stats
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
 }
 
 #[derive(Default)]
@@ -142,6 +383,13 @@ pub struct ImageTextureDescriptor<'a> {
     pub usage: Option<TextureUsages>,
     pub view_formats: Option<&'a [TextureFormat]>,
     pub data_order: Option<TextureDataOrder>,
+    /// Multiply the color by the alpha before uploading, in linear space for sRGB formats.
+    ///
+    /// Premultiplied textures are filtered without dark fringes around transparent texels,
+    /// and are meant for `BlendState::PREMULTIPLIED_ALPHA_BLENDING` or additive blending of
+    /// the color alone. `BlendState::ALPHA_BLENDING`, used by the PBR node for blended
+    /// materials, expects straight alpha. Formats without alpha are left as is.
+    pub premultiply_alpha: bool,
 }
 
 pub struct Image {
@@ -268,11 +516,15 @@ impl Image {
         }
     }
 
+    /// Images larger than `max_texture_size` on either side are downscaled to fit, keeping
+    /// their aspect ratio. Usually `device.limits().max_texture_dimension_2d` or less.
     pub fn from_path(
         path: impl AsRef<Path>,
         format_override: Option<ImageFormatOverride>,
         is_srgb: bool,
+        max_texture_size: Option<u32>,
     ) -> ImageResult<Self> {
+        let path = path.as_ref();
         let img = image::open(path).map(|img| match format_override {
             Some(fmt) => match fmt {
                 ImageFormatOverride::ImageLuma8 => DynamicImage::ImageLuma8(img.into_luma8()),
@@ -292,6 +544,7 @@ impl Image {
             },
             None => img,
         })?;
+        let img = fit_texture_size(img, max_texture_size, &path.display());
         Ok(Self::from_dynamic(img, is_srgb))
     }
 
@@ -304,11 +557,18 @@ impl Image {
         }
     }
 
-    pub fn from_buffer(data: &[u8], format: ImageFormat, is_srgb: bool) -> Self {
+    /// Same as [`Image::from_path`], for encoded images in memory.
+    pub fn from_buffer(
+        data: &[u8],
+        format: ImageFormat,
+        is_srgb: bool,
+        max_texture_size: Option<u32>,
+    ) -> Self {
         let mut reader = image::ImageReader::new(std::io::Cursor::new(data));
         reader.set_format(format);
         reader.no_limits();
         let dyn_image = reader.decode().unwrap();
+        let dyn_image = fit_texture_size(dyn_image, max_texture_size, &format!("{format:?} image"));
         Self::from_dynamic(dyn_image, is_srgb)
     }
 
@@ -320,12 +580,22 @@ impl Image {
         self.height
     }
 
+    /// Panics if the image exceeds the device limit, load it with a `max_texture_size` to
+    /// downscale it instead.
     pub fn to_texture(
         &self,
         device: &Device,
         queue: &Queue,
         desc: &ImageTextureDescriptor,
     ) -> Texture {
+        let limit = device.limits().max_texture_dimension_2d;
+        assert!(
+            self.width <= limit && self.height <= limit,
+            "Image of {}x{} exceeds the maximum texture size {limit}.",
+            self.width,
+            self.height,
+        );
+
         device.create_texture_with_data(
             queue,
             &TextureDescriptor {
@@ -344,10 +614,53 @@ impl Image {
                 view_formats: desc.view_formats.unwrap_or_default(),
             },
             desc.data_order.unwrap_or_default(),
-            &self.buffer,
+            &if desc.premultiply_alpha {
+                self.premultiplied_buffer()
+            } else {
+                Cow::Borrowed(self.buffer.as_slice())
+            },
         )
     }
 
+    fn premultiplied_buffer(&self) -> Cow<'_, [u8]> {
+        let mut buffer = self.buffer.clone();
+        match self.format {
+            TextureFormat::Rgba8Unorm => {
+                for texel in buffer.chunks_exact_mut(4) {
+                    let alpha = texel[3] as u32;
+                    for c in &mut texel[..3] {
+                        *c = ((*c as u32 * alpha + 127) / 255) as u8;
+                    }
+                }
+            }
+            TextureFormat::Rgba8UnormSrgb => {
+                for texel in buffer.chunks_exact_mut(4) {
+                    let alpha = texel[3] as f32 / 255.;
+                    let linear = Srgb::new(texel[0], texel[1], texel[2]).into_linear::<f32>();
+                    let color = Srgb::<u8>::from_linear(linear * alpha);
+                    texel[..3].copy_from_slice(&[color.red, color.green, color.blue]);
+                }
+            }
+            // The bytes aren't aligned for casting to wider channels.
+            TextureFormat::Rgba16Unorm => {
+                for texel in buffer.chunks_exact_mut(8) {
+                    let [c @ .., alpha] = bytemuck::pod_read_unaligned::<[u16; 4]>(texel);
+                    let c = c.map(|c| ((c as u32 * alpha as u32 + 32767) / 65535) as u16);
+                    texel.copy_from_slice(bytemuck::bytes_of(&[c[0], c[1], c[2], alpha]));
+                }
+            }
+            TextureFormat::Rgba32Float => {
+                for texel in buffer.chunks_exact_mut(16) {
+                    let [c @ .., alpha] = bytemuck::pod_read_unaligned::<[f32; 4]>(texel);
+                    let c = c.map(|c| c * alpha);
+                    texel.copy_from_slice(bytemuck::bytes_of(&[c[0], c[1], c[2], alpha]));
+                }
+            }
+            _ => return Cow::Borrowed(&self.buffer),
+        }
+        Cow::Owned(buffer)
+    }
+
     pub fn to_cube_map(
         &self,
         device: &Device,
@@ -379,6 +692,8 @@ impl Image {
             queue,
             &ImageTextureDescriptor {
                 dimension: None,
+                // Only the cube map gets the other mips.
+                mip_level_count: None,
                 usage: Some(
                     desc.usage.unwrap_or_else(TextureUsages::empty) | TextureUsages::COPY_SRC,
                 ),
@@ -420,6 +735,30 @@ impl Image {
     }
 }
 
+/// Downscale `img` so neither side exceeds `max_texture_size`.
+fn fit_texture_size(
+    img: DynamicImage,
+    max_texture_size: Option<u32>,
+    name: &dyn std::fmt::Display,
+) -> DynamicImage {
+    let Some(max) = max_texture_size else {
+        return img;
+    };
+    if img.width() <= max && img.height() <= max {
+        return img;
+    }
+
+    let scaled = img.resize(max, max, FilterType::Triangle);
+    warn!(
+        "{name} of {}x{} exceeds the maximum texture size {max}, downscaled to {}x{}.",
+        img.width(),
+        img.height(),
+        scaled.width(),
+        scaled.height(),
+    );
+    scaled
+}
+
 pub enum ImageFormatOverride {
     ImageLuma8,
     ImageLumaA8,
@@ -437,6 +776,93 @@ pub enum ImageFormatOverride {
 pub struct RenderMesh {
     pub mesh: StaticMesh,
     pub offset: Option<u32>,
+    /// Offset of the model of the mesh, set by [`ModelUniforms::prepare`].
+    pub model_offset: Option<u32>,
+}
+
+/// Model matrices of a [`StaticMesh`], see [`StaticMesh::transform`].
+#[derive(ShaderType)]
+pub struct GpuModel {
+    pub model: Mat4,
+    /// Inverse transpose of `model`, so normals stay perpendicular under non-uniform scale.
+    pub normal: Mat3,
+}
+
+impl From<&Transform> for GpuModel {
+    fn from(transform: &Transform) -> Self {
+        let model = transform.model_matrix();
+        Self {
+            model,
+            normal: Mat3::from_mat4(model).inverse().transpose(),
+        }
+    }
+}
+
+/// Models of the meshes a node draws, each bound with its own dynamic offset, like
+/// material uniforms.
+///
+/// The first one is always the identity, for draws not tied to a single mesh.
+pub struct ModelUniforms {
+    pub layout: BindGroupLayout,
+    pub buffer: DynamicGpuBuffer,
+    pub bind_group: Option<BindGroup>,
+}
+
+impl ModelUniforms {
+    pub const IDENTITY_OFFSET: u32 = 0;
+
+    pub fn new(device: &Device) -> Self {
+        Self {
+            layout: device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("model_layout"),
+                entries: &[BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: Some(GpuModel::min_size()),
+                    },
+                    count: None,
+                }],
+            }),
+            buffer: DynamicGpuBuffer::new(BufferUsages::UNIFORM),
+            bind_group: None,
+        }
+    }
+
+    /// Upload the model of each mesh, setting [`RenderMesh::model_offset`].
+    pub fn prepare(&mut self, device: &Device, queue: &Queue, meshes: &mut [RenderMesh]) {
+        self.prepare_with(device, queue, meshes, |mesh| mesh.mesh.transform);
+    }
+
+    /// Like [`ModelUniforms::prepare`], placing each mesh by `transform` instead.
+    ///
+    /// Offsets only depend on the order of `meshes`, so uniforms prepared from the same
+    /// meshes can be bound with the same [`RenderMesh::model_offset`].
+    pub fn prepare_with(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        meshes: &mut [RenderMesh],
+        transform: impl Fn(&RenderMesh) -> Transform,
+    ) {
+        self.buffer.clear();
+        self.buffer.push(&GpuModel::from(&Transform::default()));
+        for mesh in meshes {
+            mesh.model_offset = Some(self.buffer.push(&GpuModel::from(&transform(mesh))));
+        }
+        self.buffer.write::<GpuModel>(device, queue);
+
+        self.bind_group = Some(device.create_bind_group(&BindGroupDescriptor {
+            label: Some("model_bind_group"),
+            layout: &self.layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: self.buffer.binding::<GpuModel>().unwrap(),
+            }],
+        }));
+    }
 }
 
 #[derive(ShaderType, NoUninit, Default, Debug, Clone, Copy)]
@@ -455,9 +881,10 @@ pub struct GpuSceneDesc {
     pub dir_lights: u32,
     pub point_lights: u32,
     pub spot_lights: u32,
+    pub time: f32,
 }
 
-#[derive(ShaderType)]
+#[derive(ShaderType, Debug, Clone, Copy, PartialEq)]
 pub struct GpuDirectionalLight {
     pub direction: Vec3,
     pub color: Vec3,
@@ -465,7 +892,7 @@ pub struct GpuDirectionalLight {
     pub radius: f32,
 }
 
-#[derive(ShaderType)]
+#[derive(ShaderType, Debug, Clone, Copy, PartialEq)]
 pub struct GpuPointLight {
     pub position: Vec3,
     pub color: Vec3,
@@ -473,7 +900,7 @@ pub struct GpuPointLight {
     pub radius: f32,
 }
 
-#[derive(ShaderType)]
+#[derive(ShaderType, Debug, Clone, Copy, PartialEq)]
 pub struct GpuSpotLight {
     pub position: Vec3,
     pub direction: Vec3,
@@ -483,3 +910,93 @@ pub struct GpuSpotLight {
     pub inner_angle: f32,
     pub outer_angle: f32,
 }
+
+#[cfg(test)]
+mod tests {
+    use glam::Mat4;
+    use image::{ImageFormat, RgbaImage};
+    use wgpu::{BufferUsages, TextureFormat};
+
+    use super::{DynamicGpuBuffer, Image};
+    use crate::util::testing;
+
+    #[test]
+    fn premultiply_alpha() {
+        let premultiply = |format, buffer: Vec<u8>| {
+            Image::from_raw_parts(buffer, format, 2, 1)
+                .premultiplied_buffer()
+                .into_owned()
+        };
+
+        let texels = vec![255, 128, 0, 128, 10, 20, 30, 0];
+        assert_eq!(
+            premultiply(TextureFormat::Rgba8Unorm, texels.clone()),
+            [128, 64, 0, 128, 0, 0, 0, 0]
+        );
+        // Halving in linear space keeps sRGB values well above half.
+        let srgb = premultiply(TextureFormat::Rgba8UnormSrgb, texels.clone());
+        assert_eq!((srgb[0], srgb[2], srgb[3]), (188, 0, 128));
+        assert_eq!(srgb[4..], [0; 4]);
+
+        let floats = bytemuck::cast_slice(&[2f32, 1., 0.5, 0.5, 1., 1., 1., 1.]).to_vec();
+        assert_eq!(
+            bytemuck::pod_collect_to_vec::<u8, f32>(&premultiply(
+                TextureFormat::Rgba32Float,
+                floats
+            )),
+            [1., 0.5, 0.25, 0.5, 1., 1., 1., 1.]
+        );
+
+        // No alpha to multiply with.
+        assert_eq!(premultiply(TextureFormat::R16Uint, texels.clone()), texels);
+    }
+
+    #[test]
+    fn max_texture_size() {
+        let mut png = Vec::new();
+        RgbaImage::new(64, 16)
+            .write_to(&mut std::io::Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+
+        // Fits in the limit, keeping the aspect ratio.
+        let image = Image::from_buffer(&png, ImageFormat::Png, true, Some(32));
+        assert_eq!((image.width(), image.height()), (32, 8));
+        assert_eq!(image.buffer.len(), 32 * 8 * 4);
+
+        let image = Image::from_buffer(&png, ImageFormat::Png, true, Some(64));
+        assert_eq!((image.width(), image.height()), (64, 16));
+        let image = Image::from_buffer(&png, ImageFormat::Png, true, None);
+        assert_eq!((image.width(), image.height()), (64, 16));
+    }
+
+    #[test]
+    fn dynamic_buffer_growth() {
+        let Some(renderer) = testing::renderer(None, None) else {
+            return;
+        };
+        let (device, queue) = (&renderer.device, &renderer.queue);
+
+        let mut buffer = DynamicGpuBuffer::new_with_alignment(BufferUsages::STORAGE, 64);
+        let mut write = |count: usize| {
+            buffer.clear();
+            for _ in 0..count {
+                buffer.push(&Mat4::IDENTITY);
+            }
+            buffer.write::<Mat4>(device, queue);
+            assert_eq!(buffer.len(), count * 64);
+            (buffer.buffer().unwrap().global_id(), buffer.capacity())
+        };
+
+        let (first, capacity) = write(4);
+        assert_eq!(capacity, 256);
+        // Grows by half when outgrown.
+        let (grown, capacity) = write(5);
+        assert_ne!(grown, first);
+        assert_eq!(capacity, 384);
+        assert_eq!(write(6), (grown, 384));
+
+        // Shrinking and clearing keep the buffer.
+        assert_eq!(write(2), (grown, 384));
+        assert_eq!(write(0), (grown, 384));
+    }
+}
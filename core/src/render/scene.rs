@@ -1,12 +1,13 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, path::PathBuf};
 
+use log::warn;
 use uuid::Uuid;
 use wgpu::{BindGroup, BindGroupLayout, BufferUsages, Sampler, Texture, TextureView};
 
 use crate::render::{
     helper::Scene,
-    mesh::{GpuMesh, Mesh, StaticMesh},
-    resource::DynamicGpuBuffer,
+    mesh::{GpuMesh, InstancedMesh, Mesh, StaticMesh},
+    resource::{BindGroupCache, DynamicGpuBuffer},
 };
 
 #[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -51,6 +52,14 @@ pub struct GpuAssets {
     pub textures: HashMap<TextureId, Texture>,
     pub texture_views: HashMap<TextureViewId, TextureView>,
     pub samplers: HashMap<SamplerId, Sampler>,
+    /// Files to load into textures owned by another node, like swapping the environment
+    /// map from a node drawing it. The owner takes the request when preparing.
+    pub requested_files: HashMap<TextureId, PathBuf>,
+    /// Anisotropy of material texture samplers, 1 disables it. Building a flow clamps it to
+    /// [`WgpuRenderer::max_anisotropy`].
+    ///
+    /// [`WgpuRenderer::max_anisotropy`]: crate::WgpuRenderer::max_anisotropy
+    pub max_anisotropy: u16,
 
     pub common_bind_group: Option<BindGroup>,
     pub light_bind_group: Option<BindGroup>,
@@ -61,6 +70,8 @@ pub struct GpuAssets {
     pub lights_layout: Option<BindGroupLayout>,
     pub material_layouts: HashMap<MaterialTypeId, BindGroupLayout>,
     pub extra_layouts: HashMap<ExtraLayoutId, BindGroupLayout>,
+    /// Bind groups created in `draw`, see [`BindGroupCache`].
+    pub bind_group_cache: BindGroupCache,
 }
 
 impl Default for GpuAssets {
@@ -86,15 +97,54 @@ impl Default for GpuAssets {
             texture_views: Default::default(),
             extra_buffers: Default::default(),
             samplers: Default::default(),
+            requested_files: Default::default(),
+            max_anisotropy: 16,
+            bind_group_cache: Default::default(),
         }
     }
 }
 
+impl GpuAssets {
+    /// Layout created by another node, warning that `producer` should run first when
+    /// it's missing.
+    pub fn required_layout(
+        &self,
+        id: &ExtraLayoutId,
+        name: &str,
+        producer: &str,
+    ) -> Option<&BindGroupLayout> {
+        let layout = self.extra_layouts.get(id);
+        if layout.is_none() {
+            warn!("Bind group layout `{name}` is missing, add `{producer}` before this node.");
+        }
+        layout
+    }
+
+    /// Bind group created by another node, see [`Self::required_layout`].
+    ///
+    /// Only warns in debug builds, as it's looked up every frame.
+    pub fn required_bind_group(
+        &self,
+        id: &ExtraBindGroupId,
+        name: &str,
+        producer: &str,
+    ) -> Option<&BindGroup> {
+        let bind_group = self.extra_bind_groups.get(id);
+        if cfg!(debug_assertions) && bind_group.is_none() {
+            warn!("Bind group `{name}` is missing, add `{producer}` before this node.");
+        }
+        bind_group
+    }
+}
+
 #[derive(Default)]
 pub struct GpuScene {
     pub original: Scene,
     pub assets: GpuAssets,
     pub static_meshes: Vec<StaticMesh>,
+    pub instanced_meshes: Vec<InstancedMesh>,
     pub delta_time: f32,
+    /// Sum of `delta_time`, exposed to shaders as `scene.time`.
+    pub time: f32,
     pub frame_count: u32,
 }
@@ -1,5 +1,22 @@
+use std::f32::consts::FRAC_PI_4;
+
 use glam::{UVec2, Vec3};
 use uuid::Uuid;
+use wgpu::{
+    Color, Extent3d, ImageCopyTexture, LoadOp, Operations, Origin3d, RenderPassColorAttachment,
+    RenderPassDescriptor, StoreOp, Texture, TextureAspect, TextureDescriptor, TextureDimension,
+    TextureUsages, TextureView, TextureViewDescriptor, TextureViewDimension,
+};
+
+use crate::{
+    render::{
+        flow::RenderFlow,
+        helper::{AsymmetricPerspectiveProjection, CameraProjection, Transform},
+        resource::RenderTargets,
+        scene::GpuScene,
+    },
+    WgpuRenderer,
+};
 
 // From Bevy
 pub struct CubeMapFace {
@@ -8,6 +25,19 @@ pub struct CubeMapFace {
     pub id: Uuid,
 }
 
+impl CubeMapFace {
+    /// Camera at `position` rendering this face of a cube map sampled by direction.
+    ///
+    /// `target` points away from the face, as point shadows are sampled from the surface
+    /// towards the light. Cube maps are left handed, so the rendered image is upside down
+    /// and is rendered through a vertically flipped projection, see [`render_to_cubemap`].
+    pub fn camera_transform(&self, position: Vec3) -> Transform {
+        Transform::default()
+            .with_translation(position)
+            .looking_at(position - self.target, self.up)
+    }
+}
+
 // see https://www.khronos.org/opengl/wiki/Cubemap_Texture
 pub const CUBE_MAP_FACES: [CubeMapFace; 6] = [
     // 0 	GL_TEXTURE_CUBE_MAP_POSITIVE_X
@@ -49,7 +79,7 @@ pub const CUBE_MAP_FACES: [CubeMapFace; 6] = [
 ];
 
 /// Offsets of cube map faces on a 2d texture.
-/// 
+///
 /// ** +Y ** **
 /// -X+ Z +X -Z
 /// ** -Y ** **
@@ -61,3 +91,419 @@ pub const CUBE_MAP_OFFSETS: [UVec2; 6] = [
     UVec2 { x: 1, y: 1 },
     UVec2 { x: 3, y: 1 },
 ];
+
+/// Render `flow` from `position` into each face of a cube map, e.g. for reflection probes.
+///
+/// `flow` must already be built against `targets`, whose swap chain is square and can be
+/// copied from. Faces are copied from the swap chain into a cube map of its format. The
+/// camera sees 90 degrees with its near and far planes kept, and is restored afterwards.
+///
+/// Cube maps are left handed, so the projection is flipped vertically, which also flips
+/// the winding of triangles as seen by the flow.
+pub fn render_to_cubemap(
+    renderer: &WgpuRenderer,
+    flow: &mut RenderFlow,
+    scene: &mut GpuScene,
+    targets: &RenderTargets,
+    position: Vec3,
+) -> Texture {
+    assert_eq!(
+        targets.size.x, targets.size.y,
+        "Cube map faces must be square."
+    );
+    let face_size = targets.size.x;
+    let swap_chain = targets.swap_chain;
+    let cube_map = renderer.device.create_texture(&TextureDescriptor {
+        label: Some("cube_map"),
+        size: Extent3d {
+            width: face_size,
+            height: face_size,
+            depth_or_array_layers: 6,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: targets.color_format,
+        usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST | TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+
+    let camera = scene.original.camera;
+    let (near, far) = match camera.projection {
+        CameraProjection::Perspective(proj) => (proj.near, proj.far),
+        CameraProjection::Orthographic(proj) => (proj.near, proj.far),
+        CameraProjection::AsymmetricPerspective(proj) => (proj.near, proj.far),
+    };
+    // Up and down swapped, see `CubeMapFace::camera_transform`.
+    scene.original.camera.projection =
+        CameraProjection::AsymmetricPerspective(AsymmetricPerspectiveProjection {
+            angle_left: -FRAC_PI_4,
+            angle_right: FRAC_PI_4,
+            angle_up: -FRAC_PI_4,
+            angle_down: FRAC_PI_4,
+            near,
+            far,
+        });
+
+    for (layer, face) in CUBE_MAP_FACES.iter().enumerate() {
+        scene.original.camera.transform = face.camera_transform(position);
+
+        // Nodes loading the swap chain would draw over the previous face otherwise.
+        let mut encoder = renderer.device.create_command_encoder(&Default::default());
+        encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("cube_map_face_clear"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: swap_chain.current_view(),
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Clear(Color::TRANSPARENT),
+                    store: StoreOp::Store,
+                },
+            })],
+            ..Default::default()
+        });
+        renderer.queue.submit([encoder.finish()]);
+
+        flow.run(renderer, scene, targets);
+
+        let mut encoder = renderer.device.create_command_encoder(&Default::default());
+        encoder.copy_texture_to_texture(
+            swap_chain.current_texture().as_image_copy(),
+            ImageCopyTexture {
+                texture: &cube_map,
+                mip_level: 0,
+                origin: Origin3d {
+                    x: 0,
+                    y: 0,
+                    z: layer as u32,
+                },
+                aspect: TextureAspect::All,
+            },
+            Extent3d {
+                width: face_size,
+                height: face_size,
+                depth_or_array_layers: 1,
+            },
+        );
+        renderer.queue.submit([encoder.finish()]);
+    }
+
+    scene.original.camera = camera;
+    cube_map
+}
+
+/// 2d view of a single face of `cube_map` at `mip_level`, to render into.
+pub fn face_view(cube_map: &Texture, face: u32, mip_level: u32) -> TextureView {
+    cube_map.create_view(&TextureViewDescriptor {
+        label: Some("cube_map_face_view"),
+        dimension: Some(TextureViewDimension::D2),
+        base_mip_level: mip_level,
+        mip_level_count: Some(1),
+        base_array_layer: face,
+        array_layer_count: Some(1),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Mat4, UVec2, Vec3};
+    use image::RgbaImage;
+    use wgpu::{
+        util::{BufferInitDescriptor, DeviceExt},
+        BindGroup, BindGroupDescriptor, BindGroupEntry, BindingResource, Buffer, BufferUsages,
+        Color, ColorTargetState, ColorWrites, Device, FragmentState, LoadOp, Operations,
+        RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor,
+        ShaderModuleDescriptor, ShaderSource, StoreOp, Texture, TextureFormat, TextureUsages,
+        TextureViewDescriptor, TextureViewDimension, VertexState,
+    };
+
+    use super::render_to_cubemap;
+    use crate::{
+        render::{
+            flow::{RenderContext, RenderFlow, RenderNode},
+            helper::{CameraProjection, PerspectiveProjection},
+            resource::GpuCamera,
+            scene::GpuScene,
+        },
+        util::{
+            self,
+            testing::{self, TestTargets},
+        },
+        WgpuRenderer,
+    };
+
+    const SIZE: u32 = 32;
+
+    const FULLSCREEN: &str = "
+        @vertex
+        fn vertex(@builtin(vertex_index) index: u32) -> @builtin(position) vec4f {
+            let t = vec2f(f32(index / 2u), f32(index % 2u));
+            return vec4f(t * 4. - 1., 0., 1.);
+        }
+    ";
+
+    // Every pixel shows the direction it looks towards.
+    const DRAW_DIRECTIONS: &str = "
+        struct View {
+            inv_view_proj: mat4x4f,
+            position: vec4f,
+            size: vec4f,
+        }
+
+        @group(0) @binding(0) var<uniform> view: View;
+
+        @fragment
+        fn fragment(@builtin(position) position: vec4f) -> @location(0) vec4f {
+            let uv = position.xy / view.size.xy;
+            let ndc = vec2f(uv.x * 2. - 1., 1. - uv.y * 2.);
+            let world = view.inv_view_proj * vec4f(ndc, 0.5, 1.);
+            let direction = normalize(world.xyz / world.w - view.position.xyz);
+            return vec4f(direction * 0.5 + 0.5, 1.);
+        }
+    ";
+
+    const DIRECTIONS: [Vec3; 7] = [
+        Vec3::new(1., 0.5, 0.5),
+        Vec3::new(1., -0.5, 0.5),
+        Vec3::new(0.5, 1., 0.5),
+        Vec3::new(0.5, 1., -0.5),
+        Vec3::new(0.5, 0.5, -1.),
+        Vec3::new(-0.5, 0.5, -1.),
+        Vec3::new(-1., 0.5, 0.5),
+    ];
+
+    // Pixel `i` samples `DIRECTIONS[i]`, copying texels out of cube maps isn't supported
+    // everywhere.
+    const SAMPLE_CUBE_MAP: &str = "
+        const DIRECTIONS = array(
+            vec3f(1., 0.5, 0.5),
+            vec3f(1., -0.5, 0.5),
+            vec3f(0.5, 1., 0.5),
+            vec3f(0.5, 1., -0.5),
+            vec3f(0.5, 0.5, -1.),
+            vec3f(-0.5, 0.5, -1.),
+            vec3f(-1., 0.5, 0.5),
+        );
+
+        @group(0) @binding(0) var cube_map: texture_cube<f32>;
+        @group(0) @binding(1) var cube_sampler: sampler;
+
+        @fragment
+        fn fragment(@builtin(position) position: vec4f) -> @location(0) vec4f {
+            var directions = DIRECTIONS;
+            return textureSampleLevel(cube_map, cube_sampler, directions[u32(position.x)], 0.);
+        }
+    ";
+
+    fn fullscreen_pipeline(
+        device: &Device,
+        fragment: &str,
+        format: TextureFormat,
+    ) -> RenderPipeline {
+        let module = device.create_shader_module(ShaderModuleDescriptor {
+            label: None,
+            source: ShaderSource::Wgsl(format!("{FULLSCREEN}{fragment}").into()),
+        });
+        device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: None,
+            layout: None,
+            vertex: VertexState {
+                module: &module,
+                entry_point: "vertex",
+                compilation_options: Default::default(),
+                buffers: &[],
+            },
+            fragment: Some(FragmentState {
+                module: &module,
+                entry_point: "fragment",
+                compilation_options: Default::default(),
+                targets: &[Some(ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: ColorWrites::ALL,
+                })],
+            }),
+            primitive: Default::default(),
+            depth_stencil: None,
+            multisample: Default::default(),
+            multiview: None,
+            cache: None,
+        })
+    }
+
+    /// Draws [`DRAW_DIRECTIONS`] to the swap chain.
+    #[derive(Default)]
+    struct DirectionNode {
+        pipeline: Option<RenderPipeline>,
+        view: Option<Buffer>,
+        bind_group: Option<BindGroup>,
+    }
+
+    impl RenderNode for DirectionNode {
+        fn build(&mut self, _scene: &mut GpuScene, context: RenderContext) {
+            let device = context.device;
+            let pipeline =
+                fullscreen_pipeline(device, DRAW_DIRECTIONS, context.targets.color_format);
+            let view = device.create_buffer_init(&BufferInitDescriptor {
+                label: None,
+                contents: &[0; 96],
+                usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+            });
+            self.bind_group = Some(device.create_bind_group(&BindGroupDescriptor {
+                label: None,
+                layout: &pipeline.get_bind_group_layout(0),
+                entries: &[BindGroupEntry {
+                    binding: 0,
+                    resource: view.as_entire_binding(),
+                }],
+            }));
+            self.pipeline = Some(pipeline);
+            self.view = Some(view);
+        }
+
+        fn draw(&self, scene: &mut GpuScene, context: RenderContext) {
+            let camera: GpuCamera = scene.original.camera.into();
+            let mut view = Mat4::inverse(&(camera.proj * camera.view))
+                .to_cols_array()
+                .to_vec();
+            view.extend(camera.position_ws.extend(1.).to_array());
+            view.extend(
+                context
+                    .targets
+                    .size
+                    .as_vec2()
+                    .extend(0.)
+                    .extend(0.)
+                    .to_array(),
+            );
+            context
+                .queue
+                .write_buffer(self.view.as_ref().unwrap(), 0, bytemuck::cast_slice(&view));
+
+            let mut encoder = context.device.create_command_encoder(&Default::default());
+            {
+                let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                    color_attachments: &[Some(RenderPassColorAttachment {
+                        view: context.targets.swap_chain.current_view(),
+                        resolve_target: None,
+                        ops: Operations {
+                            load: LoadOp::Clear(Color::BLACK),
+                            store: StoreOp::Store,
+                        },
+                    })],
+                    ..Default::default()
+                });
+                pass.set_pipeline(self.pipeline.as_ref().unwrap());
+                pass.set_bind_group(0, self.bind_group.as_ref().unwrap(), &[]);
+                pass.draw(0..3, 0..1);
+            }
+            context.queue.submit([encoder.finish()]);
+        }
+    }
+
+    fn sample_directions(renderer: &WgpuRenderer, cube_map: &Texture) -> RgbaImage {
+        let device = &renderer.device;
+        let target = util::create_texture(
+            device,
+            UVec2::new(DIRECTIONS.len() as u32, 2).extend(1),
+            TextureFormat::Rgba8Unorm,
+            TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+        );
+        let pipeline = fullscreen_pipeline(device, SAMPLE_CUBE_MAP, TextureFormat::Rgba8Unorm);
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&cube_map.create_view(
+                        &TextureViewDescriptor {
+                            dimension: Some(TextureViewDimension::Cube),
+                            ..Default::default()
+                        },
+                    )),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(&util::create_sampler(
+                        device,
+                        &Default::default(),
+                        renderer.max_anisotropy(),
+                    )),
+                },
+            ],
+        });
+
+        let mut encoder = device.create_command_encoder(&Default::default());
+        {
+            let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: &target.create_view(&Default::default()),
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Clear(Color::BLACK),
+                        store: StoreOp::Store,
+                    },
+                })],
+                ..Default::default()
+            });
+            pass.set_pipeline(&pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.draw(0..3, 0..1);
+        }
+        renderer.queue.submit([encoder.finish()]);
+
+        pollster::block_on(util::read_color_texture(
+            &target,
+            &renderer.device,
+            &renderer.queue,
+        ))
+    }
+
+    #[test]
+    fn cube_map_faces() {
+        let Some(renderer) = testing::renderer(None, None) else {
+            return;
+        };
+
+        let mut scene = GpuScene::default();
+        scene.original.camera.projection = CameraProjection::Perspective(PerspectiveProjection {
+            fov: 1.,
+            aspect_ratio: 2.,
+            near: 0.1,
+            far: 10.,
+        });
+        let camera = scene.original.camera;
+
+        let test_targets = TestTargets::new(
+            &renderer.device,
+            UVec2::splat(SIZE),
+            TextureFormat::Rgba8Unorm,
+        );
+        let targets = test_targets.targets();
+        let mut flow = RenderFlow::default();
+        flow.add::<DirectionNode>();
+        flow.build(&renderer, &mut scene, None, &targets).unwrap();
+
+        let position = Vec3::new(1., 2., 3.);
+        let cube_map = render_to_cubemap(&renderer, &mut flow, &mut scene, &targets, position);
+        assert_eq!(cube_map.depth_or_array_layers(), 6);
+        assert_eq!(scene.original.camera.projection, camera.projection);
+        assert_eq!(scene.original.camera.transform, camera.transform);
+
+        // Each direction finds the texel looking towards it, on the right face and the
+        // right way up.
+        let image = sample_directions(&renderer, &cube_map);
+        for (x, direction) in DIRECTIONS.into_iter().enumerate() {
+            let expected = (direction.normalize() * 0.5 + 0.5) * 255.;
+            let pixel = image.get_pixel(x as u32, 0);
+            for channel in 0..3 {
+                assert!(
+                    (pixel[channel] as f32 - expected[channel]).abs() < 10.,
+                    "{direction} {pixel:?}"
+                );
+            }
+        }
+    }
+}
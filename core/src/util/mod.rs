@@ -3,13 +3,18 @@ use std::path::Path;
 use glam::UVec3;
 use image::RgbaImage;
 use wgpu::{
-    BufferDescriptor, BufferUsages, CommandEncoderDescriptor, Device, Extent3d, Features,
-    ImageCopyBuffer, ImageDataLayout, Maintain, MapMode, Queue, Texture, TextureAspect,
-    TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType, TextureUsages,
+    Device, Extent3d, Features, FilterMode, Maintain, Queue, Sampler, SamplerDescriptor, Texture,
+    TextureAspect, TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType,
+    TextureUsages,
 };
 
 pub mod cube;
 pub mod ext;
+mod readback;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
+pub use readback::*;
 
 pub fn create_texture(
     device: &Device,
@@ -41,59 +46,64 @@ pub fn create_texture(
     })
 }
 
+/// Create a sampler, keeping `anisotropy_clamp` within `1..=max_anisotropy`, usually
+/// [`GpuAssets::max_anisotropy`](crate::render::scene::GpuAssets::max_anisotropy).
+///
+/// Anisotropic filtering requires linear min, mag and mipmap filters, so it's dropped for
+/// samplers using nearest filtering, like most comparison samplers, instead of failing
+/// validation.
+pub fn create_sampler(device: &Device, desc: &SamplerDescriptor, max_anisotropy: u16) -> Sampler {
+    let linear = [desc.mag_filter, desc.min_filter, desc.mipmap_filter]
+        .iter()
+        .all(|filter| *filter == FilterMode::Linear);
+
+    device.create_sampler(&SamplerDescriptor {
+        anisotropy_clamp: if linear {
+            desc.anisotropy_clamp.clamp(1, max_anisotropy.max(1))
+        } else {
+            1
+        },
+        ..desc.clone()
+    })
+}
+
 pub async fn save_color_texture_as_image(
     path: impl AsRef<Path>,
     texture: &Texture,
     device: &Device,
     queue: &Queue,
 ) {
-    let extent = texture.size();
-    let mut texture_data = Vec::<u8>::with_capacity((extent.width * extent.height * 4) as usize);
-
-    let out_staging_buffer = device.create_buffer(&BufferDescriptor {
-        label: None,
-        size: texture_data.capacity() as u64,
-        usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
-        mapped_at_creation: false,
-    });
-
-    let mut command_encoder = device.create_command_encoder(&CommandEncoderDescriptor::default());
-    command_encoder.copy_texture_to_buffer(
-        texture.as_image_copy(),
-        ImageCopyBuffer {
-            buffer: &out_staging_buffer,
-            layout: ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(extent.width * 4 as u32),
-                rows_per_image: Some(extent.height as u32),
-            },
-        },
-        extent,
-    );
-    queue.submit(Some(command_encoder.finish()));
-
-    let buffer_slice = out_staging_buffer.slice(..);
-    let (sender, receiver) = flume::bounded(1);
-
-    buffer_slice.map_async(MapMode::Read, move |r| sender.send(r).unwrap());
-    device.poll(Maintain::wait()).panic_on_timeout();
-    receiver.recv_async().await.unwrap().unwrap();
-
-    {
-        let view = buffer_slice.get_mapped_range();
-        texture_data.extend_from_slice(&view[..]);
+    if let Some(parent) = path.as_ref().parent() {
+        let _ = std::fs::create_dir_all(parent);
     }
 
-    out_staging_buffer.unmap();
-
-    let _ = std::fs::create_dir_all(&path);
-
-    RgbaImage::from_raw(extent.width, extent.height, texture_data)
-        .unwrap()
+    read_color_texture(texture, device, queue)
+        .await
         .save(path)
         .unwrap();
 }
 
+/// Read back a 8 bit rgba or bgra texture, waiting for the device to finish all work.
+///
+/// The texture needs [`TextureUsages::COPY_SRC`]. See [`TextureReadback`] to read without
+/// stalling rendering, or other formats.
+pub async fn read_color_texture(texture: &Texture, device: &Device, queue: &Queue) -> RgbaImage {
+    let format = texture.format();
+    if !matches!(
+        format,
+        TextureFormat::Rgba8Unorm
+            | TextureFormat::Rgba8UnormSrgb
+            | TextureFormat::Bgra8Unorm
+            | TextureFormat::Bgra8UnormSrgb
+    ) {
+        panic!("Format {:?} can't be read back as rgba image.", format);
+    }
+
+    let readback = TextureReadback::new(texture, device, queue).unwrap();
+    device.poll(Maintain::wait()).panic_on_timeout();
+    readback.read().await.unwrap().into_rgba_image().unwrap()
+}
+
 pub fn struct_to_bytes<T>(s: &T) -> &[u8] {
     unsafe { core::slice::from_raw_parts(s as *const T as *const u8, core::mem::size_of::<T>()) }
 }
@@ -133,3 +143,78 @@ pub fn texel_format_to_sampled(
         TextureSampleType::Uint => "u32",
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use glam::UVec2;
+    use wgpu::{CompareFunction, ErrorFilter, FilterMode, SamplerDescriptor, TextureFormat};
+
+    use crate::{
+        render::{flow::RenderFlow, scene::GpuScene},
+        util::testing::{self, TestTargets},
+    };
+
+    #[test]
+    fn sampler_anisotropy() {
+        let Some(renderer) = testing::renderer(None, None) else {
+            return;
+        };
+        let device = &renderer.device;
+
+        device.push_error_scope(ErrorFilter::Validation);
+        // Comparison sampler with nearest filtering.
+        super::create_sampler(
+            device,
+            &SamplerDescriptor {
+                compare: Some(CompareFunction::LessEqual),
+                anisotropy_clamp: 16,
+                ..Default::default()
+            },
+            renderer.max_anisotropy(),
+        );
+        super::create_sampler(
+            device,
+            &SamplerDescriptor {
+                mag_filter: FilterMode::Linear,
+                min_filter: FilterMode::Linear,
+                mipmap_filter: FilterMode::Linear,
+                anisotropy_clamp: 64,
+                ..Default::default()
+            },
+            renderer.max_anisotropy(),
+        );
+        super::create_sampler(
+            device,
+            &SamplerDescriptor {
+                anisotropy_clamp: 0,
+                ..Default::default()
+            },
+            renderer.max_anisotropy(),
+        );
+        let error = pollster::block_on(device.pop_error_scope());
+        assert!(error.is_none(), "{error:?}");
+    }
+
+    #[test]
+    fn build_clamps_anisotropy() {
+        let Some(renderer) = testing::renderer(None, None) else {
+            return;
+        };
+
+        let targets =
+            TestTargets::new(&renderer.device, UVec2::splat(4), TextureFormat::Rgba8Unorm);
+        let mut scene = GpuScene::default();
+        scene.assets.max_anisotropy = 64;
+        RenderFlow::default()
+            .build(&renderer, &mut scene, None, &targets.targets())
+            .unwrap();
+        assert_eq!(scene.assets.max_anisotropy, renderer.max_anisotropy());
+
+        // Zero would fail validation, it turns anisotropic filtering off instead.
+        scene.assets.max_anisotropy = 0;
+        RenderFlow::default()
+            .build(&renderer, &mut scene, None, &targets.targets())
+            .unwrap();
+        assert_eq!(scene.assets.max_anisotropy, 1);
+    }
+}
@@ -0,0 +1,198 @@
+use flume::Receiver;
+use image::RgbaImage;
+use thiserror::Error;
+use wgpu::{
+    Buffer, BufferAsyncError, BufferDescriptor, BufferUsages, CommandEncoderDescriptor, Device,
+    Extent3d, ImageCopyBuffer, ImageDataLayout, MapMode, Queue, Texture, TextureAspect,
+    TextureFormat, COPY_BYTES_PER_ROW_ALIGNMENT,
+};
+
+#[derive(Error, Debug)]
+pub enum TextureReadbackError {
+    #[error("Format {0:?} can't be copied to a buffer as a whole, like depth stencil formats.")]
+    UnsupportedFormat(TextureFormat),
+    #[error("Failed to map the readback buffer.")]
+    Map(#[from] BufferAsyncError),
+}
+
+/// First mip and layer of a texture, read with [`TextureReadback`].
+#[derive(Debug, Clone)]
+pub struct TextureData {
+    /// Rows of texel blocks, `bytes_per_row` apart without padding.
+    pub bytes: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+    pub format: TextureFormat,
+    pub bytes_per_row: u32,
+}
+
+impl TextureData {
+    /// `None` unless the texture is 8 bit rgba or bgra.
+    pub fn into_rgba_image(mut self) -> Option<RgbaImage> {
+        match self.format {
+            TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb => {}
+            TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb => {
+                for pixel in self.bytes.chunks_exact_mut(4) {
+                    pixel.swap(0, 2);
+                }
+            }
+            _ => return None,
+        }
+        RgbaImage::from_raw(self.width, self.height, self.bytes)
+    }
+}
+
+/// Texture copied to a staging buffer, read without waiting for the device to go idle.
+///
+/// The mapping completes when the device is polled after the copy finished on the GPU.
+/// Rendering polls it with every submission, otherwise call `device.poll(Maintain::Poll)`,
+/// for example from a worker thread. [`TextureReadback::read`] never resolves unless
+/// something polls the device.
+pub struct TextureReadback {
+    buffer: Buffer,
+    mapped: Receiver<Result<(), BufferAsyncError>>,
+    extent: Extent3d,
+    format: TextureFormat,
+    bytes_per_row: u32,
+    padded_bytes_per_row: u32,
+}
+
+impl TextureReadback {
+    /// Copy the first mip and layer of `texture` and start mapping the copy.
+    ///
+    /// The texture needs [`TextureUsages::COPY_SRC`](wgpu::TextureUsages::COPY_SRC).
+    pub fn new(
+        texture: &Texture,
+        device: &Device,
+        queue: &Queue,
+    ) -> Result<Self, TextureReadbackError> {
+        let format = texture.format();
+        let block_size = format
+            .block_copy_size(Some(TextureAspect::All))
+            .ok_or(TextureReadbackError::UnsupportedFormat(format))?;
+        let (block_width, block_height) = format.block_dimensions();
+
+        let extent = Extent3d {
+            depth_or_array_layers: 1,
+            ..texture.size()
+        };
+        let rows = extent.height.div_ceil(block_height);
+        let bytes_per_row = extent.width.div_ceil(block_width) * block_size;
+        let padded_bytes_per_row = bytes_per_row.next_multiple_of(COPY_BYTES_PER_ROW_ALIGNMENT);
+
+        let buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("texture_readback_buffer"),
+            size: (padded_bytes_per_row * rows) as u64,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor::default());
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            ImageCopyBuffer {
+                buffer: &buffer,
+                layout: ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(rows),
+                },
+            },
+            extent,
+        );
+        queue.submit(Some(encoder.finish()));
+
+        let (sender, mapped) = flume::bounded(1);
+        buffer.slice(..).map_async(MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+
+        Ok(Self {
+            buffer,
+            mapped,
+            extent,
+            format,
+            bytes_per_row,
+            padded_bytes_per_row,
+        })
+    }
+
+    /// Whether [`TextureReadback::read`] resolves right away.
+    pub fn is_ready(&self) -> bool {
+        !self.mapped.is_empty()
+    }
+
+    /// Wait for the mapping, without blocking the thread, and copy out the rows.
+    pub async fn read(self) -> Result<TextureData, TextureReadbackError> {
+        // The callback is dropped without being called only if the buffer is destroyed.
+        self.mapped
+            .recv_async()
+            .await
+            .unwrap_or(Err(BufferAsyncError))?;
+
+        let mut bytes = Vec::with_capacity(self.buffer.size() as usize);
+        {
+            let view = self.buffer.slice(..).get_mapped_range();
+            for row in view.chunks_exact(self.padded_bytes_per_row as usize) {
+                bytes.extend_from_slice(&row[..self.bytes_per_row as usize]);
+            }
+        }
+        self.buffer.unmap();
+
+        Ok(TextureData {
+            bytes,
+            width: self.extent.width,
+            height: self.extent.height,
+            format: self.format,
+            bytes_per_row: self.bytes_per_row,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::UVec3;
+    use wgpu::{ImageDataLayout, Maintain, TextureFormat, TextureUsages};
+
+    use super::TextureReadback;
+    use crate::util::{self, testing};
+
+    #[test]
+    fn float_texture_readback() {
+        let Some(renderer) = testing::renderer(None, None) else {
+            return;
+        };
+        let (device, queue) = (&renderer.device, &renderer.queue);
+
+        // 12 bytes a row, far from the copy alignment.
+        let texture = util::create_texture(
+            device,
+            UVec3::new(3, 2, 1),
+            TextureFormat::R32Float,
+            TextureUsages::COPY_DST | TextureUsages::COPY_SRC,
+        );
+        queue.write_texture(
+            texture.as_image_copy(),
+            bytemuck::cast_slice(&[0.25f32; 6]),
+            ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(12),
+                rows_per_image: None,
+            },
+            texture.size(),
+        );
+
+        let readback = TextureReadback::new(&texture, device, queue).unwrap();
+        while !readback.is_ready() {
+            device.poll(Maintain::Poll);
+        }
+
+        let data = pollster::block_on(readback.read()).unwrap();
+        assert_eq!((data.width, data.height, data.bytes_per_row), (3, 2, 12));
+        assert_eq!(
+            bytemuck::pod_collect_to_vec::<u8, f32>(&data.bytes),
+            [0.25; 6]
+        );
+        assert!(data.into_rgba_image().is_none());
+    }
+}
@@ -0,0 +1,223 @@
+//! Fixtures shared by tests rendering through a [`RenderFlow`](crate::render::flow::RenderFlow).
+//!
+//! Enabled in other crates with the `testing` feature.
+
+use glam::{UVec2, Vec2, Vec3};
+use image::RgbaImage;
+use uuid::Uuid;
+use wgpu::{
+    Device, Extent3d, Features, ImageCopyTexture, Limits, Origin3d, Texture, TextureDescriptor,
+    TextureFormat, TextureUsages,
+};
+
+use crate::{
+    render::{
+        mesh::{Mesh, MeshIndices, MeshVertexAttributeData, StaticMesh, DEFAULT_RENDER_LAYER},
+        resource::{ColorSpace, RenderTargets},
+        scene::{GpuScene, MaterialInstanceId, MeshInstanceId},
+    },
+    util, RendererError, SwapChain, SwapChainConfig, WgpuRenderer,
+};
+
+/// Request a renderer, `None` when no GPU or software rasterizer is available or it lacks
+/// `features`, so the test can return early.
+pub fn renderer(features: Option<Features>, limits: Option<Limits>) -> Option<WgpuRenderer> {
+    skip_unsupported(pollster::block_on(WgpuRenderer::new(features, limits)))
+}
+
+/// Like [`renderer`], for renderers requested any other way.
+pub fn skip_unsupported(renderer: Result<WgpuRenderer, RendererError>) -> Option<WgpuRenderer> {
+    match renderer {
+        Ok(renderer) => Some(renderer),
+        Err(RendererError::NoAdapter | RendererError::UnsupportedFeatures(_)) => None,
+        Err(err) => panic!("{err}"),
+    }
+}
+
+/// Quad from `min` to `max` on the plane at `z`, facing +Z, with texture coordinates,
+/// tangents and indices.
+pub fn quad(min: Vec2, max: Vec2, z: f32) -> Mesh {
+    let mut quad = Mesh::new()
+        .with_attribute(
+            Mesh::POSITION_ATTR,
+            MeshVertexAttributeData::Float32x3(vec![
+                Vec3::new(min.x, min.y, z),
+                Vec3::new(max.x, min.y, z),
+                Vec3::new(max.x, max.y, z),
+                Vec3::new(min.x, max.y, z),
+            ]),
+        )
+        .with_attribute(
+            Mesh::NORMAL_ATTR,
+            MeshVertexAttributeData::Float32x3(vec![Vec3::Z; 4]),
+        )
+        .with_attribute(
+            Mesh::TEX_COORDS_ATTR,
+            MeshVertexAttributeData::Float32x2(vec![
+                Vec2::new(0., 1.),
+                Vec2::new(1., 1.),
+                Vec2::new(1., 0.),
+                Vec2::ZERO,
+            ]),
+        )
+        .with_indices(MeshIndices::UInt32(vec![0, 1, 2, 0, 2, 3]));
+    quad.recalculate_tangent().unwrap();
+    quad
+}
+
+/// Add `mesh` to the static meshes of `scene`, drawn with `material`.
+pub fn add_static_mesh(
+    scene: &mut GpuScene,
+    mesh: Mesh,
+    material: MaterialInstanceId,
+) -> MeshInstanceId {
+    let mesh_id = MeshInstanceId(Uuid::new_v4());
+    scene.assets.meshes.insert(mesh_id, mesh);
+    scene.static_meshes.push(StaticMesh {
+        mesh: mesh_id,
+        material,
+        render_layer: DEFAULT_RENDER_LAYER,
+        transform: Default::default(),
+    });
+    mesh_id
+}
+
+/// Owns the textures behind a [`RenderTargets`].
+pub struct TestTargets {
+    pub swap_chain: SwapChain,
+    pub surface: Texture,
+    pub depth: Option<Texture>,
+    pub sample_count: u32,
+    pub hdr_output: bool,
+}
+
+impl TestTargets {
+    /// A `format` swap chain of `size`, which can be copied from and written to, and an
+    /// sRGB surface.
+    pub fn new(device: &Device, size: UVec2, format: TextureFormat) -> Self {
+        Self {
+            swap_chain: SwapChain::from_config(
+                device,
+                &SwapChainConfig {
+                    format,
+                    usage: SwapChain::REQUIRED_USAGES
+                        | TextureUsages::COPY_SRC
+                        | TextureUsages::COPY_DST,
+                    size,
+                },
+            )
+            .unwrap(),
+            surface: util::create_texture(
+                device,
+                size.extend(1),
+                TextureFormat::Rgba8UnormSrgb,
+                TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+            ),
+            depth: None,
+            sample_count: 1,
+            hdr_output: false,
+        }
+    }
+
+    pub fn with_depth(mut self, device: &Device) -> Self {
+        self.depth = Some(util::create_texture(
+            device,
+            self.size().extend(1),
+            TextureFormat::Depth32Float,
+            TextureUsages::RENDER_ATTACHMENT,
+        ));
+        self
+    }
+
+    pub fn with_surface_format(mut self, device: &Device, format: TextureFormat) -> Self {
+        self.surface = util::create_texture(
+            device,
+            self.size().extend(1),
+            format,
+            TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+        );
+        self
+    }
+
+    #[inline]
+    pub fn size(&self) -> UVec2 {
+        let texture = self.swap_chain.current_texture();
+        UVec2::new(texture.width(), texture.height())
+    }
+
+    pub fn targets(&self) -> RenderTargets<'_> {
+        RenderTargets {
+            color_format: self.swap_chain.current_texture().format(),
+            swap_chain: &self.swap_chain,
+            surface: self.surface.create_view(&Default::default()),
+            surface_format: self.surface.format(),
+            depth_format: self.depth.as_ref().map(Texture::format),
+            depth: self
+                .depth
+                .as_ref()
+                .map(|depth| depth.create_view(&Default::default())),
+            size: self.size(),
+            sample_count: self.sample_count,
+            hdr_output: self.hdr_output,
+            color_space: ColorSpace::Srgb,
+        }
+    }
+
+    /// Read back the current swap chain texture.
+    pub fn read(&self, renderer: &WgpuRenderer) -> RgbaImage {
+        pollster::block_on(util::read_color_texture(
+            self.swap_chain.current_texture(),
+            &renderer.device,
+            &renderer.queue,
+        ))
+    }
+
+    /// Replace the swap chain with one holding a layer per eye, for stereo flows.
+    pub fn with_eyes(mut self, device: &Device) -> Self {
+        let desc = self.swap_chain.desc();
+        let desc = TextureDescriptor {
+            label: None,
+            size: Extent3d {
+                depth_or_array_layers: 2,
+                ..desc.size
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: desc.dimension,
+            format: desc.format,
+            usage: desc.usage,
+            view_formats: &[],
+        };
+        self.swap_chain = SwapChain::new(device, &desc);
+        self
+    }
+
+    /// Read back `layer` of the current swap chain texture, like an eye of a stereo flow.
+    pub fn read_layer(&self, renderer: &WgpuRenderer, layer: u32) -> RgbaImage {
+        let texture = self.swap_chain.current_texture();
+        let copy = util::create_texture(
+            &renderer.device,
+            self.size().extend(1),
+            texture.format(),
+            TextureUsages::COPY_DST | TextureUsages::COPY_SRC,
+        );
+        let mut encoder = renderer.device.create_command_encoder(&Default::default());
+        encoder.copy_texture_to_texture(
+            ImageCopyTexture {
+                origin: Origin3d {
+                    z: layer,
+                    ..Origin3d::ZERO
+                },
+                ..texture.as_image_copy()
+            },
+            copy.as_image_copy(),
+            copy.size(),
+        );
+        renderer.queue.submit([encoder.finish()]);
+        pollster::block_on(util::read_color_texture(
+            &copy,
+            &renderer.device,
+            &renderer.queue,
+        ))
+    }
+}
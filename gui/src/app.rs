@@ -12,18 +12,17 @@ use aurora_chest::{
 use aurora_core::{
     render::{
         helper::{Camera, CameraProjection, Exposure, Transform},
-        resource::RenderTargets,
+        resource::{hdr_surface_format, ColorSpace, RenderTargets},
         scene::GpuScene,
         ShaderDefEnum,
     },
     util::{self, ext::StrAsShaderDef},
-    SwapChain, WgpuRenderer,
+    SwapChain, SwapChainConfig, WgpuRenderer,
 };
 use glam::{EulerRot, Quat, UVec2, Vec2, Vec3};
 use naga_oil::compose::ShaderDefValue;
 use wgpu::{
-    Surface, SurfaceConfiguration, Texture, TextureDescriptor, TextureDimension, TextureFormat,
-    TextureUsages, TextureViewDescriptor,
+    Surface, SurfaceConfiguration, Texture, TextureFormat, TextureUsages, TextureViewDescriptor,
 };
 use winit::{
     application::ApplicationHandler,
@@ -37,6 +36,18 @@ use winit::{
 use crate::scene::{CameraConfig, ControllableCamera};
 
 const HDR_TARGET_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+const SAMPLE_COUNT: u32 = 1;
+
+/// Presents HDR when the display supports it, SDR otherwise.
+fn surface_config(surface: &Surface, renderer: &WgpuRenderer, dim: UVec2) -> SurfaceConfiguration {
+    let mut config = surface
+        .get_default_config(&renderer.adapter, dim.x, dim.y)
+        .unwrap();
+    if let Some(format) = hdr_surface_format(&surface.get_capabilities(&renderer.adapter)) {
+        config.format = format;
+    }
+    config
+}
 
 pub struct Application<'a> {
     renderer: WgpuRenderer,
@@ -45,6 +56,7 @@ pub struct Application<'a> {
     window: Arc<Window>,
     depth_texture: Texture,
     dim: UVec2,
+    resized: bool,
 
     main_camera: Arc<Mutex<ControllableCamera>>,
     scene: GpuScene,
@@ -67,14 +79,9 @@ impl<'a> Application<'a> {
         );
 
         let flow: crate::render::PbrRenderFlow = Default::default();
-        let renderer = flow.inner.request_renderer(None, None).await;
+        let renderer = flow.inner.request_renderer(None, None).await.unwrap();
         let surface = renderer.instance.create_surface(window.clone()).unwrap();
-        surface.configure(
-            &renderer.device,
-            &surface
-                .get_default_config(&renderer.adapter, dim.x, dim.y)
-                .unwrap(),
-        );
+        surface.configure(&renderer.device, &surface_config(&surface, &renderer, dim));
 
         let depth_texture = util::create_texture(
             &renderer.device,
@@ -99,6 +106,7 @@ impl<'a> Application<'a> {
             // "gui/assets/randomized_plane.glb",
             &renderer.device,
             &renderer.queue,
+            &Default::default(),
         )
         .unwrap();
 
@@ -138,6 +146,7 @@ impl<'a> Application<'a> {
             post_process_chain: None,
             depth_texture,
             dim,
+            resized: false,
 
             scene,
             flow,
@@ -205,6 +214,9 @@ impl<'a> Application<'a> {
                 depth: Some(depth.create_view(&TextureViewDescriptor::default())),
                 swap_chain: &swap_chain,
                 size: self.dim,
+                sample_count: SAMPLE_COUNT,
+                hdr_output: false,
+                color_space: ColorSpace::Srgb,
             }),
             true,
         );
@@ -232,19 +244,17 @@ impl<'a> Application<'a> {
                 sc
             }
             None => {
-                self.post_process_chain.replace(SwapChain::new(
-                    &self.renderer.device,
-                    &TextureDescriptor {
-                        label: Some("post_process_chain"),
-                        size: frame.texture.size(),
-                        mip_level_count: 1,
-                        sample_count: 1,
-                        dimension: TextureDimension::D2,
-                        format: HDR_TARGET_FORMAT,
-                        usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
-                        view_formats: &[],
-                    },
-                ));
+                self.post_process_chain.replace(
+                    SwapChain::from_config(
+                        &self.renderer.device,
+                        &SwapChainConfig {
+                            format: HDR_TARGET_FORMAT,
+                            usage: SwapChain::REQUIRED_USAGES | TextureUsages::COPY_SRC,
+                            size: UVec2::new(frame.texture.width(), frame.texture.height()),
+                        },
+                    )
+                    .unwrap(),
+                );
                 self.post_process_chain.as_ref().unwrap()
             }
         };
@@ -262,6 +272,9 @@ impl<'a> Application<'a> {
             ),
             swap_chain,
             size: self.dim,
+            sample_count: SAMPLE_COUNT,
+            hdr_output: true,
+            color_space: ColorSpace::Srgb,
         });
 
         self.flow.inner.set_queue(self.scene.static_meshes.clone());
@@ -269,11 +282,18 @@ impl<'a> Application<'a> {
         if force_build {
             self.flow
                 .inner
-                .force_build(&self.renderer, &mut self.scene, None, &targets);
+                .force_build(&self.renderer, &mut self.scene, None, &targets)
+                .unwrap();
         } else {
             self.flow
                 .inner
-                .build(&self.renderer, &mut self.scene, None, &targets);
+                .build(&self.renderer, &mut self.scene, None, &targets)
+                .unwrap();
+            if std::mem::take(&mut self.resized) {
+                self.flow
+                    .inner
+                    .resize(&self.renderer, &mut self.scene, &targets, self.dim);
+            }
         }
 
         self.flow
@@ -294,10 +314,7 @@ impl<'a> Application<'a> {
         self.dim = dim;
         self.surface.configure(
             &self.renderer.device,
-            &self
-                .surface
-                .get_default_config(&self.renderer.adapter, dim.x, dim.y)
-                .unwrap(),
+            &surface_config(&self.surface, &self.renderer, dim),
         );
         self.depth_texture = util::create_texture(
             &self.renderer.device,
@@ -307,8 +324,11 @@ impl<'a> Application<'a> {
                 | TextureUsages::TEXTURE_BINDING
                 | TextureUsages::COPY_SRC,
         );
-        self.post_process_chain = None;
-        self.redraw(None, true);
+        if let Some(swap_chain) = &mut self.post_process_chain {
+            swap_chain.resize(&self.renderer.device, dim);
+        }
+        self.resized = true;
+        self.redraw(None, false);
     }
 }
 
@@ -1,8 +1,8 @@
 use aurora_chest::node::{
     BasicTriangleNode, BloomNode, DepthOfFieldNode, DepthPrepassNode, EnvironmentMappingNode,
-    EnvironmentMappingNodeConfig, LensFlareNode, MotionBlurNode, MotionVectorPrepassNode,
+    EnvironmentMappingNodeConfig, FxaaNode, LensFlareNode, MotionBlurNode, MotionVectorPrepassNode,
     NormalPrepassNode, PbrNode, PbrNodeConfig, ShadowMappingNode, ShadowMappingNodeConfig,
-    SkyboxNode, SkyboxNodeConfig, SsaoNode, TonemappingNode, ENVIRONMENT_MAP_PATH_ATTR,
+    SkyboxNode, SkyboxNodeConfig, SsaoNode, TaaNode, TonemappingNode, ENVIRONMENT_MAP_PATH_ATTR,
 };
 use aurora_core::render::flow::{
     GeneralNode, ImageFallbackNode, PostProcessGeneralNode, PresentNode, RenderFlow,
@@ -40,24 +40,32 @@ impl Default for PbrRenderFlow {
                 data: None,
                 config: Default::default(),
                 convolution_config: Default::default(),
+                prefilter_config: Default::default(),
             })
-            // .add_initialized(SkyboxNode {
-            //     node_config: SkyboxNodeConfig {
-            //         skybox_path: "chest/assets/envmap/sunny_prairie_expanse_cube_map.hdr".into(),
-            //     },
-            //     data: None,
-            // })
             // .add::<PbrNode>()
             .add_initialized(PbrNode {
                 node_cfg: PbrNodeConfig::ENVIRONMENT_MAPPING,
                 ..Default::default()
             })
+            .add_initialized(SkyboxNode {
+                node_config: SkyboxNodeConfig {
+                    skybox_path: None,
+                    background_exposure: 0.,
+                },
+                data: None,
+            })
             // .add::<BloomNode>()
             // .add::<DepthOfFieldNode>()
             // .add::<LensFlareNode>()
             // .add::<MotionBlurNode>()
+            // .add::<TaaNode>()
             // .add::<PresentNode>()
-            .add::<TonemappingNode>();
+            .add_initialized(TonemappingNode {
+                to_surface: false,
+                ..Default::default()
+            })
+            .add::<FxaaNode>()
+            .add::<PresentNode>();
 
         // flow.config_node::<PbrNode>(PbrNodeConfig::ENVIRONMENT_MAPPING);
         // flow.add_extra_data::<EnvironmentMappingNode>(